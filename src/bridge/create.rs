@@ -2,12 +2,17 @@
 //! https://github.com/KillTheMule/nvim-rs/blob/master/src/create/tokio.rs
 
 use std::{
+    collections::VecDeque,
     io::{self, Error, ErrorKind},
     process::Stdio,
+    sync::Arc,
 };
 
+use log::warn;
+use parking_lot::Mutex;
+
 use tokio::{
-    io::split,
+    io::{split, AsyncBufReadExt, BufReader},
     net::{TcpStream, ToSocketAddrs},
     process::Command,
     spawn,
@@ -36,17 +41,72 @@ where
     Ok((neovim, io_handle))
 }
 
+/// Connect to a neovim instance via a unix socket
+#[cfg(unix)]
+pub async fn new_path<H>(
+    path: &str,
+    handler: H,
+) -> io::Result<(Neovim<TxWrapper>, JoinHandle<Result<(), Box<LoopError>>>)>
+where
+    H: Handler<Writer = TxWrapper>,
+{
+    let stream = tokio::net::UnixStream::connect(path).await?;
+    let (reader, writer) = split(stream);
+    let (neovim, io) = Neovim::<TxWrapper>::new(reader.compat(), writer.wrap_tx(), handler);
+    let io_handle = spawn(io);
+
+    Ok((neovim, io_handle))
+}
+
+/// Connect to a neovim instance via a named pipe, like \\.\pipe\nvim
+#[cfg(windows)]
+pub async fn new_path<H>(
+    path: &str,
+    handler: H,
+) -> io::Result<(Neovim<TxWrapper>, JoinHandle<Result<(), Box<LoopError>>>)>
+where
+    H: Handler<Writer = TxWrapper>,
+{
+    let pipe = tokio::net::windows::named_pipe::ClientOptions::new().open(path)?;
+    let (reader, writer) = split(pipe);
+    let (neovim, io) = Neovim::<TxWrapper>::new(reader.compat(), writer.wrap_tx(), handler);
+    let io_handle = spawn(io);
+
+    Ok((neovim, io_handle))
+}
+
+// How many of the last stderr lines are shown when neovim crashes
+const STDERR_LINES: usize = 10;
+// How much of the stderr output is kept around for :NeovideProcessOutput
+const PROCESS_OUTPUT_LINES: usize = 1000;
+
+/// What the spawned neovim wrote to stderr, oldest first.
+pub type ProcessOutput = Arc<Mutex<VecDeque<String>>>;
+
 /// Connect to a neovim instance by spawning a new one
 ///
-/// stdin/stdout will be rewritten to `Stdio::piped()`
+/// stdin/stdout will be rewritten to `Stdio::piped()`. The returned exit handle resolves once the
+/// process exits, to a description of the failure if it didn't exit cleanly. Piped stderr is
+/// logged and collected into the process output.
 pub async fn new_child_cmd<H>(
     cmd: &mut Command,
     handler: H,
-) -> io::Result<(Neovim<TxWrapper>, JoinHandle<Result<(), Box<LoopError>>>)>
+    process_output: ProcessOutput,
+) -> io::Result<(
+    Neovim<TxWrapper>,
+    JoinHandle<Result<(), Box<LoopError>>>,
+    JoinHandle<Option<String>>,
+)>
 where
     H: Handler<Writer = TxWrapper>,
 {
-    let mut child = cmd.stdin(Stdio::piped()).stdout(Stdio::piped()).spawn()?;
+    // Neovim is killed when the connection is dropped without it having quit, like when it didn't
+    // quit in time on close
+    let mut child = cmd
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()?;
     let stdout = child
         .stdout
         .take()
@@ -58,8 +118,48 @@ where
         .ok_or_else(|| Error::new(ErrorKind::Other, "Can't open stdin"))?
         .wrap_tx();
 
+    // Stderr is only piped in release builds, debug builds print it to the terminal directly
+    let stderr_output = process_output.clone();
+    let stderr_handle = child.stderr.take().map(|stderr| {
+        spawn(async move {
+            let mut lines = BufReader::new(stderr).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                warn!("Neovim stderr: {}", line);
+                let mut output = stderr_output.lock();
+                if output.len() == PROCESS_OUTPUT_LINES {
+                    output.pop_front();
+                }
+                output.push_back(line);
+            }
+        })
+    });
+
+    let exit_handle = spawn(async move {
+        let status = child.wait().await.ok()?;
+        if status.success() {
+            return None;
+        }
+
+        let mut description = match status.code() {
+            Some(code) => format!("Neovim exited with code {}", code),
+            None => "Neovim was terminated by a signal".to_owned(),
+        };
+        if let Some(stderr_handle) = stderr_handle {
+            stderr_handle.await.ok();
+            let output = process_output.lock();
+            for line in output
+                .iter()
+                .skip(output.len().saturating_sub(STDERR_LINES))
+            {
+                description.push('\n');
+                description.push_str(line);
+            }
+        }
+        Some(description)
+    });
+
     let (neovim, io) = Neovim::<TxWrapper>::new(stdout, stdin, handler);
     let io_handle = spawn(io);
 
-    Ok((neovim, io_handle))
+    Ok((neovim, io_handle, exit_handle))
 }
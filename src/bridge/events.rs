@@ -6,6 +6,7 @@ use std::fmt::Debug;
 use log::debug;
 use rmpv::Value;
 use skia_safe::Color4f;
+use smol_str::SmolStr;
 
 use crate::editor::{Colors, CursorMode, CursorShape, Style};
 
@@ -51,7 +52,8 @@ impl error::Error for ParseError {
 
 #[derive(Clone, Debug)]
 pub struct GridLineCell {
-    pub text: String,
+    // Nearly always a single character, which is kept inline rather than on the heap
+    pub text: SmolStr,
     pub highlight_id: Option<u64>,
     pub repeat: Option<u64>,
 }
@@ -118,7 +120,7 @@ pub enum WindowAnchor {
     SouthEast,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum EditorMode {
     // The set of modes reported will change in new versions of Nvim, for
     // instance more sub-modes and temporary states might be represented as
@@ -133,6 +135,14 @@ pub enum EditorMode {
     Unknown(String),
 }
 
+#[derive(Clone, Debug, PartialEq)]
+pub struct PopupMenuItem {
+    pub word: String,
+    pub kind: String,
+    pub menu: String,
+    pub info: String,
+}
+
 #[derive(Clone, Debug)]
 pub enum RedrawEvent {
     SetTitle {
@@ -165,6 +175,10 @@ pub enum RedrawEvent {
         id: u64,
         style: Style,
     },
+    HighlightGroupSet {
+        name: String,
+        id: u64,
+    },
     GridLine {
         grid: u64,
         row: u64,
@@ -224,6 +238,7 @@ pub enum RedrawEvent {
     },
     WindowViewport {
         grid: u64,
+        window: u64,
         top_line: f64,
         bottom_line: f64,
         current_line: f64,
@@ -273,6 +288,17 @@ pub enum RedrawEvent {
     MessageHistoryShow {
         entries: Vec<(MessageKind, StyledContent)>,
     },
+    PopupMenuShow {
+        items: Vec<PopupMenuItem>,
+        selected: Option<u64>,
+        row: u64,
+        column: u64,
+        grid: u64,
+    },
+    PopupMenuSelect {
+        selected: Option<u64>,
+    },
+    PopupMenuHide,
 }
 
 #[derive(Debug)]
@@ -422,6 +448,17 @@ fn parse_f64(f64_value: Value) -> Result<f64> {
     f64_value.try_into().map_err(ParseError::F64)
 }
 
+// Window handles are sent as msgpack extension values wrapping the integer id of the window
+fn parse_window_handle(window_value: Value) -> Result<u64> {
+    match &window_value {
+        Value::Ext(_, data) => rmpv::decode::read_value(&mut data.as_slice())
+            .ok()
+            .and_then(|handle| handle.as_u64())
+            .ok_or(ParseError::U64(window_value)),
+        _ => parse_u64(window_value),
+    }
+}
+
 fn parse_bool(bool_value: Value) -> Result<bool> {
     bool_value.try_into().map_err(ParseError::Bool)
 }
@@ -562,6 +599,7 @@ fn parse_style(style_map: Value) -> Result<Style> {
                 ("underline", Value::Boolean(underline)) => style.underline = underline,
                 ("undercurl", Value::Boolean(undercurl)) => style.undercurl = undercurl,
                 ("blend", Value::Integer(blend)) => style.blend = blend.as_u64().unwrap() as u8,
+                ("url", Value::String(url)) => style.url = url.into_str(),
                 _ => debug!("Ignored style attribute: {}", name),
             }
         } else {
@@ -582,31 +620,29 @@ fn parse_hl_attr_define(hl_attr_define_arguments: Vec<Value>) -> Result<RedrawEv
     })
 }
 
-fn parse_grid_line_cell(grid_line_cell: Value) -> Result<GridLineCell> {
-    fn take_value(val: &mut Value) -> Value {
-        std::mem::replace(val, Value::Nil)
-    }
+fn parse_hl_group_set(hl_group_set_arguments: Vec<Value>) -> Result<RedrawEvent> {
+    let [name, id] = extract_values(hl_group_set_arguments)?;
 
-    let mut cell_contents = parse_array(grid_line_cell)?;
+    Ok(RedrawEvent::HighlightGroupSet {
+        name: parse_string(name)?,
+        id: parse_u64(id)?,
+    })
+}
 
-    let text_value = cell_contents
-        .first_mut()
-        .map(take_value)
-        .ok_or_else(|| ParseError::Format(format!("{:?}", cell_contents)))?;
+// The text is moved out of the decoded message instead of copied, and the cell array is
+// consumed rather than cloned
+fn parse_grid_line_cell(grid_line_cell: Value) -> Result<GridLineCell> {
+    let mut cell_contents = parse_array(grid_line_cell)?.into_iter();
 
-    let highlight_id = cell_contents
-        .get_mut(1)
-        .map(take_value)
-        .map(parse_u64)
-        .transpose()?;
-    let repeat = cell_contents
-        .get_mut(2)
-        .map(take_value)
-        .map(parse_u64)
-        .transpose()?;
+    let text = match cell_contents.next() {
+        Some(text_value) => SmolStr::new(parse_string(text_value)?),
+        None => return Err(ParseError::Format("empty grid_line cell".to_owned())),
+    };
+    let highlight_id = cell_contents.next().map(parse_u64).transpose()?;
+    let repeat = cell_contents.next().map(parse_u64).transpose()?;
 
     Ok(GridLineCell {
-        text: parse_string(text_value)?,
+        text,
         highlight_id,
         repeat,
     })
@@ -615,14 +651,19 @@ fn parse_grid_line_cell(grid_line_cell: Value) -> Result<GridLineCell> {
 fn parse_grid_line(grid_line_arguments: Vec<Value>) -> Result<RedrawEvent> {
     let [grid_id, row, column_start, cells] = extract_values(grid_line_arguments)?;
 
+    // Collecting into a result can't know the length up front, and would grow the vector a
+    // couple of times for every full line
+    let cell_values = parse_array(cells)?;
+    let mut cells = Vec::with_capacity(cell_values.len());
+    for cell_value in cell_values {
+        cells.push(parse_grid_line_cell(cell_value)?);
+    }
+
     Ok(RedrawEvent::GridLine {
         grid: parse_u64(grid_id)?,
         row: parse_u64(row)?,
         column_start: parse_u64(column_start)?,
-        cells: parse_array(cells)?
-            .into_iter()
-            .map(parse_grid_line_cell)
-            .collect::<Result<Vec<GridLineCell>>>()?,
+        cells,
     })
 }
 
@@ -746,7 +787,7 @@ fn parse_msg_set_pos(msg_set_pos_arguments: Vec<Value>) -> Result<RedrawEvent> {
 }
 
 fn parse_win_viewport(win_viewport_arguments: Vec<Value>) -> Result<RedrawEvent> {
-    let ([grid, _window, top_line, bottom_line, current_line, current_column], [line_count]) =
+    let ([grid, window, top_line, bottom_line, current_line, current_column], [line_count]) =
         extract_values_with_optional(win_viewport_arguments)?;
 
     let line_count = if let Some(line_count) = line_count {
@@ -757,6 +798,7 @@ fn parse_win_viewport(win_viewport_arguments: Vec<Value>) -> Result<RedrawEvent>
 
     Ok(RedrawEvent::WindowViewport {
         grid: parse_u64(grid)?,
+        window: parse_window_handle(window)?,
         top_line: parse_f64(top_line)?,
         bottom_line: parse_f64(bottom_line)?,
         current_line: parse_f64(current_line)?,
@@ -882,6 +924,50 @@ fn parse_msg_history_show(msg_history_show_arguments: Vec<Value>) -> Result<Redr
     })
 }
 
+fn parse_popupmenu_item(popupmenu_item: Value) -> Result<PopupMenuItem> {
+    let [word, kind, menu, info] = extract_values(parse_array(popupmenu_item)?)?;
+
+    Ok(PopupMenuItem {
+        word: parse_string(word)?,
+        kind: parse_string(kind)?,
+        menu: parse_string(menu)?,
+        info: parse_string(info)?,
+    })
+}
+
+// Nothing is selected when the index is -1
+fn parse_popupmenu_selected(selected: Value) -> Result<Option<u64>> {
+    let selected = parse_i64(selected)?;
+    Ok(if selected < 0 {
+        None
+    } else {
+        Some(selected as u64)
+    })
+}
+
+fn parse_popupmenu_show(popupmenu_show_arguments: Vec<Value>) -> Result<RedrawEvent> {
+    let [items, selected, row, column, grid] = extract_values(popupmenu_show_arguments)?;
+
+    Ok(RedrawEvent::PopupMenuShow {
+        items: parse_array(items)?
+            .into_iter()
+            .map(parse_popupmenu_item)
+            .collect::<Result<_>>()?,
+        selected: parse_popupmenu_selected(selected)?,
+        row: parse_u64(row)?,
+        column: parse_u64(column)?,
+        grid: parse_u64(grid)?,
+    })
+}
+
+fn parse_popupmenu_select(popupmenu_select_arguments: Vec<Value>) -> Result<RedrawEvent> {
+    let [selected] = extract_values(popupmenu_select_arguments)?;
+
+    Ok(RedrawEvent::PopupMenuSelect {
+        selected: parse_popupmenu_selected(selected)?,
+    })
+}
+
 pub fn parse_redraw_event(event_value: Value) -> Result<Vec<RedrawEvent>> {
     let mut event_contents = parse_array(event_value)?.into_iter();
     let event_name = event_contents
@@ -908,6 +994,7 @@ pub fn parse_redraw_event(event_value: Value) -> Result<Vec<RedrawEvent>> {
             "grid_resize" => Some(parse_grid_resize(event_parameters)?),
             "default_colors_set" => Some(parse_default_colors(event_parameters)?),
             "hl_attr_define" => Some(parse_hl_attr_define(event_parameters)?),
+            "hl_group_set" => Some(parse_hl_group_set(event_parameters)?),
             "grid_line" => Some(parse_grid_line(event_parameters)?),
             "grid_clear" => Some(parse_grid_clear(event_parameters)?),
             "grid_destroy" => Some(parse_grid_destroy(event_parameters)?),
@@ -933,6 +1020,9 @@ pub fn parse_redraw_event(event_value: Value) -> Result<Vec<RedrawEvent>> {
             "msg_showcmd" => Some(parse_msg_showcmd(event_parameters)?),
             "msg_ruler" => Some(parse_msg_ruler(event_parameters)?),
             "msg_history_show" => Some(parse_msg_history_show(event_parameters)?),
+            "popupmenu_show" => Some(parse_popupmenu_show(event_parameters)?),
+            "popupmenu_select" => Some(parse_popupmenu_select(event_parameters)?),
+            "popupmenu_hide" => Some(RedrawEvent::PopupMenuHide),
             _ => None,
         };
 
@@ -1,36 +1,180 @@
 use std::sync::Arc;
 
 use async_trait::async_trait;
-use log::trace;
+use log::{error, trace};
 use nvim_rs::{Handler, Neovim};
 use parking_lot::Mutex;
 use rmpv::Value;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
 use tokio::task;
 
-use super::events::{parse_redraw_event, RedrawEvent};
+use super::events::parse_redraw_event;
+use super::image_paste::paste_image;
+use super::recording::EventRecorder;
+use super::send_font_settings;
 #[cfg(windows)]
 use super::ui_commands::{ParallelCommand, UiCommand};
-use crate::bridge::TxWrapper;
+use crate::bridge::{create::ProcessOutput, TxWrapper};
 use crate::channel_utils::*;
+use crate::clipboard;
+use crate::cmd_line::CmdLineSettings;
+use crate::editor::{Decoration, EditorCommand, WindowCommand, WindowDrawCommand};
 use crate::error_handling::ResultPanicExplanation;
-use crate::settings::SETTINGS;
+use crate::notification::NotificationLevel;
+use crate::redraw_scheduler::REDRAW_SCHEDULER;
+use crate::renderer::FontSettings;
+use crate::settings::{profile_settings, SETTINGS};
+use crate::window::WindowSettings;
+
+// Lua passes booleans, vimscript often 0 and 1
+fn value_to_bool(value: &Value) -> Option<bool> {
+    value
+        .as_bool()
+        .or_else(|| value.as_i64().map(|value| value != 0))
+}
+
+// What scripts can find out about the gui through neovide.get_info
+fn gui_info() -> Value {
+    let cmd_line_settings = SETTINGS.get::<CmdLineSettings>();
+    let window_settings = SETTINGS.get::<WindowSettings>();
+    Value::Map(vec![
+        (Value::from("version"), Value::from(crate_version!())),
+        (Value::from("os"), Value::from(std::env::consts::OS)),
+        (
+            Value::from("backend"),
+            Value::from(cmd_line_settings.backend),
+        ),
+        (
+            Value::from("multigrid"),
+            Value::from(cmd_line_settings.multi_grid),
+        ),
+        (
+            Value::from("transparency"),
+            Value::from(window_settings.transparency as f64),
+        ),
+    ])
+}
+
+// The notifications the editor is told about
+fn is_editor_notification(event_name: &str) -> bool {
+    matches!(
+        event_name,
+        "redraw"
+            | "neovide.export"
+            | "neovide.add_decoration"
+            | "neovide.remove_decoration"
+            | "neovide.clear_decorations"
+    )
+}
+
+// Runs on a blocking thread, since parsing a large redraw or loading an image takes a while
+fn editor_commands(event_name: &str, arguments: Vec<Value>) -> Vec<EditorCommand> {
+    match event_name {
+        "redraw" => {
+            let mut editor_commands = Vec::new();
+            for events in arguments {
+                profile_scope!("parse redraw event");
+                let parsed_events = parse_redraw_event(events)
+                    .unwrap_or_explained_panic("Could not parse event from neovim");
+                editor_commands.extend(
+                    parsed_events
+                        .into_iter()
+                        .map(EditorCommand::NeovimRedrawEvent),
+                );
+            }
+            editor_commands
+        }
+        "neovide.export" => {
+            let path = arguments
+                .first()
+                .and_then(|path| path.as_str())
+                .filter(|path| !path.is_empty())
+                .map(|path| path.to_owned());
+            vec![EditorCommand::Export(path)]
+        }
+        _ => {
+            let window = arguments
+                .first()
+                .and_then(|window| window.as_u64())
+                .unwrap_or(0);
+            let id = arguments
+                .get(1)
+                .and_then(|id| id.as_str())
+                .unwrap_or_default()
+                .to_owned();
+            let command = match event_name {
+                "neovide.add_decoration" => {
+                    let spec = arguments.get(2).cloned().unwrap_or(Value::Nil);
+                    match Decoration::parse(id, &spec) {
+                        Ok(mut decoration) => {
+                            decoration.load_image();
+                            WindowDrawCommand::Decorate(decoration)
+                        }
+                        Err(message) => {
+                            error!("{}", message);
+                            return Vec::new();
+                        }
+                    }
+                }
+                "neovide.remove_decoration" => WindowDrawCommand::RemoveDecoration(id),
+                _ => WindowDrawCommand::ClearDecorations,
+            };
+            vec![EditorCommand::Decorate { window, command }]
+        }
+    }
+}
+
+// Nvim-rs handles every notification in a task of its own, so the ones for the editor are
+// queued here as they come in and turned into editor commands one after another. While the
+// editor queue is full only this task waits, and the notifications behind it keep their order.
+async fn forward_editor_notifications(
+    mut notification_receiver: UnboundedReceiver<(String, Vec<Value>)>,
+    editor_command_sender: LoggingBoundedTx<EditorCommand>,
+) {
+    while let Some((event_name, arguments)) = notification_receiver.recv().await {
+        let editor_commands =
+            match task::spawn_blocking(move || editor_commands(&event_name, arguments)).await {
+                Ok(editor_commands) => editor_commands,
+                Err(_) => continue,
+            };
+        for editor_command in editor_commands {
+            if editor_command_sender.send(editor_command).await.is_err() {
+                return;
+            }
+        }
+    }
+}
 
 #[derive(Clone)]
 pub struct NeovimHandler {
     #[cfg(windows)]
     ui_command_sender: Arc<Mutex<LoggingTx<UiCommand>>>,
-    redraw_event_sender: Arc<Mutex<LoggingTx<RedrawEvent>>>,
+    editor_notification_sender: UnboundedSender<(String, Vec<Value>)>,
+    window_command_sender: Arc<Mutex<LoggingSender<WindowCommand>>>,
+    process_output: ProcessOutput,
+    recorder: Option<EventRecorder>,
 }
 
 impl NeovimHandler {
     pub fn new(
         #[cfg(windows)] ui_command_sender: LoggingTx<UiCommand>,
-        redraw_event_sender: LoggingTx<RedrawEvent>,
+        editor_command_sender: LoggingBoundedTx<EditorCommand>,
+        window_command_sender: LoggingSender<WindowCommand>,
+        process_output: ProcessOutput,
+        recorder: Option<EventRecorder>,
     ) -> NeovimHandler {
+        let (editor_notification_sender, editor_notification_receiver) = unbounded_channel();
+        tokio::spawn(forward_editor_notifications(
+            editor_notification_receiver,
+            editor_command_sender,
+        ));
         NeovimHandler {
             #[cfg(windows)]
             ui_command_sender: Arc::new(Mutex::new(ui_command_sender)),
-            redraw_event_sender: Arc::new(Mutex::new(redraw_event_sender)),
+            editor_notification_sender,
+            window_command_sender: Arc::new(Mutex::new(window_command_sender)),
+            process_output,
+            recorder,
         }
     }
 }
@@ -39,32 +183,363 @@ impl NeovimHandler {
 impl Handler for NeovimHandler {
     type Writer = TxWrapper;
 
+    async fn handle_request(
+        &self,
+        event_name: String,
+        arguments: Vec<Value>,
+        neovim: Neovim<TxWrapper>,
+    ) -> Result<Value, Value> {
+        trace!("Neovim request: {:?}", &event_name);
+
+        match event_name.as_ref() {
+            "neovide.get_clipboard" => {
+                let register = arguments
+                    .first()
+                    .and_then(|register| register.as_str())
+                    .unwrap_or("+")
+                    .to_owned();
+                let (lines, regtype) =
+                    task::spawn_blocking(move || clipboard::get_register(&register))
+                        .await
+                        .unwrap_or_else(|_| (Vec::new(), "v".to_owned()));
+                // Without the type neovim would paste linewise yanks charwise
+                Ok(Value::Array(vec![
+                    Value::Array(lines.into_iter().map(Value::from).collect()),
+                    Value::from(regtype),
+                ]))
+            }
+            // Asked for explicitly, since reading the clipboard happens for every register access
+            // and shouldn't leave files behind
+            "neovide.paste_image" => Ok(Value::from(paste_image(&neovim).await)),
+            "neovide.get_info" => Ok(gui_info()),
+            "neovide.process_output" => {
+                let output = self.process_output.lock();
+                Ok(Value::Array(
+                    output.iter().cloned().map(Value::from).collect(),
+                ))
+            }
+            _ => Err(Value::from(format!("Unknown request: {}", event_name))),
+        }
+    }
+
     async fn handle_notify(
         &self,
         event_name: String,
         arguments: Vec<Value>,
-        _neovim: Neovim<TxWrapper>,
+        neovim: Neovim<TxWrapper>,
     ) {
         trace!("Neovim notification: {:?}", &event_name);
 
-        #[cfg(windows)]
-        let ui_command_sender = self.ui_command_sender.clone();
+        // Goes through the variable so g:neovide_transparency doesn't get out of sync, the change
+        // then comes back as a setting_changed notification
+        if event_name == "neovide.set_transparency" {
+            if let Some(transparency) = arguments.first().and_then(|value| value.as_f64()) {
+                neovim
+                    .set_var(
+                        "neovide_transparency",
+                        Value::from(transparency.max(0.0).min(1.0)),
+                    )
+                    .await
+                    .ok();
+            }
+            return;
+        }
 
-        let redraw_event_sender = self.redraw_event_sender.clone();
-        task::spawn_blocking(move || match event_name.as_ref() {
-            "redraw" => {
-                for events in arguments {
-                    let parsed_events = parse_redraw_event(events)
-                        .unwrap_or_explained_panic("Could not parse event from neovim");
-
-                    for parsed_event in parsed_events {
-                        let redraw_event_sender = redraw_event_sender.lock();
-                        redraw_event_sender.send(parsed_event).ok();
+        // Same for the settings of a profile, which stay what the variables say afterwards
+        if event_name == "neovide.set_profile" {
+            let name = arguments
+                .first()
+                .and_then(|name| name.as_str())
+                .unwrap_or_default();
+            let custom_profiles = neovim.get_var("neovide_profiles").await.ok();
+            match profile_settings(name, custom_profiles.as_ref()) {
+                Some(settings) => {
+                    for (setting, value) in settings {
+                        neovim
+                            .set_var(&format!("neovide_{}", setting), value)
+                            .await
+                            .ok();
                     }
+                    neovim
+                        .set_var("neovide_profile", Value::from(name))
+                        .await
+                        .ok();
                 }
+                None => error!("Unknown profile {:?}", name),
+            }
+            return;
+        }
+
+        // Recorded in the order they arrived, before they are handled anywhere else
+        if let Some(recorder) = &self.recorder {
+            if event_name == "redraw" {
+                recorder.record(&arguments);
             }
+        }
+
+        if is_editor_notification(&event_name) {
+            self.editor_notification_sender
+                .send((event_name, arguments))
+                .ok();
+            return;
+        }
+
+        #[cfg(windows)]
+        let ui_command_sender = self.ui_command_sender.clone();
+
+        let window_command_sender = self.window_command_sender.clone();
+        task::spawn_blocking(move || match event_name.as_ref() {
             "setting_changed" => {
+                let font_setting = arguments
+                    .first()
+                    .and_then(|name| name.as_str())
+                    .map_or(false, FontSettings::is_font_setting);
                 SETTINGS.handle_changed_notification(arguments);
+                if font_setting {
+                    send_font_settings(&window_command_sender.lock());
+                }
+                // Settings like the transparency are read while drawing, so a new frame is
+                // enough to apply them
+                REDRAW_SCHEDULER.queue_flushed_frame();
+            }
+            "neovide.buffer_changed" => {
+                let argument = |index: usize| {
+                    arguments
+                        .get(index)
+                        .and_then(|argument| argument.as_str())
+                        .unwrap_or_default()
+                        .to_owned()
+                };
+                let modified = arguments
+                    .get(1)
+                    .and_then(|modified| modified.as_i64())
+                    .map(|modified| modified != 0)
+                    .unwrap_or(false);
+                let window_command_sender = window_command_sender.lock();
+                window_command_sender
+                    .send(WindowCommand::BufferChanged {
+                        path: argument(0),
+                        modified,
+                        cwd: argument(2),
+                        filetype: argument(3),
+                    })
+                    .ok();
+            }
+            "neovide.set_clipboard" => {
+                let lines: Vec<String> = arguments
+                    .first()
+                    .and_then(|lines| lines.as_array())
+                    .map(|lines| {
+                        lines
+                            .iter()
+                            .filter_map(|line| line.as_str().map(|line| line.to_owned()))
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                let register = arguments
+                    .get(1)
+                    .and_then(|register| register.as_str())
+                    .unwrap_or("+")
+                    .to_owned();
+                let regtype = arguments
+                    .get(2)
+                    .and_then(|regtype| regtype.as_str())
+                    .unwrap_or("v")
+                    .to_owned();
+                // Writing the clipboard talks to the os, and on X11 can wait on other programs
+                task::spawn_blocking(move || clipboard::set_lines(&register, &lines, &regtype))
+                    .await
+                    .ok();
+            }
+            "neovide.set_rich_clipboard" => {
+                let argument = |index: usize| {
+                    arguments
+                        .get(index)
+                        .and_then(|argument| argument.as_str())
+                        .unwrap_or_default()
+                        .to_owned()
+                };
+                // The window owns the clipboard on windows
+                let window_command_sender = window_command_sender.lock();
+                window_command_sender
+                    .send(WindowCommand::SetRichClipboard {
+                        html: argument(0),
+                        text: argument(1),
+                    })
+                    .ok();
+            }
+            "neovide.notify" => {
+                let argument = |index: usize| {
+                    arguments
+                        .get(index)
+                        .and_then(|argument| argument.as_str())
+                        .unwrap_or_default()
+                        .to_owned()
+                };
+                let level = arguments
+                    .get(2)
+                    .map(NotificationLevel::parse)
+                    .unwrap_or(NotificationLevel::Info);
+                // Only the window knows whether it is in the background
+                let window_command_sender = window_command_sender.lock();
+                window_command_sender
+                    .send(WindowCommand::Notify {
+                        title: argument(0),
+                        body: argument(1),
+                        level,
+                    })
+                    .ok();
+            }
+            "neovide.mousehide" => {
+                let mousehide = arguments
+                    .first()
+                    .and_then(|mousehide| mousehide.as_i64())
+                    .map(|mousehide| mousehide != 0)
+                    .unwrap_or(false);
+                let window_command_sender = window_command_sender.lock();
+                window_command_sender
+                    .send(WindowCommand::SetMouseHide(mousehide))
+                    .ok();
+            }
+            "neovide.mousemoveevent" => {
+                let mouse_move_event = arguments
+                    .first()
+                    .and_then(|mouse_move_event| mouse_move_event.as_i64())
+                    .map(|mouse_move_event| mouse_move_event != 0)
+                    .unwrap_or(false);
+                let window_command_sender = window_command_sender.lock();
+                window_command_sender
+                    .send(WindowCommand::SetMouseMoveEvent(mouse_move_event))
+                    .ok();
+            }
+            "neovide.mousetime" => {
+                if let Some(mousetime) = arguments.first().and_then(|mousetime| mousetime.as_u64())
+                {
+                    let window_command_sender = window_command_sender.lock();
+                    window_command_sender
+                        .send(WindowCommand::SetMouseTime(mousetime))
+                        .ok();
+                }
+            }
+            "neovide.set_progress" => {
+                let progress = arguments.first().and_then(|progress| progress.as_f64());
+                let window_command_sender = window_command_sender.lock();
+                window_command_sender
+                    .send(WindowCommand::SetProgress(progress))
+                    .ok();
+            }
+            "neovide.suppress_context_menu" => {
+                let suppressed = arguments
+                    .first()
+                    .and_then(|suppressed| suppressed.as_bool())
+                    .unwrap_or(true);
+                let window_command_sender = window_command_sender.lock();
+                window_command_sender
+                    .send(WindowCommand::SuppressContextMenu(suppressed))
+                    .ok();
+            }
+            "neovide.screenshot" => {
+                let path = arguments
+                    .first()
+                    .and_then(|path| path.as_str())
+                    .filter(|path| !path.is_empty())
+                    .map(|path| path.to_owned());
+                let window_command_sender = window_command_sender.lock();
+                window_command_sender
+                    .send(WindowCommand::Screenshot(path))
+                    .ok();
+            }
+            "neovide.diagnostics" => {
+                let path = arguments
+                    .first()
+                    .and_then(|path| path.as_str())
+                    .filter(|path| !path.is_empty())
+                    .map(|path| path.to_owned());
+                let window_command_sender = window_command_sender.lock();
+                window_command_sender
+                    .send(WindowCommand::WriteDiagnostics(path))
+                    .ok();
+            }
+            "neovide.record" => {
+                let path = arguments
+                    .first()
+                    .and_then(|path| path.as_str())
+                    .filter(|path| !path.is_empty())
+                    .map(|path| path.to_owned());
+                let window_command_sender = window_command_sender.lock();
+                window_command_sender
+                    .send(WindowCommand::StartRecording(path))
+                    .ok();
+            }
+            "neovide.stop_recording" => {
+                let window_command_sender = window_command_sender.lock();
+                window_command_sender
+                    .send(WindowCommand::StopRecording)
+                    .ok();
+            }
+            "neovide.toggle_fullscreen" => {
+                let window_command_sender = window_command_sender.lock();
+                window_command_sender
+                    .send(WindowCommand::ToggleFullscreen)
+                    .ok();
+            }
+            "neovide.set_fullscreen" => {
+                let fullscreen = arguments.first().and_then(value_to_bool).unwrap_or(true);
+                let window_command_sender = window_command_sender.lock();
+                window_command_sender
+                    .send(WindowCommand::SetFullscreen(fullscreen))
+                    .ok();
+            }
+            "neovide.set_presentation" => {
+                let active = arguments.first().and_then(value_to_bool).unwrap_or(true);
+                let window_command_sender = window_command_sender.lock();
+                window_command_sender
+                    .send(WindowCommand::SetPresentation(active))
+                    .ok();
+            }
+            "neovide.focus" | "neovide.zoom_in" | "neovide.zoom_out" | "neovide.zoom_reset" => {
+                let window_command = match event_name.as_ref() {
+                    "neovide.focus" => WindowCommand::Focus,
+                    "neovide.zoom_in" => WindowCommand::ZoomIn,
+                    "neovide.zoom_out" => WindowCommand::ZoomOut,
+                    _ => WindowCommand::ZoomReset,
+                };
+                let window_command_sender = window_command_sender.lock();
+                window_command_sender.send(window_command).ok();
+            }
+            "neovide.toggle_always_on_top" => {
+                let window_command_sender = window_command_sender.lock();
+                window_command_sender
+                    .send(WindowCommand::ToggleAlwaysOnTop)
+                    .ok();
+            }
+            "neovide.new_window" => {
+                let path = arguments
+                    .first()
+                    .and_then(|path| path.as_str())
+                    .filter(|path| !path.is_empty())
+                    .map(|path| path.to_owned());
+                let window_command_sender = window_command_sender.lock();
+                window_command_sender
+                    .send(WindowCommand::NewWindow(path))
+                    .ok();
+            }
+            "neovide.restart" => {
+                // Split by neovim like <f-args>, so spaces in paths can be escaped
+                let neovim_args = arguments
+                    .first()
+                    .and_then(|arguments| arguments.as_array())
+                    .map(|arguments| {
+                        arguments
+                            .iter()
+                            .filter_map(|argument| argument.as_str().map(String::from))
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                let window_command_sender = window_command_sender.lock();
+                window_command_sender
+                    .send(WindowCommand::Restart(neovim_args))
+                    .ok();
             }
             #[cfg(windows)]
             "neovide.register_right_click" => {
@@ -81,6 +556,20 @@ impl Handler for NeovimHandler {
                     .ok();
             }
             _ => {}
-        });
+        })
+        .await
+        .ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_value_to_bool() {
+        assert_eq!(value_to_bool(&Value::from(true)), Some(true));
+        assert_eq!(value_to_bool(&Value::from(0)), Some(false));
+        assert_eq!(value_to_bool(&Value::from("yes")), None);
     }
 }
@@ -0,0 +1,113 @@
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use log::{error, info};
+use nvim_rs::Neovim;
+use rmpv::Value;
+use skia_safe::{Data, EncodedImageFormat, Image};
+
+use crate::bridge::TxWrapper;
+use crate::clipboard;
+use crate::settings::SETTINGS;
+use crate::window::WindowSettings;
+
+// Named after the time with milliseconds, so pasting twice in a row doesn't overwrite the first
+fn image_file_name(since_epoch: u128) -> String {
+    format!("pasted_image_{}.png", since_epoch)
+}
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+// Pngs are saved as they were copied, anything else is converted
+fn encode_png(image: Vec<u8>) -> Option<Data> {
+    if image.starts_with(PNG_SIGNATURE) {
+        return Some(Data::new_copy(&image));
+    }
+    Image::from_encoded(Data::new_copy(&image))?.encode_to_data(EncodedImageFormat::PNG)
+}
+
+fn save_image(directory: &Path, image: Vec<u8>) -> Option<PathBuf> {
+    let since_epoch = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis())
+        .unwrap_or(0);
+    let path = directory.join(image_file_name(since_epoch));
+    let data = encode_png(image).or_else(|| {
+        error!("Could not encode the pasted image");
+        None
+    })?;
+    let written =
+        std::fs::create_dir_all(directory).and_then(|_| std::fs::write(&path, data.as_bytes()));
+    match written {
+        Ok(()) => Some(path),
+        Err(error) => {
+            error!(
+                "Could not save the pasted image to {}: {}",
+                path.display(),
+                error
+            );
+            None
+        }
+    }
+}
+
+/// Saves the image on the clipboard to `g:neovide_image_paste_directory` and lets neovim know
+/// through `g:neovide_pasted_image` and the `NeovideImagePasted` user autocommand. Returns whether
+/// there was an image to paste, which is never the case without a directory.
+pub async fn paste_image(nvim: &Neovim<TxWrapper>) -> bool {
+    let directory = SETTINGS.get::<WindowSettings>().image_paste_directory;
+    if directory.is_empty() {
+        return false;
+    }
+    let image = tokio::task::spawn_blocking(clipboard::get_image)
+        .await
+        .ok()
+        .flatten();
+    let image = match image {
+        Some(image) => image,
+        None => return false,
+    };
+
+    // Relative to neovim's working directory, which isn't neovide's when attached to a server,
+    // and ~ is expanded like in any other path option
+    let directory = nvim
+        .call_function(
+            "fnamemodify",
+            vec![Value::from(directory), Value::from(":p")],
+        )
+        .await
+        .ok()
+        .and_then(|directory| directory.as_str().map(PathBuf::from));
+    let directory = match directory {
+        Some(directory) => directory,
+        None => return true,
+    };
+    let path = tokio::task::spawn_blocking(move || save_image(&directory, image))
+        .await
+        .ok()
+        .flatten();
+    if let Some(path) = path {
+        info!("Saved the pasted image to {}", path.display());
+        let path = path.to_string_lossy().into_owned();
+        nvim.set_var("neovide_pasted_image", Value::from(path))
+            .await
+            .ok();
+        nvim.command("silent doautocmd <nomodeline> User NeovideImagePasted")
+            .await
+            .ok();
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_image_file_name() {
+        assert_eq!(
+            image_file_name(1_650_000_000_123),
+            "pasted_image_1650000000123.png"
+        );
+    }
+}
@@ -1,27 +1,48 @@
 pub mod create;
 mod events;
 mod handler;
+mod image_paste;
+mod recording;
 mod tx_wrapper;
 mod ui_commands;
 
-use std::path::Path;
+use std::io::Read;
+use std::path::{Path, PathBuf};
 use std::process::Stdio;
-use std::sync::Arc;
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+use std::time::Duration;
 
 use log::{error, info, warn};
-use nvim_rs::UiAttachOptions;
+use nvim_rs::{
+    error::{CallError, LoopError},
+    Neovim, UiAttachOptions,
+};
+use parking_lot::RwLock;
 use rmpv::Value;
 use tokio::process::Command;
-use tokio::runtime::Runtime;
+use tokio::runtime::{Builder, Runtime};
 use tokio::sync::mpsc::UnboundedReceiver;
+use tokio::task::JoinHandle;
 
 use crate::channel_utils::*;
+use crate::editor::{EditorCommand, WindowCommand};
 use crate::running_tracker::*;
-use crate::settings::*;
-use crate::{cmd_line::CmdLineSettings, error_handling::ResultPanicExplanation};
+use crate::settings::{config::config_value, *};
+use crate::{
+    cmd_line::CmdLineSettings,
+    diagnostics::set_neovim_version,
+    error_handling::{show_error_dialog, ResultPanicExplanation},
+    renderer::{high_contrast_colors, FontSettings, RendererSettings},
+    startup_profile::{mark_startup, StartupPhase},
+};
 pub use events::*;
 use handler::NeovimHandler;
+use recording::EventRecorder;
 pub use tx_wrapper::{TxWrapper, WrapTx};
+use ui_commands::SharedNeovim;
 pub use ui_commands::{start_ui_command_handler, ParallelCommand, SerialCommand, UiCommand};
 
 #[cfg(windows)]
@@ -55,12 +76,30 @@ fn platform_build_nvim_cmd(bin: &str) -> Option<Command> {
     }
 }
 
-fn build_nvim_cmd() -> Command {
-    if let Some(path) = SETTINGS.get::<CmdLineSettings>().neovim_bin {
+// Only the window being started needs neovim, so failing to find it is left to that window
+fn build_nvim_cmd() -> Result<Command, String> {
+    let settings = SETTINGS.get::<CmdLineSettings>();
+    if let Some(host) = settings.ssh {
+        // Neovim is looked up in the PATH of the remote machine
+        let bin = settings.neovim_bin.unwrap_or_else(|| "nvim".to_owned());
+        let mut cmd = Command::new("ssh");
+        cmd.args(&["-T", &host, "--", &shell_quote(&bin)]);
+        return Ok(cmd);
+    }
+    if let Some(path) = settings.neovim_bin {
+        // Names like nvim-nightly are looked up in the PATH, except for wsl where the binary
+        // lives on the other side
+        let path = match which::which(&path) {
+            Ok(resolved_path) if !settings.wsl => resolved_path.to_string_lossy().into_owned(),
+            _ => path,
+        };
         if let Some(cmd) = platform_build_nvim_cmd(&path) {
-            return cmd;
+            return Ok(cmd);
         } else {
-            warn!("NEOVIM_BIN is invalid falling back to first bin in PATH");
+            warn!(
+                "Neovim binary {} is invalid, falling back to the first nvim in PATH",
+                path
+            );
         }
     }
     #[cfg(windows)]
@@ -77,30 +116,22 @@ fn build_nvim_cmd() -> Command {
                     "-c",
                     "let \\$PATH=system(\"\\$SHELL -lic 'echo \\$PATH' 2>/dev/null\")",
                 ]);
-                return cmd;
+                return Ok(cmd);
             } else {
-                error!("nvim not found in WSL path");
-                std::process::exit(1);
+                return Err("nvim not found in WSL path".to_owned());
             }
         } else {
-            error!("wsl which nvim failed");
-            std::process::exit(1);
+            return Err("wsl which nvim failed".to_owned());
         }
     }
     if let Ok(path) = which::which("nvim") {
-        if let Some(cmd) = platform_build_nvim_cmd(path.to_str().unwrap()) {
-            cmd
-        } else {
-            error!("nvim does not have proper permissions!");
-            std::process::exit(1);
-        }
+        platform_build_nvim_cmd(path.to_str().unwrap())
+            .ok_or_else(|| "nvim does not have proper permissions!".to_owned())
     } else {
-        error!("nvim not found!");
-        std::process::exit(1);
+        Err("nvim not found!".to_owned())
     }
 }
 
-#[cfg(windows)]
 pub fn build_neovide_command(channel: u64, num_args: u64, command: &str, event: &str) -> String {
     let nargs: String = if num_args > 1 {
         "+".to_string()
@@ -108,23 +139,65 @@ pub fn build_neovide_command(channel: u64, num_args: u64, command: &str, event:
         num_args.to_string()
     };
     if num_args == 0 {
-        return format!(
+        format!(
             "command! -nargs={} {} call rpcnotify({}, 'neovide.{}')",
             nargs, command, channel, event
-        );
+        )
     } else {
-        return format!(
+        format!(
             "command! -nargs={} -complete=expression {} call rpcnotify({}, 'neovide.{}', <args>)",
             nargs, command, channel, event
-        );
-    };
+        )
+    }
 }
 
-pub fn create_nvim_command() -> Command {
-    let mut cmd = build_nvim_cmd();
+// Paths are made absolute by neovim, since relative ones and ~ mean something else in neovide's
+// working directory. Leaving the path out still picks the default one.
+pub fn build_neovide_file_command(channel: u64, command: &str, event: &str) -> String {
+    format!(
+        "command! -nargs=? -complete=file {} call rpcnotify({}, 'neovide.{}', empty(<q-args>) ? '' : fnamemodify(expand(<q-args>), ':p'))",
+        command, channel, event
+    )
+}
 
-    cmd.arg("--embed")
-        .args(SETTINGS.get::<CmdLineSettings>().neovim_args.iter());
+// Ssh hands the command to the remote shell as a single string, so every argument is quoted to
+// keep spaces and quotes in file names intact
+fn shell_quote(arg: &str) -> String {
+    format!("'{}'", arg.replace('\'', "'\\''"))
+}
+
+// Neovim in wsl doesn't understand windows paths, so files passed on the command line are
+// translated to where wsl mounts them. C:\Users\foo.txt becomes /mnt/c/Users/foo.txt
+fn wsl_path(arg: &str) -> String {
+    let bytes = arg.as_bytes();
+    let is_absolute = bytes.len() >= 3
+        && bytes[0].is_ascii_alphabetic()
+        && bytes[1] == b':'
+        && (bytes[2] == b'\\' || bytes[2] == b'/');
+    if is_absolute {
+        let drive = (bytes[0] as char).to_ascii_lowercase();
+        format!("/mnt/{}/{}", drive, arg[3..].replace('\\', "/"))
+    } else if arg.contains('\\') && Path::new(arg).exists() {
+        // Wsl starts in the current directory, so relative paths only need their separators
+        // swapped
+        arg.replace('\\', "/")
+    } else {
+        arg.to_owned()
+    }
+}
+
+pub fn create_nvim_command(neovim_args: &[String]) -> Result<Command, String> {
+    let mut cmd = build_nvim_cmd()?;
+
+    let settings = SETTINGS.get::<CmdLineSettings>();
+    let neovim_args: Vec<String> = if settings.ssh.is_some() {
+        neovim_args.iter().map(|arg| shell_quote(arg)).collect()
+    } else if cfg!(windows) && settings.wsl {
+        neovim_args.iter().map(|arg| wsl_path(arg)).collect()
+    } else {
+        neovim_args.to_vec()
+    };
+    cmd.arg("--embed").args(neovim_args.iter());
 
     info!("Starting neovim with: {:?}", cmd);
 
@@ -137,67 +210,249 @@ pub fn create_nvim_command() -> Command {
     #[cfg(windows)]
     set_windows_creation_flags(&mut cmd);
 
-    cmd
+    Ok(cmd)
+}
+
+// Fills the startup buffer with the text piped in, or a new one when files were opened as well
+const STDIN_BUFFER_LUA: &str = r#"
+    local lines = ...
+    local buffer = vim.api.nvim_get_current_buf()
+    if vim.api.nvim_buf_get_name(buffer) ~= '' or vim.bo[buffer].modified then
+        buffer = vim.api.nvim_create_buf(true, false)
+        vim.api.nvim_set_current_buf(buffer)
+    end
+    vim.api.nvim_buf_set_lines(buffer, 0, -1, false, lines)
+"#;
+
+// The embedded neovim's stdin is taken by the rpc connection, so for `neovide -` the piped text is
+// read here instead and handed over once neovim started
+fn take_stdin(neovim_args: &mut Vec<String>) -> Option<Vec<String>> {
+    let position = neovim_args.iter().position(|arg| arg == "-")?;
+    neovim_args.remove(position);
+
+    let mut text = String::new();
+    if let Err(error) = std::io::stdin().read_to_string(&mut text) {
+        error!("Could not read stdin: {}", error);
+        return None;
+    }
+    Some(text.lines().map(|line| line.to_owned()).collect())
+}
+
+const MIN_NEOVIM_VERSION: (u64, u64, u64) = (0, 4, 0);
+
+// The version is part of the metadata nvim_get_api_info returns after the channel id
+fn neovim_version(api_info: &[Value]) -> Option<(u64, u64, u64)> {
+    let metadata = api_info.get(1)?.as_map()?;
+    let version = metadata
+        .iter()
+        .find(|(key, _)| key.as_str() == Some("version"))?
+        .1
+        .as_map()?;
+    let part = |name: &str| {
+        version
+            .iter()
+            .find(|(key, _)| key.as_str() == Some(name))
+            .and_then(|(_, value)| value.as_u64())
+    };
+    Some((part("major")?, part("minor")?, part("patch")?))
+}
+
+// Neovim takes a moment until it listens on the daemon address
+const DAEMON_CONNECT_ATTEMPTS: usize = 50;
+const DAEMON_CONNECT_INTERVAL: Duration = Duration::from_millis(100);
+
+// Every daemon needs its own address, since each window starts its own neovim
+static DAEMON_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+fn daemon_address() -> String {
+    let name = format!(
+        "neovide-{}-{}",
+        std::process::id(),
+        DAEMON_COUNT.fetch_add(1, Ordering::Relaxed)
+    );
+    if cfg!(windows) {
+        format!("\\\\.\\pipe\\{}", name)
+    } else {
+        std::env::temp_dir()
+            .join(format!("{}.sock", name))
+            .to_string_lossy()
+            .into_owned()
+    }
+}
+
+/// The file holding the address of the daemon started last, for clients to attach to.
+fn daemon_address_file() -> Option<PathBuf> {
+    dirs::data_local_dir().map(|directory| directory.join("neovide").join("daemon-address"))
+}
+
+// Neovide usually runs detached from the terminal, so the address is also left in a file
+fn publish_daemon_address(address: &str) {
+    println!("Neovim daemon listening on {}", address);
+    if let Some(path) = daemon_address_file() {
+        let written = path
+            .parent()
+            .map_or(Ok(()), std::fs::create_dir_all)
+            .and_then(|_| std::fs::write(&path, address));
+        if let Err(error) = written {
+            error!(
+                "Could not write the daemon address to {}: {}",
+                path.display(),
+                error
+            );
+        }
+    }
+}
+
+// A daemon is a headless neovim listening on an address rather than one embedded over stdio, so
+// it keeps running when the window closes and can be attached to again with --server
+fn start_daemon(neovim_args: &[String]) -> std::io::Result<String> {
+    let address = daemon_address();
+    let mut cmd = build_nvim_cmd()
+        .map_err(|error| std::io::Error::new(std::io::ErrorKind::NotFound, error))?;
+    cmd.args(&["--headless", "--listen", &address])
+        .args(neovim_args.iter())
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+
+    #[cfg(windows)]
+    set_windows_creation_flags(&mut cmd);
+
+    info!("Starting neovim daemon with: {:?}", cmd);
+    cmd.spawn()?;
+    Ok(address)
 }
 
 enum ConnectionMode {
     Child,
+    Daemon,
     RemoteTcp(String),
+    Server(String),
 }
 
 fn connection_mode() -> ConnectionMode {
-    if let Some(arg) = SETTINGS.get::<CmdLineSettings>().remote_tcp {
+    if SETTINGS.get::<CmdLineSettings>().daemon {
+        ConnectionMode::Daemon
+    } else if let Some(arg) = SETTINGS.get::<CmdLineSettings>().remote_tcp {
         ConnectionMode::RemoteTcp(arg)
+    } else if let Some(address) = SETTINGS.get::<CmdLineSettings>().server {
+        ConnectionMode::Server(address)
     } else {
         ConnectionMode::Child
     }
 }
 
-async fn start_neovim_runtime(
-    #[cfg(windows)] ui_command_sender: LoggingTx<UiCommand>,
-    ui_command_receiver: UnboundedReceiver<UiCommand>,
-    redraw_event_sender: LoggingTx<RedrawEvent>,
-) {
-    #[cfg(windows)]
-    let handler = NeovimHandler::new(ui_command_sender.clone(), redraw_event_sender.clone());
-    #[cfg(not(windows))]
-    let handler = NeovimHandler::new(redraw_event_sender.clone());
-    let (nvim, io_handler) = match connection_mode() {
-        ConnectionMode::Child => create::new_child_cmd(&mut create_nvim_command(), handler).await,
-        ConnectionMode::RemoteTcp(address) => create::new_tcp(address, handler).await,
+type IoHandle = JoinHandle<Result<(), Box<LoopError>>>;
+
+// Waits between attempts to reach a remote neovim again, giving up after the last one
+const RECONNECT_DELAYS: [Duration; 6] = [
+    Duration::from_millis(500),
+    Duration::from_secs(1),
+    Duration::from_secs(2),
+    Duration::from_secs(4),
+    Duration::from_secs(8),
+    Duration::from_secs(16),
+];
+
+// Where a neovim that keeps running without neovide listens, so it can be reached again when the
+// connection breaks
+enum RemoteAddress {
+    Tcp(String),
+    Path(String),
+}
+
+impl RemoteAddress {
+    async fn connect(
+        &self,
+        handler: NeovimHandler,
+    ) -> std::io::Result<(Neovim<TxWrapper>, IoHandle)> {
+        match self {
+            RemoteAddress::Tcp(address) => create::new_tcp(address.as_str(), handler).await,
+            RemoteAddress::Path(address) => create::new_path(address, handler).await,
+        }
     }
-    .unwrap_or_explained_panic("Could not locate or start neovim process");
 
-    if nvim.get_api_info().await.is_err() {
-        error!("Cannot get neovim api info, either neovide is launched with an unknown command line option or neovim version not supported!");
-        std::process::exit(-1);
+    fn address(&self) -> &str {
+        match self {
+            RemoteAddress::Tcp(address) | RemoteAddress::Path(address) => address,
+        }
     }
+}
 
-    tokio::spawn(async move {
-        info!("Close watcher started");
-        match io_handler.await {
-            Err(join_error) => error!("Error joining IO loop: '{}'", join_error),
-            Ok(Err(error)) => {
-                if !error.is_channel_closed() {
-                    error!("Error: '{}'", error);
-                }
+// The renderer only gets the fonts for bold and italic text and the symbol map when they change,
+// parsed here rather than on every frame
+fn send_font_settings(window_command_sender: &LoggingSender<WindowCommand>) {
+    let font_settings = FontSettings::new(&SETTINGS.get::<RendererSettings>());
+    window_command_sender
+        .send(WindowCommand::FontSettingsChanged(font_settings))
+        .ok();
+}
+
+// The window stays open showing the last frame while this retries, and ui commands are dropped
+// until there is a connection again
+async fn reconnect(
+    remote_address: &RemoteAddress,
+    handler: NeovimHandler,
+    window_command_sender: LoggingSender<WindowCommand>,
+    shared_nvim: SharedNeovim,
+) -> Option<IoHandle> {
+    *shared_nvim.write() = None;
+    window_command_sender
+        .send(WindowCommand::SetConnectionLost(true))
+        .ok();
+
+    for (attempt, delay) in RECONNECT_DELAYS.iter().enumerate() {
+        window_command_sender
+            .send(WindowCommand::ShowBanner(Some(format!(
+                "Reconnecting to neovim… ({}/{})",
+                attempt + 1,
+                RECONNECT_DELAYS.len()
+            ))))
+            .ok();
+        tokio::time::sleep(*delay).await;
+        let (nvim, io_handler) = match remote_address.connect(handler.clone()).await {
+            Ok(connection) => connection,
+            Err(error) => {
+                warn!(
+                    "Could not reconnect to neovim at {}: {}",
+                    remote_address.address(),
+                    error
+                );
+                continue;
             }
-            Ok(Ok(())) => {}
         };
-        RUNNING_TRACKER.quit("neovim processed failed");
-    });
-
-    match nvim.command_output("echo has('nvim-0.4')").await.as_deref() {
-        Ok("1") => {} // This is just a guard
-        _ => {
-            error!("Neovide requires nvim version 0.4 or higher. Download the latest version here https://github.com/neovim/neovim/wiki/Installing-Neovim");
-            std::process::exit(0);
+        // A neovim that is still starting up or going away again may not answer yet, which is
+        // worth another attempt after the next delay
+        if let Err(error) = attach_ui(&nvim).await {
+            warn!(
+                "Could not attach to neovim at {}: {}",
+                remote_address.address(),
+                error
+            );
+            io_handler.abort();
+            continue;
         }
+
+        info!("Reconnected to neovim at {}", remote_address.address());
+        SETTINGS.read_initial_values(&nvim).await;
+        SETTINGS.setup_changed_listeners(&nvim).await;
+        send_font_settings(&window_command_sender);
+        *shared_nvim.write() = Some(Arc::new(nvim));
+        window_command_sender
+            .send(WindowCommand::SetConnectionLost(false))
+            .ok();
+        window_command_sender
+            .send(WindowCommand::ShowBanner(None))
+            .ok();
+        return Some(io_handler);
     }
+    None
+}
 
-    nvim.set_var("neovide", Value::Boolean(true))
-        .await
-        .unwrap_or_explained_panic("Could not communicate with neovim process");
+// Everything a connection needs before neovim draws to it, done again for each new connection to a
+// remote neovim
+async fn attach_ui(nvim: &Neovim<TxWrapper>) -> Result<(), Box<CallError>> {
+    nvim.set_var("neovide", Value::Boolean(true)).await?;
 
     if let Err(command_error) = nvim.command("runtime! ginit.vim").await {
         nvim.command(&format!(
@@ -242,6 +497,17 @@ async fn start_neovim_runtime(
         "Neovide registered to nvim with channel id {}",
         neovide_channel
     );
+    // Scripts call the neovide.* functions on this channel with rpcnotify and rpcrequest
+    nvim.set_var("neovide_channel_id", Value::from(neovide_channel))
+        .await
+        .ok();
+    // Lets a config pick a high contrast colorscheme right away, the window keeps it up to date
+    nvim.set_var(
+        "neovide_high_contrast",
+        Value::from(high_contrast_colors().is_some()),
+    )
+    .await
+    .ok();
 
     #[cfg(windows)]
     nvim.command(&build_neovide_command(
@@ -263,6 +529,327 @@ async fn start_neovim_runtime(
     .await
     .ok();
 
+    nvim.command(&build_neovide_command(
+        neovide_channel,
+        0,
+        "NeovideToggleFullscreen",
+        "toggle_fullscreen",
+    ))
+    .await
+    .ok();
+
+    nvim.command(&build_neovide_command(
+        neovide_channel,
+        0,
+        "NeovideToggleAlwaysOnTop",
+        "toggle_always_on_top",
+    ))
+    .await
+    .ok();
+
+    // These only change the variables, which then apply like any other setting. The levels go
+    // from opaque to the most transparent, and cycling wraps back around to the first.
+    nvim.command(
+        "command! NeovideCycleTransparency let g:neovide_transparency = {levels -> get(filter(copy(levels), {_, level -> level < g:neovide_transparency - 0.001}), 0, levels[0])}(get(g:, 'neovide_transparency_levels', [1.0, 0.9, 0.8, 0.7]))",
+    )
+    .await
+    .ok();
+    nvim.command("command! NeovideToggleBlur let g:neovide_window_blur = !g:neovide_window_blur")
+        .await
+        .ok();
+    // Profiles set a bundle of variables at once, see neovide.set_profile
+    nvim.command(&format!(
+        "command! -nargs=1 -complete=customlist,NeovideProfileNames NeovideProfile call rpcnotify({}, 'neovide.set_profile', <q-args>)",
+        neovide_channel
+    ))
+    .await
+    .ok();
+    // Completes the built in profiles along with the ones in g:neovide_profiles
+    nvim.exec(
+        "function! NeovideProfileNames(...)\n\
+            return uniq(sort(['quality', 'balanced', 'low-power'] + keys(get(g:, 'neovide_profiles', {}))))\n\
+        endfunction",
+        false,
+    )
+    .await
+    .ok();
+    // Presentation mode hides the chrome on the neovim side, and puts it back the way it was
+    // when leaving again, while neovide zooms and centers the grid
+    nvim.command(&format!(
+        "command! NeovidePresentation \
+            if exists('g:neovide_presentation_saved') | \
+                let [&showtabline, &laststatus, g:neovide_scrollbar, g:neovide_minimap] = g:neovide_presentation_saved | \
+                unlet g:neovide_presentation_saved | \
+                call rpcnotify({channel}, 'neovide.set_presentation', v:false) | \
+            else | \
+                let g:neovide_presentation_saved = [&showtabline, &laststatus, get(g:, 'neovide_scrollbar', v:false), get(g:, 'neovide_minimap', v:false)] | \
+                set showtabline=0 laststatus=0 | \
+                let [g:neovide_scrollbar, g:neovide_minimap] = [v:false, v:false] | \
+                call rpcnotify({channel}, 'neovide.set_presentation', v:true) | \
+            endif",
+        channel = neovide_channel
+    ))
+    .await
+    .ok();
+    // Mapped once the config ran, so they don't replace mappings of the user's own
+    let default_mappings = "\
+        if empty(maparg('<F10>', 'n')) | execute 'nnoremap <silent> <F10> :NeovideCycleTransparency<CR>' | endif | \
+        if empty(maparg('<S-F10>', 'n')) | execute 'nnoremap <silent> <S-F10> :NeovideToggleBlur<CR>' | endif";
+    nvim.command("augroup NeovideDefaultMappings | autocmd! | augroup END")
+        .await
+        .ok();
+    // A neovim that was running before neovide attached has long entered already
+    for command in &[
+        format!(
+            "autocmd NeovideDefaultMappings VimEnter * {}",
+            default_mappings
+        ),
+        format!("if v:vim_did_enter | {} | endif", default_mappings),
+    ] {
+        nvim.command(command).await.ok();
+    }
+
+    for (command, event) in &[
+        ("NeovideFocus", "focus"),
+        ("NeovideZoomIn", "zoom_in"),
+        ("NeovideZoomOut", "zoom_out"),
+        ("NeovideZoomReset", "zoom_reset"),
+    ] {
+        nvim.command(&build_neovide_command(neovide_channel, 0, command, event))
+            .await
+            .ok();
+    }
+
+    nvim.command(&build_neovide_file_command(
+        neovide_channel,
+        "NeovideScreenshot",
+        "screenshot",
+    ))
+    .await
+    .ok();
+
+    nvim.command(&build_neovide_file_command(
+        neovide_channel,
+        "NeovideDiagnostics",
+        "diagnostics",
+    ))
+    .await
+    .ok();
+
+    nvim.command(&build_neovide_file_command(
+        neovide_channel,
+        "NeovideRecord",
+        "record",
+    ))
+    .await
+    .ok();
+
+    nvim.command(&build_neovide_command(
+        neovide_channel,
+        0,
+        "NeovideStopRecording",
+        "stop_recording",
+    ))
+    .await
+    .ok();
+
+    nvim.command(&build_neovide_file_command(
+        neovide_channel,
+        "NeovideExport",
+        "export",
+    ))
+    .await
+    .ok();
+
+    nvim.command(&build_neovide_file_command(
+        neovide_channel,
+        "NeovideNewWindow",
+        "new_window",
+    ))
+    .await
+    .ok();
+
+    // Opens what neovim wrote to stderr in a scratch buffer, like errors from init.lua that came
+    // up before anything could be shown
+    nvim.command(&format!(
+        "command! NeovideProcessOutput new | setlocal buftype=nofile bufhidden=wipe noswapfile | call setline(1, rpcrequest({}, 'neovide.process_output'))",
+        neovide_channel
+    ))
+    .await
+    .ok();
+
+    nvim.command(&format!(
+        "command! NeovidePasteImage call rpcrequest({}, 'neovide.paste_image')",
+        neovide_channel
+    ))
+    .await
+    .ok();
+
+    // Restarting only makes sense for a neovim neovide started itself. Buffers with changes would
+    // be lost, so those have to be dealt with first.
+    let cmd_line_settings = SETTINGS.get::<CmdLineSettings>();
+    if !cmd_line_settings.is_remote() {
+        nvim.command(&format!(
+            "command! -nargs=* -complete=file NeovideRestart if empty(getbufinfo({{'bufmodified': 1}})) | call rpcnotify({}, 'neovide.restart', [<f-args>]) | else | echoerr 'Save or discard the changes before restarting' | endif",
+            neovide_channel
+        ))
+        .await
+        .ok();
+    }
+
+    if cmd_line_settings.restores_session() {
+        nvim.command("augroup NeovideSession | autocmd! | augroup END")
+            .await
+            .ok();
+        nvim.command(&save_session_command()).await.ok();
+    }
+
+    // Keeps the window title and the Discord presence up to date with the current buffer.
+    // BufModifiedSet is missing from older neovim versions, where the modified state only updates
+    // on the other events.
+    let buffer_changed_notification = format!(
+        "call rpcnotify({}, 'neovide.buffer_changed', expand('%:p'), &modified, getcwd(), &filetype)",
+        neovide_channel
+    );
+    nvim.command("augroup NeovideBufferChanged | autocmd! | augroup END")
+        .await
+        .ok();
+    for event in &[
+        "BufEnter,BufFilePost,BufWritePost,DirChanged,FileType",
+        "BufModifiedSet",
+    ] {
+        nvim.command(&format!(
+            "autocmd NeovideBufferChanged {} * {}",
+            event, buffer_changed_notification
+        ))
+        .await
+        .ok();
+    }
+
+    // Neovide serves the clipboard itself, so "+y and "+p work without xclip, wl-copy or
+    // win32yank. A g:clipboard the user already set is kept, for example in a server neovide
+    // attached to after its config ran, while one from an earlier connection gets the new channel.
+    nvim.command(&format!(
+        "if !exists('g:clipboard') || get(g:clipboard, 'name', '') ==# 'neovide' | let g:clipboard = {{ 'name': 'neovide', \
+            'copy': {{ \
+                '+': {{ lines, regtype -> rpcnotify({0}, 'neovide.set_clipboard', lines, '+', regtype) }}, \
+                '*': {{ lines, regtype -> rpcnotify({0}, 'neovide.set_clipboard', lines, '*', regtype) }} \
+            }}, \
+            'paste': {{ \
+                '+': {{ -> rpcrequest({0}, 'neovide.get_clipboard', '+') }}, \
+                '*': {{ -> rpcrequest({0}, 'neovide.get_clipboard', '*') }} \
+            }} \
+        }} | endif",
+        neovide_channel
+    ))
+    .await
+    .ok();
+
+    // Copies the lines with their highlighting, using the html the TOhtml plugin builds for them.
+    // Mappings with <Cmd> run it without a range while the lines are still selected, and the
+    // html uses the guifont unless g:html_font picks another one.
+    nvim.exec(
+        &format!(
+            r#"function! NeovideCopyRich(line1, line2)
+                let [first, last] = [a:line1, a:line2]
+                if mode() =~# "^[vV\<C-v>]"
+                    let [first, last] = sort([line('v'), line('.')], 'n')
+                    execute "normal! \<Esc>"
+                endif
+                let text = join(getline(first, last), "
+")
+                let set_font = !exists('g:html_font') && !empty(&guifont)
+                if set_font
+                    let g:html_font = split(split(&guifont, ':')[0], ',')
+                endif
+                try
+                    execute first . ',' . last . 'TOhtml'
+                    let html = join(getline(1, '$'), "
+")
+                    bwipeout!
+                finally
+                    if set_font
+                        unlet g:html_font
+                    endif
+                endtry
+                call rpcnotify({}, 'neovide.set_rich_clipboard', html, text)
+            endfunction"#,
+            neovide_channel
+        ),
+        false,
+    )
+    .await
+    .ok();
+    nvim.command("command! -range NeovideCopyRich call NeovideCopyRich(<line1>, <line2>)")
+        .await
+        .ok();
+
+    // Hides the pointer while typing like the hide_mouse_when_typing setting does, in the neovim
+    // versions that have the mousehide option
+    let mousehide_notification = format!(
+        "call rpcnotify({}, 'neovide.mousehide', exists('+mousehide') && &mousehide)",
+        neovide_channel
+    );
+    nvim.command("augroup NeovideMouseHide | autocmd! | augroup END")
+        .await
+        .ok();
+    for event in &["VimEnter *", "OptionSet mousehide"] {
+        nvim.command(&format!(
+            "autocmd NeovideMouseHide {} {}",
+            event, mousehide_notification
+        ))
+        .await
+        .ok();
+    }
+
+    // Mouse moves are only sent when the mousemoveevent option asks for them, since they would
+    // otherwise wake neovim up for nothing on every move
+    let mousemoveevent_notification = format!(
+        "call rpcnotify({}, 'neovide.mousemoveevent', exists('+mousemoveevent') && &mousemoveevent)",
+        neovide_channel
+    );
+    nvim.command("augroup NeovideMouseMoveEvent | autocmd! | augroup END")
+        .await
+        .ok();
+    for event in &["VimEnter *", "OptionSet mousemoveevent"] {
+        nvim.command(&format!(
+            "autocmd NeovideMouseMoveEvent {} {}",
+            event, mousemoveevent_notification
+        ))
+        .await
+        .ok();
+    }
+
+    // Multi clicks are counted by neovide, so it needs the mousetime they have to fall within
+    let mousetime_notification = format!(
+        "call rpcnotify({}, 'neovide.mousetime', &mousetime)",
+        neovide_channel
+    );
+    nvim.command("augroup NeovideMouseTime | autocmd! | augroup END")
+        .await
+        .ok();
+    for event in &["VimEnter *", "OptionSet mousetime"] {
+        nvim.command(&format!(
+            "autocmd NeovideMouseTime {} {}",
+            event, mousetime_notification
+        ))
+        .await
+        .ok();
+    }
+
+    // A project font replaces the one from the vim config, so it is set once that was sourced
+    if let Some(font) = config_value("font") {
+        let set_font = format!("let &guifont = '{}'", font.replace('\'', "''"));
+        // The autocmd would take the rest of the line, so it is behind an execute
+        nvim.command(&format!(
+            "if v:vim_did_enter | {} | else | execute \"autocmd VimEnter * ++once {}\" | endif",
+            set_font,
+            set_font.replace('\\', "\\\\").replace('"', "\\\"")
+        ))
+        .await
+        .ok();
+    }
+
     nvim.set_option("lazyredraw", Value::Boolean(false))
         .await
         .ok();
@@ -275,18 +862,261 @@ async fn start_neovim_runtime(
     let mut options = UiAttachOptions::new();
     options.set_linegrid_external(true);
     options.set_multigrid_external(settings.multi_grid);
+    options.set_popupmenu_external(settings.external_popupmenu);
     options.set_rgb(true);
     nvim.ui_attach(geometry.width as i64, geometry.height as i64, &options)
+        .await?;
+    mark_startup(StartupPhase::UiAttached);
+
+    info!("Neovim process attached");
+    Ok(())
+}
+
+async fn connect_remote(
+    remote_address: RemoteAddress,
+    handler: NeovimHandler,
+) -> (
+    Neovim<TxWrapper>,
+    IoHandle,
+    Option<JoinHandle<Option<String>>>,
+    Option<RemoteAddress>,
+) {
+    // The remote neovim keeps running after neovide exits, so there is no exit to wait on
+    let (nvim, io_handler) = remote_address
+        .connect(handler)
+        .await
+        .unwrap_or_explained_panic(&format!(
+            "Could not connect to neovim at {}, is it running with --listen {}?",
+            remote_address.address(),
+            remote_address.address()
+        ));
+    (nvim, io_handler, None, Some(remote_address))
+}
+
+// How often reports about invalid settings are passed on. Fonts are only found to be missing
+// while drawing, so the reports can't be shown as a response to setting them.
+const SETTING_REPORT_INTERVAL: Duration = Duration::from_millis(500);
+
+fn start_setting_report_handler(nvim: SharedNeovim, running_tracker: RunningTracker) {
+    tokio::spawn(async move {
+        while running_tracker.is_running() {
+            tokio::time::sleep(SETTING_REPORT_INTERVAL).await;
+            let current_nvim = nvim.read().clone();
+            let current_nvim = match current_nvim {
+                Some(current_nvim) => current_nvim,
+                None => continue,
+            };
+            for message in take_invalid_setting_reports() {
+                current_nvim
+                    .command(&format!(
+                        "echohl WarningMsg | echomsg '{}' | echohl None",
+                        message.replace('\'', "''")
+                    ))
+                    .await
+                    .ok();
+            }
+        }
+    });
+}
+
+async fn start_neovim_runtime(
+    neovim_args: Vec<String>,
+    stdin_lines: Option<Vec<String>>,
+    running_tracker: RunningTracker,
+    #[cfg(windows)] ui_command_sender: LoggingTx<UiCommand>,
+    ui_command_receiver: UnboundedReceiver<UiCommand>,
+    editor_command_sender: LoggingBoundedTx<EditorCommand>,
+    window_command_sender: LoggingSender<WindowCommand>,
+) {
+    let cmd_line_settings = SETTINGS.get::<CmdLineSettings>();
+    if let Some(path) = cmd_line_settings.replay {
+        recording::replay(
+            path,
+            ui_command_receiver,
+            editor_command_sender,
+            running_tracker,
+        )
+        .await;
+        return;
+    }
+    let recorder = cmd_line_settings.record_events.and_then(|path| {
+        EventRecorder::create(&path)
+            .map_err(|error| error!("Could not record events to {}: {}", path, error))
+            .ok()
+    });
+
+    let process_output = create::ProcessOutput::default();
+    let reconnect_window_command_sender = window_command_sender.clone();
+    let settings_window_command_sender = window_command_sender.clone();
+    #[cfg(windows)]
+    let handler = NeovimHandler::new(
+        ui_command_sender.clone(),
+        editor_command_sender.clone(),
+        window_command_sender,
+        process_output.clone(),
+        recorder.clone(),
+    );
+    #[cfg(not(windows))]
+    let handler = NeovimHandler::new(
+        editor_command_sender.clone(),
+        window_command_sender,
+        process_output.clone(),
+        recorder.clone(),
+    );
+    let reconnect_handler = handler.clone();
+    let (nvim, io_handler, exit_handler, remote_address) = match connection_mode() {
+        ConnectionMode::Child => {
+            let mut cmd = match create_nvim_command(&neovim_args) {
+                Ok(cmd) => cmd,
+                Err(message) => {
+                    error!("{}", message);
+                    show_error_dialog("Could not start neovim", &message);
+                    running_tracker.quit("neovim could not be found");
+                    return;
+                }
+            };
+            create::new_child_cmd(&mut cmd, handler, process_output)
+                .await
+                .map(|(nvim, io_handler, exit_handler)| {
+                    (nvim, io_handler, Some(exit_handler), None)
+                })
+                .unwrap_or_explained_panic("Could not locate or start neovim process")
+        }
+        ConnectionMode::Daemon => {
+            let address = start_daemon(&neovim_args)
+                .unwrap_or_explained_panic("Could not locate or start neovim process");
+            let mut connection = create::new_path(&address, handler.clone()).await;
+            for _ in 0..DAEMON_CONNECT_ATTEMPTS {
+                if connection.is_ok() {
+                    break;
+                }
+                tokio::time::sleep(DAEMON_CONNECT_INTERVAL).await;
+                connection = create::new_path(&address, handler.clone()).await;
+            }
+            let (nvim, io_handler) = connection.unwrap_or_explained_panic(&format!(
+                "Could not connect to the neovim daemon at {}",
+                address
+            ));
+            publish_daemon_address(&address);
+            (nvim, io_handler, None, Some(RemoteAddress::Path(address)))
+        }
+        // The remote neovim keeps running after neovide exits, so there is no exit to wait on
+        ConnectionMode::RemoteTcp(address) => {
+            connect_remote(RemoteAddress::Tcp(address), handler).await
+        }
+        ConnectionMode::Server(address) => {
+            connect_remote(RemoteAddress::Path(address), handler).await
+        }
+    };
+    // Spawned or connected to, whether or not neovim is done starting up
+    mark_startup(StartupPhase::NeovimConnected);
+
+    let api_info = match nvim.get_api_info().await {
+        Ok(api_info) => api_info,
+        Err(_) => {
+            let message = "Cannot get neovim api info, either neovide is launched with an unknown command line option or neovim version not supported!";
+            error!("{}", message);
+            show_error_dialog("Could not start neovim", message);
+            running_tracker.quit("neovim api info is unavailable");
+            return;
+        }
+    };
+    let version = neovim_version(&api_info);
+    if let Some((major, minor, patch)) = version {
+        set_neovim_version(format!("v{}.{}.{}", major, minor, patch));
+    }
+    match version {
+        Some(version) if version >= MIN_NEOVIM_VERSION => {}
+        version => {
+            let found = version
+                .map(|(major, minor, patch)| format!("{}.{}.{}", major, minor, patch))
+                .unwrap_or_else(|| "an unknown version".to_owned());
+            let (major, minor, patch) = MIN_NEOVIM_VERSION;
+            let message = format!(
+                "Neovide requires nvim >= {}.{}.{}, found {}. Download the latest version here https://github.com/neovim/neovim/wiki/Installing-Neovim",
+                major, minor, patch, found
+            );
+            error!("{}", message);
+            show_error_dialog("Neovim is too old", &message);
+            running_tracker.quit("neovim is too old");
+            return;
+        }
+    }
+
+    let shared_nvim: SharedNeovim = Arc::new(RwLock::new(None));
+
+    let close_watcher_running_tracker = running_tracker.clone();
+    let close_watcher_nvim = shared_nvim.clone();
+    tokio::spawn(async move {
+        info!("Close watcher started");
+        let mut io_handler = io_handler;
+        loop {
+            let connection_broke = match io_handler.await {
+                Err(join_error) => {
+                    error!("Error joining IO loop: '{}'", join_error);
+                    false
+                }
+                Ok(Err(error)) => {
+                    if error.is_channel_closed() {
+                        false
+                    } else {
+                        error!("Error: '{}'", error);
+                        true
+                    }
+                }
+                Ok(Ok(())) => false,
+            };
+
+            // A neovim that quit closes the channel, only a broken connection is worth retrying
+            let remote_address = match &remote_address {
+                Some(remote_address) if connection_broke => remote_address,
+                _ => break,
+            };
+            match reconnect(
+                remote_address,
+                reconnect_handler.clone(),
+                reconnect_window_command_sender.clone(),
+                close_watcher_nvim.clone(),
+            )
+            .await
+            {
+                Some(new_io_handler) => io_handler = new_io_handler,
+                None => break,
+            }
+        }
+
+        let exit_error = match exit_handler {
+            Some(exit_handler) => exit_handler.await.ok().flatten(),
+            None => None,
+        };
+        match exit_error {
+            Some(exit_error) => {
+                error!("{}", exit_error);
+                close_watcher_running_tracker.quit_with_error("neovim crashed", exit_error);
+            }
+            None => close_watcher_running_tracker.quit("neovim processed failed"),
+        }
+    });
+
+    attach_ui(&nvim)
         .await
         .unwrap_or_explained_panic("Could not attach ui to neovim process");
 
-    info!("Neovim process attached");
+    if let Some(lines) = stdin_lines {
+        let lines = lines.into_iter().map(Value::from).collect();
+        nvim.exec_lua(STDIN_BUFFER_LUA, vec![Value::Array(lines)])
+            .await
+            .ok();
+    }
 
     let nvim = Arc::new(nvim);
+    *shared_nvim.write() = Some(nvim.clone());
 
-    start_ui_command_handler(ui_command_receiver, nvim.clone());
+    start_setting_report_handler(shared_nvim.clone(), running_tracker.clone());
+    start_ui_command_handler(ui_command_receiver, shared_nvim, running_tracker);
     SETTINGS.read_initial_values(&nvim).await;
     SETTINGS.setup_changed_listeners(&nvim).await;
+    send_font_settings(&settings_window_command_sender);
 }
 
 pub struct Bridge {
@@ -294,16 +1124,70 @@ pub struct Bridge {
 }
 
 pub fn start_bridge(
+    mut neovim_args: Vec<String>,
+    settings: Arc<Settings>,
+    running_tracker: RunningTracker,
     #[cfg(windows)] ui_command_sender: LoggingTx<UiCommand>,
     ui_command_receiver: UnboundedReceiver<UiCommand>,
-    redraw_event_sender: LoggingTx<RedrawEvent>,
+    editor_command_sender: LoggingBoundedTx<EditorCommand>,
+    window_command_sender: LoggingSender<WindowCommand>,
 ) -> Bridge {
-    let runtime = Runtime::new().unwrap();
+    let stdin_lines = take_stdin(&mut neovim_args);
+    // Every thread of the runtime handles this window's neovim, so its settings go to the window
+    let runtime = Builder::new_multi_thread()
+        .enable_all()
+        .on_thread_start(move || use_settings(settings.clone()))
+        .build()
+        .unwrap();
     runtime.spawn(start_neovim_runtime(
+        neovim_args,
+        stdin_lines,
+        running_tracker,
         #[cfg(windows)]
         ui_command_sender,
         ui_command_receiver,
-        redraw_event_sender,
+        editor_command_sender,
+        window_command_sender,
     ));
     Bridge { _runtime: runtime }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wsl_path() {
+        assert_eq!(wsl_path("C:\\Users\\foo.txt"), "/mnt/c/Users/foo.txt");
+        assert_eq!(wsl_path("d:/notes.md"), "/mnt/d/notes.md");
+        // Flags and their values are left alone
+        assert_eq!(wsl_path("-u"), "-u");
+        assert_eq!(wsl_path("+10"), "+10");
+    }
+
+    #[test]
+    fn test_neovim_version() {
+        let version = Value::Map(vec![
+            (Value::from("major"), Value::from(0)),
+            (Value::from("minor"), Value::from(5)),
+            (Value::from("patch"), Value::from(1)),
+        ]);
+        let metadata = Value::Map(vec![(Value::from("version"), version)]);
+        assert_eq!(neovim_version(&[Value::from(1), metadata]), Some((0, 5, 1)));
+        assert_eq!(neovim_version(&[Value::from(1)]), None);
+    }
+
+    #[test]
+    fn test_file_commands_resolve_paths_in_neovim() {
+        assert_eq!(
+            build_neovide_file_command(3, "NeovideScreenshot", "screenshot"),
+            "command! -nargs=? -complete=file NeovideScreenshot call rpcnotify(3, 'neovide.screenshot', empty(<q-args>) ? '' : fnamemodify(expand(<q-args>), ':p'))"
+        );
+    }
+
+    #[test]
+    fn test_shell_quote() {
+        assert_eq!(shell_quote("my file.txt"), "'my file.txt'");
+        assert_eq!(shell_quote("it's"), "'it'\\''s'");
+    }
+}
@@ -0,0 +1,143 @@
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use log::{error, info};
+use parking_lot::Mutex;
+use rmpv::{decode::read_value, encode::write_value, Value};
+use tokio::sync::mpsc::UnboundedReceiver;
+
+use super::events::parse_redraw_event;
+use super::ui_commands::UiCommand;
+use crate::channel_utils::*;
+use crate::editor::EditorCommand;
+use crate::running_tracker::RunningTracker;
+
+/// Writes the redraw notifications neovim sends to a file, so the same stream can be fed to the
+/// editor again later with `--replay`.
+#[derive(Clone)]
+pub struct EventRecorder {
+    file: Arc<Mutex<BufWriter<File>>>,
+    start: Instant,
+}
+
+impl EventRecorder {
+    pub fn create(path: &str) -> io::Result<EventRecorder> {
+        Ok(EventRecorder {
+            file: Arc::new(Mutex::new(BufWriter::new(File::create(path)?))),
+            start: Instant::now(),
+        })
+    }
+
+    // Each record is a msgpack array of the milliseconds since the recording started and the
+    // arguments of the notification. Records are flushed right away so a crash keeps the events
+    // that led up to it.
+    pub fn record(&self, arguments: &[Value]) {
+        let record = Value::Array(vec![
+            Value::from(self.start.elapsed().as_millis() as u64),
+            Value::Array(arguments.to_vec()),
+        ]);
+        let mut file = self.file.lock();
+        let result = write_value(&mut *file, &record)
+            .map_err(io::Error::from)
+            .and_then(|_| file.flush());
+        if let Err(error) = result {
+            error!("Could not record redraw event: {}", error);
+        }
+    }
+}
+
+fn parse_record(record: Value) -> Option<(Duration, Vec<Value>)> {
+    match record {
+        Value::Array(mut parts) if parts.len() == 2 => {
+            let arguments = match parts.pop() {
+                Some(Value::Array(arguments)) => arguments,
+                _ => return None,
+            };
+            let time = parts[0].as_u64()?;
+            Some((Duration::from_millis(time), arguments))
+        }
+        _ => None,
+    }
+}
+
+// A recording that was cut off by a crash ends in a partial record, which is skipped
+fn read_records(path: &str) -> io::Result<Vec<(Duration, Vec<Value>)>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut records = Vec::new();
+    while let Ok(record) = read_value(&mut reader) {
+        match parse_record(record) {
+            Some(record) => records.push(record),
+            None => error!("Skipping malformed record in {}", path),
+        }
+    }
+    Ok(records)
+}
+
+/// Sends the recorded redraw events to the editor at the pace they were recorded with, in place
+/// of a neovim. Input has nowhere to go, and the window stays open after the last event until it
+/// is closed.
+pub async fn replay(
+    path: String,
+    mut ui_command_receiver: UnboundedReceiver<UiCommand>,
+    editor_command_sender: LoggingBoundedTx<EditorCommand>,
+    running_tracker: RunningTracker,
+) {
+    let records = match read_records(&path) {
+        Ok(records) => records,
+        Err(error) => {
+            running_tracker.quit_with_error(
+                "replay could not be read",
+                format!("Could not read {}: {}", path, error),
+            );
+            return;
+        }
+    };
+
+    tokio::spawn(async move { while ui_command_receiver.recv().await.is_some() {} });
+
+    info!("Replaying {} records from {}", records.len(), path);
+    let start = tokio::time::Instant::now();
+    for (time, arguments) in records {
+        tokio::time::sleep_until(start + time).await;
+        for events in arguments {
+            let parsed_events = match parse_redraw_event(events) {
+                Ok(parsed_events) => parsed_events,
+                Err(error) => {
+                    error!("Could not parse recorded event: {}", error);
+                    continue;
+                }
+            };
+            for parsed_event in parsed_events {
+                editor_command_sender
+                    .send(EditorCommand::NeovimRedrawEvent(parsed_event))
+                    .await
+                    .ok();
+            }
+        }
+    }
+    info!("Replay finished");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_records_round_trip() {
+        let path = std::env::temp_dir().join(format!("neovide-recording-{}", std::process::id()));
+        let path = path.to_string_lossy().into_owned();
+        let recorder = EventRecorder::create(&path).expect("Could not create recording");
+        recorder.record(&[Value::Array(vec![Value::from("flush")])]);
+        recorder.record(&[Value::Array(vec![Value::from("mouse_on")])]);
+
+        let records = read_records(&path).expect("Could not read recording");
+        std::fs::remove_file(&path).ok();
+        assert_eq!(records.len(), 2);
+        assert_eq!(
+            records[1].1,
+            vec![Value::Array(vec![Value::from("mouse_on")])]
+        );
+    }
+}
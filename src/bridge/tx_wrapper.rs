@@ -8,10 +8,19 @@ use tokio::{
     process::ChildStdin,
 };
 
+#[cfg(windows)]
+use tokio::net::windows::named_pipe::NamedPipeClient;
+#[cfg(unix)]
+use tokio::net::UnixStream;
+
 #[pin_project(project = TxProj)]
 pub enum TxWrapper {
     Child(#[pin] ChildStdin),
     Tcp(#[pin] WriteHalf<TcpStream>),
+    #[cfg(unix)]
+    Unix(#[pin] WriteHalf<UnixStream>),
+    #[cfg(windows)]
+    Pipe(#[pin] WriteHalf<NamedPipeClient>),
 }
 
 impl futures::io::AsyncWrite for TxWrapper {
@@ -23,6 +32,10 @@ impl futures::io::AsyncWrite for TxWrapper {
         match self.project() {
             TxProj::Child(inner) => inner.poll_write(cx, buf),
             TxProj::Tcp(inner) => inner.poll_write(cx, buf),
+            #[cfg(unix)]
+            TxProj::Unix(inner) => inner.poll_write(cx, buf),
+            #[cfg(windows)]
+            TxProj::Pipe(inner) => inner.poll_write(cx, buf),
         }
     }
 
@@ -30,6 +43,10 @@ impl futures::io::AsyncWrite for TxWrapper {
         match self.project() {
             TxProj::Child(inner) => inner.poll_flush(cx),
             TxProj::Tcp(inner) => inner.poll_flush(cx),
+            #[cfg(unix)]
+            TxProj::Unix(inner) => inner.poll_flush(cx),
+            #[cfg(windows)]
+            TxProj::Pipe(inner) => inner.poll_flush(cx),
         }
     }
 
@@ -37,6 +54,10 @@ impl futures::io::AsyncWrite for TxWrapper {
         match self.project() {
             TxProj::Child(inner) => inner.poll_shutdown(cx),
             TxProj::Tcp(inner) => inner.poll_shutdown(cx),
+            #[cfg(unix)]
+            TxProj::Unix(inner) => inner.poll_shutdown(cx),
+            #[cfg(windows)]
+            TxProj::Pipe(inner) => inner.poll_shutdown(cx),
         }
     }
 }
@@ -56,3 +77,17 @@ impl WrapTx for WriteHalf<TcpStream> {
         TxWrapper::Tcp(self)
     }
 }
+
+#[cfg(unix)]
+impl WrapTx for WriteHalf<UnixStream> {
+    fn wrap_tx(self) -> TxWrapper {
+        TxWrapper::Unix(self)
+    }
+}
+
+#[cfg(windows)]
+impl WrapTx for WriteHalf<NamedPipeClient> {
+    fn wrap_tx(self) -> TxWrapper {
+        TxWrapper::Pipe(self)
+    }
+}
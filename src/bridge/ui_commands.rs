@@ -1,19 +1,64 @@
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
-#[cfg(windows)]
-use log::error;
 use log::trace;
+#[cfg(windows)]
+use log::{error, warn};
 
 use nvim_rs::Neovim;
+use parking_lot::RwLock;
+use rmpv::Value;
 use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
 
+use super::image_paste::paste_image;
 use crate::bridge::TxWrapper;
-use crate::running_tracker::RUNNING_TRACKER;
+use crate::clipboard;
+use crate::running_tracker::RunningTracker;
 #[cfg(windows)]
 use crate::windows_utils::{
     register_rightclick_directory, register_rightclick_file, unregister_rightclick,
 };
 
+/// The connection ui commands are sent over. A remote neovim gets a new connection when the old one
+/// broke, and there is none in between.
+pub type SharedNeovim = Arc<RwLock<Option<Arc<Neovim<TxWrapper>>>>>;
+
+// Large pastes are split up so neovim can keep redrawing in between, and can cancel the paste
+const PASTE_CHUNK_SIZE: usize = 64 * 1024;
+
+// Escaped so spaces and the like in the path aren't taken as part of a command
+async fn escape_path(nvim: &Neovim<TxWrapper>, path: &Path) -> Option<String> {
+    let path = path.to_string_lossy().into_owned();
+    nvim.call_function("fnameescape", vec![Value::from(path)])
+        .await
+        .ok()
+        .and_then(|path| path.as_str().map(|path| path.to_owned()))
+}
+
+// Splits the text into chunks of about the given size, without cutting through a character
+fn paste_chunks(text: &str, chunk_size: usize) -> Vec<&str> {
+    let mut chunks = Vec::new();
+    let mut rest = text;
+    while rest.len() > chunk_size {
+        let mut end = chunk_size;
+        while !rest.is_char_boundary(end) {
+            end += 1;
+        }
+        let (chunk, remaining) = rest.split_at(end);
+        chunks.push(chunk);
+        rest = remaining;
+    }
+    chunks.push(rest);
+    chunks
+}
+
+// Moves the cursor of the given window to the line and centers the view around it
+const SCROLLBAR_JUMP_LUA: &str = r#"
+    local window, line = ...
+    vim.api.nvim_win_set_cursor(window, { line, 0 })
+    vim.api.nvim_win_call(window, function() vim.cmd("normal! zz") end)
+"#;
+
 // Serial commands are any commands which must complete before the next value is sent. This
 // includes keyboard and mouse input which would cuase problems if sent out of order.
 #[derive(Debug, Clone)]
@@ -38,11 +83,25 @@ pub enum SerialCommand {
         position: (u32, u32),
         modifier_string: String,
     },
+    MouseMove {
+        grid_id: u64,
+        position: (u32, u32),
+        modifier_string: String,
+    },
+    ScrollbarJump {
+        window_handle: u64,
+        line: u64,
+    },
+    // Quitting waits for the input sent before it, so nothing typed right before closing is lost
+    Quit,
 }
 
 impl SerialCommand {
     async fn execute(self, nvim: &Neovim<TxWrapper>) {
         match self {
+            SerialCommand::Quit => {
+                nvim.command("qa!").await.ok();
+            }
             SerialCommand::Keyboard(input_command) => {
                 trace!("Keyboard Input Sent: {}", input_command);
                 nvim.input(&input_command).await.expect("Input failed");
@@ -99,18 +158,64 @@ impl SerialCommand {
                 .await
                 .expect("Mouse Drag Failed");
             }
+            SerialCommand::MouseMove {
+                grid_id,
+                position: (grid_x, grid_y),
+                modifier_string,
+            } => {
+                nvim.input_mouse(
+                    "move",
+                    "",
+                    &modifier_string,
+                    grid_id as i64,
+                    grid_y as i64,
+                    grid_x as i64,
+                )
+                .await
+                .expect("Mouse Move Failed");
+            }
+            SerialCommand::ScrollbarJump {
+                window_handle,
+                line,
+            } => {
+                nvim.exec_lua(
+                    SCROLLBAR_JUMP_LUA,
+                    vec![Value::from(window_handle), Value::from(line)],
+                )
+                .await
+                .ok();
+            }
         }
     }
 }
 
 #[derive(Debug, Clone)]
 pub enum ParallelCommand {
-    Quit,
     Resize {
         width: u64,
         height: u64,
     },
-    FileDrop(String),
+    ResizeGrid {
+        grid_id: u64,
+        width: u64,
+        height: u64,
+    },
+    CloseWindow {
+        window_handle: u64,
+    },
+    // Commands can open prompts which wait for keyboard input, so they mustn't hold up the serial
+    // commands
+    Command(String),
+    // Pastes the system clipboard the way neovim pastes from a terminal, in chunks
+    PasteClipboard,
+    FileDrop {
+        path: String,
+        command: String,
+    },
+    // A file or folder opened from Finder or the Dock
+    OpenDocument {
+        path: String,
+    },
     FocusLost,
     FocusGained,
     #[cfg(windows)]
@@ -122,13 +227,33 @@ pub enum ParallelCommand {
 impl ParallelCommand {
     async fn execute(self, nvim: &Neovim<TxWrapper>) {
         match self {
-            ParallelCommand::Quit => {
-                nvim.command("qa!").await.ok();
-            }
             ParallelCommand::Resize { width, height } => nvim
                 .ui_try_resize(width.max(10) as i64, height.max(3) as i64)
                 .await
                 .expect("Resize failed"),
+            ParallelCommand::ResizeGrid {
+                grid_id,
+                width,
+                height,
+            } => {
+                // The grid can be gone by the time its external window was resized, neovim
+                // just refuses then
+                if let Err(error) = nvim
+                    .ui_try_resize_grid(grid_id as i64, width.max(1) as i64, height.max(1) as i64)
+                    .await
+                {
+                    warn!("Could not resize grid {}: {}", grid_id, error);
+                }
+            }
+            ParallelCommand::CloseWindow { window_handle } => {
+                // Neovim refuses to close the last window, which leaves its external window open
+                nvim.exec_lua(
+                    "vim.api.nvim_win_close(..., false)",
+                    vec![Value::from(window_handle)],
+                )
+                .await
+                .ok();
+            }
             ParallelCommand::FocusLost => nvim
                 .command("if exists('#FocusLost') | doautocmd <nomodeline> FocusLost | endif")
                 .await
@@ -137,8 +262,73 @@ impl ParallelCommand {
                 .command("if exists('#FocusGained') | doautocmd <nomodeline> FocusGained | endif")
                 .await
                 .expect("Focus Gained Failed"),
-            ParallelCommand::FileDrop(path) => {
-                nvim.command(format!("e {}", path).as_str()).await.ok();
+            ParallelCommand::Command(command) => {
+                trace!("Command Sent: {}", command);
+                nvim.command(&command).await.ok();
+            }
+            ParallelCommand::PasteClipboard => {
+                let text = tokio::task::spawn_blocking(|| clipboard::get_lines("+").join("\n"))
+                    .await
+                    .unwrap_or_default();
+                // Text wins, since programs like office suites put a picture of what was copied
+                // next to the text
+                if text.is_empty() && paste_image(nvim).await {
+                    return;
+                }
+                let chunks = paste_chunks(&text, PASTE_CHUNK_SIZE);
+                let last = chunks.len() - 1;
+                for (index, chunk) in chunks.into_iter().enumerate() {
+                    // A single chunk is a whole paste, otherwise the phases mark the start,
+                    // middle and end
+                    let phase = match (index, last) {
+                        (_, 0) => -1,
+                        (0, _) => 1,
+                        (index, last) if index == last => 3,
+                        _ => 2,
+                    };
+                    // Neovim returns false when the paste got cancelled
+                    match nvim.paste(chunk, false, phase).await {
+                        Ok(true) => {}
+                        _ => break,
+                    }
+                }
+            }
+            ParallelCommand::FileDrop { path, command } => {
+                // Escaped so spaces and the like in the path aren't taken as part of the command
+                let escaped_path = nvim
+                    .call_function("fnameescape", vec![Value::from(path)])
+                    .await
+                    .ok()
+                    .and_then(|path| path.as_str().map(|path| path.to_owned()));
+                if let Some(escaped_path) = escaped_path {
+                    nvim.command(&format!("{} {}", command, escaped_path))
+                        .await
+                        .ok();
+                }
+            }
+            ParallelCommand::OpenDocument { path } => {
+                // Folders become the working directory, files open in a tab working in their
+                // folder. It is one command so the tcd can't run before the file opened.
+                let path = PathBuf::from(path);
+                let command = if path.is_dir() {
+                    escape_path(nvim, &path)
+                        .await
+                        .map(|path| format!("cd {}", path))
+                } else {
+                    let directory = path.parent().unwrap_or(&path);
+                    match (
+                        escape_path(nvim, &path).await,
+                        escape_path(nvim, directory).await,
+                    ) {
+                        (Some(path), Some(directory)) => {
+                            Some(format!("tab drop {} | tcd {}", path, directory))
+                        }
+                        _ => None,
+                    }
+                };
+                if let Some(command) = command {
+                    nvim.command(&command).await.ok();
+                }
             }
             #[cfg(windows)]
             ParallelCommand::RegisterRightClick => {
@@ -192,39 +382,60 @@ impl From<ParallelCommand> for UiCommand {
 
 pub fn start_ui_command_handler(
     mut ui_command_receiver: UnboundedReceiver<UiCommand>,
-    nvim: Arc<Neovim<TxWrapper>>,
+    nvim: SharedNeovim,
+    running_tracker: RunningTracker,
 ) {
     let (serial_tx, mut serial_rx) = unbounded_channel::<SerialCommand>();
     let ui_command_nvim = nvim.clone();
+    let ui_command_running_tracker = running_tracker.clone();
     tokio::spawn(async move {
-        while RUNNING_TRACKER.is_running() {
+        while ui_command_running_tracker.is_running() {
             match ui_command_receiver.recv().await {
                 Some(UiCommand::Serial(serial_command)) => serial_tx
                     .send(serial_command)
                     .expect("Could not send serial ui command"),
                 Some(UiCommand::Parallel(parallel_command)) => {
-                    let ui_command_nvim = ui_command_nvim.clone();
+                    let ui_command_nvim = ui_command_nvim.read().clone();
                     tokio::spawn(async move {
-                        parallel_command.execute(&ui_command_nvim).await;
+                        if let Some(nvim) = ui_command_nvim {
+                            parallel_command.execute(&nvim).await;
+                        }
                     });
                 }
                 None => {
-                    RUNNING_TRACKER.quit("ui command channel failed");
+                    ui_command_running_tracker.quit("ui command channel failed");
                 }
             }
         }
     });
 
     tokio::spawn(async move {
-        while RUNNING_TRACKER.is_running() {
+        while running_tracker.is_running() {
             match serial_rx.recv().await {
                 Some(serial_command) => {
-                    serial_command.execute(&nvim).await;
+                    // Input while reconnecting has nowhere to go
+                    let current_nvim = nvim.read().clone();
+                    if let Some(current_nvim) = current_nvim {
+                        serial_command.execute(&current_nvim).await;
+                    }
                 }
                 None => {
-                    RUNNING_TRACKER.quit("serial ui command channel failed");
+                    running_tracker.quit("serial ui command channel failed");
                 }
             }
         }
     });
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_paste_chunks() {
+        assert_eq!(paste_chunks("abcdef", 4), vec!["abcd", "ef"]);
+        assert_eq!(paste_chunks("", 4), vec![""]);
+        // The two byte ä isn't cut in half
+        assert_eq!(paste_chunks("abcäd", 4), vec!["abcä", "d"]);
+    }
+}
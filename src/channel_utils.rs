@@ -2,7 +2,9 @@ use std::fmt::Debug;
 use std::sync::mpsc::{SendError, Sender};
 
 use log::trace;
-use tokio::sync::mpsc::{error::SendError as TokioSendError, UnboundedSender};
+use tokio::sync::mpsc::{
+    error::SendError as TokioSendError, Sender as TokioSender, UnboundedSender,
+};
 
 #[derive(Clone)]
 pub struct LoggingSender<T>
@@ -52,3 +54,27 @@ where
         self.tx.send(message)
     }
 }
+
+// Like LoggingTx, but the channel has a capacity and sending waits for room in it
+#[derive(Clone)]
+pub struct LoggingBoundedTx<T>
+where
+    T: Debug,
+{
+    tx: TokioSender<T>,
+    channel_name: String,
+}
+
+impl<T> LoggingBoundedTx<T>
+where
+    T: Debug,
+{
+    pub fn attach(tx: TokioSender<T>, channel_name: String) -> Self {
+        Self { tx, channel_name }
+    }
+
+    pub async fn send(&self, message: T) -> Result<(), TokioSendError<T>> {
+        trace!("{} {:?}", self.channel_name, &message);
+        self.tx.send(message).await
+    }
+}
@@ -0,0 +1,921 @@
+use std::error::Error;
+
+use copypasta::{ClipboardContext, ClipboardProvider};
+use log::error;
+use parking_lot::Mutex;
+
+#[cfg(target_os = "linux")]
+use copypasta::{
+    wayland_clipboard,
+    x11_clipboard::{Primary, X11ClipboardContext},
+};
+
+type ClipboardResult<T> = Result<T, Box<dyn Error + Send + Sync + 'static>>;
+
+// On X11 the copied text is served for as long as the context that copied it is alive, so the
+// contexts are created once and kept around
+lazy_static! {
+    static ref CLIPBOARD: Mutex<Option<ClipboardContext>> = Mutex::new(None);
+    // The text and register type neovide copied last, per register. Other programs only see the
+    // text, so the type is only known while the clipboard still holds that text.
+    static ref LAST_COPIED: Mutex<Vec<(String, String, String)>> = Mutex::new(Vec::new());
+}
+#[cfg(target_os = "linux")]
+lazy_static! {
+    static ref PRIMARY_SELECTION: Mutex<Option<X11ClipboardContext<Primary>>> = Mutex::new(None);
+    // The primary selection and the clipboard of the wayland compositor, when neovide runs on
+    // one. They have to share the window's connection to it.
+    static ref WAYLAND_CLIPBOARDS: Mutex<Option<(wayland_clipboard::Primary, wayland_clipboard::Clipboard)>> =
+        Mutex::new(None);
+}
+
+/// Makes the registers use the selections of the wayland compositor the window is shown on,
+/// rather than those of xwayland, which other wayland programs don't see.
+///
+/// # Safety
+///
+/// The display has to be a valid `wl_display` that stays connected until
+/// [`release_wayland_display`] is called, since the clipboards keep using it from a thread of
+/// their own.
+#[cfg(target_os = "linux")]
+pub unsafe fn use_wayland_display(display: *mut std::ffi::c_void) {
+    let clipboards = wayland_clipboard::create_clipboards_from_external(display);
+    *WAYLAND_CLIPBOARDS.lock() = Some(clipboards);
+}
+
+/// Drops the clipboards of the wayland display, so they are gone before the connection they
+/// share is closed. Registers go through xwayland afterwards.
+#[cfg(target_os = "linux")]
+pub fn release_wayland_display() {
+    WAYLAND_CLIPBOARDS.lock().take();
+}
+
+fn with_provider<T>(
+    register: &str,
+    action: impl FnOnce(&mut dyn ClipboardProvider) -> ClipboardResult<T>,
+) -> ClipboardResult<T> {
+    // The * register is the primary selection on linux, everywhere else it is the same as +
+    #[cfg(target_os = "linux")]
+    {
+        if let Some((primary, clipboard)) = WAYLAND_CLIPBOARDS.lock().as_mut() {
+            return if register == "*" {
+                action(primary)
+            } else {
+                action(clipboard)
+            };
+        }
+        if register == "*" {
+            let mut primary_selection = PRIMARY_SELECTION.lock();
+            if primary_selection.is_none() {
+                *primary_selection = Some(X11ClipboardContext::<Primary>::new()?);
+            }
+            return action(primary_selection.as_mut().unwrap());
+        }
+    }
+    #[cfg(not(target_os = "linux"))]
+    let _ = register;
+
+    let mut clipboard = CLIPBOARD.lock();
+    if clipboard.is_none() {
+        *clipboard = Some(ClipboardContext::new()?);
+    }
+    action(clipboard.as_mut().unwrap())
+}
+
+/// The clipboard contents as lines, the way neovim's clipboard provider returns them.
+pub fn get_lines(register: &str) -> Vec<String> {
+    match with_provider(register, |provider| provider.get_contents()) {
+        Ok(contents) => split_lines(&contents),
+        Err(error) => {
+            error!("Could not read the clipboard: {}", error);
+            Vec::new()
+        }
+    }
+}
+
+/// The clipboard contents as lines together with the register type the paste should use:
+/// the one they were copied with, or linewise when the text ends in a line break.
+pub fn get_register(register: &str) -> (Vec<String>, String) {
+    let mut lines = get_lines(register);
+    let contents = lines.join("\n");
+    let copied_regtype = LAST_COPIED
+        .lock()
+        .iter()
+        .find(|(copied_register, copied_contents, _)| {
+            copied_register == register && *copied_contents == contents
+        })
+        .map(|(_, _, regtype)| regtype.clone());
+    let regtype = copied_regtype.unwrap_or_else(|| infer_regtype(&lines).to_owned());
+    if regtype == "V" && lines.len() > 1 && lines.last().map_or(false, String::is_empty) {
+        lines.pop();
+    }
+    (lines, regtype)
+}
+
+/// Copies the lines of a register with its type, which is `v`, `V` or a blockwise one like neovim
+/// gives its clipboard providers. Linewise text ends in a line break, so other programs paste it
+/// as whole lines too.
+pub fn set_lines(register: &str, lines: &[String], regtype: &str) {
+    let mut contents = lines.join("\n");
+    if regtype == "V" {
+        contents.push('\n');
+    }
+    {
+        let mut last_copied = LAST_COPIED.lock();
+        last_copied.retain(|(copied_register, _, _)| copied_register != register);
+        last_copied.push((register.to_owned(), contents.clone(), regtype.to_owned()));
+    }
+    if let Err(error) = with_provider(register, |provider| provider.set_contents(contents)) {
+        error!("Could not write the clipboard: {}", error);
+    }
+}
+
+// Text copied by other programs is linewise when it ends in a line break
+fn infer_regtype(lines: &[String]) -> &'static str {
+    if lines.len() > 1 && lines.last().map_or(false, String::is_empty) {
+        "V"
+    } else {
+        "v"
+    }
+}
+
+/// The image on the clipboard still encoded, as a png or on windows possibly a bmp, if it holds
+/// one.
+pub fn get_image() -> Option<Vec<u8>> {
+    match image_clipboard::get_image() {
+        Ok(image) => image,
+        Err(error) => {
+            error!("Could not read an image from the clipboard: {}", error);
+            None
+        }
+    }
+}
+
+/// Puts highlighted text on the clipboard as html, with the plain text alongside it for programs
+/// that don't take html, so it keeps its colors and font when pasted into documents and slides.
+/// On windows the window becomes the owner of the clipboard, which it needs to have one.
+pub fn set_html(html: &str, text: &str, window: &glutin::window::Window) {
+    if let Err(error) = html_clipboard::set_html(html, text, window) {
+        error!(
+            "Could not copy as rich text, copying plain text instead: {}",
+            error
+        );
+        set_lines("+", &[text.to_owned()]);
+    }
+}
+
+// The part of a document between its body tags, which is what was copied, or all of it when it
+// has no body
+#[cfg_attr(not(windows), allow(dead_code))]
+fn split_fragment(html: &str) -> (&str, &str, &str) {
+    let lowercase = html.to_ascii_lowercase();
+    let start = lowercase
+        .find("<body")
+        .and_then(|body| lowercase[body..].find('>').map(|end| body + end + 1));
+    let end = lowercase.rfind("</body>");
+    match (start, end) {
+        (Some(start), Some(end)) if start <= end => {
+            (&html[..start], &html[start..end], &html[end..])
+        }
+        _ => ("", html, ""),
+    }
+}
+
+// The windows clipboard wants html with a header giving the byte offsets of the document and of
+// the part that was copied
+#[cfg_attr(not(windows), allow(dead_code))]
+fn cf_html(html: &str) -> String {
+    const START_FRAGMENT: &str = "<!--StartFragment-->";
+    const END_FRAGMENT: &str = "<!--EndFragment-->";
+    let header = |start_html: usize,
+                  end_html: usize,
+                  start_fragment: usize,
+                  end_fragment: usize| {
+        format!(
+            "Version:0.9\r\nStartHTML:{:010}\r\nEndHTML:{:010}\r\nStartFragment:{:010}\r\nEndFragment:{:010}\r\n",
+            start_html, end_html, start_fragment, end_fragment
+        )
+    };
+    let (before, fragment, after) = split_fragment(html);
+    // The offsets are always printed with ten digits, so the header has the same length
+    let start_html = header(0, 0, 0, 0).len();
+    let start_fragment = start_html + before.len() + START_FRAGMENT.len();
+    let end_fragment = start_fragment + fragment.len();
+    let end_html = end_fragment + END_FRAGMENT.len() + after.len();
+    format!(
+        "{}{}{}{}{}{}",
+        header(start_html, end_html, start_fragment, end_fragment),
+        before,
+        START_FRAGMENT,
+        fragment,
+        END_FRAGMENT,
+        after
+    )
+}
+
+// XA_STRING is latin-1, which the characters outside of it are left out of
+#[cfg_attr(any(windows, target_os = "macos"), allow(dead_code))]
+fn latin1(text: &str) -> Vec<u8> {
+    text.chars()
+        .map(|character| {
+            if (character as u32) < 0x100 {
+                character as u8
+            } else {
+                b'?'
+            }
+        })
+        .collect()
+}
+
+#[cfg(windows)]
+mod html_clipboard {
+    use glutin::{platform::windows::WindowExtWindows, window::Window};
+    use winapi::{
+        shared::{minwindef::HGLOBAL, windef::HWND},
+        um::{
+            winbase::{GlobalAlloc, GlobalFree, GlobalLock, GlobalUnlock, GMEM_MOVEABLE},
+            winuser::{
+                CloseClipboard, EmptyClipboard, OpenClipboard, RegisterClipboardFormatW,
+                SetClipboardData, CF_UNICODETEXT,
+            },
+        },
+    };
+
+    use super::{cf_html, ClipboardResult};
+
+    // The clipboard takes ownership of the memory once it is set, until then it is still ours to
+    // free
+    unsafe fn set_data(format: u32, bytes: &[u8]) -> ClipboardResult<()> {
+        let memory: HGLOBAL = GlobalAlloc(GMEM_MOVEABLE, bytes.len());
+        if memory.is_null() {
+            return Err("Could not allocate the clipboard data".into());
+        }
+        let pointer = GlobalLock(memory) as *mut u8;
+        if pointer.is_null() {
+            GlobalFree(memory);
+            return Err("Could not lock the clipboard data".into());
+        }
+        pointer.copy_from_nonoverlapping(bytes.as_ptr(), bytes.len());
+        GlobalUnlock(memory);
+        if SetClipboardData(format, memory).is_null() {
+            GlobalFree(memory);
+            return Err("Could not set the clipboard data".into());
+        }
+        Ok(())
+    }
+
+    pub fn set_html(html: &str, text: &str, window: &Window) -> ClipboardResult<()> {
+        let format_name: Vec<u16> = "HTML Format".encode_utf16().chain(Some(0)).collect();
+        let mut html = cf_html(html).into_bytes();
+        html.push(0);
+        let text: Vec<u8> = text
+            .encode_utf16()
+            .chain(Some(0))
+            .flat_map(|unit| unit.to_le_bytes())
+            .collect();
+        unsafe {
+            let html_format = RegisterClipboardFormatW(format_name.as_ptr());
+            // Emptying a clipboard opened without a window leaves it without an owner, which
+            // setting the data then fails on
+            if OpenClipboard(window.hwnd() as HWND) == 0 {
+                return Err("Could not open the clipboard".into());
+            }
+            EmptyClipboard();
+            let result = set_data(html_format, &html).and_then(|_| set_data(CF_UNICODETEXT, &text));
+            CloseClipboard();
+            result
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod html_clipboard {
+    use cocoa::{
+        base::{id, nil},
+        foundation::NSString,
+    };
+    use glutin::window::Window;
+    use objc::{class, msg_send, sel, sel_impl};
+
+    use super::ClipboardResult;
+
+    pub fn set_html(html: &str, text: &str, _window: &Window) -> ClipboardResult<()> {
+        unsafe {
+            let pasteboard: id = msg_send![class!(NSPasteboard), generalPasteboard];
+            let _: i64 = msg_send![pasteboard, clearContents];
+            for (contents, kind) in [(html, "public.html"), (text, "public.utf8-plain-text")] {
+                // The pasteboard copies the strings, so they are released with the pool
+                let ns_contents: id =
+                    msg_send![NSString::alloc(nil).init_str(contents), autorelease];
+                let ns_kind: id = msg_send![NSString::alloc(nil).init_str(kind), autorelease];
+                let written: bool = msg_send![pasteboard, setString: ns_contents forType: ns_kind];
+                if !written {
+                    return Err(format!("Could not put {} on the pasteboard", kind).into());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// Copypasta only offers text, and xclip or wl-copy only one type at a time, so neovide owns the
+// selection itself to serve the html together with the plain text. Wayland compositors pass the
+// selection on to wayland programs through xwayland.
+#[cfg(not(any(windows, target_os = "macos")))]
+mod html_clipboard {
+    use std::borrow::Cow;
+    use std::os::raw::{c_char, c_int, c_uchar};
+    use std::ptr::null;
+    use std::thread;
+
+    use glutin::window::Window;
+    use x11_dl::xlib;
+
+    use super::{latin1, ClipboardResult};
+
+    struct Atoms {
+        clipboard: xlib::Atom,
+        targets: xlib::Atom,
+        html: xlib::Atom,
+        utf8_string: xlib::Atom,
+        text_plain_utf8: xlib::Atom,
+        text_plain: xlib::Atom,
+    }
+
+    // Keeps its own connection and window, and serves whoever pastes until another program
+    // takes over the clipboard
+    struct Owner {
+        xlib: xlib::Xlib,
+        display: *mut xlib::Display,
+        window: xlib::Window,
+        atoms: Atoms,
+        html: String,
+        text: String,
+    }
+
+    // The connection is only used by the thread serving the selection
+    unsafe impl Send for Owner {}
+
+    impl Owner {
+        fn contents(&self, target: xlib::Atom) -> Option<Cow<[u8]>> {
+            let atoms = &self.atoms;
+            if target == atoms.html {
+                Some(Cow::Borrowed(self.html.as_bytes()))
+            } else if [atoms.utf8_string, atoms.text_plain_utf8, atoms.text_plain].contains(&target)
+            {
+                Some(Cow::Borrowed(self.text.as_bytes()))
+            } else if target == xlib::XA_STRING {
+                Some(Cow::Owned(latin1(&self.text)))
+            } else {
+                None
+            }
+        }
+
+        unsafe fn answer(&self, request: &xlib::XSelectionRequestEvent) {
+            let xlib = &self.xlib;
+            // Old clients leave out the property, which then is the target
+            let property = if request.property == 0 {
+                request.target
+            } else {
+                request.property
+            };
+            // Larger contents would need incremental transfers, which are left out
+            let max_bytes = (xlib.XExtendedMaxRequestSize)(self.display)
+                .max((xlib.XMaxRequestSize)(self.display)) as usize
+                * 4;
+
+            let stored = if request.target == self.atoms.targets {
+                let targets = [
+                    self.atoms.targets,
+                    self.atoms.html,
+                    self.atoms.utf8_string,
+                    self.atoms.text_plain_utf8,
+                    self.atoms.text_plain,
+                    xlib::XA_STRING,
+                ];
+                (xlib.XChangeProperty)(
+                    self.display,
+                    request.requestor,
+                    property,
+                    xlib::XA_ATOM,
+                    32,
+                    xlib::PropModeReplace,
+                    targets.as_ptr() as *const c_uchar,
+                    targets.len() as c_int,
+                );
+                true
+            } else {
+                match self.contents(request.target) {
+                    Some(contents) if contents.len() < max_bytes => {
+                        (xlib.XChangeProperty)(
+                            self.display,
+                            request.requestor,
+                            property,
+                            request.target,
+                            8,
+                            xlib::PropModeReplace,
+                            contents.as_ptr(),
+                            contents.len() as c_int,
+                        );
+                        true
+                    }
+                    _ => false,
+                }
+            };
+
+            let mut notify = xlib::XEvent {
+                selection: xlib::XSelectionEvent {
+                    type_: xlib::SelectionNotify,
+                    serial: 0,
+                    send_event: xlib::True,
+                    display: self.display,
+                    requestor: request.requestor,
+                    selection: request.selection,
+                    target: request.target,
+                    property: if stored { property } else { 0 },
+                    time: request.time,
+                },
+            };
+            (xlib.XSendEvent)(self.display, request.requestor, xlib::False, 0, &mut notify);
+            (xlib.XFlush)(self.display);
+        }
+
+        fn serve(self) {
+            unsafe {
+                let mut event: xlib::XEvent = std::mem::zeroed();
+                loop {
+                    (self.xlib.XNextEvent)(self.display, &mut event);
+                    match event.get_type() {
+                        xlib::SelectionRequest => self.answer(&event.selection_request),
+                        xlib::SelectionClear => break,
+                        _ => {}
+                    }
+                }
+                (self.xlib.XDestroyWindow)(self.display, self.window);
+                (self.xlib.XCloseDisplay)(self.display);
+            }
+        }
+    }
+
+    pub fn set_html(html: &str, text: &str, _window: &Window) -> ClipboardResult<()> {
+        let xlib = xlib::Xlib::open()?;
+        unsafe {
+            let display = (xlib.XOpenDisplay)(null());
+            if display.is_null() {
+                return Err("Could not connect to the X server".into());
+            }
+            let atom = |name: &[u8]| {
+                (xlib.XInternAtom)(display, name.as_ptr() as *const c_char, xlib::False)
+            };
+            let atoms = Atoms {
+                clipboard: atom(b"CLIPBOARD\0"),
+                targets: atom(b"TARGETS\0"),
+                html: atom(b"text/html\0"),
+                utf8_string: atom(b"UTF8_STRING\0"),
+                text_plain_utf8: atom(b"text/plain;charset=utf-8\0"),
+                text_plain: atom(b"text/plain\0"),
+            };
+            let root = (xlib.XDefaultRootWindow)(display);
+            let window = (xlib.XCreateSimpleWindow)(display, root, 0, 0, 1, 1, 0, 0, 0);
+            (xlib.XSetSelectionOwner)(display, atoms.clipboard, window, xlib::CurrentTime);
+            if (xlib.XGetSelectionOwner)(display, atoms.clipboard) != window {
+                (xlib.XDestroyWindow)(display, window);
+                (xlib.XCloseDisplay)(display);
+                return Err("Could not take over the clipboard".into());
+            }
+
+            let owner = Owner {
+                xlib,
+                display,
+                window,
+                atoms,
+                html: html.to_owned(),
+                text: text.to_owned(),
+            };
+            thread::spawn(move || owner.serve());
+        }
+        Ok(())
+    }
+}
+
+// A device independent bitmap is a bmp file without its file header, which says where the pixels
+// start after the info header, the color masks and the color table
+#[cfg_attr(not(windows), allow(dead_code))]
+fn bmp_from_dib(dib: &[u8]) -> Option<Vec<u8>> {
+    const FILE_HEADER_SIZE: usize = 14;
+    const INFO_HEADER_SIZE: u32 = 40;
+    const BI_BITFIELDS: u32 = 3;
+    let field = |offset: usize, size: usize| -> Option<u32> {
+        let bytes = dib.get(offset..offset + size)?;
+        Some(
+            bytes
+                .iter()
+                .rev()
+                .fold(0, |value, byte| value << 8 | *byte as u32),
+        )
+    };
+    let header_size = field(0, 4)?;
+    let bit_count = field(14, 2)?;
+    let compression = field(16, 4)?;
+    let colors_used = field(32, 4)?;
+
+    // Only the original header leaves the masks out of itself
+    let masks_size = if header_size == INFO_HEADER_SIZE && compression == BI_BITFIELDS {
+        12
+    } else {
+        0
+    };
+    let colors = match colors_used {
+        0 if bit_count <= 8 => 1 << bit_count,
+        colors_used => colors_used,
+    };
+    let pixels_offset = FILE_HEADER_SIZE as u32 + header_size + masks_size + colors * 4;
+    let file_size = (FILE_HEADER_SIZE + dib.len()) as u32;
+
+    let mut bmp = Vec::with_capacity(file_size as usize);
+    bmp.extend_from_slice(b"BM");
+    bmp.extend_from_slice(&file_size.to_le_bytes());
+    bmp.extend_from_slice(&0u32.to_le_bytes());
+    bmp.extend_from_slice(&pixels_offset.to_le_bytes());
+    bmp.extend_from_slice(dib);
+    Some(bmp)
+}
+
+// Images are read from the clipboard of each platform directly, the way the html is written
+#[cfg(windows)]
+mod image_clipboard {
+    use std::ptr::null_mut;
+    use std::slice;
+
+    use winapi::um::{
+        winbase::{GlobalLock, GlobalSize, GlobalUnlock},
+        winuser::{
+            CloseClipboard, GetClipboardData, OpenClipboard, RegisterClipboardFormatW, CF_DIB,
+        },
+    };
+
+    use super::{bmp_from_dib, ClipboardResult};
+
+    unsafe fn get_data(format: u32) -> Option<Vec<u8>> {
+        let memory = GetClipboardData(format);
+        if memory.is_null() {
+            return None;
+        }
+        let pointer = GlobalLock(memory) as *const u8;
+        if pointer.is_null() {
+            return None;
+        }
+        let bytes = slice::from_raw_parts(pointer, GlobalSize(memory)).to_vec();
+        GlobalUnlock(memory);
+        Some(bytes)
+    }
+
+    pub fn get_image() -> ClipboardResult<Option<Vec<u8>>> {
+        let format_name: Vec<u16> = "PNG".encode_utf16().chain(Some(0)).collect();
+        unsafe {
+            let png_format = RegisterClipboardFormatW(format_name.as_ptr());
+            if OpenClipboard(null_mut()) == 0 {
+                return Err("Could not open the clipboard".into());
+            }
+            // Browsers and office programs copy pngs, which keep the transparency, screenshots
+            // are only bitmaps
+            let image = get_data(png_format)
+                .or_else(|| get_data(CF_DIB).and_then(|dib| bmp_from_dib(&dib)));
+            CloseClipboard();
+            Ok(image)
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod image_clipboard {
+    use std::slice;
+
+    use cocoa::{
+        base::{id, nil},
+        foundation::{NSAutoreleasePool, NSString},
+    };
+    use objc::{class, msg_send, sel, sel_impl};
+
+    use super::ClipboardResult;
+
+    // NSBitmapImageFileTypePNG
+    const PNG_FILE_TYPE: u64 = 4;
+
+    unsafe fn bytes(data: id) -> Vec<u8> {
+        let pointer: *const u8 = msg_send![data, bytes];
+        let length: usize = msg_send![data, length];
+        if pointer.is_null() {
+            return Vec::new();
+        }
+        slice::from_raw_parts(pointer, length).to_vec()
+    }
+
+    unsafe fn data_for_type(pasteboard: id, kind: &str) -> id {
+        let kind: id = msg_send![NSString::alloc(nil).init_str(kind), autorelease];
+        msg_send![pasteboard, dataForType: kind]
+    }
+
+    unsafe fn read_png(pasteboard: id) -> ClipboardResult<Option<Vec<u8>>> {
+        let png = data_for_type(pasteboard, "public.png");
+        if png != nil {
+            return Ok(Some(bytes(png)));
+        }
+        // Screenshots and most programs only offer a tiff, which skia can't decode
+        let tiff = data_for_type(pasteboard, "public.tiff");
+        if tiff == nil {
+            return Ok(None);
+        }
+        let representation: id = msg_send![class!(NSBitmapImageRep), imageRepWithData: tiff];
+        if representation == nil {
+            return Err("Could not read the tiff on the pasteboard".into());
+        }
+        let properties: id = msg_send![class!(NSDictionary), dictionary];
+        let png: id = msg_send![representation, representationUsingType: PNG_FILE_TYPE properties: properties];
+        if png == nil {
+            return Err("Could not convert the tiff on the pasteboard to a png".into());
+        }
+        Ok(Some(bytes(png)))
+    }
+
+    pub fn get_image() -> ClipboardResult<Option<Vec<u8>>> {
+        unsafe {
+            // Called off the main thread, which has no pool to take the autoreleased objects
+            let pool = NSAutoreleasePool::new(nil);
+            let pasteboard: id = msg_send![class!(NSPasteboard), generalPasteboard];
+            let image = read_png(pasteboard);
+            pool.drain();
+            image
+        }
+    }
+}
+
+// Asks the owner of the clipboard for a png like any other X11 program pasting would, which on
+// wayland compositors reaches wayland programs through xwayland
+#[cfg(not(any(windows, target_os = "macos")))]
+mod image_clipboard {
+    use std::os::raw::{c_char, c_int, c_long, c_uchar, c_ulong};
+    use std::ptr::{null, null_mut};
+    use std::slice;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use x11_dl::xlib;
+
+    use super::ClipboardResult;
+
+    // Owners that never answer are given up on after this long
+    const TIMEOUT: Duration = Duration::from_secs(2);
+
+    struct Atoms {
+        clipboard: xlib::Atom,
+        png: xlib::Atom,
+        property: xlib::Atom,
+        incr: xlib::Atom,
+    }
+
+    // Keeps its own connection and window for the owner to put the png on
+    struct Reader {
+        xlib: xlib::Xlib,
+        display: *mut xlib::Display,
+        window: xlib::Window,
+        atoms: Atoms,
+    }
+
+    impl Reader {
+        fn open() -> ClipboardResult<Reader> {
+            let xlib = xlib::Xlib::open()?;
+            unsafe {
+                let display = (xlib.XOpenDisplay)(null());
+                if display.is_null() {
+                    return Err("Could not connect to the X server".into());
+                }
+                let atom = |name: &[u8]| {
+                    (xlib.XInternAtom)(display, name.as_ptr() as *const c_char, xlib::False)
+                };
+                let atoms = Atoms {
+                    clipboard: atom(b"CLIPBOARD\0"),
+                    png: atom(b"image/png\0"),
+                    property: atom(b"NEOVIDE_CLIPBOARD\0"),
+                    incr: atom(b"INCR\0"),
+                };
+                let root = (xlib.XDefaultRootWindow)(display);
+                let window = (xlib.XCreateSimpleWindow)(display, root, 0, 0, 1, 1, 0, 0, 0);
+                // Large contents come in chunks, each announced by a change of the property
+                (xlib.XSelectInput)(display, window, xlib::PropertyChangeMask);
+                Ok(Reader {
+                    xlib,
+                    display,
+                    window,
+                    atoms,
+                })
+            }
+        }
+
+        // Polls rather than blocking in XNextEvent, so an owner that doesn't answer can't hang
+        // the paste
+        unsafe fn wait_for(
+            &self,
+            event_type: c_int,
+            accept: impl Fn(&xlib::XEvent) -> bool,
+        ) -> Option<xlib::XEvent> {
+            let deadline = Instant::now() + TIMEOUT;
+            let mut event: xlib::XEvent = std::mem::zeroed();
+            while Instant::now() < deadline {
+                while (self.xlib.XCheckTypedWindowEvent)(
+                    self.display,
+                    self.window,
+                    event_type,
+                    &mut event,
+                ) != 0
+                {
+                    if accept(&event) {
+                        return Some(event);
+                    }
+                }
+                thread::sleep(Duration::from_millis(5));
+            }
+            None
+        }
+
+        // Deleting the property tells the owner to send the next chunk
+        unsafe fn take_property(&self) -> (xlib::Atom, Vec<u8>) {
+            let mut kind = 0;
+            let mut format = 0;
+            let mut items: c_ulong = 0;
+            let mut remaining: c_ulong = 0;
+            let mut data: *mut c_uchar = null_mut();
+            (self.xlib.XGetWindowProperty)(
+                self.display,
+                self.window,
+                self.atoms.property,
+                0,
+                i32::MAX as c_long,
+                xlib::True,
+                xlib::AnyPropertyType as xlib::Atom,
+                &mut kind,
+                &mut format,
+                &mut items,
+                &mut remaining,
+                &mut data,
+            );
+            (self.xlib.XFlush)(self.display);
+            if data.is_null() {
+                return (kind, Vec::new());
+            }
+            let bytes = if format == 8 {
+                slice::from_raw_parts(data, items as usize).to_vec()
+            } else {
+                Vec::new()
+            };
+            (self.xlib.XFree)(data as *mut _);
+            (kind, bytes)
+        }
+
+        unsafe fn read_png(&self) -> ClipboardResult<Option<Vec<u8>>> {
+            let atoms = &self.atoms;
+            if (self.xlib.XGetSelectionOwner)(self.display, atoms.clipboard) == 0 {
+                return Ok(None);
+            }
+            (self.xlib.XConvertSelection)(
+                self.display,
+                atoms.clipboard,
+                atoms.png,
+                atoms.property,
+                self.window,
+                xlib::CurrentTime,
+            );
+            (self.xlib.XFlush)(self.display);
+            let notify = self
+                .wait_for(xlib::SelectionNotify, |_| true)
+                .ok_or("The clipboard owner didn't answer")?;
+            // Owners without a png refuse by leaving out the property
+            if notify.selection.property == 0 {
+                return Ok(None);
+            }
+
+            let (kind, mut png) = self.take_property();
+            if kind != atoms.incr {
+                return Ok(Some(png));
+            }
+            loop {
+                let new_value = |event: &xlib::XEvent| {
+                    event.property.atom == atoms.property
+                        && event.property.state == xlib::PropertyNewValue
+                };
+                self.wait_for(xlib::PropertyNotify, new_value)
+                    .ok_or("The clipboard owner stopped sending the image")?;
+                let (_, chunk) = self.take_property();
+                // An empty chunk ends the transfer
+                if chunk.is_empty() {
+                    return Ok(Some(png));
+                }
+                png.extend(chunk);
+            }
+        }
+    }
+
+    impl Drop for Reader {
+        fn drop(&mut self) {
+            unsafe {
+                (self.xlib.XDestroyWindow)(self.display, self.window);
+                (self.xlib.XCloseDisplay)(self.display);
+            }
+        }
+    }
+
+    pub fn get_image() -> ClipboardResult<Option<Vec<u8>>> {
+        let reader = Reader::open()?;
+        unsafe { reader.read_png() }
+    }
+}
+
+// Other programs copy with windows line endings too, which would show up as ^M in the buffer
+fn split_lines(contents: &str) -> Vec<String> {
+    contents
+        .split('\n')
+        .map(|line| line.strip_suffix('\r').unwrap_or(line).to_owned())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cf_html_offsets() {
+        let cf_html = cf_html("<b>bold</b>");
+        let offset = |name: &str| -> usize {
+            let start = cf_html.find(name).unwrap() + name.len();
+            cf_html[start..start + 10].parse().unwrap()
+        };
+        assert_eq!(
+            &cf_html[offset("StartFragment:")..offset("EndFragment:")],
+            "<b>bold</b>"
+        );
+        assert!(cf_html[offset("StartHTML:")..].starts_with("<!--StartFragment-->"));
+        assert_eq!(offset("EndHTML:"), cf_html.len());
+    }
+
+    #[test]
+    fn test_cf_html_fragment_is_the_body() {
+        let document =
+            "<html><head><style>pre {}</style></head><BODY class=\"x\"><pre>fn</pre></BODY></html>";
+        let cf_html = cf_html(document);
+        let offset = |name: &str| -> usize {
+            let start = cf_html.find(name).unwrap() + name.len();
+            cf_html[start..start + 10].parse().unwrap()
+        };
+        assert_eq!(
+            &cf_html[offset("StartFragment:")..offset("EndFragment:")],
+            "<pre>fn</pre>"
+        );
+        assert!(cf_html[offset("StartHTML:")..].starts_with("<html><head>"));
+        assert!(cf_html.ends_with("<!--EndFragment--></BODY></html>"));
+        assert_eq!(offset("EndHTML:"), cf_html.len());
+    }
+
+    #[test]
+    fn test_latin1() {
+        assert_eq!(latin1("café → ok"), b"caf\xe9 ? ok".to_vec());
+    }
+
+    #[test]
+    fn test_bmp_from_dib() {
+        // A 1x1 24 bit bitmap, whose pixels follow the info header right away
+        let mut dib = vec![0; 40];
+        dib[0] = 40;
+        dib[4] = 1;
+        dib[8] = 1;
+        dib[12] = 1;
+        dib[14] = 24;
+        dib.extend_from_slice(&[0, 0, 255, 0]);
+        let bmp = bmp_from_dib(&dib).unwrap();
+        assert_eq!(&bmp[..2], b"BM");
+        assert_eq!(bmp[2..6], (bmp.len() as u32).to_le_bytes());
+        assert_eq!(bmp[10..14], 54u32.to_le_bytes());
+        assert_eq!(&bmp[14..], &dib[..]);
+
+        // The masks of a bitfield bitmap sit between the header and the pixels
+        dib[14] = 32;
+        dib[16] = 3;
+        let bmp = bmp_from_dib(&dib).unwrap();
+        assert_eq!(bmp[10..14], 66u32.to_le_bytes());
+
+        assert_eq!(bmp_from_dib(&dib[..20]), None);
+    }
+
+    #[test]
+    fn test_split_lines() {
+        assert_eq!(split_lines("one\r\ntwo"), vec!["one", "two"]);
+        // A trailing newline keeps the text linewise
+        assert_eq!(split_lines("line\n"), vec!["line", ""]);
+    }
+
+    #[test]
+    fn test_infer_regtype() {
+        assert_eq!(infer_regtype(&split_lines("line\n")), "V");
+        assert_eq!(infer_regtype(&split_lines("one\ntwo")), "v");
+        assert_eq!(infer_regtype(&split_lines("")), "v");
+    }
+}
@@ -1,4 +1,7 @@
-use crate::settings::*;
+use crate::settings::{
+    config::{config_value, env_or_config, env_or_config_flag},
+    *,
+};
 use crate::utils::Dimensions;
 
 use clap::{App, Arg};
@@ -9,18 +12,37 @@ pub struct CmdLineSettings {
     pub neovim_args: Vec<String>,
     // Command-line arguments only
     pub geometry: Dimensions,
+    pub position: Option<(i32, i32)>,
     pub log_to_file: bool,
     pub no_fork: bool,
+    pub daemon: bool,
     pub remote_tcp: Option<String>,
+    pub server: Option<String>,
+    pub ssh: Option<String>,
     pub wsl: bool,
+    pub render_to: Option<String>,
+    pub record_events: Option<String>,
+    pub startup_profile: Option<String>,
+    pub diagnostics: Option<String>,
+    pub replay: Option<String>,
     // Command-line flags with environment variable fallback
     pub frameless: bool,
+    pub fullscreen: bool,
     pub maximized: bool,
     pub multi_grid: bool,
+    pub external_popupmenu: bool,
     pub no_idle: bool,
     pub srgb: bool,
+    pub tray: bool,
+    pub single_instance: bool,
+    pub restore_session: bool,
     // Command-line arguments with environment variable fallback
+    pub backend: String,
+    pub gpu: Option<String>,
     pub neovim_bin: Option<String>,
+    pub transparency: f32,
+    pub fullscreen_monitor: Option<String>,
+    pub background_color: Option<u32>,
     pub wayland_app_id: String,
     pub x11_wm_class: String,
 }
@@ -32,25 +54,140 @@ impl Default for CmdLineSettings {
             neovim_args: vec![],
             // Command-line arguments only
             geometry: DEFAULT_WINDOW_GEOMETRY,
+            position: None,
             log_to_file: false,
             no_fork: false,
+            daemon: false,
             remote_tcp: None,
+            server: None,
+            ssh: None,
             wsl: false,
+            render_to: None,
+            record_events: None,
+            startup_profile: None,
+            diagnostics: None,
+            replay: None,
             // Command-line flags with environment variable fallback
             frameless: false,
+            fullscreen: false,
             maximized: false,
             multi_grid: false,
+            external_popupmenu: false,
             no_idle: false,
             srgb: true,
+            tray: false,
+            single_instance: false,
+            restore_session: false,
             // Command-line arguments with environment variable fallback
+            backend: "auto".to_owned(),
+            gpu: None,
             neovim_bin: None,
+            transparency: 1.0,
+            fullscreen_monitor: None,
+            background_color: None,
             wayland_app_id: String::new(),
             x11_wm_class: String::new(),
         }
     }
 }
 
+impl CmdLineSettings {
+    /// Whether the files and window layout of the last session are saved on exit, and opened again
+    /// when neovide starts without arguments. Only a neovim neovide started itself on this
+    /// machine shares the paths the session is saved with.
+    pub fn restores_session(&self) -> bool {
+        self.restore_session && !self.is_remote() && !self.wsl && self.ssh.is_none()
+    }
+
+    /// Whether closing neovide leaves neovim alone, because neovide attached to one that keeps
+    /// running or only replays what one sent.
+    pub fn is_remote(&self) -> bool {
+        self.daemon || self.remote_tcp.is_some() || self.server.is_some() || self.replay.is_some()
+    }
+}
+
+fn parse_transparency(input: Option<String>) -> Result<f32, String> {
+    let input = match input {
+        Some(input) => input,
+        None => return Ok(1.0),
+    };
+
+    match input.parse::<f32>() {
+        Ok(transparency) if (0.0..=1.0).contains(&transparency) => Ok(transparency),
+        _ => Err(format!(
+            "Invalid transparency {}: expected a number between 0.0 and 1.0",
+            input
+        )),
+    }
+}
+
+/// Parses a color like `#1e1e2e` into its rgb value.
+fn parse_background_color(input: Option<String>) -> Result<Option<u32>, String> {
+    let input = match input {
+        Some(input) => input,
+        None => return Ok(None),
+    };
+
+    let hex = input.strip_prefix('#').unwrap_or(&input);
+    match u32::from_str_radix(hex, 16) {
+        Ok(color) if hex.len() == 6 => Ok(Some(color)),
+        _ => Err(format!(
+            "Invalid background color {}: expected a hex color like #1e1e2e",
+            input
+        )),
+    }
+}
+
+// Neovim flags which take the next argument as their value
+pub const NEOVIM_VALUE_FLAGS: [&str; 11] = [
+    "-c",
+    "-i",
+    "-q",
+    "-s",
+    "-S",
+    "-t",
+    "-u",
+    "-w",
+    "-W",
+    "--cmd",
+    "--startuptime",
+];
+// Long neovim flags that neovide doesn't have itself
+const NEOVIM_LONG_FLAGS: [&str; 3] = ["--clean", "--noplugin", "--noswapfile"];
+
+// Pulls neovim's own flags like -u NONE or -p out of the arguments, so neovide can be called the
+// same way as nvim without putting them after --. Neovide only has long flags, apart from clap's
+// -h and -V.
+fn split_neovim_flags(args: Vec<String>) -> (Vec<String>, Vec<String>) {
+    let mut neovide_args = Vec::new();
+    let mut neovim_flags = Vec::new();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            neovide_args.push(arg);
+            neovide_args.extend(args);
+            break;
+        }
+
+        let is_short_flag = arg.starts_with('-')
+            && !arg.starts_with("--")
+            && arg.len() > 1
+            && arg != "-h"
+            && arg != "-V";
+        if NEOVIM_VALUE_FLAGS.contains(&arg.as_str()) {
+            neovim_flags.push(arg);
+            neovim_flags.extend(args.next());
+        } else if is_short_flag || NEOVIM_LONG_FLAGS.contains(&arg.as_str()) {
+            neovim_flags.push(arg);
+        } else {
+            neovide_args.push(arg);
+        }
+    }
+    (neovide_args, neovim_flags)
+}
+
 pub fn handle_command_line_arguments(args: Vec<String>) -> Result<(), String> {
+    let (args, neovim_flags) = split_neovim_flags(args);
     let clapp = App::new("Neovide")
         .version(crate_version!())
         .author(crate_authors!())
@@ -74,7 +211,7 @@ pub fn handle_command_line_arguments(args: Vec<String>) -> Result<(), String> {
             Arg::with_name("geometry")
                 .long("geometry")
                 .takes_value(true)
-                .help("Specify the Geometry of the window"),
+                .help("Specify the Geometry of the window as <width>x<height>, optionally followed by +<x>+<y>"),
         )
         .arg(
             Arg::with_name("log_to_file")
@@ -86,23 +223,87 @@ pub fn handle_command_line_arguments(args: Vec<String>) -> Result<(), String> {
                 .long("nofork")
                 .help("Do not detach process from terminal"),
         )
+        .arg(
+            Arg::with_name("daemon")
+                .long("daemon")
+                .conflicts_with_all(&["remote_tcp", "server", "ssh", "wsl"])
+                .help("Keep neovim running after the window is closed, to attach to it again with --server"),
+        )
         .arg(
             Arg::with_name("remote_tcp")
                 .long("remote-tcp")
                 .takes_value(true)
-                .help("Connect to Remote TCP"),
+                .value_name("HOST:PORT")
+                .help("Attach to a neovim started with --listen <HOST:PORT> instead of starting one"),
+        )
+        .arg(
+            Arg::with_name("server")
+                .long("server")
+                .takes_value(true)
+                .value_name("ADDRESS")
+                .conflicts_with("remote_tcp")
+                .help("Attach to a neovim listening on a unix socket, or a named pipe on windows"),
+        )
+        .arg(
+            Arg::with_name("ssh")
+                .long("ssh")
+                .takes_value(true)
+                .value_name("USER@HOST")
+                .conflicts_with_all(&["remote_tcp", "server", "wsl"])
+                .help("Run neovim on another machine over ssh"),
         )
         .arg(
             Arg::with_name("wsl")
                 .long("wsl")
                 .help("Run in WSL")
         )
+        .arg(
+            Arg::with_name("render_to")
+                .long("render-to")
+                .takes_value(true)
+                .value_name("DIRECTORY")
+                .help("Render the frames to pngs in the directory without opening a window, until neovim quits"),
+        )
+        .arg(
+            Arg::with_name("record_events")
+                .long("record-events")
+                .takes_value(true)
+                .value_name("FILE")
+                .help("Record the redraw events neovim sends to the file, to replay them later"),
+        )
+        .arg(
+            Arg::with_name("startup_profile")
+                .long("startup-profile")
+                .takes_value(true)
+                .value_name("FILE")
+                .help("Write how long each phase of the startup took to the file"),
+        )
+        .arg(
+            Arg::with_name("diagnostics")
+                .long("diagnostics")
+                .takes_value(true)
+                .value_name("FILE")
+                .help("Write the versions, gpu, fonts and settings to the file for a bug report"),
+        )
+        .arg(
+            Arg::with_name("replay")
+                .long("replay")
+                .takes_value(true)
+                .value_name("FILE")
+                .conflicts_with_all(&["record_events", "daemon", "remote_tcp", "server", "ssh", "wsl"])
+                .help("Show the redraw events recorded with --record-events instead of starting neovim"),
+        )
         // Command-line flags with environment variable fallback
         .arg(
             Arg::with_name("frameless")
             .long("frameless")
             .help("Removes the window frame. NOTE: Window might not be resizable after this setting is enabled.")
         )
+        .arg(
+            Arg::with_name("fullscreen")
+                .long("fullscreen")
+                .help("Start in borderless fullscreen"),
+        )
         .arg(
             Arg::with_name("maximized")
                 .long("maximized")
@@ -113,6 +314,11 @@ pub fn handle_command_line_arguments(args: Vec<String>) -> Result<(), String> {
                 .long("multigrid")
                 .help("Enable Multigrid"),
         )
+        .arg(
+            Arg::with_name("external_popupmenu")
+                .long("external-popupmenu")
+                .help("Draw the completion menu in neovide, with a documentation panel and kind icons"),
+        )
         .arg(
             Arg::with_name("noidle")
                 .long("noidle")
@@ -123,12 +329,65 @@ pub fn handle_command_line_arguments(args: Vec<String>) -> Result<(), String> {
                 .long("nosrgb")
                 .help("Do not use standard color space to initialize the window. Swapping this variable sometimes fixes issues on startup"),
         )
+        .arg(
+            Arg::with_name("tray")
+                .long("tray")
+                .help("Show a tray icon for hiding and showing the window"),
+        )
+        .arg(
+            Arg::with_name("single_instance")
+                .long("single-instance")
+                .help("Open files in the neovide that is already running, instead of starting another one"),
+        )
+        .arg(
+            Arg::with_name("restore_session")
+                .long("restore-session")
+                .help("Reopen the files and window layout of the last session when started without arguments"),
+        )
         // Command-line arguments with environment variable fallback
+        .arg(
+            Arg::with_name("backend")
+                .long("backend")
+                .takes_value(true)
+                .possible_values(&["auto", "opengl", "metal", "d3d", "software"])
+                .help("Specify the graphics backend used to render the window"),
+        )
+        .arg(
+            Arg::with_name("gpu")
+                .long("gpu")
+                .takes_value(true)
+                .help("Prefer the integrated or discrete gpu, or pick one by name"),
+        )
         .arg(
             Arg::with_name("neovim_bin")
                 .long("neovim-bin")
                 .takes_value(true)
-                .help("Specify path to neovim"),
+                .value_name("NEOVIM_BIN")
+                .help("The neovim binary to run, as a path or a name in the PATH"),
+        )
+        .arg(
+            Arg::with_name("transparency")
+                .long("transparency")
+                .takes_value(true)
+                .help("Specify the opacity of the window background, between 0.0 and 1.0"),
+        )
+        .arg(
+            Arg::with_name("fullscreen_monitor")
+                .long("fullscreen-monitor")
+                .takes_value(true)
+                .help("Go fullscreen on this monitor, by its number counting from 1 or its name"),
+        )
+        .arg(
+            Arg::with_name("background_color")
+                .long("background-color")
+                .takes_value(true)
+                .help("Specify the background shown until neovim has drawn, like #1e1e2e"),
+        )
+        .arg(
+            Arg::with_name("wm_class")
+                .long("wm-class")
+                .takes_value(true)
+                .help("Specify both the X11 WM class and the Wayland App ID, for window manager rules"),
         )
         .arg(
             Arg::with_name("wayland_app_id")
@@ -144,10 +403,13 @@ pub fn handle_command_line_arguments(args: Vec<String>) -> Result<(), String> {
         );
 
     let matches = clapp.get_matches_from(args);
-    let mut neovim_args: Vec<String> = matches
-        .values_of("neovim_args")
-        .map(|opt| opt.map(|v| v.to_owned()).collect())
-        .unwrap_or_default();
+    let mut neovim_args = neovim_flags;
+    neovim_args.extend::<Vec<String>>(
+        matches
+            .values_of("neovim_args")
+            .map(|opt| opt.map(|v| v.to_owned()).collect())
+            .unwrap_or_default(),
+    );
     neovim_args.extend::<Vec<String>>(
         matches
             .values_of("files_to_open")
@@ -158,38 +420,95 @@ pub fn handle_command_line_arguments(args: Vec<String>) -> Result<(), String> {
     /*
      * Integrate Environment Variables as Defaults to the command-line ones.
      *
-     * If the command-line argument is not set, the environment variable is used.
+     * If the command-line argument is not set, the environment variable is used, and without that
+     * the value from the config file.
      */
+    // A project can pick its own window size
+    let geometry = matches
+        .value_of("geometry")
+        .map(|v| v.to_owned())
+        .or_else(|| config_value("geometry"));
+
+    // The specific X11 and Wayland arguments take precedence over --wm-class
+    let wm_class = matches
+        .value_of("wm_class")
+        .map(|v| v.to_owned())
+        .or_else(|| env_or_config("NEOVIDE_WM_CLASS"));
+
     SETTINGS.set::<CmdLineSettings>(&CmdLineSettings {
         // Pass through arguments
         neovim_args,
         // Command-line arguments only
-        geometry: parse_window_geometry(matches.value_of("geometry").map(|i| i.to_owned()))?,
+        geometry: parse_window_geometry(geometry.clone())?,
+        position: parse_window_position(geometry)?,
         log_to_file: matches.is_present("log_to_file"),
         no_fork: matches.is_present("nofork"),
+        daemon: matches.is_present("daemon"),
         remote_tcp: matches.value_of("remote_tcp").map(|i| i.to_owned()),
+        server: matches.value_of("server").map(|i| i.to_owned()),
+        ssh: matches.value_of("ssh").map(|i| i.to_owned()),
         wsl: matches.is_present("wsl"),
+        render_to: matches.value_of("render_to").map(|i| i.to_owned()),
+        record_events: matches.value_of("record_events").map(|i| i.to_owned()),
+        startup_profile: matches.value_of("startup_profile").map(|i| i.to_owned()),
+        diagnostics: matches.value_of("diagnostics").map(|i| i.to_owned()),
+        replay: matches.value_of("replay").map(|i| i.to_owned()),
         // Command-line flags with environment variable fallback
-        frameless: matches.is_present("frameless") || std::env::var("NEOVIDE_FRAMELESS").is_ok(),
-        maximized: matches.is_present("maximized") || std::env::var("NEOVIDE_MAXIMIZED").is_ok(),
-        multi_grid: matches.is_present("multi_grid") || std::env::var("NEOVIDE_MULTIGRID").is_ok(),
-        no_idle: matches.is_present("noidle") || std::env::var("NEOVIDE_NO_IDLE").is_ok(),
+        frameless: matches.is_present("frameless") || env_or_config_flag("NEOVIDE_FRAMELESS"),
+        fullscreen: matches.is_present("fullscreen") || env_or_config_flag("NEOVIDE_FULLSCREEN"),
+        maximized: matches.is_present("maximized") || env_or_config_flag("NEOVIDE_MAXIMIZED"),
+        multi_grid: matches.is_present("multi_grid") || env_or_config_flag("NEOVIDE_MULTIGRID"),
+        external_popupmenu: matches.is_present("external_popupmenu")
+            || env_or_config_flag("NEOVIDE_EXTERNAL_POPUPMENU"),
+        no_idle: matches.is_present("noidle") || env_or_config_flag("NEOVIDE_NO_IDLE"),
         // Srgb is enabled by default, so set it to false if nosrgb or NOEVIDE_NO_SRGB is set
-        srgb: !(matches.is_present("nosrgb") || std::env::var("NEOVIDE_NO_SRGB").is_ok()),
+        srgb: !(matches.is_present("nosrgb") || env_or_config_flag("NEOVIDE_NO_SRGB")),
+        tray: matches.is_present("tray") || env_or_config_flag("NEOVIDE_TRAY"),
+        single_instance: matches.is_present("single_instance")
+            || env_or_config_flag("NEOVIDE_SINGLE_INSTANCE"),
+        restore_session: matches.is_present("restore_session")
+            || env_or_config_flag("NEOVIDE_RESTORE_SESSION"),
         // Command-line arguments with environment variable fallback
+        backend: matches
+            .value_of("backend")
+            .map(|v| v.to_owned())
+            .or_else(|| env_or_config("NEOVIDE_BACKEND"))
+            .unwrap_or_else(|| "auto".to_owned()),
+        gpu: matches
+            .value_of("gpu")
+            .map(|v| v.to_owned())
+            .or_else(|| env_or_config("NEOVIDE_GPU")),
         neovim_bin: matches
             .value_of("neovim_bin")
             .map(|v| v.to_owned())
-            .or_else(|| std::env::var("NEOVIM_BIN").ok()),
+            .or_else(|| env_or_config("NEOVIM_BIN")),
+        transparency: parse_transparency(
+            matches
+                .value_of("transparency")
+                .map(|v| v.to_owned())
+                .or_else(|| env_or_config("NEOVIDE_TRANSPARENCY")),
+        )?,
+        fullscreen_monitor: matches
+            .value_of("fullscreen_monitor")
+            .map(|v| v.to_owned())
+            .or_else(|| env_or_config("NEOVIDE_FULLSCREEN_MONITOR")),
+        background_color: parse_background_color(
+            matches
+                .value_of("background_color")
+                .map(|v| v.to_owned())
+                .or_else(|| env_or_config("NEOVIDE_BACKGROUND_COLOR")),
+        )?,
         wayland_app_id: matches
             .value_of("wayland_app_id")
             .map(|v| v.to_owned())
-            .or_else(|| std::env::var("NEOVIDE_APP_ID").ok())
+            .or_else(|| env_or_config("NEOVIDE_APP_ID"))
+            .or_else(|| wm_class.clone())
             .unwrap_or_else(|| "neovide".to_owned()),
         x11_wm_class: matches
             .value_of("x11_wm_class")
             .map(|v| v.to_owned())
-            .or_else(|| std::env::var("NEOVIDE_X11_WM_CLASS").ok())
+            .or_else(|| env_or_config("NEOVIDE_X11_WM_CLASS"))
+            .or_else(|| wm_class.clone())
             .unwrap_or_else(|| "neovide".to_owned()),
     });
     Ok(())
@@ -254,6 +573,53 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_neovim_flags_without_separator() {
+        let args: Vec<String> = vec![
+            "neovide",
+            "-p",
+            "./foo.txt",
+            "-u",
+            "NONE",
+            "+10",
+            "--clean",
+            "./bar.md",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+
+        let _accessing_settings = ACCESSING_SETTINGS.lock().unwrap();
+        handle_command_line_arguments(args).expect("Could not parse arguments");
+        assert_eq!(
+            SETTINGS.get::<CmdLineSettings>().neovim_args,
+            vec![
+                "-p",
+                "-u",
+                "NONE",
+                "--clean",
+                "./foo.txt",
+                "+10",
+                "./bar.md"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_stdin_argument() {
+        let args: Vec<String> = vec!["neovide", "-", "-R"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+
+        let _accessing_settings = ACCESSING_SETTINGS.lock().unwrap();
+        handle_command_line_arguments(args).expect("Could not parse arguments");
+        assert_eq!(
+            SETTINGS.get::<CmdLineSettings>().neovim_args,
+            vec!["-R", "-"]
+        );
+    }
+
     #[test]
     fn test_files_to_open_with_flag() {
         let args: Vec<String> = vec!["neovide", "./foo.txt", "./bar.md", "--geometry=42x24"]
@@ -295,6 +661,25 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_geometry_with_position() {
+        let args: Vec<String> = vec!["neovide", "--geometry=42x24+100-50"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+
+        let _accessing_settings = ACCESSING_SETTINGS.lock().unwrap();
+        handle_command_line_arguments(args).expect("Could not parse arguments");
+        assert_eq!(
+            SETTINGS.get::<CmdLineSettings>().geometry,
+            Dimensions {
+                width: 42,
+                height: 24
+            }
+        );
+        assert_eq!(SETTINGS.get::<CmdLineSettings>().position, Some((100, -50)));
+    }
+
     #[test]
     fn test_log_to_file() {
         let args: Vec<String> = vec!["neovide", "--log"]
@@ -329,6 +714,206 @@ mod tests {
         assert_eq!(SETTINGS.get::<CmdLineSettings>().frameless, true);
     }
 
+    #[test]
+    fn test_external_popupmenu_flag() {
+        let args: Vec<String> = vec!["neovide", "--external-popupmenu"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+
+        let _accessing_settings = ACCESSING_SETTINGS.lock().unwrap();
+        handle_command_line_arguments(args).expect("Could not parse arguments");
+        assert_eq!(SETTINGS.get::<CmdLineSettings>().external_popupmenu, true);
+    }
+
+    #[test]
+    fn test_tray_flag() {
+        let args: Vec<String> = vec!["neovide", "--tray"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+
+        let _accessing_settings = ACCESSING_SETTINGS.lock().unwrap();
+        handle_command_line_arguments(args).expect("Could not parse arguments");
+        assert_eq!(SETTINGS.get::<CmdLineSettings>().tray, true);
+    }
+
+    #[test]
+    fn test_restore_session_flag() {
+        let args: Vec<String> = vec!["neovide", "--restore-session"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+
+        let _accessing_settings = ACCESSING_SETTINGS.lock().unwrap();
+        handle_command_line_arguments(args).expect("Could not parse arguments");
+        assert_eq!(SETTINGS.get::<CmdLineSettings>().restore_session, true);
+        assert!(SETTINGS.get::<CmdLineSettings>().restores_session());
+    }
+
+    #[test]
+    fn test_daemon_flag() {
+        let args: Vec<String> = vec!["neovide", "--daemon"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+
+        let _accessing_settings = ACCESSING_SETTINGS.lock().unwrap();
+        handle_command_line_arguments(args).expect("Could not parse arguments");
+        assert!(SETTINGS.get::<CmdLineSettings>().is_remote());
+    }
+
+    #[test]
+    fn test_remote_tcp_arg() {
+        let args: Vec<String> = vec!["neovide", "--remote-tcp", "localhost:6666"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+
+        let _accessing_settings = ACCESSING_SETTINGS.lock().unwrap();
+        handle_command_line_arguments(args).expect("Could not parse arguments");
+        assert_eq!(
+            SETTINGS.get::<CmdLineSettings>().remote_tcp,
+            Some("localhost:6666".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_replay_arg() {
+        let args: Vec<String> = vec!["neovide", "--replay", "events.msgpack"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+
+        let _accessing_settings = ACCESSING_SETTINGS.lock().unwrap();
+        handle_command_line_arguments(args).expect("Could not parse arguments");
+        let settings = SETTINGS.get::<CmdLineSettings>();
+        assert_eq!(settings.replay, Some("events.msgpack".to_owned()));
+        // There is no neovim to quit when the window closes
+        assert!(settings.is_remote());
+    }
+
+    #[test]
+    fn test_server_arg() {
+        let args: Vec<String> = vec!["neovide", "--server", "/tmp/nvim.sock"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+
+        let _accessing_settings = ACCESSING_SETTINGS.lock().unwrap();
+        handle_command_line_arguments(args).expect("Could not parse arguments");
+        let settings = SETTINGS.get::<CmdLineSettings>();
+        assert_eq!(settings.server, Some("/tmp/nvim.sock".to_owned()));
+        assert!(settings.is_remote());
+    }
+
+    #[test]
+    fn test_fullscreen_flag() {
+        let args: Vec<String> = vec!["neovide", "--fullscreen"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+
+        let _accessing_settings = ACCESSING_SETTINGS.lock().unwrap();
+        handle_command_line_arguments(args).expect("Could not parse arguments");
+        assert_eq!(SETTINGS.get::<CmdLineSettings>().fullscreen, true);
+    }
+
+    #[test]
+    fn test_fullscreen_environment_variable() {
+        let args: Vec<String> = vec!["neovide"].iter().map(|s| s.to_string()).collect();
+
+        let _accessing_settings = ACCESSING_SETTINGS.lock().unwrap();
+        set_var("NEOVIDE_FULLSCREEN", "true");
+        handle_command_line_arguments(args).expect("Could not parse arguments");
+        assert_eq!(SETTINGS.get::<CmdLineSettings>().fullscreen, true);
+    }
+
+    #[test]
+    fn test_transparency_arg() {
+        let args: Vec<String> = vec!["neovide", "--transparency", "0.8"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+
+        let _accessing_settings = ACCESSING_SETTINGS.lock().unwrap();
+        handle_command_line_arguments(args).expect("Could not parse arguments");
+        assert_eq!(SETTINGS.get::<CmdLineSettings>().transparency, 0.8);
+    }
+
+    #[test]
+    fn test_fullscreen_monitor_arg() {
+        let args: Vec<String> = vec!["neovide", "--fullscreen-monitor", "2"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+
+        let _accessing_settings = ACCESSING_SETTINGS.lock().unwrap();
+        handle_command_line_arguments(args).expect("Could not parse arguments");
+        assert_eq!(
+            SETTINGS.get::<CmdLineSettings>().fullscreen_monitor,
+            Some("2".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_transparency_environment_variable() {
+        let args: Vec<String> = vec!["neovide"].iter().map(|s| s.to_string()).collect();
+
+        let _accessing_settings = ACCESSING_SETTINGS.lock().unwrap();
+        set_var("NEOVIDE_TRANSPARENCY", "0.5");
+        handle_command_line_arguments(args).expect("Could not parse arguments");
+        assert_eq!(SETTINGS.get::<CmdLineSettings>().transparency, 0.5);
+    }
+
+    #[test]
+    fn test_invalid_transparency() {
+        assert!(parse_transparency(Some("1.5".to_owned())).is_err());
+        assert!(parse_transparency(Some("opaque".to_owned())).is_err());
+        assert_eq!(parse_transparency(None), Ok(1.0));
+    }
+
+    #[test]
+    fn test_background_color_arg() {
+        let args: Vec<String> = vec!["neovide", "--background-color", "#1e1e2e"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+
+        let _accessing_settings = ACCESSING_SETTINGS.lock().unwrap();
+        handle_command_line_arguments(args).expect("Could not parse arguments");
+        assert_eq!(
+            SETTINGS.get::<CmdLineSettings>().background_color,
+            Some(0x1e1e2e)
+        );
+    }
+
+    #[test]
+    fn test_invalid_background_color() {
+        assert_eq!(
+            parse_background_color(Some("282828".to_owned())),
+            Ok(Some(0x282828))
+        );
+        assert!(parse_background_color(Some("#fff".to_owned())).is_err());
+        assert!(parse_background_color(Some("black".to_owned())).is_err());
+        assert_eq!(parse_background_color(None), Ok(None));
+    }
+
+    #[test]
+    fn test_startup_profile_arg() {
+        let args: Vec<String> = vec!["neovide", "--startup-profile", "startup.log"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+
+        let _accessing_settings = ACCESSING_SETTINGS.lock().unwrap();
+        handle_command_line_arguments(args).expect("Could not parse arguments");
+        assert_eq!(
+            SETTINGS.get::<CmdLineSettings>().startup_profile,
+            Some("startup.log".to_owned())
+        );
+    }
+
     #[test]
     fn test_neovim_bin_arg() {
         let args: Vec<String> = vec!["neovide", "--neovim-bin", "foo"]
@@ -356,4 +941,86 @@ mod tests {
             Some("foo".to_owned())
         );
     }
+
+    #[test]
+    fn test_backend_arg() {
+        let args: Vec<String> = vec!["neovide", "--backend", "d3d"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+
+        let _accessing_settings = ACCESSING_SETTINGS.lock().unwrap();
+        handle_command_line_arguments(args).expect("Could not parse arguments");
+        assert_eq!(SETTINGS.get::<CmdLineSettings>().backend, "d3d");
+    }
+
+    #[test]
+    fn test_backend_environment_variable() {
+        let args: Vec<String> = vec!["neovide"].iter().map(|s| s.to_string()).collect();
+
+        let _accessing_settings = ACCESSING_SETTINGS.lock().unwrap();
+        set_var("NEOVIDE_BACKEND", "opengl");
+        handle_command_line_arguments(args).expect("Could not parse arguments");
+        assert_eq!(SETTINGS.get::<CmdLineSettings>().backend, "opengl");
+    }
+
+    #[test]
+    fn test_gpu_arg() {
+        let args: Vec<String> = vec!["neovide", "--gpu", "discrete"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+
+        let _accessing_settings = ACCESSING_SETTINGS.lock().unwrap();
+        handle_command_line_arguments(args).expect("Could not parse arguments");
+        assert_eq!(
+            SETTINGS.get::<CmdLineSettings>().gpu,
+            Some("discrete".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_gpu_environment_variable() {
+        let args: Vec<String> = vec!["neovide"].iter().map(|s| s.to_string()).collect();
+
+        let _accessing_settings = ACCESSING_SETTINGS.lock().unwrap();
+        set_var("NEOVIDE_GPU", "integrated");
+        handle_command_line_arguments(args).expect("Could not parse arguments");
+        assert_eq!(
+            SETTINGS.get::<CmdLineSettings>().gpu,
+            Some("integrated".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_wm_class_arg() {
+        let args: Vec<String> = vec!["neovide", "--wm-class", "project"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+
+        let _accessing_settings = ACCESSING_SETTINGS.lock().unwrap();
+        handle_command_line_arguments(args).expect("Could not parse arguments");
+        assert_eq!(SETTINGS.get::<CmdLineSettings>().x11_wm_class, "project");
+        assert_eq!(SETTINGS.get::<CmdLineSettings>().wayland_app_id, "project");
+    }
+
+    #[test]
+    fn test_x11_wm_class_overrides_wm_class() {
+        let args: Vec<String> = vec![
+            "neovide",
+            "--wm-class",
+            "project",
+            "--x11-wm-class",
+            "other",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+
+        let _accessing_settings = ACCESSING_SETTINGS.lock().unwrap();
+        handle_command_line_arguments(args).expect("Could not parse arguments");
+        assert_eq!(SETTINGS.get::<CmdLineSettings>().x11_wm_class, "other");
+        assert_eq!(SETTINGS.get::<CmdLineSettings>().wayland_app_id, "project");
+    }
 }
@@ -0,0 +1,187 @@
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::fmt::Write;
+use std::panic::PanicInfo;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use backtrace::Backtrace;
+use parking_lot::Mutex;
+
+use crate::error_handling::show_error_dialog;
+
+// Enough to see what the user was doing right before the crash without the report getting huge
+const RECENT_EVENT_COUNT: usize = 50;
+
+#[derive(Default)]
+struct CrashContext {
+    gpu: Option<String>,
+    recent_events: VecDeque<Cow<'static, str>>,
+}
+
+lazy_static! {
+    static ref CRASH_CONTEXT: Mutex<CrashContext> = Mutex::new(CrashContext::default());
+}
+
+/// Where log files and crash reports are written.
+pub fn log_directory() -> PathBuf {
+    dirs::data_local_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join("neovide")
+        .join("logs")
+}
+
+/// Remembers the graphics backend and the gpu it runs on for the crash report.
+pub fn set_gpu_info(gpu: String) {
+    CRASH_CONTEXT.lock().gpu = Some(gpu);
+}
+
+/// The graphics backend and gpu, once the window is created.
+pub fn gpu_info() -> Option<String> {
+    CRASH_CONTEXT.lock().gpu.clone()
+}
+
+/// Keeps the last few window events for the crash report. Recording happens for every event, so
+/// the frequent ones should only be named rather than formatted.
+pub fn record_event(event: Cow<'static, str>) {
+    push_event(&mut CRASH_CONTEXT.lock().recent_events, event);
+}
+
+fn push_event(recent_events: &mut VecDeque<Cow<'static, str>>, event: Cow<'static, str>) {
+    if recent_events.len() == RECENT_EVENT_COUNT {
+        recent_events.pop_front();
+    }
+    recent_events.push_back(event);
+}
+
+fn panic_message(info: &PanicInfo) -> String {
+    let payload = info
+        .payload()
+        .downcast_ref::<&str>()
+        .map(|message| message.to_string())
+        .or_else(|| info.payload().downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown panic".to_owned());
+    match info.location() {
+        Some(location) => format!("{} at {}", payload, location),
+        None => payload,
+    }
+}
+
+fn format_report(
+    message: &str,
+    thread: &str,
+    gpu: Option<&str>,
+    recent_events: &VecDeque<Cow<'static, str>>,
+    backtrace: &str,
+) -> String {
+    let mut report = String::new();
+    writeln!(report, "Neovide {} crashed", crate_version!()).ok();
+    writeln!(report, "Panic: {}", message).ok();
+    writeln!(report, "Thread: {}", thread).ok();
+    writeln!(
+        report,
+        "OS: {} {}",
+        std::env::consts::OS,
+        std::env::consts::ARCH
+    )
+    .ok();
+    writeln!(report, "GPU: {}", gpu.unwrap_or("not created yet")).ok();
+
+    writeln!(report, "\nLast events:").ok();
+    for event in recent_events {
+        writeln!(report, "  {}", event).ok();
+    }
+
+    writeln!(report, "\nBacktrace:\n{}", backtrace).ok();
+    report
+}
+
+fn write_report(report: &str) -> std::io::Result<PathBuf> {
+    let directory = log_directory();
+    std::fs::create_dir_all(&directory)?;
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or_default();
+    let path = directory.join(format!("crash-{}.log", time));
+    std::fs::write(&path, report)?;
+    Ok(path)
+}
+
+fn handle_panic(info: &PanicInfo) {
+    let message = panic_message(info);
+    let thread = std::thread::current();
+    let thread_name = thread.name().unwrap_or("unnamed");
+    let backtrace = format!("{:?}", Backtrace::new());
+
+    // The panic could have happened while the context was locked
+    let report = match CRASH_CONTEXT.try_lock() {
+        Some(context) => format_report(
+            &message,
+            thread_name,
+            context.gpu.as_deref(),
+            &context.recent_events,
+            &backtrace,
+        ),
+        None => format_report(&message, thread_name, None, &VecDeque::new(), &backtrace),
+    };
+
+    let explanation = match write_report(&report) {
+        Ok(path) => format!(
+            "{}\n\nA crash report was written to {}. Please attach it when reporting the \
+             crash at https://github.com/neovide/neovide/issues",
+            message,
+            path.display()
+        ),
+        Err(error) => format!(
+            "{}\n\nThe crash report could not be written: {}",
+            message, error
+        ),
+    };
+    eprintln!("{}", report);
+
+    // Appkit only shows dialogs from the main thread
+    if cfg!(not(target_os = "macos")) || thread_name == "main" {
+        show_error_dialog("Neovide crashed", &explanation);
+    }
+}
+
+/// Writes a crash report and tells the user where to find it when neovide panics, instead of
+/// just disappearing. The default hook still runs afterwards.
+pub fn install_panic_hook() {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        handle_panic(info);
+        default_hook(info);
+    }));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recent_events_are_capped() {
+        let mut recent_events = VecDeque::new();
+        for index in 0..RECENT_EVENT_COUNT + 5 {
+            push_event(&mut recent_events, index.to_string().into());
+        }
+        assert_eq!(recent_events.len(), RECENT_EVENT_COUNT);
+        assert_eq!(recent_events.front().map(|event| event.as_ref()), Some("5"));
+    }
+
+    #[test]
+    fn test_format_report() {
+        let recent_events: VecDeque<Cow<'static, str>> = vec!["Focused(true)".into()].into();
+        let report = format_report(
+            "index out of bounds at src/editor/grid.rs:10:5",
+            "main",
+            Some("opengl on llvmpipe"),
+            &recent_events,
+            "frames",
+        );
+        assert!(report.contains("Panic: index out of bounds at src/editor/grid.rs:10:5"));
+        assert!(report.contains("GPU: opengl on llvmpipe"));
+        assert!(report.contains("  Focused(true)"));
+    }
+}
@@ -0,0 +1,135 @@
+use std::fmt::Write;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use log::{error, info};
+use parking_lot::Mutex;
+
+use crate::cmd_line::CmdLineSettings;
+use crate::crash_report::{gpu_info, log_directory};
+use crate::settings::{Value, SETTINGS};
+
+lazy_static! {
+    static ref NEOVIM_VERSION: Mutex<Option<String>> = Mutex::new(None);
+}
+
+/// Remembers the version of the neovim neovide attached to for the diagnostics.
+pub fn set_neovim_version(version: String) {
+    *NEOVIM_VERSION.lock() = Some(version);
+}
+
+/// What only the window knows about how it is shown.
+pub struct WindowDiagnostics {
+    pub scale_factor: f64,
+    pub monitor: Option<String>,
+    pub font_size: f32,
+    pub fonts: Vec<String>,
+}
+
+fn format_report(
+    neovim_version: Option<&str>,
+    gpu: Option<&str>,
+    cmd_line_settings: &CmdLineSettings,
+    window: &WindowDiagnostics,
+    settings: &[(String, Value)],
+) -> String {
+    let mut report = String::new();
+    writeln!(report, "Neovide: {}", crate_version!()).ok();
+    writeln!(
+        report,
+        "Neovim: {}",
+        neovim_version.unwrap_or("not attached yet")
+    )
+    .ok();
+    writeln!(
+        report,
+        "OS: {} {}",
+        std::env::consts::OS,
+        std::env::consts::ARCH
+    )
+    .ok();
+    writeln!(report, "Backend: {}", cmd_line_settings.backend).ok();
+    writeln!(report, "GPU: {}", gpu.unwrap_or("not created yet")).ok();
+    writeln!(report, "Multigrid: {}", cmd_line_settings.multi_grid).ok();
+    writeln!(report, "Scale factor: {}", window.scale_factor).ok();
+    writeln!(
+        report,
+        "Monitor: {}",
+        window.monitor.as_deref().unwrap_or("unknown")
+    )
+    .ok();
+    writeln!(report, "Font size: {}", window.font_size).ok();
+    writeln!(report, "Fonts: {}", window.fonts.join(", ")).ok();
+
+    writeln!(report, "\nSettings:").ok();
+    for (name, value) in settings {
+        writeln!(report, "  neovide_{} = {}", name, value).ok();
+    }
+    report
+}
+
+fn default_diagnostics_path() -> PathBuf {
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or_default();
+    log_directory().join(format!("diagnostics-{}.txt", time))
+}
+
+/// Writes the versions, the graphics backend, the fonts and the value of every setting to the
+/// file, or to one in the log directory without a path, and returns where it went.
+pub fn write_diagnostics(path: Option<String>, window: &WindowDiagnostics) -> Option<PathBuf> {
+    let report = format_report(
+        NEOVIM_VERSION.lock().as_deref(),
+        gpu_info().as_deref(),
+        &SETTINGS.get::<CmdLineSettings>(),
+        window,
+        &SETTINGS.values(),
+    );
+    let path = path
+        .map(PathBuf::from)
+        .unwrap_or_else(default_diagnostics_path);
+    if let Some(directory) = path.parent() {
+        std::fs::create_dir_all(directory).ok();
+    }
+    match std::fs::write(&path, report) {
+        Ok(()) => {
+            info!("Wrote the diagnostics to {}", path.display());
+            Some(path)
+        }
+        Err(error) => {
+            error!(
+                "Could not write the diagnostics to {}: {}",
+                path.display(),
+                error
+            );
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_report() {
+        let window = WindowDiagnostics {
+            scale_factor: 2.0,
+            monitor: Some("Built-in Retina Display".to_owned()),
+            font_size: 28.0,
+            fonts: vec!["Fira Code".to_owned(), "Apple Color Emoji".to_owned()],
+        };
+        let report = format_report(
+            Some("v0.7.2"),
+            Some("metal on Apple M1"),
+            &CmdLineSettings::default(),
+            &window,
+            &[("refresh_rate".to_owned(), Value::from(60))],
+        );
+        assert!(report.contains("Neovim: v0.7.2"));
+        assert!(report.contains("GPU: metal on Apple M1"));
+        assert!(report.contains("Fonts: Fira Code, Apple Color Emoji"));
+        assert!(report.contains("  neovide_refresh_rate = 60"));
+    }
+}
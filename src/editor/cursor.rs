@@ -46,6 +46,8 @@ pub struct Cursor {
     pub enabled: bool,
     pub double_width: bool,
     pub character: String,
+    // The style of the character under the cursor
+    pub cell_style: Option<Arc<Style>>,
 }
 
 impl Cursor {
@@ -62,6 +64,23 @@ impl Cursor {
             enabled: true,
             double_width: false,
             character: " ".to_string(),
+            cell_style: None,
+        }
+    }
+
+    // Without colors of its own the cursor inverts the cell, so the character under it stays
+    // readable whatever color it has
+    fn cell_foreground(&self, default_colors: &Colors) -> Color4f {
+        match &self.cell_style {
+            Some(style) => style.foreground(default_colors),
+            None => default_colors.foreground.unwrap(),
+        }
+    }
+
+    fn cell_background(&self, default_colors: &Colors) -> Color4f {
+        match &self.cell_style {
+            Some(style) => style.background(default_colors),
+            None => default_colors.background.unwrap(),
         }
     }
 
@@ -69,14 +88,14 @@ impl Cursor {
         self.style
             .as_ref()
             .and_then(|s| s.colors.foreground)
-            .unwrap_or_else(|| default_colors.background.unwrap())
+            .unwrap_or_else(|| self.cell_background(default_colors))
     }
 
     pub fn background(&self, default_colors: &Colors) -> Color4f {
         self.style
             .as_ref()
             .and_then(|s| s.colors.background)
-            .unwrap_or_else(|| default_colors.foreground.unwrap())
+            .unwrap_or_else(|| self.cell_foreground(default_colors))
     }
 
     pub fn change_mode(&mut self, cursor_mode: &CursorMode, styles: &HashMap<u64, Arc<Style>>) {
@@ -186,6 +205,21 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_inverts_cell_without_colors() {
+        let mut cursor = Cursor::new();
+        cursor.cell_style = Some(Arc::new(Style::new(COLORS)));
+
+        assert_eq!(
+            cursor.foreground(&DEFAULT_COLORS),
+            COLORS.background.unwrap()
+        );
+        assert_eq!(
+            cursor.background(&DEFAULT_COLORS),
+            COLORS.foreground.unwrap()
+        );
+    }
+
     #[test]
     fn test_change_mode() {
         let cursor_mode = CursorMode {
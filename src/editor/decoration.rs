@@ -0,0 +1,234 @@
+use std::fmt;
+
+use log::error;
+use rmpv::Value;
+use skia_safe::{image::CachingHint, Color4f, Data, Image};
+
+/// An image decoration's picture, read and decoded into pixels on a blocking thread when the
+/// decoration arrives, so drawing never waits on the file or the decoder.
+#[derive(Clone)]
+pub struct DecorationImage(pub Image);
+
+impl fmt::Debug for DecorationImage {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            formatter,
+            "DecorationImage({}x{})",
+            self.0.width(),
+            self.0.height()
+        )
+    }
+}
+
+impl PartialEq for DecorationImage {
+    fn eq(&self, other: &DecorationImage) -> bool {
+        self.0.unique_id() == other.0.unique_id()
+    }
+}
+
+fn load_image(path: &str) -> Option<DecorationImage> {
+    let bytes = std::fs::read(path)
+        .map_err(|error| error!("Could not read decoration image {}: {}", path, error))
+        .ok()?;
+    // Encoded images are only decoded when they are first drawn otherwise
+    let image = Image::from_encoded(Data::new_copy(&bytes))
+        .and_then(|image| image.to_raster_image(CachingHint::Disallow));
+    if image.is_none() {
+        error!("Could not decode decoration image {}", path);
+    }
+    image.map(DecorationImage)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum DecorationShape {
+    Rectangle {
+        width: f64,
+        height: f64,
+        filled: bool,
+    },
+    // Runs from the anchor to the end cell
+    Line {
+        end_row: f64,
+        end_column: f64,
+    },
+    Badge {
+        text: String,
+    },
+    Image {
+        path: String,
+        width: f64,
+        height: f64,
+        // Empty until load_image ran, or when the file couldn't be read
+        image: Option<DecorationImage>,
+    },
+}
+
+/// Something a plugin asked neovide to draw on top of a grid, anchored to a cell so it moves
+/// along when the grid scrolls. Positions and sizes are in cells and may be fractional.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Decoration {
+    pub id: String,
+    pub row: f64,
+    pub column: f64,
+    pub color: Option<Color4f>,
+    pub background: Option<Color4f>,
+    pub shape: DecorationShape,
+}
+
+// Either a packed 0xRRGGBB number like nvim_get_hl returns or a "#rrggbb" string
+fn parse_color(value: &Value) -> Option<Color4f> {
+    let packed = match value {
+        Value::String(text) => {
+            let text = text.as_str()?;
+            let hex = text.strip_prefix('#')?;
+            if hex.len() != 6 {
+                return None;
+            }
+            u32::from_str_radix(hex, 16).ok()?
+        }
+        _ => value.as_u64()? as u32,
+    };
+    Some(Color4f::new(
+        ((packed >> 16) & 0xff) as f32 / 255.0,
+        ((packed >> 8) & 0xff) as f32 / 255.0,
+        (packed & 0xff) as f32 / 255.0,
+        1.0,
+    ))
+}
+
+impl Decoration {
+    /// Reads a decoration from the table a plugin passed, like
+    /// `{kind = "rectangle", row = 2, col = 4, width = 10, height = 1, color = "#ff0000"}`.
+    pub fn parse(id: String, spec: &Value) -> Result<Decoration, String> {
+        let fields = spec
+            .as_map()
+            .ok_or_else(|| format!("Decoration {} is not a table", id))?;
+        let field = |name: &str| {
+            fields
+                .iter()
+                .find(|(key, _)| key.as_str() == Some(name))
+                .map(|(_, value)| value)
+        };
+        let number = |name: &str| field(name).and_then(Value::as_f64);
+        let text = |name: &str| field(name).and_then(Value::as_str).map(str::to_owned);
+
+        let kind = text("kind").unwrap_or_default();
+        let shape = match kind.as_str() {
+            "rectangle" => DecorationShape::Rectangle {
+                width: number("width").unwrap_or(1.0),
+                height: number("height").unwrap_or(1.0),
+                filled: field("fill")
+                    .and_then(|fill| {
+                        fill.as_bool()
+                            .or_else(|| fill.as_i64().map(|fill| fill != 0))
+                    })
+                    .unwrap_or(false),
+            },
+            "line" => DecorationShape::Line {
+                end_row: number("end_row").ok_or_else(|| format!("Line {} has no end_row", id))?,
+                end_column: number("end_col")
+                    .ok_or_else(|| format!("Line {} has no end_col", id))?,
+            },
+            "badge" => DecorationShape::Badge {
+                text: text("text").ok_or_else(|| format!("Badge {} has no text", id))?,
+            },
+            "image" => DecorationShape::Image {
+                path: text("path").ok_or_else(|| format!("Image {} has no path", id))?,
+                width: number("width").unwrap_or(1.0),
+                height: number("height").unwrap_or(1.0),
+                image: None,
+            },
+            _ => return Err(format!("Unknown decoration kind {:?} for {}", kind, id)),
+        };
+
+        Ok(Decoration {
+            row: number("row").unwrap_or(0.0),
+            column: number("col").unwrap_or(0.0),
+            color: field("color").and_then(parse_color),
+            background: field("background").and_then(parse_color),
+            shape,
+            id,
+        })
+    }
+
+    /// Reads and decodes the file of an image decoration. It blocks, so it has to run on a
+    /// blocking thread.
+    pub fn load_image(&mut self) {
+        if let DecorationShape::Image { path, image, .. } = &mut self.shape {
+            *image = load_image(path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec(fields: Vec<(&str, Value)>) -> Value {
+        Value::Map(
+            fields
+                .into_iter()
+                .map(|(key, value)| (Value::from(key), value))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_parse_color() {
+        assert_eq!(
+            parse_color(&Value::from("#ff0000")),
+            Some(Color4f::new(1.0, 0.0, 0.0, 1.0))
+        );
+        assert_eq!(
+            parse_color(&Value::from(0x0000ff)),
+            Some(Color4f::new(0.0, 0.0, 1.0, 1.0))
+        );
+        assert_eq!(parse_color(&Value::from("red")), None);
+    }
+
+    #[test]
+    fn test_parse_decoration() {
+        let decoration = Decoration::parse(
+            "build".to_owned(),
+            &spec(vec![
+                ("kind", Value::from("badge")),
+                ("row", Value::from(3)),
+                ("col", Value::from(1.5)),
+                ("text", Value::from("ok")),
+            ]),
+        )
+        .unwrap();
+        assert_eq!(decoration.row, 3.0);
+        assert_eq!(decoration.column, 1.5);
+        assert_eq!(
+            decoration.shape,
+            DecorationShape::Badge {
+                text: "ok".to_owned()
+            }
+        );
+
+        assert!(Decoration::parse(
+            "line".to_owned(),
+            &spec(vec![("kind", Value::from("line"))])
+        )
+        .is_err());
+        assert!(Decoration::parse("circle".to_owned(), &Value::from("circle")).is_err());
+    }
+
+    #[test]
+    fn test_missing_images_are_left_empty() {
+        let mut decoration = Decoration::parse(
+            "plot".to_owned(),
+            &spec(vec![
+                ("kind", Value::from("image")),
+                ("path", Value::from("/nonexistent/neovide/plot.png")),
+            ]),
+        )
+        .unwrap();
+        decoration.load_image();
+        assert!(matches!(
+            decoration.shape,
+            DecorationShape::Image { image: None, .. }
+        ));
+    }
+}
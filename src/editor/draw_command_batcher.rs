@@ -1,23 +1,195 @@
+use std::collections::HashSet;
 use std::sync::mpsc::{channel, Receiver, SendError, Sender};
+use std::sync::Arc;
+use std::time::Instant;
 
-use super::DrawCommand;
-use crate::channel_utils::*;
+use log::trace;
+use parking_lot::Mutex;
+
+use super::{DrawCommand, WindowDrawCommand};
+
+// Flushed commands are only compacted once this many piled up, which the renderer keeps up with
+// unless neovim floods it or the window is minimized
+const COMPACT_THRESHOLD: usize = 4096;
+
+// The rows a draw line command redraws, which is always the whole row
+fn drawn_row(draw_command: &DrawCommand) -> Option<(u64, u64)> {
+    match draw_command {
+        DrawCommand::Window {
+            grid_id,
+            command: WindowDrawCommand::DrawLine(line_fragments),
+        } => line_fragments
+            .first()
+            .map(|fragment| (*grid_id, fragment.window_top)),
+        _ => None,
+    }
+}
+
+// What the commands after the one being looked at replace, going from the last one back
+#[derive(Default)]
+struct Superseded {
+    rows: HashSet<(u64, u64)>,
+    cursor: bool,
+    mode: bool,
+    popup_menu: bool,
+    popup_menu_selection: bool,
+    viewports: HashSet<u64>,
+    visibilities: HashSet<u64>,
+    // Positions are only replaced by the next one of the grid when nothing else happened to it
+    // in between, since the first one may be what created the grid
+    positions: HashSet<u64>,
+}
+
+impl Superseded {
+    fn forget_rows(&mut self, grid_id: u64) {
+        self.rows.retain(|(row_grid_id, _)| *row_grid_id != grid_id);
+    }
+
+    fn keep(&mut self, draw_command: &DrawCommand) -> bool {
+        if let Some(row) = drawn_row(draw_command) {
+            self.positions.remove(&row.0);
+            return self.rows.insert(row);
+        }
+        match draw_command {
+            DrawCommand::Window { grid_id, command } => {
+                let grid_id = *grid_id;
+                match command {
+                    WindowDrawCommand::Viewport { .. } => {
+                        self.positions.remove(&grid_id);
+                        self.viewports.insert(grid_id)
+                    }
+                    WindowDrawCommand::Show | WindowDrawCommand::Hide => {
+                        self.positions.remove(&grid_id);
+                        self.visibilities.insert(grid_id)
+                    }
+                    WindowDrawCommand::Position { .. } => {
+                        self.forget_rows(grid_id);
+                        self.visibilities.remove(&grid_id);
+                        self.positions.insert(grid_id)
+                    }
+                    // These move what is on the grid or take it away
+                    WindowDrawCommand::Scroll { .. }
+                    | WindowDrawCommand::Clear
+                    | WindowDrawCommand::Close
+                    | WindowDrawCommand::External(_) => {
+                        self.forget_rows(grid_id);
+                        self.visibilities.remove(&grid_id);
+                        self.positions.remove(&grid_id);
+                        true
+                    }
+                    _ => {
+                        self.positions.remove(&grid_id);
+                        true
+                    }
+                }
+            }
+            DrawCommand::CloseWindow(grid_id) => {
+                self.forget_rows(*grid_id);
+                self.visibilities.remove(grid_id);
+                self.positions.remove(grid_id);
+                true
+            }
+            DrawCommand::UpdateCursor(_) => !std::mem::replace(&mut self.cursor, true),
+            DrawCommand::ModeChanged(_) => !std::mem::replace(&mut self.mode, true),
+            DrawCommand::ShowPopupMenu(_) | DrawCommand::HidePopupMenu => {
+                !std::mem::replace(&mut self.popup_menu, true)
+            }
+            DrawCommand::SelectPopupMenuItem(_) => {
+                !self.popup_menu && !std::mem::replace(&mut self.popup_menu_selection, true)
+            }
+            // Fonts and the default style decide how the lines after them are drawn
+            DrawCommand::FontChanged(_)
+            | DrawCommand::WideFontChanged(_)
+            | DrawCommand::DefaultStyleChanged(_) => {
+                self.rows.clear();
+                true
+            }
+            DrawCommand::Export { .. } => true,
+        }
+    }
+}
+
+// Drops the commands whose effect is replaced by later ones: lines that are drawn again, and
+// the cursor, mode, popup menu, viewports and window positions that are set again. Anything
+// that happens to a grid in between, like a scroll or a resize, could move an earlier line, so
+// it is kept then.
+fn compact(draw_commands: Vec<DrawCommand>) -> Vec<DrawCommand> {
+    let mut superseded = Superseded::default();
+    let mut compacted: Vec<DrawCommand> = draw_commands
+        .into_iter()
+        .rev()
+        .filter(|draw_command| superseded.keep(draw_command))
+        .collect();
+    compacted.reverse();
+    compacted
+}
+
+#[derive(Default)]
+struct PendingDrawCommands {
+    draw_commands: Vec<DrawCommand>,
+    // How many were left after the last compaction, so it only runs again once they doubled
+    compacted_length: usize,
+    last_flush: Option<Instant>,
+}
+
+/// The draw commands the editor flushed that the renderer hasn't taken yet. Instead of queueing
+/// up every batch, later batches are added to the ones still waiting, and commands replaced by
+/// later ones are dropped once they pile up. So a flood of output, or a minimized window that isn't drawn, keeps
+/// about a screen of lines around rather than every line neovim sent.
+#[derive(Clone, Default)]
+pub struct FlushedDrawCommands {
+    pending: Arc<Mutex<PendingDrawCommands>>,
+}
+
+impl FlushedDrawCommands {
+    pub fn new() -> FlushedDrawCommands {
+        FlushedDrawCommands::default()
+    }
+
+    pub fn push(&self, batch: Vec<DrawCommand>) {
+        let mut pending = self.pending.lock();
+        pending.draw_commands.extend(batch);
+        pending.last_flush = Some(Instant::now());
+
+        let length = pending.draw_commands.len();
+        if length > COMPACT_THRESHOLD.max(2 * pending.compacted_length) {
+            let draw_commands = std::mem::take(&mut pending.draw_commands);
+            pending.draw_commands = compact(draw_commands);
+            pending.compacted_length = pending.draw_commands.len();
+            trace!(
+                "Compacted {} waiting draw commands to {}",
+                length,
+                pending.compacted_length
+            );
+        }
+    }
+
+    /// Everything flushed since the last time, in order, along with when neovim last flushed.
+    pub fn take(&self) -> (Vec<DrawCommand>, Option<Instant>) {
+        let mut pending = self.pending.lock();
+        pending.compacted_length = 0;
+        (
+            std::mem::take(&mut pending.draw_commands),
+            pending.last_flush,
+        )
+    }
+}
 
 pub struct DrawCommandBatcher {
     window_draw_command_sender: Sender<DrawCommand>,
     window_draw_command_receiver: Receiver<DrawCommand>,
 
-    batched_draw_command_sender: LoggingSender<Vec<DrawCommand>>,
+    flushed_draw_commands: FlushedDrawCommands,
 }
 
 impl DrawCommandBatcher {
-    pub fn new(batched_draw_command_sender: LoggingSender<Vec<DrawCommand>>) -> DrawCommandBatcher {
+    pub fn new(flushed_draw_commands: FlushedDrawCommands) -> DrawCommandBatcher {
         let (sender, receiver) = channel();
 
         DrawCommandBatcher {
             window_draw_command_sender: sender,
             window_draw_command_receiver: receiver,
-            batched_draw_command_sender,
+            flushed_draw_commands,
         }
     }
 
@@ -25,8 +197,147 @@ impl DrawCommandBatcher {
         self.window_draw_command_sender.send(draw_command)
     }
 
-    pub fn send_batch(&self) -> Result<(), SendError<Vec<DrawCommand>>> {
+    pub fn send_batch(&self) {
         let batch = self.window_draw_command_receiver.try_iter().collect();
-        self.batched_draw_command_sender.send(batch)
+        self.flushed_draw_commands.push(batch);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::editor::{Cursor, LineFragment};
+
+    fn draw_line(grid_id: u64, row: u64, text: &str) -> DrawCommand {
+        DrawCommand::Window {
+            grid_id,
+            command: WindowDrawCommand::DrawLine(vec![LineFragment {
+                text: text.to_owned(),
+                window_left: 0,
+                window_top: row,
+                width: text.len() as u64,
+                style: None,
+                right_to_left: false,
+            }]),
+        }
+    }
+
+    fn texts(draw_commands: &[DrawCommand]) -> Vec<String> {
+        draw_commands
+            .iter()
+            .map(|draw_command| match draw_command {
+                DrawCommand::Window {
+                    command: WindowDrawCommand::DrawLine(line_fragments),
+                    ..
+                } => line_fragments[0].text.clone(),
+                DrawCommand::Window { .. } => "window".to_owned(),
+                _ => "other".to_owned(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_compact_keeps_last_draw_of_each_row() {
+        let draw_commands = vec![
+            draw_line(1, 0, "old"),
+            draw_line(2, 0, "other grid"),
+            draw_line(1, 0, "new"),
+            draw_line(1, 1, "before scroll"),
+            DrawCommand::Window {
+                grid_id: 1,
+                command: WindowDrawCommand::Clear,
+            },
+            draw_line(1, 1, "after scroll"),
+        ];
+        assert_eq!(
+            texts(&compact(draw_commands)),
+            vec![
+                "other grid",
+                "new",
+                "before scroll",
+                "window",
+                "after scroll"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_compact_keeps_the_last_state() {
+        let viewport = |grid_id: u64, top_line: f64| DrawCommand::Window {
+            grid_id,
+            command: WindowDrawCommand::Viewport {
+                window_handle: 1000 + grid_id,
+                top_line,
+                bottom_line: top_line + 10.0,
+                line_count: None,
+            },
+        };
+        let position = |grid_id: u64| DrawCommand::Window {
+            grid_id,
+            command: WindowDrawCommand::Position {
+                grid_position: (0.0, 0.0),
+                grid_size: (10, 10),
+                floating_order: None,
+            },
+        };
+        let draw_commands = vec![
+            position(2),
+            DrawCommand::UpdateCursor(Cursor::new()),
+            viewport(1, 0.0),
+            DrawCommand::SelectPopupMenuItem(Some(1)),
+            DrawCommand::HidePopupMenu,
+            viewport(1, 1.0),
+            viewport(2, 0.0),
+            position(3),
+            position(3),
+            DrawCommand::SelectPopupMenuItem(Some(2)),
+            DrawCommand::SelectPopupMenuItem(None),
+            DrawCommand::UpdateCursor(Cursor::new()),
+        ];
+        let compacted = compact(draw_commands);
+        assert_eq!(compacted.len(), 7);
+        assert!(matches!(
+            compacted[0],
+            DrawCommand::Window {
+                grid_id: 2,
+                command: WindowDrawCommand::Position { .. }
+            }
+        ));
+        assert!(matches!(compacted[1], DrawCommand::HidePopupMenu));
+        assert!(matches!(
+            compacted[2],
+            DrawCommand::Window {
+                grid_id: 1,
+                command: WindowDrawCommand::Viewport { top_line, .. }
+            } if top_line == 1.0
+        ));
+        assert!(matches!(
+            compacted[3],
+            DrawCommand::Window { grid_id: 2, .. }
+        ));
+        assert!(matches!(
+            compacted[4],
+            DrawCommand::Window {
+                grid_id: 3,
+                command: WindowDrawCommand::Position { .. }
+            }
+        ));
+        assert!(matches!(
+            compacted[5],
+            DrawCommand::SelectPopupMenuItem(None)
+        ));
+        assert!(matches!(compacted[6], DrawCommand::UpdateCursor(_)));
+    }
+
+    #[test]
+    fn test_flushed_draw_commands_collect_batches() {
+        let flushed_draw_commands = FlushedDrawCommands::new();
+        flushed_draw_commands.push(vec![draw_line(1, 0, "first")]);
+        flushed_draw_commands.push(vec![draw_line(1, 1, "second")]);
+        assert_eq!(
+            texts(&flushed_draw_commands.take().0),
+            vec!["first", "second"]
+        );
+        assert!(flushed_draw_commands.take().0.is_empty());
     }
 }
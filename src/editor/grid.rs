@@ -1,13 +1,16 @@
 use std::sync::Arc;
 
+use smol_str::SmolStr;
+
 use super::style::Style;
 
-pub type GridCell = (String, Option<Arc<Style>>);
+// The text of a cell is a single grapheme, or empty after a double width one, which fits inline
+pub type GridCell = (SmolStr, Option<Arc<Style>>);
 
 #[macro_export]
 macro_rules! default_cell {
     () => {
-        (" ".to_owned(), None)
+        (smol_str::SmolStr::new_inline(" "), None)
     };
 }
 
@@ -146,11 +149,11 @@ mod tests {
         let mut character_grid = CharacterGrid::new(context.size);
 
         character_grid.characters[context.index] = (
-            "foo".to_string(),
+            SmolStr::from("foo"),
             Some(Arc::new(Style::new(context.none_colors.clone()))),
         );
         let result = (
-            "foo".to_string(),
+            SmolStr::from("foo"),
             Some(Arc::new(Style::new(context.none_colors.clone()))),
         );
 
@@ -167,18 +170,18 @@ mod tests {
         let mut character_grid = CharacterGrid::new(context.size);
 
         character_grid.characters[context.index] = (
-            "foo".to_string(),
+            SmolStr::from("foo"),
             Some(Arc::new(Style::new(context.none_colors.clone()))),
         );
         let result = (
-            "bar".to_string(),
+            SmolStr::from("bar"),
             Some(Arc::new(Style::new(context.none_colors.clone()))),
         );
 
         // RUN FUNCTION
         let cell = character_grid.get_cell_mut(context.x, context.y).unwrap();
         *cell = (
-            "bar".to_string(),
+            SmolStr::from("bar"),
             Some(Arc::new(Style::new(context.none_colors.clone()))),
         );
 
@@ -192,7 +195,7 @@ mod tests {
     fn set_all_characters_sets_all_cells_to_given_character() {
         let context = Context::new();
         let grid_cell = (
-            "foo".to_string(),
+            SmolStr::from("foo"),
             Some(Arc::new(Style::new(context.none_colors))),
         );
         let mut character_grid = CharacterGrid::new(context.size);
@@ -211,7 +214,7 @@ mod tests {
         let mut character_grid = CharacterGrid::new(context.size);
 
         let grid_cell = (
-            "foo".to_string(),
+            SmolStr::from("foo"),
             Some(Arc::new(Style::new(context.none_colors))),
         );
         character_grid.characters = vec![grid_cell.clone(); context.area];
@@ -237,7 +240,7 @@ mod tests {
         );
 
         let grid_cell = (
-            "foo".to_string(),
+            SmolStr::from("foo"),
             Some(Arc::new(Style::new(context.none_colors))),
         );
         character_grid.characters = vec![grid_cell.clone(); context.area];
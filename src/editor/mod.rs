@@ -1,21 +1,30 @@
 mod cursor;
+mod decoration;
 mod draw_command_batcher;
 mod grid;
 mod style;
 mod window;
 
 use std::collections::HashMap;
-use std::sync::Arc;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
 use std::thread;
 
 use log::{error, trace};
-use tokio::sync::mpsc::UnboundedReceiver;
+use tokio::sync::mpsc::Receiver as TokioReceiver;
 
-use crate::bridge::{EditorMode, GuiOption, RedrawEvent, WindowAnchor};
+use crate::bridge::{EditorMode, GuiOption, PopupMenuItem, RedrawEvent, WindowAnchor};
 use crate::channel_utils::*;
+use crate::notification::NotificationLevel;
 use crate::redraw_scheduler::REDRAW_SCHEDULER;
+use crate::renderer::FontSettings;
+use crate::settings::{use_settings, Settings};
+use crate::startup_profile::{mark_startup, StartupPhase};
 pub use cursor::{Cursor, CursorMode, CursorShape};
-pub use draw_command_batcher::DrawCommandBatcher;
+pub use decoration::{Decoration, DecorationImage, DecorationShape};
+pub use draw_command_batcher::{DrawCommandBatcher, FlushedDrawCommands};
 pub use grid::CharacterGrid;
 pub use style::{Colors, Style};
 pub use window::*;
@@ -46,6 +55,18 @@ impl WindowAnchor {
     }
 }
 
+/// The completion menu neovim asked neovide to draw, anchored below a cell of a grid.
+#[derive(Clone, Debug)]
+pub struct PopupMenu {
+    pub items: Vec<PopupMenuItem>,
+    pub selected: Option<u64>,
+    pub grid: u64,
+    pub row: u64,
+    pub column: u64,
+    pub style: Option<Arc<Style>>,
+    pub selected_style: Option<Arc<Style>>,
+}
+
 #[derive(Debug)]
 pub enum DrawCommand {
     CloseWindow(u64),
@@ -55,40 +76,144 @@ pub enum DrawCommand {
     },
     UpdateCursor(Cursor),
     FontChanged(String),
+    WideFontChanged(String),
     DefaultStyleChanged(Style),
     ModeChanged(EditorMode),
+    ShowPopupMenu(PopupMenu),
+    SelectPopupMenuItem(Option<u64>),
+    HidePopupMenu,
+    Export {
+        path: Option<String>,
+        grid_size: (u64, u64),
+        lines: Vec<Vec<LineFragment>>,
+    },
+}
+
+#[derive(Debug)]
+pub enum EditorCommand {
+    NeovimRedrawEvent(RedrawEvent),
+    Export(Option<String>),
+    // Decorations come from plugins rather than from redraws and are drawn with neovim's next
+    // flush
+    Decorate {
+        // A neovim window id, or 0 for the current window
+        window: u64,
+        command: WindowDrawCommand,
+    },
+}
+
+// Set once assistive technology asked for the accessibility tree, until then there is no one to
+// build the text for
+static ACCESSIBLE_TEXT_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// Makes the editors send their text on every flush from now on, for the screen reader that
+/// connected.
+pub fn request_accessible_text() {
+    ACCESSIBLE_TEXT_REQUESTED.store(true, Ordering::Relaxed);
+}
+
+/// What a screen reader gets to see of the editor: the text of the window with the cursor, where
+/// the cursor is in it and which mode neovim is in.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AccessibleText {
+    pub lines: Vec<String>,
+    // The line and the character in it
+    pub cursor: (usize, usize),
+    pub mode: EditorMode,
 }
 
 #[derive(Debug)]
 pub enum WindowCommand {
     TitleChanged(String),
+    BufferChanged {
+        path: String,
+        modified: bool,
+        cwd: String,
+        filetype: String,
+    },
     SetMouseEnabled(bool),
+    SetMouseHide(bool),
+    SetMouseMoveEvent(bool),
+    SetMouseTime(u64),
+    SetBusy(bool),
+    SetProgress(Option<f64>),
+    SuppressContextMenu(bool),
+    ToggleFullscreen,
+    ToggleAlwaysOnTop,
+    SetFullscreen(bool),
+    Focus,
+    ZoomIn,
+    ZoomOut,
+    ZoomReset,
+    SetPresentation(bool),
+    Screenshot(Option<String>),
+    WriteDiagnostics(Option<String>),
+    StartRecording(Option<String>),
+    StopRecording,
+    NewWindow(Option<String>),
+    // Restarts neovim with the given arguments
+    Restart(Vec<String>),
+    // Whether the connection to a remote neovim broke and is being retried
+    SetConnectionLost(bool),
+    // Shows a message at the top of the window, or hides it again
+    ShowBanner(Option<String>),
+    Notify {
+        title: String,
+        body: String,
+        level: NotificationLevel,
+    },
+    SetRichClipboard {
+        html: String,
+        text: String,
+    },
+    UpdateAccessibility(AccessibleText),
+    FontSettingsChanged(FontSettings),
 }
 
 pub struct Editor {
     pub windows: HashMap<u64, Window>,
+    // The grid of each neovim window, known from the viewports ext_multigrid sends
+    window_grids: HashMap<u64, u64>,
     pub cursor: Cursor,
     pub defined_styles: HashMap<u64, Arc<Style>>,
+    // The highlight ids neovim uses for its builtin groups, like Pmenu
+    pub highlight_groups: HashMap<String, u64>,
     pub mode_list: Vec<CursorMode>,
+    pub current_mode: EditorMode,
+    // Only changes are sent, most flushes leave the text as it was
+    accessible_text: Option<AccessibleText>,
     pub draw_command_batcher: Arc<DrawCommandBatcher>,
     pub window_command_sender: LoggingSender<WindowCommand>,
 }
 
 impl Editor {
     pub fn new(
-        batched_draw_command_sender: LoggingSender<Vec<DrawCommand>>,
+        flushed_draw_commands: FlushedDrawCommands,
         window_command_sender: LoggingSender<WindowCommand>,
     ) -> Editor {
         Editor {
             windows: HashMap::new(),
+            window_grids: HashMap::new(),
             cursor: Cursor::new(),
             defined_styles: HashMap::new(),
+            highlight_groups: HashMap::new(),
             mode_list: Vec::new(),
-            draw_command_batcher: Arc::new(DrawCommandBatcher::new(batched_draw_command_sender)),
+            current_mode: EditorMode::Unknown(String::new()),
+            accessible_text: None,
+            draw_command_batcher: Arc::new(DrawCommandBatcher::new(flushed_draw_commands)),
             window_command_sender,
         }
     }
 
+    pub fn handle_editor_command(&mut self, command: EditorCommand) {
+        profile_scope!("handle editor command");
+        match command {
+            EditorCommand::NeovimRedrawEvent(event) => self.handle_redraw_event(event),
+            EditorCommand::Export(path) => self.export_current_window(path),
+            EditorCommand::Decorate { window, command } => self.decorate_window(window, command),
+        }
+    }
+
     pub fn handle_redraw_event(&mut self, event: RedrawEvent) {
         match event {
             RedrawEvent::SetTitle { title } => {
@@ -102,6 +227,7 @@ impl Editor {
                 if let Some(cursor_mode) = self.mode_list.get(mode_index as usize) {
                     self.cursor.change_mode(cursor_mode, &self.defined_styles);
                 }
+                self.current_mode = mode.clone();
                 self.draw_command_batcher
                     .queue(DrawCommand::ModeChanged(mode))
                     .ok();
@@ -119,16 +245,24 @@ impl Editor {
             RedrawEvent::BusyStart => {
                 trace!("Cursor off");
                 self.cursor.enabled = false;
+                self.window_command_sender
+                    .send(WindowCommand::SetBusy(true))
+                    .ok();
             }
             RedrawEvent::BusyStop => {
                 trace!("Cursor on");
                 self.cursor.enabled = true;
+                self.window_command_sender
+                    .send(WindowCommand::SetBusy(false))
+                    .ok();
             }
             RedrawEvent::Flush => {
                 trace!("Image flushed");
+                mark_startup(StartupPhase::FirstFlush);
                 self.send_cursor_info();
-                self.draw_command_batcher.send_batch().ok();
-                REDRAW_SCHEDULER.queue_next_frame();
+                self.send_accessible_text();
+                self.draw_command_batcher.send_batch();
+                REDRAW_SCHEDULER.queue_flushed_frame();
             }
             RedrawEvent::DefaultColorsSet { colors } => {
                 self.draw_command_batcher
@@ -138,6 +272,39 @@ impl Editor {
             RedrawEvent::HighlightAttributesDefine { id, style } => {
                 self.defined_styles.insert(id, Arc::new(style));
             }
+            RedrawEvent::HighlightGroupSet { name, id } => {
+                self.highlight_groups.insert(name, id);
+            }
+            RedrawEvent::PopupMenuShow {
+                items,
+                selected,
+                row,
+                column,
+                grid,
+            } => {
+                let popup_menu = PopupMenu {
+                    items,
+                    selected,
+                    grid,
+                    row,
+                    column,
+                    style: self.highlight_group_style("Pmenu"),
+                    selected_style: self.highlight_group_style("PmenuSel"),
+                };
+                self.draw_command_batcher
+                    .queue(DrawCommand::ShowPopupMenu(popup_menu))
+                    .ok();
+            }
+            RedrawEvent::PopupMenuSelect { selected } => {
+                self.draw_command_batcher
+                    .queue(DrawCommand::SelectPopupMenuItem(selected))
+                    .ok();
+            }
+            RedrawEvent::PopupMenuHide => {
+                self.draw_command_batcher
+                    .queue(DrawCommand::HidePopupMenu)
+                    .ok();
+            }
             RedrawEvent::CursorGoto {
                 grid,
                 column: left,
@@ -206,6 +373,7 @@ impl Editor {
                 anchor_top,
                 sort_order,
             ),
+            RedrawEvent::WindowExternalPosition { grid } => self.set_window_external_position(grid),
             RedrawEvent::WindowHide { grid } => {
                 let window = self.windows.get(&grid);
                 if let Some(window) = window {
@@ -218,15 +386,22 @@ impl Editor {
             }
             RedrawEvent::WindowViewport {
                 grid,
+                window,
                 top_line,
                 bottom_line,
+                line_count,
                 ..
-            } => self.send_updated_viewport(grid, top_line, bottom_line),
+            } => {
+                self.window_grids.insert(window, grid);
+                self.send_updated_viewport(grid, window, top_line, bottom_line, line_count)
+            }
             _ => {}
         };
     }
 
     fn close_window(&mut self, grid: u64) {
+        self.window_grids
+            .retain(|_, window_grid| *window_grid != grid);
         if let Some(window) = self.windows.remove(&grid) {
             window.close();
             self.draw_command_batcher
@@ -313,6 +488,17 @@ impl Editor {
         }
     }
 
+    fn set_window_external_position(&mut self, grid: u64) {
+        if let Some(window) = self.windows.get_mut(&grid) {
+            // External windows aren't drawn on top of the editor, so they aren't floating anymore
+            window.anchor_info = None;
+            window.set_external(true);
+            window.show();
+        } else {
+            error!("Attempted to move window that does not exist to an external window.");
+        }
+    }
+
     fn set_message_position(&mut self, grid: u64, grid_top: u64) {
         let parent_width = self
             .windows
@@ -406,33 +592,118 @@ impl Editor {
     fn send_cursor_info(&mut self) {
         let (grid_left, grid_top) = self.cursor.grid_position;
         if let Some(window) = self.windows.get(&self.cursor.parent_window_id) {
-            let (character, double_width) = window.get_cursor_character(grid_left, grid_top);
+            let (character, cell_style, double_width) =
+                window.get_cursor_character(grid_left, grid_top);
             self.cursor.character = character;
+            self.cursor.cell_style = cell_style;
             self.cursor.double_width = double_width;
         } else {
             self.cursor.double_width = false;
             self.cursor.character = " ".to_string();
+            self.cursor.cell_style = None;
         }
         self.draw_command_batcher
             .queue(DrawCommand::UpdateCursor(self.cursor.clone()))
             .ok();
     }
 
+    fn send_accessible_text(&mut self) {
+        if !ACCESSIBLE_TEXT_REQUESTED.load(Ordering::Relaxed) {
+            return;
+        }
+        let window = match self.windows.get(&self.cursor.parent_window_id) {
+            Some(window) => window,
+            None => return,
+        };
+        let (grid_left, grid_top) = self.cursor.grid_position;
+        let accessible_text = AccessibleText {
+            lines: window.text_lines(),
+            cursor: (grid_top as usize, window.text_offset(grid_left, grid_top)),
+            mode: self.current_mode.clone(),
+        };
+
+        if self.accessible_text.as_ref() != Some(&accessible_text) {
+            self.accessible_text = Some(accessible_text.clone());
+            self.window_command_sender
+                .send(WindowCommand::UpdateAccessibility(accessible_text))
+                .ok();
+        }
+    }
+
+    fn highlight_group_style(&self, name: &str) -> Option<Arc<Style>> {
+        self.highlight_groups
+            .get(name)
+            .and_then(|id| self.defined_styles.get(id))
+            .cloned()
+    }
+
     fn set_option(&mut self, gui_option: GuiOption) {
         trace!("Option set {:?}", &gui_option);
-        if let GuiOption::GuiFont(guifont) = gui_option {
+        let draw_command = match gui_option {
+            GuiOption::GuiFont(guifont) => DrawCommand::FontChanged(guifont),
+            GuiOption::GuiFontWide(guifontwide) => DrawCommand::WideFontChanged(guifontwide),
+            _ => return,
+        };
+        self.draw_command_batcher.queue(draw_command).ok();
+        for window in self.windows.values() {
+            window.redraw();
+        }
+    }
+
+    fn export_current_window(&mut self, path: Option<String>) {
+        if let Some(window) = self.windows.get(&self.cursor.parent_window_id) {
             self.draw_command_batcher
-                .queue(DrawCommand::FontChanged(guifont))
+                .queue(DrawCommand::Export {
+                    path,
+                    grid_size: window.get_grid_size(),
+                    lines: window.build_lines(),
+                })
                 .ok();
-            for window in self.windows.values() {
-                window.redraw();
-            }
+            // The export shouldn't have to wait for neovim to redraw something else
+            self.draw_command_batcher.send_batch();
+            REDRAW_SCHEDULER.queue_flushed_frame();
+        } else {
+            error!("No current window to export");
         }
     }
 
-    fn send_updated_viewport(&mut self, grid: u64, top_line: f64, bottom_line: f64) {
+    // Without ext_multigrid all windows are drawn on the one global grid
+    fn grid_of_window(&self, window: u64) -> Option<u64> {
+        if window == 0 {
+            Some(self.cursor.parent_window_id)
+        } else if self.window_grids.is_empty() {
+            Some(1)
+        } else {
+            self.window_grids.get(&window).copied()
+        }
+    }
+
+    fn decorate_window(&mut self, window: u64, command: WindowDrawCommand) {
+        let grid = match self.grid_of_window(window) {
+            Some(grid) if self.windows.contains_key(&grid) => grid,
+            _ => {
+                error!("No window {} to decorate", window);
+                return;
+            }
+        };
+        self.draw_command_batcher
+            .queue(DrawCommand::Window {
+                grid_id: grid,
+                command,
+            })
+            .ok();
+    }
+
+    fn send_updated_viewport(
+        &mut self,
+        grid: u64,
+        window_handle: u64,
+        top_line: f64,
+        bottom_line: f64,
+        line_count: Option<f64>,
+    ) {
         if let Some(window) = self.windows.get_mut(&grid) {
-            window.update_viewport(top_line, bottom_line);
+            window.update_viewport(window_handle, top_line, bottom_line, line_count);
         } else {
             trace!("viewport event received before window initialized");
         }
@@ -440,15 +711,17 @@ impl Editor {
 }
 
 pub fn start_editor(
-    mut redraw_event_receiver: UnboundedReceiver<RedrawEvent>,
-    batched_draw_command_sender: LoggingSender<Vec<DrawCommand>>,
+    mut editor_command_receiver: TokioReceiver<EditorCommand>,
+    flushed_draw_commands: FlushedDrawCommands,
     window_command_sender: LoggingSender<WindowCommand>,
+    settings: Arc<Settings>,
 ) {
     thread::spawn(move || {
-        let mut editor = Editor::new(batched_draw_command_sender, window_command_sender);
+        use_settings(settings);
+        let mut editor = Editor::new(flushed_draw_commands, window_command_sender);
 
-        while let Some(redraw_event) = redraw_event_receiver.blocking_recv() {
-            editor.handle_redraw_event(redraw_event);
+        while let Some(editor_command) = editor_command_receiver.blocking_recv() {
+            editor.handle_editor_command(editor_command);
         }
     });
 }
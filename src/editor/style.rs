@@ -24,6 +24,9 @@ pub struct Style {
     pub undercurl: bool,
     #[new(default)]
     pub blend: u8,
+    // Set for text neovim knows to be a link to this url
+    #[new(default)]
+    pub url: Option<String>,
 }
 
 impl Style {
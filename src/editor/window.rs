@@ -2,11 +2,12 @@ use std::collections::HashMap;
 use std::sync::Arc;
 
 use log::warn;
+use smol_str::SmolStr;
 use unicode_segmentation::UnicodeSegmentation;
 
 use super::grid::CharacterGrid;
 use super::style::Style;
-use super::{AnchorInfo, DrawCommand, DrawCommandBatcher};
+use super::{AnchorInfo, Decoration, DrawCommand, DrawCommandBatcher};
 use crate::bridge::GridLineCell;
 
 #[derive(Clone, Debug)]
@@ -16,6 +17,9 @@ pub struct LineFragment {
     pub window_top: u64,
     pub width: u64,
     pub style: Option<Arc<Style>>,
+    // Whether the text is part of a right to left run, which the renderer finds out once it laid
+    // out the whole row
+    pub right_to_left: bool,
 }
 
 #[derive(Clone, Debug)]
@@ -38,10 +42,16 @@ pub enum WindowDrawCommand {
     Show,
     Hide,
     Close,
+    External(bool),
     Viewport {
+        window_handle: u64,
         top_line: f64,
         bottom_line: f64,
+        line_count: Option<f64>,
     },
+    Decorate(Decoration),
+    RemoveDecoration(String),
+    ClearDecorations,
 }
 
 pub enum WindowType {
@@ -56,6 +66,7 @@ pub struct Window {
 
     pub anchor_info: Option<AnchorInfo>,
     grid_position: (f64, f64),
+    external: bool,
 
     draw_command_batcher: Arc<DrawCommandBatcher>,
 }
@@ -75,6 +86,7 @@ impl Window {
             window_type,
             anchor_info,
             grid_position,
+            external: false,
             draw_command_batcher,
         };
         window.send_updated_position();
@@ -98,10 +110,14 @@ impl Window {
         });
     }
 
-    pub fn get_cursor_character(&self, window_left: u64, window_top: u64) -> (String, bool) {
-        let character = match self.grid.get_cell(window_left, window_top) {
-            Some((character, _)) => character.clone(),
-            _ => ' '.to_string(),
+    pub fn get_cursor_character(
+        &self,
+        window_left: u64,
+        window_top: u64,
+    ) -> (String, Option<Arc<Style>>, bool) {
+        let (character, style) = match self.grid.get_cell(window_left, window_top) {
+            Some((character, style)) => (character.to_string(), style.clone()),
+            _ => (' '.to_string(), None),
         };
 
         let double_width = match self.grid.get_cell(window_left + 1, window_top) {
@@ -109,7 +125,7 @@ impl Window {
             _ => false,
         };
 
-        (character, double_width)
+        (character, style, double_width)
     }
 
     pub fn get_width(&self) -> u64 {
@@ -134,9 +150,18 @@ impl Window {
         self.anchor_info = anchor_info;
         self.grid_position = grid_position;
         self.send_updated_position();
+        // Positioning a grid inside the editor brings it back from its external window
+        self.set_external(false);
         self.redraw();
     }
 
+    pub fn set_external(&mut self, external: bool) {
+        if self.external != external {
+            self.external = external;
+            self.send_command(WindowDrawCommand::External(external));
+        }
+    }
+
     pub fn resize(&mut self, new_size: (u64, u64)) {
         self.grid.resize(new_size);
         self.send_updated_position();
@@ -158,24 +183,25 @@ impl Window {
             None => previous_style.clone(),
         };
 
-        // Compute text
-        let mut text = cell.text;
-        if let Some(times) = cell.repeat {
-            text = text.repeat(times as usize);
-        }
-
-        // Insert the contents of the cell into the grid.
-        if text.is_empty() {
+        // Insert the contents of the cell into the grid. The graphemes are stored inline, so
+        // redrawing a line doesn't allocate, and repeated cells like a run of spaces aren't
+        // built up as one long string first. A cell repeated 0 times still takes up a column,
+        // like an empty one.
+        let text = cell.text;
+        let repeat = cell.repeat.unwrap_or(1);
+        if text.is_empty() || repeat == 0 {
             if let Some(cell) = self.grid.get_cell_mut(*column_pos, row_index) {
-                *cell = (text, style.clone());
+                *cell = (SmolStr::default(), style.clone());
             }
             *column_pos += 1;
         } else {
-            for character in text.graphemes(true) {
-                if let Some(cell) = self.grid.get_cell_mut(*column_pos, row_index) {
-                    *cell = (character.to_string(), style.clone());
+            for _ in 0..repeat {
+                for character in text.graphemes(true) {
+                    if let Some(cell) = self.grid.get_cell_mut(*column_pos, row_index) {
+                        *cell = (SmolStr::new(character), style.clone());
+                    }
+                    *column_pos += 1;
                 }
-                *column_pos += 1;
             }
         }
 
@@ -215,6 +241,7 @@ impl Window {
             window_top: row_index,
             width,
             style: style.clone(),
+            right_to_left: false,
         };
 
         (start + width, line_fragment)
@@ -224,6 +251,11 @@ impl Window {
     // until current_start is greater than the grid width and sending the resulting
     // fragments as a batch
     fn redraw_line(&self, row: u64) {
+        let line_fragments = self.build_line(row);
+        self.send_command(WindowDrawCommand::DrawLine(line_fragments));
+    }
+
+    fn build_line(&self, row: u64) -> Vec<LineFragment> {
         // until current_start is greater than the grid width and sending the resulting
         // fragments as a batch
         let mut current_start = 0;
@@ -233,7 +265,47 @@ impl Window {
             current_start = next_start;
             line_fragments.push(line_fragment);
         }
-        self.send_command(WindowDrawCommand::DrawLine(line_fragments));
+        line_fragments
+    }
+
+    /// Every row of the grid as it would be drawn, for rendering the window somewhere other
+    /// than the screen.
+    pub fn build_lines(&self) -> Vec<Vec<LineFragment>> {
+        (0..self.grid.height)
+            .map(|row| self.build_line(row))
+            .collect()
+    }
+
+    /// The text of every row without its trailing blanks, which is what screen readers read out.
+    pub fn text_lines(&self) -> Vec<String> {
+        (0..self.grid.height)
+            .map(|row| {
+                let text: String = self
+                    .grid
+                    .row(row)
+                    .unwrap_or_default()
+                    .iter()
+                    .map(|(character, _)| character.as_str())
+                    .collect();
+                text.trim_end().to_owned()
+            })
+            .collect()
+    }
+
+    /// The index in its line of `text_lines` of the first character of a cell. Double width
+    /// characters take up two cells but are only one character.
+    pub fn text_offset(&self, window_left: u64, window_top: u64) -> usize {
+        self.grid
+            .row(window_top)
+            .unwrap_or_default()
+            .iter()
+            .take(window_left as usize)
+            .map(|(character, _)| character.chars().count())
+            .sum()
+    }
+
+    pub fn get_grid_size(&self) -> (u64, u64) {
+        (self.grid.width, self.grid.height)
     }
 
     pub fn draw_grid_line(
@@ -345,10 +417,18 @@ impl Window {
         self.send_command(WindowDrawCommand::Close);
     }
 
-    pub fn update_viewport(&self, top_line: f64, bottom_line: f64) {
+    pub fn update_viewport(
+        &self,
+        window_handle: u64,
+        top_line: f64,
+        bottom_line: f64,
+        line_count: Option<f64>,
+    ) {
         self.send_command(WindowDrawCommand::Viewport {
+            window_handle,
             top_line,
             bottom_line,
+            line_count,
         });
     }
 }
@@ -356,21 +436,14 @@ impl Window {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::channel_utils::*;
+    use crate::editor::FlushedDrawCommands;
     use std::collections::HashMap;
-    use std::sync::mpsc::*;
 
-    fn build_test_channels() -> (Receiver<Vec<DrawCommand>>, Arc<DrawCommandBatcher>) {
-        let (batched_draw_command_sender, batched_draw_command_receiver) = channel();
-        let logging_batched_draw_command_sender = LoggingSender::attach(
-            batched_draw_command_sender,
-            "batched_draw_command".to_owned(),
-        );
+    fn build_test_channels() -> (FlushedDrawCommands, Arc<DrawCommandBatcher>) {
+        let flushed_draw_commands = FlushedDrawCommands::new();
+        let draw_command_batcher = Arc::new(DrawCommandBatcher::new(flushed_draw_commands.clone()));
 
-        let draw_command_batcher =
-            Arc::new(DrawCommandBatcher::new(logging_batched_draw_command_sender));
-
-        (batched_draw_command_receiver, draw_command_batcher)
+        (flushed_draw_commands, draw_command_batcher)
     }
 
     #[test]
@@ -384,29 +457,118 @@ mod tests {
             (114, 64),
             batched_sender.clone(),
         );
-        batched_sender
-            .send_batch()
-            .expect("Could not send batch of commands");
-        batched_receiver.recv().expect("Could not receive commands");
+        batched_sender.send_batch();
+        batched_receiver.take();
 
         window.draw_grid_line(
             1,
             70,
             vec![GridLineCell {
-                text: "|".to_owned(),
+                text: SmolStr::from("|"),
                 highlight_id: None,
                 repeat: None,
             }],
             &HashMap::new(),
         );
 
-        assert_eq!(window.grid.get_cell(70, 1), Some(&("|".to_owned(), None)));
+        assert_eq!(
+            window.grid.get_cell(70, 1),
+            Some(&(SmolStr::from("|"), None))
+        );
 
-        batched_sender
-            .send_batch()
-            .expect("Could not send batch of commands");
+        batched_sender.send_batch();
 
-        let sent_commands = batched_receiver.recv().expect("Could not receive commands");
+        let sent_commands = batched_receiver.take().0;
         assert!(sent_commands.len() != 0);
     }
+
+    #[test]
+    fn repeated_cells_fill_the_grid() {
+        let (_batched_receiver, batched_sender) = build_test_channels();
+        let mut window = Window::new(
+            1,
+            WindowType::Editor,
+            None,
+            (0.0, 0.0),
+            (10, 1),
+            batched_sender,
+        );
+
+        let cell = |text: &str, repeat: Option<u64>| GridLineCell {
+            text: SmolStr::from(text),
+            highlight_id: None,
+            repeat,
+        };
+        window.draw_grid_line(
+            0,
+            0,
+            vec![
+                cell("ab", None),
+                cell("界", None),
+                cell("", None),
+                cell("-", Some(3)),
+            ],
+            &HashMap::new(),
+        );
+        // Drawn over again, with a cell repeated 0 times that still takes up a column
+        window.draw_grid_line(
+            0,
+            5,
+            vec![cell("x", Some(0)), cell("x", Some(2))],
+            &HashMap::new(),
+        );
+
+        assert_eq!(window.text_lines(), vec!["ab界-xx".to_owned()]);
+        assert_eq!(
+            window.grid.get_cell(3, 0),
+            Some(&(SmolStr::default(), None))
+        );
+        assert_eq!(
+            window.grid.get_cell(5, 0),
+            Some(&(SmolStr::default(), None))
+        );
+        assert_eq!(
+            window.grid.get_cell(8, 0),
+            Some(&(SmolStr::from(" "), None))
+        );
+    }
+
+    #[test]
+    fn text_lines_skip_double_width_continuations() {
+        let (_batched_receiver, batched_sender) = build_test_channels();
+        let mut window = Window::new(
+            1,
+            WindowType::Editor,
+            None,
+            (0.0, 0.0),
+            (10, 2),
+            batched_sender,
+        );
+
+        window.draw_grid_line(
+            0,
+            0,
+            vec![
+                GridLineCell {
+                    text: SmolStr::from("界"),
+                    highlight_id: None,
+                    repeat: None,
+                },
+                GridLineCell {
+                    text: SmolStr::default(),
+                    highlight_id: None,
+                    repeat: None,
+                },
+                GridLineCell {
+                    text: SmolStr::from("a"),
+                    highlight_id: None,
+                    repeat: None,
+                },
+            ],
+            &HashMap::new(),
+        );
+
+        assert_eq!(window.text_lines(), vec!["界a".to_owned(), String::new()]);
+        assert_eq!(window.text_offset(2, 0), 1);
+    }
 }
@@ -1,5 +1,130 @@
 use log::error;
 
+/// Shows a blocking error message box. Linux has no common dialog api, so zenity or kdialog are
+/// used when one of them is installed.
+#[cfg(windows)]
+pub fn show_error_dialog(title: &str, message: &str) {
+    use std::{ffi::OsStr, iter::once, os::windows::ffi::OsStrExt, ptr::null_mut};
+    use winapi::um::winuser::{MessageBoxW, MB_ICONERROR, MB_OK};
+
+    let wide = |text: &str| -> Vec<u16> { OsStr::new(text).encode_wide().chain(once(0)).collect() };
+    unsafe {
+        MessageBoxW(
+            null_mut(),
+            wide(message).as_ptr(),
+            wide(title).as_ptr(),
+            MB_OK | MB_ICONERROR,
+        );
+    }
+}
+
+#[cfg(target_os = "macos")]
+pub fn show_error_dialog(title: &str, message: &str) {
+    use cocoa::{
+        base::{id, nil},
+        foundation::NSString,
+    };
+    use objc::{class, msg_send, sel, sel_impl};
+
+    const NS_ALERT_STYLE_CRITICAL: u64 = 2;
+
+    unsafe {
+        let alert: id = msg_send![class!(NSAlert), new];
+        let _: () = msg_send![alert, setMessageText: NSString::alloc(nil).init_str(title)];
+        let _: () = msg_send![alert, setInformativeText: NSString::alloc(nil).init_str(message)];
+        let _: () = msg_send![alert, setAlertStyle: NS_ALERT_STYLE_CRITICAL];
+        let _: i64 = msg_send![alert, runModal];
+        let _: () = msg_send![alert, release];
+    }
+}
+
+#[cfg(not(any(windows, target_os = "macos")))]
+pub fn show_error_dialog(title: &str, message: &str) {
+    use std::process::Command;
+
+    let shown = Command::new("zenity")
+        .args(&["--error", "--title", title, "--text", message])
+        .status()
+        .or_else(|_| {
+            Command::new("kdialog")
+                .args(&["--title", title, "--error", message])
+                .status()
+        })
+        .is_ok();
+    if !shown {
+        eprintln!("{}: {}", title, message);
+    }
+}
+
+/// Asks a yes or no question in a blocking message box, returning whether it was answered with
+/// yes. Without a way to ask there is no answer.
+#[cfg(windows)]
+pub fn show_confirm_dialog(title: &str, message: &str) -> Option<bool> {
+    use std::{ffi::OsStr, iter::once, os::windows::ffi::OsStrExt, ptr::null_mut};
+    use winapi::um::winuser::{MessageBoxW, IDYES, MB_ICONQUESTION, MB_YESNO};
+
+    let wide = |text: &str| -> Vec<u16> { OsStr::new(text).encode_wide().chain(once(0)).collect() };
+    let answer = unsafe {
+        MessageBoxW(
+            null_mut(),
+            wide(message).as_ptr(),
+            wide(title).as_ptr(),
+            MB_YESNO | MB_ICONQUESTION,
+        )
+    };
+    // Zero means the message box couldn't be shown
+    match answer {
+        0 => None,
+        answer => Some(answer == IDYES),
+    }
+}
+
+#[cfg(target_os = "macos")]
+pub fn show_confirm_dialog(title: &str, message: &str) -> Option<bool> {
+    use cocoa::{
+        base::{id, nil},
+        foundation::NSString,
+    };
+    use objc::{class, msg_send, sel, sel_impl};
+
+    const NS_ALERT_FIRST_BUTTON_RETURN: i64 = 1000;
+
+    unsafe {
+        let alert: id = msg_send![class!(NSAlert), new];
+        let _: () = msg_send![alert, setMessageText: NSString::alloc(nil).init_str(title)];
+        let _: () = msg_send![alert, setInformativeText: NSString::alloc(nil).init_str(message)];
+        let _: id = msg_send![alert, addButtonWithTitle: NSString::alloc(nil).init_str("Yes")];
+        let _: id = msg_send![alert, addButtonWithTitle: NSString::alloc(nil).init_str("No")];
+        let response: i64 = msg_send![alert, runModal];
+        let _: () = msg_send![alert, release];
+        Some(response == NS_ALERT_FIRST_BUTTON_RETURN)
+    }
+}
+
+#[cfg(not(any(windows, target_os = "macos")))]
+pub fn show_confirm_dialog(title: &str, message: &str) -> Option<bool> {
+    use std::process::Command;
+
+    if std::env::var_os("DISPLAY").is_none() && std::env::var_os("WAYLAND_DISPLAY").is_none() {
+        return None;
+    }
+    // Both exit with 0 for yes and 1 for no, anything else means the dialog didn't get an answer
+    let status = Command::new("zenity")
+        .args(&["--question", "--title", title, "--text", message])
+        .status()
+        .or_else(|_| {
+            Command::new("kdialog")
+                .args(&["--title", title, "--yesno", message])
+                .status()
+        })
+        .ok()?;
+    match status.code() {
+        Some(0) => Some(true),
+        Some(1) => Some(false),
+        _ => None,
+    }
+}
+
 fn show_error(explanation: &str) -> ! {
     error!("{}", explanation);
     panic!("{}", explanation.to_string());
@@ -0,0 +1,103 @@
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use log::{error, info};
+use skia_safe::{AlphaType, ColorType, ImageInfo, Surface};
+
+use crate::cmd_line::CmdLineSettings;
+use crate::instance::NeovimInstance;
+use crate::redraw_scheduler::REDRAW_SCHEDULER;
+use crate::renderer::Renderer;
+use crate::settings::SETTINGS;
+use crate::window::save_screenshot;
+
+// Every frame advances the animations by the same amount, so the same script renders the same
+// images no matter how long each frame took
+const FRAME_DURATION: Duration = Duration::from_micros(16_667);
+
+fn create_surface(renderer: &Renderer) -> Surface {
+    let geometry = SETTINGS.get::<CmdLineSettings>().geometry;
+    let size = renderer.grid_renderer.convert_grid_to_physical(geometry);
+    let image_info = ImageInfo::new(
+        (size.width.max(1) as i32, size.height.max(1) as i32),
+        ColorType::RGBA8888,
+        AlphaType::Premul,
+        None,
+    );
+    Surface::new_raster(&image_info, None, None).expect("Could not create raster surface")
+}
+
+fn frame_path(directory: &Path, frame: usize) -> PathBuf {
+    directory.join(format!("frame_{:05}.png", frame))
+}
+
+/// Renders the frames neovim produces into pngs in the directory instead of a window, until
+/// neovim quits. Afterwards the time spent per frame is printed, which makes scripted runs usable
+/// as benchmarks.
+pub fn render_headless(instance: NeovimInstance, directory: String) {
+    let directory = PathBuf::from(directory);
+    if let Err(error) = std::fs::create_dir_all(&directory) {
+        error!("Could not create {}: {}", directory.display(), error);
+        return;
+    }
+
+    let mut renderer = Renderer::new(instance.flushed_draw_commands, 1.0);
+    let mut surface = create_surface(&renderer);
+    let mut frame_times = Vec::new();
+
+    while instance.running_tracker.is_running() {
+        // There is no window to act on, but the commands shouldn't pile up either
+        instance.window_command_receiver.try_iter().for_each(drop);
+
+        if !REDRAW_SCHEDULER.should_draw() {
+            std::thread::sleep(FRAME_DURATION);
+            continue;
+        }
+
+        let frame_start = Instant::now();
+        if renderer.draw_frame(surface.canvas(), FRAME_DURATION.as_secs_f32()) {
+            // The grid is drawn at the size from the command line, in the new font
+            surface = create_surface(&renderer);
+            REDRAW_SCHEDULER.queue_next_frame();
+            continue;
+        }
+        frame_times.push(frame_start.elapsed());
+
+        save_screenshot(
+            surface.canvas(),
+            Some(
+                frame_path(&directory, frame_times.len())
+                    .to_string_lossy()
+                    .into_owned(),
+            ),
+        );
+    }
+
+    info!(
+        "Rendered {} frames to {}",
+        frame_times.len(),
+        directory.display()
+    );
+    if let Some(slowest) = frame_times.iter().max() {
+        let total: Duration = frame_times.iter().sum();
+        println!(
+            "{} frames, {:.2}ms average, {:.2}ms slowest",
+            frame_times.len(),
+            total.as_secs_f64() * 1000.0 / frame_times.len() as f64,
+            slowest.as_secs_f64() * 1000.0
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_path() {
+        assert_eq!(
+            frame_path(Path::new("out"), 12),
+            Path::new("out").join("frame_00012.png")
+        );
+    }
+}
@@ -0,0 +1,73 @@
+use std::sync::mpsc::{channel, Receiver};
+use std::sync::Arc;
+
+use tokio::sync::mpsc::{self, unbounded_channel};
+
+use crate::bridge::{start_bridge, Bridge, UiCommand};
+use crate::channel_utils::*;
+use crate::editor::{start_editor, FlushedDrawCommands, WindowCommand};
+use crate::running_tracker::RunningTracker;
+use crate::settings::{new_window_settings, Settings};
+
+// Redraw events wait here while the editor is busy. Once it falls this far behind, the bridge
+// stops parsing neovim's notifications until it caught up, and they wait unparsed instead.
+const EDITOR_COMMAND_CAPACITY: usize = 1024;
+
+/// A neovim process together with the bridge and editor feeding its window. Every window owns one
+/// of these, and the bridge runtime shuts down when the window drops it.
+pub struct NeovimInstance {
+    pub flushed_draw_commands: FlushedDrawCommands,
+    pub window_command_receiver: Receiver<WindowCommand>,
+    pub ui_command_sender: LoggingTx<UiCommand>,
+    pub running_tracker: RunningTracker,
+    pub bridge: Bridge,
+    // What the window's neovim set its `g:neovide_*` variables to, apart from the other windows
+    pub settings: Arc<Settings>,
+    // The arguments neovim was started with, which a restart without any reuses
+    pub neovim_args: Vec<String>,
+}
+
+pub fn start_neovim_instance(neovim_args: Vec<String>) -> NeovimInstance {
+    let running_tracker = RunningTracker::new();
+    let settings = new_window_settings();
+
+    let (editor_command_sender, editor_command_receiver) = mpsc::channel(EDITOR_COMMAND_CAPACITY);
+    let logging_editor_command_sender =
+        LoggingBoundedTx::attach(editor_command_sender, "editor_command".to_owned());
+
+    let flushed_draw_commands = FlushedDrawCommands::new();
+
+    let (ui_command_sender, ui_command_receiver) = unbounded_channel();
+    let logging_ui_command_sender = LoggingTx::attach(ui_command_sender, "ui_command".to_owned());
+
+    let (window_command_sender, window_command_receiver) = channel();
+    let logging_window_command_sender =
+        LoggingSender::attach(window_command_sender, "window_command".to_owned());
+
+    let bridge = start_bridge(
+        neovim_args.clone(),
+        settings.clone(),
+        running_tracker.clone(),
+        #[cfg(windows)]
+        logging_ui_command_sender.clone(),
+        ui_command_receiver,
+        logging_editor_command_sender,
+        logging_window_command_sender.clone(),
+    );
+    start_editor(
+        editor_command_receiver,
+        flushed_draw_commands.clone(),
+        logging_window_command_sender,
+        settings.clone(),
+    );
+
+    NeovimInstance {
+        flushed_draw_commands,
+        window_command_receiver,
+        ui_command_sender: logging_ui_command_sender,
+        running_tracker,
+        bridge,
+        settings,
+        neovim_args,
+    }
+}
@@ -0,0 +1,87 @@
+use std::env;
+use std::process::Command;
+
+use log::{trace, warn};
+
+// Printed ahead of the environment, so whatever the shell's startup files print is skipped
+const ENVIRONMENT_MARKER: &str = "__NEOVIDE_LOGIN_ENVIRONMENT__";
+
+// Those describe the shell that printed the environment rather than the user's setup, and TERM
+// would make neovide think it was started from a terminal
+const SKIPPED_VARIABLES: &[&str] = &["_", "PWD", "OLDPWD", "SHLVL", "TERM"];
+
+// The output of env -0 after the marker, with one variable per nul separated entry
+fn parse_environment(output: &[u8]) -> Vec<(String, String)> {
+    let output = String::from_utf8_lossy(output);
+    let environment = match output.rsplit_once(ENVIRONMENT_MARKER) {
+        Some((_, environment)) => environment,
+        None => return Vec::new(),
+    };
+    environment
+        .trim_start_matches('\n')
+        .split('\0')
+        .filter_map(|entry| entry.split_once('='))
+        .filter(|(name, _)| !name.is_empty() && !SKIPPED_VARIABLES.contains(name))
+        .map(|(name, value)| (name.to_owned(), value.to_owned()))
+        .collect()
+}
+
+/// Apps started from the Finder or the Dock only get the bare environment of launchd, without
+/// what the shell's startup files add, like the PATH to language servers or the nvm and pyenv
+/// shims. So the environment of an interactive login shell is taken over before neovim is
+/// spawned, which gives plugins the same setup as in a terminal.
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+pub fn import_login_environment() {
+    let shell = env::var("SHELL").unwrap_or_else(|_| "/bin/zsh".to_owned());
+    // The marker is printed by the shell itself and env -0 is a separate program, so this works
+    // the same in fish as in posix shells
+    let command = format!("echo {}; env -0", ENVIRONMENT_MARKER);
+    let output = match Command::new(&shell)
+        // An interactive login shell, like a terminal emulator opens
+        .arg("-lic")
+        .arg(command)
+        .output()
+    {
+        Ok(output) => output,
+        Err(error) => {
+            warn!("Could not read the environment of {}: {}", shell, error);
+            return;
+        }
+    };
+
+    let environment = parse_environment(&output.stdout);
+    if environment.is_empty() {
+        warn!(
+            "{} printed no environment, so the current one is kept",
+            shell
+        );
+        return;
+    }
+    trace!("Taking over {} variables from {}", environment.len(), shell);
+    for (name, value) in environment {
+        env::set_var(name, value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_environment() {
+        let output = format!(
+            "Welcome!\n{}\nPATH=/opt/homebrew/bin:/usr/bin\0LANG=en_US.UTF-8\0SHLVL=2\0\
+             MULTILINE=first\nsecond\0",
+            ENVIRONMENT_MARKER
+        );
+        assert_eq!(
+            parse_environment(output.as_bytes()),
+            vec![
+                ("PATH".to_owned(), "/opt/homebrew/bin:/usr/bin".to_owned()),
+                ("LANG".to_owned(), "en_US.UTF-8".to_owned()),
+                ("MULTILINE".to_owned(), "first\nsecond".to_owned()),
+            ]
+        );
+        assert!(parse_environment(b"PATH=/usr/bin\0").is_empty());
+    }
+}
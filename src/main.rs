@@ -12,15 +12,29 @@ extern crate neovide_derive;
 #[macro_use]
 extern crate clap;
 
+// Ahead of the other modules, so they can use its macros
+#[macro_use]
+mod profiling;
+
 mod bridge;
 mod channel_utils;
+mod clipboard;
 mod cmd_line;
+mod crash_report;
+mod diagnostics;
 mod editor;
 mod error_handling;
+mod headless;
+mod instance;
+#[cfg(any(target_os = "macos", test))]
+mod login_environment;
+mod notification;
 mod redraw_scheduler;
 mod renderer;
 mod running_tracker;
 mod settings;
+mod single_instance;
+mod startup_profile;
 mod utils;
 mod window;
 mod windows_utils;
@@ -31,16 +45,14 @@ extern crate derive_new;
 extern crate lazy_static;
 
 use std::env::args;
-use std::sync::mpsc::channel;
 
 use log::trace;
-use tokio::sync::mpsc::unbounded_channel;
 
-use bridge::start_bridge;
 use cmd_line::CmdLineSettings;
-use editor::start_editor;
+use instance::start_neovim_instance;
 use renderer::{cursor_renderer::CursorSettings, RendererSettings};
-use settings::SETTINGS;
+use settings::{restore_session_args, restore_ui_state, SETTINGS};
+use startup_profile::{mark_startup, StartupPhase};
 use window::{create_window, KeyboardSettings, WindowSettings};
 
 pub use channel_utils::*;
@@ -99,6 +111,9 @@ fn main() {
     //   The ui commands are things like text input/key bindings, outer window resizes, and mouse
     //   inputs.
     //
+    // Each os window runs its own copy of this loop with a separate neovim process. The windows
+    // share the event loop, the render backend and the settings below.
+    //
     //  ------------------
     // | Other Components |
     //  ------------------
@@ -117,9 +132,14 @@ fn main() {
     //   Multiple other parts of the app "queue_next_frame" function to ensure animations continue
     //   properly or updates to the graphics are pushed to the screen.
 
+    mark_startup(StartupPhase::Started);
+
     #[cfg(target_os = "windows")]
     windows_attach_to_console();
 
+    // The command line takes its defaults from the config
+    settings::config::load_config(settings::config::user_config);
+
     //Will exit if -h or -v
     if let Err(err) = cmd_line::handle_command_line_arguments(args().collect()) {
         eprintln!("{}", err);
@@ -128,11 +148,29 @@ fn main() {
 
     #[cfg(not(test))]
     init_logger();
+    crash_report::install_panic_hook();
 
     trace!("Neovide version: {}", crate_version!());
 
+    let settings = SETTINGS.get::<CmdLineSettings>();
+    if settings.single_instance {
+        let files = single_instance::files_to_forward(&settings.neovim_args);
+        if !files.is_empty() && single_instance::forward_to_running_instance(&files) {
+            return;
+        }
+    }
+
     maybe_disown();
 
+    // Asking whether to use the project config waits for the user, which the terminal shouldn't,
+    // so it only happens in the copy that disowned it. The project can pick the window size,
+    // which the command line defaults to.
+    settings::config::load_project_config();
+    if let Err(err) = cmd_line::handle_command_line_arguments(args().collect()) {
+        eprintln!("{}", err);
+        return;
+    }
+
     #[cfg(target_os = "windows")]
     windows_fix_dpi();
 
@@ -143,41 +181,27 @@ fn main() {
     RendererSettings::register();
     CursorSettings::register();
     KeyboardSettings::register();
+    // Defaults, then the config file, the environment and the command line, then neovim's
+    // g:neovide_* variables once it attached
+    SETTINGS.apply_config();
+    renderer::detect_reduced_motion(renderer::system_reduce_motion);
+    // The zoom, fullscreen and transparency the last session ended with
+    restore_ui_state();
+    mark_startup(StartupPhase::SettingsLoaded);
+
+    if settings.single_instance {
+        single_instance::start_listener();
+    }
 
-    let (redraw_event_sender, redraw_event_receiver) = unbounded_channel();
-    let logging_redraw_event_sender =
-        LoggingTx::attach(redraw_event_sender, "redraw_event".to_owned());
-
-    let (batched_draw_command_sender, batched_draw_command_receiver) = channel();
-    let logging_batched_draw_command_sender = LoggingSender::attach(
-        batched_draw_command_sender,
-        "batched_draw_command".to_owned(),
-    );
-
-    let (ui_command_sender, ui_command_receiver) = unbounded_channel();
-    let logging_ui_command_sender = LoggingTx::attach(ui_command_sender, "ui_command".to_owned());
-
-    let (window_command_sender, window_command_receiver) = channel();
-    let logging_window_command_sender =
-        LoggingSender::attach(window_command_sender, "window_command".to_owned());
-
-    // We need to keep the bridge reference around to prevent the tokio runtime from getting freed
-    let _bridge = start_bridge(
-        #[cfg(windows)]
-        logging_ui_command_sender.clone(),
-        ui_command_receiver,
-        logging_redraw_event_sender,
-    );
-    start_editor(
-        redraw_event_receiver,
-        logging_batched_draw_command_sender,
-        logging_window_command_sender,
-    );
-    create_window(
-        batched_draw_command_receiver,
-        window_command_receiver,
-        logging_ui_command_sender,
-    );
+    let mut neovim_args = SETTINGS.get::<CmdLineSettings>().neovim_args;
+    if settings.restores_session() {
+        neovim_args = restore_session_args(neovim_args);
+    }
+    let instance = start_neovim_instance(neovim_args);
+    match settings.render_to {
+        Some(directory) => headless::render_headless(instance, directory),
+        None => create_window(instance),
+    }
 }
 
 #[cfg(not(test))]
@@ -187,6 +211,7 @@ pub fn init_logger() {
     let logger = if settings.log_to_file {
         Logger::with_env_or_str("neovide")
             .log_to_file()
+            .directory(crash_report::log_directory())
             .rotate(
                 Criterion::Size(10_000_000),
                 Naming::Timestamps,
@@ -200,21 +225,45 @@ pub fn init_logger() {
     logger.start().expect("Could not start logger");
 }
 
+// Set for the detached copy, so it knows the console it was started from isn't its own
+#[cfg(target_os = "windows")]
+const FORKED_VARIABLE: &str = "NEOVIDE_FORKED";
+
 fn maybe_disown() {
     use std::{env, process};
 
     let settings = SETTINGS.get::<CmdLineSettings>();
 
-    if cfg!(debug_assertions) || settings.no_fork {
+    // Headless runs are waited on by the scripts that start them
+    if cfg!(debug_assertions) || settings.no_fork || settings.render_to.is_some() {
+        return;
+    }
+
+    // Text piped in with `neovide -` can only be read by this process
+    if cfg!(target_os = "windows") && settings.neovim_args.iter().any(|arg| arg == "-") {
         return;
     }
 
     if let Ok(current_exe) = env::current_exe() {
-        assert!(process::Command::new(current_exe)
-            .arg("--nofork")
-            .args(env::args().skip(1))
-            .spawn()
-            .is_ok());
+        let mut command = process::Command::new(current_exe);
+        command.arg("--nofork").args(env::args().skip(1));
+
+        // Without its own console the terminal gets its prompt back right away, and the copy
+        // doesn't keep writing into it
+        #[cfg(target_os = "windows")]
+        {
+            use std::os::windows::process::CommandExt;
+            use winapi::um::winbase::DETACHED_PROCESS;
+
+            command
+                .env(FORKED_VARIABLE, "1")
+                .creation_flags(DETACHED_PROCESS)
+                .stdin(process::Stdio::null())
+                .stdout(process::Stdio::null())
+                .stderr(process::Stdio::null());
+        }
+
+        assert!(command.spawn().is_ok());
         process::exit(0);
     } else {
         eprintln!("error in disowning process, cannot obtain the path for the current executable, continuing without disowning...");
@@ -235,21 +284,27 @@ fn handle_macos() {
     use std::env;
 
     if env::var_os("TERM").is_none() {
-        let shell = env::var("SHELL").unwrap();
-        // printenv is the proper way to print env variables. using echo $PATH would break Fish.
-        let cmd = "printenv PATH";
-        if let Ok(path) = std::process::Command::new(shell)
-            .arg("-lic") // interactive login shell, this simulates opening a real terminal emulator
-            .arg(cmd)
-            .output()
-        {
-            env::set_var("PATH", std::str::from_utf8(&path.stdout).unwrap());
+        login_environment::import_login_environment();
+
+        // Finder and the Dock start apps in the root folder, home is a more useful place for
+        // neovim to start in until a document is opened
+        if env::current_dir().map_or(false, |dir| dir == std::path::Path::new("/")) {
+            if let Some(home) = dirs::home_dir() {
+                env::set_current_dir(home).ok();
+            }
         }
     }
 }
 
 #[cfg(target_os = "windows")]
 fn windows_attach_to_console() {
+    // The detached copy would otherwise attach right back to the console it was forked from.
+    // The variable is removed again so neovim doesn't inherit it.
+    if std::env::var_os(FORKED_VARIABLE).is_some() {
+        std::env::remove_var(FORKED_VARIABLE);
+        return;
+    }
+
     // Attach to parent console tip found here: https://github.com/rust-lang/rust/issues/67159#issuecomment-987882771
     use winapi::um::wincon::{AttachConsole, ATTACH_PARENT_PROCESS};
     unsafe {
@@ -0,0 +1,81 @@
+use std::thread;
+
+use log::{error, info, warn};
+use notify_rust::Notification;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NotificationLevel {
+    Info,
+    Warning,
+    Error,
+}
+
+impl NotificationLevel {
+    /// Takes either the name of the level or one of the numbers in `vim.log.levels`, like what
+    /// `vim.notify` gets.
+    pub fn parse(level: &rmpv::Value) -> NotificationLevel {
+        if let Some(number) = level.as_i64() {
+            return match number {
+                3 => NotificationLevel::Warning,
+                4 => NotificationLevel::Error,
+                _ => NotificationLevel::Info,
+            };
+        }
+        match level.as_str().map(|level| level.to_lowercase()).as_deref() {
+            Some("warn") | Some("warning") => NotificationLevel::Warning,
+            Some("error") => NotificationLevel::Error,
+            _ => NotificationLevel::Info,
+        }
+    }
+}
+
+fn show_notification(title: &str, body: &str, level: NotificationLevel) {
+    let mut notification = Notification::new();
+    notification.summary(title).body(body).appname("Neovide");
+    // Only the linux notification servers know about urgency, so errors stay until dismissed
+    #[cfg(target_os = "linux")]
+    if level == NotificationLevel::Error {
+        notification.urgency(notify_rust::Urgency::Critical);
+    }
+
+    if let Err(error) = notification.show() {
+        error!("Could not show notification {}: {}", title, error);
+    }
+}
+
+/// Shows a notification on the desktop while the window is in the background, like once a build
+/// finished, and only logs it otherwise. Showing it can wait on the notification server, so that
+/// happens on a thread of its own.
+pub fn notify(title: String, body: String, level: NotificationLevel, window_focused: bool) {
+    match level {
+        NotificationLevel::Info => info!("Notification {}: {}", title, body),
+        NotificationLevel::Warning => warn!("Notification {}: {}", title, body),
+        NotificationLevel::Error => error!("Notification {}: {}", title, body),
+    }
+
+    if !window_focused {
+        thread::spawn(move || show_notification(&title, &body, level));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rmpv::Value;
+
+    #[test]
+    fn test_parse_level() {
+        assert_eq!(
+            NotificationLevel::parse(&Value::from("WARN")),
+            NotificationLevel::Warning
+        );
+        assert_eq!(
+            NotificationLevel::parse(&Value::from(4)),
+            NotificationLevel::Error
+        );
+        assert_eq!(
+            NotificationLevel::parse(&Value::Nil),
+            NotificationLevel::Info
+        );
+    }
+}
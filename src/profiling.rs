@@ -0,0 +1,20 @@
+// Scopes for the Tracy profiler, compiled in with the profiling feature and to nothing without
+// it. Each scope lasts until the end of the block it is in.
+
+#[cfg(feature = "profiling")]
+macro_rules! profile_scope {
+    ($name:expr) => {
+        let _profile_scope = tracy_client::span!($name, 0);
+    };
+}
+
+#[cfg(not(feature = "profiling"))]
+macro_rules! profile_scope {
+    ($name:expr) => {};
+}
+
+/// Ends the frame in the trace, once it was presented.
+pub fn finish_frame() {
+    #[cfg(feature = "profiling")]
+    tracy_client::finish_continuous_frame!();
+}
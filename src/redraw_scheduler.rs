@@ -2,6 +2,7 @@ use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Mutex;
 use std::time::Instant;
 
+use glutin::event_loop::EventLoopProxy;
 use log::trace;
 
 lazy_static! {
@@ -11,6 +12,7 @@ lazy_static! {
 pub struct RedrawScheduler {
     scheduled_frame: Mutex<Option<Instant>>,
     frame_queued: AtomicBool,
+    event_loop_proxy: Mutex<Option<EventLoopProxy<()>>>,
 }
 
 impl RedrawScheduler {
@@ -18,6 +20,7 @@ impl RedrawScheduler {
         RedrawScheduler {
             scheduled_frame: Mutex::new(None),
             frame_queued: AtomicBool::new(true),
+            event_loop_proxy: Mutex::new(None),
         }
     }
 
@@ -39,6 +42,21 @@ impl RedrawScheduler {
         self.frame_queued.store(true, Ordering::Relaxed);
     }
 
+    pub fn set_event_loop_proxy(&self, proxy: EventLoopProxy<()>) {
+        *self.event_loop_proxy.lock().unwrap() = Some(proxy);
+    }
+
+    /// Queues a frame for a finished neovim flush and wakes the event loop so it is drawn right
+    /// away instead of on the next frame timer tick. Frames are still limited to the refresh
+    /// rate, so several flushes within one frame get coalesced.
+    pub fn queue_flushed_frame(&self) {
+        self.queue_next_frame();
+
+        if let Some(proxy) = &*self.event_loop_proxy.lock().unwrap() {
+            proxy.send_event(()).ok();
+        }
+    }
+
     pub fn should_draw(&self) -> bool {
         if self.frame_queued.load(Ordering::Relaxed) {
             self.frame_queued.store(false, Ordering::Relaxed);
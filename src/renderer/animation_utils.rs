@@ -65,6 +65,22 @@ pub fn ease_out_expo(t: f32) -> f32 {
     }
 }
 
+/// Looks up an easing function by the name used in settings, falling back to ease_out_expo for
+/// unknown names.
+pub fn easing_function_from_name(name: &str) -> fn(f32) -> f32 {
+    match name {
+        "linear" => ease_linear,
+        "in_quad" => ease_in_quad,
+        "out_quad" => ease_out_quad,
+        "in_out_quad" => ease_in_out_quad,
+        "in_cubic" => ease_in_cubic,
+        "out_cubic" => ease_out_cubic,
+        "in_out_cubic" => ease_in_out_cubic,
+        "in_expo" => ease_in_expo,
+        _ => ease_out_expo,
+    }
+}
+
 pub fn lerp(start: f32, end: f32, t: f32) -> f32 {
     start + (end - start) * t
 }
@@ -73,6 +89,13 @@ pub fn ease(ease_func: fn(f32) -> f32, start: f32, end: f32, t: f32) -> f32 {
     lerp(start, end, ease_func(t))
 }
 
+/// Splits the time since the last frame into equal steps no longer than `max_step`, for
+/// simulations that drift when stepped by a whole slow frame at once. The steps add up to `dt`.
+pub fn time_steps(dt: f32, max_step: f32) -> impl Iterator<Item = f32> {
+    let count = (dt / max_step).ceil().max(1.0) as usize;
+    std::iter::repeat(dt / count as f32).take(count)
+}
+
 pub fn ease_point(ease_func: fn(f32) -> f32, start: Point, end: Point, t: f32) -> Point {
     Point {
         x: ease(ease_func, start.x, end.x, t),
@@ -84,6 +107,24 @@ pub fn ease_point(ease_func: fn(f32) -> f32, start: Point, end: Point, t: f32) -
 mod test {
     use super::*;
 
+    #[test]
+    fn test_time_steps() {
+        let steps: Vec<f32> = time_steps(0.05, 0.01).collect();
+        assert_eq!(steps.len(), 5);
+        assert!((steps.iter().sum::<f32>() - 0.05).abs() < 1e-6);
+        assert_eq!(time_steps(0.004, 0.01).count(), 1);
+    }
+
+    #[test]
+    fn test_easing_function_from_name() {
+        assert_eq!(easing_function_from_name("linear")(0.25), 0.25);
+        assert_eq!(easing_function_from_name("in_quad")(0.5), 0.25);
+        assert_eq!(
+            easing_function_from_name("unknown")(0.5),
+            ease_out_expo(0.5)
+        );
+    }
+
     #[test]
     fn test_lerp() {
         assert_eq!(lerp(1.0, 0.0, 1.0), 0.0);
@@ -0,0 +1,151 @@
+use log::error;
+use skia_safe::{image_filters::blur, Canvas, Data, Image, Paint, Rect, SamplingOptions, TileMode};
+
+use super::RendererSettings;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BackgroundImageFit {
+    Fill,
+    Fit,
+    Stretch,
+    Center,
+    Tile,
+}
+
+impl BackgroundImageFit {
+    pub fn from_name(name: &str) -> BackgroundImageFit {
+        match name {
+            "fit" => BackgroundImageFit::Fit,
+            "stretch" => BackgroundImageFit::Stretch,
+            "center" => BackgroundImageFit::Center,
+            "tile" => BackgroundImageFit::Tile,
+            _ => BackgroundImageFit::Fill,
+        }
+    }
+
+    /// Where an image of the given size lands inside the bounds. Fill covers the bounds and crops
+    /// whatever overflows while fit keeps the whole image visible.
+    fn destination(&self, image_size: (f32, f32), bounds: Rect) -> Rect {
+        let (image_width, image_height) = image_size;
+        let width_scale = bounds.width() / image_width;
+        let height_scale = bounds.height() / image_height;
+        let scale = match self {
+            BackgroundImageFit::Fill => width_scale.max(height_scale),
+            BackgroundImageFit::Fit => width_scale.min(height_scale),
+            BackgroundImageFit::Stretch | BackgroundImageFit::Tile => return bounds,
+            BackgroundImageFit::Center => 1.0,
+        };
+
+        let size = (image_width * scale, image_height * scale);
+        let center = bounds.center();
+        Rect::from_xywh(
+            center.x - size.0 / 2.0,
+            center.y - size.1 / 2.0,
+            size.0,
+            size.1,
+        )
+    }
+}
+
+fn load_image(path: &str) -> Option<Image> {
+    let bytes = std::fs::read(path)
+        .map_err(|error| error!("Could not read background image {}: {}", path, error))
+        .ok()?;
+    let image = Image::from_encoded(Data::new_copy(&bytes));
+    if image.is_none() {
+        error!("Could not decode background image {}", path);
+    }
+    image
+}
+
+/// An image drawn beneath the grid. It is painted over the default background color with the
+/// configured opacity so text stays readable on busy images.
+pub struct BackgroundImage {
+    path: String,
+    image: Option<Image>,
+}
+
+impl BackgroundImage {
+    pub fn new() -> BackgroundImage {
+        BackgroundImage {
+            path: String::new(),
+            image: None,
+        }
+    }
+
+    // Reloads the image whenever the setting points somewhere new. Failed loads are remembered
+    // so a bad path is only reported once
+    fn update(&mut self, path: &str) {
+        if self.path != path {
+            self.path = path.to_owned();
+            self.image = if path.is_empty() {
+                None
+            } else {
+                load_image(path)
+            };
+        }
+    }
+
+    pub fn draw(&mut self, root_canvas: &mut Canvas, settings: &RendererSettings, bounds: Rect) {
+        self.update(&settings.background_image);
+
+        let image = match &self.image {
+            Some(image) => image,
+            None => return,
+        };
+
+        let mut paint = Paint::default();
+        paint.set_anti_alias(true);
+        paint.set_alpha_f(settings.background_image_opacity.min(1.0).max(0.0));
+        if settings.background_image_blur > 0.0 {
+            let sigma = settings.background_image_blur;
+            paint.set_image_filter(blur((sigma, sigma), None, None, None));
+        }
+
+        root_canvas.save();
+        root_canvas.clip_rect(&bounds, None, Some(false));
+
+        let fit = BackgroundImageFit::from_name(&settings.background_image_fit);
+        if fit == BackgroundImageFit::Tile {
+            paint.set_shader(image.to_shader(
+                (TileMode::Repeat, TileMode::Repeat),
+                SamplingOptions::default(),
+                None,
+            ));
+            root_canvas.draw_rect(&bounds, &paint);
+        } else {
+            let image_size = (image.width() as f32, image.height() as f32);
+            let destination = fit.destination(image_size, bounds);
+            root_canvas.draw_image_rect(image, None, destination, &paint);
+        }
+
+        root_canvas.restore();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_destination() {
+        let bounds = Rect::from_wh(200.0, 100.0);
+
+        assert_eq!(
+            BackgroundImageFit::Fill.destination((100.0, 100.0), bounds),
+            Rect::from_xywh(0.0, -50.0, 200.0, 200.0)
+        );
+        assert_eq!(
+            BackgroundImageFit::Fit.destination((100.0, 100.0), bounds),
+            Rect::from_xywh(50.0, 0.0, 100.0, 100.0)
+        );
+        assert_eq!(
+            BackgroundImageFit::Center.destination((20.0, 20.0), bounds),
+            Rect::from_xywh(90.0, 40.0, 20.0, 20.0)
+        );
+        assert_eq!(
+            BackgroundImageFit::Stretch.destination((20.0, 20.0), bounds),
+            bounds
+        );
+    }
+}
@@ -0,0 +1,69 @@
+use skia_safe::{Canvas, Color, Font, Paint, Rect, Typeface};
+
+const FONT_SIZE: f32 = 14.0;
+const PADDING: f32 = 8.0;
+const MARGIN: f32 = 12.0;
+const CORNER_RADIUS: f32 = 6.0;
+
+// Where the banner goes: centered at the top of the window, just large enough for the message
+fn banner_rect(bounds: Rect, text_width: f32) -> Rect {
+    let width = text_width + 2.0 * PADDING;
+    let height = FONT_SIZE + 2.0 * PADDING;
+    Rect::from_xywh(
+        bounds.center_x() - width / 2.0,
+        bounds.top + MARGIN,
+        width,
+        height,
+    )
+}
+
+/// A short message drawn over everything else at the top of the window, like while the
+/// connection to a remote neovim is being retried.
+pub struct Banner {
+    font: Font,
+    pub message: Option<String>,
+}
+
+impl Banner {
+    pub fn new() -> Banner {
+        Banner {
+            font: Font::new(Typeface::default(), FONT_SIZE),
+            message: None,
+        }
+    }
+
+    pub fn draw(&self, canvas: &mut Canvas, bounds: Rect) {
+        let message = match &self.message {
+            Some(message) => message,
+            None => return,
+        };
+
+        let mut paint = Paint::default();
+        paint.set_anti_alias(true);
+        let (text_width, _) = self.font.measure_str(message, Some(&paint));
+        let rect = banner_rect(bounds, text_width);
+
+        paint.set_color(Color::from_argb(220, 40, 40, 40));
+        canvas.draw_round_rect(rect, CORNER_RADIUS, CORNER_RADIUS, &paint);
+        paint.set_color(Color::from_rgb(230, 230, 230));
+        canvas.draw_str(
+            message,
+            (rect.left + PADDING, rect.bottom - PADDING - FONT_SIZE * 0.2),
+            &self.font,
+            &paint,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_banner_is_centered_at_the_top() {
+        let rect = banner_rect(Rect::from_wh(800.0, 600.0), 200.0);
+        assert_eq!(rect.center_x(), 400.0);
+        assert_eq!(rect.top, MARGIN);
+        assert_eq!(rect.width(), 200.0 + 2.0 * PADDING);
+    }
+}
@@ -0,0 +1,98 @@
+use skia_safe::Color;
+
+// How finely the mix towards black or white is searched for
+const SEARCH_STEPS: usize = 12;
+
+fn linear_channel(channel: u8) -> f32 {
+    let channel = channel as f32 / 255.0;
+    if channel <= 0.03928 {
+        channel / 12.92
+    } else {
+        ((channel + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+// As defined for WCAG
+fn relative_luminance(color: Color) -> f32 {
+    0.2126 * linear_channel(color.r())
+        + 0.7152 * linear_channel(color.g())
+        + 0.0722 * linear_channel(color.b())
+}
+
+/// The WCAG contrast ratio of two colors, from 1 for the same color up to 21 for black on white.
+pub fn contrast_ratio(first: Color, second: Color) -> f32 {
+    let (first, second) = (relative_luminance(first), relative_luminance(second));
+    (first.max(second) + 0.05) / (first.min(second) + 0.05)
+}
+
+fn mix(from: Color, to: Color, amount: f32) -> Color {
+    let channel =
+        |from: u8, to: u8| (from as f32 + (to as f32 - from as f32) * amount).round() as u8;
+    Color::from_argb(
+        from.a(),
+        channel(from.r(), to.r()),
+        channel(from.g(), to.g()),
+        channel(from.b(), to.b()),
+    )
+}
+
+/// Moves the foreground towards black or white, whichever is further from the background, just
+/// far enough to reach the minimum contrast ratio. Colors that already stand out are kept, so
+/// the colorscheme only changes where it is hard to read. The hue is kept as long as possible.
+pub fn ensure_contrast(foreground: Color, background: Color, minimum_ratio: f32) -> Color {
+    if minimum_ratio <= 1.0 || contrast_ratio(foreground, background) >= minimum_ratio {
+        return foreground;
+    }
+
+    // Middle grays are further from black than the luminance halfway to white suggests
+    let target =
+        if contrast_ratio(Color::BLACK, background) > contrast_ratio(Color::WHITE, background) {
+            Color::BLACK
+        } else {
+            Color::WHITE
+        };
+    let (mut low, mut high) = (0.0, 1.0);
+    for _ in 0..SEARCH_STEPS {
+        let amount = (low + high) / 2.0;
+        if contrast_ratio(mix(foreground, target, amount), background) >= minimum_ratio {
+            high = amount;
+        } else {
+            low = amount;
+        }
+    }
+    mix(foreground, target, high)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_contrast_ratio() {
+        assert!((contrast_ratio(Color::BLACK, Color::WHITE) - 21.0).abs() < 0.01);
+        assert!((contrast_ratio(Color::RED, Color::RED) - 1.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_ensure_contrast() {
+        let background = Color::from_rgb(30, 30, 30);
+        let dim = Color::from_rgb(60, 60, 70);
+        let adjusted = ensure_contrast(dim, background, 4.5);
+        assert!(contrast_ratio(adjusted, background) >= 4.5);
+        assert!(adjusted.r() > dim.r());
+
+        // Readable colors and a minimum of 1 leave the foreground alone
+        assert_eq!(ensure_contrast(Color::WHITE, background, 4.5), Color::WHITE);
+        assert_eq!(ensure_contrast(dim, background, 1.0), dim);
+    }
+
+    #[test]
+    fn test_middle_gray_darkens() {
+        // Only black gets to 4.6 on this gray, even though its luminance is below a half
+        let background = Color::from_rgb(119, 119, 119);
+        let foreground = Color::from_rgb(110, 110, 110);
+        let adjusted = ensure_contrast(foreground, background, 4.6);
+        assert!(contrast_ratio(adjusted, background) >= 4.6);
+        assert!(adjusted.r() < foreground.r());
+    }
+}
@@ -6,6 +6,8 @@ use crate::editor::Cursor;
 use crate::renderer::{animation_utils::*, grid_renderer::GridRenderer};
 use crate::settings::*;
 
+const PARTICLE_TIME_STEP: f32 = 1.0 / 120.0;
+
 pub trait CursorVfx {
     fn update(
         &mut self,
@@ -57,7 +59,10 @@ impl FromValue for VfxMode {
                 "pixiedust" => VfxMode::Trail(TrailMode::PixieDust),
                 "" => VfxMode::Disabled,
                 value => {
-                    error!("Expected a VfxMode name, but received {:?}", value);
+                    report_invalid_setting(format!(
+                        "Ignored neovide_cursor_vfx_mode = {:?}: it has to be railgun, torpedo, pixiedust, sonicboom, ripple, wireframe or empty",
+                        value
+                    ));
                     return;
                 }
             };
@@ -233,11 +238,13 @@ impl CursorVfx for ParticleTrail {
             }
         }
 
-        // Update particle positions
-        for i in 0..self.particles.len() {
-            let particle = &mut self.particles[i];
-            particle.pos += particle.speed * dt;
-            particle.speed = rotate_vec(particle.speed, dt * particle.rotation_speed);
+        // Update particle positions. Curling particles are stepped at least as often as at
+        // 120hz, so their paths have the same shape at any frame rate.
+        for step in time_steps(dt, PARTICLE_TIME_STEP) {
+            for particle in self.particles.iter_mut() {
+                particle.pos += particle.speed * step;
+                particle.speed = rotate_vec(particle.speed, step * particle.rotation_speed);
+            }
         }
 
         // Spawn new particles
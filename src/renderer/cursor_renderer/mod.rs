@@ -6,7 +6,7 @@ use std::collections::HashMap;
 // use neovide_derive::SettingGroup;
 use skia_safe::{Canvas, Paint, Path, Point};
 
-use super::{GridRenderer, RenderedWindow};
+use super::{GridRenderer, RenderedWindow, RendererSettings};
 use crate::bridge::EditorMode;
 use crate::editor::{Cursor, CursorShape};
 use crate::redraw_scheduler::REDRAW_SCHEDULER;
@@ -19,6 +19,28 @@ const DEFAULT_CELL_PERCENTAGE: f32 = 1.0 / 8.0;
 
 const STANDARD_CORNERS: &[(f32, f32); 4] = &[(-0.5, -0.5), (0.5, -0.5), (0.5, 0.5), (-0.5, 0.5)];
 
+// How much of the cell a bar or underline cursor covers. The thickness settings win over the
+// percentage from guicursor, in pixels from 1 up or as a part of the cell below that.
+fn cell_percentage(
+    cursor_shape: &CursorShape,
+    guicursor_percentage: Option<f32>,
+    settings: &CursorSettings,
+    cell_dimensions: Point,
+) -> f32 {
+    let (thickness, cell_size) = match cursor_shape {
+        CursorShape::Block => return 1.0,
+        CursorShape::Vertical => (settings.bar_thickness, cell_dimensions.x),
+        CursorShape::Horizontal => (settings.underline_thickness, cell_dimensions.y),
+    };
+    if thickness >= 1.0 && cell_size > 0.0 {
+        (thickness / cell_size).min(1.0)
+    } else if thickness > 0.0 {
+        thickness
+    } else {
+        guicursor_percentage.unwrap_or(DEFAULT_CELL_PERCENTAGE)
+    }
+}
+
 #[derive(SettingGroup)]
 #[setting_prefix = "cursor"]
 #[derive(Clone)]
@@ -29,6 +51,9 @@ pub struct CursorSettings {
     animate_in_insert_mode: bool,
     animate_command_line: bool,
     trail_size: f32,
+    morph_length: f32,
+    bar_thickness: f32,
+    underline_thickness: f32,
     vfx_mode: cursor_vfx::VfxMode,
     vfx_opacity: f32,
     vfx_particle_lifetime: f32,
@@ -47,6 +72,9 @@ impl Default for CursorSettings {
             animate_in_insert_mode: true,
             animate_command_line: true,
             trail_size: 0.7,
+            morph_length: 0.1,
+            bar_thickness: 0.0,
+            underline_thickness: 0.0,
             vfx_mode: cursor_vfx::VfxMode::Disabled,
             vfx_opacity: 200.0,
             vfx_particle_lifetime: 1.2,
@@ -63,6 +91,10 @@ pub struct Corner {
     start_position: Point,
     current_position: Point,
     relative_position: Point,
+    // Where the corner was within the cell when the shape last changed, and how far it got
+    // from there to the new relative position
+    shape_start_position: Point,
+    shape_t: f32,
     previous_destination: Point,
     length_multiplier: f32,
     t: f32,
@@ -74,12 +106,44 @@ impl Corner {
             start_position: Point::new(0.0, 0.0),
             current_position: Point::new(0.0, 0.0),
             relative_position: Point::new(0.0, 0.0),
+            shape_start_position: Point::new(0.0, 0.0),
+            shape_t: 1.0,
             previous_destination: Point::new(-1000.0, -1000.0),
             length_multiplier: 1.0,
             t: 0.0,
         }
     }
 
+    // The position within the cell, part way to the one of the new shape while it morphs
+    fn shape_position(&self) -> Point {
+        ease_point(
+            ease_out_cubic,
+            self.shape_start_position,
+            self.relative_position,
+            self.shape_t,
+        )
+    }
+
+    fn morph_to(&mut self, relative_position: Point) {
+        self.shape_start_position = self.shape_position();
+        self.relative_position = relative_position;
+        self.shape_t = 0.0;
+    }
+
+    // Returns whether the shape is still morphing. The morph only follows reduced motion, not the
+    // insert mode and command line settings, since changing modes is what changes the shape.
+    fn update_shape(&mut self, settings: &CursorSettings, dt: f32, reduce_motion: bool) -> bool {
+        if self.shape_t >= 1.0 {
+            return false;
+        }
+        self.shape_t = if reduce_motion || settings.morph_length <= 0.0 {
+            1.0
+        } else {
+            (self.shape_t + dt / settings.morph_length).min(1.0)
+        };
+        true
+    }
+
     pub fn update(
         &mut self,
         settings: &CursorSettings,
@@ -102,20 +166,22 @@ impl Corner {
             }
         }
 
-        // Check first if animation's over
-        if (self.t - 1.0).abs() < std::f32::EPSILON {
-            return false;
-        }
-
         // Calculate window-space destination for corner
+        let shape_position = self.shape_position();
         let relative_scaled_position: Point = (
-            self.relative_position.x * font_dimensions.x,
-            self.relative_position.y * font_dimensions.y,
+            shape_position.x * font_dimensions.x,
+            shape_position.y * font_dimensions.y,
         )
             .into();
 
         let corner_destination = destination + relative_scaled_position;
 
+        // Check first if animation's over, the corner only follows the shape from then on
+        if (self.t - 1.0).abs() < std::f32::EPSILON {
+            self.current_position = corner_destination;
+            return false;
+        }
+
         if immediate_movement {
             self.t = 1.0;
             self.current_position = corner_destination;
@@ -170,6 +236,7 @@ pub struct CursorRenderer {
     destination: Point,
     blink_status: BlinkStatus,
     previous_cursor_shape: Option<CursorShape>,
+    previous_cell_percentage: f32,
     previous_editor_mode: EditorMode,
     cursor_vfx: Option<Box<dyn cursor_vfx::CursorVfx>>,
     previous_vfx_mode: cursor_vfx::VfxMode,
@@ -183,6 +250,7 @@ impl CursorRenderer {
             destination: (0.0, 0.0).into(),
             blink_status: BlinkStatus::new(),
             previous_cursor_shape: None,
+            previous_cell_percentage: DEFAULT_CELL_PERCENTAGE,
             previous_editor_mode: EditorMode::Normal,
             cursor_vfx: None,
             previous_vfx_mode: cursor_vfx::VfxMode::Disabled,
@@ -191,38 +259,36 @@ impl CursorRenderer {
         renderer
     }
 
+    pub fn parent_window_id(&self) -> u64 {
+        self.cursor.parent_window_id
+    }
+
     pub fn update_cursor(&mut self, new_cursor: Cursor) {
         self.cursor = new_cursor;
     }
 
+    // The corners morph from the old shape to the new one over the morph length, while they
+    // keep following the cursor around
     fn set_cursor_shape(&mut self, cursor_shape: &CursorShape, cell_percentage: f32) {
-        self.corners = self
-            .corners
-            .clone()
-            .into_iter()
-            .enumerate()
-            .map(|(i, corner)| {
-                let (x, y) = STANDARD_CORNERS[i];
-
-                Corner {
-                    relative_position: match cursor_shape {
-                        CursorShape::Block => (x, y).into(),
-                        // Transform the x position so that the right side is translated over to
-                        // the BAR_WIDTH position
-                        CursorShape::Vertical => ((x + 0.5) * cell_percentage - 0.5, y).into(),
-                        // Do the same as above, but flip the y coordinate and then flip the result
-                        // so that the horizontal bar is at the bottom of the character space
-                        // instead of the top.
-                        CursorShape::Horizontal => {
-                            (x, -((-y + 0.5) * cell_percentage - 0.5)).into()
-                        }
-                    },
-                    t: 0.0,
-                    start_position: corner.current_position,
-                    ..corner
-                }
-            })
-            .collect::<Vec<Corner>>();
+        for (i, corner) in self.corners.iter_mut().enumerate() {
+            let (x, y) = STANDARD_CORNERS[i];
+
+            corner.morph_to(match cursor_shape {
+                CursorShape::Block => (x, y).into(),
+                // Transform the x position so that the right side is translated over to
+                // the BAR_WIDTH position
+                CursorShape::Vertical => ((x + 0.5) * cell_percentage - 0.5, y).into(),
+                // Do the same as above, but flip the y coordinate and then flip the result
+                // so that the horizontal bar is at the bottom of the character space
+                // instead of the top.
+                CursorShape::Horizontal => (x, -((-y + 0.5) * cell_percentage - 0.5)).into(),
+            });
+        }
+    }
+
+    /// Where the cursor is headed on the grid, in pixels.
+    pub fn destination(&self) -> Point {
+        self.destination
     }
 
     pub fn update_cursor_destination(
@@ -263,6 +329,7 @@ impl CursorRenderer {
     ) {
         let render = self.blink_status.update_status(&self.cursor);
         let settings = SETTINGS.get::<CursorSettings>();
+        let reduce_motion = SETTINGS.get::<RendererSettings>().reduce_motion();
 
         if settings.vfx_mode != self.previous_vfx_mode {
             self.cursor_vfx = cursor_vfx::new_cursor_vfx(&settings.vfx_mode);
@@ -292,16 +359,24 @@ impl CursorRenderer {
 
         let center_destination = self.destination + cursor_dimensions * 0.5;
         let new_cursor = Some(self.cursor.shape.clone());
+        let new_cell_percentage = cell_percentage(
+            &self.cursor.shape,
+            self.cursor.cell_percentage,
+            &settings,
+            cursor_dimensions,
+        );
 
-        if self.previous_cursor_shape != new_cursor {
+        let shape_changed = self.previous_cursor_shape != new_cursor;
+        // Also when only the thickness changed, through guicursor, the settings or the font size
+        if shape_changed
+            || (self.previous_cell_percentage - new_cell_percentage).abs() > f32::EPSILON
+        {
             self.previous_cursor_shape = new_cursor.clone();
-            self.set_cursor_shape(
-                &new_cursor.unwrap(),
-                self.cursor
-                    .cell_percentage
-                    .unwrap_or(DEFAULT_CELL_PERCENTAGE),
-            );
+            self.previous_cell_percentage = new_cell_percentage;
+            self.set_cursor_shape(&new_cursor.unwrap(), new_cell_percentage);
+        }
 
+        if shape_changed {
             if let Some(vfx) = self.cursor_vfx.as_mut() {
                 vfx.restart(center_destination);
             }
@@ -311,9 +386,11 @@ impl CursorRenderer {
 
         if !center_destination.is_zero() {
             for corner in self.corners.iter_mut() {
-                let immediate_movement = !settings.animate_in_insert_mode && in_insert_mode
+                let immediate_movement = reduce_motion
+                    || !settings.animate_in_insert_mode && in_insert_mode
                     || !settings.animate_command_line && !changed_to_from_cmdline;
 
+                let corner_morphing = corner.update_shape(&settings, dt, reduce_motion);
                 let corner_animating = corner.update(
                     &settings,
                     cursor_dimensions,
@@ -322,13 +399,14 @@ impl CursorRenderer {
                     immediate_movement,
                 );
 
-                animating |= corner_animating;
+                animating |= corner_animating || corner_morphing;
             }
 
-            let vfx_animating = if let Some(vfx) = self.cursor_vfx.as_mut() {
-                vfx.update(&settings, center_destination, cursor_dimensions, dt)
-            } else {
-                false
+            let vfx_animating = match self.cursor_vfx.as_mut() {
+                Some(vfx) if !reduce_motion => {
+                    vfx.update(&settings, center_destination, cursor_dimensions, dt)
+                }
+                _ => false,
             };
 
             animating |= vfx_animating;
@@ -370,8 +448,21 @@ impl CursorRenderer {
             canvas.save();
             canvas.clip_path(&path, None, Some(false));
 
+            // In the font of the cell, so a bold or italic character keeps its look
+            let (bold, italic) = self
+                .cursor
+                .cell_style
+                .as_ref()
+                .map(|style| (style.bold, style.italic))
+                .unwrap_or((false, false));
             let y_adjustment = grid_renderer.shaper.y_adjustment();
-            let blobs = &grid_renderer.shaper.shape_cached(character, false, false);
+            let blobs = &grid_renderer.shaper.shape_cached(
+                character,
+                bold,
+                italic,
+                self.cursor.double_width,
+                false,
+            );
 
             for blob in blobs.iter() {
                 canvas.draw_text_blob(
@@ -383,9 +474,58 @@ impl CursorRenderer {
 
             canvas.restore();
 
-            if let Some(vfx) = self.cursor_vfx.as_ref() {
+            if let Some(vfx) = self.cursor_vfx.as_ref().filter(|_| !reduce_motion) {
                 vfx.render(&settings, canvas, grid_renderer, &self.cursor);
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cell_percentage() {
+        let cell_dimensions = Point::new(10.0, 20.0);
+        let mut settings = CursorSettings::default();
+        assert_eq!(
+            cell_percentage(
+                &CursorShape::Vertical,
+                Some(0.25),
+                &settings,
+                cell_dimensions
+            ),
+            0.25
+        );
+        assert_eq!(
+            cell_percentage(&CursorShape::Vertical, None, &settings, cell_dimensions),
+            DEFAULT_CELL_PERCENTAGE
+        );
+
+        settings.bar_thickness = 3.0;
+        settings.underline_thickness = 0.5;
+        assert_eq!(
+            cell_percentage(
+                &CursorShape::Vertical,
+                Some(0.25),
+                &settings,
+                cell_dimensions
+            ),
+            0.3
+        );
+        assert_eq!(
+            cell_percentage(
+                &CursorShape::Horizontal,
+                Some(0.25),
+                &settings,
+                cell_dimensions
+            ),
+            0.5
+        );
+        assert_eq!(
+            cell_percentage(&CursorShape::Block, Some(0.25), &settings, cell_dimensions),
+            1.0
+        );
+    }
+}
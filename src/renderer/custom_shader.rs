@@ -0,0 +1,269 @@
+use std::time::Instant;
+
+use log::{error, info};
+use skia_safe::{
+    Canvas, Color, Data, Paint, Rect, RuntimeEffect, SamplingOptions, Shader, Surface,
+};
+
+use super::{rendered_window::build_window_surface, RendererSettings};
+use crate::redraw_scheduler::REDRAW_SCHEDULER;
+
+const TIME_UNIFORM: &str = "u_time";
+const RESOLUTION_UNIFORM: &str = "u_resolution";
+const FRAME_UNIFORM: &str = "u_frame";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ShaderTarget {
+    Background,
+    Overlay,
+}
+
+impl ShaderTarget {
+    pub fn from_name(name: &str) -> ShaderTarget {
+        match name {
+            "overlay" => ShaderTarget::Overlay,
+            _ => ShaderTarget::Background,
+        }
+    }
+}
+
+fn load_effect(path: &str) -> Option<RuntimeEffect> {
+    let source = std::fs::read_to_string(path)
+        .map_err(|error| error!("Could not read shader {}: {}", path, error))
+        .ok()?;
+
+    match RuntimeEffect::new(source) {
+        Ok(effect) => {
+            info!("Loaded shader {}", path);
+            Some(effect)
+        }
+        Err(message) => {
+            error!("Could not compile shader {}: {}", path, message);
+            None
+        }
+    }
+}
+
+/// A user supplied SkSL shader which is either painted beneath the grid or over the finished
+/// frame. Shaders may declare `uniform float u_time` and `uniform float2 u_resolution`, which
+/// are filled in before every draw. Overlay shaders sample the frame they post process from
+/// `uniform shader u_frame`.
+pub struct CustomShader {
+    path: String,
+    effect: Option<RuntimeEffect>,
+    start_time: Instant,
+    // What overlay shaders draw the frame into, kept from frame to frame while the size stays
+    frame_surface: Option<Surface>,
+}
+
+impl CustomShader {
+    pub fn new() -> CustomShader {
+        CustomShader {
+            path: String::new(),
+            effect: None,
+            start_time: Instant::now(),
+            frame_surface: None,
+        }
+    }
+
+    // Recompiles whenever the setting points somewhere new. Shaders which fail to compile are
+    // remembered so the error is only reported once
+    fn update(&mut self, path: &str) {
+        if self.path != path {
+            self.path = path.to_owned();
+            self.start_time = Instant::now();
+            self.effect = if path.is_empty() {
+                None
+            } else {
+                load_effect(path)
+            };
+        }
+    }
+
+    fn is_active(&mut self, settings: &RendererSettings, target: ShaderTarget) -> bool {
+        self.update(&settings.shader);
+        self.effect.is_some() && ShaderTarget::from_name(&settings.shader_target) == target
+    }
+
+    /// The surface the frame has to be drawn into instead of the window when an overlay shader
+    /// post processes it. It goes back to `draw_overlay` once the frame is done.
+    pub fn take_frame_surface(
+        &mut self,
+        root_canvas: &mut Canvas,
+        settings: &RendererSettings,
+    ) -> Option<Surface> {
+        if !self.is_active(settings, ShaderTarget::Overlay) {
+            self.frame_surface = None;
+            return None;
+        }
+
+        let size = root_canvas.base_layer_size();
+        let surface = self
+            .frame_surface
+            .take()
+            .filter(|surface| surface.width() == size.width && surface.height() == size.height)
+            .unwrap_or_else(|| build_window_surface(root_canvas, (size.width, size.height)));
+        Some(surface)
+    }
+
+    fn build_uniforms(&self, effect: &RuntimeEffect, bounds: Rect) -> (Data, bool) {
+        let mut uniforms = vec![0u8; effect.uniform_size()];
+        let mut animated = false;
+
+        let mut write = |offset: usize, values: &[f32]| {
+            for (index, value) in values.iter().enumerate() {
+                let start = offset + index * 4;
+                if let Some(slot) = uniforms.get_mut(start..start + 4) {
+                    slot.copy_from_slice(&value.to_ne_bytes());
+                }
+            }
+        };
+
+        for uniform in effect.uniforms() {
+            match uniform.name() {
+                TIME_UNIFORM => {
+                    write(uniform.offset(), &[self.start_time.elapsed().as_secs_f32()]);
+                    animated = true;
+                }
+                RESOLUTION_UNIFORM => {
+                    write(uniform.offset(), &[bounds.width(), bounds.height()]);
+                }
+                _ => {}
+            }
+        }
+
+        (Data::new_copy(&uniforms), animated)
+    }
+
+    // The only child shader there is to bind is the frame, which background shaders don't have
+    fn build_children(effect: &RuntimeEffect, frame: Option<&Shader>) -> Option<Vec<Shader>> {
+        effect
+            .children()
+            .map(|name| match (name, frame) {
+                (FRAME_UNIFORM, Some(frame)) => Some(frame.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    // Whether the shader could be drawn
+    fn draw_effect(&self, canvas: &mut Canvas, frame: Option<&Shader>, bounds: Rect) -> bool {
+        let effect = match &self.effect {
+            Some(effect) => effect,
+            None => return false,
+        };
+
+        let children = match CustomShader::build_children(effect, frame) {
+            Some(children) => children,
+            None => {
+                error!(
+                    "Shader {} samples a child shader other than {}, which only overlays get",
+                    self.path, FRAME_UNIFORM
+                );
+                return false;
+            }
+        };
+        let (uniforms, animated) = self.build_uniforms(effect, bounds);
+        let shader = match effect.make_shader(uniforms, &children, None, false) {
+            Some(shader) => shader,
+            None => return false,
+        };
+
+        let mut paint = Paint::default();
+        paint.set_shader(shader);
+        canvas.draw_rect(&bounds, &paint);
+
+        // Shaders that read the time are expected to animate
+        if animated {
+            REDRAW_SCHEDULER.queue_next_frame();
+        }
+        true
+    }
+
+    pub fn draw_background(
+        &mut self,
+        root_canvas: &mut Canvas,
+        settings: &RendererSettings,
+        bounds: Rect,
+    ) {
+        if self.is_active(settings, ShaderTarget::Background) {
+            self.draw_effect(root_canvas, None, bounds);
+        }
+    }
+
+    /// Draws the frame from `take_frame_surface` into the window through the overlay shader.
+    pub fn draw_overlay(&mut self, root_canvas: &mut Canvas, mut frame_surface: Surface) {
+        let size = root_canvas.base_layer_size();
+        let bounds = Rect::from_wh(size.width as f32, size.height as f32);
+        let image = frame_surface.image_snapshot();
+        let frame = image.to_shader(None, SamplingOptions::default(), None);
+
+        root_canvas.save();
+        root_canvas.reset_matrix();
+        // The frame has its own background, which could be transparent
+        root_canvas.clear(Color::TRANSPARENT);
+        // The frame is still shown when the shader can't be drawn
+        if !self.draw_effect(root_canvas, frame.as_ref(), bounds) {
+            root_canvas.draw_image(&image, (0, 0), None);
+        }
+        root_canvas.restore();
+        self.frame_surface = Some(frame_surface);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use skia_safe::{AlphaType, ColorType, ImageInfo};
+
+    use super::*;
+
+    const PASS_THROUGH: &str = "
+        uniform shader u_frame;
+
+        half4 main(float2 position) {
+            return sample(u_frame, position);
+        }
+    ";
+
+    fn raster_surface() -> Surface {
+        let info = ImageInfo::new((4, 4), ColorType::RGBA8888, AlphaType::Premul, None);
+        Surface::new_raster(&info, None, None).unwrap()
+    }
+
+    fn pixel(surface: &mut Surface) -> Color {
+        let info = ImageInfo::new((1, 1), ColorType::BGRA8888, AlphaType::Unpremul, None);
+        let mut pixel = [0u8; 4];
+        assert!(surface.read_pixels(&info, &mut pixel, 4, (1, 1)));
+        Color::from_argb(pixel[3], pixel[2], pixel[1], pixel[0])
+    }
+
+    #[test]
+    fn test_pass_through_overlay_keeps_the_frame() {
+        let mut custom_shader = CustomShader::new();
+        custom_shader.effect = Some(RuntimeEffect::new(PASS_THROUGH).unwrap());
+
+        let mut frame_surface = raster_surface();
+        frame_surface.canvas().clear(Color::RED);
+        let mut window_surface = raster_surface();
+        window_surface.canvas().clear(Color::BLUE);
+        custom_shader.draw_overlay(window_surface.canvas(), frame_surface);
+
+        assert_eq!(pixel(&mut window_surface), Color::RED);
+        // The frame surface is reused for the next frame
+        assert!(custom_shader.frame_surface.is_some());
+    }
+
+    #[test]
+    fn test_only_the_frame_can_be_sampled() {
+        let effect = RuntimeEffect::new(PASS_THROUGH).unwrap();
+        assert!(CustomShader::build_children(&effect, None).is_none());
+        let frame = raster_surface()
+            .image_snapshot()
+            .to_shader(None, SamplingOptions::default(), None)
+            .unwrap();
+        assert_eq!(
+            CustomShader::build_children(&effect, Some(&frame)).map(|children| children.len()),
+            Some(1)
+        );
+    }
+}
@@ -0,0 +1,200 @@
+use skia_safe::{Canvas, Color4f, Font, Paint, PaintStyle, Point, RRect, Rect, Typeface};
+
+use crate::editor::{Decoration, DecorationImage, DecorationShape};
+use crate::utils::Dimensions;
+
+const STROKE_WIDTH: f32 = 2.0;
+// Of the cell height
+const BADGE_TEXT_SCALE: f32 = 0.75;
+const BADGE_PADDING: f32 = 4.0;
+
+/// The decorations plugins placed on a grid. They are kept in line with the grid as it scrolls
+/// like the links, and dropped once they are scrolled out of the region they were in. Images go
+/// away together with the decorations showing them.
+pub struct GridDecorations {
+    decorations: Vec<Decoration>,
+}
+
+impl GridDecorations {
+    pub fn new() -> GridDecorations {
+        GridDecorations {
+            decorations: Vec::new(),
+        }
+    }
+
+    /// Adds the decoration, or replaces the one with the same id.
+    pub fn set(&mut self, decoration: Decoration) {
+        match self
+            .decorations
+            .iter_mut()
+            .find(|existing| existing.id == decoration.id)
+        {
+            Some(existing) => *existing = decoration,
+            None => self.decorations.push(decoration),
+        }
+    }
+
+    pub fn remove(&mut self, id: &str) {
+        self.decorations.retain(|decoration| decoration.id != id);
+    }
+
+    pub fn clear(&mut self) {
+        self.decorations.clear();
+    }
+
+    pub fn scroll(&mut self, top: u64, bottom: u64, left: u64, right: u64, rows: i64) {
+        let (top, bottom) = (top as f64, bottom as f64);
+        let (left, right) = (left as f64, right as f64);
+        self.decorations = self
+            .decorations
+            .drain(..)
+            .filter_map(|mut decoration| {
+                let in_region = decoration.row >= top
+                    && decoration.row < bottom
+                    && decoration.column >= left
+                    && decoration.column < right;
+                if !in_region {
+                    return Some(decoration);
+                }
+                decoration.row -= rows as f64;
+                if let DecorationShape::Line { end_row, .. } = &mut decoration.shape {
+                    *end_row -= rows as f64;
+                }
+                let still_in_region = decoration.row >= top && decoration.row < bottom;
+                still_in_region.then(|| decoration)
+            })
+            .collect();
+    }
+
+    /// Draws the decorations with the grid origin at `origin`, fading them with the window.
+    pub fn draw(
+        &self,
+        canvas: &mut Canvas,
+        origin: Point,
+        font_dimensions: Dimensions,
+        opacity: f32,
+    ) {
+        let cell_width = font_dimensions.width as f32;
+        let cell_height = font_dimensions.height as f32;
+        let cell_point = |row: f64, column: f64| {
+            Point::new(
+                origin.x + column as f32 * cell_width,
+                origin.y + row as f32 * cell_height,
+            )
+        };
+        let paint_for = |color: Option<Color4f>, fallback: Color4f| {
+            let mut color = color.unwrap_or(fallback);
+            color.a *= opacity;
+            let mut paint = Paint::new(color, None);
+            paint.set_anti_alias(true);
+            paint
+        };
+        let white = Color4f::new(1.0, 1.0, 1.0, 1.0);
+        let black = Color4f::new(0.0, 0.0, 0.0, 1.0);
+
+        for decoration in &self.decorations {
+            let top_left = cell_point(decoration.row, decoration.column);
+            match &decoration.shape {
+                DecorationShape::Rectangle {
+                    width,
+                    height,
+                    filled,
+                } => {
+                    let region = Rect::from_point_and_size(
+                        top_left,
+                        (*width as f32 * cell_width, *height as f32 * cell_height),
+                    );
+                    let mut paint = paint_for(decoration.color, white);
+                    if !filled {
+                        paint.set_style(PaintStyle::Stroke);
+                        paint.set_stroke_width(STROKE_WIDTH);
+                    }
+                    canvas.draw_rect(region, &paint);
+                }
+                DecorationShape::Line {
+                    end_row,
+                    end_column,
+                } => {
+                    let mut paint = paint_for(decoration.color, white);
+                    paint.set_stroke_width(STROKE_WIDTH);
+                    canvas.draw_line(top_left, cell_point(*end_row, *end_column), &paint);
+                }
+                DecorationShape::Badge { text } => {
+                    let font = Font::new(Typeface::default(), cell_height * BADGE_TEXT_SCALE);
+                    let text_paint = paint_for(decoration.color, black);
+                    let (text_width, _) = font.measure_str(text, Some(&text_paint));
+                    let region = Rect::from_point_and_size(
+                        top_left,
+                        (text_width + 2.0 * BADGE_PADDING, cell_height),
+                    );
+                    let radius = cell_height / 2.0;
+                    canvas.draw_rrect(
+                        RRect::new_rect_xy(&region, radius, radius),
+                        &paint_for(decoration.background, white),
+                    );
+                    let (_, metrics) = font.metrics();
+                    let baseline = region.center_y() - (metrics.ascent + metrics.descent) / 2.0;
+                    canvas.draw_str(
+                        text,
+                        (region.left + BADGE_PADDING, baseline),
+                        &font,
+                        &text_paint,
+                    );
+                }
+                DecorationShape::Image {
+                    width,
+                    height,
+                    image: Some(DecorationImage(image)),
+                    ..
+                } => {
+                    let region = Rect::from_point_and_size(
+                        top_left,
+                        (*width as f32 * cell_width, *height as f32 * cell_height),
+                    );
+                    canvas.draw_image_rect(image, None, region, &paint_for(None, white));
+                }
+                // The file couldn't be read, which was reported when the decoration arrived
+                DecorationShape::Image { image: None, .. } => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn badge(id: &str, row: f64) -> Decoration {
+        Decoration {
+            id: id.to_owned(),
+            row,
+            column: 2.0,
+            color: None,
+            background: None,
+            shape: DecorationShape::Badge {
+                text: id.to_owned(),
+            },
+        }
+    }
+
+    #[test]
+    fn test_decorations_follow_scroll() {
+        let mut decorations = GridDecorations::new();
+        decorations.set(badge("first", 1.0));
+        decorations.set(badge("second", 5.0));
+        decorations.set(badge("statusline", 10.0));
+
+        // Scrolling the text up by two lines, leaving the statusline where it is
+        decorations.scroll(0, 10, 0, 80, 2);
+        let rows: Vec<(&str, f64)> = decorations
+            .decorations
+            .iter()
+            .map(|decoration| (decoration.id.as_str(), decoration.row))
+            .collect();
+        assert_eq!(rows, vec![("second", 3.0), ("statusline", 10.0)]);
+
+        decorations.set(badge("second", 4.0));
+        decorations.remove("statusline");
+        assert_eq!(decorations.decorations, vec![badge("second", 4.0)]);
+    }
+}
@@ -0,0 +1,111 @@
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use log::{error, info};
+use skia_safe::{pdf, svg, Canvas, Data, Rect};
+
+use super::GridRenderer;
+use crate::editor::LineFragment;
+
+fn default_export_path() -> PathBuf {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0);
+    PathBuf::from(format!("neovide_export_{}.pdf", timestamp))
+}
+
+fn is_svg(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| extension.eq_ignore_ascii_case("svg"))
+        .unwrap_or(false)
+}
+
+// Uses the same background and foreground drawing as the window surfaces so the export matches
+// what is on screen, minus animations
+fn draw_lines(
+    canvas: &mut Canvas,
+    grid_renderer: &mut GridRenderer,
+    lines: Vec<Vec<LineFragment>>,
+) {
+    canvas.clear(grid_renderer.get_default_background());
+
+    for line_fragments in lines {
+        grid_renderer.draw_line_background(canvas, &line_fragments, false);
+
+        for line_fragment in line_fragments.into_iter() {
+            let LineFragment {
+                text,
+                window_left,
+                window_top,
+                width,
+                style,
+                right_to_left,
+            } = line_fragment;
+            grid_renderer.draw_foreground(
+                canvas,
+                text,
+                (window_left, window_top),
+                width,
+                &style,
+                right_to_left,
+            );
+        }
+    }
+}
+
+fn render_document(
+    grid_renderer: &mut GridRenderer,
+    svg: bool,
+    grid_size: (u64, u64),
+    lines: Vec<Vec<LineFragment>>,
+) -> Data {
+    let (width, height) = grid_size;
+    let font_dimensions = grid_renderer.font_dimensions;
+    let size = (
+        (width * font_dimensions.width) as f32,
+        (height * font_dimensions.height) as f32,
+    );
+
+    if svg {
+        let mut canvas = svg::Canvas::new(Rect::from_size(size), None);
+        draw_lines(&mut canvas, grid_renderer, lines);
+        canvas.end()
+    } else {
+        let mut page = pdf::new_document(None).begin_page(size, None);
+        draw_lines(page.canvas(), grid_renderer, lines);
+        page.end_page().close()
+    }
+}
+
+/// Writes the given grid lines to a vector document. Paths ending in `.svg` become an svg and
+/// everything else a single page pdf. Without a path the file is put in the working directory and
+/// named after the current time.
+pub fn export_grid(
+    grid_renderer: &mut GridRenderer,
+    path: Option<String>,
+    grid_size: (u64, u64),
+    lines: Vec<Vec<LineFragment>>,
+) {
+    let path = path.map(PathBuf::from).unwrap_or_else(default_export_path);
+    let data = render_document(grid_renderer, is_svg(&path), grid_size, lines);
+
+    match std::fs::write(&path, data.as_bytes()) {
+        Ok(()) => info!("Exported view to {}", path.display()),
+        Err(error) => error!("Could not export view to {}: {}", path.display(), error),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_svg() {
+        assert!(is_svg(Path::new("snippet.svg")));
+        assert!(is_svg(Path::new("snippet.SVG")));
+        assert!(!is_svg(Path::new("snippet.pdf")));
+        assert!(!is_svg(Path::new("snippet")));
+    }
+}
@@ -0,0 +1,176 @@
+use std::ops::Range;
+
+use unicode_bidi::{BidiInfo, Level};
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::editor::LineFragment;
+
+// A grapheme of the row, with the fragment it came from
+struct Grapheme {
+    fragment: usize,
+    bytes: Range<usize>,
+    cells: u64,
+}
+
+// The graphemes of every fragment in the order neovim put them into the cells. A double width
+// character always ends its fragment and covers the cell after it as well.
+fn row_graphemes(line_fragments: &[LineFragment], text: &str) -> Vec<Grapheme> {
+    let mut graphemes = Vec::new();
+    let mut start = 0;
+    for (fragment_index, fragment) in line_fragments.iter().enumerate() {
+        let fragment_graphemes: Vec<(usize, &str)> = fragment.text.grapheme_indices(true).collect();
+        let extra_cells = fragment
+            .width
+            .saturating_sub(fragment_graphemes.len() as u64);
+        for (index, (offset, grapheme)) in fragment_graphemes.iter().enumerate() {
+            let last = index + 1 == fragment_graphemes.len();
+            graphemes.push(Grapheme {
+                fragment: fragment_index,
+                bytes: start + offset..start + offset + grapheme.len(),
+                cells: if last { 1 + extra_cells } else { 1 },
+            });
+        }
+        start += fragment.text.len();
+    }
+    debug_assert_eq!(start, text.len());
+    graphemes
+}
+
+/// Lays out a row with right to left parts. Neovim puts the characters into the cells in logical
+/// order, like a terminal with termbidi does, so the levels are resolved once for the whole row
+/// and its fragments are split where the direction changes and moved to the cells they are seen
+/// in. Right to left fragments keep their text in logical order, the shaper mirrors them within
+/// their cells. Rows without right to left characters come back as they are.
+pub fn reorder_line(line_fragments: Vec<LineFragment>) -> Vec<LineFragment> {
+    let text: String = line_fragments
+        .iter()
+        .map(|fragment| fragment.text.as_str())
+        .collect();
+    if text.is_ascii() {
+        return line_fragments;
+    }
+    // The paragraph direction is left to right like neovim's own, regardless of what the row
+    // starts with
+    let bidi_info = BidiInfo::new(&text, Some(Level::ltr()));
+    if !bidi_info.has_rtl() {
+        return line_fragments;
+    }
+
+    let graphemes = row_graphemes(&line_fragments, &text);
+    let mut cell = line_fragments
+        .first()
+        .map_or(0, |fragment| fragment.window_left);
+    let mut reordered = Vec::with_capacity(line_fragments.len());
+    for paragraph in &bidi_info.paragraphs {
+        let (levels, runs) = bidi_info.visual_runs(paragraph, paragraph.range.clone());
+        for run in runs {
+            let right_to_left = levels[run.start].is_rtl();
+            // Split where the fragments were, so styles and double width characters stay on
+            // their own
+            let mut pieces: Vec<(usize, LineFragment)> = Vec::new();
+            for grapheme in graphemes
+                .iter()
+                .filter(|grapheme| run.contains(&grapheme.bytes.start))
+            {
+                let fragment = &line_fragments[grapheme.fragment];
+                match pieces.last_mut() {
+                    Some((fragment_index, piece)) if *fragment_index == grapheme.fragment => {
+                        piece.text.push_str(&text[grapheme.bytes.clone()]);
+                        piece.width += grapheme.cells;
+                    }
+                    _ => pieces.push((
+                        grapheme.fragment,
+                        LineFragment {
+                            text: text[grapheme.bytes.clone()].to_owned(),
+                            window_left: 0,
+                            window_top: fragment.window_top,
+                            width: grapheme.cells,
+                            style: fragment.style.clone(),
+                            right_to_left,
+                        },
+                    )),
+                }
+            }
+            // The pieces of a right to left run are seen last to first
+            if right_to_left {
+                pieces.reverse();
+            }
+            for (_, mut piece) in pieces {
+                piece.window_left = cell;
+                cell += piece.width;
+                reordered.push(piece);
+            }
+        }
+    }
+    reordered
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::editor::{Colors, Style};
+
+    fn fragment(text: &str, window_left: u64, style: &Option<Arc<Style>>) -> LineFragment {
+        LineFragment {
+            text: text.to_owned(),
+            window_left,
+            window_top: 0,
+            width: text.chars().count() as u64,
+            style: style.clone(),
+            right_to_left: false,
+        }
+    }
+
+    fn layout(line_fragments: &[LineFragment]) -> Vec<(&str, u64, bool)> {
+        line_fragments
+            .iter()
+            .map(|fragment| {
+                (
+                    fragment.text.as_str(),
+                    fragment.window_left,
+                    fragment.right_to_left,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_left_to_right_rows_stay_as_they_are() {
+        let line = vec![fragment("plain text", 0, &None)];
+        assert_eq!(layout(&reorder_line(line)), vec![("plain text", 0, false)]);
+        let line = vec![fragment("naïve", 0, &None)];
+        assert_eq!(layout(&reorder_line(line)), vec![("naïve", 0, false)]);
+    }
+
+    #[test]
+    fn test_mixed_directions_in_one_row() {
+        let keyword = Some(Arc::new(Style::new(Colors::new(None, None, None))));
+        // The hebrew words span two styles, so they only come out right when the whole row is
+        // laid out at once
+        let line = vec![
+            fragment("ab שלום", 0, &None),
+            fragment(" עולם", 7, &keyword),
+            fragment(" cd", 12, &None),
+        ];
+        assert_eq!(
+            layout(&reorder_line(line)),
+            vec![
+                ("ab ", 0, false),
+                (" עולם", 3, true),
+                ("שלום", 8, true),
+                (" cd", 12, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rows_starting_right_to_left_keep_the_left_to_right_direction() {
+        let line = vec![fragment("שלום ab", 0, &None)];
+        assert_eq!(
+            layout(&reorder_line(line)),
+            vec![("שלום", 0, true), (" ab", 4, false)]
+        );
+    }
+}
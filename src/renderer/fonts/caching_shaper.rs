@@ -1,28 +1,91 @@
+use std::collections::HashSet;
 use std::sync::Arc;
 
 use log::trace;
 use lru::LruCache;
-use skia_safe::{TextBlob, TextBlobBuilder};
-use swash::shape::ShapeContext;
+use rayon::prelude::*;
+use skia_safe::{graphics, RSXform, TextBlob, TextBlobBuilder};
+use swash::shape::{Direction, ShapeContext};
 use swash::text::cluster::{CharCluster, Parser, Status, Token};
-use swash::text::Script;
+use swash::text::{Codepoint, Script};
 use swash::Metrics;
 use unicode_segmentation::UnicodeSegmentation;
 
 use super::font_loader::*;
 use super::font_options::*;
+use crate::renderer::RendererSettings;
+use crate::settings::{report_invalid_setting, SETTINGS};
+
+// Glyphs may overhang their cells by this many pixels before they are shrunk, which leaves the
+// slight overhang of italics and of fonts with tight metrics alone
+const OVERHANG_TOLERANCE: f32 = 1.0;
+
+/// The scale a glyph has to be drawn at to stay within the cells neovim gave it. Neovim decides
+/// on the cells with ambiwidth and its emoji width table, so ambiguous characters from a CJK font
+/// or emoji drawn as a single cell would otherwise overlap the next one.
+fn fit_scale(advance: f32, cells_width: f32) -> Option<f32> {
+    if advance > cells_width + OVERHANG_TOLERANCE {
+        Some(cells_width / advance)
+    } else {
+        None
+    }
+}
+
+// Rough sizes of what a cached shape holds besides its text: the key and list, each blob, and
+// the id and position of each glyph, of which there is about one per character
+const ENTRY_OVERHEAD: usize = 96;
+const BLOB_OVERHEAD: usize = 64;
+const GLYPH_SIZE: usize = 10;
+// What skia limits its glyph cache to unless told otherwise
+const MAX_FONT_CACHE_LIMIT: usize = 2 * 1024 * 1024;
+
+fn estimated_size(key: &ShapeKey, blobs: &[TextBlob]) -> usize {
+    ENTRY_OVERHEAD
+        + key.text.len()
+        + blobs.len() * BLOB_OVERHEAD
+        + key.text.chars().count() * GLYPH_SIZE
+}
+
+// A double width character always ends the text it is drawn with
+fn wide_glyph_index(text: &str, double_width: bool) -> Option<usize> {
+    if double_width {
+        text.graphemes(true).count().checked_sub(1)
+    } else {
+        None
+    }
+}
+
+// Fewer texts than this are shaped while drawing, as handing them to the pool costs more than
+// shaping them
+const PARALLEL_THRESHOLD: usize = 16;
+
+// The clusters shaped together with one font, and whether they are right to left
+type ClusterGroup = (Vec<CharCluster>, Arc<FontPair>, bool);
 
 #[derive(new, Clone, Hash, PartialEq, Eq, Debug)]
-struct ShapeKey {
+pub struct ShapeKey {
     pub text: String,
     pub bold: bool,
     pub italic: bool,
+    pub double_width: bool,
+    pub right_to_left: bool,
 }
 
 pub struct CachingShaper {
     options: FontOptions,
+    // The fonts from guifontwide, tried first for double width characters
+    wide_font_list: Vec<String>,
+    style_fonts: StyleFonts,
+    symbol_map: SymbolMap,
+    // The setting the symbol map was parsed from, so it is only parsed again once it changed
+    symbol_map_setting: String,
     font_loader: FontLoader,
-    blob_cache: LruCache<ShapeKey, Vec<TextBlob>>,
+    // Shaped text with its estimated size, evicted least recently used first once the sizes add
+    // up to more than the budget
+    blob_cache: LruCache<ShapeKey, (Vec<TextBlob>, usize)>,
+    blob_cache_size: usize,
+    // In bytes, from g:neovide_glyph_cache_size
+    cache_budget: usize,
     shape_context: ShapeContext,
     scale_factor: f32,
 }
@@ -31,10 +94,17 @@ impl CachingShaper {
     pub fn new(scale_factor: f32) -> CachingShaper {
         let options = FontOptions::default();
         let font_size = options.size * scale_factor;
+        let font_loader = FontLoader::new(font_size, options.weight);
         CachingShaper {
             options,
-            font_loader: FontLoader::new(font_size),
-            blob_cache: LruCache::new(10000),
+            wide_font_list: Vec::new(),
+            style_fonts: StyleFonts::default(),
+            symbol_map: SymbolMap::default(),
+            symbol_map_setting: String::new(),
+            font_loader,
+            blob_cache: LruCache::unbounded(),
+            blob_cache_size: 0,
+            cache_budget: 0,
             shape_context: ShapeContext::new(),
             scale_factor,
         }
@@ -53,10 +123,19 @@ impl CachingShaper {
             .expect("Could not load font")
     }
 
+    /// The font size from guifont, before scaling.
+    pub fn base_size(&self) -> f32 {
+        self.options.size
+    }
+
     pub fn current_size(&self) -> f32 {
         self.options.size * self.scale_factor
     }
 
+    pub fn loaded_fonts(&self) -> Vec<String> {
+        self.font_loader.loaded_families()
+    }
+
     pub fn update_scale_factor(&mut self, scale_factor: f32) {
         trace!("scale_factor changed: {:.2}", scale_factor);
         self.scale_factor = scale_factor;
@@ -75,15 +154,131 @@ impl CachingShaper {
             self.reset_font_loader();
         } else {
             trace!("Font can't be updated to: {}", guifont_setting);
+            report_invalid_setting(format!(
+                "Ignored guifont={}: the font could not be found, so the previous one is kept",
+                guifont_setting
+            ));
+        }
+    }
+
+    /// Only the font names of guifontwide are used. Double width characters are drawn at the
+    /// size of guifont and in its cells, so the grid keeps the metrics of the primary font.
+    pub fn update_wide_font(&mut self, guifontwide_setting: &str) {
+        trace!("Updating wide font: {}", guifontwide_setting);
+
+        let font_list = FontOptions::parse(guifontwide_setting).font_list;
+        if self.loads_any(&font_list) {
+            self.wide_font_list = font_list;
+            self.clear_blob_cache();
+        } else {
+            report_invalid_setting(format!(
+                "Ignored guifontwide={}: the font could not be found, so the previous one is kept",
+                guifontwide_setting
+            ));
+        }
+    }
+
+    /// Takes the style fonts from the settings. Fonts that can't be found are reported, but
+    /// kept, since they are only ever tried before the guifont ones.
+    pub fn update_style_fonts(&mut self, style_fonts: StyleFonts) {
+        if self.style_fonts == style_fonts {
+            return;
+        }
+        trace!("Updating style fonts: {:?}", style_fonts);
+
+        for font_list in [
+            &style_fonts.bold,
+            &style_fonts.italic,
+            &style_fonts.bold_italic,
+        ] {
+            if !self.loads_any(font_list) {
+                report_invalid_setting(format!(
+                    "None of the fonts {} could be found, guifont is used instead",
+                    font_list.join(",")
+                ));
+            }
+        }
+        self.style_fonts = style_fonts;
+        self.clear_blob_cache();
+    }
+
+    pub fn update_symbol_map(&mut self, symbol_map_setting: &str) {
+        if self.symbol_map_setting == symbol_map_setting {
+            return;
+        }
+        self.symbol_map_setting = symbol_map_setting.to_owned();
+        trace!("Updating symbol map: {}", symbol_map_setting);
+
+        match SymbolMap::parse(symbol_map_setting) {
+            Ok(symbol_map) => {
+                let missing: Vec<String> = symbol_map
+                    .font_names()
+                    .filter(|font_name| !self.loads_any(&[font_name.to_string()]))
+                    .cloned()
+                    .collect();
+                if !missing.is_empty() {
+                    report_invalid_setting(format!(
+                        "The fonts {} of g:neovide_symbol_map could not be found, the usual fallbacks are used instead",
+                        missing.join(", ")
+                    ));
+                }
+                self.symbol_map = symbol_map;
+                self.clear_blob_cache();
+            }
+            Err(message) => report_invalid_setting(format!(
+                "Ignored g:neovide_symbol_map={}: {}, so the previous one is kept",
+                symbol_map_setting, message
+            )),
         }
     }
 
+    // An empty list counts as loadable, as it leaves the fallbacks to the other fonts
+    fn loads_any(&mut self, font_list: &[String]) -> bool {
+        font_list.is_empty()
+            || font_list.iter().any(|font_name| {
+                self.font_loader
+                    .get_or_load(&FontKey {
+                        italic: false,
+                        bold: false,
+                        font_selection: font_name.into(),
+                    })
+                    .is_some()
+            })
+    }
+
     fn reset_font_loader(&mut self) {
         let font_size = self.options.size * self.scale_factor;
         trace!("Using font_size: {:.2}px", font_size);
 
-        self.font_loader = FontLoader::new(font_size);
+        self.font_loader = FontLoader::new(font_size, self.options.weight);
+        self.clear_blob_cache();
+    }
+
+    fn clear_blob_cache(&mut self) {
         self.blob_cache.clear();
+        self.blob_cache_size = 0;
+    }
+
+    // The budget covers the shaped text here. The rasterized glyphs skia keeps get at most its
+    // own default, which is plenty for the few fonts drawn at once and doesn't grow with it.
+    fn update_cache_budget(&mut self) {
+        let megabytes = SETTINGS.get::<RendererSettings>().glyph_cache_size.max(1) as usize;
+        let cache_budget = megabytes * 1024 * 1024;
+        if self.cache_budget != cache_budget {
+            self.cache_budget = cache_budget;
+            graphics::set_font_cache_limit(cache_budget.min(MAX_FONT_CACHE_LIMIT));
+            self.evict_to_budget();
+        }
+    }
+
+    fn evict_to_budget(&mut self) {
+        // The newest entry is always kept, since it is about to be drawn
+        while self.blob_cache_size > self.cache_budget && self.blob_cache.len() > 1 {
+            match self.blob_cache.pop_lru() {
+                Some((_, (_, size))) => self.blob_cache_size -= size,
+                None => break,
+            }
+        }
     }
 
     fn metrics(&mut self) -> Metrics {
@@ -120,8 +315,11 @@ impl CachingShaper {
         text: &str,
         bold: bool,
         italic: bool,
-    ) -> Vec<(Vec<CharCluster>, Arc<FontPair>)> {
+        double_width: bool,
+        right_to_left: bool,
+    ) -> Vec<ClusterGroup> {
         let mut cluster = CharCluster::new();
+        let wide_glyph_index = wide_glyph_index(text, double_width);
 
         // Enumerate the characters storing the glyph index in the user data so that we can position
         // glyphs according to Neovim's grid rules
@@ -152,6 +350,34 @@ impl CachingShaper {
             // Create font fallback list
             let mut font_fallback_keys = Vec::new();
 
+            // The symbol map overrides every other font for the characters it covers
+            if let Some(font_name) = self.symbol_map.font_for(cluster.chars()[0].ch) {
+                font_fallback_keys.push(FontKey {
+                    italic: self.options.italic || italic,
+                    bold: self.options.bold || bold,
+                    font_selection: font_name.into(),
+                });
+            }
+
+            // Add parsed fonts from guifontwide for double width characters
+            if wide_glyph_index == Some(cluster.chars()[0].data as usize) {
+                font_fallback_keys.extend(self.wide_font_list.iter().map(|font_name| FontKey {
+                    italic: self.options.italic || italic,
+                    bold: self.options.bold || bold,
+                    font_selection: font_name.into(),
+                }));
+            }
+
+            // Add the families picked for bold and italic text
+            let styled_bold = self.options.bold || bold;
+            let styled_italic = self.options.italic || italic;
+            let style_font_list = self.style_fonts.for_style(styled_bold, styled_italic);
+            font_fallback_keys.extend(style_font_list.iter().map(|font_name| FontKey {
+                italic: styled_italic,
+                bold: styled_bold,
+                font_selection: font_name.into(),
+            }));
+
             // Add parsed fonts from guifont
             font_fallback_keys.extend(self.options.font_list.iter().map(|font_name| FontKey {
                 italic: self.options.italic || italic,
@@ -203,7 +429,8 @@ impl CachingShaper {
         }
 
         // Now we have to group clusters by the font used so that the shaper can actually form
-        // ligatures across clusters
+        // ligatures across clusters. Right to left texts are shaped in their direction, so arabic
+        // letters join.
         let mut grouped_results = Vec::new();
         let mut current_group = Vec::new();
         let mut current_font_option = None;
@@ -212,7 +439,7 @@ impl CachingShaper {
                 if current_font == font {
                     current_group.push(cluster);
                 } else {
-                    grouped_results.push((current_group, current_font));
+                    grouped_results.push((current_group, current_font, right_to_left));
                     current_group = vec![cluster];
                     current_font_option = Some(font);
                 }
@@ -223,69 +450,239 @@ impl CachingShaper {
         }
 
         if !current_group.is_empty() {
-            grouped_results.push((current_group, current_font_option.unwrap()));
+            grouped_results.push((current_group, current_font_option.unwrap(), right_to_left));
         }
 
         grouped_results
     }
 
-    pub fn shape(&mut self, text: String, bold: bool, italic: bool) -> Vec<TextBlob> {
-        let current_size = self.current_size();
+    fn shape_layout(&mut self) -> ShapeLayout {
         let (glyph_width, ..) = self.font_base_dimensions();
+        let metrics = self.metrics();
+        ShapeLayout {
+            size: self.current_size(),
+            glyph_width,
+            // Shrunk glyphs are kept centered on the line, around the middle of the primary font
+            line_center: (metrics.descent - metrics.ascent) / 2.0,
+        }
+    }
 
-        let mut resulting_blobs = Vec::new();
-
+    pub fn shape(
+        &mut self,
+        text: String,
+        bold: bool,
+        italic: bool,
+        double_width: bool,
+        right_to_left: bool,
+    ) -> Vec<TextBlob> {
+        profile_scope!("shape");
+        let layout = self.shape_layout();
         trace!("Shaping text: {}", text);
 
-        for (cluster_group, font_pair) in self.build_clusters(&text, bold, italic) {
-            let mut shaper = self
-                .shape_context
-                .builder(font_pair.swash_font.as_ref())
-                .size(current_size)
-                .build();
-
-            let charmap = font_pair.swash_font.as_ref().charmap();
-            for mut cluster in cluster_group {
-                cluster.map(|ch| charmap.map(ch));
-                shaper.add_cluster(&cluster);
-            }
+        let cluster_groups = self.build_clusters(&text, bold, italic, double_width, right_to_left);
+        shape_clusters(
+            &mut self.shape_context,
+            &layout,
+            &text,
+            double_width,
+            cluster_groups,
+        )
+    }
 
-            let mut glyph_data = Vec::new();
+    // Only called for keys that aren't cached yet
+    fn cache_blobs(&mut self, key: ShapeKey, blobs: Vec<TextBlob>) {
+        let size = estimated_size(&key, &blobs);
+        self.blob_cache.put(key, (blobs, size));
+        self.blob_cache_size += size;
+    }
 
-            shaper.shape_with(|glyph_cluster| {
-                for glyph in glyph_cluster.glyphs {
-                    let position = ((glyph.data as u64 * glyph_width) as f32, glyph.y);
-                    glyph_data.push((glyph.id, position));
-                }
-            });
+    /// Shapes the texts that aren't cached yet on the thread pool, so a large repaint is spread
+    /// over the cores instead of being shaped one fragment at a time while drawing. The fonts
+    /// are still picked here, since loading a fallback needs the font loader. The glyphs are
+    /// rasterized by skia once the blobs are drawn, into its own glyph cache.
+    pub fn shape_all(&mut self, keys: Vec<ShapeKey>) {
+        let keys: HashSet<ShapeKey> = keys
+            .into_iter()
+            .filter(|key| !self.blob_cache.contains(key))
+            .collect();
+        if keys.len() < PARALLEL_THRESHOLD {
+            return;
+        }
+        trace!("Shaping {} texts in parallel", keys.len());
+        profile_scope!("shape in parallel");
+
+        let layout = self.shape_layout();
+        let jobs: Vec<_> = keys
+            .into_iter()
+            .map(|key| {
+                let cluster_groups = self.build_clusters(
+                    &key.text,
+                    key.bold,
+                    key.italic,
+                    key.double_width,
+                    key.right_to_left,
+                );
+                (key, cluster_groups)
+            })
+            .collect();
+        let shaped: Vec<(ShapeKey, Vec<TextBlob>)> = jobs
+            .into_par_iter()
+            .map_init(ShapeContext::new, |shape_context, (key, cluster_groups)| {
+                let blobs = shape_clusters(
+                    shape_context,
+                    &layout,
+                    &key.text,
+                    key.double_width,
+                    cluster_groups,
+                );
+                (key, blobs)
+            })
+            .collect();
+
+        for (key, blobs) in shaped {
+            self.cache_blobs(key, blobs);
+        }
+        self.update_cache_budget();
+        self.evict_to_budget();
+    }
+
+    pub fn shape_cached(
+        &mut self,
+        text: String,
+        bold: bool,
+        italic: bool,
+        double_width: bool,
+        right_to_left: bool,
+    ) -> &Vec<TextBlob> {
+        let key = ShapeKey::new(text.clone(), bold, italic, double_width, right_to_left);
+
+        if !self.blob_cache.contains(&key) {
+            let blobs = self.shape(text, bold, italic, double_width, right_to_left);
+            self.cache_blobs(key.clone(), blobs);
+            self.update_cache_budget();
+            self.evict_to_budget();
+        }
 
-            if glyph_data.is_empty() {
-                continue;
+        &self.blob_cache.get(&key).unwrap().0
+    }
+}
+
+// What every text is shaped against, taken from the primary font
+struct ShapeLayout {
+    size: f32,
+    glyph_width: u64,
+    line_center: f32,
+}
+
+// Shapes the clusters of one text into blobs, once build_clusters picked the fonts for them. It
+// only needs a shape context, so it can run on any thread.
+fn shape_clusters(
+    shape_context: &mut ShapeContext,
+    layout: &ShapeLayout,
+    text: &str,
+    double_width: bool,
+    cluster_groups: Vec<ClusterGroup>,
+) -> Vec<TextBlob> {
+    let glyph_width = layout.glyph_width;
+    let line_center = layout.line_center;
+    let wide_glyph_index = wide_glyph_index(text, double_width);
+    // Right to left texts are mirrored within their cells, so the first character ends up in the
+    // last cell
+    let cell_count = text.graphemes(true).count() as u32 + wide_glyph_index.is_some() as u32;
+
+    let mut resulting_blobs = Vec::new();
+
+    for (cluster_group, font_pair, right_to_left) in cluster_groups {
+        let mut builder = shape_context
+            .builder(font_pair.swash_font.as_ref())
+            .size(layout.size);
+        if right_to_left {
+            let script = cluster_group[0].chars()[0].ch.script();
+            builder = builder.script(script).direction(Direction::RightToLeft);
+        }
+        let mut shaper = builder.build();
+
+        let charmap = font_pair.swash_font.as_ref().charmap();
+        for mut cluster in cluster_group {
+            cluster.map(|ch| charmap.map(ch));
+            shaper.add_cluster(&cluster);
+        }
+
+        let mut glyph_data = Vec::new();
+
+        shaper.shape_with(|glyph_cluster| {
+            for glyph in glyph_cluster.glyphs {
+                let cells = if wide_glyph_index == Some(glyph.data as usize) {
+                    2
+                } else {
+                    1
+                };
+                let cell = if right_to_left {
+                    cell_count.saturating_sub(glyph.data + cells as u32)
+                } else {
+                    glyph.data
+                };
+                let position = ((cell as u64 * glyph_width) as f32, glyph.y);
+                // Ligatures are meant to span the cells of all their characters
+                let scale = if glyph_cluster.is_ligature() {
+                    None
+                } else {
+                    fit_scale(glyph.advance, (cells * glyph_width) as f32)
+                };
+                glyph_data.push((glyph.id, position, scale));
             }
+        });
 
-            let mut blob_builder = TextBlobBuilder::new();
+        if glyph_data.is_empty() {
+            continue;
+        }
+
+        let mut blob_builder = TextBlobBuilder::new();
+        if glyph_data.iter().any(|(_, _, scale)| scale.is_some()) {
+            // Only runs with glyphs that need shrinking pay for a transform per glyph
+            let (glyphs, transforms) =
+                blob_builder.alloc_run_rsxform(&font_pair.skia_font, glyph_data.len());
+            for (i, (glyph_id, (x, y), scale)) in glyph_data.iter().enumerate() {
+                let scale = scale.unwrap_or(1.0);
+                glyphs[i] = *glyph_id;
+                transforms[i] = RSXform::new(scale, 0.0, (*x, y + line_center * (1.0 - scale)));
+            }
+        } else {
             let (glyphs, positions) =
                 blob_builder.alloc_run_pos(&font_pair.skia_font, glyph_data.len(), None);
-            for (i, (glyph_id, glyph_position)) in glyph_data.iter().enumerate() {
+            for (i, (glyph_id, glyph_position, _)) in glyph_data.iter().enumerate() {
                 glyphs[i] = *glyph_id;
                 positions[i] = (*glyph_position).into();
             }
-
-            let blob = blob_builder.make();
-            resulting_blobs.push(blob.expect("Could not create textblob"));
         }
 
-        resulting_blobs
+        let blob = blob_builder.make();
+        resulting_blobs.push(blob.expect("Could not create textblob"));
     }
 
-    pub fn shape_cached(&mut self, text: String, bold: bool, italic: bool) -> &Vec<TextBlob> {
-        let key = ShapeKey::new(text.clone(), bold, italic);
+    resulting_blobs
+}
 
-        if !self.blob_cache.contains(&key) {
-            let blobs = self.shape(text, bold, italic);
-            self.blob_cache.put(key.clone(), blobs);
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_estimated_size_grows_with_text() {
+        let short = ShapeKey::new("ab".to_owned(), false, false, false, false);
+        let long = ShapeKey::new("abcdefgh".to_owned(), false, false, false, false);
+        assert_eq!(
+            estimated_size(&short, &[]),
+            ENTRY_OVERHEAD + 2 + 2 * GLYPH_SIZE
+        );
+        assert!(estimated_size(&long, &[]) > estimated_size(&short, &[]));
+    }
 
-        self.blob_cache.get(&key).unwrap()
+    #[test]
+    fn test_fit_scale() {
+        assert_eq!(fit_scale(10.0, 10.0), None);
+        assert_eq!(fit_scale(10.5, 10.0), None);
+        assert_eq!(fit_scale(20.0, 10.0), Some(0.5));
+        assert_eq!(fit_scale(20.0, 20.0), None);
     }
 }
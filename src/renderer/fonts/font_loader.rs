@@ -1,7 +1,11 @@
 use std::sync::Arc;
 
 use lru::LruCache;
-use skia_safe::{font::Edging, Data, Font, FontHinting, FontMgr, FontStyle, Typeface};
+use skia_safe::{
+    font::Edging,
+    font_style::{Slant, Weight, Width},
+    Data, Font, FontHinting, FontMgr, FontStyle, Typeface,
+};
 
 use super::font_options::FontOptions;
 use super::swash_font::SwashFont;
@@ -9,6 +13,10 @@ use super::swash_font::SwashFont;
 static DEFAULT_FONT: &[u8] = include_bytes!("../../../assets/fonts/FiraCode-Regular.ttf");
 static LAST_RESORT_FONT: &[u8] = include_bytes!("../../../assets/fonts/LastResort-Regular.ttf");
 
+const NORMAL_WEIGHT: i32 = 400;
+const BOLD_WEIGHT: i32 = 700;
+const BLACK_WEIGHT: i32 = 900;
+
 pub struct FontPair {
     pub skia_font: Font,
     pub swash_font: SwashFont,
@@ -40,6 +48,7 @@ pub struct FontLoader {
     font_mgr: FontMgr,
     cache: LruCache<FontKey, Arc<FontPair>>,
     font_size: f32,
+    weight: Option<i32>,
 }
 
 #[derive(Debug, Hash, PartialEq, Eq, Clone)]
@@ -106,21 +115,35 @@ impl From<char> for FontSelection {
 }
 
 impl FontLoader {
-    pub fn new(font_size: f32) -> FontLoader {
+    pub fn new(font_size: f32, weight: Option<i32>) -> FontLoader {
         FontLoader {
             font_mgr: FontMgr::new(),
             cache: LruCache::new(10),
             font_size,
+            weight,
         }
     }
 
-    fn load(&mut self, font_key: FontKey) -> Option<FontPair> {
-        let font_style = match (font_key.bold, font_key.italic) {
-            (true, true) => FontStyle::bold_italic(),
-            (false, true) => FontStyle::italic(),
-            (true, false) => FontStyle::bold(),
-            (false, false) => FontStyle::normal(),
+    // Bold text stays as far above a picked weight as bold is above regular, up to black
+    fn font_style(&self, bold: bool, italic: bool) -> FontStyle {
+        let weight = match (bold, self.weight) {
+            (false, Some(weight)) => weight,
+            (false, None) => NORMAL_WEIGHT,
+            (true, Some(weight)) => (weight + BOLD_WEIGHT - NORMAL_WEIGHT)
+                .max(BOLD_WEIGHT)
+                .min(BLACK_WEIGHT),
+            (true, None) => BOLD_WEIGHT,
+        };
+        let slant = if italic {
+            Slant::Italic
+        } else {
+            Slant::Upright
         };
+        FontStyle::new(Weight::from(weight), Width::NORMAL, slant)
+    }
+
+    fn load(&mut self, font_key: FontKey) -> Option<FontPair> {
+        let font_style = self.font_style(font_key.bold, font_key.italic);
 
         match font_key.font_selection {
             FontSelection::Name(name) => {
@@ -149,6 +172,20 @@ impl FontLoader {
         }
     }
 
+    /// The families of the fonts loaded so far, each only once.
+    pub fn loaded_families(&self) -> Vec<String> {
+        let mut families: Vec<String> = Vec::new();
+        for (_, font_pair) in self.cache.iter() {
+            if let Some(typeface) = font_pair.skia_font.typeface() {
+                let family = typeface.family_name();
+                if !families.contains(&family) {
+                    families.push(family);
+                }
+            }
+        }
+        families
+    }
+
     pub fn get_or_load(&mut self, font_key: &FontKey) -> Option<Arc<FontPair>> {
         if let Some(cached) = self.cache.get(font_key) {
             return Some(cached.clone());
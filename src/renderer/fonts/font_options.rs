@@ -2,12 +2,37 @@ use super::font_loader::FontSelection;
 
 const DEFAULT_FONT_SIZE: f32 = 14.0;
 
+// The css names of the weights, which fonts usually name their variants after
+fn parse_weight(part: &str) -> Option<i32> {
+    if let Some(weight) = part.strip_prefix('w') {
+        return weight
+            .parse::<i32>()
+            .ok()
+            .filter(|weight| (1..=1000).contains(weight));
+    }
+    let weight = match part.to_lowercase().replace(&['-', ' '][..], "").as_str() {
+        "thin" | "hairline" => 100,
+        "extralight" | "ultralight" => 200,
+        "light" => 300,
+        "regular" | "normal" => 400,
+        "medium" => 500,
+        "semibold" | "demibold" => 600,
+        "bold" => 700,
+        "extrabold" | "ultrabold" => 800,
+        "black" | "heavy" => 900,
+        _ => return None,
+    };
+    Some(weight)
+}
+
 #[derive(Clone, Debug)]
 pub struct FontOptions {
     pub font_list: Vec<String>,
     pub size: f32,
     pub bold: bool,
     pub italic: bool,
+    // The weight of regular text, when it isn't the family's regular one
+    pub weight: Option<i32>,
 }
 
 impl FontOptions {
@@ -16,6 +41,7 @@ impl FontOptions {
         let mut size = DEFAULT_FONT_SIZE;
         let mut bold = false;
         let mut italic = false;
+        let mut weight = None;
 
         let mut parts = guifont_setting.split(':').filter(|part| !part.is_empty());
 
@@ -32,7 +58,10 @@ impl FontOptions {
         }
 
         for part in parts {
-            if part.starts_with('h') && part.len() > 1 {
+            // Weights first, since heavy and hairline start with an h as well
+            if let Some(parsed_weight) = parse_weight(part) {
+                weight = Some(parsed_weight);
+            } else if part.starts_with('h') && part.len() > 1 {
                 if let Ok(parsed_size) = part[1..].parse::<f32>() {
                     size = parsed_size
                 }
@@ -47,6 +76,7 @@ impl FontOptions {
             font_list,
             bold,
             italic,
+            weight,
             size: points_to_pixels(size),
         }
     }
@@ -65,6 +95,7 @@ impl Default for FontOptions {
             font_list: Vec::new(),
             bold: false,
             italic: false,
+            weight: None,
             size: points_to_pixels(DEFAULT_FONT_SIZE),
         }
     }
@@ -76,6 +107,98 @@ impl PartialEq for FontOptions {
             && (self.size - other.size).abs() < std::f32::EPSILON
             && self.bold == other.bold
             && self.italic == other.italic
+            && self.weight == other.weight
+    }
+}
+
+/// Families used for bold and italic text in place of the variants of the guifont family, from
+/// `g:neovide_font_bold`, `g:neovide_font_italic` and `g:neovide_font_bold_italic`. They are
+/// written like guifont, but only the font names count.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct StyleFonts {
+    pub bold: Vec<String>,
+    pub italic: Vec<String>,
+    pub bold_italic: Vec<String>,
+}
+
+impl StyleFonts {
+    pub fn parse(bold: &str, italic: &str, bold_italic: &str) -> StyleFonts {
+        StyleFonts {
+            bold: FontOptions::parse(bold).font_list,
+            italic: FontOptions::parse(italic).font_list,
+            bold_italic: FontOptions::parse(bold_italic).font_list,
+        }
+    }
+
+    /// The fonts to try before those of guifont. Bold italic text without its own family uses
+    /// the bold one, and the italic one without that.
+    pub fn for_style(&self, bold: bool, italic: bool) -> &[String] {
+        match (bold, italic) {
+            (true, true) => [&self.bold_italic, &self.bold, &self.italic]
+                .iter()
+                .find(|fonts| !fonts.is_empty())
+                .map_or(&[][..], |fonts| fonts.as_slice()),
+            (false, true) => &self.italic,
+            (true, false) => &self.bold,
+            (false, false) => &[],
+        }
+    }
+}
+
+// A code point written like U+E0A0
+fn parse_code_point(text: &str) -> Option<u32> {
+    let hex = text.trim().strip_prefix("U+")?;
+    u32::from_str_radix(hex, 16).ok()
+}
+
+/// Fonts picked for ranges of code points ahead of every other font, from
+/// `g:neovide_symbol_map`. The setting is a list like
+/// `U+E000-U+F8FF,U+F0000-U+FFFFD=Symbols Nerd Font;U+4E00-U+9FFF=Noto Sans CJK JP`, where the
+/// first range a character falls in decides its font.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SymbolMap {
+    mappings: Vec<(Vec<(u32, u32)>, String)>,
+}
+
+impl SymbolMap {
+    pub fn parse(symbol_map_setting: &str) -> Result<SymbolMap, String> {
+        let mut mappings = Vec::new();
+        for mapping in symbol_map_setting.split(';') {
+            if mapping.trim().is_empty() {
+                continue;
+            }
+            let (ranges, font_name) = mapping
+                .split_once('=')
+                .ok_or_else(|| format!("{} has no font after =", mapping))?;
+            let ranges = ranges
+                .split(',')
+                .map(|range| {
+                    let (start, end) = range.split_once('-').unwrap_or((range, range));
+                    match (parse_code_point(start), parse_code_point(end)) {
+                        (Some(start), Some(end)) if start <= end => Ok((start, end)),
+                        _ => Err(format!("{} is not a range like U+E000-U+F8FF", range)),
+                    }
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            mappings.push((ranges, font_name.trim().to_owned()));
+        }
+        Ok(SymbolMap { mappings })
+    }
+
+    pub fn font_for(&self, character: char) -> Option<&str> {
+        let code_point = character as u32;
+        self.mappings
+            .iter()
+            .find(|(ranges, _)| {
+                ranges
+                    .iter()
+                    .any(|(start, end)| (*start..=*end).contains(&code_point))
+            })
+            .map(|(_, font_name)| font_name.as_str())
+    }
+
+    pub fn font_names(&self) -> impl Iterator<Item = &String> {
+        self.mappings.iter().map(|(_, font_name)| font_name)
     }
 }
 
@@ -99,3 +222,62 @@ fn points_to_pixels(value: f32) -> f32 {
         value * (pixels_per_inch / points_per_inch)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_weight() {
+        assert_eq!(FontOptions::parse("Iosevka:h13:w350").weight, Some(350));
+        assert_eq!(FontOptions::parse("Iosevka:SemiBold").weight, Some(600));
+        assert_eq!(
+            FontOptions::parse("Iosevka:extra-light:i").weight,
+            Some(200)
+        );
+        assert_eq!(FontOptions::parse("Iosevka:h13:b").weight, None);
+        assert_eq!(FontOptions::parse("Iosevka:w0").weight, None);
+    }
+
+    #[test]
+    fn test_weights_starting_with_h_are_not_sizes() {
+        let heavy = FontOptions::parse("Iosevka:h13:heavy");
+        assert_eq!(heavy.weight, Some(900));
+        assert_eq!(heavy.size, points_to_pixels(13.0));
+        let hairline = FontOptions::parse("Iosevka:hairline");
+        assert_eq!(hairline.weight, Some(100));
+        assert_eq!(hairline.size, points_to_pixels(DEFAULT_FONT_SIZE));
+    }
+
+    #[test]
+    fn test_style_fonts_for_style() {
+        let style_fonts = StyleFonts::parse("Fira Code:h12", "Victor Mono,Cascadia Code", "");
+        assert_eq!(style_fonts.for_style(true, false), ["Fira Code"]);
+        assert_eq!(style_fonts.for_style(true, true), ["Fira Code"]);
+        assert_eq!(
+            style_fonts.for_style(false, true),
+            ["Victor Mono", "Cascadia Code"]
+        );
+        assert!(style_fonts.for_style(false, false).is_empty());
+
+        let style_fonts = StyleFonts::parse("", "Victor Mono", "");
+        assert_eq!(style_fonts.for_style(true, true), ["Victor Mono"]);
+        let style_fonts = StyleFonts::parse("Fira Code", "Victor Mono", "Operator Mono");
+        assert_eq!(style_fonts.for_style(true, true), ["Operator Mono"]);
+    }
+
+    #[test]
+    fn test_symbol_map() {
+        let symbol_map =
+            SymbolMap::parse("U+E000-U+F8FF,U+2665=Symbols Nerd Font; U+4E00-U+9FFF=Noto Sans CJK")
+                .unwrap();
+        assert_eq!(symbol_map.font_for('\u{e0a0}'), Some("Symbols Nerd Font"));
+        assert_eq!(symbol_map.font_for('♥'), Some("Symbols Nerd Font"));
+        assert_eq!(symbol_map.font_for('中'), Some("Noto Sans CJK"));
+        assert_eq!(symbol_map.font_for('a'), None);
+
+        assert_eq!(SymbolMap::parse(""), Ok(SymbolMap::default()));
+        assert!(SymbolMap::parse("U+E000-U+F8FF").is_err());
+        assert!(SymbolMap::parse("E000=Symbols").is_err());
+    }
+}
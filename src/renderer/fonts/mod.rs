@@ -1,4 +1,7 @@
+pub mod bidi;
 pub mod caching_shaper;
 mod font_loader;
 mod font_options;
 mod swash_font;
+
+pub use font_options::StyleFonts;
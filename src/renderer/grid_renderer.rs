@@ -3,20 +3,125 @@ use std::sync::Arc;
 use glutin::dpi::PhysicalSize;
 use log::trace;
 use skia_safe::{colors, dash_path_effect, BlendMode, Canvas, Color, Paint, Rect, HSV};
+use unicode_segmentation::UnicodeSegmentation;
 
+use super::contrast::ensure_contrast;
+use super::fonts::{caching_shaper::ShapeKey, StyleFonts};
 use super::{CachingShaper, RendererSettings};
-use crate::editor::{Colors, Style};
+use crate::editor::{Colors, LineFragment, Style};
 use crate::settings::*;
+use crate::startup_profile::{mark_startup, StartupPhase};
 use crate::utils::Dimensions;
 use crate::window::WindowSettings;
 
+// Neighbouring cells that share a background color, filled with a single rectangle
+#[derive(Debug, PartialEq)]
+struct BackgroundRun {
+    grid_position: (u64, u64),
+    width: u64,
+    color: Color,
+}
+
+// Fragments are split wherever the style changes, but many styles only differ in their
+// foreground, and double width characters split a fragment without changing the style at all
+fn background_runs(
+    line_fragments: &[LineFragment],
+    default_style: &Arc<Style>,
+) -> Vec<BackgroundRun> {
+    let mut runs: Vec<BackgroundRun> = Vec::new();
+    for fragment in line_fragments {
+        let color = fragment
+            .style
+            .as_ref()
+            .unwrap_or(default_style)
+            .background(&default_style.colors)
+            .to_color();
+        let grid_position = (fragment.window_left, fragment.window_top);
+
+        match runs.last_mut() {
+            Some(run)
+                if run.color == color
+                    && run.grid_position.1 == fragment.window_top
+                    && run.grid_position.0 + run.width == fragment.window_left =>
+            {
+                run.width += fragment.width;
+            }
+            _ => runs.push(BackgroundRun {
+                grid_position,
+                width: fragment.width,
+                color,
+            }),
+        }
+    }
+    runs
+}
+
+// The cell after a double width character is left out of the text
+fn is_double_width(text: &str, cell_width: u64) -> bool {
+    text.graphemes(true).count() < cell_width as usize
+}
+
+/// The font settings that are g: variables rather than options, the families for bold and italic
+/// text and the symbol map. They are parsed once when one of them changes.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FontSettings {
+    style_fonts: StyleFonts,
+    symbol_map: String,
+}
+
+impl FontSettings {
+    const SETTING_NAMES: [&'static str; 4] =
+        ["font_bold", "font_italic", "font_bold_italic", "symbol_map"];
+
+    pub fn new(settings: &RendererSettings) -> FontSettings {
+        FontSettings {
+            style_fonts: StyleFonts::parse(
+                &settings.font_bold,
+                &settings.font_italic,
+                &settings.font_bold_italic,
+            ),
+            symbol_map: settings.symbol_map.clone(),
+        }
+    }
+
+    /// Whether a changed setting is one of these, for which they have to be sent to the renderer.
+    pub fn is_font_setting(name: &str) -> bool {
+        FontSettings::SETTING_NAMES.contains(&name)
+    }
+}
+
+/// The settings cells are drawn with, read once per frame rather than for every cell.
+#[derive(Clone, Debug, PartialEq)]
+struct DrawSettings {
+    debug_renderer: bool,
+    // Default background cells are left see through so the background layer or the
+    // transparent window behind them shows
+    see_through_background: bool,
+    floating_opacity: f32,
+    minimum_contrast: f32,
+}
+
+impl DrawSettings {
+    fn new(settings: &RendererSettings, transparent: bool) -> DrawSettings {
+        DrawSettings {
+            debug_renderer: settings.debug_renderer,
+            see_through_background: transparent || settings.has_background_layer(),
+            floating_opacity: settings.floating_opacity,
+            minimum_contrast: settings.minimum_contrast,
+        }
+    }
+}
+
 pub struct GridRenderer {
     pub shaper: CachingShaper,
     pub paint: Paint,
     pub default_style: Arc<Style>,
     pub font_dimensions: Dimensions,
     pub scale_factor: f64,
+    // Scales the font on top of guifont and the os scale factor, set by zooming the window
+    pub font_zoom: f32,
     pub is_ready: bool,
+    draw_settings: DrawSettings,
 }
 
 impl GridRenderer {
@@ -29,6 +134,10 @@ impl GridRenderer {
             Some(colors::BLACK),
             Some(colors::GREY),
         )));
+        let settings = SETTINGS.get::<RendererSettings>();
+        let font_settings = FontSettings::new(&settings);
+        shaper.update_style_fonts(font_settings.style_fonts);
+        shaper.update_symbol_map(&font_settings.symbol_map);
         let font_dimensions: Dimensions = shaper.font_base_dimensions().into();
 
         GridRenderer {
@@ -37,10 +146,20 @@ impl GridRenderer {
             default_style,
             font_dimensions,
             scale_factor,
+            font_zoom: 1.0,
             is_ready: false,
+            draw_settings: DrawSettings::new(
+                &settings,
+                SETTINGS.get::<WindowSettings>().is_transparent(),
+            ),
         }
     }
 
+    /// Picks up the settings cells are drawn with, once at the start of every frame.
+    pub fn update_draw_settings(&mut self, settings: &RendererSettings, transparent: bool) {
+        self.draw_settings = DrawSettings::new(settings, transparent);
+    }
+
     /// Convert PhysicalSize to grid size
     pub fn convert_physical_to_grid(&self, physical: PhysicalSize<u32>) -> Dimensions {
         Dimensions::from(physical) / self.font_dimensions
@@ -52,7 +171,16 @@ impl GridRenderer {
     }
 
     pub fn handle_scale_factor_update(&mut self, scale_factor: f64) {
-        self.shaper.update_scale_factor(scale_factor as f32);
+        self.scale_factor = scale_factor;
+        self.shaper
+            .update_scale_factor(scale_factor as f32 * self.font_zoom);
+        self.update_font_dimensions();
+    }
+
+    pub fn set_font_zoom(&mut self, font_zoom: f32) {
+        self.font_zoom = font_zoom;
+        self.shaper
+            .update_scale_factor(self.scale_factor as f32 * font_zoom);
         self.update_font_dimensions();
     }
 
@@ -61,9 +189,19 @@ impl GridRenderer {
         self.update_font_dimensions();
     }
 
+    pub fn update_wide_font(&mut self, guifontwide_setting: &str) {
+        self.shaper.update_wide_font(guifontwide_setting);
+    }
+
+    pub fn update_font_settings(&mut self, font_settings: FontSettings) {
+        self.shaper.update_style_fonts(font_settings.style_fonts);
+        self.shaper.update_symbol_map(&font_settings.symbol_map);
+    }
+
     fn update_font_dimensions(&mut self) {
         self.font_dimensions = self.shaper.font_base_dimensions().into();
         self.is_ready = true;
+        mark_startup(StartupPhase::FontLoaded);
         trace!("Updated font dimensions: {:?}", self.font_dimensions,);
     }
 
@@ -74,38 +212,68 @@ impl GridRenderer {
         Rect::new(x as f32, y as f32, (x + width) as f32, (y + height) as f32)
     }
 
+    /// Stands in for the default background until neovim sends its own.
+    pub fn set_startup_background(&mut self, color: Color) {
+        let mut style = (*self.default_style).clone();
+        style.colors.background = Some(color.into());
+        self.default_style = Arc::new(style);
+    }
+
     pub fn get_default_background(&self) -> Color {
         self.default_style.colors.background.unwrap().to_color()
     }
 
-    pub fn draw_background(
+    /// Fills the backgrounds of a line, with one rectangle for each run of cells sharing a
+    /// background color rather than one for each fragment.
+    pub fn draw_line_background(
         &mut self,
         canvas: &mut Canvas,
-        grid_position: (u64, u64),
-        cell_width: u64,
-        style: &Option<Arc<Style>>,
+        line_fragments: &[LineFragment],
         is_floating: bool,
     ) {
+        for run in background_runs(line_fragments, &self.default_style) {
+            self.draw_background(canvas, run, is_floating);
+        }
+    }
+
+    /// Shapes the text of the lines ahead of drawing them, all at once so the shaper can spread
+    /// the work over its threads.
+    pub fn prepare_lines<'a>(&mut self, line_fragments: impl Iterator<Item = &'a LineFragment>) {
+        let keys = line_fragments
+            .filter(|fragment| !fragment.text.chars().all(char::is_whitespace))
+            .map(|fragment| {
+                let style = fragment.style.as_ref().unwrap_or(&self.default_style);
+                ShapeKey::new(
+                    fragment.text.clone(),
+                    style.bold,
+                    style.italic,
+                    is_double_width(&fragment.text, fragment.width),
+                    fragment.right_to_left,
+                )
+            })
+            .collect();
+        self.shaper.shape_all(keys);
+    }
+
+    fn draw_background(&mut self, canvas: &mut Canvas, run: BackgroundRun, is_floating: bool) {
         self.paint.set_blend_mode(BlendMode::Src);
 
-        let region = self.compute_text_region(grid_position, cell_width);
-        let style = style.as_ref().unwrap_or(&self.default_style);
+        let region = self.compute_text_region(run.grid_position, run.width);
 
-        if SETTINGS.get::<RendererSettings>().debug_renderer {
+        if self.draw_settings.debug_renderer {
             let random_hsv: HSV = (rand::random::<f32>() * 360.0, 0.3, 0.3).into();
             let random_color = random_hsv.to_color(255);
             self.paint.set_color(random_color);
         } else {
-            self.paint
-                .set_color(style.background(&self.default_style.colors).to_color());
+            self.paint.set_color(run.color);
         }
 
-        if (SETTINGS.get::<WindowSettings>().transparency - 1.0).abs() > f32::EPSILON
+        if self.draw_settings.see_through_background
             && self.paint.color() == self.get_default_background()
         {
             if is_floating {
                 self.paint
-                    .set_alpha((255.0 * SETTINGS.get::<RendererSettings>().floating_opacity) as u8);
+                    .set_alpha((255.0 * self.draw_settings.floating_opacity) as u8);
             } else {
                 self.paint.set_alpha(0);
             }
@@ -120,12 +288,19 @@ impl GridRenderer {
         grid_position: (u64, u64),
         cell_width: u64,
         style: &Option<Arc<Style>>,
+        right_to_left: bool,
     ) {
         let (x, y) = grid_position * self.font_dimensions;
         let width = cell_width * self.font_dimensions.width;
 
         let style = style.as_ref().unwrap_or(&self.default_style);
 
+        // Blank runs without lines through or under them would draw nothing
+        let decorated = style.underline || style.undercurl || style.strikethrough;
+        if !decorated && text.chars().all(char::is_whitespace) {
+            return;
+        }
+
         canvas.save();
 
         // We don't want to clip text in the x position, only the y so we add a buffer of 1
@@ -168,19 +343,24 @@ impl GridRenderer {
 
         let y_adjustment = self.shaper.y_adjustment();
 
-        if SETTINGS.get::<RendererSettings>().debug_renderer {
+        if self.draw_settings.debug_renderer {
             let random_hsv: HSV = (rand::random::<f32>() * 360.0, 1.0, 1.0).into();
             let random_color = random_hsv.to_color(255);
             self.paint.set_color(random_color);
         } else {
-            self.paint
-                .set_color(style.foreground(&self.default_style.colors).to_color());
+            let colors = &self.default_style.colors;
+            self.paint.set_color(ensure_contrast(
+                style.foreground(colors).to_color(),
+                style.background(colors).to_color(),
+                self.draw_settings.minimum_contrast,
+            ));
         }
         self.paint.set_anti_alias(false);
 
+        let double_width = is_double_width(&text, cell_width);
         for blob in self
             .shaper
-            .shape_cached(text, style.bold, style.italic)
+            .shape_cached(text, style.bold, style.italic, double_width, right_to_left)
             .iter()
         {
             canvas.draw_text_blob(blob, (x as f32, (y + y_adjustment) as f32), &self.paint);
@@ -200,3 +380,67 @@ impl GridRenderer {
         canvas.restore();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use skia_safe::Color4f;
+
+    fn fragment(window_left: u64, width: u64, style: &Option<Arc<Style>>) -> LineFragment {
+        LineFragment {
+            text: "a".repeat(width as usize),
+            window_left,
+            window_top: 3,
+            width,
+            style: style.clone(),
+            right_to_left: false,
+        }
+    }
+
+    #[test]
+    fn test_background_runs_join_matching_backgrounds() {
+        let colors = |foreground: Color4f, background: Color4f| {
+            Colors::new(Some(foreground), Some(background), None)
+        };
+        let default_style = Arc::new(Style::new(colors(
+            Color4f::from(Color::WHITE),
+            Color4f::from(Color::BLACK),
+        )));
+        let keyword = Some(Arc::new(Style::new(colors(
+            Color4f::from(Color::YELLOW),
+            Color4f::from(Color::BLACK),
+        ))));
+        let selection = Some(Arc::new(Style::new(colors(
+            Color4f::from(Color::WHITE),
+            Color4f::from(Color::BLUE),
+        ))));
+
+        let line = vec![
+            fragment(0, 2, &None),
+            fragment(2, 3, &keyword),
+            fragment(5, 2, &selection),
+            fragment(7, 2, &selection),
+            fragment(9, 1, &None),
+        ];
+        assert_eq!(
+            background_runs(&line, &default_style),
+            vec![
+                BackgroundRun {
+                    grid_position: (0, 3),
+                    width: 5,
+                    color: Color::BLACK,
+                },
+                BackgroundRun {
+                    grid_position: (5, 3),
+                    width: 4,
+                    color: Color::BLUE,
+                },
+                BackgroundRun {
+                    grid_position: (9, 3),
+                    width: 1,
+                    color: Color::BLACK,
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,96 @@
+use parking_lot::RwLock;
+use skia_safe::Color;
+
+/// The system colors windows asks applications to draw with while a high contrast theme is on.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HighContrastColors {
+    pub background: Color,
+    pub foreground: Color,
+    pub highlight: Color,
+    pub highlight_text: Color,
+}
+
+lazy_static! {
+    static ref HIGH_CONTRAST: RwLock<Option<HighContrastColors>> =
+        RwLock::new(system_high_contrast());
+}
+
+// A COLORREF is laid out as 0x00bbggrr
+#[cfg_attr(not(windows), allow(dead_code))]
+fn colorref_to_color(colorref: u32) -> Color {
+    Color::from_rgb(
+        (colorref & 0xff) as u8,
+        ((colorref >> 8) & 0xff) as u8,
+        ((colorref >> 16) & 0xff) as u8,
+    )
+}
+
+#[cfg(windows)]
+fn system_high_contrast() -> Option<HighContrastColors> {
+    use std::{mem::size_of, ptr::null_mut};
+
+    use winapi::um::winuser::{
+        GetSysColor, SystemParametersInfoW, COLOR_HIGHLIGHT, COLOR_HIGHLIGHTTEXT, COLOR_WINDOW,
+        COLOR_WINDOWTEXT, HCF_HIGHCONTRASTON, HIGHCONTRASTW, SPI_GETHIGHCONTRAST,
+    };
+
+    let mut high_contrast = HIGHCONTRASTW {
+        cbSize: size_of::<HIGHCONTRASTW>() as u32,
+        dwFlags: 0,
+        lpszDefaultScheme: null_mut(),
+    };
+    let succeeded = unsafe {
+        SystemParametersInfoW(
+            SPI_GETHIGHCONTRAST,
+            high_contrast.cbSize,
+            &mut high_contrast as *mut HIGHCONTRASTW as *mut _,
+            0,
+        )
+    };
+    if succeeded == 0 || high_contrast.dwFlags & HCF_HIGHCONTRASTON == 0 {
+        return None;
+    }
+
+    unsafe {
+        Some(HighContrastColors {
+            background: colorref_to_color(GetSysColor(COLOR_WINDOW)),
+            foreground: colorref_to_color(GetSysColor(COLOR_WINDOWTEXT)),
+            highlight: colorref_to_color(GetSysColor(COLOR_HIGHLIGHT)),
+            highlight_text: colorref_to_color(GetSysColor(COLOR_HIGHLIGHTTEXT)),
+        })
+    }
+}
+
+// Other systems have no forced colors, their contrast settings only change the native widgets
+#[cfg(not(windows))]
+fn system_high_contrast() -> Option<HighContrastColors> {
+    None
+}
+
+/// The colors of the active high contrast theme, which the parts neovide draws itself use in
+/// place of their own.
+pub fn high_contrast_colors() -> Option<HighContrastColors> {
+    *HIGH_CONTRAST.read()
+}
+
+/// Checks the system again, and returns whether high contrast mode was switched on or off.
+pub fn refresh_high_contrast() -> bool {
+    let colors = system_high_contrast();
+    let mut current = HIGH_CONTRAST.write();
+    let toggled = current.is_some() != colors.is_some();
+    *current = colors;
+    toggled
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_colorref_to_color() {
+        assert_eq!(
+            colorref_to_color(0x00ff8000),
+            Color::from_rgb(0x00, 0x80, 0xff)
+        );
+    }
+}
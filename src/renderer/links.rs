@@ -0,0 +1,337 @@
+use crate::editor::LineFragment;
+
+// Also the only schemes that are opened, so a link can't run a local file or another program
+const URL_SCHEMES: [&str; 3] = ["https://", "http://", "mailto:"];
+
+/// A link in a row of a grid, covering the columns from `start` up to but not including `end`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Link {
+    pub url: String,
+    pub row: u64,
+    pub start: u64,
+    pub end: u64,
+}
+
+// Punctuation right after a link usually belongs to the sentence around it
+fn trim_url_end(url: &str) -> &str {
+    let mut url = url.trim_end_matches(|character| ".,;:!?'\"".contains(character));
+    // Parentheses are only part of the url when they are balanced, like in wikipedia links
+    while url.ends_with(')') && url.matches('(').count() < url.matches(')').count() {
+        url = &url[..url.len() - 1];
+        url = url.trim_end_matches(|character| ".,;:!?'\"".contains(character));
+    }
+    url
+}
+
+fn is_url_character(character: char) -> bool {
+    !character.is_whitespace() && !"<>\"`{}|\\^".contains(character)
+}
+
+// Finds the urls in the text by their scheme, returning the range of characters they span
+fn detect_urls(text: &[char]) -> Vec<(usize, usize)> {
+    let schemes: Vec<Vec<char>> = URL_SCHEMES
+        .iter()
+        .map(|scheme| scheme.chars().collect())
+        .collect();
+    let mut urls = Vec::new();
+    let mut index = 0;
+    while index < text.len() {
+        let scheme = schemes
+            .iter()
+            .find(|scheme| text[index..].starts_with(scheme));
+        let scheme_length = match scheme {
+            Some(scheme) => scheme.len(),
+            None => {
+                index += 1;
+                continue;
+            }
+        };
+
+        let end = text[index..]
+            .iter()
+            .position(|character| !is_url_character(*character))
+            .map_or(text.len(), |length| index + length);
+        let candidate: String = text[index..end].iter().collect();
+        let trimmed_length = trim_url_end(&candidate).chars().count();
+        if trimmed_length > scheme_length {
+            urls.push((index, index + trimmed_length));
+        }
+        index = end.max(index + 1);
+    }
+    urls
+}
+
+/// The links in a row drawn by neovim: those it marked with the url highlight attribute and,
+/// when `detect` is set, anything else in the text that looks like a url.
+pub fn find_links(line_fragments: &[LineFragment], detect: bool) -> Vec<Link> {
+    let mut links: Vec<Link> = Vec::new();
+    let row = match line_fragments.first() {
+        Some(fragment) => fragment.window_top,
+        None => return links,
+    };
+
+    for fragment in line_fragments {
+        let url = match fragment.style.as_ref().and_then(|style| style.url.as_ref()) {
+            Some(url) => url,
+            None => continue,
+        };
+        let end = fragment.window_left + fragment.width;
+        match links.last_mut() {
+            // A link drawn in several highlights is split into fragments, which are joined again
+            Some(link) if link.url == *url && link.end == fragment.window_left => link.end = end,
+            _ => links.push(Link {
+                url: url.clone(),
+                row,
+                start: fragment.window_left,
+                end,
+            }),
+        }
+    }
+
+    if detect {
+        // Each character is taken to fill one cell, so urls after double width text may be off
+        let mut characters = Vec::new();
+        let mut columns = Vec::new();
+        for fragment in line_fragments {
+            for (offset, character) in fragment.text.chars().enumerate() {
+                characters.push(character);
+                columns.push(fragment.window_left + offset as u64);
+            }
+        }
+
+        for (start, end) in detect_urls(&characters) {
+            let start_column = columns[start];
+            let end_column = columns[end - 1] + 1;
+            let already_linked = links
+                .iter()
+                .any(|link| link.start < end_column && start_column < link.end);
+            if !already_linked {
+                links.push(Link {
+                    url: characters[start..end].iter().collect(),
+                    row,
+                    start: start_column,
+                    end: end_column,
+                });
+            }
+        }
+    }
+
+    links
+}
+
+/// The links of every row of a grid, kept in line with the grid as it scrolls like the minimap
+/// rows.
+pub struct GridLinks {
+    rows: Vec<Vec<Link>>,
+}
+
+impl GridLinks {
+    pub fn new() -> GridLinks {
+        GridLinks { rows: Vec::new() }
+    }
+
+    pub fn draw_line(&mut self, line_fragments: &[LineFragment], detect: bool) {
+        let row = match line_fragments.first() {
+            Some(fragment) => fragment.window_top as usize,
+            None => return,
+        };
+
+        if self.rows.len() <= row {
+            self.rows.resize(row + 1, Vec::new());
+        }
+        self.rows[row] = find_links(line_fragments, detect);
+    }
+
+    pub fn scroll(&mut self, top: u64, bottom: u64, rows: i64) {
+        let bottom = (bottom as usize).min(self.rows.len());
+        let top = top as usize;
+        if top >= bottom {
+            return;
+        }
+
+        let region = &mut self.rows[top..bottom];
+        let amount = (rows.abs() as usize).min(region.len());
+        if rows > 0 {
+            region.rotate_left(amount);
+        } else {
+            region.rotate_right(amount);
+        }
+        // The links moved along with their rows
+        for (index, links) in self.rows[top..bottom].iter_mut().enumerate() {
+            for link in links.iter_mut() {
+                link.row = (top + index) as u64;
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.rows.clear();
+    }
+
+    pub fn link_at(&self, column: u64, row: u64) -> Option<&Link> {
+        self.rows
+            .get(row as usize)?
+            .iter()
+            .find(|link| link.start <= column && column < link.end)
+    }
+}
+
+// The program that opens urls, which gets the url as its only argument. It never goes through a
+// shell, which would run whatever follows a & or | in the url.
+#[cfg(not(windows))]
+fn open_command(url: &str) -> std::process::Command {
+    let program = if cfg!(target_os = "macos") {
+        "open"
+    } else {
+        "xdg-open"
+    };
+    let mut command = std::process::Command::new(program);
+    command.arg(url);
+    command
+}
+
+#[cfg(windows)]
+fn shell_execute(url: &str) -> Result<(), String> {
+    use std::{ffi::OsStr, iter::once, os::windows::ffi::OsStrExt, ptr::null_mut};
+
+    use winapi::um::{shellapi::ShellExecuteW, winuser::SW_SHOWNORMAL};
+
+    let wide = |text: &str| -> Vec<u16> { OsStr::new(text).encode_wide().chain(once(0)).collect() };
+    let (verb, file) = (wide("open"), wide(url));
+    let result = unsafe {
+        ShellExecuteW(
+            null_mut(),
+            verb.as_ptr(),
+            file.as_ptr(),
+            null_mut(),
+            null_mut(),
+            SW_SHOWNORMAL,
+        )
+    };
+    // Values above 32 mean success, anything else is an error code
+    if result as isize > 32 {
+        Ok(())
+    } else {
+        Err(format!("ShellExecute failed with {}", result as isize))
+    }
+}
+
+/// Opens the link with whatever the system uses for urls, usually the browser.
+fn is_openable(url: &str) -> bool {
+    let url = url.to_ascii_lowercase();
+    URL_SCHEMES.iter().any(|scheme| url.starts_with(scheme))
+}
+
+pub fn open_url(url: &str) {
+    if !is_openable(url) {
+        log::warn!("Not opening {}, only http, https and mailto links are", url);
+        return;
+    }
+
+    #[cfg(windows)]
+    let result = shell_execute(url);
+    #[cfg(not(windows))]
+    let result = open_command(url)
+        .spawn()
+        .map(|_| ())
+        .map_err(|error| error.to_string());
+
+    if let Err(error) = result {
+        log::error!("Could not open {}: {}", url, error);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::editor::{Colors, Style};
+
+    fn fragment(text: &str, window_left: u64, url: Option<&str>) -> LineFragment {
+        let style = url.map(|url| {
+            let mut style = Style::new(Colors::new(None, None, None));
+            style.url = Some(url.to_owned());
+            Arc::new(style)
+        });
+        LineFragment {
+            text: text.to_owned(),
+            window_left,
+            window_top: 2,
+            width: text.chars().count() as u64,
+            style,
+            right_to_left: false,
+        }
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn test_urls_are_not_run_through_a_shell() {
+        let url = "http://x&calc.exe|rm";
+        let command = open_command(url);
+        let program = command.get_program().to_string_lossy().into_owned();
+        assert!(!["sh", "bash", "cmd"].contains(&program.as_str()));
+        let arguments: Vec<_> = command.get_args().collect();
+        assert_eq!(arguments, vec![std::ffi::OsStr::new(url)]);
+    }
+
+    #[test]
+    fn test_only_web_and_mail_links_are_openable() {
+        assert!(is_openable("https://neovide.dev"));
+        assert!(is_openable("HTTP://neovide.dev"));
+        assert!(is_openable("mailto:someone@neovide.dev"));
+        assert!(!is_openable("file:///C:/Windows/System32/calc.exe"));
+        assert!(!is_openable("javascript:alert(1)"));
+        assert!(!is_openable("/usr/bin/xterm"));
+    }
+
+    #[test]
+    fn test_detect_urls() {
+        let text: Vec<char> =
+            "see https://neovide.dev/faq. or (http://a.b/c_(d)) or http:// file:///a"
+                .chars()
+                .collect();
+        let urls: Vec<String> = detect_urls(&text)
+            .into_iter()
+            .map(|(start, end)| text[start..end].iter().collect())
+            .collect();
+        assert_eq!(urls, vec!["https://neovide.dev/faq", "http://a.b/c_(d)"]);
+    }
+
+    #[test]
+    fn test_find_links_joins_url_fragments() {
+        let fragments = vec![
+            fragment("docs ", 0, None),
+            fragment("here", 5, Some("https://neovide.dev")),
+            fragment(" too", 9, Some("https://neovide.dev")),
+            fragment(" https://github.com", 13, None),
+        ];
+
+        let links = find_links(&fragments, false);
+        assert_eq!(
+            links,
+            vec![Link {
+                url: "https://neovide.dev".to_owned(),
+                row: 2,
+                start: 5,
+                end: 13,
+            }]
+        );
+
+        let links = find_links(&fragments, true);
+        assert_eq!(links.len(), 2);
+        assert_eq!(links[1].url, "https://github.com");
+        assert_eq!((links[1].start, links[1].end), (14, 32));
+    }
+
+    #[test]
+    fn test_links_follow_scrolling() {
+        let mut grid_links = GridLinks::new();
+        grid_links.draw_line(&[fragment("https://neovide.dev", 0, None)], true);
+        assert!(grid_links.link_at(3, 2).is_some());
+
+        grid_links.scroll(0, 3, 1);
+        assert!(grid_links.link_at(3, 2).is_none());
+        assert_eq!(grid_links.link_at(3, 1).map(|link| link.row), Some(1));
+    }
+}
@@ -0,0 +1,98 @@
+use skia_safe::{Canvas, Color, Font, Paint, PaintStyle, Rect, Typeface};
+
+const MESSAGE: &str = "Waiting for Neovim";
+const FONT_SIZE: f32 = 14.0;
+const SPINNER_RADIUS: f32 = 12.0;
+const SPINNER_STROKE: f32 = 3.0;
+const SPINNER_SWEEP: f32 = 270.0;
+// Turns per second
+const SPINNER_SPEED: f32 = 1.0;
+const GAP: f32 = 16.0;
+
+// Grey that stands out from the startup background, whether it is light or dark
+fn indicator_color(background: Color) -> Color {
+    let luminance = 0.299 * background.r() as f32
+        + 0.587 * background.g() as f32
+        + 0.114 * background.b() as f32;
+    if luminance < 128.0 {
+        Color::from_rgb(180, 180, 180)
+    } else {
+        Color::from_rgb(90, 90, 90)
+    }
+}
+
+/// A spinner and a short message in the middle of the window, shown while neovim hasn't drawn
+/// anything yet, like during a slow init.lua or while attaching to a remote server. With reduced
+/// motion only the message is shown.
+pub struct LoadingIndicator {
+    elapsed: f32,
+    font: Font,
+}
+
+impl LoadingIndicator {
+    pub fn new() -> LoadingIndicator {
+        LoadingIndicator {
+            elapsed: 0.0,
+            font: Font::new(Typeface::default(), FONT_SIZE),
+        }
+    }
+
+    pub fn draw(
+        &mut self,
+        canvas: &mut Canvas,
+        bounds: Rect,
+        background: Color,
+        spin: bool,
+        dt: f32,
+    ) {
+        self.elapsed += dt;
+
+        let mut paint = Paint::default();
+        paint.set_anti_alias(true);
+        paint.set_color(indicator_color(background));
+
+        let center_x = bounds.center_x();
+        let center_y = bounds.center_y();
+
+        if spin {
+            let oval = Rect::from_xywh(
+                center_x - SPINNER_RADIUS,
+                center_y - SPINNER_RADIUS - GAP,
+                2.0 * SPINNER_RADIUS,
+                2.0 * SPINNER_RADIUS,
+            );
+            let start_angle = (self.elapsed * SPINNER_SPEED * 360.0) % 360.0;
+            paint.set_style(PaintStyle::Stroke);
+            paint.set_stroke_width(SPINNER_STROKE);
+            canvas.draw_arc(oval, start_angle, SPINNER_SWEEP, false, &paint);
+            paint.set_style(PaintStyle::Fill);
+        }
+
+        let (text_width, _) = self.font.measure_str(MESSAGE, Some(&paint));
+        let text_y = if spin {
+            center_y + SPINNER_RADIUS + FONT_SIZE
+        } else {
+            center_y + FONT_SIZE / 2.0
+        };
+        canvas.draw_str(
+            MESSAGE,
+            (center_x - text_width / 2.0, text_y),
+            &self.font,
+            &paint,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_indicator_color_contrasts_background() {
+        assert_eq!(
+            indicator_color(Color::BLACK),
+            Color::from_rgb(180, 180, 180)
+        );
+        assert_eq!(indicator_color(Color::WHITE), Color::from_rgb(90, 90, 90));
+    }
+}
@@ -0,0 +1,258 @@
+use std::collections::HashMap;
+
+use skia_safe::{Canvas, Color, Paint, Rect};
+
+use super::scrollbar::ScrollbarDetails;
+use super::{high_contrast_colors, GridRenderer, RendererSettings};
+use crate::editor::LineFragment;
+
+const MAX_LINE_HEIGHT: f32 = 3.0;
+const BLOCK_ALPHA: u8 = 180;
+
+// A run of non whitespace characters drawn as a single colored bar
+#[derive(Clone, Debug, PartialEq)]
+struct MinimapBlock {
+    column: u64,
+    width: u64,
+    color: Color,
+}
+
+fn build_blocks(
+    line_fragments: &[LineFragment],
+    grid_renderer: &GridRenderer,
+) -> Vec<MinimapBlock> {
+    let mut blocks = Vec::new();
+
+    for fragment in line_fragments {
+        let style = fragment
+            .style
+            .as_ref()
+            .unwrap_or(&grid_renderer.default_style);
+        let color = style
+            .foreground(&grid_renderer.default_style.colors)
+            .to_color();
+
+        let mut run_start = None;
+        let mut column = fragment.window_left;
+        for character in fragment.text.chars().chain(std::iter::once(' ')) {
+            match (character.is_whitespace(), run_start) {
+                (false, None) => run_start = Some(column),
+                (true, Some(start)) => {
+                    blocks.push(MinimapBlock {
+                        column: start,
+                        width: column - start,
+                        color,
+                    });
+                    run_start = None;
+                }
+                _ => {}
+            }
+            column += 1;
+        }
+    }
+
+    blocks
+}
+
+/// A scaled down outline of a window's buffer built from the grid lines neovim has drawn. Lines
+/// are remembered by their buffer line as the window scrolls, so the parts of the buffer that
+/// have been on screen fill in over time.
+pub struct Minimap {
+    rows: Vec<Vec<MinimapBlock>>,
+    lines: HashMap<u64, Vec<MinimapBlock>>,
+    dirty: bool,
+
+    window_handle: u64,
+    top_line: u64,
+    bottom_line: u64,
+    line_count: Option<f64>,
+}
+
+impl Minimap {
+    pub fn new() -> Minimap {
+        Minimap {
+            rows: Vec::new(),
+            lines: HashMap::new(),
+            dirty: false,
+
+            window_handle: 0,
+            top_line: 0,
+            bottom_line: 0,
+            line_count: None,
+        }
+    }
+
+    pub fn draw_line(&mut self, line_fragments: &[LineFragment], grid_renderer: &GridRenderer) {
+        let row = match line_fragments.first() {
+            Some(fragment) => fragment.window_top as usize,
+            None => return,
+        };
+
+        if self.rows.len() <= row {
+            self.rows.resize(row + 1, Vec::new());
+        }
+        self.rows[row] = build_blocks(line_fragments, grid_renderer);
+        self.dirty = true;
+    }
+
+    // Mirrors the grid scroll so rows keep lining up with the lines neovim redraws afterwards
+    pub fn scroll(&mut self, top: u64, bottom: u64, rows: i64) {
+        let bottom = (bottom as usize).min(self.rows.len());
+        let top = top as usize;
+        if top >= bottom {
+            return;
+        }
+
+        let region = &mut self.rows[top..bottom];
+        let amount = (rows.abs() as usize).min(region.len());
+        if rows > 0 {
+            region.rotate_left(amount);
+        } else {
+            region.rotate_right(amount);
+        }
+        self.dirty = true;
+    }
+
+    pub fn clear(&mut self) {
+        self.rows.clear();
+        self.dirty = true;
+    }
+
+    pub fn update_viewport(
+        &mut self,
+        window_handle: u64,
+        top_line: f64,
+        bottom_line: f64,
+        line_count: Option<f64>,
+    ) {
+        // Lines shift around when the buffer grows or shrinks, which makes everything
+        // remembered from outside the viewport unreliable
+        if self.line_count != line_count {
+            self.lines.clear();
+        }
+
+        self.window_handle = window_handle;
+        self.top_line = top_line as u64;
+        self.bottom_line = bottom_line as u64;
+        self.line_count = line_count;
+        self.dirty = true;
+    }
+
+    fn commit_rows(&mut self) {
+        if self.dirty {
+            let visible_rows = (self.bottom_line - self.top_line.min(self.bottom_line)) as usize;
+            for (row, blocks) in self.rows.iter().take(visible_rows).enumerate() {
+                self.lines
+                    .insert(self.top_line + row as u64, blocks.clone());
+            }
+            self.dirty = false;
+        }
+    }
+
+    pub fn draw(
+        &mut self,
+        root_canvas: &mut Canvas,
+        settings: &RendererSettings,
+        default_background: Color,
+        grid_width: u64,
+        pixel_region: Rect,
+    ) -> Option<ScrollbarDetails> {
+        self.commit_rows();
+
+        let line_count = self.line_count?;
+        if line_count <= 0.0 || grid_width == 0 {
+            return None;
+        }
+
+        let width = settings.minimap_width.min(pixel_region.width()).max(1.0);
+        let column_width = width / grid_width as f32;
+        let line_height = (pixel_region.height() / line_count as f32)
+            .min(column_width * 2.0)
+            .min(MAX_LINE_HEIGHT);
+        let track = Rect::from_xywh(
+            pixel_region.right - width,
+            pixel_region.top,
+            width,
+            line_height * line_count as f32,
+        );
+
+        let mut paint = Paint::default();
+        paint.set_anti_alias(false);
+
+        paint.set_color(default_background.with_a(220));
+        root_canvas.draw_rect(
+            Rect::new(
+                track.left,
+                pixel_region.top,
+                track.right,
+                pixel_region.bottom,
+            ),
+            &paint,
+        );
+
+        for (line, blocks) in self.lines.iter() {
+            let y = track.top + *line as f32 * line_height;
+            for block in blocks {
+                paint.set_color(block.color.with_a(BLOCK_ALPHA));
+                root_canvas.draw_rect(
+                    Rect::from_xywh(
+                        track.left + block.column as f32 * column_width,
+                        y,
+                        block.width as f32 * column_width,
+                        line_height.max(1.0),
+                    ),
+                    &paint,
+                );
+            }
+        }
+
+        let viewport_color = match high_contrast_colors() {
+            Some(colors) => colors.highlight.with_a(120),
+            None => Color::from_argb(50, 200, 200, 200),
+        };
+        paint.set_color(viewport_color);
+        root_canvas.draw_rect(
+            Rect::from_xywh(
+                track.left,
+                track.top + self.top_line as f32 * line_height,
+                width,
+                (self.bottom_line - self.top_line.min(self.bottom_line)) as f32 * line_height,
+            ),
+            &paint,
+        );
+
+        Some(ScrollbarDetails {
+            window_handle: self.window_handle,
+            track,
+            line_count,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scroll_rotates_rows() {
+        let block = |column| MinimapBlock {
+            column,
+            width: 1,
+            color: Color::WHITE,
+        };
+        let mut minimap = Minimap::new();
+        minimap.rows = vec![vec![block(0)], vec![block(1)], vec![block(2)]];
+
+        minimap.scroll(0, 3, 1);
+        assert_eq!(
+            minimap.rows,
+            vec![vec![block(1)], vec![block(2)], vec![block(0)]]
+        );
+
+        minimap.scroll(0, 3, -1);
+        assert_eq!(
+            minimap.rows,
+            vec![vec![block(0)], vec![block(1)], vec![block(2)]]
+        );
+    }
+}
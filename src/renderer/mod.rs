@@ -1,47 +1,173 @@
 pub mod animation_utils;
+mod background_image;
+mod banner;
+mod contrast;
 pub mod cursor_renderer;
+mod custom_shader;
+mod decorations;
+mod export;
 mod fonts;
 pub mod grid_renderer;
+mod high_contrast;
+mod links;
+mod loading_indicator;
+mod minimap;
+mod overscroll;
+mod popup_menu;
+mod profiler;
+mod reduce_motion;
 mod rendered_window;
+mod scrollbar;
+mod unfocused;
+mod window_animation;
 
 use crate::WindowSettings;
 use std::cmp::Ordering;
-use std::collections::{hash_map::Entry, HashMap};
-use std::sync::mpsc::Receiver;
+use std::collections::{hash_map::Entry, HashMap, VecDeque};
 use std::sync::Arc;
+use std::time::Instant;
 
 use log::error;
-use skia_safe::Canvas;
+use skia_safe::{canvas::SaveLayerRec, Canvas, Paint, Point, Rect};
 
 use crate::bridge::EditorMode;
-use crate::editor::{DrawCommand, WindowDrawCommand};
+use crate::editor::{DrawCommand, FlushedDrawCommands, WindowDrawCommand};
+use crate::redraw_scheduler::REDRAW_SCHEDULER;
 use crate::settings::*;
+use crate::window::PowerSavingMode;
+use background_image::BackgroundImage;
+use banner::Banner;
 use cursor_renderer::CursorRenderer;
+use custom_shader::{CustomShader, ShaderTarget};
+use fonts::bidi::reorder_line;
 pub use fonts::caching_shaper::CachingShaper;
-pub use grid_renderer::GridRenderer;
+pub use grid_renderer::{FontSettings, GridRenderer};
+pub use high_contrast::{high_contrast_colors, refresh_high_contrast, HighContrastColors};
+pub use links::{open_url, Link};
+use loading_indicator::LoadingIndicator;
+use popup_menu::PopupMenuRenderer;
+pub use profiler::{Profiler, ProfilerPhase};
+use reduce_motion::system_prefers_reduced_motion;
+pub use reduce_motion::{detect_reduced_motion, system_reduce_motion, ReduceMotion};
 pub use rendered_window::{RenderedWindow, WindowDrawDetails};
+pub use scrollbar::ScrollbarDetails;
+use unfocused::unfocused_filter;
 
 #[derive(SettingGroup, Clone)]
 pub struct RendererSettings {
+    reduce_motion: ReduceMotion,
+    detect_urls: bool,
     position_animation_length: f32,
     scroll_animation_length: f32,
+    scroll_animation_easing: String,
+    scroll_animation_far_lines: u32,
+    elastic_overscroll: bool,
     floating_opacity: f32,
     floating_blur: bool,
+    floating_blend_blur: f32,
+    floating_shadow: bool,
+    floating_shadow_blur: f32,
+    floating_shadow_opacity: f32,
+    floating_corner_radius: f32,
+    scrollbar: bool,
+    scrollbar_width: f32,
+    scrollbar_fade_delay: f32,
+    window_animations: bool,
+    window_animation_style: String,
+    window_animation_length: f32,
+    background_image: String,
+    background_image_fit: String,
+    background_image_opacity: f32,
+    background_image_blur: f32,
+    shader: String,
+    shader_target: String,
+    minimap: bool,
+    minimap_width: f32,
     debug_renderer: bool,
+    profiler: bool,
+    glyph_cache_size: u32,
+    font_bold: String,
+    font_italic: String,
+    font_bold_italic: String,
+    symbol_map: String,
+    minimum_contrast: f32,
+    bidi: bool,
+    unfocused_dim: f32,
+    unfocused_desaturate: f32,
+    power_saving: PowerSavingMode,
 }
 
 impl Default for RendererSettings {
     fn default() -> Self {
         Self {
+            reduce_motion: ReduceMotion::Auto,
+            detect_urls: false,
             position_animation_length: 0.15,
             scroll_animation_length: 0.3,
+            scroll_animation_easing: "out_expo".to_owned(),
+            scroll_animation_far_lines: 0,
+            elastic_overscroll: true,
             floating_opacity: 0.7,
             floating_blur: true,
+            floating_blend_blur: 0.0,
+            floating_shadow: false,
+            floating_shadow_blur: 8.0,
+            floating_shadow_opacity: 0.5,
+            floating_corner_radius: 0.0,
+            scrollbar: false,
+            scrollbar_width: 6.0,
+            scrollbar_fade_delay: 1.0,
+            window_animations: false,
+            window_animation_style: "fade".to_owned(),
+            window_animation_length: 0.15,
+            background_image: "".to_owned(),
+            background_image_fit: "fill".to_owned(),
+            background_image_opacity: 0.2,
+            background_image_blur: 0.0,
+            shader: "".to_owned(),
+            shader_target: "background".to_owned(),
+            minimap: false,
+            minimap_width: 80.0,
             debug_renderer: false,
+            profiler: false,
+            glyph_cache_size: 32,
+            font_bold: "".to_owned(),
+            font_italic: "".to_owned(),
+            font_bold_italic: "".to_owned(),
+            symbol_map: "".to_owned(),
+            minimum_contrast: 1.0,
+            bidi: false,
+            unfocused_dim: 0.0,
+            unfocused_desaturate: 0.0,
+            power_saving: PowerSavingMode::Auto,
         }
     }
 }
 
+impl RendererSettings {
+    /// Whether the cursor, scrolling, window animations and cursor effects jump straight to
+    /// where they end up, because of `g:neovide_reduce_motion`, or unless it is v:false the os
+    /// preference or saving power.
+    pub fn reduce_motion(&self) -> bool {
+        self.reduce_motion
+            .reduces_motion(|| system_prefers_reduced_motion() || self.saves_power())
+    }
+
+    /// Whether frames are capped, animations and cursor effects turned off and frames only
+    /// drawn when something changed, because of `g:neovide_power_saving` or running on battery.
+    pub fn saves_power(&self) -> bool {
+        self.power_saving.saves_power()
+    }
+
+    /// Whether something is painted beneath the windows which their default background would
+    /// otherwise cover up.
+    pub fn has_background_layer(&self) -> bool {
+        !self.background_image.is_empty()
+            || (!self.shader.is_empty()
+                && ShaderTarget::from_name(&self.shader_target) == ShaderTarget::Background)
+    }
+}
+
 pub struct Renderer {
     cursor_renderer: CursorRenderer,
     pub grid_renderer: GridRenderer,
@@ -49,21 +175,41 @@ pub struct Renderer {
 
     rendered_windows: HashMap<u64, RenderedWindow>,
     pub window_regions: Vec<WindowDrawDetails>,
+    pub profiler: Profiler,
+    background_image: BackgroundImage,
+    custom_shader: CustomShader,
+    /// Where the grid starts within the window. Anything drawn by the window itself, such as a
+    /// custom titlebar or the padding, lives outside of it.
+    pub content_origin: Point,
+    /// Scales the drawn grid while a pinch gesture is in progress. The font size is only changed
+    /// once the gesture ends.
+    pub zoom: f32,
+    /// The link under the mouse pointer and the grid it is in, which is underlined.
+    pub hovered_link: Option<(u64, Link)>,
+    popup_menu: PopupMenuRenderer,
+    loading_indicator: LoadingIndicator,
+    pub banner: Banner,
+    /// Whether neovim flushed at least once, which is when there is something to show.
+    pub flushed: bool,
+    /// When neovim flushed what the last frame drew, if it drew anything new of neovim's.
+    pub frame_flush: Option<Instant>,
+    /// Whether neovide has the focus. Otherwise the grid is dimmed or desaturated if asked for.
+    pub focused: bool,
 
-    pub batched_draw_command_receiver: Receiver<Vec<DrawCommand>>,
+    pub flushed_draw_commands: FlushedDrawCommands,
 }
 
 impl Renderer {
-    pub fn new(
-        batched_draw_command_receiver: Receiver<Vec<DrawCommand>>,
-        scale_factor: f64,
-    ) -> Self {
+    pub fn new(flushed_draw_commands: FlushedDrawCommands, scale_factor: f64) -> Self {
         let cursor_renderer = CursorRenderer::new();
         let grid_renderer = GridRenderer::new(scale_factor);
         let current_mode = EditorMode::Unknown(String::from(""));
 
         let rendered_windows = HashMap::new();
         let window_regions = Vec::new();
+        let profiler = Profiler::new();
+        let background_image = BackgroundImage::new();
+        let custom_shader = CustomShader::new();
 
         Renderer {
             rendered_windows,
@@ -71,7 +217,19 @@ impl Renderer {
             grid_renderer,
             current_mode,
             window_regions,
-            batched_draw_command_receiver,
+            profiler,
+            background_image,
+            custom_shader,
+            content_origin: Point::new(0.0, 0.0),
+            zoom: 1.0,
+            hovered_link: None,
+            popup_menu: PopupMenuRenderer::new(),
+            loading_indicator: LoadingIndicator::new(),
+            banner: Banner::new(),
+            flushed: false,
+            frame_flush: None,
+            focused: true,
+            flushed_draw_commands,
         }
     }
 
@@ -79,34 +237,79 @@ impl Renderer {
     ///
     /// # Returns
     /// `bool` indicating whether or not font was changed during this frame.
-    #[allow(clippy::needless_collect)]
     pub fn draw_frame(&mut self, root_canvas: &mut Canvas, dt: f32) -> bool {
-        let draw_commands: Vec<_> = self
-            .batched_draw_command_receiver
-            .try_iter() // Iterator of Vec of DrawCommand
-            .map(|batch| batch.into_iter()) // Iterator of Iterator of DrawCommand
-            .flatten() // Iterator of DrawCommand
-            .collect();
+        profile_scope!("draw frame");
+        self.profiler.begin_frame(dt);
+
+        let (draw_commands, last_flush) = self.flushed_draw_commands.take();
+        let settings = SETTINGS.get::<RendererSettings>();
+        let background_alpha = { SETTINGS.get::<WindowSettings>().background_alpha() };
+        self.grid_renderer
+            .update_draw_settings(&settings, background_alpha < 255);
         let mut font_changed = false;
+        // Batches are only sent on flush
+        self.flushed |= !draw_commands.is_empty();
+        self.frame_flush = last_flush.filter(|_| !draw_commands.is_empty());
 
-        for draw_command in draw_commands.into_iter() {
+        let mut draw_commands: VecDeque<DrawCommand> = if settings.bidi {
+            draw_commands
+                .into_iter()
+                .map(reorder_draw_command)
+                .collect()
+        } else {
+            draw_commands.into()
+        };
+        self.prepare_lines(draw_commands.iter());
+        while let Some(draw_command) = draw_commands.pop_front() {
             if let DrawCommand::FontChanged(_) = draw_command {
                 font_changed = true;
             }
-            self.handle_draw_command(root_canvas, draw_command);
+            let shaping_changed = changes_shaping(&draw_command);
+            self.handle_draw_command(root_canvas, &settings, draw_command);
+            if shaping_changed {
+                self.prepare_lines(draw_commands.iter());
+            }
         }
+        self.profiler.mark(ProfilerPhase::Parse);
 
         let default_background = self.grid_renderer.get_default_background();
         let font_dimensions = self.grid_renderer.font_dimensions;
 
-        let transparency = { SETTINGS.get::<WindowSettings>().transparency };
-        root_canvas.clear(default_background.with_a((255.0 * transparency) as u8));
-        root_canvas.save();
-        root_canvas.reset_matrix();
+        // Overlay shaders post process the frame, so it is drawn offscreen for them first
+        let mut frame_surface = self
+            .custom_shader
+            .take_frame_surface(root_canvas, &settings);
+        let canvas = match &mut frame_surface {
+            Some(surface) => surface.canvas(),
+            None => &mut *root_canvas,
+        };
+
+        canvas.clear(default_background.with_a(background_alpha));
+        canvas.save();
+        canvas.reset_matrix();
+
+        let canvas_size = canvas.base_layer_size();
+        let canvas_bounds = Rect::from_wh(canvas_size.width as f32, canvas_size.height as f32);
+        self.background_image.draw(canvas, &settings, canvas_bounds);
+        self.custom_shader
+            .draw_background(canvas, &settings, canvas_bounds);
+        if !self.flushed {
+            self.loading_indicator.draw(
+                canvas,
+                canvas_bounds,
+                default_background,
+                !settings.reduce_motion(),
+                dt,
+            );
+            // Keeps the spinner turning, since nothing else asks for frames yet
+            REDRAW_SCHEDULER.queue_next_frame();
+        }
+        canvas.translate(self.content_origin);
+        canvas.scale((self.zoom, self.zoom));
 
         if let Some(root_window) = self.rendered_windows.get(&1) {
             let clip_rect = root_window.pixel_region(font_dimensions);
-            root_canvas.clip_rect(&clip_rect, None, Some(false));
+            canvas.clip_rect(&clip_rect, None, Some(false));
         }
 
         let windows: Vec<&mut RenderedWindow> = {
@@ -116,7 +319,7 @@ impl Renderer {
             ) = self
                 .rendered_windows
                 .values_mut()
-                .filter(|window| !window.hidden)
+                .filter(|window| !window.hidden && !window.external)
                 .partition(|window| window.floating_order.is_none());
 
             root_windows
@@ -130,46 +333,287 @@ impl Renderer {
                 .collect()
         };
 
-        let settings = SETTINGS.get::<RendererSettings>();
+        // Everything drawn on the grid goes through the filter, up to the popup menu
+        let unfocused_filter = if self.focused {
+            None
+        } else {
+            unfocused_filter(settings.unfocused_dim, settings.unfocused_desaturate)
+        };
+        if let Some(filter) = &unfocused_filter {
+            let mut paint = Paint::default();
+            paint.set_color_filter(filter.clone());
+            canvas.save_layer(&SaveLayerRec::default().paint(&paint));
+        }
+
+        let current_window_id = self.cursor_renderer.parent_window_id();
         self.window_regions = windows
             .into_iter()
-            .map(|window| {
-                window.draw(
-                    root_canvas,
+            .filter_map(|window| {
+                let current = window.id == current_window_id;
+                let details = window.draw(
+                    canvas,
                     &settings,
-                    default_background.with_a((255.0 * transparency) as u8),
+                    default_background.with_a(background_alpha),
                     font_dimensions,
+                    current,
                     dt,
-                )
+                );
+                // Closing windows are still drawn while they animate out, but shouldn't receive
+                // mouse input anymore
+                if window.animation.is_closing() {
+                    None
+                } else {
+                    Some(details)
+                }
             })
             .collect();
+        self.rendered_windows
+            .retain(|_, window| !window.animation.is_closed());
+        self.draw_hovered_link(canvas);
+        self.profiler.mark(ProfilerPhase::Draw);
 
         let windows = &self.rendered_windows;
         self.cursor_renderer
             .update_cursor_destination(font_dimensions.into(), windows);
+        self.profiler.mark(ProfilerPhase::Update);
 
-        self.cursor_renderer
-            .draw(&mut self.grid_renderer, &self.current_mode, root_canvas, dt);
+        if !self.is_external(current_window_id) {
+            self.cursor_renderer
+                .draw(&mut self.grid_renderer, &self.current_mode, canvas, dt);
+        }
 
-        root_canvas.restore();
+        let anchor = self
+            .popup_menu
+            .anchor_grid()
+            .and_then(|grid_id| self.rendered_windows.get(&grid_id))
+            .map(|window| window.pixel_region(font_dimensions));
+        let bounds = self
+            .rendered_windows
+            .get(&1)
+            .map(|root_window| root_window.pixel_region(font_dimensions))
+            .unwrap_or(canvas_bounds);
+        self.popup_menu
+            .draw(canvas, &mut self.grid_renderer, anchor, bounds);
+
+        if unfocused_filter.is_some() {
+            canvas.restore();
+        }
+        canvas.restore();
+
+        if let Some(frame_surface) = frame_surface {
+            self.custom_shader.draw_overlay(root_canvas, frame_surface);
+        }
+        self.banner.draw(root_canvas, canvas_bounds);
+
+        if settings.profiler {
+            self.profiler.draw(root_canvas, settings.saves_power());
+        }
+        self.profiler.mark(ProfilerPhase::Overlay);
 
         font_changed
     }
 
-    fn handle_draw_command(&mut self, root_canvas: &mut Canvas, draw_command: DrawCommand) {
+    fn draw_hovered_link(&self, root_canvas: &mut Canvas) {
+        let (grid_id, link) = match &self.hovered_link {
+            Some(hovered_link) => hovered_link,
+            None => return,
+        };
+        let region = match self
+            .window_regions
+            .iter()
+            .find(|details| details.id == *grid_id)
+        {
+            Some(details) => details.region,
+            None => return,
+        };
+
+        let font_dimensions = self.grid_renderer.font_dimensions;
+        let width = font_dimensions.width as f32;
+        let height = font_dimensions.height as f32;
+        let y = region.top + (link.row + 1) as f32 * height - 1.0;
+        let mut paint = Paint::default();
+        paint.set_anti_alias(false);
+        paint.set_stroke_width(1.0);
+        paint.set_color(
+            self.grid_renderer
+                .default_style
+                .foreground(&self.grid_renderer.default_style.colors)
+                .to_color(),
+        );
+        root_canvas.draw_line(
+            (region.left + link.start as f32 * width, y),
+            (region.left + link.end as f32 * width, y),
+            &paint,
+        );
+    }
+
+    /// The link at a cell of a grid, while links are turned on with `g:neovide_detect_urls`.
+    pub fn link_at(&self, grid_id: u64, column: u64, row: u64) -> Option<Link> {
+        if !SETTINGS.get::<RendererSettings>().detect_urls {
+            return None;
+        }
+
+        self.rendered_windows
+            .get(&grid_id)?
+            .links
+            .link_at(column, row)
+            .cloned()
+    }
+
+    fn is_external(&self, grid_id: u64) -> bool {
+        self.rendered_windows
+            .get(&grid_id)
+            .map(|window| window.external)
+            .unwrap_or(false)
+    }
+
+    /// Shifts the grid being scrolled on a touchpad by the part of a line that wasn't sent to
+    /// neovim yet. Every other window is drawn where neovim put it.
+    pub fn set_pixel_scroll_offset(&mut self, offset: Option<(u64, f32)>) {
+        for window in self.rendered_windows.values_mut() {
+            window.pixel_scroll_offset = match offset {
+                Some((grid_id, lines)) if grid_id == window.id => lines,
+                _ => 0.0,
+            };
+        }
+    }
+
+    /// The cell the cursor is in, in pixels of the main window, or None while it is in an
+    /// external window.
+    pub fn caret_region(&self) -> Option<Rect> {
+        if self.is_external(self.cursor_renderer.parent_window_id()) {
+            return None;
+        }
+        let font_dimensions = self.grid_renderer.font_dimensions;
+        let top_left = self.content_origin + self.cursor_renderer.destination() * self.zoom;
+        Some(Rect::from_point_and_size(
+            top_left,
+            (
+                font_dimensions.width as f32 * self.zoom,
+                font_dimensions.height as f32 * self.zoom,
+            ),
+        ))
+    }
+
+    /// Where the documentation of the selected completion is shown.
+    pub fn popup_menu_info_region(&self) -> Option<Rect> {
+        self.popup_menu.info_region()
+    }
+
+    pub fn scroll_popup_menu_info(&mut self, lines: f32) {
+        if lines != 0.0 {
+            self.popup_menu.scroll_info(lines);
+            REDRAW_SCHEDULER.queue_next_frame();
+        }
+    }
+
+    /// Bounces the grid scrolled past the end of its buffer, unless motion is reduced.
+    pub fn overscroll(&mut self, overscroll: Option<(u64, f32)>) {
+        let settings = SETTINGS.get::<RendererSettings>();
+        if !settings.elastic_overscroll || settings.reduce_motion() {
+            return;
+        }
+
+        if let Some((grid_id, lines)) = overscroll {
+            if let Some(window) = self.rendered_windows.get_mut(&grid_id) {
+                window.overscroll(lines);
+            }
+        }
+    }
+
+    /// Grids neovim moved out of the editor with win_external_pos, which the window shows in
+    /// their own os windows.
+    pub fn external_windows(&self) -> impl Iterator<Item = &RenderedWindow> {
+        self.rendered_windows
+            .values()
+            .filter(|window| window.external && !window.hidden)
+    }
+
+    /// Draws an external grid filling the whole canvas. Draw commands are only parsed by
+    /// `draw_frame`, so this should be called after it.
+    pub fn draw_external_window(&mut self, root_canvas: &mut Canvas, grid_id: u64, dt: f32) {
+        let default_background = self.grid_renderer.get_default_background();
+        let font_dimensions = self.grid_renderer.font_dimensions;
+        let background_alpha = { SETTINGS.get::<WindowSettings>().background_alpha() };
+        let settings = SETTINGS.get::<RendererSettings>();
+        let current_window_id = self.cursor_renderer.parent_window_id();
+
+        let window = match self.rendered_windows.get_mut(&grid_id) {
+            Some(window) => window,
+            None => return,
+        };
+
+        root_canvas.clear(default_background.with_a(background_alpha));
+        root_canvas.save();
+        root_canvas.reset_matrix();
+
+        // Grid positions are in editor coordinates, so the window is moved to the origin
+        let pixel_region = window.pixel_region(font_dimensions);
+        root_canvas.translate((-pixel_region.left, -pixel_region.top));
+        window.draw(
+            root_canvas,
+            &settings,
+            default_background.with_a(background_alpha),
+            font_dimensions,
+            grid_id == current_window_id,
+            dt,
+        );
+
+        if grid_id == current_window_id {
+            self.cursor_renderer
+                .draw(&mut self.grid_renderer, &self.current_mode, root_canvas, dt);
+        }
+
+        root_canvas.restore();
+    }
+
+    // Shapes the lines up to the next command that would change how they are shaped
+    fn prepare_lines<'a>(&mut self, draw_commands: impl Iterator<Item = &'a DrawCommand>) {
+        let line_fragments = draw_commands
+            .take_while(|draw_command| !changes_shaping(draw_command))
+            .filter_map(|draw_command| match draw_command {
+                DrawCommand::Window {
+                    command: WindowDrawCommand::DrawLine(line_fragments),
+                    ..
+                } => Some(line_fragments),
+                _ => None,
+            })
+            .flatten();
+        self.grid_renderer.prepare_lines(line_fragments);
+    }
+
+    fn handle_draw_command(
+        &mut self,
+        root_canvas: &mut Canvas,
+        settings: &RendererSettings,
+        draw_command: DrawCommand,
+    ) {
+        profile_scope!("handle draw command");
         match draw_command {
             DrawCommand::Window {
                 grid_id,
                 command: WindowDrawCommand::Close,
             } => {
-                self.rendered_windows.remove(&grid_id);
+                let animate =
+                    grid_id != 1 && settings.window_animations && !settings.reduce_motion();
+                match self.rendered_windows.get_mut(&grid_id) {
+                    Some(rendered_window) if animate && !rendered_window.hidden => {
+                        rendered_window.animation.close();
+                    }
+                    _ => {
+                        self.rendered_windows.remove(&grid_id);
+                    }
+                }
             }
             DrawCommand::Window { grid_id, command } => {
                 match self.rendered_windows.entry(grid_id) {
                     Entry::Occupied(mut occupied_entry) => {
                         let rendered_window = occupied_entry.get_mut();
-                        rendered_window
-                            .handle_window_draw_command(&mut self.grid_renderer, command);
+                        rendered_window.handle_window_draw_command(
+                            &mut self.grid_renderer,
+                            settings,
+                            command,
+                        );
                     }
                     Entry::Vacant(vacant_entry) => {
                         if let WindowDrawCommand::Position {
@@ -185,7 +629,11 @@ impl Renderer {
                                 (grid_left as f32, grid_top as f32).into(),
                                 (width, height).into(),
                             );
-                            vacant_entry.insert(new_window);
+                            let new_window = vacant_entry.insert(new_window);
+                            // The root grid fills the whole window and is never animated in
+                            if grid_id != 1 {
+                                new_window.animation.appear();
+                            }
                         } else {
                             error!("WindowDrawCommand sent for uninitialized grid {}", grid_id);
                         }
@@ -198,12 +646,25 @@ impl Renderer {
             DrawCommand::FontChanged(new_font) => {
                 self.grid_renderer.update_font(&new_font);
             }
+            DrawCommand::WideFontChanged(new_wide_font) => {
+                self.grid_renderer.update_wide_font(&new_wide_font);
+            }
             DrawCommand::DefaultStyleChanged(new_style) => {
                 self.grid_renderer.default_style = Arc::new(new_style);
             }
+            DrawCommand::Export {
+                path,
+                grid_size,
+                lines,
+            } => {
+                export::export_grid(&mut self.grid_renderer, path, grid_size, lines);
+            }
             DrawCommand::ModeChanged(new_mode) => {
                 self.current_mode = new_mode;
             }
+            DrawCommand::ShowPopupMenu(popup_menu) => self.popup_menu.show(popup_menu),
+            DrawCommand::SelectPopupMenuItem(selected) => self.popup_menu.select(selected),
+            DrawCommand::HidePopupMenu => self.popup_menu.hide(),
             _ => {}
         }
     }
@@ -235,3 +696,27 @@ fn floating_sort(window_a: &&mut RenderedWindow, window_b: &&mut RenderedWindow)
     }
     ord
 }
+
+// Fonts and the default style decide how the text of the lines after them is shaped
+fn changes_shaping(draw_command: &DrawCommand) -> bool {
+    matches!(
+        draw_command,
+        DrawCommand::FontChanged(_)
+            | DrawCommand::WideFontChanged(_)
+            | DrawCommand::DefaultStyleChanged(_)
+    )
+}
+
+// With g:neovide_bidi each row is laid out once as a whole, before its fragments are shaped
+fn reorder_draw_command(draw_command: DrawCommand) -> DrawCommand {
+    match draw_command {
+        DrawCommand::Window {
+            grid_id,
+            command: WindowDrawCommand::DrawLine(line_fragments),
+        } => DrawCommand::Window {
+            grid_id,
+            command: WindowDrawCommand::DrawLine(reorder_line(line_fragments)),
+        },
+        draw_command => draw_command,
+    }
+}
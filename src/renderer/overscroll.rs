@@ -0,0 +1,123 @@
+// How far, in lines, the grid can be pulled past the end of the buffer
+const OVERSCROLL_LIMIT: f32 = 3.0;
+// How quickly the grid springs back once the scrolling stops, per second
+const OVERSCROLL_RETURN_RATE: f32 = 12.0;
+
+/// The rubber band shift of a grid scrolled past the top or the bottom of its buffer, which
+/// springs back to where neovim put the grid.
+pub struct Overscroll {
+    // In lines, positive when pulled down past the top
+    offset: f32,
+    at_top: bool,
+    at_bottom: bool,
+}
+
+impl Overscroll {
+    pub fn new() -> Overscroll {
+        Overscroll {
+            offset: 0.0,
+            at_top: false,
+            at_bottom: false,
+        }
+    }
+
+    pub fn offset(&self) -> f32 {
+        self.offset
+    }
+
+    /// Older neovim versions leave out the line count, in which case only the top bounces.
+    pub fn update_viewport(&mut self, top_line: f64, bottom_line: f64, line_count: Option<f64>) {
+        self.at_top = top_line < 1.0;
+        self.at_bottom = line_count.map_or(false, |line_count| bottom_line >= line_count);
+    }
+
+    /// Scrolling by this many lines, positive towards the top of the buffer, only pulls the grid
+    /// when there is nothing left to scroll in that direction. The further it is pulled the more
+    /// it resists.
+    pub fn push(&mut self, lines: f32) {
+        let at_edge = (lines > 0.0 && self.at_top) || (lines < 0.0 && self.at_bottom);
+        if !at_edge {
+            return;
+        }
+
+        let resistance = (1.0 - self.offset.abs() / OVERSCROLL_LIMIT).max(0.0);
+        self.offset = (self.offset + lines * resistance)
+            .max(-OVERSCROLL_LIMIT)
+            .min(OVERSCROLL_LIMIT);
+    }
+
+    pub fn update(&mut self, dt: f32) -> bool {
+        if self.offset == 0.0 {
+            return false;
+        }
+
+        // Decays by the same amount over a second whether that took 60 frames or 144
+        self.offset *= (-dt * OVERSCROLL_RETURN_RATE).exp();
+        if self.offset.abs() < 0.01 {
+            self.offset = 0.0;
+        }
+        true
+    }
+
+    pub fn reset(&mut self) {
+        self.offset = 0.0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_only_pulls_at_the_edges() {
+        let mut overscroll = Overscroll::new();
+        overscroll.update_viewport(10.0, 40.0, Some(100.0));
+        overscroll.push(1.0);
+        overscroll.push(-1.0);
+        assert_eq!(overscroll.offset(), 0.0);
+
+        overscroll.update_viewport(0.0, 30.0, Some(100.0));
+        overscroll.push(-1.0);
+        assert_eq!(overscroll.offset(), 0.0);
+        overscroll.push(1.0);
+        assert_eq!(overscroll.offset(), 1.0);
+
+        overscroll.reset();
+        overscroll.update_viewport(70.0, 100.0, Some(100.0));
+        overscroll.push(-1.0);
+        assert_eq!(overscroll.offset(), -1.0);
+    }
+
+    #[test]
+    fn test_resists_and_springs_back() {
+        let mut overscroll = Overscroll::new();
+        overscroll.update_viewport(0.0, 30.0, None);
+        for _ in 0..100 {
+            overscroll.push(1.0);
+        }
+        assert!(overscroll.offset() <= OVERSCROLL_LIMIT);
+
+        let mut frames = 0;
+        while overscroll.update(1.0 / 60.0) {
+            frames += 1;
+        }
+        assert_eq!(overscroll.offset(), 0.0);
+        assert!(frames < 60);
+    }
+
+    #[test]
+    fn test_springs_back_at_any_frame_rate() {
+        let mut slow = Overscroll::new();
+        let mut fast = Overscroll::new();
+        for overscroll in [&mut slow, &mut fast].iter_mut() {
+            overscroll.update_viewport(0.0, 30.0, None);
+            overscroll.push(1.0);
+        }
+
+        slow.update(0.1);
+        for _ in 0..4 {
+            fast.update(0.025);
+        }
+        assert!((slow.offset() - fast.offset()).abs() < 1e-4);
+    }
+}
@@ -0,0 +1,388 @@
+use std::sync::Arc;
+
+use skia_safe::{Canvas, Paint, Rect};
+
+use super::GridRenderer;
+use crate::bridge::PopupMenuItem;
+use crate::editor::{PopupMenu, Style};
+
+const MAX_VISIBLE_ITEMS: usize = 15;
+const INFO_MAX_WIDTH: usize = 60;
+const INFO_MAX_HEIGHT: usize = 20;
+
+// Symbols for the kinds of the builtin completion and the names language servers use, which fit
+// in a single cell of most fonts
+fn kind_icon(kind: &str) -> Option<&'static str> {
+    let icon = match kind.to_lowercase().as_str() {
+        "f" | "function" | "method" => "ƒ",
+        "constructor" => "⊕",
+        "v" | "variable" => "α",
+        "m" | "field" | "property" => "▪",
+        "t" | "class" | "struct" => "◆",
+        "interface" => "◇",
+        "module" => "▣",
+        "d" | "macro" => "#",
+        "constant" => "π",
+        "enum" | "enummember" => "∈",
+        "keyword" => "κ",
+        "snippet" => "✂",
+        "text" => "¶",
+        "file" => "▤",
+        "folder" => "▥",
+        "operator" => "±",
+        "reference" => "&",
+        "color" => "●",
+        "event" => "⚡",
+        "typeparameter" => "τ",
+        "value" | "unit" => "□",
+        _ => return None,
+    };
+    Some(icon)
+}
+
+// The widths of the columns, in cells, which fit every item
+struct MenuLayout {
+    word_width: usize,
+    menu_width: usize,
+}
+
+impl MenuLayout {
+    fn new(items: &[PopupMenuItem]) -> MenuLayout {
+        let widest = |column: fn(&PopupMenuItem) -> &str| {
+            items
+                .iter()
+                .map(|item| column(item).chars().count())
+                .max()
+                .unwrap_or(0)
+        };
+        MenuLayout {
+            word_width: widest(|item| &item.word),
+            menu_width: widest(|item| &item.menu),
+        }
+    }
+
+    fn width(&self) -> usize {
+        let menu_width = if self.menu_width > 0 {
+            self.menu_width + 2
+        } else {
+            0
+        };
+        // Padding, the kind and the space after it, the word, the menu text and padding again
+        3 + self.word_width + menu_width + 1
+    }
+
+    // Kinds without an icon show their first letter instead
+    fn format(&self, item: &PopupMenuItem) -> String {
+        let icon = kind_icon(&item.kind)
+            .map(str::to_owned)
+            .or_else(|| item.kind.chars().next().map(String::from))
+            .unwrap_or_else(|| " ".to_owned());
+        let mut text = format!(" {} {:<width$}", icon, item.word, width = self.word_width);
+        if self.menu_width > 0 {
+            text.push_str(&format!("  {:<width$}", item.menu, width = self.menu_width));
+        }
+        text.push(' ');
+        text
+    }
+}
+
+// Keeps the selected item in view while moving the list as little as possible
+fn first_visible_item(first: usize, selected: Option<u64>, count: usize, visible: usize) -> usize {
+    if count <= visible {
+        return 0;
+    }
+    let first = first.min(count - visible);
+    match selected.map(|selected| selected as usize) {
+        Some(selected) if selected < first => selected,
+        Some(selected) if selected >= first + visible => selected + 1 - visible,
+        _ => first,
+    }
+}
+
+// Wraps the documentation at word boundaries, breaking up words longer than a line. Indentation
+// is kept, since documentation often contains code.
+fn wrap_text(text: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    for line in text.replace('\t', "    ").lines() {
+        let mut current = String::new();
+        let mut current_width = 0;
+        for (index, word) in line.split(' ').enumerate() {
+            let word_width = word.chars().count();
+            if current_width > 0 && current_width + 1 + word_width > width {
+                lines.push(std::mem::take(&mut current));
+                current_width = 0;
+            } else if index > 0 {
+                current.push(' ');
+                current_width += 1;
+            }
+
+            for character in word.chars() {
+                if current_width >= width {
+                    lines.push(std::mem::take(&mut current));
+                    current_width = 0;
+                }
+                current.push(character);
+                current_width += 1;
+            }
+        }
+        lines.push(current);
+    }
+    lines
+}
+
+fn style_background(grid_renderer: &GridRenderer, style: &Option<Arc<Style>>) -> Paint {
+    let default_style = &grid_renderer.default_style;
+    let color = style
+        .as_ref()
+        .unwrap_or(default_style)
+        .background(&default_style.colors);
+    Paint::new(color, None)
+}
+
+/// Draws the completion menu neovim hands to neovide with ext_popupmenu: an icon for the kind of
+/// each item, and the documentation of the selected one in a scrollable panel next to the list.
+pub struct PopupMenuRenderer {
+    menu: Option<PopupMenu>,
+    first_visible_item: usize,
+    // In lines, fractional while scrolling on a touchpad
+    info_scroll: f32,
+    info_region: Option<Rect>,
+}
+
+impl PopupMenuRenderer {
+    pub fn new() -> PopupMenuRenderer {
+        PopupMenuRenderer {
+            menu: None,
+            first_visible_item: 0,
+            info_scroll: 0.0,
+            info_region: None,
+        }
+    }
+
+    pub fn show(&mut self, menu: PopupMenu) {
+        self.menu = Some(menu);
+        self.first_visible_item = 0;
+        self.info_scroll = 0.0;
+    }
+
+    pub fn select(&mut self, selected: Option<u64>) {
+        if let Some(menu) = &mut self.menu {
+            menu.selected = selected;
+        }
+        self.info_scroll = 0.0;
+    }
+
+    pub fn hide(&mut self) {
+        self.menu = None;
+        self.info_region = None;
+    }
+
+    /// The grid the menu is placed in.
+    pub fn anchor_grid(&self) -> Option<u64> {
+        self.menu.as_ref().map(|menu| menu.grid)
+    }
+
+    /// Where the documentation panel was last drawn, which is scrolled instead of the grid below.
+    pub fn info_region(&self) -> Option<Rect> {
+        self.info_region
+    }
+
+    /// Scrolls the documentation by this many lines, positive towards the top like the mouse
+    /// wheel. The panel keeps it within the text when drawn.
+    pub fn scroll_info(&mut self, lines: f32) {
+        self.info_scroll = (self.info_scroll - lines).max(0.0);
+    }
+
+    /// `anchor` is the region of the grid the menu is placed in and `bounds` what it has to fit
+    /// in.
+    pub fn draw(
+        &mut self,
+        canvas: &mut Canvas,
+        grid_renderer: &mut GridRenderer,
+        anchor: Option<Rect>,
+        bounds: Rect,
+    ) {
+        self.info_region = None;
+        let (menu, anchor) = match (&self.menu, anchor) {
+            (Some(menu), Some(anchor)) if !menu.items.is_empty() => (menu, anchor),
+            _ => return,
+        };
+
+        let font_width = grid_renderer.font_dimensions.width as f32;
+        let font_height = grid_renderer.font_dimensions.height as f32;
+        let layout = MenuLayout::new(&menu.items);
+        let visible = menu.items.len().min(MAX_VISIBLE_ITEMS);
+        self.first_visible_item = first_visible_item(
+            self.first_visible_item,
+            menu.selected,
+            menu.items.len(),
+            visible,
+        );
+
+        // Below the anchor cell when there is room, above it otherwise
+        let width = layout.width() as f32 * font_width;
+        let height = visible as f32 * font_height;
+        let anchor_top = anchor.top + menu.row as f32 * font_height;
+        let top = if anchor_top + font_height + height > bounds.bottom
+            && anchor_top - height >= bounds.top
+        {
+            anchor_top - height
+        } else {
+            anchor_top + font_height
+        };
+        let left = (anchor.left + menu.column as f32 * font_width)
+            .min(bounds.right - width)
+            .max(bounds.left);
+        let menu_region = Rect::from_xywh(left, top, width, height);
+
+        canvas.save();
+        canvas.translate((left, top));
+        let items = self.first_visible_item..self.first_visible_item + visible;
+        for (row, index) in items.enumerate() {
+            let style = if menu.selected == Some(index as u64) {
+                &menu.selected_style
+            } else {
+                &menu.style
+            };
+            let row_region = Rect::from_xywh(0.0, row as f32 * font_height, width, font_height);
+            canvas.draw_rect(row_region, &style_background(grid_renderer, style));
+            grid_renderer.draw_foreground(
+                canvas,
+                layout.format(&menu.items[index]),
+                (0, row as u64),
+                layout.width() as u64,
+                style,
+                false,
+            );
+        }
+        canvas.restore();
+
+        let info = match menu
+            .selected
+            .and_then(|selected| menu.items.get(selected as usize))
+        {
+            Some(item) if !item.info.is_empty() => item.info.clone(),
+            _ => return,
+        };
+        let style = menu.style.clone();
+        self.draw_info(canvas, grid_renderer, &info, &style, menu_region, bounds);
+    }
+
+    // Right of the menu when there is room, left of it otherwise
+    fn draw_info(
+        &mut self,
+        canvas: &mut Canvas,
+        grid_renderer: &mut GridRenderer,
+        info: &str,
+        style: &Option<Arc<Style>>,
+        menu_region: Rect,
+        bounds: Rect,
+    ) {
+        let font_width = grid_renderer.font_dimensions.width as f32;
+        let font_height = grid_renderer.font_dimensions.height as f32;
+        let lines = wrap_text(info, INFO_MAX_WIDTH);
+        let text_width = lines
+            .iter()
+            .map(|line| line.chars().count())
+            .max()
+            .unwrap_or(0)
+            .max(1);
+        let visible_lines = lines.len().min(INFO_MAX_HEIGHT);
+        let max_scroll = lines.len() - visible_lines;
+        self.info_scroll = self.info_scroll.min(max_scroll as f32);
+        let first_line = self.info_scroll as usize;
+
+        // A cell of padding on either side
+        let width = (text_width + 2) as f32 * font_width;
+        let height = visible_lines as f32 * font_height;
+        let left = if menu_region.right + width <= bounds.right {
+            menu_region.right
+        } else {
+            (menu_region.left - width).max(bounds.left)
+        };
+        let top = menu_region.top.min(bounds.bottom - height).max(bounds.top);
+        let info_region = Rect::from_xywh(left, top, width, height);
+        self.info_region = Some(info_region);
+
+        canvas.save();
+        canvas.clip_rect(info_region, None, Some(false));
+        canvas.draw_rect(info_region, &style_background(grid_renderer, style));
+        canvas.translate((left, top));
+        for (row, line) in lines[first_line..first_line + visible_lines]
+            .iter()
+            .enumerate()
+        {
+            let cell_width = line.chars().count() as u64;
+            grid_renderer.draw_foreground(
+                canvas,
+                line.clone(),
+                (1, row as u64),
+                cell_width,
+                style,
+                false,
+            );
+        }
+
+        // A thumb shows where in the documentation the panel is
+        if max_scroll > 0 {
+            let default_style = &grid_renderer.default_style;
+            let mut color = style
+                .as_ref()
+                .unwrap_or(default_style)
+                .foreground(&default_style.colors);
+            color.a = 0.5;
+            let thumb_height = height * visible_lines as f32 / lines.len() as f32;
+            let thumb_top = (height - thumb_height) * first_line as f32 / max_scroll as f32;
+            let thumb_width = (font_width / 4.0).max(1.0);
+            canvas.draw_rect(
+                Rect::from_xywh(width - thumb_width, thumb_top, thumb_width, thumb_height),
+                &Paint::new(color, None),
+            );
+        }
+        canvas.restore();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(word: &str, kind: &str, menu: &str) -> PopupMenuItem {
+        PopupMenuItem {
+            word: word.to_owned(),
+            kind: kind.to_owned(),
+            menu: menu.to_owned(),
+            info: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_menu_layout() {
+        let items = vec![item("len", "Method", "[LSP]"), item("iter_mut", "x", "")];
+        let layout = MenuLayout::new(&items);
+        assert_eq!(layout.format(&items[0]), " ƒ len       [LSP] ");
+        assert_eq!(layout.format(&items[1]), " x iter_mut        ");
+        assert_eq!(layout.width(), layout.format(&items[0]).chars().count());
+    }
+
+    #[test]
+    fn test_selected_item_stays_visible() {
+        assert_eq!(first_visible_item(0, Some(3), 10, 15), 0);
+        assert_eq!(first_visible_item(0, Some(20), 30, 15), 6);
+        assert_eq!(first_visible_item(6, Some(10), 30, 15), 6);
+        assert_eq!(first_visible_item(6, Some(2), 30, 15), 2);
+        assert_eq!(first_visible_item(20, None, 30, 15), 15);
+    }
+
+    #[test]
+    fn test_wrap_text() {
+        assert_eq!(
+            wrap_text("fn len(&self) -> usize\n\n    Returns the length", 13),
+            vec!["fn len(&self)", "-> usize", "", "    Returns", "the length"]
+                .into_iter()
+                .map(str::to_owned)
+                .collect::<Vec<String>>()
+        );
+        assert_eq!(wrap_text("abcdefgh", 3), vec!["abc", "def", "gh"]);
+    }
+}
@@ -0,0 +1,214 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use skia_safe::{colors, Canvas, Color, Font, Paint, Rect, Typeface};
+
+use crate::settings::SETTINGS;
+use crate::WindowSettings;
+
+const FRAME_COUNT: usize = 120;
+const FONT_SIZE: f32 = 14.0;
+const LINE_HEIGHT: f32 = 18.0;
+const PADDING: f32 = 8.0;
+const GRAPH_HEIGHT: f32 = 40.0;
+const OVERLAY_WIDTH: f32 = 2.0 * PADDING + 3.0 * FRAME_COUNT as f32;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ProfilerPhase {
+    // Handling the draw commands of the frame
+    Parse,
+    // Drawing the grids
+    Draw,
+    // Moving the cursor to where the grids ended up
+    Update,
+    // The cursor, popup menus, shaders and overlays drawn on top of the grids
+    Overlay,
+    Present,
+}
+
+#[derive(Clone, Copy, Default)]
+struct FrameTimings {
+    dt: f32,
+    parse: Duration,
+    update: Duration,
+    draw: Duration,
+    overlay: Duration,
+    present: Duration,
+}
+
+impl FrameTimings {
+    fn total(&self) -> Duration {
+        self.parse + self.update + self.draw + self.overlay + self.present
+    }
+}
+
+fn milliseconds(duration: Duration) -> f32 {
+    duration.as_secs_f32() * 1000.0
+}
+
+pub struct Profiler {
+    font: Font,
+    paint: Paint,
+    frames: VecDeque<FrameTimings>,
+    current_frame: FrameTimings,
+    phase_start: Instant,
+    /// The median and 95th percentile input latency in milliseconds, while it is measured.
+    pub latency: Option<(f32, f32)>,
+}
+
+impl Profiler {
+    pub fn new() -> Profiler {
+        Profiler {
+            font: Font::new(Typeface::default(), FONT_SIZE),
+            paint: Paint::new(colors::WHITE, None),
+            frames: VecDeque::with_capacity(FRAME_COUNT),
+            current_frame: FrameTimings::default(),
+            phase_start: Instant::now(),
+            latency: None,
+        }
+    }
+
+    /// Starts timing a new frame, so the time waiting for it isn't counted.
+    pub fn begin_frame(&mut self, dt: f32) {
+        self.current_frame = FrameTimings {
+            dt,
+            ..Default::default()
+        };
+        self.phase_start = Instant::now();
+    }
+
+    /// Sets the given phase to the time since the last mark, or since the frame began. Presenting
+    /// finishes the frame, which happens after the overlay is drawn, so the numbers shown are
+    /// always those of the last finished frame.
+    pub fn mark(&mut self, phase: ProfilerPhase) {
+        let now = Instant::now();
+        let elapsed = now - self.phase_start;
+        self.phase_start = now;
+
+        match phase {
+            ProfilerPhase::Parse => self.current_frame.parse = elapsed,
+            ProfilerPhase::Draw => self.current_frame.draw = elapsed,
+            ProfilerPhase::Update => self.current_frame.update = elapsed,
+            ProfilerPhase::Overlay => self.current_frame.overlay = elapsed,
+            ProfilerPhase::Present => {
+                self.current_frame.present = elapsed;
+                if self.frames.len() == FRAME_COUNT {
+                    self.frames.pop_front();
+                }
+                self.frames.push_back(self.current_frame);
+            }
+        }
+    }
+
+    fn fps(&self) -> f32 {
+        let total_dt: f32 = self.frames.iter().map(|frame| frame.dt).sum();
+        if total_dt > 0.0 {
+            self.frames.len() as f32 / total_dt
+        } else {
+            0.0
+        }
+    }
+
+    // A frame counts as dropped if it arrived more than half a frame later than the refresh rate
+    // asks for.
+    fn dropped_frames(&self, expected_frame_length: f32) -> usize {
+        let threshold = expected_frame_length * 1.5;
+        self.frames
+            .iter()
+            .filter(|frame| frame.dt > threshold)
+            .count()
+    }
+
+    fn draw_text(&mut self, root_canvas: &mut Canvas, text: &str, line: usize) {
+        self.paint.set_color(colors::WHITE);
+        root_canvas.draw_str(
+            text,
+            (PADDING, PADDING + FONT_SIZE + line as f32 * LINE_HEIGHT),
+            &self.font,
+            &self.paint,
+        );
+    }
+
+    fn draw_graph(&mut self, root_canvas: &mut Canvas, top: f32, expected_frame_length: f32) {
+        // The graph tops out at two expected frames, anything above that is clipped
+        let scale = GRAPH_HEIGHT / (expected_frame_length * 2.0);
+
+        for (index, frame) in self.frames.iter().enumerate() {
+            let height = (frame.total().as_secs_f32() * scale).min(GRAPH_HEIGHT);
+            let color = if frame.dt > expected_frame_length * 1.5 {
+                Color::from_rgb(230, 80, 80)
+            } else {
+                Color::from_rgb(120, 200, 120)
+            };
+
+            self.paint.set_color(color);
+            root_canvas.draw_rect(
+                Rect::from_xywh(
+                    PADDING + index as f32 * 3.0,
+                    top + GRAPH_HEIGHT - height,
+                    2.0,
+                    height,
+                ),
+                &self.paint,
+            );
+        }
+
+        // Marks the time budget of a single frame
+        self.paint.set_color(Color::from_argb(160, 255, 255, 255));
+        let budget_top = top + GRAPH_HEIGHT - expected_frame_length * scale;
+        root_canvas.draw_rect(
+            Rect::from_xywh(PADDING, budget_top, OVERLAY_WIDTH - 2.0 * PADDING, 1.0),
+            &self.paint,
+        );
+    }
+
+    pub fn draw(&mut self, root_canvas: &mut Canvas, saves_power: bool) {
+        let expected_frame_length = 1.0
+            / SETTINGS
+                .get::<WindowSettings>()
+                .target_refresh_rate(saves_power);
+        let last_frame = self.frames.back().copied().unwrap_or_default();
+        let mut lines = vec![
+            format!("fps: {:.1}", self.fps()),
+            format!("frame: {:.2}ms", milliseconds(last_frame.total())),
+            format!(
+                "parse: {:.2}ms draw: {:.2}ms update: {:.2}ms",
+                milliseconds(last_frame.parse),
+                milliseconds(last_frame.draw),
+                milliseconds(last_frame.update)
+            ),
+            format!(
+                "overlay: {:.2}ms present: {:.2}ms",
+                milliseconds(last_frame.overlay),
+                milliseconds(last_frame.present)
+            ),
+            format!(
+                "dropped: {} of the last {} frames",
+                self.dropped_frames(expected_frame_length),
+                self.frames.len()
+            ),
+        ];
+        if let Some((p50, p95)) = self.latency {
+            lines.push(format!("latency p50: {:.1}ms p95: {:.1}ms", p50, p95));
+        }
+
+        let graph_top = PADDING + lines.len() as f32 * LINE_HEIGHT + PADDING;
+        let overlay_height = graph_top + GRAPH_HEIGHT + PADDING;
+
+        root_canvas.save();
+        root_canvas.reset_matrix();
+
+        self.paint.set_color(Color::from_argb(200, 0, 0, 0));
+        root_canvas.draw_rect(
+            Rect::from_xywh(0.0, 0.0, OVERLAY_WIDTH, overlay_height),
+            &self.paint,
+        );
+
+        for (line, text) in lines.iter().enumerate() {
+            self.draw_text(root_canvas, text, line);
+        }
+        self.draw_graph(root_canvas, graph_top, expected_frame_length);
+
+        root_canvas.restore();
+    }
+}
@@ -0,0 +1,154 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::redraw_scheduler::REDRAW_SCHEDULER;
+use crate::settings::{report_invalid_setting, FromValue, Value};
+
+// Checked once, the preference is rarely changed while neovide runs. Animations stay on until
+// the os answered.
+static SYSTEM_REDUCE_MOTION: AtomicBool = AtomicBool::new(false);
+
+/// How `g:neovide_reduce_motion` is set: v:true always keeps motion to a minimum and v:false never
+/// does, overriding the os preference and power saving. Unset or "auto" follows those.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ReduceMotion {
+    Auto,
+    On,
+    Off,
+}
+
+impl ReduceMotion {
+    pub fn reduces_motion(self, automatically: impl FnOnce() -> bool) -> bool {
+        match self {
+            ReduceMotion::On => true,
+            ReduceMotion::Off => false,
+            ReduceMotion::Auto => automatically(),
+        }
+    }
+}
+
+impl FromValue for ReduceMotion {
+    fn from_value(&mut self, value: Value) {
+        *self = match &value {
+            Value::Boolean(true) => ReduceMotion::On,
+            Value::Boolean(false) => ReduceMotion::Off,
+            value if value.as_u64() == Some(1) => ReduceMotion::On,
+            value if value.as_u64() == Some(0) => ReduceMotion::Off,
+            value if value.as_str() == Some("auto") => ReduceMotion::Auto,
+            _ => {
+                report_invalid_setting(format!(
+                    "Ignored neovide_reduce_motion = {}: it has to be v:true, v:false or auto",
+                    value
+                ));
+                return;
+            }
+        };
+    }
+}
+
+impl From<ReduceMotion> for Value {
+    fn from(reduce_motion: ReduceMotion) -> Self {
+        match reduce_motion {
+            ReduceMotion::Auto => Value::from("auto"),
+            ReduceMotion::On => Value::from(true),
+            ReduceMotion::Off => Value::from(false),
+        }
+    }
+}
+
+#[cfg(not(windows))]
+fn command_output(program: &str, args: &[&str]) -> Option<String> {
+    let output = std::process::Command::new(program)
+        .args(args)
+        .output()
+        .ok()?;
+    if output.status.success() {
+        String::from_utf8(output.stdout).ok()
+    } else {
+        None
+    }
+}
+
+// Windows only has the switch to turn off animations in general
+#[cfg(windows)]
+pub fn system_reduce_motion() -> bool {
+    use winapi::{
+        shared::minwindef::{BOOL, FALSE},
+        um::winuser::{SystemParametersInfoW, SPI_GETCLIENTAREAANIMATION},
+    };
+
+    let mut animations_enabled: BOOL = 1;
+    let succeeded = unsafe {
+        SystemParametersInfoW(
+            SPI_GETCLIENTAREAANIMATION,
+            0,
+            &mut animations_enabled as *mut BOOL as *mut _,
+            0,
+        )
+    };
+    succeeded != FALSE && animations_enabled == FALSE
+}
+
+#[cfg(target_os = "macos")]
+pub fn system_reduce_motion() -> bool {
+    command_output(
+        "defaults",
+        &["read", "com.apple.universalaccess", "reduceMotion"],
+    )
+    .map_or(false, |value| value.trim() == "1")
+}
+
+#[cfg(target_os = "linux")]
+pub fn system_reduce_motion() -> bool {
+    command_output(
+        "gsettings",
+        &["get", "org.gnome.desktop.interface", "enable-animations"],
+    )
+    .map_or(false, |value| value.trim() == "false")
+}
+
+#[cfg(not(any(windows, target_os = "macos", target_os = "linux")))]
+pub fn system_reduce_motion() -> bool {
+    false
+}
+
+/// Asks the os whether to keep motion to a minimum with the query, usually
+/// `system_reduce_motion`. It runs on its own thread, since asking can mean starting a process.
+pub fn detect_reduced_motion(query: fn() -> bool) {
+    std::thread::spawn(move || {
+        if query() {
+            SYSTEM_REDUCE_MOTION.store(true, Ordering::Relaxed);
+            REDRAW_SCHEDULER.queue_next_frame();
+        }
+    });
+}
+
+/// Whether the os asks applications to keep motion to a minimum.
+pub fn system_prefers_reduced_motion() -> bool {
+    SYSTEM_REDUCE_MOTION.load(Ordering::Relaxed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_explicit_false_wins() {
+        assert!(!ReduceMotion::Off.reduces_motion(|| true));
+        assert!(ReduceMotion::On.reduces_motion(|| false));
+        assert!(ReduceMotion::Auto.reduces_motion(|| true));
+        assert!(!ReduceMotion::Auto.reduces_motion(|| false));
+    }
+
+    #[test]
+    fn test_reduce_motion_values() {
+        let mut reduce_motion = ReduceMotion::Auto;
+        reduce_motion.from_value(Value::from(false));
+        assert_eq!(reduce_motion, ReduceMotion::Off);
+        reduce_motion.from_value(Value::from(1));
+        assert_eq!(reduce_motion, ReduceMotion::On);
+        reduce_motion.from_value(Value::from("auto"));
+        assert_eq!(reduce_motion, ReduceMotion::Auto);
+        reduce_motion.from_value(Value::from("sometimes"));
+        assert_eq!(reduce_motion, ReduceMotion::Auto);
+    }
+}
@@ -3,19 +3,28 @@ use std::collections::VecDeque;
 use skia_safe::canvas::{SaveLayerRec, SrcRectConstraint};
 use skia_safe::gpu::SurfaceOrigin;
 use skia_safe::{
-    image_filters::blur, BlendMode, Budgeted, Canvas, Color, Image, ImageInfo, Paint, Point, Rect,
-    SamplingOptions, Surface, SurfaceProps, SurfacePropsFlags,
+    image_filters::blur, BlendMode, BlurStyle, Budgeted, Canvas, ClipOp, Color, Image, ImageInfo,
+    MaskFilter, Paint, Point, RRect, Rect, SamplingOptions, Surface, SurfaceProps,
+    SurfacePropsFlags,
 };
 
 use super::animation_utils::*;
-use super::{GridRenderer, RendererSettings};
+use super::decorations::GridDecorations;
+use super::links::GridLinks;
+use super::minimap::Minimap;
+use super::overscroll::Overscroll;
+use super::scrollbar::{Scrollbar, ScrollbarDetails};
+use super::window_animation::WindowAnimation;
+use super::{high_contrast_colors, GridRenderer, RendererSettings};
 use crate::editor::{LineFragment, WindowDrawCommand};
 use crate::redraw_scheduler::REDRAW_SCHEDULER;
+use crate::settings::SETTINGS;
 use crate::utils::Dimensions;
 
-fn build_window_surface(parent_canvas: &mut Canvas, pixel_size: (i32, i32)) -> Surface {
-    let mut context = parent_canvas.recording_context().unwrap();
-    let budgeted = Budgeted::Yes;
+// How much floats are blurred by g:neovide_floating_blur
+const FLOATING_BLUR_SIGMA: f32 = 2.0;
+
+pub(super) fn build_window_surface(parent_canvas: &mut Canvas, pixel_size: (i32, i32)) -> Surface {
     let parent_image_info = parent_canvas.image_info();
     let image_info = ImageInfo::new(
         pixel_size,
@@ -23,9 +32,20 @@ fn build_window_surface(parent_canvas: &mut Canvas, pixel_size: (i32, i32)) -> S
         parent_image_info.alpha_type(),
         parent_image_info.color_space(),
     );
-    let surface_origin = SurfaceOrigin::TopLeft;
     // subpixel layout (should be configurable/obtained from fontconfig)
     let props = SurfaceProps::new(SurfacePropsFlags::default(), skia_safe::PixelGeometry::RGBH);
+
+    // The software renderer draws into a raster canvas which has no gpu context to allocate
+    // render targets from
+    let mut context = match parent_canvas.recording_context() {
+        Some(context) => context,
+        None => {
+            return Surface::new_raster(&image_info, None, Some(&props))
+                .expect("Could not create surface")
+        }
+    };
+    let budgeted = Budgeted::Yes;
+    let surface_origin = SurfaceOrigin::TopLeft;
     Surface::new_render_target(
         &mut context,
         budgeted,
@@ -38,6 +58,34 @@ fn build_window_surface(parent_canvas: &mut Canvas, pixel_size: (i32, i32)) -> S
     .expect("Could not create surface")
 }
 
+// Floating windows cast a soft shadow offset slightly downward so they read as sitting above the
+// windows beneath them.
+fn draw_shadow(root_canvas: &mut Canvas, settings: &RendererSettings, pixel_region: Rect) {
+    let blur_radius = settings.floating_shadow_blur.max(0.0);
+    let opacity = settings.floating_shadow_opacity.min(1.0).max(0.0);
+    let radius = settings.floating_corner_radius.max(0.0);
+
+    let mut paint = Paint::new(skia_safe::colors::BLACK, None);
+    paint.set_alpha_f(opacity);
+    paint.set_anti_alias(true);
+    if blur_radius > 0.0 {
+        // Skia expects a gaussian sigma, which is roughly half of the visible blur radius
+        paint.set_mask_filter(MaskFilter::blur(BlurStyle::Normal, blur_radius / 2.0, None));
+    }
+
+    // Floats are partially transparent, so the shadow is kept out from underneath the window
+    // itself to avoid darkening its contents
+    root_canvas.save();
+    root_canvas.clip_rrect(
+        RRect::new_rect_xy(&pixel_region, radius, radius),
+        ClipOp::Difference,
+        Some(true),
+    );
+    let shadow_region = pixel_region.with_offset((0.0, blur_radius / 2.0));
+    root_canvas.draw_rrect(RRect::new_rect_xy(&shadow_region, radius, radius), &paint);
+    root_canvas.restore();
+}
+
 fn build_window_surface_with_grid_size(
     parent_canvas: &mut Canvas,
     grid_renderer: &GridRenderer,
@@ -90,7 +138,13 @@ pub struct RenderedWindow {
 
     pub id: u64,
     pub hidden: bool,
+    // Shown in its own os window instead of the main one
+    pub external: bool,
+    pub window_handle: Option<u64>,
     pub floating_order: Option<u64>,
+    // The highest winblend or pumblend the highlights drawn in the window were defined with,
+    // which is how neovim tells a see through float apart
+    blend: u8,
 
     pub grid_size: Dimensions,
 
@@ -103,6 +157,16 @@ pub struct RenderedWindow {
     pub current_scroll: f32,
     scroll_destination: f32,
     scroll_t: f32,
+    // Touchpad scrolling that didn't add up to a whole line for neovim yet, drawn as a partial
+    // line shift so the scrolling doesn't jump by lines
+    pub pixel_scroll_offset: f32,
+    overscroll: Overscroll,
+
+    scrollbar: Scrollbar,
+    minimap: Minimap,
+    pub links: GridLinks,
+    decorations: GridDecorations,
+    pub animation: WindowAnimation,
 }
 
 #[derive(Clone, Debug)]
@@ -110,6 +174,8 @@ pub struct WindowDrawDetails {
     pub id: u64,
     pub region: Rect,
     pub floating_order: Option<u64>,
+    pub scrollbar: Option<ScrollbarDetails>,
+    pub minimap: Option<ScrollbarDetails>,
 }
 
 impl RenderedWindow {
@@ -127,6 +193,8 @@ impl RenderedWindow {
             current_surface,
             id,
             hidden: false,
+            external: false,
+            window_handle: None,
             floating_order: None,
 
             grid_size,
@@ -138,24 +206,53 @@ impl RenderedWindow {
 
             start_scroll: 0.0,
             current_scroll: 0.0,
+            pixel_scroll_offset: 0.0,
+            overscroll: Overscroll::new(),
             scroll_destination: 0.0,
             scroll_t: 2.0, // 2.0 is out of the 0.0 to 1.0 range and stops animation
+
+            scrollbar: Scrollbar::new(),
+            minimap: Minimap::new(),
+            links: GridLinks::new(),
+            decorations: GridDecorations::new(),
+            blend: 0,
+            animation: WindowAnimation::new(grid_size),
         }
     }
 
+    /// Pulls the grid past the end of its buffer when scrolling by this many lines, positive
+    /// towards the top, has nowhere left to go.
+    pub fn overscroll(&mut self, lines: f32) {
+        self.overscroll.push(lines);
+        REDRAW_SCHEDULER.queue_next_frame();
+    }
+
     pub fn pixel_region(&self, font_dimensions: Dimensions) -> Rect {
         let current_pixel_position = Point::new(
             self.grid_current_position.x * font_dimensions.width as f32,
             self.grid_current_position.y * font_dimensions.height as f32,
         );
 
-        let image_size: (i32, i32) = (self.grid_size * font_dimensions).into();
+        // The region follows the animated size while resizing so the window grows and shrinks
+        // smoothly instead of snapping to the new grid size
+        let current_size = self.animation.current_size();
+        let region_size = (
+            current_size.x * font_dimensions.width as f32,
+            current_size.y * font_dimensions.height as f32,
+        );
 
-        Rect::from_point_and_size(current_pixel_position, image_size)
+        Rect::from_point_and_size(current_pixel_position, region_size)
+    }
+
+    fn surface_region(&self, pixel_region: Rect, font_dimensions: Dimensions) -> Rect {
+        let image_size: (i32, i32) = (self.grid_size * font_dimensions).into();
+        Rect::from_point_and_size(pixel_region.top_left(), image_size)
     }
 
     pub fn update(&mut self, settings: &RendererSettings, dt: f32) -> bool {
         let mut animating = false;
+        // Parking t at the end still takes one more frame, like an animation that just finished
+        let reduce_motion = settings.reduce_motion();
 
         {
             if 1.0 - self.position_t < std::f32::EPSILON {
@@ -163,8 +260,11 @@ impl RenderedWindow {
                 self.position_t = 2.0;
             } else {
                 animating = true;
-                self.position_t =
-                    (self.position_t + dt / settings.position_animation_length).min(1.0);
+                self.position_t = if reduce_motion {
+                    1.0
+                } else {
+                    (self.position_t + dt / settings.position_animation_length).min(1.0)
+                };
             }
 
             self.grid_current_position = ease_point(
@@ -182,17 +282,30 @@ impl RenderedWindow {
                 self.snapshots.clear();
             } else {
                 animating = true;
-                self.scroll_t = (self.scroll_t + dt / settings.scroll_animation_length).min(1.0);
+                self.scroll_t = if reduce_motion {
+                    1.0
+                } else {
+                    (self.scroll_t + dt / settings.scroll_animation_length).min(1.0)
+                };
             }
 
             self.current_scroll = ease(
-                ease_out_expo,
+                easing_function_from_name(&settings.scroll_animation_easing),
                 self.start_scroll,
                 self.scroll_destination,
-                self.scroll_t,
+                // t is parked at 2.0 once the animation is done, which only lands on the
+                // destination for easing functions that flatten out
+                self.scroll_t.min(1.0),
             );
         }
 
+        if reduce_motion || !settings.elastic_overscroll {
+            self.overscroll.reset();
+        }
+        animating |= self.overscroll.update(dt);
+
+        animating |= self.animation.update(settings, dt);
+
         animating
     }
 
@@ -202,23 +315,49 @@ impl RenderedWindow {
         settings: &RendererSettings,
         default_background: Color,
         font_dimensions: Dimensions,
+        current: bool,
         dt: f32,
     ) -> WindowDrawDetails {
+        profile_scope!("draw window");
         if self.update(settings, dt) {
             REDRAW_SCHEDULER.queue_next_frame();
         }
 
         let pixel_region = self.pixel_region(font_dimensions);
+        let surface_region = self.surface_region(pixel_region, font_dimensions);
+        let floating = self.floating_order.is_some();
+        let visibility = self.animation.visibility();
 
         root_canvas.save();
-        root_canvas.clip_rect(&pixel_region, None, Some(false));
+        self.animation.apply_transform(
+            root_canvas,
+            settings,
+            pixel_region,
+            font_dimensions.height as f32,
+        );
 
-        if self.floating_order.is_none() {
+        // Shadows blur the edges of the window, which high contrast themes want crisp
+        if floating && settings.floating_shadow && high_contrast_colors().is_none() {
+            draw_shadow(root_canvas, settings, pixel_region);
+        }
+
+        root_canvas.save();
+        if floating && settings.floating_corner_radius > 0.0 {
+            let radius = settings.floating_corner_radius;
+            let rounded_region = RRect::new_rect_xy(&pixel_region, radius, radius);
+            root_canvas.clip_rrect(&rounded_region, None, Some(true));
+        } else {
+            root_canvas.clip_rect(&pixel_region, None, Some(false));
+        }
+
+        // The background layer was already drawn beneath the windows, so clearing would erase it
+        if self.floating_order.is_none() && !settings.has_background_layer() {
             root_canvas.clear(default_background);
         }
 
-        if self.floating_order.is_some() && settings.floating_blur {
-            let blur = blur((2.0, 2.0), None, None, None).unwrap();
+        let backdrop_blur = self.backdrop_blur(settings);
+        if let Some(sigma) = backdrop_blur {
+            let blur = blur((sigma, sigma), None, None, None).unwrap();
             let save_layer_rec = SaveLayerRec::default()
                 .backdrop(&blur)
                 .bounds(&pixel_region);
@@ -238,6 +377,7 @@ impl RenderedWindow {
         if self.floating_order.is_some() {
             a = (settings.floating_opacity.min(1.0).max(0.0) * 255.0) as u8;
         }
+        a = (a as f32 * visibility) as u8;
 
         paint.set_color(default_background.with_a(a));
         root_canvas.draw_rect(pixel_region, &paint);
@@ -249,44 +389,83 @@ impl RenderedWindow {
         // Draw scrolling snapshots
         for snapshot in self.snapshots.iter_mut().rev() {
             let scroll_offset = (snapshot.top_line * font_height) as f32
-                - (self.current_scroll * font_height as f32);
+                - ((self.current_scroll - self.pixel_scroll_offset - self.overscroll.offset())
+                    * font_height as f32);
             let image = &mut snapshot.image;
             root_canvas.draw_image_rect(
                 image,
                 None,
-                pixel_region.with_offset((0.0, scroll_offset as f32)),
+                surface_region.with_offset((0.0, scroll_offset as f32)),
                 &paint,
             );
         }
         // Draw current surface
         let scroll_offset = (self.current_surface.top_line * font_height) as f32
-            - (self.current_scroll * font_height as f32);
+            - ((self.current_scroll - self.pixel_scroll_offset - self.overscroll.offset())
+                * font_height as f32);
         let snapshot = self.current_surface.surface.image_snapshot();
         root_canvas.draw_image_rect(
             snapshot,
             None,
-            pixel_region.with_offset((0.0, scroll_offset as f32)),
+            surface_region.with_offset((0.0, scroll_offset as f32)),
             &paint,
         );
+        self.decorations.draw(
+            root_canvas,
+            surface_region.top_left() + Point::new(0.0, scroll_offset as f32),
+            font_dimensions,
+            visibility,
+        );
 
         root_canvas.restore();
 
-        if self.floating_order.is_some() {
+        if backdrop_blur.is_some() {
             root_canvas.restore();
         }
 
+        // Only the window holding the cursor gets a minimap so splits don't all lose a column
+        let minimap = if settings.minimap && current {
+            self.minimap.draw(
+                root_canvas,
+                settings,
+                default_background,
+                self.grid_size.width,
+                pixel_region,
+            )
+        } else {
+            None
+        };
+        let scrollbar = self.scrollbar.draw(root_canvas, settings, pixel_region);
+
+        root_canvas.restore();
         root_canvas.restore();
 
         WindowDrawDetails {
             id: self.id,
             region: pixel_region,
             floating_order: self.floating_order,
+            scrollbar,
+            minimap,
+        }
+    }
+
+    // How much the content beneath the window is blurred, if at all. Floats with a blend get
+    // their own blur, so a frosted look can be kept to hovers and pickers.
+    fn backdrop_blur(&self, settings: &RendererSettings) -> Option<f32> {
+        self.floating_order?;
+        if self.blend > 0 && settings.floating_blend_blur > 0.0 {
+            Some(settings.floating_blend_blur)
+        } else if settings.floating_blur {
+            Some(FLOATING_BLUR_SIGMA)
+        } else {
+            None
         }
     }
 
     pub fn handle_window_draw_command(
         &mut self,
         grid_renderer: &mut GridRenderer,
+        settings: &RendererSettings,
         draw_command: WindowDrawCommand,
     ) {
         match draw_command {
@@ -330,38 +509,47 @@ impl RenderedWindow {
 
                     self.current_surface.surface = new_surface;
                     self.grid_size = new_grid_size;
+                    // The root grid tracks the window size, which is already animated by the os
+                    self.animation.resize(new_grid_size, self.id != 1);
                 }
 
                 self.floating_order = floating_order;
 
+                if self.animation.is_closing() {
+                    // The grid was reused before its close animation finished
+                    self.animation.appear();
+                }
+
                 if self.hidden {
                     self.hidden = false;
+                    self.animation.appear();
                     self.position_t = 2.0; // We don't want to animate since the window is becoming visible, so we set t to 2.0 to stop animations.
                     self.grid_start_position = new_destination;
                     self.grid_destination = new_destination;
                 }
             }
             WindowDrawCommand::DrawLine(line_fragments) => {
+                if settings.minimap {
+                    self.minimap.draw_line(&line_fragments, grid_renderer);
+                }
+                self.links.draw_line(&line_fragments, settings.detect_urls);
+                if let Some(blend) = line_fragments
+                    .iter()
+                    .filter_map(|line_fragment| line_fragment.style.as_ref())
+                    .map(|style| style.blend)
+                    .max()
+                {
+                    self.blend = self.blend.max(blend);
+                }
+
                 let canvas = self.current_surface.surface.canvas();
 
                 canvas.save();
-                for line_fragment in line_fragments.iter() {
-                    let LineFragment {
-                        window_left,
-                        window_top,
-                        width,
-                        style,
-                        ..
-                    } = line_fragment;
-                    let grid_position = (*window_left, *window_top);
-                    grid_renderer.draw_background(
-                        canvas,
-                        grid_position,
-                        *width,
-                        style,
-                        self.floating_order.is_some(),
-                    );
-                }
+                grid_renderer.draw_line_background(
+                    canvas,
+                    &line_fragments,
+                    self.floating_order.is_some(),
+                );
 
                 for line_fragment in line_fragments.into_iter() {
                     let LineFragment {
@@ -370,9 +558,17 @@ impl RenderedWindow {
                         window_top,
                         width,
                         style,
+                        right_to_left,
                     } = line_fragment;
                     let grid_position = (window_left, window_top);
-                    grid_renderer.draw_foreground(canvas, text, grid_position, width, &style);
+                    grid_renderer.draw_foreground(
+                        canvas,
+                        text,
+                        grid_position,
+                        width,
+                        &style,
+                        right_to_left,
+                    );
                 }
                 canvas.restore();
             }
@@ -384,6 +580,10 @@ impl RenderedWindow {
                 rows,
                 cols,
             } => {
+                self.minimap.scroll(top, bottom, rows);
+                self.links.scroll(top, bottom, rows);
+                self.decorations.scroll(top, bottom, left, right, rows);
+
                 let Dimensions {
                     width: font_width,
                     height: font_height,
@@ -424,16 +624,42 @@ impl RenderedWindow {
                 );
 
                 self.snapshots.clear();
+                self.minimap.clear();
+                self.blend = 0;
+                self.links.clear();
             }
             WindowDrawCommand::Show => {
                 if self.hidden {
                     self.hidden = false;
+                    self.animation.appear();
                     self.position_t = 2.0; // We don't want to animate since the window is becoming visible, so we set t to 2.0 to stop animations.
                     self.grid_start_position = self.grid_destination;
                 }
             }
             WindowDrawCommand::Hide => self.hidden = true,
-            WindowDrawCommand::Viewport { top_line, .. } => {
+            WindowDrawCommand::Decorate(decoration) => self.decorations.set(decoration),
+            WindowDrawCommand::RemoveDecoration(id) => self.decorations.remove(&id),
+            WindowDrawCommand::ClearDecorations => self.decorations.clear(),
+            WindowDrawCommand::External(external) => {
+                self.external = external;
+                if external {
+                    self.floating_order = None;
+                }
+            }
+            WindowDrawCommand::Viewport {
+                window_handle,
+                top_line,
+                bottom_line,
+                line_count,
+            } => {
+                self.window_handle = Some(window_handle);
+                self.scrollbar
+                    .update(window_handle, top_line, bottom_line, line_count);
+                self.minimap
+                    .update_viewport(window_handle, top_line, bottom_line, line_count);
+                self.overscroll
+                    .update_viewport(top_line, bottom_line, line_count);
+
                 if self.current_surface.top_line != top_line as u64 {
                     let new_snapshot = self.current_surface.snapshot();
                     self.snapshots.push_back(new_snapshot);
@@ -444,10 +670,27 @@ impl RenderedWindow {
 
                     self.current_surface.top_line = top_line as u64;
 
-                    // Set new target viewport position and initialize animation timer
-                    self.start_scroll = self.current_scroll;
-                    self.scroll_destination = top_line as f32;
-                    self.scroll_t = 0.0;
+                    let far_lines = SETTINGS
+                        .get::<RendererSettings>()
+                        .scroll_animation_far_lines;
+                    let scroll_distance = (top_line as f32 - self.current_scroll).abs();
+                    // Touchpad scrolling already moves with the fingers, animating the whole
+                    // lines on top of that would make it lag behind
+                    let pixel_scrolling = self.pixel_scroll_offset.abs() > std::f32::EPSILON;
+                    if pixel_scrolling || far_lines > 0 && scroll_distance > far_lines as f32 {
+                        // Animating across large jumps only shows a blur of unrelated lines, so
+                        // snap straight to the destination instead
+                        self.start_scroll = top_line as f32;
+                        self.current_scroll = top_line as f32;
+                        self.scroll_destination = top_line as f32;
+                        self.scroll_t = 2.0;
+                        self.snapshots.clear();
+                    } else {
+                        // Set new target viewport position and initialize animation timer
+                        self.start_scroll = self.current_scroll;
+                        self.scroll_destination = top_line as f32;
+                        self.scroll_t = 0.0;
+                    }
                 }
             }
             _ => {}
@@ -0,0 +1,157 @@
+use std::time::{Duration, Instant};
+
+use skia_safe::{Canvas, Color, Paint, Point, RRect, Rect};
+
+use super::{high_contrast_colors, RendererSettings};
+use crate::redraw_scheduler::REDRAW_SCHEDULER;
+
+const FADE_LENGTH: f32 = 0.3;
+const MIN_THUMB_LENGTH_FACTOR: f32 = 2.0;
+
+/// Where a window's scrollbar was drawn, so the mouse can jump through the buffer by clicking or
+/// dragging on it.
+#[derive(Clone, Debug)]
+pub struct ScrollbarDetails {
+    pub window_handle: u64,
+    pub track: Rect,
+    pub line_count: f64,
+}
+
+impl ScrollbarDetails {
+    pub fn contains(&self, point: Point) -> bool {
+        self.track.contains(point)
+    }
+
+    /// Converts a vertical pixel position on the track into a one based buffer line.
+    pub fn line_at(&self, y: f32) -> u64 {
+        let fraction = ((y - self.track.top) / self.track.height())
+            .min(1.0)
+            .max(0.0) as f64;
+        ((fraction * self.line_count) as u64 + 1).min(self.line_count.max(1.0) as u64)
+    }
+}
+
+pub struct Scrollbar {
+    window_handle: u64,
+    top_line: f64,
+    bottom_line: f64,
+    line_count: Option<f64>,
+    last_change: Instant,
+}
+
+impl Scrollbar {
+    pub fn new() -> Scrollbar {
+        Scrollbar {
+            window_handle: 0,
+            top_line: 0.0,
+            bottom_line: 0.0,
+            line_count: None,
+            last_change: Instant::now(),
+        }
+    }
+
+    pub fn update(
+        &mut self,
+        window_handle: u64,
+        top_line: f64,
+        bottom_line: f64,
+        line_count: Option<f64>,
+    ) {
+        self.window_handle = window_handle;
+        self.top_line = top_line;
+        self.bottom_line = bottom_line;
+        self.line_count = line_count;
+        self.last_change = Instant::now();
+    }
+
+    // Stays fully visible for the fade delay after the viewport last moved, then fades out
+    fn opacity(&self, settings: &RendererSettings) -> f32 {
+        if settings.scrollbar_fade_delay <= 0.0 {
+            return 1.0;
+        }
+
+        let idle = self.last_change.elapsed().as_secs_f32();
+        if idle < settings.scrollbar_fade_delay {
+            REDRAW_SCHEDULER.schedule(
+                self.last_change + Duration::from_secs_f32(settings.scrollbar_fade_delay),
+            );
+            1.0
+        } else {
+            let fade = (idle - settings.scrollbar_fade_delay) / FADE_LENGTH;
+            if fade < 1.0 {
+                REDRAW_SCHEDULER.queue_next_frame();
+            }
+            (1.0 - fade).max(0.0)
+        }
+    }
+
+    pub fn draw(
+        &self,
+        root_canvas: &mut Canvas,
+        settings: &RendererSettings,
+        pixel_region: Rect,
+    ) -> Option<ScrollbarDetails> {
+        if !settings.scrollbar {
+            return None;
+        }
+
+        let line_count = self.line_count?;
+        let visible_lines = self.bottom_line - self.top_line;
+        if line_count <= visible_lines || line_count <= 0.0 {
+            return None;
+        }
+
+        let width = settings.scrollbar_width.max(1.0);
+        let track = Rect::new(
+            pixel_region.right - width,
+            pixel_region.top,
+            pixel_region.right,
+            pixel_region.bottom,
+        );
+
+        let opacity = self.opacity(settings);
+        if opacity > 0.0 {
+            let track_height = track.height();
+            let thumb_height = (visible_lines / line_count) as f32 * track_height;
+            let thumb_height = thumb_height.max(width * MIN_THUMB_LENGTH_FACTOR);
+            let scrolled_fraction = (self.top_line / (line_count - visible_lines)).min(1.0);
+            let thumb_top = track.top + scrolled_fraction as f32 * (track_height - thumb_height);
+            let thumb = Rect::from_xywh(track.left, thumb_top, width, thumb_height);
+
+            let mut paint = Paint::default();
+            paint.set_anti_alias(true);
+            let color = match high_contrast_colors() {
+                Some(colors) => colors.foreground.with_a((opacity * 255.0) as u8),
+                None => Color::from_argb((opacity * 140.0) as u8, 128, 128, 128),
+            };
+            paint.set_color(color);
+            root_canvas.draw_rrect(RRect::new_rect_xy(&thumb, width / 2.0, width / 2.0), &paint);
+        }
+
+        Some(ScrollbarDetails {
+            window_handle: self.window_handle,
+            track,
+            line_count,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_line_at() {
+        let details = ScrollbarDetails {
+            window_handle: 1000,
+            track: Rect::from_xywh(100.0, 0.0, 6.0, 200.0),
+            line_count: 100.0,
+        };
+
+        assert_eq!(details.line_at(0.0), 1);
+        assert_eq!(details.line_at(100.0), 51);
+        assert_eq!(details.line_at(200.0), 100);
+        assert_eq!(details.line_at(-50.0), 1);
+        assert_eq!(details.line_at(500.0), 100);
+    }
+}
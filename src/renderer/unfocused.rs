@@ -0,0 +1,60 @@
+use skia_safe::{color_filters, ColorFilter};
+
+// The luminance weights of the Rec. 709 primaries, which desaturated colors are mixed towards
+const LUMINANCE: [f32; 3] = [0.2126, 0.7152, 0.0722];
+
+// Row major, mixing each channel towards the luminance before scaling it down. Alpha is kept.
+fn color_matrix(dim: f32, desaturate: f32) -> [f32; 20] {
+    let brightness = 1.0 - dim.max(0.0).min(1.0);
+    let saturation = 1.0 - desaturate.max(0.0).min(1.0);
+    let mut matrix = [0.0; 20];
+    for row in 0..3 {
+        for column in 0..3 {
+            let identity = if row == column { 1.0 } else { 0.0 };
+            matrix[row * 5 + column] =
+                brightness * (saturation * identity + (1.0 - saturation) * LUMINANCE[column]);
+        }
+    }
+    matrix[18] = 1.0;
+    matrix
+}
+
+/// The filter the grid is drawn through while the window doesn't have the focus, or None when
+/// neither dimming nor desaturating is asked for.
+pub fn unfocused_filter(dim: f32, desaturate: f32) -> Option<ColorFilter> {
+    if dim <= 0.0 && desaturate <= 0.0 {
+        return None;
+    }
+    Some(color_filters::matrix_row_major(&color_matrix(
+        dim, desaturate,
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apply(matrix: &[f32; 20], color: [f32; 3]) -> [f32; 3] {
+        let mut result = [0.0; 3];
+        for (row, channel) in result.iter_mut().enumerate() {
+            *channel = (0..3)
+                .map(|column| matrix[row * 5 + column] * color[column])
+                .sum();
+        }
+        result
+    }
+
+    #[test]
+    fn test_color_matrix() {
+        let red = [1.0, 0.0, 0.0];
+        assert_eq!(apply(&color_matrix(0.0, 0.0), red), red);
+        assert_eq!(apply(&color_matrix(0.5, 0.0), red), [0.5, 0.0, 0.0]);
+        // Fully desaturated colors are gray with the same luminance
+        assert_eq!(
+            apply(&color_matrix(0.0, 1.0), red),
+            [LUMINANCE[0], LUMINANCE[0], LUMINANCE[0]]
+        );
+        assert_eq!(color_matrix(0.3, 0.6)[18], 1.0);
+        assert!(unfocused_filter(0.0, 0.0).is_none());
+    }
+}
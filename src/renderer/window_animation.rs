@@ -0,0 +1,231 @@
+use skia_safe::{Canvas, Point, Rect};
+
+use super::animation_utils::*;
+use super::RendererSettings;
+use crate::utils::Dimensions;
+
+const SLIDE_DISTANCE_LINES: f32 = 2.0;
+const SCALE_START: f32 = 0.9;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WindowAnimationStyle {
+    Fade,
+    Slide,
+    Scale,
+}
+
+impl WindowAnimationStyle {
+    pub fn from_name(name: &str) -> WindowAnimationStyle {
+        match name {
+            "slide" => WindowAnimationStyle::Slide,
+            "scale" => WindowAnimationStyle::Scale,
+            _ => WindowAnimationStyle::Fade,
+        }
+    }
+}
+
+// Advances t towards 1.0 and returns whether the animation is still running. Like the other
+// window animations, t is parked at 2.0 once it has finished.
+fn advance(t: &mut f32, settings: &RendererSettings, dt: f32) -> bool {
+    if !settings.window_animations || settings.reduce_motion() || 1.0 - *t < std::f32::EPSILON {
+        *t = 2.0;
+        false
+    } else {
+        *t = (*t + dt / settings.window_animation_length).min(1.0);
+        true
+    }
+}
+
+/// Tracks a window appearing, closing and being resized so the layout changes animate instead of
+/// snapping into place.
+pub struct WindowAnimation {
+    appear_t: f32,
+    closing: bool,
+    close_t: f32,
+
+    size_start: Point,
+    size_current: Point,
+    size_destination: Point,
+    size_t: f32,
+}
+
+impl WindowAnimation {
+    pub fn new(grid_size: Dimensions) -> WindowAnimation {
+        let size = Point::new(grid_size.width as f32, grid_size.height as f32);
+
+        WindowAnimation {
+            appear_t: 2.0, // 2.0 is out of the 0.0 to 1.0 range and stops animation
+            closing: false,
+            close_t: 2.0,
+
+            size_start: size,
+            size_current: size,
+            size_destination: size,
+            size_t: 2.0,
+        }
+    }
+
+    pub fn appear(&mut self) {
+        self.appear_t = 0.0;
+        self.closing = false;
+    }
+
+    pub fn close(&mut self) {
+        if !self.closing {
+            self.closing = true;
+            self.close_t = 0.0;
+        }
+    }
+
+    pub fn is_closing(&self) -> bool {
+        self.closing
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closing && self.close_t > 1.0
+    }
+
+    pub fn resize(&mut self, new_grid_size: Dimensions, animate: bool) {
+        self.size_destination = Point::new(new_grid_size.width as f32, new_grid_size.height as f32);
+        if animate {
+            self.size_start = self.size_current;
+            self.size_t = 0.0;
+        } else {
+            self.size_start = self.size_destination;
+            self.size_current = self.size_destination;
+            self.size_t = 2.0;
+        }
+    }
+
+    /// The size of the window in grid cells, which is fractional while resizing.
+    pub fn current_size(&self) -> Point {
+        self.size_current
+    }
+
+    pub fn update(&mut self, settings: &RendererSettings, dt: f32) -> bool {
+        let mut animating = advance(&mut self.appear_t, settings, dt);
+
+        if self.closing {
+            animating |= advance(&mut self.close_t, settings, dt);
+        }
+
+        animating |= advance(&mut self.size_t, settings, dt);
+        self.size_current = ease_point(
+            ease_out_expo,
+            self.size_start,
+            self.size_destination,
+            self.size_t.min(1.0),
+        );
+
+        animating
+    }
+
+    /// How far the window has appeared, from 0.0 (invisible) to 1.0 (fully shown).
+    pub fn visibility(&self) -> f32 {
+        if self.closing {
+            1.0 - ease_out_cubic(self.close_t.min(1.0))
+        } else {
+            ease_out_cubic(self.appear_t.min(1.0))
+        }
+    }
+
+    /// Moves or scales the canvas for the current animation style. Every style also fades, which
+    /// is applied by the caller through the visibility.
+    pub fn apply_transform(
+        &self,
+        root_canvas: &mut Canvas,
+        settings: &RendererSettings,
+        pixel_region: Rect,
+        font_height: f32,
+    ) {
+        let hidden_amount = 1.0 - self.visibility();
+        if hidden_amount <= 0.0 {
+            return;
+        }
+
+        match WindowAnimationStyle::from_name(&settings.window_animation_style) {
+            WindowAnimationStyle::Fade => {}
+            WindowAnimationStyle::Slide => {
+                root_canvas.translate((0.0, hidden_amount * SLIDE_DISTANCE_LINES * font_height));
+            }
+            WindowAnimationStyle::Scale => {
+                let scale = lerp(1.0, SCALE_START, hidden_amount);
+                let center = pixel_region.center();
+                root_canvas.translate((center.x, center.y));
+                root_canvas.scale((scale, scale));
+                root_canvas.translate((-center.x, -center.y));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::ReduceMotion;
+
+    #[test]
+    fn test_close_finishes_immediately_without_animations() {
+        let settings = RendererSettings::default();
+        let mut animation = WindowAnimation::new(Dimensions {
+            width: 10,
+            height: 10,
+        });
+
+        animation.close();
+        assert!(animation.is_closing());
+        assert!(!animation.is_closed());
+
+        animation.update(&settings, 0.01);
+        assert!(animation.is_closed());
+    }
+
+    #[test]
+    fn test_resize_animates_towards_new_size() {
+        let settings = RendererSettings {
+            window_animations: true,
+            ..RendererSettings::default()
+        };
+        let mut animation = WindowAnimation::new(Dimensions {
+            width: 10,
+            height: 10,
+        });
+
+        animation.resize(
+            Dimensions {
+                width: 20,
+                height: 10,
+            },
+            true,
+        );
+        assert!(animation.update(&settings, 0.01));
+        assert!(animation.current_size().x > 10.0);
+        assert!(animation.current_size().x < 20.0);
+
+        while animation.update(&settings, 0.05) {}
+        assert_eq!(animation.current_size(), Point::new(20.0, 10.0));
+    }
+
+    #[test]
+    fn test_reduce_motion_skips_the_animation() {
+        let settings = RendererSettings {
+            window_animations: true,
+            reduce_motion: ReduceMotion::On,
+            ..RendererSettings::default()
+        };
+        let mut animation = WindowAnimation::new(Dimensions {
+            width: 10,
+            height: 10,
+        });
+
+        animation.resize(
+            Dimensions {
+                width: 20,
+                height: 10,
+            },
+            true,
+        );
+        assert!(!animation.update(&settings, 0.01));
+        assert_eq!(animation.current_size(), Point::new(20.0, 10.0));
+    }
+}
@@ -4,19 +4,21 @@ use std::sync::{
 };
 
 use log::info;
+use parking_lot::Mutex;
 
-lazy_static! {
-    pub static ref RUNNING_TRACKER: RunningTracker = RunningTracker::new();
-}
-
+/// Tracks whether a neovim instance and the window attached to it are still running. Clones share
+/// the same state, so the bridge can mark the instance as finished for the window to pick up.
+#[derive(Clone)]
 pub struct RunningTracker {
     running: Arc<AtomicBool>,
+    exit_error: Arc<Mutex<Option<String>>>,
 }
 
 impl RunningTracker {
-    fn new() -> Self {
+    pub fn new() -> Self {
         Self {
             running: Arc::new(AtomicBool::new(true)),
+            exit_error: Arc::new(Mutex::new(None)),
         }
     }
 
@@ -25,7 +27,17 @@ impl RunningTracker {
         info!("Quit {}", reason);
     }
 
+    /// Quits and keeps the error around so the window can tell the user why it closed.
+    pub fn quit_with_error(&self, reason: &str, error: String) {
+        *self.exit_error.lock() = Some(error);
+        self.quit(reason);
+    }
+
     pub fn is_running(&self) -> bool {
         self.running.load(Ordering::Relaxed)
     }
+
+    pub fn exit_error(&self) -> Option<String> {
+        self.exit_error.lock().clone()
+    }
 }
@@ -0,0 +1,504 @@
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use parking_lot::RwLock;
+use toml::value::Table;
+
+use super::Value;
+use crate::error_handling::show_confirm_dialog;
+
+// Picks a different file, for example to try out a config without replacing the current one
+const CONFIG_VARIABLE: &str = "NEOVIDE_CONFIG";
+
+// Settings which the command line and environment already feed into the defaults of their
+// setting group. Applying them from the config again would override the command line.
+const COMMAND_LINE_SETTINGS: [&str; 4] = ["transparency", "fullscreen", "frameless", "no_idle"];
+
+// A project can only change how neovide looks, never what it runs
+const PROJECT_CONFIG_NAME: &str = ".neovide.toml";
+const PROJECT_KEYS: [&str; 3] = ["geometry", "font", "theme"];
+
+lazy_static! {
+    // Empty until a config is loaded, so anything that runs without one sees the defaults
+    static ref CONFIG: RwLock<Table> = RwLock::new(Table::new());
+}
+
+/// Makes what the loader returns the config, usually `user_config`. Has to run before the command
+/// line is handled, which takes its defaults from the config.
+pub fn load_config(loader: impl FnOnce() -> Table) {
+    *CONFIG.write() = loader();
+}
+
+/// The config file, without the project config, which `load_project_config` adds later.
+pub fn user_config() -> Table {
+    read_config(config_path(), |path| std::fs::read_to_string(path))
+}
+
+/// Adds the project config of the working directory to the loaded config, if it is trusted.
+/// Asking about an unknown project waits for the user, so it runs once neovide disowned the
+/// terminal it was started from.
+pub fn load_project_config() {
+    let mut config = CONFIG.read().clone();
+    apply_project_config(&mut config);
+    *CONFIG.write() = config;
+}
+
+/// Where the config is read from, `neovide/config.toml` in the platform's config directory.
+pub fn config_path() -> Option<PathBuf> {
+    std::env::var_os(CONFIG_VARIABLE)
+        .map(PathBuf::from)
+        .or_else(|| {
+            dirs::config_dir().map(|directory| directory.join("neovide").join("config.toml"))
+        })
+}
+
+fn parse_config(contents: &str) -> Result<Table, String> {
+    contents.parse::<Table>().map_err(|error| error.to_string())
+}
+
+// A missing config is the same as an empty one
+fn read_config(
+    path: Option<PathBuf>,
+    read: impl FnOnce(&Path) -> std::io::Result<String>,
+) -> Table {
+    let path = match path {
+        Some(path) => path,
+        None => return Table::new(),
+    };
+    let contents = match read(&path) {
+        Err(error) if error.kind() == ErrorKind::NotFound => return Table::new(),
+        contents => contents.map_err(|error| error.to_string()),
+    };
+    // The logger isn't running yet while the command line is parsed
+    match contents.and_then(|contents| parse_config(&contents)) {
+        Ok(config) => config,
+        Err(error) => {
+            eprintln!("Could not load {}: {}", path.display(), error);
+            Table::new()
+        }
+    }
+}
+
+// A project config in the working directory or one of its parents, stopping at the root of the
+// repository it is in
+fn find_project_config(start: &Path) -> Option<PathBuf> {
+    for directory in start.ancestors() {
+        let path = directory.join(PROJECT_CONFIG_NAME);
+        if path.is_file() {
+            return Some(path);
+        }
+        if directory.join(".git").exists() {
+            return None;
+        }
+    }
+    None
+}
+
+fn trust_store_path() -> Option<PathBuf> {
+    dirs::data_local_dir().map(|directory| directory.join("neovide").join("trusted-projects"))
+}
+
+// Each line of the store is "trust <hash> <path>" or "ignore <hash> <path>", so projects are only
+// asked about once either way. The hash is the sha-256 of the file the answer was given for, so
+// an edited file is asked about again instead of staying trusted.
+fn stored_decision(store: &str, project: &Path, hash: &str) -> Option<bool> {
+    let project = project.to_string_lossy();
+    store.lines().find_map(|line| {
+        let mut parts = line.splitn(3, ' ');
+        let decision = match parts.next()? {
+            "trust" => true,
+            "ignore" => false,
+            _ => return None,
+        };
+        (parts.next()? == hash && parts.next()? == project).then(|| decision)
+    })
+}
+
+// Whether the project is trusted, along with the store to save when the user was asked. Without
+// an answer the project isn't used this time and asked about again on the next launch.
+fn decide_trust(
+    store: &str,
+    project: &Path,
+    hash: &str,
+    ask: impl FnOnce() -> Option<bool>,
+) -> (bool, Option<String>) {
+    if let Some(trusted) = stored_decision(store, project, hash) {
+        return (trusted, None);
+    }
+    match ask() {
+        Some(trusted) => {
+            let decision = if trusted { "trust" } else { "ignore" };
+            // The answers about earlier versions of the file don't matter anymore
+            let project_name = project.to_string_lossy();
+            let mut new_store: String = store
+                .lines()
+                .filter(|line| line.splitn(3, ' ').nth(2) != Some(&project_name))
+                .map(|line| format!("{}\n", line))
+                .collect();
+            new_store.push_str(&format!("{} {} {}\n", decision, hash, project_name));
+            (trusted, Some(new_store))
+        }
+        None => (false, None),
+    }
+}
+
+fn sha256(bytes: &[u8]) -> [u8; 32] {
+    const ROUND_CONSTANTS: [u32; 64] = [
+        0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4,
+        0xab1c5ed5, 0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe,
+        0x9bdc06a7, 0xc19bf174, 0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f,
+        0x4a7484aa, 0x5cb0a9dc, 0x76f988da, 0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7,
+        0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967, 0x27b70a85, 0x2e1b2138, 0x4d2c6dfc,
+        0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85, 0xa2bfe8a1, 0xa81a664b,
+        0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070, 0x19a4c116,
+        0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+        0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7,
+        0xc67178f2,
+    ];
+    let mut state: [u32; 8] = [
+        0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab,
+        0x5be0cd19,
+    ];
+
+    // The message is padded with a one bit, zeros and its length in bits to whole blocks
+    let mut message = bytes.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((bytes.len() as u64) * 8).to_be_bytes());
+
+    for block in message.chunks_exact(64) {
+        let mut schedule = [0u32; 64];
+        for (index, word) in block.chunks_exact(4).enumerate() {
+            schedule[index] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for index in 16..64 {
+            let s0 = schedule[index - 15].rotate_right(7)
+                ^ schedule[index - 15].rotate_right(18)
+                ^ (schedule[index - 15] >> 3);
+            let s1 = schedule[index - 2].rotate_right(17)
+                ^ schedule[index - 2].rotate_right(19)
+                ^ (schedule[index - 2] >> 10);
+            schedule[index] = schedule[index - 16]
+                .wrapping_add(s0)
+                .wrapping_add(schedule[index - 7])
+                .wrapping_add(s1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = state;
+        for index in 0..64 {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let choice = (e & f) ^ (!e & g);
+            let temp1 = h
+                .wrapping_add(s1)
+                .wrapping_add(choice)
+                .wrapping_add(ROUND_CONSTANTS[index])
+                .wrapping_add(schedule[index]);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let majority = (a & b) ^ (a & c) ^ (b & c);
+            let temp2 = s0.wrapping_add(majority);
+            h = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(temp1);
+            d = c;
+            c = b;
+            b = a;
+            a = temp1.wrapping_add(temp2);
+        }
+        for (word, value) in state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+            *word = word.wrapping_add(value);
+        }
+    }
+
+    let mut digest = [0; 32];
+    for (bytes, word) in digest.chunks_exact_mut(4).zip(state.iter()) {
+        bytes.copy_from_slice(&word.to_be_bytes());
+    }
+    digest
+}
+
+fn content_hash(contents: &str) -> String {
+    sha256(contents.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+fn is_trusted(project: &Path, contents: &str) -> bool {
+    let store_path = match trust_store_path() {
+        Some(store_path) => store_path,
+        None => return false,
+    };
+    let store = std::fs::read_to_string(&store_path).unwrap_or_default();
+    let hash = content_hash(contents);
+    let (trusted, new_store) = decide_trust(&store, project, &hash, || {
+        show_confirm_dialog(
+            "Neovide project config",
+            &format!(
+                "Use the window size, font and theme from {}?",
+                project.display()
+            ),
+        )
+    });
+
+    if let Some(new_store) = new_store {
+        let saved = store_path
+            .parent()
+            .map_or(Ok(()), std::fs::create_dir_all)
+            .and_then(|_| std::fs::write(&store_path, new_store));
+        if let Err(error) = saved {
+            eprintln!("Could not save {}: {}", store_path.display(), error);
+        }
+    }
+    trusted
+}
+
+fn project_values(contents: &str) -> Result<Table, String> {
+    Ok(parse_config(contents)?
+        .into_iter()
+        .filter(|(key, _)| PROJECT_KEYS.contains(&key.as_str()))
+        .collect())
+}
+
+fn apply_project_config(config: &mut Table) {
+    let path = match std::env::current_dir()
+        .ok()
+        .and_then(|directory| find_project_config(&directory))
+    {
+        Some(path) => path,
+        None => return,
+    };
+    // The contents are read once, so what is applied is what the trust was decided for
+    let contents = match std::fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(error) => {
+            eprintln!("Could not load {}: {}", path.display(), error);
+            return;
+        }
+    };
+    if !is_trusted(&path, &contents) {
+        return;
+    }
+    match project_values(&contents) {
+        Ok(project) => config.extend(project),
+        Err(error) => eprintln!("Could not load {}: {}", path.display(), error),
+    }
+}
+
+// Config keys are named like the environment variables without the NEOVIDE_ prefix, so
+// NEOVIDE_MULTIGRID=1 and `multigrid = true` mean the same
+fn config_key(variable: &str) -> String {
+    variable
+        .strip_prefix("NEOVIDE_")
+        .unwrap_or(variable)
+        .to_lowercase()
+}
+
+fn toml_to_string(value: &toml::Value) -> Option<String> {
+    match value {
+        toml::Value::String(value) => Some(value.clone()),
+        toml::Value::Integer(value) => Some(value.to_string()),
+        toml::Value::Float(value) => Some(value.to_string()),
+        _ => None,
+    }
+}
+
+fn toml_to_value(value: &toml::Value) -> Value {
+    match value {
+        toml::Value::String(value) => Value::from(value.as_str()),
+        toml::Value::Integer(value) => Value::from(*value),
+        toml::Value::Float(value) => Value::from(*value),
+        toml::Value::Boolean(value) => Value::from(*value),
+        toml::Value::Datetime(value) => Value::from(value.to_string()),
+        toml::Value::Array(values) => Value::Array(values.iter().map(toml_to_value).collect()),
+        toml::Value::Table(table) => Value::Map(
+            table
+                .iter()
+                .map(|(key, value)| (Value::from(key.as_str()), toml_to_value(value)))
+                .collect(),
+        ),
+    }
+}
+
+fn flag_in(config: &Table, variable: &str) -> bool {
+    match config.get(&config_key(variable)) {
+        Some(toml::Value::Boolean(value)) => *value,
+        Some(value) => toml_to_string(value).is_some(),
+        None => false,
+    }
+}
+
+/// Whether the flag is set in the environment, or otherwise in the config.
+pub fn env_or_config_flag(variable: &str) -> bool {
+    std::env::var(variable).is_ok() || flag_in(&CONFIG.read(), variable)
+}
+
+/// The value of the environment variable, or otherwise of the matching config key.
+pub fn env_or_config(variable: &str) -> Option<String> {
+    std::env::var(variable).ok().or_else(|| {
+        CONFIG
+            .read()
+            .get(&config_key(variable))
+            .and_then(toml_to_string)
+    })
+}
+
+/// A value from the config that has no environment variable, like the project's window size.
+pub fn config_value(key: &str) -> Option<String> {
+    CONFIG.read().get(key).and_then(toml_to_string)
+}
+
+/// The config values for the settings that neovim can also set as `g:neovide_<name>`, which
+/// are applied on top of the defaults. Neovim's variables still take precedence once it
+/// attached.
+pub fn setting_values() -> Vec<(String, Value)> {
+    CONFIG
+        .read()
+        .iter()
+        .filter(|(name, _)| !COMMAND_LINE_SETTINGS.contains(&name.as_str()))
+        .map(|(name, value)| (name.clone(), toml_to_value(value)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_keys_match_environment_variables() {
+        let config = parse_config("multigrid = true\nno_srgb = false\nbackend = 'software'")
+            .expect("Could not parse config");
+        assert!(flag_in(&config, "NEOVIDE_MULTIGRID"));
+        assert!(!flag_in(&config, "NEOVIDE_NO_SRGB"));
+        assert_eq!(
+            config
+                .get(&config_key("NEOVIDE_BACKEND"))
+                .and_then(toml_to_string),
+            Some("software".to_owned())
+        );
+        assert_eq!(config_key("NEOVIM_BIN"), "neovim_bin");
+    }
+
+    #[test]
+    fn test_read_config() {
+        let path = Some(PathBuf::from("config.toml"));
+        let config = read_config(path.clone(), |_| Ok("multigrid = true".to_owned()));
+        assert!(flag_in(&config, "NEOVIDE_MULTIGRID"));
+
+        let missing = read_config(path.clone(), |_| Err(ErrorKind::NotFound.into()));
+        assert!(missing.is_empty());
+        let invalid = read_config(path, |_| Ok("multigrid = ".to_owned()));
+        assert!(invalid.is_empty());
+        assert!(read_config(None, |_| unreachable!()).is_empty());
+    }
+
+    #[test]
+    fn test_find_project_config() {
+        let root = std::env::temp_dir().join(format!("neovide-project-{}", std::process::id()));
+        let nested = root.join("src").join("module");
+        std::fs::create_dir_all(&nested).unwrap();
+        std::fs::create_dir_all(root.join(".git")).unwrap();
+        assert_eq!(find_project_config(&nested), None);
+
+        std::fs::write(root.join(PROJECT_CONFIG_NAME), "theme = 'dark'").unwrap();
+        let found = find_project_config(&nested);
+        std::fs::remove_dir_all(&root).ok();
+        assert_eq!(found, Some(root.join(PROJECT_CONFIG_NAME)));
+    }
+
+    #[test]
+    fn test_project_values_only_change_the_looks() {
+        let project = project_values("theme = 'light'\nneovim_bin = '/tmp/evil'")
+            .expect("Could not parse config");
+        assert!(project.contains_key("theme"));
+        assert!(!project.contains_key("neovim_bin"));
+    }
+
+    #[test]
+    fn test_stored_decision() {
+        let store = "trust 01 /home/user/a\nignore 02 /home/user/b\n";
+        assert_eq!(
+            stored_decision(store, Path::new("/home/user/a"), "01"),
+            Some(true)
+        );
+        assert_eq!(
+            stored_decision(store, Path::new("/home/user/b"), "02"),
+            Some(false)
+        );
+        assert_eq!(
+            stored_decision(store, Path::new("/home/user/c"), "01"),
+            None
+        );
+        // An edited file is asked about again
+        assert_eq!(
+            stored_decision(store, Path::new("/home/user/a"), "03"),
+            None
+        );
+        // So are the answers stored by path alone
+        assert_eq!(
+            stored_decision("trust /home/user/a\n", Path::new("/home/user/a"), "01"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_decide_trust_only_saves_answers() {
+        let project = Path::new("/home/user/a");
+        assert_eq!(
+            decide_trust("", project, "01", || Some(true)),
+            (true, Some("trust 01 /home/user/a\n".to_owned()))
+        );
+        assert_eq!(
+            decide_trust("", project, "01", || Some(false)),
+            (false, Some("ignore 01 /home/user/a\n".to_owned()))
+        );
+        assert_eq!(decide_trust("", project, "01", || None), (false, None));
+        assert_eq!(
+            decide_trust("trust 01 /home/user/a\n", project, "01", || unreachable!()),
+            (true, None)
+        );
+        // The answer for the earlier version is replaced
+        assert_eq!(
+            decide_trust(
+                "trust 01 /home/user/a\nignore 02 /home/user/b\n",
+                project,
+                "03",
+                || Some(false)
+            ),
+            (
+                false,
+                Some("ignore 02 /home/user/b\nignore 03 /home/user/a\n".to_owned())
+            )
+        );
+    }
+
+    #[test]
+    fn test_content_hash() {
+        assert_eq!(
+            content_hash(""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(
+            content_hash("abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        // Long enough to take a second block
+        assert_eq!(
+            content_hash("abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"),
+            "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+        );
+    }
+
+    #[test]
+    fn test_toml_to_value() {
+        let config = parse_config("cursor_animation_length = 0.05\nrefresh_rate = 144")
+            .expect("Could not parse config");
+        assert_eq!(
+            toml_to_value(&config["cursor_animation_length"]),
+            Value::from(0.05)
+        );
+        assert_eq!(toml_to_value(&config["refresh_rate"]).as_u64(), Some(144));
+    }
+}
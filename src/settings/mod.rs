@@ -1,21 +1,134 @@
 use std::any::{Any, TypeId};
+use std::cell::RefCell;
 use std::collections::HashMap;
 use std::convert::TryInto;
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
 
+pub mod config;
 mod from_value;
+mod profiles;
+mod recent_files;
+mod session;
+mod ui_state;
+mod validation;
 mod window_geometry;
 pub use from_value::FromValue;
-use log::trace;
+use log::{error, trace};
 use nvim_rs::Neovim;
-use parking_lot::RwLock;
+use parking_lot::{Mutex, RwLock};
+pub use profiles::profile_settings;
+pub use recent_files::{recent_files, remember_recent_file};
 pub use rmpv::Value;
-pub use window_geometry::{maybe_save_window_size, parse_window_geometry, DEFAULT_WINDOW_GEOMETRY};
+pub use session::{restore_session_args, save_session_command};
+pub use ui_state::{maybe_save_ui_state, restore_ui_state, PersistentUiState};
+pub use window_geometry::{
+    maybe_save_window_geometry, parse_window_geometry, parse_window_position,
+    try_to_load_last_window_geometry, PersistentWindowGeometry, DEFAULT_WINDOW_GEOMETRY,
+};
 
 use crate::bridge::TxWrapper;
 use crate::error_handling::ResultPanicExplanation;
 
 lazy_static! {
-    pub static ref SETTINGS: Settings = Settings::new();
+    // What the settings of every window start out as: the defaults, the config and the command line
+    static ref ROOT_SETTINGS: Arc<Settings> = Arc::new(Settings::new());
+    static ref INVALID_SETTINGS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+}
+
+thread_local! {
+    static CURRENT_SETTINGS: RefCell<Option<Arc<Settings>>> = RefCell::new(None);
+}
+
+/// The settings of the window the calling thread works for, which its neovim's `g:neovide_*`
+/// variables go to. Threads that don't belong to a window see the settings neovide started with.
+pub struct CurrentSettings;
+
+pub static SETTINGS: CurrentSettings = CurrentSettings;
+
+/// The settings `SETTINGS` refers to on the calling thread.
+pub fn current_settings() -> Arc<Settings> {
+    CURRENT_SETTINGS
+        .with(|current| current.borrow().clone())
+        .unwrap_or_else(|| ROOT_SETTINGS.clone())
+}
+
+/// Settings for a new window, starting out like neovide started. The window and the threads of
+/// its neovim share them, so they are freed once the last of those is done with them.
+pub fn new_window_settings() -> Arc<Settings> {
+    Arc::new(ROOT_SETTINGS.fork())
+}
+
+/// Makes `SETTINGS` refer to the given settings on the calling thread.
+pub fn use_settings(settings: Arc<Settings>) {
+    CURRENT_SETTINGS.with(|current| *current.borrow_mut() = Some(settings));
+}
+
+/// Starts a thread which sees the same settings as the calling one, so work done on behalf of a
+/// window reads that window's settings.
+pub fn spawn_with_settings<F, T>(f: F) -> JoinHandle<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let settings = current_settings();
+    thread::spawn(move || {
+        use_settings(settings);
+        f()
+    })
+}
+
+// The settings live in a thread local that can't hand out references, so these go to the
+// current settings each time they are called
+impl CurrentSettings {
+    pub fn set_setting_handlers(
+        &self,
+        property_name: &str,
+        update_func: UpdateHandlerFunc,
+        reader_func: ReaderFunc,
+    ) {
+        current_settings().set_setting_handlers(property_name, update_func, reader_func);
+    }
+
+    pub fn set<T: Clone + Send + Sync + 'static>(&self, t: &T) {
+        current_settings().set(t);
+    }
+
+    pub fn get<T: Clone + Send + Sync + 'static>(&self) -> T {
+        current_settings().get()
+    }
+
+    pub fn apply_config(&self) {
+        current_settings().apply_config();
+    }
+
+    pub fn values(&self) -> Vec<(String, Value)> {
+        current_settings().values()
+    }
+
+    pub async fn read_initial_values(&self, nvim: &Neovim<TxWrapper>) {
+        current_settings().read_initial_values(nvim).await;
+    }
+
+    pub async fn setup_changed_listeners(&self, nvim: &Neovim<TxWrapper>) {
+        current_settings().setup_changed_listeners(nvim).await;
+    }
+
+    pub fn handle_changed_notification(&self, arguments: Vec<Value>) {
+        current_settings().handle_changed_notification(arguments);
+    }
+}
+
+/// Tells the user about a setting value that couldn't be used. The bridge shows the messages in
+/// neovim, since the setting keeps its previous value without any other sign.
+pub fn report_invalid_setting(message: String) {
+    error!("{}", message);
+    INVALID_SETTINGS.lock().push(message);
+}
+
+/// Takes the messages reported since the last call.
+pub fn take_invalid_setting_reports() -> Vec<String> {
+    INVALID_SETTINGS.lock().drain(..).collect()
 }
 
 pub trait SettingGroup {
@@ -25,6 +138,15 @@ pub trait SettingGroup {
 // Function types to handle settings updates
 type UpdateHandlerFunc = fn(Value);
 type ReaderFunc = fn() -> Value;
+type SettingValue = Box<dyn Any + Send + Sync>;
+type ClonerFunc = fn(&SettingValue) -> SettingValue;
+
+fn clone_setting<T: Clone + Send + Sync + 'static>(value: &SettingValue) -> SettingValue {
+    let value: &T = value
+        .downcast_ref::<T>()
+        .expect("Attempted to clone a settings object of the wrong type");
+    Box::new(value.clone())
+}
 
 // The Settings struct acts as a global container where each of Neovide's subsystems can store
 // their own settings. It will also coordinate updates between Neovide and nvim to make sure the
@@ -34,7 +156,9 @@ type ReaderFunc = fn() -> Value;
 // struct except when prompted by an update event from nvim. Otherwise, the settings in Neovide and
 // nvim will get out of sync.
 pub struct Settings {
-    settings: RwLock<HashMap<TypeId, Box<dyn Any + Send + Sync>>>,
+    settings: RwLock<HashMap<TypeId, SettingValue>>,
+    // How to copy each of the settings objects, which are only known by type when they are set
+    cloners: RwLock<HashMap<TypeId, ClonerFunc>>,
     listeners: RwLock<HashMap<String, UpdateHandlerFunc>>,
     readers: RwLock<HashMap<String, ReaderFunc>>,
 }
@@ -43,11 +167,29 @@ impl Settings {
     fn new() -> Self {
         Self {
             settings: RwLock::new(HashMap::new()),
+            cloners: RwLock::new(HashMap::new()),
             listeners: RwLock::new(HashMap::new()),
             readers: RwLock::new(HashMap::new()),
         }
     }
 
+    // A copy of every settings object, with the same settings registered
+    fn fork(&self) -> Self {
+        let cloners = self.cloners.read();
+        let settings = self
+            .settings
+            .read()
+            .iter()
+            .map(|(type_id, value)| (*type_id, cloners[type_id](value)))
+            .collect();
+        Self {
+            settings: RwLock::new(settings),
+            cloners: RwLock::new(cloners.clone()),
+            listeners: RwLock::new(self.listeners.read().clone()),
+            readers: RwLock::new(self.readers.read().clone()),
+        }
+    }
+
     pub fn set_setting_handlers(
         &self,
         property_name: &str,
@@ -70,6 +212,7 @@ impl Settings {
         }
         let mut write_lock = self.settings.write();
         write_lock.insert(type_id, Box::new(t));
+        self.cloners.write().insert(type_id, clone_setting::<T>);
     }
 
     pub fn get<T: Clone + Send + Sync + 'static>(&'_ self) -> T {
@@ -83,15 +226,51 @@ impl Settings {
         (*value).clone()
     }
 
+    /// Applies the settings from the config file. Has to run after the setting groups registered
+    /// and before neovim attached, whose variables override it.
+    pub fn apply_config(&self) {
+        for (name, value) in config::setting_values() {
+            self.update(&name, value);
+        }
+    }
+
+    // Values that don't fit the setting are reported and leave it as it was
+    fn update(&self, name: &str, value: Value) {
+        let listener = match self.listeners.read().get(name) {
+            Some(listener) => *listener,
+            None => {
+                trace!("{} is not a setting", name);
+                return;
+            }
+        };
+        let reader = self.readers.read().get(name).copied();
+        if let Some(reader) = reader {
+            if let Err(reason) = validation::validate(name, &reader(), &value) {
+                report_invalid_setting(format!("Ignored neovide_{} = {}: {}", name, value, reason));
+                return;
+            }
+        }
+        listener(value);
+    }
+
+    /// The current value of every setting by name, in order of the names.
+    pub fn values(&self) -> Vec<(String, Value)> {
+        let readers = self.readers.read();
+        let mut values: Vec<(String, Value)> = readers
+            .iter()
+            .map(|(name, reader)| (name.clone(), reader()))
+            .collect();
+        values.sort_by(|(first, _), (second, _)| first.cmp(second));
+        values
+    }
+
     pub async fn read_initial_values(&self, nvim: &Neovim<TxWrapper>) {
         let keys: Vec<String> = self.listeners.read().keys().cloned().collect();
 
         for name in keys {
             let variable_name = format!("neovide_{}", name);
             match nvim.get_var(&variable_name).await {
-                Ok(value) => {
-                    self.listeners.read().get(&name).unwrap()(value);
-                }
+                Ok(value) => self.update(&name, value),
                 Err(error) => {
                     trace!("Initial value load failed for {}: {}", name, error);
                     let setting = self.readers.read().get(&name).unwrap()();
@@ -101,21 +280,13 @@ impl Settings {
         }
     }
 
+    /// Watches the `g:neovide_*` variable of every setting, so assigning one applies it right away.
+    /// Can be run again on a new connection to the same neovim.
     pub async fn setup_changed_listeners(&self, nvim: &Neovim<TxWrapper>) {
         let keys: Vec<String> = self.listeners.read().keys().cloned().collect();
 
         for name in keys {
-            let vimscript = format!(
-                concat!(
-                    "exe \"",
-                    "fun! NeovideNotify{0}Changed(d, k, z)\n",
-                    "call rpcnotify(1, 'setting_changed', '{0}', g:neovide_{0})\n",
-                    "endf\n",
-                    "call dictwatcheradd(g:, 'neovide_{0}', 'NeovideNotify{0}Changed')\"",
-                ),
-                name
-            );
-            nvim.command(&vimscript)
+            nvim.command(&changed_listener_vimscript(&name))
                 .await
                 .unwrap_or_explained_panic(&format!(
                     "Could not setup setting notifier for {}",
@@ -126,15 +297,40 @@ impl Settings {
 
     pub fn handle_changed_notification(&self, arguments: Vec<Value>) {
         let mut arguments = arguments.into_iter();
-        let (name, value) = (arguments.next().unwrap(), arguments.next().unwrap());
+        let (name, value) = match (arguments.next(), arguments.next()) {
+            (Some(name), Some(value)) => (name, value),
+            _ => return,
+        };
 
         let name: Result<String, _> = name.try_into();
-        let name = name.unwrap();
+        let name = match name {
+            Ok(name) => name,
+            Err(_) => return,
+        };
 
-        self.listeners.read().get(&name).unwrap()(value);
+        self.update(&name, value);
     }
 }
 
+// The notification goes to the channel neovide registered on, which isn't the first one when
+// attached to a neovim that was started elsewhere. Unletting the variable keeps the last value,
+// and the previous watcher is removed so a new connection doesn't get every change twice.
+fn changed_listener_vimscript(name: &str) -> String {
+    format!(
+        concat!(
+            "exe \"",
+            "fun! NeovideNotify{0}Changed(d, k, z)\n",
+            "if has_key(a:z, 'new')\n",
+            "call rpcnotify(g:neovide_channel_id, 'setting_changed', '{0}', a:z.new)\n",
+            "endif\n",
+            "endf\n",
+            "silent! call dictwatcherdel(g:, 'neovide_{0}', 'NeovideNotify{0}Changed')\n",
+            "call dictwatcheradd(g:, 'neovide_{0}', 'NeovideNotify{0}Changed')\"",
+        ),
+        name
+    )
+}
+
 #[cfg(test)]
 mod tests {
     use async_trait::async_trait;
@@ -184,6 +380,30 @@ mod tests {
         assert_eq!(&(noop_read as ReaderFunc), reader);
     }
 
+    #[test]
+    fn test_changed_listener_vimscript() {
+        let vimscript = changed_listener_vimscript("transparency");
+        assert!(vimscript.contains("fun! NeovideNotifytransparencyChanged"));
+        assert!(vimscript.contains("rpcnotify(g:neovide_channel_id, 'setting_changed'"));
+        assert!(vimscript.contains("dictwatcheradd(g:, 'neovide_transparency'"));
+    }
+
+    #[test]
+    fn test_forked_settings_are_separate() {
+        let settings = Settings::new();
+        settings.set(&1u32);
+        settings.set(&String::from("first"));
+
+        let forked = settings.fork();
+        assert_eq!(forked.get::<u32>(), 1);
+        forked.set(&2u32);
+        forked.set(&String::from("second"));
+
+        assert_eq!(settings.get::<u32>(), 1);
+        assert_eq!(settings.get::<String>(), "first");
+        assert_eq!(forked.get::<String>(), "second");
+    }
+
     #[test]
     fn test_set() {
         let settings = Settings::new();
@@ -244,13 +464,17 @@ mod tests {
         let v4: String = format!("neovide_{}", v1);
         let v5: String = format!("neovide_{}", v2);
 
-        //create_nvim_command tries to read from CmdLineSettings.neovim_args
+        //create_nvim_command tries to read from CmdLineSettings.neovim_bin
         //TODO: this sets a static variable. Can this have side effects on other tests?
         SETTINGS.set::<CmdLineSettings>(&CmdLineSettings::default());
 
-        let (nvim, _) = create::new_child_cmd(&mut create_nvim_command(), NeovimHandler())
-            .await
-            .unwrap_or_explained_panic("Could not locate or start the neovim process");
+        let (nvim, _, _) = create::new_child_cmd(
+            &mut create_nvim_command(&[]).unwrap(),
+            NeovimHandler(),
+            Default::default(),
+        )
+        .await
+        .unwrap_or_explained_panic("Could not locate or start the neovim process");
         nvim.set_var(&v4, Value::from(v2.clone())).await.ok();
 
         fn noop_update(_v: Value) {}
@@ -0,0 +1,74 @@
+use rmpv::Value;
+
+// Each built in profile sets all of these, so switching between them never leaves a value of
+// the previous one behind. The cursor effects and blur are the user's taste rather than a matter
+// of performance, so they are left alone.
+fn built_in_profile(name: &str) -> Option<Vec<(&'static str, Value)>> {
+    let (refresh_rate, reduce_motion, power_saving) = match name {
+        "quality" => (0, false, "off"),
+        "balanced" => (60, false, "auto"),
+        "low-power" => (30, true, "on"),
+        _ => return None,
+    };
+    Some(vec![
+        ("refresh_rate", Value::from(refresh_rate)),
+        ("reduce_motion", Value::from(reduce_motion)),
+        ("power_saving", Value::from(power_saving)),
+    ])
+}
+
+/// The `g:neovide_*` variables a profile sets, without the prefix. Profiles of the user's own in
+/// `g:neovide_profiles`, a dictionary of setting names to values per profile, win over the built
+/// in `quality`, `balanced` and `low-power`.
+pub fn profile_settings(
+    name: &str,
+    custom_profiles: Option<&Value>,
+) -> Option<Vec<(String, Value)>> {
+    let custom_profile = custom_profiles
+        .and_then(Value::as_map)
+        .and_then(|profiles| {
+            profiles
+                .iter()
+                .find(|(profile_name, _)| profile_name.as_str() == Some(name))
+        })
+        .and_then(|(_, settings)| settings.as_map());
+    if let Some(settings) = custom_profile {
+        return Some(
+            settings
+                .iter()
+                .filter_map(|(setting, value)| Some((setting.as_str()?.to_owned(), value.clone())))
+                .collect(),
+        );
+    }
+    built_in_profile(name).map(|settings| {
+        settings
+            .into_iter()
+            .map(|(setting, value)| (setting.to_owned(), value))
+            .collect()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profile_settings() {
+        let low_power = profile_settings("low-power", None).unwrap();
+        assert!(low_power.contains(&("refresh_rate".to_owned(), Value::from(30))));
+        let quality = profile_settings("quality", None).unwrap();
+        assert!(quality
+            .iter()
+            .all(|(setting, _)| !setting.contains("vfx") && !setting.contains("blur")));
+        assert!(profile_settings("ultra", None).is_none());
+
+        let custom = Value::Map(vec![(
+            Value::from("balanced"),
+            Value::Map(vec![(Value::from("refresh_rate"), Value::from(120))]),
+        )]);
+        assert_eq!(
+            profile_settings("balanced", Some(&custom)),
+            Some(vec![("refresh_rate".to_owned(), Value::from(120))])
+        );
+    }
+}
@@ -0,0 +1,91 @@
+use std::path::{Path, PathBuf};
+
+use parking_lot::Mutex;
+
+#[cfg(unix)]
+const RECENT_FILES_PATH: &str = ".local/share/nvim/neovide-recent-files.json";
+#[cfg(windows)]
+const RECENT_FILES_PATH: &str = "AppData/Local/nvim-data/neovide-recent-files.json";
+const RECENT_FILE_COUNT: usize = 10;
+
+lazy_static! {
+    // Loaded on first use, so switching buffers doesn't read the file every time
+    static ref RECENT_FILES: Mutex<Option<Vec<String>>> = Mutex::new(None);
+}
+
+fn recent_files_path() -> PathBuf {
+    let mut recent_files_path = dirs::home_dir().unwrap();
+    recent_files_path.push(RECENT_FILES_PATH);
+    recent_files_path
+}
+
+// The most recent file comes first, and files opened again move back to the front
+fn push_recent_file(recent_files: &mut Vec<String>, path: &str) -> bool {
+    if recent_files.first().map(String::as_str) == Some(path) {
+        return false;
+    }
+    recent_files.retain(|recent_file| recent_file != path);
+    recent_files.insert(0, path.to_owned());
+    recent_files.truncate(RECENT_FILE_COUNT);
+    true
+}
+
+fn load_recent_files() -> Vec<String> {
+    std::fs::read_to_string(recent_files_path())
+        .ok()
+        .and_then(|json| serde_json::from_str::<Vec<String>>(&json).ok())
+        .unwrap_or_default()
+        .into_iter()
+        // Files deleted since they were opened would only fail to open
+        .filter(|path| Path::new(path).is_file())
+        .collect()
+}
+
+/// The files opened in neovide lately, most recent first.
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+pub fn recent_files() -> Vec<String> {
+    RECENT_FILES
+        .lock()
+        .get_or_insert_with(load_recent_files)
+        .clone()
+}
+
+/// Adds the file shown in a window to the files opened in neovide lately, which are shared by
+/// every window and kept between sessions. Returns the new list when it changed, which buffers
+/// without a file on disk, like terminals, never do.
+pub fn remember_recent_file(path: &str) -> Option<Vec<String>> {
+    if path.is_empty() || !Path::new(path).is_file() {
+        return None;
+    }
+
+    let mut recent_files = RECENT_FILES.lock();
+    let recent_files = recent_files.get_or_insert_with(load_recent_files);
+    if !push_recent_file(recent_files, path) {
+        return None;
+    }
+
+    let json = serde_json::to_string(&recent_files).unwrap();
+    if let Err(error) = std::fs::write(recent_files_path(), json) {
+        log::error!("Could not save the recent files: {}", error);
+    }
+    Some(recent_files.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_push_recent_file() {
+        let mut recent_files = vec!["a".to_owned(), "b".to_owned()];
+        assert!(!push_recent_file(&mut recent_files, "a"));
+        assert!(push_recent_file(&mut recent_files, "b"));
+        assert_eq!(recent_files, vec!["b", "a"]);
+
+        for index in 0..RECENT_FILE_COUNT {
+            push_recent_file(&mut recent_files, &index.to_string());
+        }
+        assert_eq!(recent_files.len(), RECENT_FILE_COUNT);
+        assert_eq!(recent_files[0], (RECENT_FILE_COUNT - 1).to_string());
+    }
+}
@@ -0,0 +1,59 @@
+use std::path::{Path, PathBuf};
+
+#[cfg(unix)]
+const SESSION_PATH: &str = ".local/share/nvim/neovide-session.vim";
+#[cfg(windows)]
+const SESSION_PATH: &str = "AppData/Local/nvim-data/neovide-session.vim";
+
+/// Where the files and window layout of the last session are saved with `:mksession`, when
+/// `--restore-session` is set.
+pub fn session_path() -> PathBuf {
+    let mut session_path = dirs::home_dir().unwrap();
+    session_path.push(SESSION_PATH);
+    session_path
+}
+
+// Anything on the command line means the user asked for something else than the last session
+fn session_args(neovim_args: Vec<String>, session: &Path) -> Vec<String> {
+    if !neovim_args.is_empty() || !session.is_file() {
+        return neovim_args;
+    }
+    vec!["-S".to_owned(), session.to_string_lossy().into_owned()]
+}
+
+/// The arguments neovim is started with, which source the last session when neovide was started
+/// without any.
+pub fn restore_session_args(neovim_args: Vec<String>) -> Vec<String> {
+    session_args(neovim_args, &session_path())
+}
+
+/// Saves the session as neovim quits, unless only empty buffers are left, like in a window
+/// opened just to look something up, which would replace a more useful session.
+pub fn save_session_command() -> String {
+    let path = session_path().to_string_lossy().replace('\'', "''");
+    format!(
+        "autocmd NeovideSession VimLeavePre * if !empty(filter(getbufinfo({{'buflisted': 1}}), 'v:val.name != \"\"')) | execute 'mksession! ' . fnameescape('{}') | endif",
+        path
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_session_args() {
+        let session = std::env::temp_dir().join("neovide-test-session.vim");
+        std::fs::write(&session, "").unwrap();
+
+        let args = session_args(Vec::new(), &session);
+        assert_eq!(args[0], "-S");
+        assert_eq!(args[1], session.to_string_lossy());
+
+        let args = session_args(vec!["notes.md".to_owned()], &session);
+        assert_eq!(args, vec!["notes.md"]);
+
+        std::fs::remove_file(&session).ok();
+        assert!(session_args(Vec::new(), &session).is_empty());
+    }
+}
@@ -0,0 +1,124 @@
+use crate::cmd_line::CmdLineSettings;
+use crate::settings::SETTINGS;
+use crate::window::WindowSettings;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+#[cfg(unix)]
+const STATE_PATH: &str = ".local/share/nvim/neovide-state.json";
+#[cfg(windows)]
+const STATE_PATH: &str = "AppData/Local/nvim-data/neovide-state.json";
+
+/// What the user changed while neovide was running, restored on the next launch when
+/// `g:neovide_remember_ui_state` is set. Kept apart from the config, which neovide never writes.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PersistentUiState {
+    pub font_zoom: f32,
+    pub fullscreen: bool,
+    pub transparency: Option<f32>,
+    pub fullscreen_monitor: Option<String>,
+}
+
+impl Default for PersistentUiState {
+    fn default() -> Self {
+        Self {
+            font_zoom: 1.0,
+            fullscreen: false,
+            transparency: None,
+            fullscreen_monitor: None,
+        }
+    }
+}
+
+fn state_path() -> PathBuf {
+    let mut state_path = dirs::home_dir().unwrap();
+    state_path.push(STATE_PATH);
+    state_path
+}
+
+fn parse_saved_state(json: &str) -> Result<PersistentUiState, String> {
+    let mut state = serde_json::from_str::<PersistentUiState>(json).map_err(|e| e.to_string())?;
+    if state.font_zoom.is_nan() || state.font_zoom <= 0.0 {
+        log::warn!("Invalid Saved Font Zoom. Reverting to default");
+        state.font_zoom = 1.0;
+    }
+    state.transparency = state
+        .transparency
+        .map(|transparency| transparency.max(0.0).min(1.0));
+    Ok(state)
+}
+
+fn try_to_load_ui_state() -> Result<PersistentUiState, String> {
+    let json = std::fs::read_to_string(&state_path()).map_err(|e| e.to_string())?;
+    let state = parse_saved_state(&json)?;
+    log::debug!("Loaded UI State: {:?}", state);
+    Ok(state)
+}
+
+/// Loads the state of the last session into the window settings. The command line takes
+/// precedence, and the zoom is picked up by the first window from `SETTINGS`.
+pub fn restore_ui_state() {
+    let state = try_to_load_ui_state().unwrap_or_default();
+    let cmd_line_settings = SETTINGS.get::<CmdLineSettings>();
+    let mut window_settings = SETTINGS.get::<WindowSettings>();
+    if !cmd_line_settings.fullscreen {
+        window_settings.fullscreen = state.fullscreen;
+    }
+    if let Some(transparency) = state.transparency {
+        if cmd_line_settings.transparency >= 1.0 {
+            window_settings.transparency = transparency;
+        }
+    }
+    if let Some(fullscreen_monitor) = &state.fullscreen_monitor {
+        if cmd_line_settings.fullscreen_monitor.is_none() {
+            window_settings.fullscreen_monitor = fullscreen_monitor.clone();
+        }
+    }
+    SETTINGS.set(&window_settings);
+    SETTINGS.set(&state);
+}
+
+pub fn maybe_save_ui_state(state: PersistentUiState) {
+    let settings = SETTINGS.get::<WindowSettings>();
+    let saved_state = if settings.remember_ui_state {
+        state
+    } else {
+        PersistentUiState::default()
+    };
+
+    let json = serde_json::to_string(&saved_state).unwrap();
+    log::debug!("Saved UI State: {}", json);
+    if let Err(error) = std::fs::write(state_path(), json) {
+        log::error!("Could not save the UI state: {}", error);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_saved_state() {
+        let state = PersistentUiState {
+            font_zoom: 1.5,
+            fullscreen: true,
+            transparency: Some(0.8),
+            fullscreen_monitor: Some("2".to_owned()),
+        };
+        let json = serde_json::to_string(&state).unwrap();
+
+        assert_eq!(parse_saved_state(&json), Ok(state));
+    }
+
+    #[test]
+    fn test_parse_partial_saved_state() {
+        assert_eq!(
+            parse_saved_state("{\"font_zoom\":0,\"transparency\":2.0}"),
+            Ok(PersistentUiState {
+                transparency: Some(1.0),
+                ..PersistentUiState::default()
+            })
+        );
+    }
+}
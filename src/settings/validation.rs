@@ -0,0 +1,85 @@
+use super::Value;
+
+// Settings which only make sense within a range, checked in addition to the type
+const RANGES: [(&str, f64, f64); 4] = [
+    ("transparency", 0.0, 1.0),
+    ("floating_opacity", 0.0, 1.0),
+    ("floating_shadow_opacity", 0.0, 1.0),
+    ("background_image_opacity", 0.0, 1.0),
+];
+
+fn describe(value: &Value) -> &'static str {
+    if value.is_bool() {
+        "a boolean"
+    } else if value.is_u64() {
+        "a whole number of 0 or more"
+    } else if value.is_number() {
+        "a number"
+    } else if value.is_str() {
+        "a string"
+    } else {
+        "a list or dictionary"
+    }
+}
+
+/// Checks a new value against the current one of the setting, which has the type the setting
+/// expects. Returns why the value can't be used otherwise.
+pub fn validate(name: &str, current: &Value, new: &Value) -> Result<(), String> {
+    let compatible = if current.is_bool() {
+        // Vimscript has no booleans, so 0 and 1 work too
+        new.is_bool() || new.as_u64().map_or(false, |number| number <= 1)
+    } else if current.is_u64() {
+        new.is_u64()
+    } else if current.is_number() {
+        new.is_number()
+    } else if current.is_str() {
+        new.is_str()
+    } else {
+        true
+    };
+    if !compatible {
+        return Err(format!("it has to be {}", describe(current)));
+    }
+
+    if let Some((_, min, max)) = RANGES.iter().find(|(setting, _, _)| *setting == name) {
+        let number = new
+            .as_f64()
+            .or_else(|| new.as_i64().map(|number| number as f64));
+        if let Some(number) = number {
+            if number < *min || number > *max {
+                return Err(format!("it has to be between {} and {}", min, max));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_types() {
+        assert!(validate(
+            "cursor_animation_length",
+            &Value::from(0.13),
+            &Value::from(1)
+        )
+        .is_ok());
+        assert!(validate("refresh_rate", &Value::from(60u64), &Value::from(-1)).is_err());
+        assert!(validate("no_idle", &Value::from(false), &Value::from(1)).is_ok());
+        assert_eq!(
+            validate("theme", &Value::from(""), &Value::from(1)),
+            Err("it has to be a string".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_validate_ranges() {
+        assert!(validate("transparency", &Value::from(1.0), &Value::from(0.8)).is_ok());
+        assert_eq!(
+            validate("transparency", &Value::from(1.0), &Value::from(1.5)),
+            Err("it has to be between 0 and 1".to_owned())
+        );
+    }
+}
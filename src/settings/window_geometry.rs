@@ -1,6 +1,7 @@
 use crate::settings::SETTINGS;
 use crate::utils::Dimensions;
 use crate::window::WindowSettings;
+use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 
 #[cfg(unix)]
@@ -13,52 +14,94 @@ pub const DEFAULT_WINDOW_GEOMETRY: Dimensions = Dimensions {
     height: 50,
 };
 
+/// Everything about the window which is restored on the next launch when
+/// `g:neovide_remember_window_size` is set.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PersistentWindowGeometry {
+    pub grid_size: Dimensions,
+    pub position: Option<(i32, i32)>,
+    pub monitor: Option<String>,
+    pub maximized: bool,
+}
+
+impl Default for PersistentWindowGeometry {
+    fn default() -> Self {
+        Self {
+            grid_size: DEFAULT_WINDOW_GEOMETRY,
+            position: None,
+            monitor: None,
+            maximized: false,
+        }
+    }
+}
+
 fn neovim_std_datapath() -> PathBuf {
     let mut settings_path = dirs::home_dir().unwrap();
     settings_path.push(SETTINGS_PATH);
     settings_path
 }
 
-pub fn try_to_load_last_window_size() -> Result<Dimensions, String> {
+// Older versions only stored the grid size, so that format is still accepted
+fn parse_saved_geometry(json: &str) -> Result<PersistentWindowGeometry, String> {
+    serde_json::from_str::<PersistentWindowGeometry>(json)
+        .or_else(|_| {
+            serde_json::from_str::<Dimensions>(json).map(|grid_size| PersistentWindowGeometry {
+                grid_size,
+                ..PersistentWindowGeometry::default()
+            })
+        })
+        .map_err(|e| e.to_string())
+}
+
+pub fn try_to_load_last_window_geometry() -> Result<PersistentWindowGeometry, String> {
     let settings_path = neovim_std_datapath();
     let json = std::fs::read_to_string(&settings_path).map_err(|e| e.to_string())?;
 
-    let loaded_geometry: Dimensions = serde_json::from_str(&json).map_err(|e| e.to_string())?;
-    log::debug!("Loaded Window Size: {:?}", loaded_geometry);
+    let mut loaded_geometry = parse_saved_geometry(&json)?;
+    log::debug!("Loaded Window Geometry: {:?}", loaded_geometry);
 
-    if loaded_geometry.width == 0 || loaded_geometry.height == 0 {
+    if loaded_geometry.grid_size.width == 0 || loaded_geometry.grid_size.height == 0 {
         log::warn!("Invalid Saved Window Size. Reverting to default");
-        Ok(DEFAULT_WINDOW_GEOMETRY)
-    } else {
-        Ok(loaded_geometry)
+        loaded_geometry.grid_size = DEFAULT_WINDOW_GEOMETRY;
     }
+
+    Ok(loaded_geometry)
 }
 
-pub fn maybe_save_window_size(grid_size: Option<Dimensions>) {
+pub fn try_to_load_last_window_size() -> Result<Dimensions, String> {
+    try_to_load_last_window_geometry().map(|geometry| geometry.grid_size)
+}
+
+pub fn maybe_save_window_geometry(geometry: PersistentWindowGeometry) {
     let settings = SETTINGS.get::<WindowSettings>();
-    let saved_window_size = if settings.remember_window_size {
-        grid_size.unwrap_or(DEFAULT_WINDOW_GEOMETRY)
+    let saved_window_geometry = if settings.remember_window_size {
+        geometry
     } else {
-        DEFAULT_WINDOW_GEOMETRY
+        PersistentWindowGeometry::default()
     };
 
     let settings_path = neovim_std_datapath();
-    let json = serde_json::to_string(&saved_window_size).unwrap();
-    log::debug!("Saved Window Size: {}", json);
+    let json = serde_json::to_string(&saved_window_geometry).unwrap();
+    log::debug!("Saved Window Geometry: {}", json);
     std::fs::write(settings_path, json).unwrap();
 }
 
+fn is_position_sign(character: char) -> bool {
+    character == '+' || character == '-'
+}
+
 pub fn parse_window_geometry(geometry: Option<String>) -> Result<Dimensions, String> {
     let saved_window_size =
         try_to_load_last_window_size().or::<String>(Ok(DEFAULT_WINDOW_GEOMETRY));
     geometry.map_or(saved_window_size, |input| {
         let invalid_parse_err = format!(
-            "Invalid geometry: {}\nValid format: <width>x<height>",
+            "Invalid geometry: {}\nValid format: <width>x<height>[+<x>+<y>]",
             input
         );
 
-        input
-            .split('x')
+        // The position is parsed separately by parse_window_position
+        let size = input.split(is_position_sign).next().unwrap_or_default();
+        size.split('x')
             .map(|dimension| {
                 dimension
                     .parse::<u64>()
@@ -82,3 +125,96 @@ pub fn parse_window_geometry(geometry: Option<String>) -> Result<Dimensions, Str
             .map_err(|msg| msg.to_owned())
     })
 }
+
+/// Parses the optional `+<x>+<y>` suffix of a geometry such as `120x40+100-20`. Like X11
+/// geometries, either sign may be used for each coordinate.
+pub fn parse_window_position(geometry: Option<String>) -> Result<Option<(i32, i32)>, String> {
+    let input = match geometry {
+        Some(input) => input,
+        None => return Ok(None),
+    };
+    let invalid_parse_err = format!(
+        "Invalid geometry: {}\nValid format: <width>x<height>[+<x>+<y>]",
+        input
+    );
+
+    let position_start = match input.find(is_position_sign) {
+        Some(position_start) => position_start,
+        None => return Ok(None),
+    };
+    let position = &input[position_start..];
+
+    // Split before every sign so each coordinate keeps its own
+    let mut coordinates = Vec::new();
+    let mut coordinate_start = 0;
+    for (index, character) in position.char_indices().skip(1) {
+        if is_position_sign(character) {
+            coordinates.push(&position[coordinate_start..index]);
+            coordinate_start = index;
+        }
+    }
+    coordinates.push(&position[coordinate_start..]);
+
+    let coordinates = coordinates
+        .iter()
+        .map(|coordinate| coordinate.parse::<i32>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| invalid_parse_err.clone())?;
+
+    if let [x, y] = coordinates[..] {
+        Ok(Some((x, y)))
+    } else {
+        Err(invalid_parse_err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_saved_geometry() {
+        let geometry = PersistentWindowGeometry {
+            grid_size: Dimensions {
+                width: 80,
+                height: 24,
+            },
+            position: Some((100, -20)),
+            monitor: Some("DP-1".to_owned()),
+            maximized: true,
+        };
+        let json = serde_json::to_string(&geometry).unwrap();
+
+        assert_eq!(parse_saved_geometry(&json), Ok(geometry));
+    }
+
+    #[test]
+    fn test_parse_legacy_saved_geometry() {
+        assert_eq!(
+            parse_saved_geometry("{\"width\":80,\"height\":24}"),
+            Ok(PersistentWindowGeometry {
+                grid_size: Dimensions {
+                    width: 80,
+                    height: 24,
+                },
+                ..PersistentWindowGeometry::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_window_position() {
+        assert_eq!(parse_window_position(None), Ok(None));
+        assert_eq!(parse_window_position(Some("120x40".to_owned())), Ok(None));
+        assert_eq!(
+            parse_window_position(Some("120x40+10+20".to_owned())),
+            Ok(Some((10, 20)))
+        );
+        assert_eq!(
+            parse_window_position(Some("120x40-10+20".to_owned())),
+            Ok(Some((-10, 20)))
+        );
+        assert!(parse_window_position(Some("120x40+10".to_owned())).is_err());
+        assert!(parse_window_position(Some("120x40+a+b".to_owned())).is_err());
+    }
+}
@@ -0,0 +1,226 @@
+use std::io::Write;
+
+use log::{error, info};
+use parking_lot::Mutex;
+
+use crate::cmd_line::NEOVIM_VALUE_FLAGS;
+use crate::redraw_scheduler::REDRAW_SCHEDULER;
+
+lazy_static! {
+    static ref OPEN_REQUESTS: Mutex<Vec<Vec<String>>> = Mutex::new(Vec::new());
+}
+
+// One address per user, so that instances of other users on the same machine aren't reused
+fn instance_address() -> String {
+    let user = std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or_default();
+    let name = format!("neovide-instance-{}", user);
+    if cfg!(windows) {
+        format!("\\\\.\\pipe\\{}", name)
+    } else {
+        let directory = std::env::var_os("XDG_RUNTIME_DIR")
+            .map(Into::into)
+            .unwrap_or_else(std::env::temp_dir);
+        directory
+            .join(format!("{}.sock", name))
+            .to_string_lossy()
+            .into_owned()
+    }
+}
+
+/// The files among the arguments passed on to neovim. Flags and commands only make sense for a
+/// new neovim, so they aren't forwarded.
+pub fn files_to_forward(neovim_args: &[String]) -> Vec<String> {
+    let current_dir = std::env::current_dir().unwrap_or_default();
+    let mut files = Vec::new();
+    let mut args = neovim_args.iter();
+    while let Some(arg) = args.next() {
+        if NEOVIM_VALUE_FLAGS.contains(&arg.as_str()) {
+            args.next();
+        } else if !arg.starts_with('-') && !arg.starts_with('+') {
+            // The running instance has its own working directory
+            files.push(current_dir.join(arg).to_string_lossy().into_owned());
+        }
+    }
+    files
+}
+
+/// Hands the files to an already running neovide, returning whether there was one to take them.
+pub fn forward_to_running_instance(files: &[String]) -> bool {
+    match send_files(&instance_address(), files) {
+        Ok(()) => {
+            info!("Forwarded {:?} to the running instance", files);
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+// The message is just the files, one per line
+fn send_files(address: &str, files: &[String]) -> std::io::Result<()> {
+    #[cfg(unix)]
+    let mut connection = std::os::unix::net::UnixStream::connect(address)?;
+    #[cfg(windows)]
+    let mut connection = std::fs::OpenOptions::new().write(true).open(address)?;
+
+    connection.write_all(files.join("\n").as_bytes())
+}
+
+fn push_request(message: &str) {
+    let files: Vec<String> = message
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(|line| line.to_owned())
+        .collect();
+    push_open_request(files);
+}
+
+/// Asks the running instance to open the files, the same way a later launch would.
+pub fn push_open_request(files: Vec<String>) {
+    if !files.is_empty() {
+        OPEN_REQUESTS.lock().push(files);
+        REDRAW_SCHEDULER.queue_flushed_frame();
+    }
+}
+
+/// Takes the files other launches forwarded since the last call.
+pub fn take_open_requests() -> Vec<Vec<String>> {
+    OPEN_REQUESTS.lock().drain(..).collect()
+}
+
+// Whether the socket at the address was left behind by an instance that crashed. Only a refused
+// connection or a missing file says so, another instance may be listening on it otherwise.
+#[cfg(unix)]
+fn is_stale_socket(address: &str) -> bool {
+    use std::io::ErrorKind;
+
+    match std::os::unix::net::UnixStream::connect(address) {
+        Ok(_) => false,
+        Err(error) => matches!(
+            error.kind(),
+            ErrorKind::ConnectionRefused | ErrorKind::NotFound
+        ),
+    }
+}
+
+/// Listens for the files of later launches, making this the running instance.
+#[cfg(unix)]
+pub fn start_listener() {
+    use std::{io::Read, os::unix::net::UnixListener, path::Path};
+
+    let address = instance_address();
+    if Path::new(&address).exists() {
+        if !is_stale_socket(&address) {
+            info!("Another instance is listening on {}", address);
+            return;
+        }
+        std::fs::remove_file(&address).ok();
+    }
+    let listener = match UnixListener::bind(&address) {
+        Ok(listener) => listener,
+        Err(error) => {
+            error!(
+                "Could not listen for other instances on {}: {}",
+                address, error
+            );
+            return;
+        }
+    };
+
+    std::thread::spawn(move || {
+        for mut connection in listener.incoming().flatten() {
+            let mut message = String::new();
+            if connection.read_to_string(&mut message).is_ok() {
+                push_request(&message);
+            }
+        }
+    });
+}
+
+/// Listens for the files of later launches, making this the running instance.
+#[cfg(windows)]
+pub fn start_listener() {
+    use tokio::{io::AsyncReadExt, net::windows::named_pipe::ServerOptions};
+
+    let address = instance_address();
+    std::thread::spawn(move || {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_io()
+            .build()
+            .expect("Could not start the instance listener runtime");
+        runtime.block_on(async move {
+            let mut server = match ServerOptions::new()
+                .first_pipe_instance(true)
+                .create(&address)
+            {
+                Ok(server) => server,
+                Err(error) => {
+                    error!(
+                        "Could not listen for other instances on {}: {}",
+                        address, error
+                    );
+                    return;
+                }
+            };
+            loop {
+                if server.connect().await.is_err() {
+                    continue;
+                }
+                // A new pipe instance has to exist before the next launch connects
+                let mut connection = server;
+                server = match ServerOptions::new().create(&address) {
+                    Ok(server) => server,
+                    Err(_) => return,
+                };
+                let mut message = String::new();
+                if connection.read_to_string(&mut message).await.is_ok() {
+                    push_request(&message);
+                }
+            }
+        });
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+
+    #[test]
+    fn test_files_to_forward() {
+        let args: Vec<String> = vec!["-p", "-u", "NONE", "notes.md", "+10"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let files = files_to_forward(&args);
+        assert_eq!(files.len(), 1);
+        assert!(Path::new(&files[0]).is_absolute());
+        assert!(files[0].ends_with("notes.md"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_only_abandoned_sockets_are_stale() {
+        use std::os::unix::net::UnixListener;
+
+        let address = std::env::temp_dir().join(format!(
+            "neovide-stale-socket-test-{}.sock",
+            std::process::id()
+        ));
+        let address_str = address.to_string_lossy().into_owned();
+        std::fs::remove_file(&address).ok();
+        assert!(is_stale_socket(&address_str));
+
+        let listener = UnixListener::bind(&address).unwrap();
+        assert!(!is_stale_socket(&address_str));
+
+        // The socket file stays behind without anybody listening on it
+        drop(listener);
+        assert!(address.exists());
+        assert!(is_stale_socket(&address_str));
+
+        std::fs::remove_file(&address).ok();
+    }
+}
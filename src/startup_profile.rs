@@ -0,0 +1,99 @@
+use std::fmt::Write;
+use std::time::{Duration, Instant};
+
+use log::error;
+use parking_lot::Mutex;
+
+use crate::cmd_line::CmdLineSettings;
+use crate::settings::SETTINGS;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StartupPhase {
+    Started,
+    SettingsLoaded,
+    NeovimConnected,
+    UiAttached,
+    WindowCreated,
+    FontLoaded,
+    FirstFlush,
+    FirstFrame,
+}
+
+impl StartupPhase {
+    fn name(&self) -> &'static str {
+        match self {
+            StartupPhase::Started => "neovide started",
+            StartupPhase::SettingsLoaded => "settings loaded",
+            StartupPhase::NeovimConnected => "neovim spawned or connected",
+            StartupPhase::UiAttached => "ui attached",
+            StartupPhase::WindowCreated => "window and surface created",
+            StartupPhase::FontLoaded => "font loaded",
+            StartupPhase::FirstFlush => "first flush",
+            StartupPhase::FirstFrame => "first frame presented",
+        }
+    }
+}
+
+struct StartupProfile {
+    start: Instant,
+    marks: Vec<(StartupPhase, Duration)>,
+}
+
+lazy_static! {
+    static ref STARTUP_PROFILE: Mutex<StartupProfile> = Mutex::new(StartupProfile {
+        start: Instant::now(),
+        marks: Vec::new(),
+    });
+}
+
+// Laid out like neovim's --startuptime, with the time since the start and since the last phase
+fn format_report(marks: &[(StartupPhase, Duration)]) -> String {
+    let mut report = String::from("times in msec\n clock   self  phase\n");
+    let mut previous = Duration::default();
+    for (phase, elapsed) in marks {
+        let clock = elapsed.as_secs_f64() * 1000.0;
+        let own = elapsed.saturating_sub(previous).as_secs_f64() * 1000.0;
+        writeln!(report, "{:7.3} {:7.3}  {}", clock, own, phase.name()).ok();
+        previous = *elapsed;
+    }
+    report
+}
+
+/// Notes when a phase of the startup is reached. Only the first time counts, so the phases of
+/// later windows or a restarted neovim don't show up. Once the first frame is presented the
+/// report is written to the file given with `--startup-profile`.
+pub fn mark_startup(phase: StartupPhase) {
+    let mut profile = STARTUP_PROFILE.lock();
+    if profile.marks.iter().any(|(marked, _)| *marked == phase) {
+        return;
+    }
+    let elapsed = profile.start.elapsed();
+    profile.marks.push((phase, elapsed));
+
+    if phase != StartupPhase::FirstFrame {
+        return;
+    }
+    if let Some(path) = SETTINGS.get::<CmdLineSettings>().startup_profile {
+        if let Err(error) = std::fs::write(&path, format_report(&profile.marks)) {
+            error!("Could not write the startup profile to {}: {}", path, error);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_report() {
+        let report = format_report(&[
+            (StartupPhase::Started, Duration::from_millis(0)),
+            (StartupPhase::WindowCreated, Duration::from_millis(40)),
+            (StartupPhase::FirstFrame, Duration::from_millis(100)),
+        ]);
+        let lines: Vec<&str> = report.lines().collect();
+        assert_eq!(lines[2], "  0.000   0.000  neovide started");
+        assert_eq!(lines[3], " 40.000  40.000  window and surface created");
+        assert_eq!(lines[4], "100.000  60.000  first frame presented");
+    }
+}
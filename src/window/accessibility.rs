@@ -0,0 +1,273 @@
+use accesskit::{
+    ActionHandler, ActionRequest, Live, NodeBuilder, NodeClassSet, NodeId, Role, TextPosition,
+    TextSelection, Tree, TreeUpdate,
+};
+use glutin::window::Window;
+
+use crate::bridge::EditorMode;
+use crate::editor::{request_accessible_text, AccessibleText};
+
+const WINDOW_ID: NodeId = NodeId(0);
+const TEXT_ID: NodeId = NodeId(1);
+const MODE_ID: NodeId = NodeId(2);
+// The lines of the text follow the fixed nodes
+const FIRST_LINE_ID: u64 = 3;
+
+#[cfg(windows)]
+type Adapter = accesskit_windows::SubclassingAdapter;
+#[cfg(target_os = "macos")]
+type Adapter = accesskit_macos::SubclassingAdapter;
+#[cfg(not(any(windows, target_os = "macos")))]
+type Adapter = accesskit_unix::Adapter;
+
+// The grid can't be edited through the accessibility tree, input still goes through the keyboard
+// like it does without a screen reader
+struct IgnoredActions;
+
+impl ActionHandler for IgnoredActions {
+    fn do_action(&mut self, _request: ActionRequest) {}
+}
+
+fn line_id(line: usize) -> NodeId {
+    NodeId(FIRST_LINE_ID + line as u64)
+}
+
+fn mode_name(mode: &EditorMode) -> String {
+    match mode {
+        EditorMode::Normal => "Normal mode".to_owned(),
+        EditorMode::Insert => "Insert mode".to_owned(),
+        EditorMode::Visual => "Visual mode".to_owned(),
+        EditorMode::Replace => "Replace mode".to_owned(),
+        EditorMode::CmdLine => "Command line".to_owned(),
+        EditorMode::Unknown(name) => name.clone(),
+    }
+}
+
+// The caret can be past the end of the line, where the trailing blanks were dropped
+fn caret_position(text: &AccessibleText) -> Option<TextPosition> {
+    let (line, character) = text.cursor;
+    let length = text.lines.get(line)?.chars().count();
+    Some(TextPosition {
+        node: line_id(line),
+        character_index: character.min(length),
+    })
+}
+
+/// The tree is a document with one text box per line, the caret at neovim's cursor and a live
+/// region which announces mode changes. Compared to the text the screen reader already has, only
+/// the nodes that changed are sent, which for most flushes is the document with the caret and the
+/// line being typed in.
+fn build_tree_update(previous: Option<&AccessibleText>, text: &AccessibleText) -> TreeUpdate {
+    let mut classes = NodeClassSet::lock_global();
+    let mut nodes = Vec::new();
+
+    if previous.is_none() {
+        let mut window = NodeBuilder::new(Role::Window);
+        window.set_name("Neovide");
+        window.set_children(vec![TEXT_ID, MODE_ID]);
+        nodes.push((WINDOW_ID, window.build(&mut classes)));
+    }
+
+    if previous.map_or(true, |previous| {
+        previous.lines.len() != text.lines.len() || previous.cursor != text.cursor
+    }) {
+        let mut document = NodeBuilder::new(Role::Document);
+        document.set_children((0..text.lines.len()).map(line_id).collect::<Vec<NodeId>>());
+        if let Some(caret) = caret_position(text) {
+            document.set_text_selection(TextSelection {
+                anchor: caret,
+                focus: caret,
+            });
+        }
+        nodes.push((TEXT_ID, document.build(&mut classes)));
+    }
+
+    if previous.map_or(true, |previous| previous.mode != text.mode) {
+        let mut mode = NodeBuilder::new(Role::Status);
+        mode.set_name(mode_name(&text.mode));
+        mode.set_live(Live::Polite);
+        nodes.push((MODE_ID, mode.build(&mut classes)));
+    }
+
+    for (index, line) in text.lines.iter().enumerate() {
+        let previous_line = previous.and_then(|previous| previous.lines.get(index));
+        if previous_line == Some(line) {
+            continue;
+        }
+        let mut text_box = NodeBuilder::new(Role::InlineTextBox);
+        text_box.set_value(line.as_str());
+        text_box.set_character_lengths(
+            line.chars()
+                .map(|character| character.len_utf8() as u8)
+                .collect::<Vec<u8>>(),
+        );
+        nodes.push((line_id(index), text_box.build(&mut classes)));
+    }
+
+    TreeUpdate {
+        nodes,
+        tree: previous.map_or_else(|| Some(Tree::new(WINDOW_ID)), |_| None),
+        focus: TEXT_ID,
+    }
+}
+
+fn empty_text() -> AccessibleText {
+    AccessibleText {
+        lines: Vec::new(),
+        cursor: (0, 0),
+        mode: EditorMode::Normal,
+    }
+}
+
+// The adapters ask for the tree once assistive technology wants to see the window, which is when
+// the editor starts building its text. Until the next flush there is nothing in it.
+fn initial_tree() -> TreeUpdate {
+    request_accessible_text();
+    build_tree_update(None, &empty_text())
+}
+
+#[cfg(windows)]
+fn create_adapter(window: &Window) -> Option<Adapter> {
+    use accesskit_windows::HWND;
+    use glutin::platform::windows::WindowExtWindows;
+
+    Some(Adapter::new(
+        HWND(window.hwnd() as isize),
+        initial_tree,
+        Box::new(IgnoredActions),
+    ))
+}
+
+#[cfg(target_os = "macos")]
+fn create_adapter(window: &Window) -> Option<Adapter> {
+    use glutin::platform::macos::WindowExtMacOS;
+
+    Some(unsafe { Adapter::new(window.ns_view(), initial_tree, Box::new(IgnoredActions)) })
+}
+
+// AT-SPI isn't tied to the window, the adapter registers neovide on the accessibility bus. There
+// is none when no assistive technology is running, in which case there is nothing to do.
+#[cfg(not(any(windows, target_os = "macos")))]
+fn create_adapter(_window: &Window) -> Option<Adapter> {
+    Adapter::new(
+        "Neovide".to_owned(),
+        "neovide".to_owned(),
+        env!("CARGO_PKG_VERSION").to_owned(),
+        initial_tree,
+        Box::new(IgnoredActions),
+    )
+}
+
+/// Mirrors the text of the current neovim window to the platform's accessibility api, so
+/// screen readers can read the editor like any other text field instead of a picture.
+pub struct Accessibility {
+    adapter: Option<Adapter>,
+    // What the screen reader was sent last, which the next update only sends the changes to
+    sent_text: AccessibleText,
+}
+
+impl Accessibility {
+    pub fn new(window: &Window) -> Accessibility {
+        Accessibility {
+            adapter: create_adapter(window),
+            sent_text: empty_text(),
+        }
+    }
+
+    // The tree is only built when a screen reader is listening
+    pub fn update(&mut self, text: AccessibleText) {
+        let adapter = match &self.adapter {
+            Some(adapter) => adapter,
+            None => return,
+        };
+
+        let sent_text = &mut self.sent_text;
+        let update = || {
+            let update = build_tree_update(Some(sent_text), &text);
+            *sent_text = text;
+            update
+        };
+        #[cfg(any(windows, target_os = "macos"))]
+        if let Some(events) = adapter.update_if_active(update) {
+            events.raise();
+        }
+        #[cfg(not(any(windows, target_os = "macos")))]
+        adapter.update_if_active(update);
+    }
+
+    pub fn set_window_focused(&self, focused: bool) {
+        // The subclassing adapters follow the focus of the window themselves
+        #[cfg(not(any(windows, target_os = "macos")))]
+        if let Some(adapter) = &self.adapter {
+            adapter.update_window_focus_state(focused);
+        }
+        #[cfg(any(windows, target_os = "macos"))]
+        let _ = focused;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_caret_position_stays_in_line() {
+        let text = AccessibleText {
+            lines: vec!["let a = 1;".to_owned(), "".to_owned()],
+            cursor: (0, 4),
+            mode: EditorMode::Normal,
+        };
+        assert_eq!(
+            caret_position(&text).map(|caret| caret.character_index),
+            Some(4)
+        );
+
+        let text = AccessibleText {
+            cursor: (1, 6),
+            ..text
+        };
+        let caret = caret_position(&text).expect("No caret");
+        assert_eq!(caret.node, line_id(1));
+        assert_eq!(caret.character_index, 0);
+
+        let text = AccessibleText {
+            cursor: (5, 0),
+            ..text
+        };
+        assert!(caret_position(&text).is_none());
+    }
+
+    #[test]
+    fn test_tree_has_a_line_per_row() {
+        let text = AccessibleText {
+            lines: vec!["first".to_owned(), "second".to_owned()],
+            cursor: (1, 0),
+            mode: EditorMode::Insert,
+        };
+        let update = build_tree_update(None, &text);
+        assert_eq!(update.nodes.len(), 5);
+        assert!(update.tree.is_some());
+        assert_eq!(update.focus, TEXT_ID);
+        assert_eq!(mode_name(&text.mode), "Insert mode");
+    }
+
+    #[test]
+    fn test_updates_only_send_what_changed() {
+        let text = AccessibleText {
+            lines: vec!["first".to_owned(), "second".to_owned()],
+            cursor: (1, 0),
+            mode: EditorMode::Insert,
+        };
+        let typed = AccessibleText {
+            lines: vec!["first".to_owned(), "second!".to_owned()],
+            cursor: (1, 7),
+            ..text.clone()
+        };
+        let update = build_tree_update(Some(&text), &typed);
+        let ids: Vec<NodeId> = update.nodes.iter().map(|(id, _)| *id).collect();
+        assert_eq!(ids, vec![TEXT_ID, line_id(1)]);
+        assert!(update.tree.is_none());
+
+        assert!(build_tree_update(Some(&typed), &typed).nodes.is_empty());
+    }
+}
@@ -0,0 +1,191 @@
+use glutin::window::Window;
+
+/// Asks the compositor to blur whatever is behind the see through parts of the window: the
+/// system backdrop (acrylic or mica) on Windows, a vibrancy view on macOS and the KDE blur hint on
+/// X11. Wayland compositors have no common protocol for this in the current toolkit, so they are
+/// left to blur according to their own configuration.
+pub struct WindowBlur {
+    enabled: bool,
+    style: String,
+    #[cfg(target_os = "macos")]
+    effect_view: Option<cocoa::base::id>,
+}
+
+impl WindowBlur {
+    pub fn new() -> WindowBlur {
+        WindowBlur {
+            enabled: false,
+            style: String::new(),
+            #[cfg(target_os = "macos")]
+            effect_view: None,
+        }
+    }
+
+    pub fn update(&mut self, window: &Window, enabled: bool, style: &str) {
+        if self.enabled == enabled && (!enabled || self.style == style) {
+            return;
+        }
+
+        self.enabled = enabled;
+        self.style = style.to_owned();
+        self.apply(window);
+    }
+
+    #[cfg(windows)]
+    fn apply(&mut self, window: &Window) {
+        use std::mem::size_of;
+
+        use glutin::platform::windows::WindowExtWindows;
+        use winapi::{
+            shared::{
+                minwindef::{DWORD, LPCVOID},
+                windef::HWND,
+            },
+            um::{
+                dwmapi::{DwmExtendFrameIntoClientArea, DwmSetWindowAttribute},
+                uxtheme::MARGINS,
+            },
+        };
+
+        // Not part of the winapi bindings yet
+        const DWMWA_SYSTEMBACKDROP_TYPE: DWORD = 38;
+        const DWMSBT_NONE: DWORD = 1;
+        const DWMSBT_MAINWINDOW: DWORD = 2;
+        const DWMSBT_TRANSIENTWINDOW: DWORD = 3;
+
+        let backdrop = match (self.enabled, self.style.as_str()) {
+            (false, _) => DWMSBT_NONE,
+            (true, "mica") => DWMSBT_MAINWINDOW,
+            (true, _) => DWMSBT_TRANSIENTWINDOW,
+        };
+        // The backdrop only shows through the parts of the client area the frame extends into
+        let margin = if self.enabled { -1 } else { 0 };
+        let margins = MARGINS {
+            cxLeftWidth: margin,
+            cxRightWidth: margin,
+            cyTopHeight: margin,
+            cyBottomHeight: margin,
+        };
+
+        let hwnd = window.hwnd() as HWND;
+        unsafe {
+            DwmExtendFrameIntoClientArea(hwnd, &margins);
+            DwmSetWindowAttribute(
+                hwnd,
+                DWMWA_SYSTEMBACKDROP_TYPE,
+                &backdrop as *const DWORD as LPCVOID,
+                size_of::<DWORD>() as DWORD,
+            );
+        }
+    }
+
+    #[cfg(target_os = "macos")]
+    fn apply(&mut self, window: &Window) {
+        use cocoa::{
+            base::{id, nil},
+            foundation::NSRect,
+        };
+        use glutin::platform::macos::WindowExtMacOS;
+        use objc::{class, msg_send, sel, sel_impl};
+
+        // AppKit enum values
+        const NS_VIEW_WIDTH_SIZABLE: u64 = 2;
+        const NS_VIEW_HEIGHT_SIZABLE: u64 = 16;
+        const NS_VISUAL_EFFECT_BLENDING_MODE_BEHIND_WINDOW: i64 = 0;
+        const NS_VISUAL_EFFECT_STATE_ACTIVE: i64 = 1;
+        const NS_VISUAL_EFFECT_MATERIAL_UNDER_WINDOW_BACKGROUND: i64 = 21;
+        const NS_WINDOW_BELOW: i64 = -1;
+
+        unsafe {
+            if let Some(effect_view) = self.effect_view.take() {
+                let _: () = msg_send![effect_view, removeFromSuperview];
+                let _: () = msg_send![effect_view, release];
+            }
+
+            if !self.enabled {
+                return;
+            }
+
+            // The content view hosts the metal layer, so the effect view goes behind it in the
+            // frame view rather than inside it
+            let content_view = window.ns_view() as id;
+            let frame_view: id = msg_send![content_view, superview];
+            if frame_view == nil {
+                return;
+            }
+
+            let bounds: NSRect = msg_send![frame_view, bounds];
+            let effect_view: id = msg_send![class!(NSVisualEffectView), alloc];
+            let effect_view: id = msg_send![effect_view, initWithFrame: bounds];
+            let _: () = msg_send![
+                effect_view,
+                setAutoresizingMask: NS_VIEW_WIDTH_SIZABLE | NS_VIEW_HEIGHT_SIZABLE
+            ];
+            let _: () = msg_send![
+                effect_view,
+                setBlendingMode: NS_VISUAL_EFFECT_BLENDING_MODE_BEHIND_WINDOW
+            ];
+            let _: () = msg_send![effect_view, setState: NS_VISUAL_EFFECT_STATE_ACTIVE];
+            let _: () = msg_send![
+                effect_view,
+                setMaterial: NS_VISUAL_EFFECT_MATERIAL_UNDER_WINDOW_BACKGROUND
+            ];
+            let _: () = msg_send![
+                frame_view,
+                addSubview: effect_view
+                positioned: NS_WINDOW_BELOW
+                relativeTo: content_view
+            ];
+            self.effect_view = Some(effect_view);
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    fn apply(&mut self, window: &Window) {
+        use std::os::raw::c_char;
+
+        use glutin::platform::unix::WindowExtUnix;
+        use log::warn;
+        use x11_dl::xlib;
+
+        let (display, x11_window) = match (window.xlib_display(), window.xlib_window()) {
+            (Some(display), Some(x11_window)) => (display as *mut xlib::Display, x11_window),
+            _ => return,
+        };
+
+        let xlib = match xlib::Xlib::open() {
+            Ok(xlib) => xlib,
+            Err(error) => {
+                warn!("Could not load xlib to request window blur: {}", error);
+                return;
+            }
+        };
+
+        unsafe {
+            let atom = (xlib.XInternAtom)(
+                display,
+                b"_KDE_NET_WM_BLUR_BEHIND_REGION\0".as_ptr() as *const c_char,
+                xlib::False,
+            );
+            if self.enabled {
+                // An empty region blurs behind the whole window
+                (xlib.XChangeProperty)(
+                    display,
+                    x11_window,
+                    atom,
+                    xlib::XA_CARDINAL,
+                    32,
+                    xlib::PropModeReplace,
+                    std::ptr::null(),
+                    0,
+                );
+            } else {
+                (xlib.XDeleteProperty)(display, x11_window, atom);
+            }
+            (xlib.XFlush)(display);
+        }
+    }
+
+    #[cfg(not(any(windows, target_os = "macos", target_os = "linux")))]
+    fn apply(&mut self, _window: &Window) {}
+}
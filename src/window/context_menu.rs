@@ -0,0 +1,117 @@
+use glutin::window::Window;
+
+use super::menu_items::MenuAction;
+#[cfg(any(windows, target_os = "macos"))]
+use super::menu_items::CONTEXT_MENU;
+
+/// Whether a native context menu can be shown. Elsewhere right clicks always go to neovim,
+/// which can show its own popup menu.
+pub fn is_supported() -> bool {
+    cfg!(any(windows, target_os = "macos"))
+}
+
+/// Shows the context menu at the pointer and waits until it is closed, returning the picked
+/// action.
+#[cfg(windows)]
+pub fn show(window: &Window) -> Option<MenuAction> {
+    use std::ptr::null_mut;
+
+    use glutin::platform::windows::WindowExtWindows;
+    use winapi::{
+        shared::windef::{HWND, POINT},
+        um::winuser::*,
+    };
+
+    unsafe {
+        let menu = CreatePopupMenu();
+        for action in CONTEXT_MENU.iter() {
+            let title: Vec<u16> = action.title().encode_utf16().chain(Some(0)).collect();
+            AppendMenuW(menu, MF_STRING, action.tag(), title.as_ptr());
+        }
+
+        let mut cursor = POINT { x: 0, y: 0 };
+        GetCursorPos(&mut cursor);
+        let id = TrackPopupMenu(
+            menu,
+            TPM_RETURNCMD | TPM_NONOTIFY | TPM_RIGHTBUTTON,
+            cursor.x,
+            cursor.y,
+            0,
+            window.hwnd() as HWND,
+            null_mut(),
+        );
+        DestroyMenu(menu);
+
+        MenuAction::from_tag(id as usize)
+    }
+}
+
+#[cfg(target_os = "macos")]
+pub fn show(_window: &Window) -> Option<MenuAction> {
+    use std::sync::Once;
+
+    use cocoa::{
+        base::{id, nil},
+        foundation::NSPoint,
+    };
+    use objc::{
+        class,
+        declare::ClassDecl,
+        msg_send,
+        runtime::{Class, Object, Sel},
+        sel, sel_impl,
+    };
+    use parking_lot::Mutex;
+
+    use super::menu::add_action_item;
+
+    lazy_static! {
+        static ref PICKED_ACTION: Mutex<Option<MenuAction>> = Mutex::new(None);
+    }
+    static REGISTER_HANDLER: Once = Once::new();
+
+    extern "C" fn handle_context_item(_this: &Object, _selector: Sel, sender: id) {
+        let tag: i64 = unsafe { msg_send![sender, tag] };
+        *PICKED_ACTION.lock() = MenuAction::from_tag(tag as usize);
+    }
+
+    REGISTER_HANDLER.call_once(|| {
+        let mut decl = ClassDecl::new("NeovideContextMenuHandler", class!(NSObject))
+            .expect("Could not declare context menu handler class");
+        unsafe {
+            decl.add_method(
+                sel!(handleContextItem:),
+                handle_context_item as extern "C" fn(&Object, Sel, id),
+            );
+        }
+        decl.register();
+    });
+
+    unsafe {
+        let handler_class = Class::get("NeovideContextMenuHandler").unwrap();
+        let handler: id = msg_send![handler_class, new];
+        let menu: id = msg_send![class!(NSMenu), new];
+        for action in CONTEXT_MENU.iter() {
+            add_action_item(menu, handler, *action, sel!(handleContextItem:), None);
+        }
+
+        // Without a view the location is in screen coordinates, which is where the pointer is
+        // reported in as well
+        let location: NSPoint = msg_send![class!(NSEvent), mouseLocation];
+        let _: i8 = msg_send![
+            menu,
+            popUpMenuPositioningItem: nil
+            atLocation: location
+            inView: nil
+        ];
+        let _: () = msg_send![menu, release];
+        let _: () = msg_send![handler, release];
+    }
+
+    PICKED_ACTION.lock().take()
+}
+
+#[cfg(not(any(windows, target_os = "macos")))]
+pub fn show(_window: &Window) -> Option<MenuAction> {
+    None
+}
@@ -0,0 +1,277 @@
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use log::{info, warn};
+use serde_json::{json, Value};
+
+const HANDSHAKE_OPCODE: u32 = 0;
+const FRAME_OPCODE: u32 = 1;
+// Discord listens on the first free one of these when several clients are running
+const PIPE_COUNT: usize = 10;
+// A Discord that stopped answering is given up on and connected to again with the next presence
+const TIMEOUT: Duration = Duration::from_secs(5);
+// Discord only ever answers with small json objects, anything longer isn't a frame from it
+const MAX_FRAME_LENGTH: usize = 64 * 1024;
+
+#[cfg(unix)]
+type Connection = std::os::unix::net::UnixStream;
+#[cfg(windows)]
+type Connection = std::fs::File;
+
+/// What is shown on the user's Discord profile. The path of the file is never sent.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Presence {
+    pub filetype: String,
+    pub project: String,
+}
+
+impl Presence {
+    pub fn new(filetype: &str, cwd: &str) -> Presence {
+        Presence {
+            filetype: filetype.to_owned(),
+            project: Path::new(cwd)
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+fn encode_frame(opcode: u32, payload: &Value) -> Vec<u8> {
+    let payload = payload.to_string();
+    let mut frame = Vec::with_capacity(payload.len() + 8);
+    frame.extend_from_slice(&opcode.to_le_bytes());
+    frame.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    frame.extend_from_slice(payload.as_bytes());
+    frame
+}
+
+// No presence clears the activity
+fn activity_payload(presence: Option<&Presence>, start: u64, nonce: u64) -> Value {
+    let activity = presence.map(|presence| {
+        let details = if presence.filetype.is_empty() {
+            "Editing".to_owned()
+        } else {
+            format!("Editing {}", presence.filetype)
+        };
+        let mut activity = json!({
+            "details": details,
+            "timestamps": { "start": start },
+        });
+        if !presence.project.is_empty() {
+            activity["state"] = json!(format!("In {}", presence.project));
+        }
+        activity
+    });
+
+    json!({
+        "cmd": "SET_ACTIVITY",
+        "args": { "pid": std::process::id(), "activity": activity },
+        "nonce": nonce.to_string(),
+    })
+}
+
+#[cfg(unix)]
+fn open_pipe(index: usize) -> io::Result<Connection> {
+    let directory = ["XDG_RUNTIME_DIR", "TMPDIR", "TMP", "TEMP"]
+        .iter()
+        .find_map(|variable| std::env::var(variable).ok())
+        .unwrap_or_else(|| "/tmp".to_owned());
+    let connection =
+        Connection::connect(Path::new(&directory).join(format!("discord-ipc-{}", index)))?;
+    connection.set_read_timeout(Some(TIMEOUT))?;
+    connection.set_write_timeout(Some(TIMEOUT))?;
+    Ok(connection)
+}
+
+#[cfg(unix)]
+fn read_exact(connection: &mut Connection, buffer: &mut [u8]) -> io::Result<()> {
+    connection.read_exact(buffer)
+}
+
+#[cfg(windows)]
+fn open_pipe(index: usize) -> io::Result<Connection> {
+    std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(format!(r"\\.\pipe\discord-ipc-{}", index))
+}
+
+// Named pipes opened as files have no read timeout, so the read only starts once the pipe holds
+// all of it
+#[cfg(windows)]
+fn read_exact(connection: &mut Connection, buffer: &mut [u8]) -> io::Result<()> {
+    use std::{os::windows::io::AsRawHandle, ptr::null_mut, time::Instant};
+    use winapi::um::namedpipeapi::PeekNamedPipe;
+
+    let deadline = Instant::now() + TIMEOUT;
+    loop {
+        let mut available = 0;
+        let peeked = unsafe {
+            PeekNamedPipe(
+                connection.as_raw_handle() as _,
+                null_mut(),
+                0,
+                null_mut(),
+                &mut available,
+                null_mut(),
+            )
+        };
+        if peeked == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        if available as usize >= buffer.len() {
+            return connection.read_exact(buffer);
+        }
+        if Instant::now() > deadline {
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+// Each command is answered with a frame, which has to be read so the pipe doesn't fill up
+fn send_frame(connection: &mut Connection, opcode: u32, payload: &Value) -> io::Result<Value> {
+    connection.write_all(&encode_frame(opcode, payload))?;
+
+    let mut header = [0; 8];
+    read_exact(connection, &mut header)?;
+    let mut length = [0; 4];
+    length.copy_from_slice(&header[4..]);
+    let length = u32::from_le_bytes(length) as usize;
+    if length > MAX_FRAME_LENGTH {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("frame of {} bytes is too long", length),
+        ));
+    }
+    let mut response = vec![0; length];
+    read_exact(connection, &mut response)?;
+    serde_json::from_slice(&response).map_err(|error| io::Error::new(io::ErrorKind::Other, error))
+}
+
+fn connect(client_id: &str) -> Option<Connection> {
+    let mut connection = (0..PIPE_COUNT).find_map(|index| open_pipe(index).ok())?;
+    match send_frame(
+        &mut connection,
+        HANDSHAKE_OPCODE,
+        &json!({ "v": 1, "client_id": client_id }),
+    ) {
+        Ok(_) => {
+            info!("Connected to Discord");
+            Some(connection)
+        }
+        Err(error) => {
+            warn!("Discord refused the connection: {}", error);
+            None
+        }
+    }
+}
+
+// Discord may be started or quit at any time, so the connection is made again whenever the
+// presence changes while there is none
+fn run(client_id: String, receiver: Receiver<Option<Presence>>) {
+    let start = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or_default();
+    let mut connection = None;
+    let mut nonce = 0;
+
+    while let Ok(presence) = receiver.recv() {
+        // Only the latest presence matters after switching buffers quickly
+        let presence = receiver.try_iter().last().unwrap_or(presence);
+        if connection.is_none() {
+            connection = connect(&client_id);
+        }
+        if let Some(stream) = connection.as_mut() {
+            nonce += 1;
+            let payload = activity_payload(presence.as_ref(), start, nonce);
+            if send_frame(stream, FRAME_OPCODE, &payload).is_err() {
+                connection = None;
+            }
+        }
+    }
+}
+
+/// Publishes the file type and project being edited as the user's Discord activity while the
+/// discord_presence setting is on. Talking to Discord happens on its own thread, so a missing or
+/// slow Discord never holds up the window.
+pub struct DiscordPresence {
+    sender: Option<(String, Sender<Option<Presence>>)>,
+    presence: Presence,
+    sent: Option<Presence>,
+}
+
+impl DiscordPresence {
+    pub fn new() -> DiscordPresence {
+        DiscordPresence {
+            sender: None,
+            presence: Presence::default(),
+            sent: None,
+        }
+    }
+
+    pub fn set_presence(&mut self, presence: Presence) {
+        self.presence = presence;
+    }
+
+    pub fn update(&mut self, enabled: bool, client_id: &str) {
+        let enabled = enabled && !client_id.is_empty();
+        if !enabled || self.sender.as_ref().map(|(id, _)| id.as_str()) != Some(client_id) {
+            // Clears the activity before the thread goes away with the sender
+            if let Some((_, sender)) = self.sender.take() {
+                sender.send(None).ok();
+            }
+            self.sent = None;
+        }
+        if !enabled {
+            return;
+        }
+
+        let (_, sender) = self.sender.get_or_insert_with(|| {
+            let (sender, receiver) = channel();
+            let client_id = client_id.to_owned();
+            let thread_client_id = client_id.clone();
+            thread::spawn(move || run(thread_client_id, receiver));
+            (client_id, sender)
+        });
+        if self.sent.as_ref() != Some(&self.presence) {
+            sender.send(Some(self.presence.clone())).ok();
+            self.sent = Some(self.presence.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_frame() {
+        let frame = encode_frame(FRAME_OPCODE, &json!({ "v": 1 }));
+        assert_eq!(&frame[..4], &[1, 0, 0, 0]);
+        assert_eq!(&frame[4..8], &[7, 0, 0, 0]);
+        assert_eq!(&frame[8..], br#"{"v":1}"#);
+    }
+
+    #[test]
+    fn test_activity_payload() {
+        let presence = Presence::new("rust", "/home/user/projects/neovide");
+        assert_eq!(presence.project, "neovide");
+
+        let payload = activity_payload(Some(&presence), 100, 3);
+        assert_eq!(payload["cmd"], "SET_ACTIVITY");
+        assert_eq!(payload["nonce"], "3");
+        let activity = &payload["args"]["activity"];
+        assert_eq!(activity["details"], "Editing rust");
+        assert_eq!(activity["state"], "In neovide");
+        assert_eq!(activity["timestamps"]["start"], 100);
+
+        let payload = activity_payload(None, 100, 4);
+        assert!(payload["args"]["activity"].is_null());
+    }
+}
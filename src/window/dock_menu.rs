@@ -0,0 +1,96 @@
+use std::mem;
+use std::path::Path;
+
+use cocoa::{
+    base::{id, nil},
+    foundation::NSString,
+};
+use objc::{
+    class, msg_send,
+    runtime::{Imp, Object, Sel},
+    sel, sel_impl,
+};
+use parking_lot::Mutex;
+
+use super::menu::{add_action_item, add_item, add_separator, push_menu_action};
+use super::menu_items::MenuAction;
+use super::open_documents::add_app_delegate_method;
+use crate::settings::recent_files;
+use crate::single_instance;
+
+// Recent files are told apart from the menu actions by their tags starting here
+const FILE_TAG_OFFSET: i64 = 1000;
+
+lazy_static! {
+    // The recent files the open Dock menu was built with, by tag
+    static ref DOCK_MENU_FILES: Mutex<Vec<String>> = Mutex::new(Vec::new());
+}
+
+extern "C" fn handle_dock_item(_this: &Object, _selector: Sel, sender: id) {
+    let tag: i64 = unsafe { msg_send![sender, tag] };
+    if tag >= FILE_TAG_OFFSET {
+        let file = DOCK_MENU_FILES
+            .lock()
+            .get((tag - FILE_TAG_OFFSET) as usize)
+            .cloned();
+        // Opens in the running window like the files of a later launch
+        if let Some(file) = file {
+            single_instance::push_open_request(vec![file]);
+        }
+    } else if let Some(action) = MenuAction::from_tag(tag as usize) {
+        push_menu_action(action);
+    }
+}
+
+unsafe fn add_file_item(menu: id, target: id, title: &str, tag: i64) {
+    let item = add_item(menu, title, sel!(handleDockItem:), None);
+    let _: () = msg_send![item, setTag: tag];
+    let _: () = msg_send![item, setTarget: target];
+}
+
+// Asked for every time the Dock icon is right clicked, so the recent files are always current
+extern "C" fn dock_menu(this: &Object, _selector: Sel, _application: id) -> id {
+    let files = recent_files();
+    let target = this as *const Object as id;
+    unsafe {
+        let title = NSString::alloc(nil).init_str("Neovide");
+        let menu: id = msg_send![class!(NSMenu), alloc];
+        let menu: id = msg_send![menu, initWithTitle: title];
+
+        for (index, file) in files.iter().enumerate() {
+            let name = Path::new(file)
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_else(|| file.clone());
+            add_file_item(menu, target, &name, FILE_TAG_OFFSET + index as i64);
+        }
+        if !files.is_empty() {
+            add_separator(menu);
+        }
+        for action in [MenuAction::NewWindow, MenuAction::NewTab].iter() {
+            add_action_item(menu, target, *action, sel!(handleDockItem:), None);
+        }
+
+        *DOCK_MENU_FILES.lock() = files;
+        msg_send![menu, autorelease]
+    }
+}
+
+/// Gives the Dock icon a menu with the recent files, and items for a new window or tab in the
+/// running neovide.
+pub fn install_dock_menu() {
+    let dock_menu: extern "C" fn(&Object, Sel, id) -> id = dock_menu;
+    let handle_dock_item: extern "C" fn(&Object, Sel, id) = handle_dock_item;
+    unsafe {
+        add_app_delegate_method(
+            sel!(applicationDockMenu:),
+            mem::transmute::<_, Imp>(dock_menu),
+            b"@@:@\0",
+        );
+        add_app_delegate_method(
+            sel!(handleDockItem:),
+            mem::transmute::<_, Imp>(handle_dock_item),
+            b"v@:@\0",
+        );
+    }
+}
@@ -0,0 +1,953 @@
+use std::path::PathBuf;
+
+use glutin::{dpi::PhysicalPosition, window::Window};
+use parking_lot::Mutex;
+
+use crate::redraw_scheduler::REDRAW_SCHEDULER;
+
+pub enum DroppedContent {
+    Files(Vec<PathBuf>),
+    Text(String),
+}
+
+pub struct DroppedItem {
+    pub content: DroppedContent,
+    // In physical pixels from the top left of the window
+    pub position: PhysicalPosition<f64>,
+}
+
+lazy_static! {
+    // Drops are handed over by the os outside of winit's events, tagged with the native handle of
+    // the window they landed on
+    static ref DROPS: Mutex<Vec<(u64, DroppedItem)>> = Mutex::new(Vec::new());
+}
+
+#[cfg_attr(
+    not(any(windows, target_os = "macos", target_os = "linux")),
+    allow(dead_code)
+)]
+fn queue_drop(window: u64, item: DroppedItem) {
+    DROPS.lock().push((window, item));
+    // Like the apple events, drops don't produce window events, so the event loop is woken up
+    REDRAW_SCHEDULER.queue_flushed_frame();
+}
+
+/// Replaces winit's drop target, which only takes files, with one that takes text dragged in
+/// from other applications as well. Files are still handed over, so they can be opened the way
+/// winit's dropped files are. Wayland drops are left to winit.
+#[cfg_attr(
+    not(any(windows, target_os = "macos", target_os = "linux")),
+    allow(unused_variables)
+)]
+pub fn install_drop_target(window: &Window) {
+    #[cfg(windows)]
+    windows::install(window);
+    #[cfg(target_os = "macos")]
+    macos::install(window);
+    #[cfg(target_os = "linux")]
+    x11::install(window);
+}
+
+/// Takes what was dropped on the window since the last call.
+pub fn take_drops(window: &Window) -> Vec<DroppedItem> {
+    let handle = native_handle(window);
+    let mut drops = DROPS.lock();
+    let (taken, kept): (Vec<_>, Vec<_>) =
+        drops.drain(..).partition(|(window, _)| *window == handle);
+    *drops = kept;
+    taken.into_iter().map(|(_, item)| item).collect()
+}
+
+#[cfg_attr(
+    not(any(windows, target_os = "macos", target_os = "linux")),
+    allow(unused_variables)
+)]
+fn native_handle(window: &Window) -> u64 {
+    #[cfg(windows)]
+    return {
+        use glutin::platform::windows::WindowExtWindows;
+        window.hwnd() as u64
+    };
+    #[cfg(target_os = "macos")]
+    return {
+        use glutin::platform::macos::WindowExtMacOS;
+        window.ns_window() as u64
+    };
+    #[cfg(target_os = "linux")]
+    return {
+        use glutin::platform::unix::WindowExtUnix;
+        window.xlib_window().unwrap_or(0) as u64
+    };
+    #[cfg(not(any(windows, target_os = "macos", target_os = "linux")))]
+    0
+}
+
+// Turns a text/uri-list, the format X11 drops files in, into the paths of its local files
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn parse_uri_list(uri_list: &str) -> Vec<PathBuf> {
+    uri_list
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| line.strip_prefix("file://"))
+        // Skips the host, which is empty or the name of this machine for local files
+        .filter_map(|path| path.find('/').map(|start| &path[start..]))
+        .map(|path| PathBuf::from(percent_decode(path)))
+        .collect()
+}
+
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let escaped = if bytes[index] == b'%' {
+            text.get(index + 1..index + 3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        } else {
+            None
+        };
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                index += 3;
+            }
+            None => {
+                decoded.push(bytes[index]);
+                index += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(windows)]
+mod windows {
+    use std::{
+        ffi::OsString,
+        os::windows::ffi::OsStringExt,
+        path::PathBuf,
+        ptr,
+        sync::atomic::{AtomicBool, AtomicU32, Ordering},
+    };
+
+    use glutin::{dpi::PhysicalPosition, platform::windows::WindowExtWindows, window::Window};
+    use log::warn;
+    use winapi::{
+        ctypes::c_void,
+        shared::{
+            guiddef::{IsEqualIID, REFIID},
+            minwindef::{DWORD, HGLOBAL, ULONG},
+            windef::{HWND, POINT, POINTL},
+            winerror::{E_NOINTERFACE, HRESULT, SUCCEEDED, S_OK},
+            wtypes::{CLIPFORMAT, DVASPECT_CONTENT},
+        },
+        um::{
+            objidl::{IDataObject, FORMATETC, STGMEDIUM, TYMED_HGLOBAL},
+            ole2::{RegisterDragDrop, RevokeDragDrop},
+            oleidl::{IDropTarget, IDropTargetVtbl, DROPEFFECT_COPY, DROPEFFECT_NONE},
+            shellapi::{DragQueryFileW, HDROP},
+            unknwnbase::{IUnknown, IUnknownVtbl},
+            winbase::{GlobalLock, GlobalUnlock},
+            winuser::{ScreenToClient, CF_HDROP, CF_UNICODETEXT},
+        },
+        Interface,
+    };
+
+    use super::{queue_drop, DroppedContent, DroppedItem};
+
+    #[link(name = "ole32")]
+    extern "system" {
+        fn ReleaseStgMedium(medium: *mut STGMEDIUM);
+    }
+
+    // The COM object handed to RegisterDragDrop. The interface has to come first, since ole only
+    // knows the pointer to it.
+    #[repr(C)]
+    struct DropTarget {
+        interface: IDropTarget,
+        references: AtomicU32,
+        hwnd: HWND,
+        accepted: AtomicBool,
+    }
+
+    static DROP_TARGET_VTBL: IDropTargetVtbl = IDropTargetVtbl {
+        parent: IUnknownVtbl {
+            QueryInterface: query_interface,
+            AddRef: add_ref,
+            Release: release,
+        },
+        DragEnter: drag_enter,
+        DragOver: drag_over,
+        DragLeave: drag_leave,
+        Drop: drop_data,
+    };
+
+    unsafe extern "system" fn query_interface(
+        this: *mut IUnknown,
+        riid: REFIID,
+        object: *mut *mut c_void,
+    ) -> HRESULT {
+        if IsEqualIID(&*riid, &IUnknown::uuidof()) || IsEqualIID(&*riid, &IDropTarget::uuidof()) {
+            add_ref(this);
+            *object = this as *mut c_void;
+            S_OK
+        } else {
+            *object = ptr::null_mut();
+            E_NOINTERFACE
+        }
+    }
+
+    unsafe extern "system" fn add_ref(this: *mut IUnknown) -> ULONG {
+        let target = &*(this as *const DropTarget);
+        target.references.fetch_add(1, Ordering::SeqCst) + 1
+    }
+
+    unsafe extern "system" fn release(this: *mut IUnknown) -> ULONG {
+        let references = {
+            let target = &*(this as *const DropTarget);
+            target.references.fetch_sub(1, Ordering::SeqCst) - 1
+        };
+        if references == 0 {
+            drop(Box::from_raw(this as *mut DropTarget));
+        }
+        references
+    }
+
+    fn format(format: u32) -> FORMATETC {
+        FORMATETC {
+            cfFormat: format as CLIPFORMAT,
+            ptd: ptr::null(),
+            dwAspect: DVASPECT_CONTENT,
+            lindex: -1,
+            tymed: TYMED_HGLOBAL,
+        }
+    }
+
+    unsafe fn has_format(data: *const IDataObject, clipboard_format: u32) -> bool {
+        let mut format = format(clipboard_format);
+        (*data).QueryGetData(&mut format) == S_OK
+    }
+
+    // Calls the reader with the memory holding the format, if the data has it
+    unsafe fn with_format<T>(
+        data: *const IDataObject,
+        clipboard_format: u32,
+        read: impl FnOnce(HGLOBAL) -> Option<T>,
+    ) -> Option<T> {
+        let mut format = format(clipboard_format);
+        let mut medium: STGMEDIUM = std::mem::zeroed();
+        if !SUCCEEDED((*data).GetData(&mut format, &mut medium)) {
+            return None;
+        }
+        let result = read(*(*medium.u).hGlobal());
+        ReleaseStgMedium(&mut medium);
+        result
+    }
+
+    unsafe fn read_text(data: *const IDataObject) -> Option<String> {
+        with_format(data, CF_UNICODETEXT, |memory| {
+            let pointer = GlobalLock(memory) as *const u16;
+            if pointer.is_null() {
+                return None;
+            }
+            let mut length = 0;
+            while *pointer.add(length) != 0 {
+                length += 1;
+            }
+            let text = String::from_utf16_lossy(std::slice::from_raw_parts(pointer, length));
+            GlobalUnlock(memory);
+            Some(text)
+        })
+    }
+
+    unsafe fn read_files(data: *const IDataObject) -> Option<Vec<PathBuf>> {
+        // The memory is the HDROP itself, which DragQueryFileW locks on its own
+        with_format(data, CF_HDROP, |memory| {
+            let hdrop = memory as HDROP;
+            let count = DragQueryFileW(hdrop, 0xFFFF_FFFF, ptr::null_mut(), 0);
+            let paths = (0..count)
+                .map(|index| {
+                    let length = DragQueryFileW(hdrop, index, ptr::null_mut(), 0) as usize;
+                    let mut path = vec![0u16; length + 1];
+                    DragQueryFileW(hdrop, index, path.as_mut_ptr(), path.len() as u32);
+                    PathBuf::from(OsString::from_wide(&path[..length]))
+                })
+                .collect();
+            Some(paths)
+        })
+    }
+
+    fn effect(target: &DropTarget) -> DWORD {
+        if target.accepted.load(Ordering::SeqCst) {
+            DROPEFFECT_COPY
+        } else {
+            DROPEFFECT_NONE
+        }
+    }
+
+    unsafe extern "system" fn drag_enter(
+        this: *mut IDropTarget,
+        data: *const IDataObject,
+        _key_state: DWORD,
+        _point: *const POINTL,
+        effect_out: *mut DWORD,
+    ) -> HRESULT {
+        let target = &*(this as *const DropTarget);
+        let accepted = has_format(data, CF_HDROP) || has_format(data, CF_UNICODETEXT);
+        target.accepted.store(accepted, Ordering::SeqCst);
+        *effect_out = effect(target);
+        S_OK
+    }
+
+    unsafe extern "system" fn drag_over(
+        this: *mut IDropTarget,
+        _key_state: DWORD,
+        _point: *const POINTL,
+        effect_out: *mut DWORD,
+    ) -> HRESULT {
+        *effect_out = effect(&*(this as *const DropTarget));
+        S_OK
+    }
+
+    unsafe extern "system" fn drag_leave(this: *mut IDropTarget) -> HRESULT {
+        let target = &*(this as *const DropTarget);
+        target.accepted.store(false, Ordering::SeqCst);
+        S_OK
+    }
+
+    unsafe extern "system" fn drop_data(
+        this: *mut IDropTarget,
+        data: *const IDataObject,
+        _key_state: DWORD,
+        point: *const POINTL,
+        effect_out: *mut DWORD,
+    ) -> HRESULT {
+        let target = &*(this as *const DropTarget);
+        *effect_out = effect(target);
+
+        // Explorer offers both, and the files are what was meant
+        let content = match read_files(data) {
+            Some(paths) => Some(DroppedContent::Files(paths)),
+            None => read_text(data).map(DroppedContent::Text),
+        };
+        if let Some(content) = content {
+            let mut client_point = POINT {
+                x: (*point).x,
+                y: (*point).y,
+            };
+            ScreenToClient(target.hwnd, &mut client_point);
+            queue_drop(
+                target.hwnd as u64,
+                DroppedItem {
+                    content,
+                    position: PhysicalPosition::new(client_point.x as f64, client_point.y as f64),
+                },
+            );
+        }
+        target.accepted.store(false, Ordering::SeqCst);
+        S_OK
+    }
+
+    pub fn install(window: &Window) {
+        let hwnd = window.hwnd() as HWND;
+        let target = Box::into_raw(Box::new(DropTarget {
+            interface: IDropTarget {
+                lpVtbl: &DROP_TARGET_VTBL,
+            },
+            references: AtomicU32::new(1),
+            hwnd,
+            accepted: AtomicBool::new(false),
+        }));
+        unsafe {
+            // Winit registered a target of its own that only takes files, and a window can only
+            // have one
+            RevokeDragDrop(hwnd);
+            let result = RegisterDragDrop(hwnd, target as *mut IDropTarget);
+            // Ole keeps its own reference while the target is registered
+            release(target as *mut IUnknown);
+            if !SUCCEEDED(result) {
+                warn!("Could not register the drop target: {:#x}", result);
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod macos {
+    use std::ffi::CStr;
+    use std::mem;
+    use std::sync::Once;
+
+    use cocoa::{
+        appkit::{NSFilenamesPboardType, NSPasteboardTypeString},
+        base::{id, nil, BOOL, NO, YES},
+        foundation::{NSArray, NSPoint, NSRect},
+    };
+    use glutin::{dpi::PhysicalPosition, platform::macos::WindowExtMacOS, window::Window};
+    use log::warn;
+    use objc::{
+        msg_send,
+        runtime::{method_setImplementation, object_getClass, Class, Imp, Method, Object, Sel},
+        sel, sel_impl,
+    };
+    use parking_lot::Mutex;
+
+    use super::{queue_drop, DroppedContent, DroppedItem};
+
+    lazy_static! {
+        // Winit's performDragOperation:, which the replacement hands file drops on to
+        static ref WINIT_PERFORM_DRAG: Mutex<Option<Imp>> = Mutex::new(None);
+    }
+
+    static REPLACE_PERFORM_DRAG: Once = Once::new();
+
+    unsafe fn string_from_nsstring(string: id) -> String {
+        let bytes: *const std::os::raw::c_char = msg_send![string, UTF8String];
+        CStr::from_ptr(bytes).to_string_lossy().into_owned()
+    }
+
+    // Winit's window delegate only reads the file names off the pasteboard, so drops without
+    // them are taken here and everything else is passed on to it
+    extern "C" fn perform_drag_operation(this: &Object, selector: Sel, sender: id) -> BOOL {
+        unsafe {
+            let pasteboard: id = msg_send![sender, draggingPasteboard];
+            let file_names: id = msg_send![pasteboard, propertyListForType: NSFilenamesPboardType];
+            let text: id = msg_send![pasteboard, stringForType: NSPasteboardTypeString];
+            if file_names == nil && text != nil {
+                let ns_window: id = msg_send![sender, draggingDestinationWindow];
+                let content_view: id = msg_send![ns_window, contentView];
+                let location: NSPoint = msg_send![sender, draggingLocation];
+                let point: NSPoint = msg_send![content_view, convertPoint: location fromView: nil];
+                let bounds: NSRect = msg_send![content_view, bounds];
+                let scale_factor: f64 = msg_send![ns_window, backingScaleFactor];
+                // Cocoa counts from the bottom left in points
+                let position = PhysicalPosition::new(
+                    point.x * scale_factor,
+                    (bounds.size.height - point.y) * scale_factor,
+                );
+                queue_drop(
+                    ns_window as u64,
+                    DroppedItem {
+                        content: DroppedContent::Text(string_from_nsstring(text)),
+                        position,
+                    },
+                );
+                return YES;
+            }
+
+            match *WINIT_PERFORM_DRAG.lock() {
+                Some(winit_perform_drag) => {
+                    let winit_perform_drag: extern "C" fn(&Object, Sel, id) -> BOOL =
+                        mem::transmute(winit_perform_drag);
+                    winit_perform_drag(this, selector, sender)
+                }
+                None => NO,
+            }
+        }
+    }
+
+    pub fn install(window: &Window) {
+        let ns_window = window.ns_window() as id;
+        unsafe {
+            let types =
+                NSArray::arrayWithObjects(nil, &[NSFilenamesPboardType, NSPasteboardTypeString]);
+            let _: () = msg_send![ns_window, registerForDraggedTypes: types];
+
+            let delegate: id = msg_send![ns_window, delegate];
+            if delegate == nil {
+                warn!("Could not find the window delegate to take text drops");
+                return;
+            }
+            // Every window shares winit's delegate class, so it is changed once
+            REPLACE_PERFORM_DRAG.call_once(|| {
+                let class = &*(object_getClass(delegate) as *const Class);
+                match class.instance_method(sel!(performDragOperation:)) {
+                    Some(method) => {
+                        let perform_drag_operation: extern "C" fn(&Object, Sel, id) -> BOOL =
+                            perform_drag_operation;
+                        let winit_perform_drag = method_setImplementation(
+                            method as *const Method as *mut Method,
+                            mem::transmute::<_, Imp>(perform_drag_operation),
+                        );
+                        *WINIT_PERFORM_DRAG.lock() = Some(winit_perform_drag);
+                    }
+                    None => warn!("Could not find the drop handler of the window delegate"),
+                }
+            });
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod x11 {
+    use std::{
+        mem,
+        os::raw::{c_char, c_int, c_long, c_uchar, c_ulong},
+        ptr, slice,
+        sync::mpsc,
+        thread,
+    };
+
+    use glutin::{dpi::PhysicalPosition, platform::unix::WindowExtUnix, window::Window};
+    use log::warn;
+    use parking_lot::Mutex;
+    use x11_dl::xlib;
+
+    use super::{parse_uri_list, queue_drop, DroppedContent, DroppedItem};
+
+    const XDND_VERSION: c_ulong = 5;
+
+    lazy_static! {
+        // Drops on all windows are redirected to one window of a connection of its own, since
+        // winit handles the drop messages on the window itself and only takes files
+        static ref PROXY_WINDOW: Mutex<Option<xlib::Window>> = Mutex::new(None);
+    }
+
+    struct Atoms {
+        aware: xlib::Atom,
+        proxy: xlib::Atom,
+        enter: xlib::Atom,
+        position: xlib::Atom,
+        status: xlib::Atom,
+        leave: xlib::Atom,
+        drop: xlib::Atom,
+        finished: xlib::Atom,
+        selection: xlib::Atom,
+        type_list: xlib::Atom,
+        action_copy: xlib::Atom,
+        uri_list: xlib::Atom,
+        // In order of preference
+        text_types: [xlib::Atom; 3],
+        incr: xlib::Atom,
+        property: xlib::Atom,
+    }
+
+    impl Atoms {
+        unsafe fn new(xlib: &xlib::Xlib, display: *mut xlib::Display) -> Atoms {
+            let atom = |name: &[u8]| {
+                (xlib.XInternAtom)(display, name.as_ptr() as *const c_char, xlib::False)
+            };
+            Atoms {
+                aware: atom(b"XdndAware\0"),
+                proxy: atom(b"XdndProxy\0"),
+                enter: atom(b"XdndEnter\0"),
+                position: atom(b"XdndPosition\0"),
+                status: atom(b"XdndStatus\0"),
+                leave: atom(b"XdndLeave\0"),
+                drop: atom(b"XdndDrop\0"),
+                finished: atom(b"XdndFinished\0"),
+                selection: atom(b"XdndSelection\0"),
+                type_list: atom(b"XdndTypeList\0"),
+                action_copy: atom(b"XdndActionCopy\0"),
+                uri_list: atom(b"text/uri-list\0"),
+                text_types: [
+                    atom(b"text/plain;charset=utf-8\0"),
+                    atom(b"UTF8_STRING\0"),
+                    atom(b"text/plain\0"),
+                ],
+                incr: atom(b"INCR\0"),
+                property: atom(b"NEOVIDE_DROP\0"),
+            }
+        }
+    }
+
+    // A drag over one of the windows, from XdndEnter until it is dropped or leaves
+    struct Drag {
+        source: xlib::Window,
+        target: xlib::Window,
+        data_type: Option<xlib::Atom>,
+        position: PhysicalPosition<f64>,
+    }
+
+    struct DropProxy {
+        xlib: xlib::Xlib,
+        display: *mut xlib::Display,
+        window: xlib::Window,
+        atoms: Atoms,
+        drag: Option<Drag>,
+    }
+
+    impl DropProxy {
+        unsafe fn send_message(
+            &self,
+            destination: xlib::Window,
+            message_type: xlib::Atom,
+            data: [c_long; 5],
+        ) {
+            let mut event = xlib::XEvent {
+                client_message: xlib::XClientMessageEvent {
+                    type_: xlib::ClientMessage,
+                    serial: 0,
+                    send_event: xlib::True,
+                    display: self.display,
+                    window: destination,
+                    message_type,
+                    format: 32,
+                    data: xlib::ClientMessageData::from(data),
+                },
+            };
+            (self.xlib.XSendEvent)(
+                self.display,
+                destination,
+                xlib::False,
+                xlib::NoEventMask,
+                &mut event,
+            );
+            (self.xlib.XFlush)(self.display);
+        }
+
+        unsafe fn offered_types(
+            &self,
+            source: xlib::Window,
+            data: &xlib::ClientMessageData,
+        ) -> Vec<xlib::Atom> {
+            // Sources with more than three types list them in a property instead
+            if data.get_long(1) & 1 == 0 {
+                return (2..5)
+                    .map(|index| data.get_long(index) as xlib::Atom)
+                    .filter(|atom| *atom != 0)
+                    .collect();
+            }
+            match self.read_property(source, self.atoms.type_list) {
+                Some((_, bytes)) => bytes
+                    .chunks_exact(mem::size_of::<c_ulong>())
+                    .map(|chunk| {
+                        let mut atom = [0; mem::size_of::<c_ulong>()];
+                        atom.copy_from_slice(chunk);
+                        c_ulong::from_ne_bytes(atom)
+                    })
+                    .collect(),
+                None => Vec::new(),
+            }
+        }
+
+        // Reads and deletes a property, returning its type and contents. Xlib hands format 32
+        // properties over as longs.
+        unsafe fn read_property(
+            &self,
+            window: xlib::Window,
+            property: xlib::Atom,
+        ) -> Option<(xlib::Atom, Vec<u8>)> {
+            let mut actual_type = 0;
+            let mut format: c_int = 0;
+            let mut item_count: c_ulong = 0;
+            let mut bytes_after: c_ulong = 0;
+            let mut data: *mut c_uchar = ptr::null_mut();
+            let result = (self.xlib.XGetWindowProperty)(
+                self.display,
+                window,
+                property,
+                0,
+                c_long::MAX / 4,
+                xlib::False,
+                xlib::AnyPropertyType as xlib::Atom,
+                &mut actual_type,
+                &mut format,
+                &mut item_count,
+                &mut bytes_after,
+                &mut data,
+            );
+            if result != xlib::Success as c_int || data.is_null() {
+                return None;
+            }
+            let item_size = match format {
+                8 => 1,
+                16 => mem::size_of::<std::os::raw::c_short>(),
+                _ => mem::size_of::<c_long>(),
+            };
+            let bytes = slice::from_raw_parts(data, item_count as usize * item_size).to_vec();
+            (self.xlib.XFree)(data as *mut _);
+            Some((actual_type, bytes))
+        }
+
+        unsafe fn handle_client_message(&mut self, message: &xlib::XClientMessageEvent) {
+            let atoms = &self.atoms;
+            let source = message.data.get_long(0) as xlib::Window;
+            if message.message_type == atoms.enter {
+                let offered = self.offered_types(source, &message.data);
+                let data_type = if offered.contains(&self.atoms.uri_list) {
+                    Some(self.atoms.uri_list)
+                } else {
+                    self.atoms
+                        .text_types
+                        .iter()
+                        .copied()
+                        .find(|text_type| offered.contains(text_type))
+                };
+                self.drag = Some(Drag {
+                    source,
+                    target: message.window,
+                    data_type,
+                    position: PhysicalPosition::new(0.0, 0.0),
+                });
+            } else if message.message_type == atoms.position {
+                let root_position = message.data.get_long(2);
+                let (root_x, root_y) = (
+                    ((root_position >> 16) & 0xffff) as c_int,
+                    (root_position & 0xffff) as c_int,
+                );
+                let accepted = match &mut self.drag {
+                    Some(drag) => {
+                        let (mut x, mut y) = (0, 0);
+                        let mut child = 0;
+                        let root = (self.xlib.XDefaultRootWindow)(self.display);
+                        (self.xlib.XTranslateCoordinates)(
+                            self.display,
+                            root,
+                            drag.target,
+                            root_x,
+                            root_y,
+                            &mut x,
+                            &mut y,
+                            &mut child,
+                        );
+                        drag.position = PhysicalPosition::new(x as f64, y as f64);
+                        drag.data_type.is_some()
+                    }
+                    None => false,
+                };
+                let action = if accepted { self.atoms.action_copy } else { 0 };
+                self.send_message(
+                    source,
+                    self.atoms.status,
+                    [
+                        message.window as c_long,
+                        accepted as c_long,
+                        0,
+                        0,
+                        action as c_long,
+                    ],
+                );
+            } else if message.message_type == atoms.leave {
+                self.drag = None;
+            } else if message.message_type == atoms.drop {
+                let time = message.data.get_long(2) as xlib::Time;
+                match self.drag.as_ref().and_then(|drag| drag.data_type) {
+                    // The data comes back in a SelectionNotify
+                    Some(data_type) => {
+                        (self.xlib.XConvertSelection)(
+                            self.display,
+                            self.atoms.selection,
+                            data_type,
+                            self.atoms.property,
+                            self.window,
+                            time,
+                        );
+                        (self.xlib.XFlush)(self.display);
+                    }
+                    None => self.finish(source, message.window, false),
+                }
+            }
+        }
+
+        unsafe fn handle_selection_notify(&mut self, selection: &xlib::XSelectionEvent) {
+            let drag = match self.drag.take() {
+                Some(drag) => drag,
+                None => return,
+            };
+            let data = if selection.property == 0 {
+                None
+            } else {
+                self.read_property(self.window, selection.property)
+            };
+            (self.xlib.XDeleteProperty)(self.display, self.window, self.atoms.property);
+
+            let content = match data {
+                Some((data_type, _)) if data_type == self.atoms.incr => {
+                    warn!("Dropped data too large to be sent at once was ignored");
+                    None
+                }
+                Some((_, bytes)) => {
+                    let text = String::from_utf8_lossy(&bytes).into_owned();
+                    if drag.data_type == Some(self.atoms.uri_list) {
+                        Some(DroppedContent::Files(parse_uri_list(&text)))
+                    } else {
+                        Some(DroppedContent::Text(text))
+                    }
+                }
+                None => None,
+            };
+            let succeeded = content.is_some();
+            if let Some(content) = content {
+                queue_drop(
+                    drag.target as u64,
+                    DroppedItem {
+                        content,
+                        position: drag.position,
+                    },
+                );
+            }
+            self.finish(drag.source, drag.target, succeeded);
+        }
+
+        unsafe fn finish(&self, source: xlib::Window, target: xlib::Window, succeeded: bool) {
+            let action = if succeeded { self.atoms.action_copy } else { 0 };
+            self.send_message(
+                source,
+                self.atoms.finished,
+                [
+                    target as c_long,
+                    succeeded as c_long,
+                    action as c_long,
+                    0,
+                    0,
+                ],
+            );
+        }
+
+        unsafe fn run(mut self) {
+            let mut event: xlib::XEvent = mem::zeroed();
+            loop {
+                (self.xlib.XNextEvent)(self.display, &mut event);
+                match event.get_type() {
+                    xlib::ClientMessage => self.handle_client_message(&event.client_message),
+                    xlib::SelectionNotify => self.handle_selection_notify(&event.selection),
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    unsafe fn set_window_property(
+        xlib: &xlib::Xlib,
+        display: *mut xlib::Display,
+        window: xlib::Window,
+        property: xlib::Atom,
+        property_type: xlib::Atom,
+        value: c_ulong,
+    ) {
+        (xlib.XChangeProperty)(
+            display,
+            window,
+            property,
+            property_type,
+            32,
+            xlib::PropModeReplace,
+            &value as *const c_ulong as *const c_uchar,
+            1,
+        );
+    }
+
+    // Creates the proxy window on a connection of its own, and handles the drops sent to it
+    // there until neovide exits
+    fn start_drop_proxy() -> Option<xlib::Window> {
+        let (window_sender, window_receiver) = mpsc::channel();
+        thread::spawn(move || unsafe {
+            let xlib = match xlib::Xlib::open() {
+                Ok(xlib) => xlib,
+                Err(error) => {
+                    warn!("Could not load xlib to take text drops: {}", error);
+                    window_sender.send(None).ok();
+                    return;
+                }
+            };
+            let display = (xlib.XOpenDisplay)(ptr::null());
+            if display.is_null() {
+                warn!("Could not open a display connection to take text drops");
+                window_sender.send(None).ok();
+                return;
+            }
+            let root = (xlib.XDefaultRootWindow)(display);
+            let window = (xlib.XCreateSimpleWindow)(display, root, -10, -10, 1, 1, 0, 0, 0);
+            let atoms = Atoms::new(&xlib, display);
+            // The protocol asks for the proxy to point at itself
+            set_window_property(&xlib, display, window, atoms.proxy, xlib::XA_WINDOW, window);
+            set_window_property(
+                &xlib,
+                display,
+                window,
+                atoms.aware,
+                xlib::XA_ATOM,
+                XDND_VERSION,
+            );
+            (xlib.XFlush)(display);
+            window_sender.send(Some(window)).ok();
+
+            DropProxy {
+                xlib,
+                display,
+                window,
+                atoms,
+                drag: None,
+            }
+            .run();
+        });
+        window_receiver.recv().ok().flatten()
+    }
+
+    pub fn install(window: &Window) {
+        let (display, x11_window) = match (window.xlib_display(), window.xlib_window()) {
+            (Some(display), Some(x11_window)) => (display as *mut xlib::Display, x11_window),
+            _ => return,
+        };
+
+        let proxy_window = {
+            let mut proxy_window = PROXY_WINDOW.lock();
+            if proxy_window.is_none() {
+                *proxy_window = start_drop_proxy();
+            }
+            match *proxy_window {
+                Some(proxy_window) => proxy_window,
+                None => return,
+            }
+        };
+
+        let xlib = match xlib::Xlib::open() {
+            Ok(xlib) => xlib,
+            Err(error) => {
+                warn!("Could not load xlib to take text drops: {}", error);
+                return;
+            }
+        };
+        unsafe {
+            let atom = |name: &[u8]| {
+                (xlib.XInternAtom)(display, name.as_ptr() as *const c_char, xlib::False)
+            };
+            set_window_property(
+                &xlib,
+                display,
+                x11_window,
+                atom(b"XdndProxy\0"),
+                xlib::XA_WINDOW,
+                proxy_window,
+            );
+            set_window_property(
+                &xlib,
+                display,
+                x11_window,
+                atom(b"XdndAware\0"),
+                xlib::XA_ATOM,
+                XDND_VERSION,
+            );
+            (xlib.XFlush)(display);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_uri_list() {
+        assert_eq!(
+            parse_uri_list(
+                "# dragged from a file manager\r\nfile:///home/user/my%20notes.md\r\nfile://host/etc/hosts\r\nhttps://neovim.io\r\n"
+            ),
+            vec![
+                PathBuf::from("/home/user/my notes.md"),
+                PathBuf::from("/etc/hosts"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_percent_decode() {
+        assert_eq!(percent_decode("caf%C3%A9"), "café");
+        // Escapes that aren't complete are kept as they are
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%zz"), "%zz");
+    }
+}
@@ -0,0 +1,279 @@
+use std::cmp::Ordering;
+
+use glutin::{
+    dpi::PhysicalPosition,
+    event::{ElementState, Event, MouseScrollDelta, WindowEvent},
+    event_loop::EventLoopWindowTarget,
+    window::WindowBuilder,
+};
+
+use super::{
+    frameless::FramelessWindowHandler,
+    keyboard_manager::KeyboardManager,
+    load_icon,
+    mouse_manager::mouse_button_to_button_text,
+    renderer::{build_skia_renderer, SkiaRenderer},
+};
+use crate::{
+    bridge::{ParallelCommand, SerialCommand, UiCommand},
+    channel_utils::LoggingTx,
+    renderer::{RenderedWindow, Renderer},
+    utils::Dimensions,
+};
+
+// Turns accumulated scroll deltas into whole line steps
+fn scroll_steps(previous: f32, current: f32, positive: &str, negative: &str) -> Vec<String> {
+    let (previous, current) = (previous as i64, current as i64);
+    let direction = match current.cmp(&previous) {
+        Ordering::Greater => positive,
+        Ordering::Less => negative,
+        Ordering::Equal => return Vec::new(),
+    };
+    vec![direction.to_owned(); (current - previous).abs() as usize]
+}
+
+/// A grid neovim moved out of the editor with win_external_pos. It gets a borderless os window of
+/// its own, which can be moved to another monitor by dragging it with the logo key held and
+/// resized from its edges just like frameless main windows.
+pub struct ExternalWindow {
+    pub skia_renderer: Box<dyn SkiaRenderer>,
+    grid_id: u64,
+    window_handle: Option<u64>,
+    grid_size: Dimensions,
+    frameless_handler: FramelessWindowHandler,
+    ui_command_sender: LoggingTx<UiCommand>,
+    mouse_position: PhysicalPosition<u32>,
+    dragging: Option<String>,
+    scroll_position: PhysicalPosition<f32>,
+}
+
+impl ExternalWindow {
+    pub fn new(
+        window_target: &EventLoopWindowTarget<()>,
+        rendered_window: &RenderedWindow,
+        renderer: &Renderer,
+        ui_command_sender: LoggingTx<UiCommand>,
+    ) -> ExternalWindow {
+        let window_builder = WindowBuilder::new()
+            .with_title("Neovide")
+            .with_window_icon(Some(load_icon()))
+            .with_transparent(true)
+            .with_decorations(false)
+            .with_inner_size(
+                renderer
+                    .grid_renderer
+                    .convert_grid_to_physical(rendered_window.grid_size),
+            );
+
+        ExternalWindow {
+            skia_renderer: build_skia_renderer(window_builder, window_target),
+            grid_id: rendered_window.id,
+            window_handle: rendered_window.window_handle,
+            grid_size: rendered_window.grid_size,
+            frameless_handler: FramelessWindowHandler::new(),
+            ui_command_sender,
+            mouse_position: PhysicalPosition::new(0, 0),
+            dragging: None,
+            scroll_position: PhysicalPosition::new(0.0, 0.0),
+        }
+    }
+
+    pub fn owns(&self, window_id: glutin::window::WindowId) -> bool {
+        self.skia_renderer.window().id() == window_id
+    }
+
+    /// Follows grid resizes coming from neovim, such as `:resize` in the external window.
+    pub fn update(&mut self, rendered_window: &RenderedWindow, renderer: &Renderer) {
+        self.window_handle = rendered_window.window_handle;
+
+        if self.grid_size != rendered_window.grid_size {
+            self.grid_size = rendered_window.grid_size;
+            self.skia_renderer.window().set_inner_size(
+                renderer
+                    .grid_renderer
+                    .convert_grid_to_physical(self.grid_size),
+            );
+        }
+    }
+
+    pub fn draw(&mut self, renderer: &mut Renderer, dt: f32) {
+        if !self.skia_renderer.has_area() {
+            return;
+        }
+        self.skia_renderer.make_current();
+        renderer.draw_external_window(self.skia_renderer.canvas(), self.grid_id, dt);
+        self.skia_renderer.flush();
+        self.skia_renderer.swap_buffers();
+    }
+
+    fn send_scroll(&self, directions: Vec<String>, keyboard_manager: &KeyboardManager) {
+        for direction in directions {
+            self.ui_command_sender
+                .send(
+                    SerialCommand::Scroll {
+                        direction,
+                        grid_id: self.grid_id,
+                        position: self.mouse_position.into(),
+                        modifier_string: keyboard_manager.format_modifier_string(true),
+                    }
+                    .into(),
+                )
+                .ok();
+        }
+    }
+
+    fn handle_scroll(&mut self, x: f32, y: f32, keyboard_manager: &KeyboardManager) {
+        let previous = self.scroll_position;
+        self.scroll_position.x += x;
+        self.scroll_position.y += y;
+
+        self.send_scroll(
+            scroll_steps(previous.y, self.scroll_position.y, "up", "down"),
+            keyboard_manager,
+        );
+        self.send_scroll(
+            scroll_steps(previous.x, self.scroll_position.x, "right", "left"),
+            keyboard_manager,
+        );
+    }
+
+    /// Keyboard input is handled by the keyboard manager of the main window, since it goes to
+    /// the same neovim instance either way. Mouse positions are sent relative to the grid.
+    pub fn handle_event(
+        &mut self,
+        event: &Event<()>,
+        keyboard_manager: &KeyboardManager,
+        renderer: &Renderer,
+        mouse_enabled: bool,
+    ) {
+        if self
+            .frameless_handler
+            .handle_event(event, self.skia_renderer.window())
+        {
+            return;
+        }
+
+        let font_dimensions = renderer.grid_renderer.font_dimensions;
+        match event {
+            Event::WindowEvent {
+                event: WindowEvent::Resized(new_size),
+                ..
+            } => {
+                if !self.skia_renderer.has_area() {
+                    return;
+                }
+                self.skia_renderer.make_current();
+                self.skia_renderer.resize();
+
+                let grid_size = renderer.grid_renderer.convert_physical_to_grid(*new_size);
+                if grid_size != self.grid_size && grid_size.width > 0 && grid_size.height > 0 {
+                    self.grid_size = grid_size;
+                    self.ui_command_sender
+                        .send(
+                            ParallelCommand::ResizeGrid {
+                                grid_id: self.grid_id,
+                                width: grid_size.width,
+                                height: grid_size.height,
+                            }
+                            .into(),
+                        )
+                        .ok();
+                }
+            }
+            Event::WindowEvent {
+                event: WindowEvent::CloseRequested,
+                ..
+            } => {
+                if let Some(window_handle) = self.window_handle {
+                    self.ui_command_sender
+                        .send(ParallelCommand::CloseWindow { window_handle }.into())
+                        .ok();
+                }
+            }
+            Event::WindowEvent {
+                event: WindowEvent::CursorMoved { position, .. },
+                ..
+            } if mouse_enabled => {
+                let new_position = PhysicalPosition::new(
+                    ((position.x.max(0.0) as u64 / font_dimensions.width)
+                        .min(self.grid_size.width.saturating_sub(1))) as u32,
+                    ((position.y.max(0.0) as u64 / font_dimensions.height)
+                        .min(self.grid_size.height.saturating_sub(1))) as u32,
+                );
+                if new_position == self.mouse_position {
+                    return;
+                }
+                self.mouse_position = new_position;
+
+                if let Some(button) = &self.dragging {
+                    self.ui_command_sender
+                        .send(
+                            SerialCommand::Drag {
+                                button: button.clone(),
+                                grid_id: self.grid_id,
+                                position: self.mouse_position.into(),
+                                modifier_string: keyboard_manager.format_modifier_string(true),
+                            }
+                            .into(),
+                        )
+                        .ok();
+                }
+            }
+            Event::WindowEvent {
+                event: WindowEvent::MouseInput { button, state, .. },
+                ..
+            } if mouse_enabled => {
+                if let Some(button_text) = mouse_button_to_button_text(button) {
+                    let down = *state == ElementState::Pressed;
+                    let action = if down { "press" } else { "release" };
+                    self.ui_command_sender
+                        .send(
+                            SerialCommand::MouseButton {
+                                button: button_text.clone(),
+                                action: action.to_owned(),
+                                grid_id: self.grid_id,
+                                position: self.mouse_position.into(),
+                                modifier_string: keyboard_manager.format_modifier_string(true),
+                            }
+                            .into(),
+                        )
+                        .ok();
+                    self.dragging = if down { Some(button_text) } else { None };
+                }
+            }
+            Event::WindowEvent {
+                event:
+                    WindowEvent::MouseWheel {
+                        delta: MouseScrollDelta::LineDelta(x, y),
+                        ..
+                    },
+                ..
+            } if mouse_enabled => self.handle_scroll(*x, *y, keyboard_manager),
+            Event::WindowEvent {
+                event:
+                    WindowEvent::MouseWheel {
+                        delta: MouseScrollDelta::PixelDelta(delta),
+                        ..
+                    },
+                ..
+            } if mouse_enabled => self.handle_scroll(
+                delta.x as f32 / font_dimensions.width as f32,
+                delta.y as f32 / font_dimensions.height as f32,
+                keyboard_manager,
+            ),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scroll_steps() {
+        assert!(scroll_steps(0.2, 0.9, "up", "down").is_empty());
+        assert_eq!(scroll_steps(0.5, 2.5, "up", "down"), vec!["up", "up"]);
+        assert_eq!(scroll_steps(1.5, 0.5, "right", "left"), vec!["left"]);
+    }
+}
@@ -0,0 +1,253 @@
+use glutin::{
+    dpi::{PhysicalPosition, PhysicalSize},
+    event::{ElementState, Event, MouseButton, WindowEvent},
+    window::{CursorIcon, Window},
+};
+use log::warn;
+
+const RESIZE_BORDER: f64 = 6.0;
+const MIN_WINDOW_SIZE: i32 = 100;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct ResizeEdges {
+    left: bool,
+    right: bool,
+    top: bool,
+    bottom: bool,
+}
+
+impl ResizeEdges {
+    fn at(position: PhysicalPosition<f64>, size: PhysicalSize<u32>) -> Option<ResizeEdges> {
+        let edges = ResizeEdges {
+            left: position.x < RESIZE_BORDER,
+            right: position.x >= size.width as f64 - RESIZE_BORDER,
+            top: position.y < RESIZE_BORDER,
+            bottom: position.y >= size.height as f64 - RESIZE_BORDER,
+        };
+
+        if edges.left || edges.right || edges.top || edges.bottom {
+            Some(edges)
+        } else {
+            None
+        }
+    }
+
+    fn cursor_icon(&self) -> CursorIcon {
+        match (self.left, self.right, self.top, self.bottom) {
+            (true, _, true, _) | (_, true, _, true) => CursorIcon::NwseResize,
+            (true, _, _, true) | (_, true, true, _) => CursorIcon::NeswResize,
+            (true, _, _, _) | (_, true, _, _) => CursorIcon::EwResize,
+            _ => CursorIcon::NsResize,
+        }
+    }
+
+    // Computes the new outer position and size after the cursor moved by the given delta since
+    // the drag started
+    fn resize(
+        &self,
+        start_position: PhysicalPosition<i32>,
+        start_size: PhysicalSize<u32>,
+        (delta_x, delta_y): (i32, i32),
+    ) -> (PhysicalPosition<i32>, PhysicalSize<u32>) {
+        let (mut x, mut y) = (start_position.x, start_position.y);
+        let (mut width, mut height) = (start_size.width as i32, start_size.height as i32);
+
+        if self.left {
+            let delta_x = delta_x.min(width - MIN_WINDOW_SIZE);
+            x += delta_x;
+            width -= delta_x;
+        } else if self.right {
+            width = (width + delta_x).max(MIN_WINDOW_SIZE);
+        }
+
+        if self.top {
+            let delta_y = delta_y.min(height - MIN_WINDOW_SIZE);
+            y += delta_y;
+            height -= delta_y;
+        } else if self.bottom {
+            height = (height + delta_y).max(MIN_WINDOW_SIZE);
+        }
+
+        (
+            PhysicalPosition::new(x, y),
+            PhysicalSize::new(width as u32, height as u32),
+        )
+    }
+}
+
+struct ResizeDrag {
+    edges: ResizeEdges,
+    start_cursor: PhysicalPosition<f64>,
+    start_position: PhysicalPosition<i32>,
+    start_size: PhysicalSize<u32>,
+}
+
+/// Without decorations the window manager provides no borders to grab, so frameless windows
+/// handle edge dragging themselves. Holding the logo key while dragging moves the window.
+pub struct FramelessWindowHandler {
+    cursor_position: PhysicalPosition<f64>,
+    hovered_edges: Option<ResizeEdges>,
+    resize_drag: Option<ResizeDrag>,
+    logo: bool,
+}
+
+impl FramelessWindowHandler {
+    pub fn new() -> FramelessWindowHandler {
+        FramelessWindowHandler {
+            cursor_position: PhysicalPosition::new(0.0, 0.0),
+            hovered_edges: None,
+            resize_drag: None,
+            logo: false,
+        }
+    }
+
+    // Cursor positions are relative to the window, which itself moves while dragging the left or
+    // top edge, so drags are tracked in screen coordinates instead
+    fn screen_cursor_position(&self, window: &Window) -> PhysicalPosition<f64> {
+        let origin = window
+            .inner_position()
+            .unwrap_or_else(|_| PhysicalPosition::new(0, 0));
+        PhysicalPosition::new(
+            origin.x as f64 + self.cursor_position.x,
+            origin.y as f64 + self.cursor_position.y,
+        )
+    }
+
+    fn update_hovered_edges(&mut self, window: &Window) {
+        let hovered_edges = ResizeEdges::at(self.cursor_position, window.inner_size());
+        if hovered_edges != self.hovered_edges {
+            window.set_cursor_icon(
+                hovered_edges
+                    .map(|edges| edges.cursor_icon())
+                    .unwrap_or(CursorIcon::Default),
+            );
+            self.hovered_edges = hovered_edges;
+        }
+    }
+
+    /// Whether the pointer is over an edge the window can be resized from, in which case the
+    /// pointer shows the resize direction.
+    pub fn hovering_edge(&self) -> bool {
+        self.hovered_edges.is_some()
+    }
+
+    /// Returns whether the event was used for moving or resizing the window, in which case it
+    /// shouldn't be forwarded to neovim.
+    pub fn handle_event(&mut self, event: &Event<()>, window: &Window) -> bool {
+        match event {
+            Event::WindowEvent {
+                event: WindowEvent::ModifiersChanged(modifiers),
+                ..
+            } => {
+                self.logo = modifiers.super_key();
+                false
+            }
+            Event::WindowEvent {
+                event: WindowEvent::CursorMoved { position, .. },
+                ..
+            } => {
+                self.cursor_position = *position;
+
+                if let Some(drag) = &self.resize_drag {
+                    let cursor = self.screen_cursor_position(window);
+                    let delta = (
+                        (cursor.x - drag.start_cursor.x) as i32,
+                        (cursor.y - drag.start_cursor.y) as i32,
+                    );
+                    let (position, size) =
+                        drag.edges
+                            .resize(drag.start_position, drag.start_size, delta);
+                    window.set_outer_position(position);
+                    window.set_inner_size(size);
+                    true
+                } else {
+                    self.update_hovered_edges(window);
+                    false
+                }
+            }
+            Event::WindowEvent {
+                event:
+                    WindowEvent::MouseInput {
+                        button: MouseButton::Left,
+                        state,
+                        ..
+                    },
+                ..
+            } => match state {
+                ElementState::Pressed => {
+                    if let Some(edges) = self.hovered_edges {
+                        self.resize_drag = Some(ResizeDrag {
+                            edges,
+                            start_cursor: self.screen_cursor_position(window),
+                            start_position: window
+                                .outer_position()
+                                .unwrap_or_else(|_| PhysicalPosition::new(0, 0)),
+                            start_size: window.inner_size(),
+                        });
+                        true
+                    } else if self.logo {
+                        if let Err(error) = window.drag_window() {
+                            warn!("Could not move the window: {}", error);
+                        }
+                        true
+                    } else {
+                        false
+                    }
+                }
+                ElementState::Released => self.resize_drag.take().is_some(),
+            },
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_edges_at() {
+        let size = PhysicalSize::new(200, 100);
+
+        assert_eq!(
+            ResizeEdges::at(PhysicalPosition::new(100.0, 50.0), size),
+            None
+        );
+        assert_eq!(
+            ResizeEdges::at(PhysicalPosition::new(1.0, 99.0), size),
+            Some(ResizeEdges {
+                left: true,
+                right: false,
+                top: false,
+                bottom: true,
+            })
+        );
+    }
+
+    #[test]
+    fn test_resize_from_top_left() {
+        let edges = ResizeEdges {
+            left: true,
+            right: false,
+            top: true,
+            bottom: false,
+        };
+
+        let (position, size) = edges.resize(
+            PhysicalPosition::new(50, 50),
+            PhysicalSize::new(300, 200),
+            (-10, 20),
+        );
+        assert_eq!(position, PhysicalPosition::new(40, 70));
+        assert_eq!(size, PhysicalSize::new(310, 180));
+
+        // The window never shrinks below the minimum size
+        let (position, size) = edges.resize(
+            PhysicalPosition::new(50, 50),
+            PhysicalSize::new(300, 200),
+            (1000, 1000),
+        );
+        assert_eq!(position, PhysicalPosition::new(250, 150));
+        assert_eq!(size, PhysicalSize::new(100, 100));
+    }
+}
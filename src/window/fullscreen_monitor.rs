@@ -0,0 +1,65 @@
+use glutin::{monitor::MonitorHandle, window::Window};
+use log::warn;
+
+// Which of the monitors, by their names, `g:neovide_fullscreen_monitor` picks. Numbers count
+// from 1 like the display settings do, anything else is matched against the names ignoring case.
+fn pick_monitor(choice: &str, names: &[Option<String>]) -> Option<usize> {
+    let choice = choice.trim();
+    if choice.is_empty() {
+        return None;
+    }
+    if let Ok(number) = choice.parse::<usize>() {
+        return number.checked_sub(1).filter(|index| *index < names.len());
+    }
+    let choice = choice.to_lowercase();
+    names
+        .iter()
+        .position(|name| name.as_ref().map(|name| name.to_lowercase()) == Some(choice.clone()))
+        .or_else(|| {
+            names.iter().position(|name| {
+                name.as_ref()
+                    .map_or(false, |name| name.to_lowercase().contains(&choice))
+            })
+        })
+}
+
+/// The monitor to go fullscreen on, the chosen one when it is connected and otherwise the one
+/// the window is on.
+pub fn fullscreen_monitor(window: &Window, choice: &str) -> Option<MonitorHandle> {
+    let monitors: Vec<MonitorHandle> = window.available_monitors().collect();
+    let names: Vec<Option<String>> = monitors.iter().map(|monitor| monitor.name()).collect();
+    match pick_monitor(choice, &names) {
+        Some(index) => Some(monitors[index].clone()),
+        None => {
+            if !choice.trim().is_empty() {
+                warn!(
+                    "There is no monitor {:?} to go fullscreen on, only {:?}",
+                    choice, names
+                );
+            }
+            window.current_monitor()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pick_monitor() {
+        let names = vec![
+            Some("DELL U2720Q".to_owned()),
+            None,
+            Some("Built-in Retina Display".to_owned()),
+        ];
+        assert_eq!(pick_monitor("", &names), None);
+        assert_eq!(pick_monitor("1", &names), Some(0));
+        assert_eq!(pick_monitor("3", &names), Some(2));
+        assert_eq!(pick_monitor("4", &names), None);
+        assert_eq!(pick_monitor("0", &names), None);
+        assert_eq!(pick_monitor("built-in retina display", &names), Some(2));
+        assert_eq!(pick_monitor("dell", &names), Some(0));
+        assert_eq!(pick_monitor("HDMI", &names), None);
+    }
+}
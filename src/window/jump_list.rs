@@ -0,0 +1,235 @@
+/// Fills the taskbar jump list with the recent files and a New Window task. Both start neovide
+/// again, with the file as its argument, so in single instance mode the file opens in the
+/// running window.
+#[cfg_attr(not(windows), allow(unused_variables))]
+pub fn update_jump_list(recent_files: Vec<String>) {
+    #[cfg(windows)]
+    windows::update(recent_files);
+}
+
+#[cfg(windows)]
+mod windows {
+    use std::{
+        ffi::c_void,
+        path::Path,
+        ptr::{null_mut, NonNull},
+        thread,
+    };
+
+    use log::warn;
+    use winapi::{
+        shared::{
+            guiddef::GUID,
+            minwindef::UINT,
+            winerror::{FAILED, HRESULT},
+            wtypes::{PROPERTYKEY, VT_LPWSTR},
+        },
+        um::{
+            combaseapi::{
+                CoCreateInstance, CoInitializeEx, CoTaskMemAlloc, CoTaskMemFree, CoUninitialize,
+                CLSCTX_INPROC_SERVER,
+            },
+            objbase::COINIT_APARTMENTTHREADED,
+            objectarray::{IObjectArray, IObjectCollection},
+            propidl::PROPVARIANT,
+            propsys::IPropertyStore,
+            shobjidl_core::{ICustomDestinationList, IShellLinkW},
+            unknwnbase::IUnknown,
+        },
+        Interface,
+    };
+
+    const CLSID_DESTINATION_LIST: GUID = GUID {
+        Data1: 0x77f1_0cf0,
+        Data2: 0x3db5,
+        Data3: 0x4966,
+        Data4: [0xb5, 0x20, 0xb7, 0xc5, 0x4f, 0xd3, 0x5e, 0xd6],
+    };
+    const CLSID_ENUMERABLE_OBJECT_COLLECTION: GUID = GUID {
+        Data1: 0x2d34_68c1,
+        Data2: 0x36a7,
+        Data3: 0x43b6,
+        Data4: [0xac, 0x24, 0xd3, 0xf0, 0x2f, 0xd9, 0x60, 0x7a],
+    };
+    const CLSID_SHELL_LINK: GUID = GUID {
+        Data1: 0x0002_1401,
+        Data2: 0x0000,
+        Data3: 0x0000,
+        Data4: [0xc0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x46],
+    };
+    // The text shown for a jump list item
+    const PKEY_TITLE: PROPERTYKEY = PROPERTYKEY {
+        fmtid: GUID {
+            Data1: 0xf29f_85e0,
+            Data2: 0x4ff9,
+            Data3: 0x1068,
+            Data4: [0xab, 0x91, 0x08, 0x00, 0x2b, 0x27, 0xb3, 0xd9],
+        },
+        pid: 2,
+    };
+    // Long enough for the arguments of any link neovide made
+    const ARGUMENTS_LENGTH: usize = 1024;
+
+    fn to_wide(text: &str) -> Vec<u16> {
+        text.encode_utf16().chain(Some(0)).collect()
+    }
+
+    fn check(result: HRESULT) -> Result<(), HRESULT> {
+        if FAILED(result) {
+            Err(result)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// A COM interface pointer, released when dropped.
+    struct ComPtr<T: Interface>(NonNull<T>);
+
+    impl<T: Interface> ComPtr<T> {
+        unsafe fn create(class: &GUID) -> Result<ComPtr<T>, HRESULT> {
+            let mut object: *mut c_void = null_mut();
+            check(CoCreateInstance(
+                class,
+                null_mut(),
+                CLSCTX_INPROC_SERVER,
+                &T::uuidof(),
+                &mut object,
+            ))?;
+            Ok(ComPtr(NonNull::new(object as *mut T).unwrap()))
+        }
+
+        unsafe fn cast<U: Interface>(&self) -> Result<ComPtr<U>, HRESULT> {
+            let mut object: *mut c_void = null_mut();
+            check(self.unknown().QueryInterface(&U::uuidof(), &mut object))?;
+            Ok(ComPtr(NonNull::new(object as *mut U).unwrap()))
+        }
+
+        fn as_ptr(&self) -> *mut T {
+            self.0.as_ptr()
+        }
+
+        unsafe fn unknown(&self) -> &IUnknown {
+            &*(self.as_ptr() as *mut IUnknown)
+        }
+    }
+
+    impl<T: Interface> std::ops::Deref for ComPtr<T> {
+        type Target = T;
+
+        fn deref(&self) -> &T {
+            unsafe { self.0.as_ref() }
+        }
+    }
+
+    impl<T: Interface> Drop for ComPtr<T> {
+        fn drop(&mut self) {
+            unsafe {
+                self.unknown().Release();
+            }
+        }
+    }
+
+    unsafe fn set_title(link: &ComPtr<IShellLinkW>, title: &str) -> Result<(), HRESULT> {
+        let properties = link.cast::<IPropertyStore>()?;
+        let title = to_wide(title);
+        // The property store copies the value, which then has to be freed like PropVariantClear
+        let buffer = CoTaskMemAlloc(title.len() * 2) as *mut u16;
+        buffer.copy_from_nonoverlapping(title.as_ptr(), title.len());
+        let mut value: PROPVARIANT = std::mem::zeroed();
+        value.vt = VT_LPWSTR as u16;
+        *value.data.pwszVal_mut() = buffer;
+        let result = check(properties.SetValue(&PKEY_TITLE, &value));
+        CoTaskMemFree(buffer as *mut c_void);
+        result?;
+        check(properties.Commit())
+    }
+
+    unsafe fn create_link(
+        executable: &str,
+        arguments: &str,
+        title: &str,
+        description: &str,
+    ) -> Result<ComPtr<IShellLinkW>, HRESULT> {
+        let link = ComPtr::<IShellLinkW>::create(&CLSID_SHELL_LINK)?;
+        let executable = to_wide(executable);
+        check(link.SetPath(executable.as_ptr()))?;
+        check(link.SetIconLocation(executable.as_ptr(), 0))?;
+        check(link.SetArguments(to_wide(arguments).as_ptr()))?;
+        check(link.SetDescription(to_wide(description).as_ptr()))?;
+        set_title(&link, title)?;
+        Ok(link)
+    }
+
+    // The items the user removed from the jump list must not be added back, or the whole
+    // category is refused
+    unsafe fn removed_arguments(removed: &ComPtr<IObjectArray>) -> Vec<String> {
+        let mut count: UINT = 0;
+        removed.GetCount(&mut count);
+        (0..count)
+            .filter_map(|index| {
+                let mut object: *mut c_void = null_mut();
+                if FAILED(removed.GetAt(index, &IShellLinkW::uuidof(), &mut object)) {
+                    return None;
+                }
+                let link = ComPtr(NonNull::new(object as *mut IShellLinkW)?);
+                let mut arguments = vec![0u16; ARGUMENTS_LENGTH];
+                link.GetArguments(arguments.as_mut_ptr(), ARGUMENTS_LENGTH as i32);
+                let length = arguments.iter().position(|c| *c == 0).unwrap_or(0);
+                Some(String::from_utf16_lossy(&arguments[..length]))
+            })
+            .collect()
+    }
+
+    unsafe fn build_jump_list(recent_files: &[String]) -> Result<(), HRESULT> {
+        let executable = std::env::current_exe()
+            .map(|path| path.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let list = ComPtr::<ICustomDestinationList>::create(&CLSID_DESTINATION_LIST)?;
+
+        let mut slots: UINT = 0;
+        let mut removed: *mut c_void = null_mut();
+        check(list.BeginList(&mut slots, &IObjectArray::uuidof(), &mut removed))?;
+        let removed = match NonNull::new(removed as *mut IObjectArray) {
+            Some(removed) => removed_arguments(&ComPtr(removed)),
+            None => Vec::new(),
+        };
+
+        let files = ComPtr::<IObjectCollection>::create(&CLSID_ENUMERABLE_OBJECT_COLLECTION)?;
+        for path in recent_files.iter().take(slots as usize) {
+            let arguments = format!("\"{}\"", path);
+            if removed.contains(&arguments) {
+                continue;
+            }
+            let name = Path::new(path)
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_else(|| path.clone());
+            let link = create_link(&executable, &arguments, &name, path)?;
+            check(files.AddObject(link.as_ptr() as *mut IUnknown))?;
+        }
+        let files = files.cast::<IObjectArray>()?;
+        check(list.AppendCategory(to_wide("Recent").as_ptr(), files.as_ptr()))?;
+
+        let tasks = ComPtr::<IObjectCollection>::create(&CLSID_ENUMERABLE_OBJECT_COLLECTION)?;
+        let new_window = create_link(&executable, "", "New Window", "Open a new Neovide window")?;
+        check(tasks.AddObject(new_window.as_ptr() as *mut IUnknown))?;
+        let tasks = tasks.cast::<IObjectArray>()?;
+        check(list.AddUserTasks(tasks.as_ptr()))?;
+
+        check(list.CommitList())
+    }
+
+    // The jump list is built on its own thread, since COM objects need an apartment of their
+    // own and the shell can take a while to answer
+    pub fn update(recent_files: Vec<String>) {
+        thread::spawn(move || unsafe {
+            if FAILED(CoInitializeEx(null_mut(), COINIT_APARTMENTTHREADED)) {
+                return;
+            }
+            if let Err(error) = build_jump_list(&recent_files) {
+                warn!("Could not update the jump list: {:#x}", error);
+            }
+            CoUninitialize();
+        });
+    }
+}
@@ -1,5 +1,7 @@
-use glutin::event::{ElementState, Event, KeyEvent, WindowEvent};
-use glutin::keyboard::Key;
+use std::time::Instant;
+
+use glutin::event::{ElementState, Event, KeyEvent, ModifiersState, WindowEvent};
+use glutin::keyboard::{Key, KeyCode, KeyLocation};
 
 use glutin::platform::modifier_supplement::KeyEventExtModifierSupplement;
 
@@ -10,24 +12,19 @@ use crate::window::KeyboardSettings;
 
 pub struct KeyboardManager {
     command_sender: LoggingTx<UiCommand>,
-    shift: bool,
-    ctrl: bool,
-    alt: bool,
-    logo: bool,
+    modifiers: Modifiers,
     ignore_input_this_frame: bool,
-    queued_key_events: Vec<KeyEvent>,
+    /// When the first key not yet picked up for the latency measurement was sent.
+    pub key_sent_at: Option<Instant>,
 }
 
 impl KeyboardManager {
     pub fn new(command_sender: LoggingTx<UiCommand>) -> KeyboardManager {
         KeyboardManager {
             command_sender,
-            shift: false,
-            ctrl: false,
-            alt: false,
-            logo: false,
+            modifiers: Modifiers::default(),
             ignore_input_this_frame: false,
-            queued_key_events: Vec::new(),
+            key_sent_at: None,
         }
     }
 
@@ -48,9 +45,19 @@ impl KeyboardManager {
                     },
                 ..
             } => {
-                // Store the event so that we can ignore it properly if the window was just
-                // focused.
-                self.queued_key_events.push(key_event.clone());
+                // Keys are sent right away instead of once the events are cleared, so typing
+                // doesn't have to wait on whatever else the event loop is doing. Keys arriving
+                // together with a focus change are still dropped.
+                let settings = SETTINGS.get::<KeyboardSettings>();
+                if key_event.state == ElementState::Pressed && !self.should_ignore_input(&settings)
+                {
+                    if let Some(keybinding) = self.maybe_get_keybinding(key_event) {
+                        self.command_sender
+                            .send(SerialCommand::Keyboard(keybinding).into())
+                            .expect("Could not send keyboard ui command");
+                        self.key_sent_at.get_or_insert_with(Instant::now);
+                    }
+                }
             }
             Event::WindowEvent {
                 event: WindowEvent::ModifiersChanged(modifiers),
@@ -58,103 +65,205 @@ impl KeyboardManager {
             } => {
                 // Record the modifer states so that we can properly add them to the keybinding
                 // text
-                self.shift = modifiers.shift_key();
-                self.ctrl = modifiers.control_key();
-                self.alt = modifiers.alt_key();
-                self.logo = modifiers.super_key();
+                self.modifiers = Modifiers::from_state(*modifiers);
             }
             Event::MainEventsCleared => {
-                // And the window wasn't just focused.
-                let settings = SETTINGS.get::<KeyboardSettings>();
-
-                if !self.should_ignore_input(&settings) {
-                    // If we have a keyboard event this frame
-                    for key_event in self.queued_key_events.iter() {
-                        // And a key was pressed
-                        if key_event.state == ElementState::Pressed {
-                            if let Some(keybinding) = self.maybe_get_keybinding(key_event) {
-                                self.command_sender
-                                    .send(SerialCommand::Keyboard(keybinding).into())
-                                    .expect("Could not send keyboard ui command");
-                            }
-                        }
-                    }
-                }
-
-                // Regardless of whether this was a valid keyboard input or not, rest ignoring and
-                // whatever event was queued.
+                // The focus change has been dealt with, so the following keys are typed again
                 self.ignore_input_this_frame = false;
-                self.queued_key_events.clear();
             }
             _ => {}
         }
     }
 
+    /// Whether the modifier which opens links on click is held, cmd on macos and ctrl elsewhere.
+    pub fn link_modifier_pressed(&self) -> bool {
+        if cfg!(target_os = "macos") {
+            self.modifiers.logo
+        } else {
+            self.modifiers.ctrl
+        }
+    }
+
     fn should_ignore_input(&self, settings: &KeyboardSettings) -> bool {
-        self.ignore_input_this_frame || (self.logo && !settings.use_logo)
+        self.ignore_input_this_frame || (self.modifiers.logo && !settings.use_logo)
     }
 
     fn maybe_get_keybinding(&self, key_event: &KeyEvent) -> Option<String> {
+        // Keypad keys have their own names so that they can be mapped separately
+        if key_event.location == KeyLocation::Numpad {
+            if let Some(key_text) = keypad_key(key_event.logical_key) {
+                return Some(self.format_keybinding_string(true, true, key_text));
+            }
+        }
+
         // Determine if this key event represents a key which won't ever
         // present text.
-
         if let Some(key_text) = is_control_key(key_event.logical_key) {
-            Some(self.format_keybinding_string(true, true, key_text))
+            return Some(self.format_keybinding_string(true, true, key_text));
+        }
+
+        // Windows reports AltGr as Ctrl+Alt. The character it composed is sent on its own, since
+        // neovim would otherwise see <C-M-q> instead of @ on a german layout.
+        if cfg!(windows)
+            && self.modifiers.ctrl
+            && self.modifiers.alt
+            && is_printable(key_event.text)
+        {
+            let key_text = key_event.text.unwrap();
+            return Some(match is_special(key_text) {
+                Some(escaped_text) => format!("<{}>", escaped_text),
+                None => key_text.to_owned(),
+            });
+        }
+
+        // The os turns modified keys into control characters or other symbols, so they are sent
+        // as the key that was pressed with all modifiers spelled out, like <C-S-p>
+        if self.sent_modifiers(true).is_chord() {
+            if let Some((key_text, use_shift)) = self.modified_key_text(key_event) {
+                return Some(self.format_keybinding_string(true, use_shift, key_text));
+            }
+        }
+
+        let is_dead_key = key_event.text_with_all_modifiers().is_some() && key_event.text.is_none();
+        let key_text = if (self.modifiers.alt || is_dead_key) && cfg!(target_os = "macos") {
+            key_event.text_with_all_modifiers()
         } else {
-            let is_dead_key =
-                key_event.text_with_all_modifiers().is_some() && key_event.text.is_none();
-            let key_text = if (self.alt || is_dead_key) && cfg!(target_os = "macos") {
-                key_event.text_with_all_modifiers()
+            key_event.text
+        };
+
+        if let Some(key_text) = key_text {
+            // This is not a control key, so we rely upon winit to determine if
+            // this is a deadkey or not.
+            let keybinding_string = if let Some(escaped_text) = is_special(key_text) {
+                self.format_keybinding_string(true, false, escaped_text)
             } else {
-                key_event.text
+                self.format_keybinding_string(false, false, key_text)
             };
 
-            if let Some(key_text) = key_text {
-                // This is not a control key, so we rely upon winit to determine if
-                // this is a deadkey or not.
-                let keybinding_string = if let Some(escaped_text) = is_special(key_text) {
-                    self.format_keybinding_string(true, false, escaped_text)
-                } else {
-                    self.format_keybinding_string(false, false, key_text)
-                };
+            Some(keybinding_string)
+        } else {
+            None
+        }
+    }
 
-                Some(keybinding_string)
-            } else {
-                None
+    // The key text for a shortcut, and whether shift is spelled out. Letters keep it as a
+    // modifier, like <C-S-p>, while other keys are sent as the character shift makes of them,
+    // like <C-?>, which is how neovim writes those. Without that <C-/> and <C-?> would both be
+    // sent as <C-S-/>.
+    fn modified_key_text(&self, key_event: &KeyEvent) -> Option<(&'static str, bool)> {
+        let key_text = match key_event.key_without_modifiers() {
+            Key::Character(key_text) => key_text,
+            // Some platforms can't tell which character a key makes together with ctrl, like
+            // ctrl+/ on windows, which would otherwise only send the control character it makes
+            _ => qwerty_key_text(key_event.physical_key)?,
+        };
+        let key_text = self
+            .physical_key_text(key_event, key_text)
+            .unwrap_or(key_text);
+
+        if self.modifiers.shift && !shift_is_modifier(key_text) {
+            if let Key::Character(shifted_text) = key_event.logical_key {
+                if shifted_text != key_text && is_printable(Some(shifted_text)) {
+                    return Some((is_special(shifted_text).unwrap_or(shifted_text), false));
+                }
             }
         }
+        Some((is_special(key_text).unwrap_or(key_text), true))
+    }
+
+    // Mappings are written for latin letters, so on layouts like russian or greek the shortcut
+    // can be picked by the physical key instead. Latin layouts keep their own letters.
+    fn physical_key_text(&self, key_event: &KeyEvent, key_text: &str) -> Option<&'static str> {
+        if key_text.is_ascii() || !SETTINGS.get::<KeyboardSettings>().physical_shortcuts {
+            return None;
+        }
+        qwerty_key_text(key_event.physical_key)
     }
 
     fn format_keybinding_string(&self, special: bool, use_shift: bool, text: &str) -> String {
-        let special = special || self.ctrl || use_alt(self.alt) || self.logo;
+        let modifiers = self.sent_modifiers(use_shift);
+        let special = special || modifiers.is_chord();
 
         let open = or_empty(special, "<");
-        let modifiers = self.format_modifier_string(use_shift);
         let close = or_empty(special, ">");
 
-        open.to_owned() + &modifiers + text + close
+        open.to_owned() + &modifiers.prefix() + text + close
     }
 
     pub fn format_modifier_string(&self, use_shift: bool) -> String {
-        let shift = or_empty(self.shift && use_shift, "S-");
+        self.sent_modifiers(use_shift).prefix()
+    }
+
+    // The modifiers neovim is told about. The option or alt key is used on macos for character
+    // set changes and does not operate the same as on other systems, unless it is set to work as
+    // meta.
+    fn sent_modifiers(&self, use_shift: bool) -> Modifiers {
+        let alt_is_meta =
+            cfg!(not(target_os = "macos")) || SETTINGS.get::<KeyboardSettings>().macos_alt_is_meta;
+        Modifiers {
+            shift: self.modifiers.shift && use_shift,
+            alt: self.modifiers.alt && alt_is_meta,
+            ..self.modifiers
+        }
+    }
+}
+
+/// The modifiers of a keybinding, in the order neovim spells them out. Keys are sent to neovim
+/// and the menu shortcuts are read with the same translation, which writes the command or
+/// windows key as D-.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Modifiers {
+    pub shift: bool,
+    pub ctrl: bool,
+    pub alt: bool,
+    pub logo: bool,
+}
+
+impl Modifiers {
+    fn from_state(state: ModifiersState) -> Modifiers {
+        Modifiers {
+            shift: state.shift_key(),
+            ctrl: state.control_key(),
+            alt: state.alt_key(),
+            logo: state.super_key(),
+        }
+    }
+
+    // Whether the key has to be spelled out with its modifiers, shift alone only changes the
+    // character
+    fn is_chord(self) -> bool {
+        self.ctrl || self.alt || self.logo
+    }
+
+    fn prefix(self) -> String {
+        let shift = or_empty(self.shift, "S-");
         let ctrl = or_empty(self.ctrl, "C-");
-        let alt = or_empty(use_alt(self.alt), "M-");
+        let alt = or_empty(self.alt, "M-");
         let logo = or_empty(self.logo, "D-");
 
         shift.to_owned() + ctrl + alt + logo
     }
 }
 
-#[cfg(not(target_os = "macos"))]
-fn use_alt(alt: bool) -> bool {
-    alt
-}
-
-// The option or alt key is used on Macos for character set changes
-// and does not operate the same as other systems.
-#[cfg(target_os = "macos")]
-fn use_alt(_: bool) -> bool {
-    false
+/// Splits a keybinding written like the ones sent to neovim, such as <S-D-z>, into its modifiers
+/// and key, the reverse of how keys are sent.
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+pub fn parse_keybinding(keybinding: &str) -> Option<(Modifiers, &str)> {
+    let mut rest = keybinding.strip_prefix('<')?.strip_suffix('>')?;
+    let mut modifiers = Modifiers::default();
+    // A key like - can follow the modifiers as well, as in <D-->
+    while rest.len() > 2 {
+        let flag = match rest.get(..2) {
+            Some("S-") => &mut modifiers.shift,
+            Some("C-") => &mut modifiers.ctrl,
+            Some("M-") => &mut modifiers.alt,
+            Some("D-") => &mut modifiers.logo,
+            _ => break,
+        };
+        *flag = true;
+        rest = &rest[2..];
+    }
+    Some((modifiers, rest))
 }
 
 fn or_empty(condition: bool, text: &str) -> &str {
@@ -192,10 +301,117 @@ fn is_control_key(key: Key<'static>) -> Option<&str> {
         Key::PageUp => Some("PageUp"),
         Key::PageDown => Some("PageDown"),
         Key::Tab => Some("Tab"),
+        Key::Enter => Some("CR"),
+        Key::Space => Some("Space"),
         _ => None,
     }
 }
 
+fn keypad_key(key: Key<'static>) -> Option<&'static str> {
+    match key {
+        Key::Enter => Some("kEnter"),
+        Key::Character(text) => match text {
+            "0" => Some("k0"),
+            "1" => Some("k1"),
+            "2" => Some("k2"),
+            "3" => Some("k3"),
+            "4" => Some("k4"),
+            "5" => Some("k5"),
+            "6" => Some("k6"),
+            "7" => Some("k7"),
+            "8" => Some("k8"),
+            "9" => Some("k9"),
+            "+" => Some("kPlus"),
+            "-" => Some("kMinus"),
+            "*" => Some("kMultiply"),
+            "/" => Some("kDivide"),
+            "." => Some("kPoint"),
+            "," => Some("kComma"),
+            "=" => Some("kEqual"),
+            _ => None,
+        },
+        // With num lock off the keypad works as arrows and the like
+        Key::ArrowUp => Some("kUp"),
+        Key::ArrowDown => Some("kDown"),
+        Key::ArrowLeft => Some("kLeft"),
+        Key::ArrowRight => Some("kRight"),
+        Key::Home => Some("kHome"),
+        Key::End => Some("kEnd"),
+        Key::PageUp => Some("kPageUp"),
+        Key::PageDown => Some("kPageDown"),
+        Key::Insert => Some("kInsert"),
+        Key::Delete => Some("kDel"),
+        _ => None,
+    }
+}
+
+// The character the key produces on a us qwerty layout
+fn qwerty_key_text(key_code: KeyCode) -> Option<&'static str> {
+    let key_text = match key_code {
+        KeyCode::KeyA => "a",
+        KeyCode::KeyB => "b",
+        KeyCode::KeyC => "c",
+        KeyCode::KeyD => "d",
+        KeyCode::KeyE => "e",
+        KeyCode::KeyF => "f",
+        KeyCode::KeyG => "g",
+        KeyCode::KeyH => "h",
+        KeyCode::KeyI => "i",
+        KeyCode::KeyJ => "j",
+        KeyCode::KeyK => "k",
+        KeyCode::KeyL => "l",
+        KeyCode::KeyM => "m",
+        KeyCode::KeyN => "n",
+        KeyCode::KeyO => "o",
+        KeyCode::KeyP => "p",
+        KeyCode::KeyQ => "q",
+        KeyCode::KeyR => "r",
+        KeyCode::KeyS => "s",
+        KeyCode::KeyT => "t",
+        KeyCode::KeyU => "u",
+        KeyCode::KeyV => "v",
+        KeyCode::KeyW => "w",
+        KeyCode::KeyX => "x",
+        KeyCode::KeyY => "y",
+        KeyCode::KeyZ => "z",
+        KeyCode::Digit0 => "0",
+        KeyCode::Digit1 => "1",
+        KeyCode::Digit2 => "2",
+        KeyCode::Digit3 => "3",
+        KeyCode::Digit4 => "4",
+        KeyCode::Digit5 => "5",
+        KeyCode::Digit6 => "6",
+        KeyCode::Digit7 => "7",
+        KeyCode::Digit8 => "8",
+        KeyCode::Digit9 => "9",
+        KeyCode::Minus => "-",
+        KeyCode::Equal => "=",
+        KeyCode::BracketLeft => "[",
+        KeyCode::BracketRight => "]",
+        KeyCode::Backslash => "\\",
+        KeyCode::Semicolon => ";",
+        KeyCode::Quote => "'",
+        KeyCode::Backquote => "`",
+        KeyCode::Comma => ",",
+        KeyCode::Period => ".",
+        KeyCode::Slash => "/",
+        _ => return None,
+    };
+    Some(key_text)
+}
+
+// Shifted letters are spelled as <S-a> in neovim, other keys as the character they make with shift
+fn shift_is_modifier(key_text: &str) -> bool {
+    key_text.chars().all(char::is_alphabetic) || key_text == " "
+}
+
+fn is_printable(text: Option<&str>) -> bool {
+    match text {
+        Some(text) => !text.is_empty() && !text.chars().any(char::is_control),
+        None => false,
+    }
+}
+
 fn is_special(text: &str) -> Option<&str> {
     match text {
         " " => Some("Space"),
@@ -204,6 +420,87 @@ fn is_special(text: &str) -> Option<&str> {
         "|" => Some("Bar"),
         "\t" => Some("Tab"),
         "\n" => Some("CR"),
+        "\r" => Some("CR"),
         _ => None,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_special_characters_are_escaped() {
+        assert_eq!(is_special("<"), Some("lt"));
+        assert_eq!(is_special("\r"), Some("CR"));
+        assert_eq!(is_special("a"), None);
+    }
+
+    #[test]
+    fn test_control_characters_are_not_printable() {
+        assert!(is_printable(Some("@")));
+        assert!(!is_printable(Some("\u{11}")));
+        assert!(!is_printable(None));
+    }
+
+    #[test]
+    fn test_shift_is_modifier_for_letters() {
+        assert!(shift_is_modifier("p"));
+        assert!(shift_is_modifier(" "));
+        assert!(!shift_is_modifier("/"));
+        assert!(!shift_is_modifier("1"));
+    }
+
+    #[test]
+    fn test_qwerty_key_text() {
+        assert_eq!(qwerty_key_text(KeyCode::KeyQ), Some("q"));
+        assert_eq!(qwerty_key_text(KeyCode::BracketLeft), Some("["));
+        assert_eq!(qwerty_key_text(KeyCode::Enter), None);
+    }
+
+    #[test]
+    fn test_parse_keybinding() {
+        let command_shift = Modifiers {
+            shift: true,
+            logo: true,
+            ..Default::default()
+        };
+        assert_eq!(parse_keybinding("<S-D-z>"), Some((command_shift, "z")));
+        assert_eq!(
+            parse_keybinding("<D-->"),
+            Some((
+                Modifiers {
+                    logo: true,
+                    ..Default::default()
+                },
+                "-"
+            ))
+        );
+        assert_eq!(
+            parse_keybinding("<Esc>"),
+            Some((Modifiers::default(), "Esc"))
+        );
+        assert_eq!(parse_keybinding("z"), None);
+    }
+
+    #[test]
+    fn test_modifiers_round_trip() {
+        let modifiers = Modifiers {
+            shift: true,
+            ctrl: true,
+            alt: true,
+            logo: true,
+        };
+        let keybinding = format!("<{}x>", modifiers.prefix());
+        assert_eq!(keybinding, "<S-C-M-D-x>");
+        assert_eq!(parse_keybinding(&keybinding), Some((modifiers, "x")));
+    }
+
+    #[test]
+    fn test_keypad_keys() {
+        assert_eq!(keypad_key(Key::Character("5")), Some("k5"));
+        assert_eq!(keypad_key(Key::Character("+")), Some("kPlus"));
+        assert_eq!(keypad_key(Key::Enter), Some("kEnter"));
+        assert_eq!(keypad_key(Key::Character("a")), None);
+    }
+}
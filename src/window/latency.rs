@@ -0,0 +1,124 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use log::info;
+
+// Enough for stable percentiles, while still following a change in the setup quickly
+const SAMPLE_COUNT: usize = 200;
+// Keys that didn't change anything on the screen, like a lone escape in normal mode, would
+// otherwise be matched with whatever is drawn next
+const MAX_LATENCY: Duration = Duration::from_secs(1);
+
+fn percentile(sorted: &[Duration], fraction: f32) -> Duration {
+    let index = ((sorted.len() - 1) as f32 * fraction).round() as usize;
+    sorted[index]
+}
+
+/// Measures how long it takes from a key being sent to neovim until a frame with what neovim
+/// drew in response is presented, with `g:neovide_latency_measurement`. The percentiles are
+/// shown in the profiler overlay and logged every few hundred keys.
+pub struct LatencyTracker {
+    pending_keys: Vec<Instant>,
+    samples: VecDeque<Duration>,
+    samples_since_log: usize,
+}
+
+impl LatencyTracker {
+    pub fn new() -> LatencyTracker {
+        LatencyTracker {
+            pending_keys: Vec::new(),
+            samples: VecDeque::with_capacity(SAMPLE_COUNT),
+            samples_since_log: 0,
+        }
+    }
+
+    pub fn key_sent(&mut self, sent_at: Instant) {
+        self.pending_keys.push(sent_at);
+    }
+
+    /// Counts the keys sent before neovim flushed what was just presented.
+    pub fn frame_presented(&mut self, last_flush: Option<Instant>, presented_at: Instant) {
+        let last_flush = match last_flush {
+            Some(last_flush) => last_flush,
+            None => return,
+        };
+        let samples = &mut self.samples;
+        let mut answered = 0;
+        self.pending_keys.retain(|sent_at| {
+            let latency = presented_at.saturating_duration_since(*sent_at);
+            if latency > MAX_LATENCY {
+                return false;
+            }
+            if *sent_at > last_flush {
+                return true;
+            }
+            if samples.len() == SAMPLE_COUNT {
+                samples.pop_front();
+            }
+            samples.push_back(latency);
+            answered += 1;
+            false
+        });
+
+        self.samples_since_log += answered;
+        if self.samples_since_log >= SAMPLE_COUNT {
+            self.samples_since_log = 0;
+            if let Some((p50, p95)) = self.percentiles() {
+                info!(
+                    "Input latency over the last {} keys: p50 {:.1}ms, p95 {:.1}ms",
+                    self.samples.len(),
+                    p50,
+                    p95
+                );
+            }
+        }
+    }
+
+    /// The median and 95th percentile latency in milliseconds.
+    pub fn percentiles(&self) -> Option<(f32, f32)> {
+        if self.samples.is_empty() {
+            return None;
+        }
+        let mut sorted: Vec<Duration> = self.samples.iter().copied().collect();
+        sorted.sort();
+        let milliseconds = |duration: Duration| duration.as_secs_f32() * 1000.0;
+        Some((
+            milliseconds(percentile(&sorted, 0.5)),
+            milliseconds(percentile(&sorted, 0.95)),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keys_wait_for_their_flush() {
+        let start = Instant::now();
+        let at = |milliseconds: u64| start + Duration::from_millis(milliseconds);
+        let mut tracker = LatencyTracker::new();
+
+        tracker.key_sent(at(0));
+        tracker.key_sent(at(10));
+        // Neovim flushed before the second key reached it
+        tracker.frame_presented(Some(at(5)), at(8));
+        assert_eq!(tracker.samples, vec![Duration::from_millis(8)]);
+        tracker.frame_presented(Some(at(20)), at(26));
+        assert_eq!(tracker.pending_keys, vec![]);
+
+        // The second key waited 16ms, which is the upper of the two samples
+        let (p50, p95) = tracker.percentiles().unwrap();
+        assert!((p50 - 16.0).abs() < 0.01);
+        assert!((p95 - 16.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_unanswered_keys_are_dropped() {
+        let start = Instant::now();
+        let mut tracker = LatencyTracker::new();
+        tracker.key_sent(start);
+        tracker.frame_presented(Some(start), start + Duration::from_secs(2));
+        assert!(tracker.percentiles().is_none());
+    }
+}
@@ -0,0 +1,252 @@
+use cocoa::{
+    appkit::{NSApp, NSEventModifierFlags},
+    base::{id, nil, selector},
+    foundation::NSString,
+};
+use objc::{
+    class,
+    declare::ClassDecl,
+    msg_send,
+    runtime::{Object, Sel},
+    sel, sel_impl,
+};
+use parking_lot::Mutex;
+
+use super::keyboard_manager::{parse_keybinding, Modifiers};
+use super::menu_items::MenuAction;
+use super::tabbing::cycle_windows;
+use crate::redraw_scheduler::REDRAW_SCHEDULER;
+
+lazy_static! {
+    static ref MENU_ACTIONS: Mutex<Vec<MenuAction>> = Mutex::new(Vec::new());
+}
+
+enum MenuBarItem {
+    Action(MenuAction),
+    // Handled by AppKit itself, like minimize or hide
+    Standard {
+        title: &'static str,
+        selector: &'static str,
+        shortcut: Option<&'static str>,
+    },
+    CycleWindows,
+    Separator,
+}
+
+// AppKit lists the open windows in this menu
+const WINDOW_MENU: &str = "Window";
+
+const MENU_BAR: [(&str, &[MenuBarItem]); 4] = [
+    (
+        "Neovide",
+        &[
+            MenuBarItem::Standard {
+                title: "Hide Neovide",
+                selector: "hide:",
+                shortcut: Some("<D-h>"),
+            },
+            MenuBarItem::Standard {
+                title: "Hide Others",
+                selector: "hideOtherApplications:",
+                shortcut: Some("<M-D-h>"),
+            },
+            MenuBarItem::Standard {
+                title: "Show All",
+                selector: "unhideAllApplications:",
+                shortcut: None,
+            },
+            MenuBarItem::Separator,
+            MenuBarItem::Action(MenuAction::Quit),
+        ],
+    ),
+    (
+        "File",
+        &[
+            MenuBarItem::Action(MenuAction::NewWindow),
+            MenuBarItem::Action(MenuAction::Save),
+            MenuBarItem::Separator,
+            MenuBarItem::Action(MenuAction::CloseWindow),
+        ],
+    ),
+    (
+        "Edit",
+        &[
+            MenuBarItem::Action(MenuAction::Undo),
+            MenuBarItem::Action(MenuAction::Redo),
+            MenuBarItem::Separator,
+            MenuBarItem::Action(MenuAction::Cut),
+            MenuBarItem::Action(MenuAction::Copy),
+            MenuBarItem::Action(MenuAction::Paste),
+            MenuBarItem::Action(MenuAction::SelectAll),
+        ],
+    ),
+    (
+        WINDOW_MENU,
+        &[
+            MenuBarItem::Standard {
+                title: "Minimize",
+                selector: "performMiniaturize:",
+                shortcut: Some("<D-m>"),
+            },
+            MenuBarItem::Standard {
+                title: "Zoom",
+                selector: "performZoom:",
+                shortcut: None,
+            },
+            MenuBarItem::Action(MenuAction::ToggleFullscreen),
+            MenuBarItem::CycleWindows,
+            MenuBarItem::Separator,
+            MenuBarItem::Standard {
+                title: "Bring All to Front",
+                selector: "arrangeInFront:",
+                shortcut: None,
+            },
+        ],
+    ),
+];
+
+/// Takes the actions picked from the menu since the last call.
+pub fn take_menu_actions() -> Vec<MenuAction> {
+    MENU_ACTIONS.lock().drain(..).collect()
+}
+
+pub fn push_menu_action(action: MenuAction) {
+    MENU_ACTIONS.lock().push(action);
+    // Menu clicks don't produce window events, so the event loop has to be woken up
+    REDRAW_SCHEDULER.queue_flushed_frame();
+}
+
+extern "C" fn handle_menu_item(_this: &Object, _selector: Sel, sender: id) {
+    let tag: i64 = unsafe { msg_send![sender, tag] };
+    if let Some(action) = MenuAction::from_tag(tag as usize) {
+        push_menu_action(action);
+    }
+}
+
+fn menu_handler() -> id {
+    let mut decl = ClassDecl::new("NeovideMenuHandler", class!(NSObject))
+        .expect("Could not declare menu handler class");
+    unsafe {
+        decl.add_method(
+            sel!(handleMenuItem:),
+            handle_menu_item as extern "C" fn(&Object, Sel, id),
+        );
+        decl.add_method(
+            sel!(cycleWindows:),
+            cycle_windows as extern "C" fn(&Object, Sel, id),
+        );
+        let class = decl.register();
+        msg_send![class, new]
+    }
+}
+
+fn key_equivalent_mask(modifiers: Modifiers) -> NSEventModifierFlags {
+    let mut mask = NSEventModifierFlags::empty();
+    if modifiers.shift {
+        mask |= NSEventModifierFlags::NSShiftKeyMask;
+    }
+    if modifiers.ctrl {
+        mask |= NSEventModifierFlags::NSControlKeyMask;
+    }
+    if modifiers.alt {
+        mask |= NSEventModifierFlags::NSAlternateKeyMask;
+    }
+    if modifiers.logo {
+        mask |= NSEventModifierFlags::NSCommandKeyMask;
+    }
+    mask
+}
+
+unsafe fn add_menu(menu_bar: id, title: &str) -> id {
+    let title: id = msg_send![NSString::alloc(nil).init_str(title), autorelease];
+    let menu: id = msg_send![class!(NSMenu), alloc];
+    let menu: id = msg_send![menu, initWithTitle: title];
+    let menu_bar_item: id = msg_send![class!(NSMenuItem), new];
+    let _: () = msg_send![menu_bar_item, setSubmenu: menu];
+    let _: () = msg_send![menu_bar, addItem: menu_bar_item];
+    // The menu bar holds on to both from here on
+    let _: () = msg_send![menu, release];
+    let _: () = msg_send![menu_bar_item, release];
+    menu
+}
+
+/// Adds an item with its key equivalent written like the keys sent to neovim, such as <D-s>.
+pub unsafe fn add_item(menu: id, title: &str, action: Sel, shortcut: Option<&str>) -> id {
+    let (modifiers, key) = shortcut.and_then(parse_keybinding).unwrap_or_default();
+    let title: id = msg_send![NSString::alloc(nil).init_str(title), autorelease];
+    let key: id = msg_send![NSString::alloc(nil).init_str(key), autorelease];
+    let item: id = msg_send![class!(NSMenuItem), alloc];
+    let item: id = msg_send![item, initWithTitle: title action: action keyEquivalent: key];
+    let _: () = msg_send![item, setKeyEquivalentModifierMask: key_equivalent_mask(modifiers)];
+    let _: () = msg_send![menu, addItem: item];
+    let _: () = msg_send![item, release];
+    item
+}
+
+/// Adds the item of an action, which is sent to `target` with the action as its tag.
+pub unsafe fn add_action_item(
+    menu: id,
+    target: id,
+    action: MenuAction,
+    selector: Sel,
+    shortcut: Option<&str>,
+) {
+    let item = add_item(menu, action.title(), selector, shortcut);
+    let _: () = msg_send![item, setTag: action.tag() as i64];
+    let _: () = msg_send![item, setTarget: target];
+}
+
+pub unsafe fn add_separator(menu: id) {
+    let separator: id = msg_send![class!(NSMenuItem), separatorItem];
+    let _: () = msg_send![menu, addItem: separator];
+}
+
+/// Replaces the default menu winit installs with the standard App, File, Edit and Window menus.
+/// Has to be called once the application finished launching, since winit sets its menu then.
+pub fn install_menu() {
+    unsafe {
+        let handler = menu_handler();
+        let app = NSApp();
+        let menu_bar: id = msg_send![class!(NSMenu), new];
+        let mut window_menu = nil;
+
+        for (title, items) in MENU_BAR.iter() {
+            let menu = add_menu(menu_bar, title);
+            for item in items.iter() {
+                match item {
+                    MenuBarItem::Action(action) => add_action_item(
+                        menu,
+                        handler,
+                        *action,
+                        sel!(handleMenuItem:),
+                        action.shortcut(),
+                    ),
+                    MenuBarItem::Standard {
+                        title,
+                        selector: name,
+                        shortcut,
+                    } => {
+                        add_item(menu, title, selector(name), *shortcut);
+                    }
+                    MenuBarItem::CycleWindows => {
+                        let item = add_item(
+                            menu,
+                            "Cycle Through Windows",
+                            sel!(cycleWindows:),
+                            Some("<D-`>"),
+                        );
+                        let _: () = msg_send![item, setTarget: handler];
+                    }
+                    MenuBarItem::Separator => add_separator(menu),
+                }
+            }
+            if *title == WINDOW_MENU {
+                window_menu = menu;
+            }
+        }
+
+        let _: () = msg_send![app, setMainMenu: menu_bar];
+        let _: () = msg_send![app, setWindowsMenu: window_menu];
+        let _: () = msg_send![menu_bar, release];
+    }
+}
@@ -0,0 +1,181 @@
+use crate::bridge::ParallelCommand;
+
+/// What the items of neovide's menus do. The menu bar, the Dock menu, the tray and the context
+/// menu all pick their items from the same table, so an action has the same title and shortcut
+/// everywhere it is offered.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MenuAction {
+    Quit,
+    NewWindow,
+    NewTab,
+    Save,
+    CloseWindow,
+    Undo,
+    Redo,
+    Cut,
+    Copy,
+    Paste,
+    SelectAll,
+    ToggleFullscreen,
+    ToggleWindow,
+}
+
+pub struct MenuItem {
+    pub action: MenuAction,
+    pub title: &'static str,
+    /// The key equivalent shown in the menu bar, written like the keys sent to neovim
+    pub shortcut: Option<&'static str>,
+}
+
+const MENU_ITEMS: [MenuItem; 13] = [
+    MenuItem {
+        action: MenuAction::Quit,
+        title: "Quit Neovide",
+        shortcut: Some("<D-q>"),
+    },
+    MenuItem {
+        action: MenuAction::NewWindow,
+        title: "New Window",
+        shortcut: Some("<D-n>"),
+    },
+    MenuItem {
+        action: MenuAction::NewTab,
+        title: "New Tab",
+        shortcut: None,
+    },
+    MenuItem {
+        action: MenuAction::Save,
+        title: "Save",
+        shortcut: Some("<D-s>"),
+    },
+    MenuItem {
+        action: MenuAction::CloseWindow,
+        title: "Close Window",
+        shortcut: Some("<D-w>"),
+    },
+    MenuItem {
+        action: MenuAction::Undo,
+        title: "Undo",
+        shortcut: Some("<D-z>"),
+    },
+    MenuItem {
+        action: MenuAction::Redo,
+        title: "Redo",
+        shortcut: Some("<S-D-z>"),
+    },
+    MenuItem {
+        action: MenuAction::Cut,
+        title: "Cut",
+        shortcut: Some("<D-x>"),
+    },
+    MenuItem {
+        action: MenuAction::Copy,
+        title: "Copy",
+        shortcut: Some("<D-c>"),
+    },
+    MenuItem {
+        action: MenuAction::Paste,
+        title: "Paste",
+        shortcut: Some("<D-v>"),
+    },
+    MenuItem {
+        action: MenuAction::SelectAll,
+        title: "Select All",
+        shortcut: Some("<D-a>"),
+    },
+    MenuItem {
+        action: MenuAction::ToggleFullscreen,
+        title: "Toggle Full Screen",
+        shortcut: Some("<C-D-f>"),
+    },
+    MenuItem {
+        action: MenuAction::ToggleWindow,
+        title: "Show/Hide Neovide",
+        shortcut: None,
+    },
+];
+
+/// The editing actions offered when right clicking.
+#[cfg_attr(target_os = "linux", allow(dead_code))]
+pub const CONTEXT_MENU: [MenuAction; 4] = [
+    MenuAction::Cut,
+    MenuAction::Copy,
+    MenuAction::Paste,
+    MenuAction::SelectAll,
+];
+
+/// The actions in the menu of the tray icon.
+pub const TRAY_MENU: [MenuAction; 2] = [MenuAction::ToggleWindow, MenuAction::Quit];
+
+impl MenuAction {
+    fn item(self) -> &'static MenuItem {
+        MENU_ITEMS
+            .iter()
+            .find(|item| item.action == self)
+            .expect("Every menu action has an item")
+    }
+
+    pub fn title(self) -> &'static str {
+        self.item().title
+    }
+
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+    pub fn shortcut(self) -> Option<&'static str> {
+        self.item().shortcut
+    }
+
+    // Menu items carry their action as a tag, leaving 0 for when nothing was picked
+    #[cfg_attr(target_os = "linux", allow(dead_code))]
+    pub fn tag(self) -> usize {
+        MENU_ITEMS
+            .iter()
+            .position(|item| item.action == self)
+            .unwrap()
+            + 1
+    }
+
+    #[cfg_attr(target_os = "linux", allow(dead_code))]
+    pub fn from_tag(tag: usize) -> Option<MenuAction> {
+        tag.checked_sub(1)
+            .and_then(|index| MENU_ITEMS.get(index))
+            .map(|item| item.action)
+    }
+
+    /// What neovim is asked to do for the action, for the actions neovim handles. Copying and
+    /// cutting only make sense with a visual selection, so they do nothing in the other modes.
+    pub fn ui_command(self) -> Option<ParallelCommand> {
+        let command = match self {
+            MenuAction::Quit | MenuAction::CloseWindow => "confirm qall",
+            MenuAction::NewTab => "tabnew",
+            MenuAction::Save => "confirm write",
+            MenuAction::Undo => "silent! undo",
+            MenuAction::Redo => "silent! redo",
+            MenuAction::Cut => {
+                "lua if vim.api.nvim_get_mode().mode:match('^[vV\\22]') then vim.api.nvim_input('\"+d') end"
+            }
+            MenuAction::Copy => {
+                "lua if vim.api.nvim_get_mode().mode:match('^[vV\\22]') then vim.api.nvim_input('\"+y') end"
+            }
+            MenuAction::Paste => return Some(ParallelCommand::PasteClipboard),
+            MenuAction::SelectAll => "lua vim.api.nvim_input('<Esc>ggVG')",
+            MenuAction::NewWindow | MenuAction::ToggleFullscreen | MenuAction::ToggleWindow => {
+                return None
+            }
+        };
+        Some(ParallelCommand::Command(command.to_owned()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tags_round_trip() {
+        for item in MENU_ITEMS.iter() {
+            assert_eq!(MenuAction::from_tag(item.action.tag()), Some(item.action));
+        }
+        assert_eq!(MenuAction::from_tag(0), None);
+        assert_eq!(MenuAction::from_tag(MENU_ITEMS.len() + 1), None);
+    }
+}
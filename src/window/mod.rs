@@ -1,83 +1,572 @@
+mod accessibility;
+mod blur;
+mod context_menu;
+mod discord;
+#[cfg(target_os = "macos")]
+mod dock_menu;
+mod drop_target;
+mod external_window;
+mod frameless;
+mod fullscreen_monitor;
+mod jump_list;
 mod keyboard_manager;
+mod latency;
+#[cfg(target_os = "macos")]
+mod menu;
+mod menu_items;
 mod mouse_manager;
+mod native_background;
+mod occlusion;
+#[cfg(target_os = "macos")]
+mod open_documents;
+// Only macos reports pinch gestures for now
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+mod pinch;
+mod power_saving;
+mod presentation;
+mod progress;
+mod refresh_rate;
 mod renderer;
+mod screen_recording;
+mod screenshot;
 mod settings;
+#[cfg(target_os = "macos")]
+mod tabbing;
+mod theme;
+mod title;
+mod titlebar;
+mod tray;
 
 use std::{
-    sync::mpsc::Receiver,
+    borrow::Cow,
+    collections::HashMap,
+    path::Path,
+    sync::{mpsc::Receiver, Arc},
     time::{Duration, Instant},
 };
 
 use glutin::{
     self,
-    dpi::PhysicalSize,
-    event::{Event, WindowEvent},
-    event_loop::{ControlFlow, EventLoop},
-    window::{self, Fullscreen, Icon},
-    ContextBuilder, GlProfile, WindowedContext,
+    dpi::{PhysicalPosition, PhysicalSize},
+    event::{ElementState, Event, WindowEvent},
+    event_loop::{ControlFlow, EventLoop, EventLoopWindowTarget},
+    keyboard::Key,
+    window::{self, Fullscreen, Icon, Theme, WindowId},
 };
-use log::trace;
+use log::{error, trace};
+use skia_safe::{Color, Point, Rect};
 
+#[cfg(target_os = "macos")]
+use glutin::event::StartCause;
 #[cfg(target_os = "linux")]
-use glutin::platform::unix::WindowBuilderExtUnix;
+use glutin::platform::unix::{WindowBuilderExtUnix, WindowExtUnix};
 
 use crate::{
-    bridge::{ParallelCommand, UiCommand},
+    bridge::{Bridge, ParallelCommand, SerialCommand, UiCommand},
     channel_utils::*,
     cmd_line::CmdLineSettings,
-    editor::DrawCommand,
+    crash_report::record_event,
+    diagnostics::{write_diagnostics, WindowDiagnostics},
     editor::WindowCommand,
+    error_handling::show_error_dialog,
+    instance::{start_neovim_instance, NeovimInstance},
+    notification::notify,
     redraw_scheduler::REDRAW_SCHEDULER,
-    renderer::Renderer,
+    renderer::{high_contrast_colors, refresh_high_contrast, ProfilerPhase, Renderer},
     running_tracker::*,
-    settings::{maybe_save_window_size, SETTINGS},
+    settings::{
+        maybe_save_ui_state, maybe_save_window_geometry, remember_recent_file,
+        try_to_load_last_window_geometry, use_settings, PersistentUiState,
+        PersistentWindowGeometry, Settings, DEFAULT_WINDOW_GEOMETRY, SETTINGS,
+    },
+    single_instance,
+    startup_profile::{mark_startup, StartupPhase},
     utils::Dimensions,
 };
+use accessibility::Accessibility;
+use blur::WindowBlur;
+use discord::{DiscordPresence, Presence};
+use drop_target::DroppedContent;
+use external_window::ExternalWindow;
+use frameless::FramelessWindowHandler;
+use fullscreen_monitor::fullscreen_monitor;
 use image::{load_from_memory, GenericImageView, Pixel};
 use keyboard_manager::KeyboardManager;
+use latency::LatencyTracker;
+use menu_items::MenuAction;
 use mouse_manager::MouseManager;
-use renderer::SkiaRenderer;
+use native_background::NativeBackground;
+use pinch::{stepped_font_zoom, PinchEvent, PinchZoom};
+use presentation::{centering_inset, Presentation};
+use refresh_rate::current_refresh_rate;
+use renderer::{build_skia_renderer, SkiaRenderer};
+use screen_recording::ScreenRecording;
+pub use screenshot::save_screenshot;
+use title::TitleState;
+use titlebar::Titlebar;
 
+pub use power_saving::PowerSavingMode;
+use settings::set_monitor_refresh_rate;
 pub use settings::{KeyboardSettings, WindowSettings};
 
 static ICON: &[u8] = include_bytes!("../../assets/neovide.ico");
 
 const MIN_WINDOW_WIDTH: u64 = 20;
 const MIN_WINDOW_HEIGHT: u64 = 6;
+const QUIT_TIMEOUT: Duration = Duration::from_secs(5);
+// Longer gaps between frames, like after the machine slept, are animated as if they were this long
+const MAX_FRAME_LENGTH: f32 = 0.25;
+// Dragging a window edge changes the size every frame. Neovim is told at most this often and the
+// surfaces are recreated at the same pace, and the size the drag ends at is always applied.
+const RESIZE_INTERVAL: Duration = Duration::from_millis(50);
+// How long the window stays hidden waiting for neovim to draw
+const FIRST_FLUSH_TIMEOUT: Duration = Duration::from_secs(2);
 
-pub struct GlutinWindowWrapper {
-    windowed_context: WindowedContext<glutin::PossiblyCurrent>,
-    skia_renderer: SkiaRenderer,
+pub struct WinitWindowWrapper {
+    skia_renderer: Box<dyn SkiaRenderer>,
     renderer: Renderer,
     keyboard_manager: KeyboardManager,
+    // Only there while g:neovide_latency_measurement is set
+    latency: Option<LatencyTracker>,
     mouse_manager: MouseManager,
+    pinch_zoom: PinchZoom,
+    presentation: Presentation,
+    frameless_handler: FramelessWindowHandler,
+    titlebar: Titlebar,
+    native_background: NativeBackground,
+    blur: WindowBlur,
+    accessibility: Accessibility,
     title: String,
+    title_state: TitleState,
+    discord_presence: DiscordPresence,
+    title_format: String,
+    // The os theme last passed on to neovim, while the theme setting is auto
+    theme: Option<Theme>,
+    follow_theme: bool,
+    high_contrast_colorscheme: String,
+    // Moving the focus between the main window and an external one shouldn't look like the
+    // editor lost focus, so neovim is only told once all events of a loop iteration are handled
+    window_focused: bool,
+    focused_external_window: Option<WindowId>,
+    neovim_focused: bool,
     fullscreen: bool,
+    // Whether the fullscreen was entered without a space of its own, which it has to be left the
+    // same way even when the setting changed in between
+    #[cfg(target_os = "macos")]
+    simple_fullscreen: bool,
+    last_fullscreen_setting: bool,
+    always_on_top: bool,
+    last_always_on_top_setting: bool,
+    frameless: bool,
+    // Hidden from the tray icon
+    hidden: bool,
+    // Not shown yet, since neovim hasn't drawn anything
+    waiting_for_first_flush: bool,
+    // Entirely out of sight, so frames aren't drawn
+    occluded: bool,
+    // Where the input method was last told the caret is, so it is only told when it moved
+    ime_position: Option<(i32, i32)>,
+    created_at: Instant,
+    // Space between the window edges and the grid, taken up by the custom titlebar and padding
+    content_insets: Rect,
     saved_inner_size: PhysicalSize<u32>,
     saved_grid_size: Option<Dimensions>,
+    last_resize: Option<Instant>,
+    // The monitor the window was last seen on
+    monitor_name: Option<String>,
+    pending_screenshots: Vec<Option<String>>,
+    screen_recording: Option<ScreenRecording>,
+    // Grids shown in their own os windows, by grid id
+    external_windows: HashMap<u64, ExternalWindow>,
+    // Windows opened with NeovideNewWindow, with the file to open in each. The event loop
+    // creates them since it owns the window target.
+    requested_windows: Vec<Option<String>>,
+    // The arguments to start neovim with again once the running one quit
+    restart_args: Option<Vec<String>>,
+    neovim_args: Vec<String>,
+    // When neovim was told to quit, input isn't sent anymore from then on
+    quit_requested: Option<Instant>,
+    // Only the first window restores and saves the window geometry
+    primary: bool,
     ui_command_sender: LoggingTx<UiCommand>,
     window_command_receiver: Receiver<WindowCommand>,
+    running_tracker: RunningTracker,
+    _bridge: Bridge,
+    settings: Arc<Settings>,
 }
 
-impl GlutinWindowWrapper {
+impl WinitWindowWrapper {
     pub fn toggle_fullscreen(&mut self) {
-        let window = self.windowed_context.window();
+        let window = self.skia_renderer.window();
+        #[cfg(target_os = "macos")]
+        {
+            use glutin::platform::macos::WindowExtMacOS;
+
+            if !self.fullscreen {
+                self.simple_fullscreen = SETTINGS.get::<WindowSettings>().macos_simple_fullscreen;
+            }
+            if self.simple_fullscreen {
+                window.set_simple_fullscreen(!self.fullscreen);
+                self.fullscreen = !self.fullscreen;
+                return;
+            }
+        }
+
         if self.fullscreen {
             window.set_fullscreen(None);
         } else {
-            let handle = window.current_monitor();
+            let choice = SETTINGS.get::<WindowSettings>().fullscreen_monitor;
+            let handle = fullscreen_monitor(window, &choice);
             window.set_fullscreen(Some(Fullscreen::Borderless(handle)));
         }
 
         self.fullscreen = !self.fullscreen;
     }
 
+    pub fn toggle_always_on_top(&mut self) {
+        self.always_on_top = !self.always_on_top;
+        self.skia_renderer
+            .window()
+            .set_always_on_top(self.always_on_top);
+    }
+
     pub fn synchronize_settings(&mut self) {
+        let title_format = { SETTINGS.get::<WindowSettings>().title_format };
+
+        if self.title_format != title_format {
+            self.title_format = title_format;
+            self.update_title();
+        }
+
+        let follow_theme = { SETTINGS.get::<WindowSettings>().theme == "auto" };
+
+        if self.follow_theme != follow_theme {
+            self.follow_theme = follow_theme;
+            self.theme = None;
+            self.update_theme(None);
+        }
+
+        let high_contrast_colorscheme =
+            { SETTINGS.get::<WindowSettings>().high_contrast_colorscheme };
+
+        if self.high_contrast_colorscheme != high_contrast_colorscheme {
+            self.high_contrast_colorscheme = high_contrast_colorscheme;
+            if high_contrast_colors().is_some() {
+                self.apply_high_contrast_colorscheme(true);
+            }
+        }
+
+        let latency_measurement = { SETTINGS.get::<WindowSettings>().latency_measurement };
+
+        if self.latency.is_some() != latency_measurement {
+            self.latency = if latency_measurement {
+                Some(LatencyTracker::new())
+            } else {
+                None
+            };
+            self.renderer.profiler.latency = None;
+        }
+
+        let frameless = { SETTINGS.get::<WindowSettings>().frameless };
+
+        if self.frameless != frameless {
+            self.frameless = frameless;
+            self.skia_renderer.window().set_decorations(!frameless);
+        }
+
         let fullscreen = { SETTINGS.get::<WindowSettings>().fullscreen };
 
-        if self.fullscreen != fullscreen {
-            self.toggle_fullscreen();
+        // Fullscreen can also be toggled with F11 or NeovideToggleFullscreen, so the window only
+        // follows the setting when the setting itself changes
+        if self.last_fullscreen_setting != fullscreen {
+            self.last_fullscreen_setting = fullscreen;
+            if self.fullscreen != fullscreen {
+                self.toggle_fullscreen();
+            }
+        }
+
+        let always_on_top = { SETTINGS.get::<WindowSettings>().always_on_top };
+
+        // Same as fullscreen, NeovideToggleAlwaysOnTop shouldn't be undone by the setting
+        if self.last_always_on_top_setting != always_on_top {
+            self.last_always_on_top_setting = always_on_top;
+            if self.always_on_top != always_on_top {
+                self.toggle_always_on_top();
+            }
+        }
+
+        let titlebar = { SETTINGS.get::<WindowSettings>().titlebar };
+        let framed = !self.fullscreen && !frameless;
+        let window = self.skia_renderer.window();
+        self.titlebar.update(
+            window,
+            framed && titlebar == "custom",
+            framed && titlebar == "transparent",
+            self.renderer.grid_renderer.get_default_background(),
+        );
+        let background_alpha = { SETTINGS.get::<WindowSettings>().background_alpha() };
+        self.native_background.update(
+            window,
+            self.renderer
+                .grid_renderer
+                .get_default_background()
+                .with_a(background_alpha),
+        );
+
+        let (blur, blur_style) = {
+            let settings = SETTINGS.get::<WindowSettings>();
+            (
+                settings.window_blur && settings.is_transparent(),
+                settings.window_blur_style,
+            )
+        };
+        self.blur.update(window, blur, &blur_style);
+
+        let scale_factor = window.scale_factor() as f32;
+        let content_insets = {
+            let settings = SETTINGS.get::<WindowSettings>();
+            let left = settings.padding_left as f32 * scale_factor;
+            let right = settings.padding_right as f32 * scale_factor;
+            let centering = if self.presentation.is_active() {
+                centering_inset(
+                    window.inner_size().width as f32 - left - right,
+                    settings.presentation_columns,
+                    self.renderer.grid_renderer.font_dimensions.width as f32,
+                )
+            } else {
+                0.0
+            };
+            Rect::new(
+                left + centering,
+                self.titlebar.height(scale_factor) + settings.padding_top as f32 * scale_factor,
+                right + centering,
+                settings.padding_bottom as f32 * scale_factor,
+            )
+        };
+        if self.content_insets != content_insets {
+            self.content_insets = content_insets;
+            if self.renderer.grid_renderer.is_ready {
+                self.handle_new_grid_size(self.skia_renderer.window().inner_size());
+            }
+        }
+        self.update_content_origin();
+        self.update_discord_presence();
+    }
+
+    fn update_discord_presence(&mut self) {
+        let settings = SETTINGS.get::<WindowSettings>();
+        self.discord_presence
+            .update(settings.discord_presence, &settings.discord_client_id);
+    }
+
+    fn content_size(&self, inner_size: PhysicalSize<u32>) -> PhysicalSize<u32> {
+        let insets = self.content_insets;
+        PhysicalSize::new(
+            inner_size
+                .width
+                .saturating_sub((insets.left + insets.right) as u32),
+            inner_size
+                .height
+                .saturating_sub((insets.top + insets.bottom) as u32),
+        )
+    }
+
+    // Windows rarely fit a whole number of cells. In padding mode the part of a cell left over
+    // is split evenly around the grid instead of being left at the bottom right.
+    fn update_content_origin(&mut self) {
+        let mut content_origin = Point::new(self.content_insets.left, self.content_insets.top);
+
+        let padding_mode = { SETTINGS.get::<WindowSettings>().resize_mode == "padding" };
+        if let Some(grid_size) = self.saved_grid_size.filter(|_| padding_mode) {
+            let content_size = self.content_size(self.skia_renderer.window().inner_size());
+            let grid_pixels = self
+                .renderer
+                .grid_renderer
+                .convert_grid_to_physical(grid_size);
+            content_origin.x += content_size.width.saturating_sub(grid_pixels.width) as f32 / 2.0;
+            content_origin.y += content_size.height.saturating_sub(grid_pixels.height) as f32 / 2.0;
         }
+
+        self.renderer.content_origin = content_origin;
+    }
+
+    // In snap mode the window size is rounded back to whole cells after each resize. Maximized
+    // and fullscreen windows are sized by the os, so those keep the remainder.
+    fn snap_to_grid(&mut self, inner_size: PhysicalSize<u32>) {
+        if self.fullscreen || self.is_maximized() {
+            return;
+        }
+
+        if let Some(grid_size) = self.saved_grid_size {
+            let insets = self.content_insets;
+            let grid_pixels = self
+                .renderer
+                .grid_renderer
+                .convert_grid_to_physical(grid_size);
+            let snapped_size = PhysicalSize::new(
+                grid_pixels.width + (insets.left + insets.right) as u32,
+                grid_pixels.height + (insets.top + insets.bottom) as u32,
+            );
+            if snapped_size != inner_size {
+                self.skia_renderer.window().set_inner_size(snapped_size);
+            }
+        }
+    }
+
+    /// Opens and closes os windows for the grids neovim moved in and out of external windows.
+    pub fn synchronize_external_windows(&mut self, window_target: &EventLoopWindowTarget<()>) {
+        let renderer = &self.renderer;
+        self.external_windows.retain(|grid_id, external_window| {
+            if renderer
+                .external_windows()
+                .any(|rendered_window| rendered_window.id == *grid_id)
+            {
+                return true;
+            }
+
+            // Skia frees its gpu resources on drop, which needs the window's own context
+            external_window.skia_renderer.make_current();
+            false
+        });
+
+        for rendered_window in renderer.external_windows() {
+            let ui_command_sender = &self.ui_command_sender;
+            self.external_windows
+                .entry(rendered_window.id)
+                .or_insert_with(|| {
+                    ExternalWindow::new(
+                        window_target,
+                        rendered_window,
+                        renderer,
+                        ui_command_sender.clone(),
+                    )
+                })
+                .update(rendered_window, renderer);
+        }
+    }
+
+    pub fn close_external_windows(&mut self) {
+        for (_, mut external_window) in self.external_windows.drain() {
+            external_window.skia_renderer.make_current();
+        }
+    }
+
+    pub fn owns_external_window(&self, window_id: WindowId) -> bool {
+        self.external_windows
+            .values()
+            .any(|external_window| external_window.owns(window_id))
+    }
+
+    pub fn handle_external_window_event(&mut self, window_id: WindowId, event: Event<()>) {
+        if let Event::WindowEvent {
+            event: WindowEvent::Focused(focus),
+            ..
+        } = event
+        {
+            if focus {
+                self.focused_external_window = Some(window_id);
+            } else if self.focused_external_window == Some(window_id) {
+                self.focused_external_window = None;
+            }
+        }
+
+        self.keyboard_manager.handle_event(&event);
+        if let Some(external_window) = self
+            .external_windows
+            .values_mut()
+            .find(|external_window| external_window.owns(window_id))
+        {
+            external_window.handle_event(
+                &event,
+                &self.keyboard_manager,
+                &self.renderer,
+                self.mouse_manager.enabled,
+            );
+        }
+        REDRAW_SCHEDULER.queue_next_frame();
+    }
+
+    /// Passes the os theme on to neovim by setting the background and firing the
+    /// NeovideThemeChanged user autocommand, so colorschemes can follow it. Only done while the
+    /// theme setting is auto.
+    fn update_theme(&mut self, theme: Option<Theme>) {
+        if !self.follow_theme {
+            return;
+        }
+
+        let theme = match theme.or_else(|| theme::system_theme(self.skia_renderer.window())) {
+            Some(theme) if self.theme != Some(theme) => theme,
+            _ => return,
+        };
+        self.theme = Some(theme);
+
+        let command = format!(
+            "set background={} | silent doautocmd <nomodeline> User NeovideThemeChanged",
+            theme::background_name(theme)
+        );
+        self.ui_command_sender
+            .send(ParallelCommand::Command(command).into())
+            .ok();
+    }
+
+    fn update_high_contrast(&mut self) {
+        if !refresh_high_contrast() {
+            return;
+        }
+
+        let high_contrast = high_contrast_colors().is_some();
+        let command = format!(
+            "let g:neovide_high_contrast = {} | silent doautocmd <nomodeline> User NeovideHighContrastChanged",
+            if high_contrast { "v:true" } else { "v:false" }
+        );
+        self.ui_command_sender
+            .send(ParallelCommand::Command(command).into())
+            .ok();
+        self.apply_high_contrast_colorscheme(high_contrast);
+        REDRAW_SCHEDULER.queue_next_frame();
+    }
+
+    // The colorscheme from before is put back once high contrast is turned off again
+    fn apply_high_contrast_colorscheme(&mut self, high_contrast: bool) {
+        if self.high_contrast_colorscheme.is_empty() {
+            return;
+        }
+
+        let command = if high_contrast {
+            format!(
+                "let g:neovide_previous_colorscheme = get(g:, 'colors_name', '') | colorscheme {}",
+                self.high_contrast_colorscheme
+            )
+        } else {
+            "if get(g:, 'neovide_previous_colorscheme', '') != '' | execute 'colorscheme' g:neovide_previous_colorscheme | endif".to_owned()
+        };
+        self.ui_command_sender
+            .send(ParallelCommand::Command(command).into())
+            .ok();
+    }
+
+    // F11 can optionally be claimed for toggling fullscreen, in which case neovim never sees it
+    fn handle_fullscreen_key(&mut self, event: &Event<()>) -> bool {
+        if !SETTINGS.get::<WindowSettings>().toggle_fullscreen_with_f11 {
+            return false;
+        }
+
+        if let Event::WindowEvent {
+            event: WindowEvent::KeyboardInput {
+                event: key_event, ..
+            },
+            ..
+        } = event
+        {
+            if key_event.logical_key == Key::F11 {
+                if key_event.state == ElementState::Pressed && !key_event.repeat {
+                    self.toggle_fullscreen();
+                }
+                return true;
+            }
+        }
+
+        false
     }
 
     #[allow(clippy::needless_collect)]
@@ -86,25 +575,338 @@ impl GlutinWindowWrapper {
         for window_command in window_commands.into_iter() {
             match window_command {
                 WindowCommand::TitleChanged(new_title) => self.handle_title_changed(new_title),
+                WindowCommand::BufferChanged {
+                    path,
+                    modified,
+                    cwd,
+                    filetype,
+                } => {
+                    #[cfg(target_os = "macos")]
+                    set_represented_file(self.skia_renderer.window(), &path);
+                    if let Some(recent_files) = remember_recent_file(&path) {
+                        jump_list::update_jump_list(recent_files);
+                    }
+                    self.discord_presence
+                        .set_presence(Presence::new(&filetype, &cwd));
+                    self.update_discord_presence();
+                    self.title_state.path = path;
+                    self.title_state.modified = modified;
+                    self.title_state.cwd = cwd;
+                    self.update_title();
+                }
                 WindowCommand::SetMouseEnabled(mouse_enabled) => {
                     self.mouse_manager.enabled = mouse_enabled
                 }
+                WindowCommand::SetBusy(busy) => {
+                    self.mouse_manager.busy = busy;
+                    self.update_pointer_icon();
+                }
+                WindowCommand::SetProgress(progress) => {
+                    progress::set_progress(self.skia_renderer.window(), progress)
+                }
+                WindowCommand::SuppressContextMenu(suppressed) => {
+                    self.mouse_manager.context_menu_suppressed = suppressed
+                }
+                WindowCommand::SetMouseHide(mousehide) => self.mouse_manager.mousehide = mousehide,
+                WindowCommand::SetMouseMoveEvent(mouse_move_event) => {
+                    self.mouse_manager.mouse_move_event = mouse_move_event
+                }
+                WindowCommand::SetMouseTime(mousetime) => {
+                    self.mouse_manager.mousetime = Duration::from_millis(mousetime)
+                }
+                WindowCommand::Notify { title, body, level } => {
+                    notify(title, body, level, self.window_focused)
+                }
+                WindowCommand::SetRichClipboard { html, text } => {
+                    crate::clipboard::set_html(&html, &text, self.skia_renderer.window())
+                }
+                WindowCommand::ToggleFullscreen => self.toggle_fullscreen(),
+                WindowCommand::ToggleAlwaysOnTop => self.toggle_always_on_top(),
+                WindowCommand::Focus => self.focus(),
+                WindowCommand::ZoomIn => self.step_font_zoom(1.0),
+                WindowCommand::ZoomOut => self.step_font_zoom(-1.0),
+                WindowCommand::ZoomReset => self.set_font_zoom(1.0),
+                WindowCommand::SetPresentation(active) => self.set_presentation(active),
+                WindowCommand::SetFullscreen(fullscreen) => {
+                    if self.fullscreen != fullscreen {
+                        self.toggle_fullscreen();
+                    }
+                }
+                WindowCommand::StartRecording(path) => {
+                    let settings = SETTINGS.get::<WindowSettings>();
+                    self.screen_recording = Some(ScreenRecording::start(
+                        path,
+                        settings.recording_duration,
+                        settings.recording_fps,
+                    ));
+                }
+                // Dropping the recording finishes the file
+                WindowCommand::StopRecording => self.screen_recording = None,
+                WindowCommand::Screenshot(path) => {
+                    // Screenshots are taken right after the next frame is drawn, since the
+                    // contents of the back buffer are undefined once it has been swapped
+                    self.pending_screenshots.push(path);
+                    REDRAW_SCHEDULER.queue_next_frame();
+                }
+                WindowCommand::WriteDiagnostics(path) => self.write_diagnostics(path, true),
+                WindowCommand::NewWindow(path) => self.requested_windows.push(path),
+                WindowCommand::Restart(neovim_args) => {
+                    // Neovim only asks for a restart without unsaved changes, so it can quit
+                    // without asking
+                    self.restart_args = Some(if neovim_args.is_empty() {
+                        self.neovim_args.clone()
+                    } else {
+                        neovim_args
+                    });
+                    self.send_quit();
+                }
+                WindowCommand::SetConnectionLost(connection_lost) => {
+                    self.title_state.connection_lost = connection_lost;
+                    self.update_title();
+                    // The new connection attached with the size from the command line
+                    if !connection_lost {
+                        self.saved_grid_size = None;
+                        self.handle_new_grid_size(self.skia_renderer.window().inner_size());
+                    }
+                }
+                WindowCommand::ShowBanner(message) => {
+                    self.renderer.banner.message = message;
+                    REDRAW_SCHEDULER.queue_next_frame();
+                }
+                WindowCommand::UpdateAccessibility(text) => self.accessibility.update(text),
+                WindowCommand::FontSettingsChanged(font_settings) => {
+                    self.renderer
+                        .grid_renderer
+                        .update_font_settings(font_settings);
+                    REDRAW_SCHEDULER.queue_next_frame();
+                }
+            }
+        }
+    }
+
+    #[cfg(target_os = "macos")]
+    pub fn handle_menu_action(&mut self, action: MenuAction) {
+        match action {
+            MenuAction::NewWindow => self.requested_windows.push(None),
+            MenuAction::ToggleFullscreen => self.toggle_fullscreen(),
+            _ => {
+                if let Some(command) = action.ui_command() {
+                    self.ui_command_sender.send(command.into()).ok();
+                }
+            }
+        }
+    }
+
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+    pub fn handle_pinch_event(&mut self, event: PinchEvent) {
+        self.renderer.zoom = self.pinch_zoom.handle_event(event);
+
+        if event == PinchEvent::Ended {
+            let grid_renderer = &self.renderer.grid_renderer;
+            let font_zoom = self
+                .pinch_zoom
+                .finish(grid_renderer.shaper.base_size(), grid_renderer.font_zoom);
+            self.set_font_zoom(font_zoom);
+        }
+        REDRAW_SCHEDULER.queue_next_frame();
+    }
+
+    fn set_font_zoom(&mut self, font_zoom: f32) {
+        self.renderer.grid_renderer.set_font_zoom(font_zoom);
+        self.handle_new_grid_size(self.skia_renderer.window().inner_size());
+        REDRAW_SCHEDULER.queue_next_frame();
+    }
+
+    fn step_font_zoom(&mut self, step: f32) {
+        let grid_renderer = &self.renderer.grid_renderer;
+        let font_zoom = stepped_font_zoom(
+            grid_renderer.shaper.base_size(),
+            grid_renderer.font_zoom,
+            step,
+        );
+        self.set_font_zoom(font_zoom);
+    }
+
+    fn set_presentation(&mut self, active: bool) {
+        let zoom = SETTINGS.get::<WindowSettings>().presentation_zoom;
+        let font_zoom = self.renderer.grid_renderer.font_zoom;
+        if let Some(font_zoom) = self.presentation.set_active(active, font_zoom, zoom) {
+            self.set_font_zoom(font_zoom);
+        }
+    }
+
+    fn update_latency(&mut self) {
+        let key_sent_at = self.keyboard_manager.key_sent_at.take();
+        let latency = match &mut self.latency {
+            Some(latency) => latency,
+            None => return,
+        };
+        if let Some(key_sent_at) = key_sent_at {
+            latency.key_sent(key_sent_at);
+        }
+        latency.frame_presented(self.renderer.frame_flush, Instant::now());
+        self.renderer.profiler.latency = latency.percentiles();
+    }
+
+    // Opened in neovim when asked for from there, so it can be copied right away
+    fn write_diagnostics(&mut self, path: Option<String>, open: bool) {
+        let shaper = &self.renderer.grid_renderer.shaper;
+        let window = WindowDiagnostics {
+            scale_factor: self.skia_renderer.window().scale_factor(),
+            monitor: self.monitor_name.clone(),
+            font_size: shaper.current_size(),
+            fonts: shaper.loaded_fonts(),
+        };
+        if let Some(path) = write_diagnostics(path, &window) {
+            if open {
+                self.ui_command_sender
+                    .send(
+                        ParallelCommand::FileDrop {
+                            path: path.to_string_lossy().into_owned(),
+                            command: "tabedit".to_owned(),
+                        }
+                        .into(),
+                    )
+                    .ok();
             }
         }
     }
 
+    // Raising the window is up to the window manager, which might only highlight it in the
+    // taskbar instead
+    fn focus(&mut self) {
+        self.set_hidden(false);
+        let window = self.skia_renderer.window();
+        window.set_minimized(false);
+        window.request_user_attention(Some(window::UserAttentionType::Informational));
+    }
+
     pub fn handle_title_changed(&mut self, new_title: String) {
-        self.title = new_title;
-        self.windowed_context.window().set_title(&self.title);
+        self.title_state.neovim_title = new_title;
+        self.update_title();
+    }
+
+    fn update_title(&mut self) {
+        let mut title = self.title_state.format(&self.title_format);
+        // The buffer can change before neovim sets a title of its own
+        if title.is_empty() {
+            title = String::from("Neovide");
+        }
+        if self.title != title {
+            self.title = title;
+            self.skia_renderer.window().set_title(&self.title);
+        }
+    }
+
+    // Neovim gets a moment to write its swap files and shada. If it doesn't manage to quit in time
+    // the window closes anyway, which kills the process.
+    fn send_quit(&mut self) {
+        self.ui_command_sender
+            .send(SerialCommand::Quit.into())
+            .expect("Could not send quit command to bridge");
+        self.quit_requested = Some(Instant::now());
+    }
+
+    pub fn check_quit_timeout(&mut self) {
+        if let Some(quit_requested) = self.quit_requested {
+            if quit_requested.elapsed() > QUIT_TIMEOUT {
+                error!("Neovim did not quit in time, closing anyway");
+                self.quit_requested = None;
+                self.running_tracker.quit("neovim did not quit in time");
+            }
+        }
     }
 
     pub fn handle_quit(&mut self) {
-        if SETTINGS.get::<CmdLineSettings>().remote_tcp.is_none() {
+        if !SETTINGS.get::<CmdLineSettings>().is_remote() {
+            self.send_quit();
+        } else {
+            self.running_tracker.quit("window closed");
+        }
+    }
+
+    // Closing the window goes through neovim's confirm prompt rather than discarding unsaved
+    // changes. Neovim only quits once the modified buffers were saved or abandoned.
+    pub fn handle_close_requested(&mut self) {
+        let remote = SETTINGS.get::<CmdLineSettings>().is_remote();
+        if !remote && SETTINGS.get::<WindowSettings>().confirm_quit {
             self.ui_command_sender
-                .send(ParallelCommand::Quit.into())
-                .expect("Could not send quit command to bridge");
+                .send(ParallelCommand::Command("confirm qall".to_owned()).into())
+                .ok();
         } else {
-            RUNNING_TRACKER.quit("window closed");
+            self.handle_quit();
+        }
+    }
+
+    // The candidate window of an input method opens just below the caret, so it doesn't cover
+    // the text being composed
+    fn update_ime_position(&mut self) {
+        let caret_region = match self.renderer.caret_region() {
+            Some(caret_region) => caret_region,
+            None => return,
+        };
+        let position = (caret_region.left as i32, caret_region.bottom as i32);
+        if self.ime_position != Some(position) {
+            self.ime_position = Some(position);
+            self.skia_renderer
+                .window()
+                .set_ime_position(PhysicalPosition::new(position.0, position.1));
+        }
+    }
+
+    // Fires FocusGained or FocusLost when the editor as a whole gained or lost the focus
+    fn synchronize_focus(&mut self) {
+        let focused = self.window_focused || self.focused_external_window.is_some();
+        if focused == self.neovim_focused {
+            return;
+        }
+
+        self.neovim_focused = focused;
+        self.renderer.focused = focused;
+        REDRAW_SCHEDULER.queue_next_frame();
+        if focused {
+            self.handle_focus_gained();
+        } else {
+            self.handle_focus_lost();
+        }
+    }
+
+    pub fn set_hidden(&mut self, hidden: bool) {
+        if self.hidden != hidden {
+            self.skia_renderer.window().set_visible(!hidden);
+            self.hidden = hidden;
+            REDRAW_SCHEDULER.queue_next_frame();
+        }
+    }
+
+    // Files forwarded by later launches in single instance mode open in new tabs, and the window
+    // comes back up so they can be seen
+    pub fn handle_open_files(&mut self, files: Vec<String>) {
+        self.set_hidden(false);
+        self.skia_renderer.window().set_minimized(false);
+        for path in files {
+            self.ui_command_sender
+                .send(
+                    ParallelCommand::FileDrop {
+                        path,
+                        command: "tab drop".to_owned(),
+                    }
+                    .into(),
+                )
+                .ok();
+        }
+    }
+
+    // Files and folders opened from Finder or the Dock, the way double clicking them would open
+    // them in any other editor
+    #[cfg(target_os = "macos")]
+    pub fn handle_open_documents(&mut self, documents: Vec<String>) {
+        self.set_hidden(false);
+        self.skia_renderer.window().set_minimized(false);
+        for path in documents {
+            self.ui_command_sender
+                .send(ParallelCommand::OpenDocument { path }.into())
+                .ok();
         }
     }
 
@@ -121,23 +923,96 @@ impl GlutinWindowWrapper {
         REDRAW_SCHEDULER.queue_next_frame();
     }
 
-    pub fn handle_event(&mut self, event: Event<()>) {
-        self.keyboard_manager.handle_event(&event);
-        self.mouse_manager.handle_event(
-            &event,
-            &self.keyboard_manager,
+    fn update_pointer_icon(&mut self) {
+        let over_resize_edge =
+            self.frameless && !self.fullscreen && self.frameless_handler.hovering_edge();
+        self.mouse_manager.update_pointer_icon(
+            over_resize_edge,
             &self.renderer,
-            &self.windowed_context,
+            self.skia_renderer.window(),
         );
+    }
+
+    fn handle_dropped_file(&mut self, path: &Path) {
+        // Dropped folders become the working directory
+        let command = if path.is_dir() {
+            "cd".to_owned()
+        } else {
+            SETTINGS.get::<WindowSettings>().file_drop_command
+        };
+        self.ui_command_sender
+            .send(
+                ParallelCommand::FileDrop {
+                    path: path.to_string_lossy().into_owned(),
+                    command,
+                }
+                .into(),
+            )
+            .ok();
+    }
+
+    // Text and files dropped on the drop target that replaced winit's
+    pub fn handle_drops(&mut self) {
+        for item in drop_target::take_drops(self.skia_renderer.window()) {
+            match item.content {
+                DroppedContent::Files(paths) => {
+                    for path in paths {
+                        self.handle_dropped_file(&path);
+                    }
+                }
+                DroppedContent::Text(text) => self.mouse_manager.handle_text_drop(
+                    &text,
+                    item.position,
+                    &self.keyboard_manager,
+                    &self.renderer,
+                    self.skia_renderer.window(),
+                ),
+            }
+        }
+    }
+
+    pub fn handle_event(&mut self, event: Event<()>) {
+        if self.handle_fullscreen_key(&event) {
+            return;
+        }
+
+        if self.frameless
+            && !self.fullscreen
+            && self
+                .frameless_handler
+                .handle_event(&event, self.skia_renderer.window())
+        {
+            return;
+        }
+
+        if self.quit_requested.is_none() {
+            self.keyboard_manager.handle_event(&event);
+            self.mouse_manager.handle_event(
+                &event,
+                &self.keyboard_manager,
+                &self.renderer,
+                self.skia_renderer.window(),
+            );
+        }
+        if let Event::WindowEvent {
+            event: WindowEvent::CursorMoved { .. },
+            ..
+        } = event
+        {
+            self.update_pointer_icon();
+        }
         match event {
             Event::LoopDestroyed => {
                 self.handle_quit();
             }
+            Event::MainEventsCleared => {
+                self.synchronize_focus();
+            }
             Event::WindowEvent {
                 event: WindowEvent::CloseRequested,
                 ..
             } => {
-                self.handle_quit();
+                self.handle_close_requested();
             }
             Event::WindowEvent {
                 event: WindowEvent::ScaleFactorChanged { scale_factor, .. },
@@ -149,23 +1024,38 @@ impl GlutinWindowWrapper {
                 event: WindowEvent::DroppedFile(path),
                 ..
             } => {
-                self.ui_command_sender
-                    .send(
-                        ParallelCommand::FileDrop(path.into_os_string().into_string().unwrap())
-                            .into(),
-                    )
-                    .ok();
+                self.handle_dropped_file(&path);
+            }
+            Event::WindowEvent {
+                event: WindowEvent::Moved(_),
+                ..
+            } => {
+                self.update_monitor();
+                REDRAW_SCHEDULER.queue_next_frame();
             }
             Event::WindowEvent {
                 event: WindowEvent::Focused(focus),
                 ..
             } => {
+                self.window_focused = focus;
+                self.accessibility.set_window_focused(focus);
                 if focus {
-                    self.handle_focus_gained();
-                } else {
-                    self.handle_focus_lost();
+                    // Another window may have set the refresh rate of its own monitor
+                    self.monitor_name = None;
+                    self.update_monitor();
+                    self.update_high_contrast();
+                    // Only windows reports theme changes, elsewhere the theme is read again in
+                    // the background whenever a window comes back into focus
+                    theme::refresh_system_theme();
                 }
             }
+            Event::WindowEvent {
+                event: WindowEvent::ThemeChanged(theme),
+                ..
+            } => {
+                self.update_theme(Some(theme));
+                self.update_high_contrast();
+            }
             Event::RedrawRequested(..) | Event::WindowEvent { .. } => {
                 REDRAW_SCHEDULER.queue_next_frame()
             }
@@ -173,21 +1063,88 @@ impl GlutinWindowWrapper {
         }
     }
 
-    pub fn draw_frame(&mut self, dt: f32) {
-        let window = self.windowed_context.window();
+    pub fn draw_frame(&mut self, dt: f32, should_draw: bool) {
+        // The draw commands wait, compacted as they pile up, and the surface is resized on
+        // restore like after any other size change
+        if !self.skia_renderer.has_area() {
+            return;
+        }
+        // Screenshots and recordings still need frames of a window that can't be seen. So does a
+        // window that stays hidden until its first flush is drawn, or it would never be shown.
+        let occluded = !self.waiting_for_first_flush
+            && occlusion::is_occluded(self.skia_renderer.window())
+            && self.pending_screenshots.is_empty()
+            && self.screen_recording.is_none();
+        if occluded != self.occluded {
+            self.occluded = occluded;
+            if !occluded {
+                // Whatever changed while out of sight is drawn right away
+                REDRAW_SCHEDULER.queue_next_frame();
+            }
+        }
         let mut font_changed = false;
+        self.skia_renderer.make_current();
+        self.mouse_manager
+            .animate_kinetic_scroll(dt, &self.keyboard_manager, &self.renderer);
+        self.renderer
+            .set_pixel_scroll_offset(self.mouse_manager.pixel_scroll_offset());
+        self.renderer
+            .overscroll(self.mouse_manager.take_overscroll());
+        self.renderer
+            .scroll_popup_menu_info(self.mouse_manager.take_popup_menu_info_scroll());
+        self.renderer.hovered_link = self.mouse_manager.hovered_link().cloned();
 
-        if REDRAW_SCHEDULER.should_draw() || SETTINGS.get::<WindowSettings>().no_idle {
+        let saves_power = SETTINGS.get::<RendererSettings>().saves_power();
+        let no_idle = SETTINGS.get::<WindowSettings>().no_idle && !saves_power;
+        if !occluded && (should_draw || no_idle) {
             font_changed = self.renderer.draw_frame(self.skia_renderer.canvas(), dt);
-            self.skia_renderer.gr_context.flush(None);
-            self.windowed_context.swap_buffers().unwrap();
+            let window = self.skia_renderer.window();
+            let width = window.inner_size().width as f32;
+            let scale_factor = window.scale_factor() as f32;
+            self.titlebar.draw(
+                self.skia_renderer.canvas(),
+                &self.title,
+                width,
+                scale_factor,
+                self.renderer.grid_renderer.get_default_background(),
+            );
+            for path in self.pending_screenshots.drain(..) {
+                save_screenshot(self.skia_renderer.canvas(), path);
+            }
+            if let Some(recording) = &mut self.screen_recording {
+                if let Some(next_frame) = recording.capture(self.skia_renderer.canvas()) {
+                    // A recording has a steady frame rate, even while nothing changes
+                    REDRAW_SCHEDULER.schedule(next_frame);
+                } else {
+                    self.screen_recording = None;
+                }
+            }
+            {
+                profile_scope!("present");
+                self.skia_renderer.flush();
+                self.skia_renderer.swap_buffers();
+            }
+            // Only counts once a frame with something of neovim's in it went out
+            if self.renderer.flushed {
+                mark_startup(StartupPhase::FirstFrame);
+            }
+            crate::profiling::finish_frame();
+            for external_window in self.external_windows.values_mut() {
+                external_window.draw(&mut self.renderer, dt);
+            }
+            self.renderer.profiler.mark(ProfilerPhase::Present);
+            self.update_latency();
+            self.update_ime_position();
         }
 
+        self.show_after_first_flush();
+
         // Wait until fonts are loaded, so we can set proper window size.
         if !self.renderer.grid_renderer.is_ready {
             return;
         }
 
+        let window = self.skia_renderer.window();
         let new_size = window.inner_size();
         let settings = SETTINGS.get::<CmdLineSettings>();
         // Resize at startup happens when window is maximized or when using tiling WM
@@ -195,11 +1152,15 @@ impl GlutinWindowWrapper {
         let resized_at_startup = settings.maximized || is_already_resized(new_size);
 
         if self.saved_grid_size.is_none() && !resized_at_startup {
-            window.set_inner_size(
-                self.renderer
-                    .grid_renderer
-                    .convert_grid_to_physical(settings.geometry),
-            );
+            let grid_size = self
+                .renderer
+                .grid_renderer
+                .convert_grid_to_physical(settings.geometry);
+            let insets = self.content_insets;
+            window.set_inner_size(PhysicalSize::new(
+                grid_size.width + (insets.left + insets.right) as u32,
+                grid_size.height + (insets.top + insets.bottom) as u32,
+            ));
             self.saved_grid_size = Some(settings.geometry);
             // Font change at startup is ignored, so grid size (and startup screen) could be preserved.
             // But only when not resized yet. With maximized or resized window we should redraw grid.
@@ -207,17 +1168,60 @@ impl GlutinWindowWrapper {
         }
 
         if self.saved_inner_size != new_size || font_changed {
+            if let Some(last_resize) = self.last_resize.filter(|_| !font_changed) {
+                let next_resize = last_resize + RESIZE_INTERVAL;
+                if Instant::now() < next_resize {
+                    // Checked again once the interval passed, even if the size stopped changing
+                    REDRAW_SCHEDULER.schedule(next_resize);
+                    return;
+                }
+            }
+            self.last_resize = Some(Instant::now());
             self.saved_inner_size = new_size;
             self.handle_new_grid_size(new_size);
-            self.skia_renderer.resize(&self.windowed_context);
+            self.skia_renderer.resize();
+            if SETTINGS.get::<WindowSettings>().resize_mode == "snap" {
+                self.snap_to_grid(new_size);
+            }
+        }
+    }
+
+    // The first frame with neovim's content has been presented by now. A neovim that is slow to
+    // start still gets a window after a while, filled with the startup background.
+    fn show_after_first_flush(&mut self) {
+        if !self.waiting_for_first_flush {
+            return;
+        }
+        if !self.renderer.flushed && self.created_at.elapsed() < FIRST_FLUSH_TIMEOUT {
+            // Keeps the loop checking while nothing else wakes it
+            REDRAW_SCHEDULER.queue_next_frame();
+            return;
+        }
+
+        self.waiting_for_first_flush = false;
+        if self.primary {
+            if let Some(path) = SETTINGS.get::<CmdLineSettings>().diagnostics {
+                self.write_diagnostics(Some(path), false);
+            }
+        }
+        // Set only now, since the g: variables from the init.vim are read before the first flush
+        #[cfg(target_os = "macos")]
+        tabbing::set_window_tabbing(
+            self.skia_renderer.window(),
+            &SETTINGS.get::<WindowSettings>().window_tabbing,
+        );
+        if !self.hidden {
+            self.skia_renderer.window().set_visible(true);
+            REDRAW_SCHEDULER.queue_next_frame();
         }
     }
 
     fn handle_new_grid_size(&mut self, new_size: PhysicalSize<u32>) {
+        let content_size = self.content_size(new_size);
         let grid_size = self
             .renderer
             .grid_renderer
-            .convert_physical_to_grid(new_size);
+            .convert_physical_to_grid(content_size);
 
         // Have a minimum size
         if grid_size.width < MIN_WINDOW_WIDTH || grid_size.height < MIN_WINDOW_HEIGHT {
@@ -240,105 +1244,525 @@ impl GlutinWindowWrapper {
             .ok();
     }
 
+    // Swaps in a new neovim while keeping the os window. Everything that was drawn belongs to the
+    // old neovim, so the renderer starts over as well.
+    fn restart(&mut self, instance: NeovimInstance) {
+        self.close_external_windows();
+        self.skia_renderer.make_current();
+        let scale_factor = self.skia_renderer.window().scale_factor();
+        self.renderer = Renderer::new(instance.flushed_draw_commands, scale_factor);
+        self.keyboard_manager = KeyboardManager::new(instance.ui_command_sender.clone());
+        self.mouse_manager = MouseManager::new(instance.ui_command_sender.clone());
+        self.ui_command_sender = instance.ui_command_sender;
+        self.window_command_receiver = instance.window_command_receiver;
+        self.running_tracker = instance.running_tracker;
+        self._bridge = instance.bridge;
+        self.settings = instance.settings;
+        self.neovim_args = instance.neovim_args;
+        self.activate();
+        self.quit_requested = None;
+        // The new neovim attaches with the startup size, the window size follows once the font
+        // is loaded
+        self.saved_grid_size = Some(SETTINGS.get::<CmdLineSettings>().geometry);
+        self.neovim_focused = false;
+        REDRAW_SCHEDULER.queue_next_frame();
+    }
+
+    fn current_window_geometry(&self) -> PersistentWindowGeometry {
+        let window = self.skia_renderer.window();
+        let monitor = window.current_monitor();
+        let maximized = self.is_maximized();
+
+        // A minimized window is moved out of sight on windows, which is no place to restore to
+        let position = if self.skia_renderer.has_area() {
+            window
+                .outer_position()
+                .ok()
+                .map(|position| (position.x, position.y))
+        } else {
+            None
+        };
+
+        PersistentWindowGeometry {
+            grid_size: self.saved_grid_size.unwrap_or(DEFAULT_WINDOW_GEOMETRY),
+            position,
+            monitor: monitor.and_then(|monitor| monitor.name()),
+            maximized,
+        }
+    }
+
+    fn current_ui_state(&self) -> PersistentUiState {
+        PersistentUiState {
+            font_zoom: self.renderer.grid_renderer.font_zoom,
+            fullscreen: self.fullscreen,
+            transparency: Some(SETTINGS.get::<WindowSettings>().transparency),
+            fullscreen_monitor: Some(SETTINGS.get::<WindowSettings>().fullscreen_monitor)
+                .filter(|monitor| !monitor.is_empty()),
+        }
+    }
+
+    // Maximized windows span the whole width of their monitor, which is used as a stand in since
+    // winit doesn't report the maximized state directly
+    fn is_maximized(&self) -> bool {
+        let window = self.skia_renderer.window();
+        window
+            .current_monitor()
+            .map(|monitor| window.outer_size().width >= monitor.size().width)
+            .unwrap_or(false)
+    }
+
     fn handle_scale_factor_update(&mut self, scale_factor: f64) {
         self.renderer
             .grid_renderer
             .handle_scale_factor_update(scale_factor);
+        // The cells changed size, so the same window now fits a different grid
+        self.handle_new_grid_size(self.skia_renderer.window().inner_size());
+    }
+
+    // Monitors can differ in their refresh rate, which the frames are paced to while the window
+    // is focused, and in their scale factor, which winit reports separately
+    fn update_monitor(&mut self) {
+        let monitor = self.skia_renderer.window().current_monitor();
+        let name = monitor.as_ref().and_then(|monitor| monitor.name());
+        if self.monitor_name == name && self.monitor_name.is_some() {
+            return;
+        }
+        self.monitor_name = name;
+        if self.window_focused {
+            let window = self.skia_renderer.window();
+            set_monitor_refresh_rate(
+                monitor
+                    .as_ref()
+                    .and_then(|monitor| current_refresh_rate(window, monitor)),
+            );
+        }
     }
 }
 
-pub fn create_window(
-    batched_draw_command_receiver: Receiver<Vec<DrawCommand>>,
-    window_command_receiver: Receiver<WindowCommand>,
-    ui_command_sender: LoggingTx<UiCommand>,
-) {
-    let icon = {
-        let icon = load_from_memory(ICON).expect("Failed to parse icon data");
-        let (width, height) = icon.dimensions();
-        let mut rgba = Vec::with_capacity((width * height) as usize * 4);
-        for (_, _, pixel) in icon.pixels() {
-            rgba.extend_from_slice(&pixel.to_rgba().0);
-        }
-        Icon::from_rgba(rgba, width, height).expect("Failed to create icon object")
+// What the crash report lists as the last events. Only their kind is kept: keys could be part of
+// a password, and the fields of the others, like paths, text and sizes, would drown out the
+// order of events the report is for. The pointer events arrive many times a second, so they are
+// named without formatting them on every move.
+fn describe_event(event: &WindowEvent) -> Cow<'static, str> {
+    match event {
+        WindowEvent::KeyboardInput { .. } => "KeyboardInput".into(),
+        WindowEvent::CursorMoved { .. } => "CursorMoved".into(),
+        WindowEvent::MouseWheel { .. } => "MouseWheel".into(),
+        WindowEvent::AxisMotion { .. } => "AxisMotion".into(),
+        WindowEvent::Touch(_) => "Touch".into(),
+        WindowEvent::TouchpadPressure { .. } => "TouchpadPressure".into(),
+        WindowEvent::Moved(_) => "Moved".into(),
+        event => {
+            let description = format!("{:?}", event);
+            let kind_length = description
+                .find(|character: char| !character.is_alphanumeric())
+                .unwrap_or(description.len());
+            description[..kind_length].to_owned().into()
+        }
+    }
+}
+
+fn restore_window_position(window: &window::Window, geometry: &PersistentWindowGeometry) {
+    if let Some((x, y)) = geometry.position {
+        // The monitor the window was last on may have been unplugged since, in which case the
+        // window manager gets to place it instead
+        let monitor_available = match &geometry.monitor {
+            Some(name) => window
+                .available_monitors()
+                .any(|monitor| monitor.name().as_ref() == Some(name)),
+            None => true,
+        };
+
+        if monitor_available {
+            window.set_outer_position(PhysicalPosition::new(x, y));
+        }
+    }
+}
+
+// Shows the document icon of the current buffer in the titlebar, which can be dragged like the file
+// itself or command clicked for its path. Buffers without a file on disk clear it again.
+#[cfg(target_os = "macos")]
+fn set_represented_file(window: &window::Window, path: &str) {
+    use cocoa::{
+        base::{id, nil},
+        foundation::NSString,
     };
+    use glutin::platform::macos::WindowExtMacOS;
+    use objc::{msg_send, sel, sel_impl};
 
-    let event_loop = EventLoop::new();
+    let path = if std::path::Path::new(path).is_file() {
+        path
+    } else {
+        ""
+    };
+
+    unsafe {
+        let ns_window = window.ns_window() as id;
+        let path = NSString::alloc(nil).init_str(path);
+        let _: () = msg_send![ns_window, setRepresentedFilename: path];
+    }
+}
+
+fn load_icon() -> Icon {
+    let icon = load_from_memory(ICON).expect("Failed to parse icon data");
+    let (width, height) = icon.dimensions();
+    let mut rgba = Vec::with_capacity((width * height) as usize * 4);
+    for (_, _, pixel) in icon.pixels() {
+        rgba.extend_from_slice(&pixel.to_rgba().0);
+    }
+    Icon::from_rgba(rgba, width, height).expect("Failed to create icon object")
+}
 
-    let cmd_line_settings = SETTINGS.get::<CmdLineSettings>();
-    let winit_window_builder = window::WindowBuilder::new()
-        .with_title("Neovide")
-        .with_window_icon(Some(icon))
-        .with_maximized(cmd_line_settings.maximized)
-        .with_transparent(true)
-        .with_decorations(!cmd_line_settings.frameless);
-
-    #[cfg(target_os = "linux")]
-    let winit_window_builder = winit_window_builder
-        .with_app_id(SETTINGS.get::<CmdLineSettings>().wayland_app_id)
-        .with_class(
-            "neovide".to_string(),
-            SETTINGS.get::<CmdLineSettings>().x11_wm_class,
+impl WinitWindowWrapper {
+    pub fn new(
+        window_target: &EventLoopWindowTarget<()>,
+        instance: NeovimInstance,
+        primary: bool,
+    ) -> WinitWindowWrapper {
+        use_settings(instance.settings.clone());
+        let cmd_line_settings = SETTINGS.get::<CmdLineSettings>();
+        let saved_geometry = if primary {
+            try_to_load_last_window_geometry().unwrap_or_default()
+        } else {
+            PersistentWindowGeometry::default()
+        };
+        let frameless = { SETTINGS.get::<WindowSettings>().frameless };
+        let winit_window_builder = window::WindowBuilder::new()
+            .with_title("Neovide")
+            .with_window_icon(Some(load_icon()))
+            .with_maximized(primary && (cmd_line_settings.maximized || saved_geometry.maximized))
+            .with_transparent(true)
+            .with_decorations(!frameless);
+
+        // Shown once neovim has drawn, instead of flashing an empty window. On macos the window
+        // can also only join the others as a tab before it is shown for the first time.
+        let winit_window_builder = winit_window_builder.with_visible(false);
+
+        #[cfg(target_os = "linux")]
+        let winit_window_builder = winit_window_builder
+            .with_app_id(SETTINGS.get::<CmdLineSettings>().wayland_app_id)
+            .with_class(
+                "neovide".to_string(),
+                SETTINGS.get::<CmdLineSettings>().x11_wm_class,
+            );
+
+        let skia_renderer = build_skia_renderer(winit_window_builder, window_target);
+        mark_startup(StartupPhase::WindowCreated);
+        let window = skia_renderer.window();
+        #[cfg(target_os = "linux")]
+        if primary {
+            if let Some(display) = window.wayland_display() {
+                // SAFETY: the display is the event loop's connection, which all windows share
+                // and which is only closed with the event loop, after the clipboards were
+                // released on the way out of it
+                unsafe { crate::clipboard::use_wayland_display(display) };
+            }
+        }
+        if primary {
+            match cmd_line_settings.position {
+                Some((x, y)) => window.set_outer_position(PhysicalPosition::new(x, y)),
+                None => restore_window_position(window, &saved_geometry),
+            }
+        }
+
+        drop_target::install_drop_target(window);
+        let titlebar = Titlebar::new(window);
+        let accessibility = Accessibility::new(window);
+
+        let scale_factor = window.scale_factor();
+        let mut renderer = Renderer::new(instance.flushed_draw_commands, scale_factor);
+        if primary {
+            renderer
+                .grid_renderer
+                .set_font_zoom(SETTINGS.get::<PersistentUiState>().font_zoom);
+        }
+        let startup_background = cmd_line_settings
+            .background_color
+            .map(|color| Color::new(0xff00_0000 | color))
+            .or_else(|| theme::system_theme(window).map(theme::startup_background));
+        if let Some(color) = startup_background {
+            renderer.grid_renderer.set_startup_background(color);
+        }
+        let saved_inner_size = window.inner_size();
+
+        log::info!(
+            "window created (scale_factor: {:.4}, font_dimensions: {:?})",
+            scale_factor,
+            renderer.grid_renderer.font_dimensions,
         );
 
-    let windowed_context = ContextBuilder::new()
-        .with_pixel_format(24, 8)
-        .with_stencil_buffer(8)
-        .with_gl_profile(GlProfile::Core)
-        .with_vsync(false)
-        .with_srgb(cmd_line_settings.srgb)
-        .build_windowed(winit_window_builder, &event_loop)
-        .unwrap();
-    let windowed_context = unsafe { windowed_context.make_current().unwrap() };
-
-    let window = windowed_context.window();
-
-    let scale_factor = windowed_context.window().scale_factor();
-    let renderer = Renderer::new(batched_draw_command_receiver, scale_factor);
-    let saved_inner_size = window.inner_size();
-
-    let skia_renderer = SkiaRenderer::new(&windowed_context);
-
-    log::info!(
-        "window created (scale_factor: {:.4}, font_dimensions: {:?})",
-        scale_factor,
-        renderer.grid_renderer.font_dimensions,
-    );
-
-    let mut window_wrapper = GlutinWindowWrapper {
-        windowed_context,
-        skia_renderer,
-        renderer,
-        keyboard_manager: KeyboardManager::new(ui_command_sender.clone()),
-        mouse_manager: MouseManager::new(ui_command_sender.clone()),
-        title: String::from("Neovide"),
-        fullscreen: false,
-        saved_inner_size,
-        saved_grid_size: None,
-        ui_command_sender,
-        window_command_receiver,
-    };
+        let ui_command_sender = instance.ui_command_sender;
+        WinitWindowWrapper {
+            skia_renderer,
+            renderer,
+            keyboard_manager: KeyboardManager::new(ui_command_sender.clone()),
+            latency: None,
+            mouse_manager: MouseManager::new(ui_command_sender.clone()),
+            pinch_zoom: PinchZoom::new(),
+            presentation: Presentation::new(),
+            frameless_handler: FramelessWindowHandler::new(),
+            titlebar,
+            native_background: NativeBackground::new(),
+            blur: WindowBlur::new(),
+            accessibility,
+            title: String::from("Neovide"),
+            title_state: TitleState::default(),
+            discord_presence: DiscordPresence::new(),
+            title_format: String::new(),
+            theme: None,
+            follow_theme: false,
+            high_contrast_colorscheme: String::new(),
+            window_focused: false,
+            focused_external_window: None,
+            neovim_focused: false,
+            fullscreen: false,
+            #[cfg(target_os = "macos")]
+            simple_fullscreen: false,
+            last_fullscreen_setting: false,
+            always_on_top: false,
+            last_always_on_top_setting: false,
+            frameless,
+            hidden: false,
+            waiting_for_first_flush: true,
+            occluded: false,
+            ime_position: None,
+            created_at: Instant::now(),
+            content_insets: Rect::new_empty(),
+            saved_inner_size,
+            last_resize: None,
+            monitor_name: None,
+            saved_grid_size: None,
+            pending_screenshots: Vec::new(),
+            screen_recording: None,
+            external_windows: HashMap::new(),
+            requested_windows: Vec::new(),
+            restart_args: None,
+            neovim_args: instance.neovim_args,
+            quit_requested: None,
+            primary,
+            ui_command_sender,
+            window_command_receiver: instance.window_command_receiver,
+            running_tracker: instance.running_tracker,
+            _bridge: instance.bridge,
+            settings: instance.settings,
+        }
+    }
+
+    // Points `SETTINGS` at this window's settings before it handles anything
+    fn activate(&mut self) -> &mut Self {
+        use_settings(self.settings.clone());
+        self
+    }
+}
+
+pub fn create_window(instance: NeovimInstance) {
+    let event_loop = EventLoop::new();
+    #[cfg(target_os = "macos")]
+    {
+        open_documents::install_open_documents_handler();
+        dock_menu::install_dock_menu();
+        tabbing::install_new_tab_handler();
+    }
+    REDRAW_SCHEDULER.set_event_loop_proxy(event_loop.create_proxy());
+
+    let primary_window = WinitWindowWrapper::new(&event_loop, instance, true);
+    if SETTINGS.get::<CmdLineSettings>().tray {
+        tray::install_tray(primary_window.skia_renderer.window());
+    }
+    let mut windows: HashMap<WindowId, WinitWindowWrapper> = HashMap::new();
+    windows.insert(primary_window.skia_renderer.window().id(), primary_window);
 
     let mut previous_frame_start = Instant::now();
+    // Menu items act on the window that was focused last
+    let mut focused_window = None;
+
+    event_loop.run(move |e, window_target, control_flow| {
+        if let Event::WindowEvent { event, .. } = &e {
+            record_event(describe_event(event));
+        }
+
+        // Windows close once their neovim instance exits, and neovide once the last one did
+        windows.retain(|_, window_wrapper| {
+            window_wrapper.activate();
+            if window_wrapper.running_tracker.is_running() {
+                return true;
+            }
 
-    event_loop.run(move |e, _window_target, control_flow| {
-        if !RUNNING_TRACKER.is_running() {
-            maybe_save_window_size(window_wrapper.saved_grid_size);
-            std::process::exit(0);
+            if let Some(neovim_args) = window_wrapper.restart_args.take() {
+                window_wrapper.restart(start_neovim_instance(neovim_args));
+                return true;
+            }
+
+            if window_wrapper.primary {
+                maybe_save_window_geometry(window_wrapper.current_window_geometry());
+                maybe_save_ui_state(window_wrapper.current_ui_state());
+                // The tray icon belongs to the first window on windows
+                tray::remove_tray(window_wrapper.skia_renderer.window());
+            }
+            if let Some(exit_error) = window_wrapper.running_tracker.exit_error() {
+                show_error_dialog("Neovim exited unexpectedly", &exit_error);
+            }
+            // Skia frees its gpu resources on drop, which needs the window's own context
+            window_wrapper.close_external_windows();
+            window_wrapper.skia_renderer.make_current();
+            false
+        });
+        if windows.is_empty() {
+            #[cfg(target_os = "linux")]
+            crate::clipboard::release_wayland_display();
+            *control_flow = ControlFlow::Exit;
+            return;
         }
 
         let frame_start = Instant::now();
 
-        window_wrapper.handle_window_commands();
-        window_wrapper.synchronize_settings();
-        window_wrapper.handle_event(e);
+        for window_wrapper in windows.values_mut().map(WinitWindowWrapper::activate) {
+            window_wrapper.check_quit_timeout();
+            window_wrapper.handle_window_commands();
+            window_wrapper.handle_drops();
+            window_wrapper.synchronize_settings();
+            window_wrapper.update_theme(None);
+            window_wrapper.synchronize_external_windows(window_target);
+        }
+
+        for action in tray::take_tray_actions() {
+            match action {
+                MenuAction::ToggleWindow => {
+                    let hidden = windows
+                        .values()
+                        .any(|window_wrapper| !window_wrapper.hidden);
+                    for window_wrapper in windows.values_mut().map(WinitWindowWrapper::activate) {
+                        window_wrapper.set_hidden(hidden);
+                    }
+                }
+                MenuAction::Quit => {
+                    // Neovim might have to ask about unsaved changes
+                    for window_wrapper in windows.values_mut().map(WinitWindowWrapper::activate) {
+                        window_wrapper.set_hidden(false);
+                        window_wrapper.handle_close_requested();
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        for files in single_instance::take_open_requests() {
+            if let Some(window_wrapper) = focused_window
+                .and_then(|window_id| windows.get_mut(&window_id))
+                .or_else(|| windows.values_mut().next())
+                .map(WinitWindowWrapper::activate)
+            {
+                window_wrapper.handle_open_files(files);
+            }
+        }
+
+        #[cfg(target_os = "macos")]
+        {
+            if let Event::NewEvents(StartCause::Init) = e {
+                menu::install_menu();
+                pinch::install_pinch_monitor();
+            }
 
-        let refresh_rate = { SETTINGS.get::<WindowSettings>().refresh_rate as f32 };
+            let documents = open_documents::take_opened_documents();
+            if !documents.is_empty() {
+                if let Some(window_wrapper) = focused_window
+                    .and_then(|window_id| windows.get_mut(&window_id))
+                    .or_else(|| windows.values_mut().next())
+                    .map(WinitWindowWrapper::activate)
+                {
+                    window_wrapper.handle_open_documents(documents);
+                }
+            }
+
+            for pinch_event in pinch::take_pinch_events() {
+                if let Some(window_wrapper) = focused_window
+                    .and_then(|window_id| windows.get_mut(&window_id))
+                    .or_else(|| windows.values_mut().next())
+                    .map(WinitWindowWrapper::activate)
+                {
+                    window_wrapper.handle_pinch_event(pinch_event);
+                }
+            }
+
+            for action in menu::take_menu_actions() {
+                if action == MenuAction::Quit {
+                    for window_wrapper in windows.values_mut().map(WinitWindowWrapper::activate) {
+                        window_wrapper.handle_menu_action(action);
+                    }
+                } else if let Some(window_wrapper) = focused_window
+                    .and_then(|window_id| windows.get_mut(&window_id))
+                    .or_else(|| windows.values_mut().next())
+                    .map(WinitWindowWrapper::activate)
+                {
+                    window_wrapper.handle_menu_action(action);
+                }
+            }
+        }
+
+        if let Event::WindowEvent {
+            window_id,
+            event: WindowEvent::Focused(true),
+        } = e
+        {
+            focused_window = Some(window_id);
+        }
+
+        match e {
+            Event::WindowEvent { window_id, .. } | Event::RedrawRequested(window_id) => {
+                if let Some(window_wrapper) = windows.get_mut(&window_id) {
+                    window_wrapper.activate().handle_event(e);
+                } else if let Some(window_wrapper) = windows
+                    .values_mut()
+                    .find(|window_wrapper| window_wrapper.owns_external_window(window_id))
+                    .map(WinitWindowWrapper::activate)
+                {
+                    window_wrapper.handle_external_window_event(window_id, e);
+                }
+            }
+            Event::MainEventsCleared => {
+                for window_wrapper in windows.values_mut().map(WinitWindowWrapper::activate) {
+                    window_wrapper.handle_event(Event::MainEventsCleared);
+                }
+            }
+            Event::LoopDestroyed => {
+                for window_wrapper in windows.values_mut().map(WinitWindowWrapper::activate) {
+                    window_wrapper.handle_event(Event::LoopDestroyed);
+                }
+            }
+            _ => {}
+        }
+
+        let requested_windows: Vec<Option<String>> = windows
+            .values_mut()
+            .flat_map(|window_wrapper| window_wrapper.requested_windows.drain(..))
+            .collect();
+        for path in requested_windows {
+            let instance = start_neovim_instance(path.into_iter().collect());
+            let window_wrapper = WinitWindowWrapper::new(window_target, instance, false);
+            windows.insert(window_wrapper.skia_renderer.window().id(), window_wrapper);
+        }
+
+        let saves_power = SETTINGS.get::<RendererSettings>().saves_power();
+        let refresh_rate = SETTINGS
+            .get::<WindowSettings>()
+            .target_refresh_rate(saves_power);
         let expected_frame_length_seconds = 1.0 / refresh_rate;
         let frame_duration = Duration::from_secs_f32(expected_frame_length_seconds);
 
+        // Flushes from neovim wake the loop early, so a frame is drawn as soon as the previous
+        // one is at least a frame old rather than waiting for the next timer tick
         if frame_start - previous_frame_start > frame_duration {
-            let dt = previous_frame_start.elapsed().as_secs_f32();
-            window_wrapper.draw_frame(dt);
+            // Animations advance by the time that actually passed, so they take as long at 144hz
+            // or with dropped frames as at 60hz
+            let dt = previous_frame_start
+                .elapsed()
+                .as_secs_f32()
+                .min(MAX_FRAME_LENGTH);
+            // The redraw scheduler is shared, so when any window needs a frame all of them draw
+            let should_draw = REDRAW_SCHEDULER.should_draw();
+            for window_wrapper in windows.values_mut().map(WinitWindowWrapper::activate) {
+                window_wrapper.draw_frame(dt, should_draw);
+            }
             previous_frame_start = frame_start;
         }
 
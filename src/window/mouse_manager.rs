@@ -1,17 +1,24 @@
-use std::cmp::Ordering;
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    time::{Duration, Instant},
+};
 
 use glutin::{
     self,
     dpi::PhysicalPosition,
-    event::{ElementState, Event, MouseButton, MouseScrollDelta, WindowEvent},
-    PossiblyCurrent, WindowedContext,
+    event::{ElementState, Event, MouseButton, MouseScrollDelta, Touch, TouchPhase, WindowEvent},
+    window::{CursorIcon, Window},
 };
-use skia_safe::Rect;
+use skia_safe::{Point, Rect};
 
+use super::context_menu;
 use super::keyboard_manager::KeyboardManager;
+use super::menu_items::MenuAction;
 use crate::bridge::{SerialCommand, UiCommand};
 use crate::channel_utils::LoggingTx;
-use crate::renderer::{Renderer, WindowDrawDetails};
+use crate::redraw_scheduler::REDRAW_SCHEDULER;
+use crate::renderer::{open_url, Link, Renderer, ScrollbarDetails, WindowDrawDetails};
 use crate::settings::SETTINGS;
 use crate::window::WindowSettings;
 
@@ -42,7 +49,100 @@ fn to_grid_coords(
     )
 }
 
-fn mouse_button_to_button_text(mouse_button: &MouseButton) -> Option<String> {
+// Kinetic scrolling keeps this fraction of its speed after a second, and stops once it gets
+// slower than the minimum speed in pixels per second
+const KINETIC_SCROLL_FRICTION: f32 = 0.05;
+const MIN_KINETIC_SCROLL_SPEED: f32 = 20.0;
+// Fingers that rested on the trackpad this long before they were lifted don't fling it
+const TRACKPAD_FLING_TIMEOUT: Duration = Duration::from_millis(100);
+
+// Presses of the same button on the same cell within neovim's mousetime option count as double,
+// triple and quadruple clicks. This is its default, used until neovim reports the real value
+const DEFAULT_MOUSETIME: Duration = Duration::from_millis(500);
+const MAX_CLICK_COUNT: u8 = 4;
+
+#[derive(Default)]
+struct ClickTracker {
+    last_click: Option<(String, u64, PhysicalPosition<u32>, Instant)>,
+    count: u8,
+}
+
+impl ClickTracker {
+    fn click(
+        &mut self,
+        button: &str,
+        grid_id: u64,
+        position: PhysicalPosition<u32>,
+        time: Instant,
+        mousetime: Duration,
+    ) -> u8 {
+        let repeated = match &self.last_click {
+            Some((last_button, last_grid_id, last_position, last_time)) => {
+                last_button == button
+                    && *last_grid_id == grid_id
+                    && *last_position == position
+                    && time.duration_since(*last_time) <= mousetime
+            }
+            None => false,
+        };
+
+        self.count = if repeated && self.count < MAX_CLICK_COUNT {
+            self.count + 1
+        } else {
+            1
+        };
+        self.last_click = Some((button.to_owned(), grid_id, position, time));
+        self.count
+    }
+}
+
+// Keys go through neovim's key notation, so everything but letters, digits and spaces is escaped
+// to keep a `<` in the text from being read as a key
+fn lua_string_literal(text: &str) -> String {
+    let mut literal = String::from("\"");
+    for byte in text.bytes() {
+        if byte.is_ascii_alphanumeric() || byte == b' ' {
+            literal.push(byte as char);
+        } else {
+            // Always three digits, so a digit after it isn't read as part of the escape
+            literal.push_str(&format!("\\{:03}", byte));
+        }
+    }
+    literal.push('"');
+    literal
+}
+
+// Neovim takes the click count as a modifier, like <2-LeftMouse>
+fn click_count_modifier(count: u8) -> String {
+    if count > 1 {
+        format!("{}-", count)
+    } else {
+        String::new()
+    }
+}
+
+struct TouchTrace {
+    start_time: Instant,
+    start: PhysicalPosition<f32>,
+    last_time: Instant,
+    last: PhysicalPosition<f32>,
+    left_deadzone: bool,
+}
+
+impl TouchTrace {
+    fn new(location: PhysicalPosition<f32>) -> TouchTrace {
+        let now = Instant::now();
+        TouchTrace {
+            start_time: now,
+            start: location,
+            last_time: now,
+            last: location,
+            left_deadzone: false,
+        }
+    }
+}
+
+pub fn mouse_button_to_button_text(mouse_button: &MouseButton) -> Option<String> {
     match mouse_button {
         MouseButton::Left => Some("left".to_owned()),
         MouseButton::Right => Some("right".to_owned()),
@@ -58,15 +158,50 @@ pub struct MouseManager {
     drag_position: PhysicalPosition<u32>,
 
     has_moved: bool,
+    pixel_position: PhysicalPosition<f32>,
     position: PhysicalPosition<u32>,
     relative_position: PhysicalPosition<u32>,
 
     scroll_position: PhysicalPosition<f32>,
+    // Touchpads report precise deltas, which are shown as partial lines until they add up
+    pixel_scrolling: bool,
+    // Vertical scrolling since the last frame, which bounces grids that can't scroll any further
+    pending_overscroll: Option<(u64, f32)>,
+    // Lines scrolled over the documentation of the completion menu since the last frame
+    pending_popup_menu_info_scroll: f32,
 
     window_details_under_mouse: Option<WindowDrawDetails>,
+    scrollbar_drag: Option<ScrollbarDetails>,
+    click_tracker: ClickTracker,
+    hovered_link: Option<(u64, Link)>,
+    // The release of a click which opened a link isn't sent to neovim either
+    link_clicked: bool,
+
+    touch_traces: HashMap<u64, TouchTrace>,
+    touch_dragging: bool,
+    touch_scrolling: bool,
+    // In pixels per second
+    scroll_velocity: PhysicalPosition<f32>,
+    // How fast the fingers moved over the trackpad when it last reported a movement, and when
+    trackpad_velocity: PhysicalPosition<f32>,
+    last_trackpad_scroll: Option<Instant>,
 
     mouse_hidden: bool,
+    pointer_in_window: bool,
+    // In window coordinates, unlike the pixel position which is relative to the grid
+    pointer_location: PhysicalPosition<f32>,
+    pointer_icon: Option<CursorIcon>,
+    pub busy: bool,
     pub enabled: bool,
+    // Neovim's mousehide option
+    pub mousehide: bool,
+    // Neovim's mousemoveevent option
+    pub mouse_move_event: bool,
+    // Neovim's mousetime option
+    pub mousetime: Duration,
+    // Set by plugins that handle right clicks themselves, which then go to neovim even when the
+    // context menu is enabled
+    pub context_menu_suppressed: bool,
 }
 
 impl MouseManager {
@@ -75,13 +210,35 @@ impl MouseManager {
             command_sender,
             dragging: None,
             has_moved: false,
+            pixel_position: PhysicalPosition::new(0.0, 0.0),
             position: PhysicalPosition::new(0, 0),
             relative_position: PhysicalPosition::new(0, 0),
             drag_position: PhysicalPosition::new(0, 0),
             scroll_position: PhysicalPosition::new(0.0, 0.0),
+            pixel_scrolling: false,
+            pending_overscroll: None,
+            pending_popup_menu_info_scroll: 0.0,
             window_details_under_mouse: None,
+            scrollbar_drag: None,
+            click_tracker: ClickTracker::default(),
+            hovered_link: None,
+            link_clicked: false,
+            touch_traces: HashMap::new(),
+            touch_dragging: false,
+            touch_scrolling: false,
+            scroll_velocity: PhysicalPosition::new(0.0, 0.0),
+            trackpad_velocity: PhysicalPosition::new(0.0, 0.0),
+            last_trackpad_scroll: None,
             mouse_hidden: false,
+            pointer_in_window: false,
+            pointer_location: PhysicalPosition::new(0.0, 0.0),
+            pointer_icon: None,
+            busy: false,
             enabled: true,
+            mousehide: false,
+            mouse_move_event: false,
+            mousetime: DEFAULT_MOUSETIME,
+            context_menu_suppressed: false,
         }
     }
 
@@ -91,14 +248,20 @@ impl MouseManager {
         y: i32,
         keyboard_manager: &KeyboardManager,
         renderer: &Renderer,
-        windowed_context: &WindowedContext<PossiblyCurrent>,
+        window: &Window,
     ) {
-        let size = windowed_context.window().inner_size();
+        let size = window.inner_size();
         if x < 0 || x as u32 >= size.width || y < 0 || y as u32 >= size.height {
             return;
         }
 
         let position: PhysicalPosition<f32> = PhysicalPosition::new(x as f32, y as f32);
+        self.pixel_position = position;
+
+        if let Some(scrollbar) = &self.scrollbar_drag {
+            self.send_scrollbar_jump(scrollbar, position.y);
+            return;
+        }
 
         // If dragging, the relevant window (the one which we send all commands to) is the one
         // which the mouse drag started on. Otherwise its the top rendered window
@@ -180,18 +343,215 @@ impl MouseManager {
             } else {
                 // otherwise, update the window_id_under_mouse to match the one selected
                 self.window_details_under_mouse = Some(relevant_window_details.clone());
+
+                // Moves within a cell don't change anything neovim can see
+                if self.mouse_move_event && self.enabled && has_moved {
+                    self.command_sender
+                        .send(
+                            SerialCommand::MouseMove {
+                                grid_id: relevant_window_details.id,
+                                position: self.drag_position.into(),
+                                modifier_string: keyboard_manager.format_modifier_string(true),
+                            }
+                            .into(),
+                        )
+                        .ok();
+                }
+            }
+
+            if self.dragging.is_none() {
+                self.hovered_link = renderer
+                    .link_at(
+                        relevant_window_details.id,
+                        self.relative_position.x as u64,
+                        self.relative_position.y as u64,
+                    )
+                    .map(|link| (relevant_window_details.id, link));
             }
 
             self.has_moved = self.dragging.is_some() && (self.has_moved || has_moved);
         }
     }
 
+    fn send_scrollbar_jump(&self, scrollbar: &ScrollbarDetails, y: f32) {
+        self.command_sender
+            .send(
+                SerialCommand::ScrollbarJump {
+                    window_handle: scrollbar.window_handle,
+                    line: scrollbar.line_at(y),
+                }
+                .into(),
+            )
+            .ok();
+    }
+
+    // Clicks on a scrollbar are handled by neovide instead of being forwarded as mouse input.
+    // Returns whether the transition was consumed by a scrollbar.
+    fn handle_scrollbar_transition(
+        &mut self,
+        mouse_button: &MouseButton,
+        down: bool,
+        renderer: &Renderer,
+    ) -> bool {
+        if *mouse_button != MouseButton::Left {
+            return false;
+        }
+
+        if !down {
+            return self.scrollbar_drag.take().is_some();
+        }
+
+        let point = Point::new(self.pixel_position.x, self.pixel_position.y);
+        let scrollbar = renderer
+            .window_regions
+            .iter()
+            .filter(|details| details.region.contains(point))
+            .last()
+            .and_then(|details| {
+                // Both the scrollbar and the minimap jump through the buffer the same way
+                details
+                    .scrollbar
+                    .iter()
+                    .chain(details.minimap.iter())
+                    .find(|scrollbar| scrollbar.contains(point))
+                    .cloned()
+            });
+
+        if let Some(scrollbar) = scrollbar {
+            self.send_scrollbar_jump(&scrollbar, point.y);
+            self.scrollbar_drag = Some(scrollbar);
+            true
+        } else {
+            false
+        }
+    }
+
+    // Returns whether the right click was used for the context menu, in which case neovim never
+    // hears about it
+    fn handle_context_menu(&mut self, down: bool, window: &Window) -> bool {
+        if !SETTINGS.get::<WindowSettings>().right_click_menu
+            || self.context_menu_suppressed
+            || !context_menu::is_supported()
+        {
+            return false;
+        }
+
+        if down {
+            if let Some(command) = context_menu::show(window).and_then(MenuAction::ui_command) {
+                self.command_sender.send(command.into()).ok();
+            }
+        }
+        true
+    }
+
+    // Opens the link under the pointer on ctrl or cmd click instead of sending the click
+    fn handle_link_click(&mut self, down: bool, keyboard_manager: &KeyboardManager) -> bool {
+        if !down {
+            return std::mem::replace(&mut self.link_clicked, false);
+        }
+
+        match &self.hovered_link {
+            Some((_, link)) if keyboard_manager.link_modifier_pressed() => {
+                open_url(&link.url);
+                self.link_clicked = true;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // Pastes the text the lua expression evaluates to at the cell under the pointer. The mouse is
+    // lent to neovim for a left click there, and the paste follows it through the input queue,
+    // so it isn't applied before the click moved the cursor.
+    fn paste_at_pointer(&self, text_expression: &str) {
+        if let Some(details) = &self.window_details_under_mouse {
+            self.command_sender
+                .send(
+                    SerialCommand::Keyboard(
+                        "<Cmd>let g:neovide_saved_mouse = &mouse | set mouse=a<CR>".to_owned(),
+                    )
+                    .into(),
+                )
+                .ok();
+            for action in &["press", "release"] {
+                self.command_sender
+                    .send(
+                        SerialCommand::MouseButton {
+                            button: "left".to_owned(),
+                            action: (*action).to_owned(),
+                            grid_id: details.id,
+                            position: self.relative_position.into(),
+                            modifier_string: String::new(),
+                        }
+                        .into(),
+                    )
+                    .ok();
+            }
+            self.command_sender
+                .send(
+                    SerialCommand::Keyboard(
+                        "<Cmd>let &mouse = g:neovide_saved_mouse<CR>".to_owned(),
+                    )
+                    .into(),
+                )
+                .ok();
+        }
+        self.command_sender
+            .send(
+                SerialCommand::Keyboard(format!(
+                    "<Cmd>lua vim.api.nvim_paste({}, true, -1)<CR>",
+                    text_expression
+                ))
+                .into(),
+            )
+            .ok();
+    }
+
+    /// Inserts text dropped from another application where it was dropped.
+    pub fn handle_text_drop(
+        &mut self,
+        text: &str,
+        position: PhysicalPosition<f64>,
+        keyboard_manager: &KeyboardManager,
+        renderer: &Renderer,
+        window: &Window,
+    ) {
+        self.handle_pointer_motion(
+            (position.x - renderer.content_origin.x as f64) as i32,
+            (position.y - renderer.content_origin.y as f64) as i32,
+            keyboard_manager,
+            renderer,
+            window,
+        );
+        self.paste_at_pointer(&lua_string_literal(text));
+    }
+
     fn handle_pointer_transition(
         &mut self,
         mouse_button: &MouseButton,
         down: bool,
         keyboard_manager: &KeyboardManager,
+        renderer: &Renderer,
     ) {
+        if self.enabled && self.handle_scrollbar_transition(mouse_button, down, renderer) {
+            return;
+        }
+
+        if *mouse_button == MouseButton::Left && self.handle_link_click(down, keyboard_manager) {
+            return;
+        }
+
+        // Neovim pastes the primary selection on middle clicks by itself, but only while it has
+        // the mouse. Otherwise it is pasted where the click was, just like terminals do.
+        if cfg!(target_os = "linux")
+            && !self.enabled
+            && down
+            && *mouse_button == MouseButton::Middle
+        {
+            self.paste_at_pointer("vim.fn.getreg('*')");
+            return;
+        }
+
         // For some reason pointer down is handled differently from pointer up and drag.
         // Floating windows: relative coordinates are great.
         // Non floating windows: rather than global coordinates, relative are needed
@@ -210,6 +570,20 @@ impl MouseManager {
                         self.relative_position
                     };
 
+                    let click_count = if down {
+                        self.click_tracker.click(
+                            &button_text,
+                            details.id,
+                            position,
+                            Instant::now(),
+                            self.mousetime,
+                        )
+                    } else {
+                        1
+                    };
+                    let modifier_string = click_count_modifier(click_count)
+                        + &keyboard_manager.format_modifier_string(true);
+
                     self.command_sender
                         .send(
                             SerialCommand::MouseButton {
@@ -217,7 +591,7 @@ impl MouseManager {
                                 action,
                                 grid_id: details.id,
                                 position: position.into(),
-                                modifier_string: keyboard_manager.format_modifier_string(true),
+                                modifier_string,
                             }
                             .into(),
                         )
@@ -242,6 +616,13 @@ impl MouseManager {
             return;
         }
 
+        if let Some(details) = &self.window_details_under_mouse {
+            self.pending_overscroll = match self.pending_overscroll {
+                Some((grid_id, lines)) if grid_id == details.id => Some((grid_id, lines + y)),
+                _ => Some((details.id, y)),
+            };
+        }
+
         let previous_y = self.scroll_position.y as i64;
         self.scroll_position.y += y;
         let new_y = self.scroll_position.y as i64;
@@ -310,29 +691,335 @@ impl MouseManager {
         );
     }
 
+    fn touch_pointer_motion(
+        &mut self,
+        location: PhysicalPosition<f32>,
+        keyboard_manager: &KeyboardManager,
+        renderer: &Renderer,
+        window: &Window,
+    ) {
+        self.handle_pointer_motion(
+            location.x as i32,
+            location.y as i32,
+            keyboard_manager,
+            renderer,
+            window,
+        );
+    }
+
+    // A single finger taps to move the cursor, long presses for a right click and drags to
+    // select. Two fingers scroll and keep scrolling for a bit after they are lifted.
+    fn handle_touch(
+        &mut self,
+        touch: &Touch,
+        keyboard_manager: &KeyboardManager,
+        renderer: &Renderer,
+        window: &Window,
+    ) {
+        let settings = SETTINGS.get::<WindowSettings>();
+        let location = PhysicalPosition::new(
+            touch.location.x as f32 - renderer.content_origin.x,
+            touch.location.y as f32 - renderer.content_origin.y,
+        );
+
+        match touch.phase {
+            TouchPhase::Started => {
+                // Touching the screen again catches a kinetic scroll
+                self.scroll_velocity = PhysicalPosition::new(0.0, 0.0);
+                if self.touch_traces.is_empty() {
+                    self.touch_pointer_motion(location, keyboard_manager, renderer, window);
+                }
+                self.touch_traces
+                    .insert(touch.id, TouchTrace::new(location));
+            }
+            TouchPhase::Moved => {
+                let finger_count = self.touch_traces.len();
+                let trace = match self.touch_traces.get_mut(&touch.id) {
+                    Some(trace) => trace,
+                    None => return,
+                };
+
+                let deadzone = settings.touch_deadzone * window.scale_factor() as f32;
+                let distance = (location.x - trace.start.x).hypot(location.y - trace.start.y);
+                trace.left_deadzone = trace.left_deadzone || distance > deadzone;
+
+                let delta =
+                    PhysicalPosition::new(location.x - trace.last.x, location.y - trace.last.y);
+                let elapsed = trace.last_time.elapsed().as_secs_f32().max(0.001);
+                trace.last = location;
+                trace.last_time = Instant::now();
+
+                if finger_count == 1 && trace.left_deadzone && !self.touch_scrolling {
+                    if !self.touch_dragging {
+                        let start = trace.start;
+                        self.touch_pointer_motion(start, keyboard_manager, renderer, window);
+                        self.handle_pointer_transition(
+                            &MouseButton::Left,
+                            true,
+                            keyboard_manager,
+                            renderer,
+                        );
+                        self.touch_dragging = true;
+                    }
+                    self.touch_pointer_motion(location, keyboard_manager, renderer, window);
+                } else if finger_count == 2 && !self.touch_dragging {
+                    // Each finger reports its own movement, so half of it goes to the average
+                    let delta = PhysicalPosition::new(delta.x / 2.0, delta.y / 2.0);
+                    self.touch_scrolling = true;
+                    self.handle_pixel_scroll(
+                        renderer.grid_renderer.font_dimensions.into(),
+                        (delta.x, delta.y),
+                        keyboard_manager,
+                    );
+                    self.scroll_velocity =
+                        PhysicalPosition::new(delta.x * 2.0 / elapsed, delta.y * 2.0 / elapsed);
+                }
+            }
+            TouchPhase::Ended | TouchPhase::Cancelled => {
+                let trace = match self.touch_traces.remove(&touch.id) {
+                    Some(trace) => trace,
+                    None => return,
+                };
+                let lifted = self.touch_traces.is_empty();
+
+                if self.touch_dragging {
+                    if lifted {
+                        self.handle_pointer_transition(
+                            &MouseButton::Left,
+                            false,
+                            keyboard_manager,
+                            renderer,
+                        );
+                        self.touch_dragging = false;
+                    }
+                } else if self.touch_scrolling {
+                    if lifted {
+                        self.touch_scrolling = false;
+                    }
+                } else if lifted && !trace.left_deadzone && touch.phase == TouchPhase::Ended {
+                    let button = if trace.start_time.elapsed().as_secs_f32()
+                        >= settings.touch_long_press_time
+                    {
+                        MouseButton::Right
+                    } else {
+                        MouseButton::Left
+                    };
+                    self.touch_pointer_motion(trace.start, keyboard_manager, renderer, window);
+                    self.handle_pointer_transition(&button, true, keyboard_manager, renderer);
+                    self.handle_pointer_transition(&button, false, keyboard_manager, renderer);
+                }
+            }
+        }
+    }
+
+    // Macos sends the momentum of a trackpad flick as more scroll events after the fingers are
+    // lifted. Elsewhere, like on wayland, the scrolling keeps going the same way touch screens
+    // do, at the speed of the fingers just before.
+    fn handle_trackpad_phase(&mut self, phase: TouchPhase, delta: PhysicalPosition<f32>) {
+        match phase {
+            TouchPhase::Started => {
+                self.trackpad_velocity = PhysicalPosition::new(0.0, 0.0);
+                self.last_trackpad_scroll = Some(Instant::now());
+            }
+            TouchPhase::Moved => {
+                let now = Instant::now();
+                if let Some(last_scroll) = self.last_trackpad_scroll {
+                    let elapsed = (now - last_scroll).as_secs_f32().max(0.001);
+                    self.trackpad_velocity =
+                        PhysicalPosition::new(delta.x / elapsed, delta.y / elapsed);
+                }
+                self.last_trackpad_scroll = Some(now);
+            }
+            TouchPhase::Ended => {
+                let flung = self
+                    .last_trackpad_scroll
+                    .take()
+                    .map(|last_scroll| last_scroll.elapsed() < TRACKPAD_FLING_TIMEOUT)
+                    .unwrap_or(false);
+                if flung && !cfg!(target_os = "macos") {
+                    self.scroll_velocity = self.trackpad_velocity;
+                }
+            }
+            TouchPhase::Cancelled => self.last_trackpad_scroll = None,
+        }
+    }
+
+    /// Keeps scrolling after a two finger swipe, slowing down until it stops.
+    pub fn animate_kinetic_scroll(
+        &mut self,
+        dt: f32,
+        keyboard_manager: &KeyboardManager,
+        renderer: &Renderer,
+    ) {
+        if self.touch_scrolling {
+            return;
+        }
+        let (distance, velocity) = match kinetic_scroll_step(self.scroll_velocity, dt) {
+            Some(step) => step,
+            None => {
+                self.scroll_velocity = PhysicalPosition::new(0.0, 0.0);
+                return;
+            }
+        };
+
+        self.handle_pixel_scroll(
+            renderer.grid_renderer.font_dimensions.into(),
+            distance,
+            keyboard_manager,
+        );
+        self.scroll_velocity = velocity;
+        // Nothing else asks for frames while the grid coasts
+        REDRAW_SCHEDULER.queue_next_frame();
+    }
+
+    // An I-beam over text, a hand over links, and the default arrow over scrollbars and the rest
+    // of the window
+    fn pointer_icon_at(&self, renderer: &Renderer) -> CursorIcon {
+        if self.busy {
+            return CursorIcon::Wait;
+        }
+        if self.hovered_link.is_some() {
+            return CursorIcon::Hand;
+        }
+
+        let point = Point::new(
+            self.pointer_location.x - renderer.content_origin.x,
+            self.pointer_location.y - renderer.content_origin.y,
+        );
+        let details = renderer
+            .window_regions
+            .iter()
+            .filter(|details| details.region.contains(point))
+            .last();
+        match details {
+            Some(details)
+                if !details
+                    .scrollbar
+                    .iter()
+                    .chain(details.minimap.iter())
+                    .any(|scrollbar| scrollbar.contains(point)) =>
+            {
+                CursorIcon::Text
+            }
+            _ => CursorIcon::Default,
+        }
+    }
+
+    /// Sets the pointer shape for what is under the pointer. Resize edges of frameless windows
+    /// set their own shape, which is left alone.
+    pub fn update_pointer_icon(
+        &mut self,
+        over_resize_edge: bool,
+        renderer: &Renderer,
+        window: &Window,
+    ) {
+        if over_resize_edge {
+            self.pointer_icon = None;
+            return;
+        }
+
+        let pointer_icon = self.pointer_icon_at(renderer);
+        if self.pointer_icon != Some(pointer_icon) {
+            window.set_cursor_icon(pointer_icon);
+            self.pointer_icon = Some(pointer_icon);
+        }
+    }
+
+    fn show_mouse(&mut self, window: &Window) {
+        if self.mouse_hidden {
+            window.set_cursor_visible(true);
+            self.mouse_hidden = false;
+        }
+    }
+
+    /// The link under the pointer and the grid it is in.
+    pub fn hovered_link(&self) -> Option<&(u64, Link)> {
+        self.hovered_link.as_ref()
+    }
+
+    // The documentation of the selected completion scrolls by itself instead of the grid under it
+    fn scroll_popup_menu_info(&mut self, lines: f32, renderer: &Renderer) -> bool {
+        let point = Point::new(
+            self.pointer_location.x - renderer.content_origin.x,
+            self.pointer_location.y - renderer.content_origin.y,
+        );
+        let over_info = renderer
+            .popup_menu_info_region()
+            .map_or(false, |region| region.contains(point));
+        if over_info {
+            self.pending_popup_menu_info_scroll += lines;
+        }
+        over_info
+    }
+
+    /// The lines scrolled over the documentation of the completion menu since the last call.
+    pub fn take_popup_menu_info_scroll(&mut self) -> f32 {
+        std::mem::take(&mut self.pending_popup_menu_info_scroll)
+    }
+
+    /// The vertical scrolling since the last call and the grid it happened in.
+    pub fn take_overscroll(&mut self) -> Option<(u64, f32)> {
+        self.pending_overscroll.take()
+    }
+
+    /// The grid being scrolled on a touchpad, with the part of a line scrolled that wasn't sent
+    /// to neovim yet.
+    pub fn pixel_scroll_offset(&self) -> Option<(u64, f32)> {
+        if !self.pixel_scrolling || !self.enabled {
+            return None;
+        }
+
+        self.window_details_under_mouse
+            .as_ref()
+            .map(|details| (details.id, self.scroll_position.y.fract()))
+    }
+
     pub fn handle_event(
         &mut self,
         event: &Event<()>,
         keyboard_manager: &KeyboardManager,
         renderer: &Renderer,
-        windowed_context: &WindowedContext<PossiblyCurrent>,
+        window: &Window,
     ) {
+        // Any input catches a kinetic scroll, a trackpad flick picks it up again when it ends
+        if let Event::WindowEvent {
+            event:
+                WindowEvent::KeyboardInput { .. }
+                | WindowEvent::MouseInput { .. }
+                | WindowEvent::MouseWheel { .. },
+            ..
+        } = event
+        {
+            self.scroll_velocity = PhysicalPosition::new(0.0, 0.0);
+        }
+
         match event {
             Event::WindowEvent {
                 event: WindowEvent::CursorMoved { position, .. },
                 ..
             } => {
+                self.pointer_location = PhysicalPosition::new(position.x as f32, position.y as f32);
                 self.handle_pointer_motion(
-                    position.x as i32,
-                    position.y as i32,
+                    (position.x - renderer.content_origin.x as f64) as i32,
+                    (position.y - renderer.content_origin.y as f64) as i32,
                     keyboard_manager,
                     renderer,
-                    windowed_context,
+                    window,
                 );
-                if self.mouse_hidden {
-                    windowed_context.window().set_cursor_visible(true);
-                    self.mouse_hidden = false;
-                }
+                self.pointer_in_window = true;
+                self.show_mouse(window);
+            }
+            Event::WindowEvent {
+                event: WindowEvent::CursorEntered { .. },
+                ..
+            } => self.pointer_in_window = true,
+            Event::WindowEvent {
+                event: WindowEvent::CursorLeft { .. },
+                ..
+            } => {
+                self.pointer_in_window = false;
+                self.hovered_link = None;
+                self.show_mouse(window);
             }
             Event::WindowEvent {
                 event:
@@ -341,27 +1028,69 @@ impl MouseManager {
                         ..
                     },
                 ..
-            } => self.handle_line_scroll(*x, *y, keyboard_manager),
+            } => {
+                self.pixel_scrolling = false;
+                if self.scroll_popup_menu_info(*y, renderer) {
+                    return;
+                }
+                // Each notch of the wheel sends this many wheel events, which neovim scrolls by
+                // its mousescroll option
+                let multiplier = SETTINGS.get::<WindowSettings>().scroll_multiplier;
+                self.handle_line_scroll(*x * multiplier, *y * multiplier, keyboard_manager);
+            }
             Event::WindowEvent {
                 event:
                     WindowEvent::MouseWheel {
                         delta: MouseScrollDelta::PixelDelta(delta),
+                        phase,
                         ..
                     },
                 ..
-            } => self.handle_pixel_scroll(
-                renderer.grid_renderer.font_dimensions.into(),
-                (delta.x as f32, delta.y as f32),
-                keyboard_manager,
-            ),
+            } => {
+                let font_height = renderer.grid_renderer.font_dimensions.height as f32;
+                if self.scroll_popup_menu_info(delta.y as f32 / font_height, renderer) {
+                    return;
+                }
+                self.handle_pixel_scroll(
+                    renderer.grid_renderer.font_dimensions.into(),
+                    (delta.x as f32, delta.y as f32),
+                    keyboard_manager,
+                );
+                self.pixel_scrolling = true;
+                self.handle_trackpad_phase(*phase, delta.cast());
+                // Once the fingers are lifted the partial line is dropped, so the grid settles
+                // on the lines neovim shows
+                if *phase == TouchPhase::Ended || *phase == TouchPhase::Cancelled {
+                    self.scroll_position.y = self.scroll_position.y.trunc();
+                    self.pixel_scrolling = false;
+                }
+            }
             Event::WindowEvent {
                 event: WindowEvent::MouseInput { button, state, .. },
                 ..
-            } => self.handle_pointer_transition(
-                button,
-                state == &ElementState::Pressed,
-                keyboard_manager,
-            ),
+            } => {
+                let down = state == &ElementState::Pressed;
+                if *button == MouseButton::Right && self.handle_context_menu(down, window) {
+                    return;
+                }
+
+                // Windows open, close and move under a pointer that stays still, so the grid
+                // under it is looked up again before a click is sent
+                if down && self.dragging.is_none() {
+                    self.handle_pointer_motion(
+                        self.pixel_position.x as i32,
+                        self.pixel_position.y as i32,
+                        keyboard_manager,
+                        renderer,
+                        window,
+                    );
+                }
+                self.handle_pointer_transition(button, down, keyboard_manager, renderer);
+            }
+            Event::WindowEvent {
+                event: WindowEvent::Touch(touch),
+                ..
+            } => self.handle_touch(touch, keyboard_manager, renderer, window),
             Event::WindowEvent {
                 event:
                     WindowEvent::KeyboardInput {
@@ -369,10 +1098,13 @@ impl MouseManager {
                     },
                 ..
             } => {
-                if key_event.state == ElementState::Pressed {
+                // Only moving the pointer within the window shows it again, so it isn't hidden
+                // while outside of the window
+                if key_event.state == ElementState::Pressed && self.pointer_in_window {
                     let window_settings = SETTINGS.get::<WindowSettings>();
-                    if window_settings.hide_mouse_when_typing && !self.mouse_hidden {
-                        windowed_context.window().set_cursor_visible(false);
+                    let hide_mouse = window_settings.hide_mouse_when_typing || self.mousehide;
+                    if hide_mouse && !self.mouse_hidden {
+                        window.set_cursor_visible(false);
                         self.mouse_hidden = true;
                     }
                 }
@@ -381,3 +1113,103 @@ impl MouseManager {
         }
     }
 }
+
+// How far a kinetic scroll goes within a frame and how fast it is afterwards, until it is too slow
+// to go on. The distance is the integral of the slowing velocity over the frame rather than the
+// velocity times its length, so flicks go equally far at any frame rate.
+fn kinetic_scroll_step(
+    velocity: PhysicalPosition<f32>,
+    dt: f32,
+) -> Option<((f32, f32), PhysicalPosition<f32>)> {
+    if velocity.x.hypot(velocity.y) < MIN_KINETIC_SCROLL_SPEED {
+        return None;
+    }
+    let friction = KINETIC_SCROLL_FRICTION.powf(dt);
+    let distance = (friction - 1.0) / KINETIC_SCROLL_FRICTION.ln();
+    Some((
+        (velocity.x * distance, velocity.y * distance),
+        PhysicalPosition::new(velocity.x * friction, velocity.y * friction),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kinetic_scroll_stops() {
+        let mut velocity = PhysicalPosition::new(0.0, 3000.0);
+        let mut scrolled = 0.0;
+        let mut frames = 0;
+        while let Some(((_, distance), next_velocity)) = kinetic_scroll_step(velocity, 1.0 / 60.0) {
+            assert!(next_velocity.y < velocity.y);
+            scrolled += distance;
+            velocity = next_velocity;
+            frames += 1;
+            assert!(frames < 600, "Kinetic scrolling never stopped");
+        }
+        assert!(scrolled > 0.0);
+        assert!(kinetic_scroll_step(PhysicalPosition::new(0.0, 0.0), 1.0 / 60.0).is_none());
+    }
+
+    #[test]
+    fn test_click_count() {
+        let mut click_tracker = ClickTracker::default();
+        let start = Instant::now();
+        let position = PhysicalPosition::new(3, 4);
+        let later = |millis| start + Duration::from_millis(millis);
+        let mousetime = DEFAULT_MOUSETIME;
+
+        assert_eq!(
+            click_tracker.click("left", 1, position, start, mousetime),
+            1
+        );
+        assert_eq!(
+            click_tracker.click("left", 1, position, later(200), mousetime),
+            2
+        );
+        assert_eq!(
+            click_tracker.click("left", 1, position, later(400), mousetime),
+            3
+        );
+        // Too slow to count as another click
+        assert_eq!(
+            click_tracker.click("left", 1, position, later(1000), mousetime),
+            1
+        );
+        // A different cell starts over
+        assert_eq!(
+            click_tracker.click(
+                "left",
+                1,
+                PhysicalPosition::new(4, 4),
+                later(1100),
+                mousetime
+            ),
+            1
+        );
+        // A longer mousetime still counts the slow click
+        assert_eq!(
+            click_tracker.click(
+                "left",
+                1,
+                PhysicalPosition::new(4, 4),
+                later(1900),
+                Duration::from_millis(1000)
+            ),
+            2
+        );
+        assert_eq!(click_count_modifier(2), "2-");
+        assert_eq!(click_count_modifier(1), "");
+    }
+
+    #[test]
+    fn test_lua_string_literal() {
+        assert_eq!(lua_string_literal("a b"), "\"a b\"");
+        assert_eq!(
+            lua_string_literal("<CR>\"é"),
+            "\"\\060CR\\062\\034\\195\\169\""
+        );
+        assert_eq!(lua_string_literal("\n5"), "\"\\0105\"");
+    }
+}
@@ -0,0 +1,85 @@
+use glutin::window::Window;
+use skia_safe::Color;
+
+/// Keeps the background of the os window itself in the default background color. The frames
+/// neovide draws already cover the padding and the part of a cell left over after a resize, but
+/// the os shows the window background before the first frame, while the window is resized faster
+/// than frames are drawn, and behind the native titlebar when it is made transparent.
+pub struct NativeBackground {
+    color: Option<Color>,
+}
+
+impl NativeBackground {
+    pub fn new() -> NativeBackground {
+        NativeBackground { color: None }
+    }
+
+    /// Follows the default background, which changes with every `default_colors_set`. The
+    /// alpha is the window transparency.
+    #[cfg_attr(not(any(windows, target_os = "macos")), allow(unused_variables))]
+    pub fn update(&mut self, window: &Window, color: Color) {
+        if self.color == Some(color) {
+            return;
+        }
+        self.color = Some(color);
+
+        #[cfg(target_os = "macos")]
+        macos::set_background(window, color);
+        #[cfg(windows)]
+        windows::set_background(window, color);
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod macos {
+    use cocoa::base::id;
+    use glutin::{platform::macos::WindowExtMacOS, window::Window};
+    use objc::{class, msg_send, sel, sel_impl};
+    use skia_safe::Color;
+
+    pub fn set_background(window: &Window, color: Color) {
+        let component = |value: u8| value as f64 / 255.0;
+        unsafe {
+            let ns_color: id = msg_send![class!(NSColor),
+                colorWithSRGBRed: component(color.r())
+                green: component(color.g())
+                blue: component(color.b())
+                alpha: component(color.a())
+            ];
+            let _: () = msg_send![window.ns_window() as id, setBackgroundColor: ns_color];
+        }
+    }
+}
+
+#[cfg(windows)]
+mod windows {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use glutin::{platform::windows::WindowExtWindows, window::Window};
+    use skia_safe::Color;
+    use winapi::{
+        shared::windef::{HBRUSH, HWND},
+        um::{
+            wingdi::{CreateSolidBrush, DeleteObject, RGB},
+            winuser::{InvalidateRect, SetClassLongPtrW, GCLP_HBRBACKGROUND},
+        },
+    };
+
+    // The brush currently set on the window class, which is deleted once it is replaced
+    static BRUSH: AtomicUsize = AtomicUsize::new(0);
+
+    pub fn set_background(window: &Window, color: Color) {
+        // Brushes are always opaque. A transparent window is composited by dwm from what
+        // neovide draws, so the class brush only matters before the first frame.
+        let hwnd = window.hwnd() as HWND;
+        unsafe {
+            let brush = CreateSolidBrush(RGB(color.r(), color.g(), color.b()));
+            SetClassLongPtrW(hwnd, GCLP_HBRBACKGROUND, brush as isize);
+            let previous = BRUSH.swap(brush as usize, Ordering::Relaxed) as HBRUSH;
+            if !previous.is_null() {
+                DeleteObject(previous as _);
+            }
+            InvalidateRect(hwnd, std::ptr::null(), 0);
+        }
+    }
+}
@@ -0,0 +1,62 @@
+use glutin::window::Window;
+
+/// Whether none of the window can be seen: minimized, on another virtual desktop, or covered
+/// entirely by other windows where the os tells. Frames drawn then would never show.
+#[cfg_attr(not(any(windows, target_os = "macos")), allow(unused_variables))]
+pub fn is_occluded(window: &Window) -> bool {
+    #[cfg(windows)]
+    return windows::is_occluded(window);
+    #[cfg(target_os = "macos")]
+    return macos::is_occluded(window);
+    // X11 and wayland don't report it through winit
+    #[cfg(not(any(windows, target_os = "macos")))]
+    false
+}
+
+#[cfg(windows)]
+mod windows {
+    use std::ffi::c_void;
+    use std::mem::size_of;
+
+    use glutin::{platform::windows::WindowExtWindows, window::Window};
+    use winapi::{
+        shared::{windef::HWND, winerror::SUCCEEDED},
+        um::{dwmapi::DwmGetWindowAttribute, winuser::IsIconic},
+    };
+
+    // Set for windows on another virtual desktop, among others
+    const DWMWA_CLOAKED: u32 = 14;
+
+    pub fn is_occluded(window: &Window) -> bool {
+        let hwnd = window.hwnd() as HWND;
+        unsafe {
+            if IsIconic(hwnd) != 0 {
+                return true;
+            }
+            let mut cloaked: u32 = 0;
+            let result = DwmGetWindowAttribute(
+                hwnd,
+                DWMWA_CLOAKED,
+                &mut cloaked as *mut u32 as *mut c_void,
+                size_of::<u32>() as u32,
+            );
+            SUCCEEDED(result) && cloaked != 0
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod macos {
+    use cocoa::base::id;
+    use glutin::{platform::macos::WindowExtMacOS, window::Window};
+    use objc::{msg_send, sel, sel_impl};
+
+    // NSWindowOcclusionStateVisible, cleared while the window is minimized, covered, on another
+    // space or the screen is off
+    const OCCLUSION_STATE_VISIBLE: u64 = 1 << 1;
+
+    pub fn is_occluded(window: &Window) -> bool {
+        let state: u64 = unsafe { msg_send![window.ns_window() as id, occlusionState] };
+        state & OCCLUSION_STATE_VISIBLE == 0
+    }
+}
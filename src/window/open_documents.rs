@@ -0,0 +1,75 @@
+use std::ffi::CStr;
+use std::mem;
+
+use cocoa::{
+    appkit::NSApp,
+    base::{id, nil},
+    foundation::NSArray,
+};
+use log::warn;
+use objc::{
+    msg_send,
+    runtime::{class_addMethod, object_getClass, Class, Imp, Object, Sel},
+    sel, sel_impl,
+};
+use parking_lot::Mutex;
+
+use crate::redraw_scheduler::REDRAW_SCHEDULER;
+
+// NSApplicationDelegateReplySuccess
+const REPLY_SUCCESS: u64 = 0;
+
+lazy_static! {
+    static ref OPENED_DOCUMENTS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+}
+
+/// Takes the files and folders Finder or the Dock asked to open since the last call.
+pub fn take_opened_documents() -> Vec<String> {
+    OPENED_DOCUMENTS.lock().drain(..).collect()
+}
+
+unsafe fn string_from_nsstring(string: id) -> String {
+    let bytes: *const std::os::raw::c_char = msg_send![string, UTF8String];
+    CStr::from_ptr(bytes).to_string_lossy().into_owned()
+}
+
+extern "C" fn open_files(_this: &Object, _selector: Sel, application: id, files: id) {
+    unsafe {
+        let mut opened = OPENED_DOCUMENTS.lock();
+        for index in 0..files.count() {
+            opened.push(string_from_nsstring(files.objectAtIndex(index)));
+        }
+        let _: () = msg_send![application, replyToOpenOrPrint: REPLY_SUCCESS];
+    }
+    // Apple events don't produce window events, so the event loop has to be woken up
+    REDRAW_SCHEDULER.queue_flushed_frame();
+}
+
+/// Adds a method to winit's application delegate, for the delegate methods it leaves out.
+/// The types are the Objective-C encoding of the return value and the arguments.
+pub unsafe fn add_app_delegate_method(selector: Sel, method: Imp, types: &[u8]) {
+    let delegate: id = msg_send![NSApp(), delegate];
+    if delegate == nil {
+        warn!("Could not find the application delegate");
+        return;
+    }
+
+    let class = object_getClass(delegate) as *mut Class;
+    let types = CStr::from_bytes_with_nul(types).unwrap();
+    class_addMethod(class, selector, method, types.as_ptr());
+}
+
+/// Teaches winit's application delegate to accept the files double clicked in Finder or dropped
+/// on the Dock icon. Has to be called before the event loop runs, since the files a launch was
+/// meant to open are handed over before the application finished launching.
+pub fn install_open_documents_handler() {
+    let open_files: extern "C" fn(&Object, Sel, id, id) = open_files;
+    unsafe {
+        // Returns void, and takes self, the selector, the application and the array of paths
+        add_app_delegate_method(
+            sel!(application:openFiles:),
+            mem::transmute::<_, Imp>(open_files),
+            b"v@:@@\0",
+        );
+    }
+}
@@ -0,0 +1,150 @@
+#[cfg(target_os = "macos")]
+use parking_lot::Mutex;
+
+// Keeps pinching from shrinking the text to nothing or blowing it up past the screen
+const MIN_PINCH_SCALE: f32 = 0.25;
+const MAX_PINCH_SCALE: f32 = 4.0;
+
+#[cfg(target_os = "macos")]
+lazy_static! {
+    static ref PINCH_EVENTS: Mutex<Vec<PinchEvent>> = Mutex::new(Vec::new());
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PinchEvent {
+    // The change in magnification since the last event, relative to the current scale
+    Changed(f32),
+    Ended,
+}
+
+/// Tracks a pinch gesture. While pinching, the grid is only scaled when drawn so the zoom stays
+/// smooth. Once the fingers are lifted the font size snaps to the nearest whole size.
+pub struct PinchZoom {
+    scale: f32,
+}
+
+impl PinchZoom {
+    pub fn new() -> PinchZoom {
+        PinchZoom { scale: 1.0 }
+    }
+
+    /// Returns the scale to draw with during the gesture, which is back at 1.0 once it ended.
+    pub fn handle_event(&mut self, event: PinchEvent) -> f32 {
+        match event {
+            PinchEvent::Changed(magnification) => {
+                self.scale = (self.scale * (1.0 + magnification))
+                    .max(MIN_PINCH_SCALE)
+                    .min(MAX_PINCH_SCALE);
+                self.scale
+            }
+            PinchEvent::Ended => 1.0,
+        }
+    }
+
+    /// The font zoom to switch to when the gesture ends, rounded so the font lands on a whole
+    /// point size.
+    pub fn finish(&mut self, font_size: f32, font_zoom: f32) -> f32 {
+        let scale = self.scale;
+        self.scale = 1.0;
+        snapped_font_zoom(font_size, font_zoom * scale)
+    }
+}
+
+fn snapped_font_zoom(font_size: f32, font_zoom: f32) -> f32 {
+    if font_size <= 0.0 {
+        return font_zoom;
+    }
+    let snapped_size = (font_size * font_zoom).round().max(1.0);
+    snapped_size / font_size
+}
+
+/// The font zoom that makes the font a whole point size larger or smaller, for zooming in steps
+/// rather than with a gesture.
+pub fn stepped_font_zoom(font_size: f32, font_zoom: f32, step: f32) -> f32 {
+    if font_size <= 0.0 {
+        return font_zoom;
+    }
+    let stepped_size = ((font_size * font_zoom).round() + step).max(1.0);
+    stepped_size / font_size
+}
+
+/// Takes the pinch events received since the last call.
+#[cfg(target_os = "macos")]
+pub fn take_pinch_events() -> Vec<PinchEvent> {
+    PINCH_EVENTS.lock().drain(..).collect()
+}
+
+/// Winit doesn't report trackpad gestures, so magnify events are picked up with an application
+/// wide event monitor instead. Other platforms don't have gesture events to listen to yet.
+#[cfg(target_os = "macos")]
+pub fn install_pinch_monitor() {
+    use block::ConcreteBlock;
+    use cocoa::base::id;
+    use objc::{class, msg_send, sel, sel_impl};
+
+    use crate::redraw_scheduler::REDRAW_SCHEDULER;
+
+    const NS_EVENT_MASK_MAGNIFY: u64 = 1 << 30;
+    const NS_EVENT_PHASE_ENDED: u64 = 8;
+    const NS_EVENT_PHASE_CANCELLED: u64 = 16;
+
+    let handler = ConcreteBlock::new(|ns_event: id| -> id {
+        let pinch_event = unsafe {
+            let phase: u64 = msg_send![ns_event, phase];
+            if phase & (NS_EVENT_PHASE_ENDED | NS_EVENT_PHASE_CANCELLED) != 0 {
+                PinchEvent::Ended
+            } else {
+                let magnification: f64 = msg_send![ns_event, magnification];
+                PinchEvent::Changed(magnification as f32)
+            }
+        };
+        PINCH_EVENTS.lock().push(pinch_event);
+        REDRAW_SCHEDULER.queue_flushed_frame();
+        ns_event
+    })
+    .copy();
+
+    unsafe {
+        let _: id = msg_send![
+            class!(NSEvent),
+            addLocalMonitorForEventsMatchingMask: NS_EVENT_MASK_MAGNIFY
+            handler: &*handler
+        ];
+    }
+    // The monitor lives as long as the app does
+    std::mem::forget(handler);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pinch_scale_is_limited() {
+        let mut pinch_zoom = PinchZoom::new();
+        assert_eq!(pinch_zoom.handle_event(PinchEvent::Changed(0.5)), 1.5);
+        for _ in 0..10 {
+            pinch_zoom.handle_event(PinchEvent::Changed(1.0));
+        }
+        assert_eq!(pinch_zoom.handle_event(PinchEvent::Changed(0.0)), 4.0);
+        assert_eq!(pinch_zoom.handle_event(PinchEvent::Ended), 1.0);
+    }
+
+    #[test]
+    fn test_stepped_font_zoom() {
+        assert!((stepped_font_zoom(14.0, 1.0, 1.0) * 14.0 - 15.0).abs() < 0.001);
+        // The font never shrinks below a single point
+        assert!((stepped_font_zoom(14.0, 1.0 / 14.0, -1.0) * 14.0 - 1.0).abs() < 0.001);
+    }
+
+    #[test]
+    fn test_finish_snaps_to_whole_sizes() {
+        let mut pinch_zoom = PinchZoom::new();
+        pinch_zoom.handle_event(PinchEvent::Changed(0.3));
+        // 14 * 1.3 = 18.2 rounds to 18
+        let font_zoom = pinch_zoom.finish(14.0, 1.0);
+        assert!((font_zoom * 14.0 - 18.0).abs() < 0.001);
+        // The gesture is reset afterwards
+        assert!((pinch_zoom.finish(14.0, font_zoom) - font_zoom).abs() < 0.001);
+    }
+}
@@ -0,0 +1,186 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Once;
+use std::thread;
+use std::time::Duration;
+
+use crate::settings::{report_invalid_setting, FromValue, Value};
+
+// The power source rarely changes, and finding out can mean starting a process, so it is only
+// checked this often on a thread of its own
+const BATTERY_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Frames are paced to at most this many per second while saving power.
+pub const POWER_SAVING_REFRESH_RATE: f32 = 30.0;
+
+static ON_BATTERY: AtomicBool = AtomicBool::new(false);
+static START_BATTERY_CHECKS: Once = Once::new();
+
+/// How `g:neovide_power_saving` is set: always saving power with "on", never with "off", and
+/// with "auto" while running on battery.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PowerSavingMode {
+    Auto,
+    On,
+    Off,
+}
+
+impl PowerSavingMode {
+    pub fn saves_power(self) -> bool {
+        match self {
+            PowerSavingMode::On => true,
+            PowerSavingMode::Off => false,
+            PowerSavingMode::Auto => on_battery(),
+        }
+    }
+}
+
+impl FromValue for PowerSavingMode {
+    fn from_value(&mut self, value: Value) {
+        *self = match value.as_str() {
+            Some("auto") => PowerSavingMode::Auto,
+            Some("on") => PowerSavingMode::On,
+            Some("off") => PowerSavingMode::Off,
+            _ => {
+                report_invalid_setting(format!(
+                    "Ignored neovide_power_saving = {}: it has to be auto, on or off",
+                    value
+                ));
+                return;
+            }
+        };
+    }
+}
+
+impl From<PowerSavingMode> for Value {
+    fn from(mode: PowerSavingMode) -> Self {
+        match mode {
+            PowerSavingMode::Auto => Value::from("auto"),
+            PowerSavingMode::On => Value::from("on"),
+            PowerSavingMode::Off => Value::from("off"),
+        }
+    }
+}
+
+fn on_battery() -> bool {
+    START_BATTERY_CHECKS.call_once(|| {
+        thread::spawn(|| loop {
+            ON_BATTERY.store(system_on_battery(), Ordering::Relaxed);
+            thread::sleep(BATTERY_CHECK_INTERVAL);
+        });
+    });
+    ON_BATTERY.load(Ordering::Relaxed)
+}
+
+#[cfg(windows)]
+fn system_on_battery() -> bool {
+    use winapi::um::winbase::{GetSystemPowerStatus, SYSTEM_POWER_STATUS};
+
+    let mut status: SYSTEM_POWER_STATUS = unsafe { std::mem::zeroed() };
+    let succeeded = unsafe { GetSystemPowerStatus(&mut status) } != 0;
+    // 0 is offline, 1 online and 255 unknown
+    succeeded && status.ACLineStatus == 0
+}
+
+#[cfg(target_os = "macos")]
+fn system_on_battery() -> bool {
+    std::process::Command::new("pmset")
+        .args(&["-g", "batt"])
+        .output()
+        .ok()
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map_or(false, |output| output.contains("'Battery Power'"))
+}
+
+// The kind of each power supply together with whether a charger is plugged in or how a battery
+// is doing, as the kernel lists them
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn supplies_on_battery(supplies: &[(String, String)]) -> bool {
+    let mut chargers = supplies
+        .iter()
+        .filter(|(kind, _)| kind == "Mains")
+        .peekable();
+    if chargers.peek().is_some() {
+        return chargers.all(|(_, online)| online != "1");
+    }
+    supplies
+        .iter()
+        .any(|(kind, status)| kind == "Battery" && status == "Discharging")
+}
+
+#[cfg(target_os = "linux")]
+fn system_on_battery() -> bool {
+    let read = |path: std::path::PathBuf| {
+        std::fs::read_to_string(path)
+            .map(|contents| contents.trim().to_owned())
+            .unwrap_or_default()
+    };
+    let supplies: Vec<(String, String)> = std::fs::read_dir("/sys/class/power_supply")
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| {
+                    let path = entry.path();
+                    let kind = read(path.join("type"));
+                    let state = if kind == "Mains" {
+                        read(path.join("online"))
+                    } else {
+                        read(path.join("status"))
+                    };
+                    (kind, state)
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    supplies_on_battery(&supplies)
+}
+
+#[cfg(not(any(windows, target_os = "macos", target_os = "linux")))]
+fn system_on_battery() -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn supplies(supplies: &[(&str, &str)]) -> Vec<(String, String)> {
+        supplies
+            .iter()
+            .map(|(kind, state)| (kind.to_string(), state.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_supplies_on_battery() {
+        assert!(supplies_on_battery(&supplies(&[
+            ("Mains", "0"),
+            ("Battery", "Discharging")
+        ])));
+        assert!(!supplies_on_battery(&supplies(&[
+            ("Mains", "1"),
+            ("Battery", "Charging")
+        ])));
+        // Some laptops only list their battery
+        assert!(supplies_on_battery(&supplies(&[(
+            "Battery",
+            "Discharging"
+        )])));
+        // Desktops have no supply at all
+        assert!(!supplies_on_battery(&[]));
+    }
+
+    #[test]
+    fn test_manual_modes() {
+        assert!(PowerSavingMode::On.saves_power());
+        assert!(!PowerSavingMode::Off.saves_power());
+    }
+
+    #[test]
+    fn test_invalid_modes_are_ignored() {
+        let mut mode = PowerSavingMode::On;
+        mode.from_value(Value::from("auto"));
+        assert_eq!(mode, PowerSavingMode::Auto);
+        mode.from_value(Value::from("sometimes"));
+        assert_eq!(mode, PowerSavingMode::Auto);
+    }
+}
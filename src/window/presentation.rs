@@ -0,0 +1,63 @@
+/// Presentation mode: the font is zoomed to `g:neovide_presentation_zoom` and, with
+/// `g:neovide_presentation_columns` set, the grid is narrowed to that many columns in the middle
+/// of the window. Neovim hides the tabline, statusline and scrollbars itself before turning it on.
+pub struct Presentation {
+    // The zoom to go back to, while presenting
+    saved_zoom: Option<f32>,
+}
+
+impl Presentation {
+    pub fn new() -> Presentation {
+        Presentation { saved_zoom: None }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.saved_zoom.is_some()
+    }
+
+    /// Turns presentation mode on or off, returning the font zoom to switch to if it changed.
+    pub fn set_active(&mut self, active: bool, font_zoom: f32, zoom: f32) -> Option<f32> {
+        match (active, self.saved_zoom) {
+            (true, None) => {
+                self.saved_zoom = Some(font_zoom);
+                Some(zoom)
+            }
+            (false, Some(saved_zoom)) => {
+                self.saved_zoom = None;
+                Some(saved_zoom)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// The padding added on both sides to center `columns` cells in the width left between the
+/// padding. Nothing is added without a column count or when they don't fit.
+pub fn centering_inset(available_width: f32, columns: u64, cell_width: f32) -> f32 {
+    if columns == 0 {
+        return 0.0;
+    }
+    ((available_width - columns as f32 * cell_width) / 2.0).max(0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_presentation_restores_zoom() {
+        let mut presentation = Presentation::new();
+        assert_eq!(presentation.set_active(true, 1.2, 1.5), Some(1.5));
+        assert!(presentation.is_active());
+        assert_eq!(presentation.set_active(true, 1.5, 1.5), None);
+        assert_eq!(presentation.set_active(false, 1.8, 1.5), Some(1.2));
+        assert_eq!(presentation.set_active(false, 1.2, 1.5), None);
+    }
+
+    #[test]
+    fn test_centering_inset() {
+        assert_eq!(centering_inset(1000.0, 80, 10.0), 100.0);
+        assert_eq!(centering_inset(500.0, 80, 10.0), 0.0);
+        assert_eq!(centering_inset(1000.0, 0, 10.0), 0.0);
+    }
+}
@@ -0,0 +1,151 @@
+use glutin::window::Window;
+
+// Values outside of 0 to 1 clear the progress, so a plugin doesn't have to special case a
+// finished build
+fn clamped_progress(progress: Option<f64>) -> Option<f64> {
+    progress.filter(|progress| (0.0..=1.0).contains(progress))
+}
+
+/// Shows the progress, from 0 to 1, on the taskbar button of the window on windows and on the
+/// Dock icon on macos. None takes the progress bar away again.
+#[cfg_attr(not(any(windows, target_os = "macos")), allow(unused_variables))]
+pub fn set_progress(window: &Window, progress: Option<f64>) {
+    let progress = clamped_progress(progress);
+    #[cfg(windows)]
+    windows::set_progress(window, progress);
+    #[cfg(target_os = "macos")]
+    macos::set_progress(progress);
+}
+
+#[cfg(windows)]
+mod windows {
+    use std::ffi::c_void;
+    use std::ptr::null_mut;
+
+    use glutin::{platform::windows::WindowExtWindows, window::Window};
+    use log::warn;
+    use winapi::{
+        shared::{guiddef::GUID, windef::HWND, winerror::FAILED},
+        um::{
+            combaseapi::{CoCreateInstance, CLSCTX_INPROC_SERVER},
+            shobjidl_core::{ITaskbarList3, TBPF_NOPROGRESS, TBPF_NORMAL},
+        },
+        Interface,
+    };
+
+    const CLSID_TASKBAR_LIST: GUID = GUID {
+        Data1: 0x56fd_f344,
+        Data2: 0xfd6d,
+        Data3: 0x11d0,
+        Data4: [0x95, 0x8a, 0x00, 0x60, 0x97, 0xc9, 0xa0, 0x90],
+    };
+    // The taskbar only takes whole steps
+    const STEPS: u64 = 1000;
+
+    pub fn set_progress(window: &Window, progress: Option<f64>) {
+        let hwnd = window.hwnd() as HWND;
+        unsafe {
+            // Com is already set up on the main thread by winit for drag and drop
+            let mut object: *mut c_void = null_mut();
+            let result = CoCreateInstance(
+                &CLSID_TASKBAR_LIST,
+                null_mut(),
+                CLSCTX_INPROC_SERVER,
+                &ITaskbarList3::uuidof(),
+                &mut object,
+            );
+            if FAILED(result) || object.is_null() {
+                warn!("Could not reach the taskbar: {:x}", result);
+                return;
+            }
+            let taskbar = &*(object as *mut ITaskbarList3);
+            taskbar.HrInit();
+            match progress {
+                Some(progress) => {
+                    taskbar.SetProgressState(hwnd, TBPF_NORMAL);
+                    taskbar.SetProgressValue(hwnd, (progress * STEPS as f64) as u64, STEPS);
+                }
+                None => {
+                    taskbar.SetProgressState(hwnd, TBPF_NOPROGRESS);
+                }
+            }
+            taskbar.Release();
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod macos {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use cocoa::{
+        appkit::NSApp,
+        base::{id, nil, NO},
+        foundation::{NSPoint, NSRect, NSSize},
+    };
+    use objc::{class, msg_send, sel, sel_impl};
+
+    // The bar drawn over the bottom of the Dock icon while there is progress to show
+    static INDICATOR: AtomicUsize = AtomicUsize::new(0);
+    const BAR_HEIGHT: f64 = 20.0;
+    // NSProgressIndicatorStyleBar
+    const STYLE_BAR: u64 = 0;
+
+    // The Dock icon is replaced by a view of the icon with the bar on top of it
+    unsafe fn build_indicator(dock_tile: id) -> id {
+        let size: NSSize = msg_send![dock_tile, size];
+        let frame = NSRect::new(NSPoint::new(0.0, 0.0), size);
+        let icon_view: id = msg_send![class!(NSImageView), alloc];
+        let icon_view: id = msg_send![icon_view, initWithFrame: frame];
+        let icon: id = msg_send![NSApp(), applicationIconImage];
+        let _: () = msg_send![icon_view, setImage: icon];
+
+        let bar_frame = NSRect::new(NSPoint::new(0.0, 0.0), NSSize::new(size.width, BAR_HEIGHT));
+        let indicator: id = msg_send![class!(NSProgressIndicator), alloc];
+        let indicator: id = msg_send![indicator, initWithFrame: bar_frame];
+        let _: () = msg_send![indicator, setStyle: STYLE_BAR];
+        let _: () = msg_send![indicator, setIndeterminate: NO];
+        let _: () = msg_send![indicator, setMinValue: 0.0f64];
+        let _: () = msg_send![indicator, setMaxValue: 1.0f64];
+        let _: () = msg_send![icon_view, addSubview: indicator];
+        let _: () = msg_send![indicator, release];
+        // Kept alive by the Dock tile from here on, until the content view is taken away
+        let _: () = msg_send![dock_tile, setContentView: icon_view];
+        let _: () = msg_send![icon_view, release];
+        indicator
+    }
+
+    pub fn set_progress(progress: Option<f64>) {
+        unsafe {
+            let dock_tile: id = msg_send![NSApp(), dockTile];
+            match progress {
+                Some(progress) => {
+                    let mut indicator = INDICATOR.load(Ordering::Relaxed) as id;
+                    if indicator == nil {
+                        indicator = build_indicator(dock_tile);
+                        INDICATOR.store(indicator as usize, Ordering::Relaxed);
+                    }
+                    let _: () = msg_send![indicator, setDoubleValue: progress];
+                }
+                None => {
+                    let _: () = msg_send![dock_tile, setContentView: nil];
+                    INDICATOR.store(0, Ordering::Relaxed);
+                }
+            }
+            let _: () = msg_send![dock_tile, display];
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clamped_progress() {
+        assert_eq!(clamped_progress(Some(0.5)), Some(0.5));
+        assert_eq!(clamped_progress(Some(1.5)), None);
+        assert_eq!(clamped_progress(Some(-1.0)), None);
+        assert_eq!(clamped_progress(None), None);
+    }
+}
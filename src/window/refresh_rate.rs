@@ -0,0 +1,140 @@
+use glutin::{monitor::MonitorHandle, window::Window};
+
+// The rate a mode refreshes at, from how fast pixels are sent and how many there are per frame
+// including the blanking. Interlaced modes send half the lines per refresh, doubled ones twice.
+#[cfg_attr(any(windows, target_os = "macos"), allow(dead_code))]
+fn mode_refresh_rate(
+    dot_clock: u64,
+    h_total: u64,
+    v_total: u64,
+    interlaced: bool,
+    doubled: bool,
+) -> Option<u32> {
+    let mut v_total = v_total;
+    if doubled {
+        v_total *= 2;
+    }
+    if interlaced {
+        v_total /= 2;
+    }
+    let pixels = h_total * v_total;
+    if pixels == 0 {
+        return None;
+    }
+    Some(((dot_clock + pixels / 2) / pixels) as u32).filter(|rate| *rate > 0)
+}
+
+/// The refresh rate of the video mode the monitor is in, when the platform tells. Winit itself
+/// only lists the modes a monitor supports without saying which one is in use.
+#[cfg(windows)]
+pub fn current_refresh_rate(_window: &Window, monitor: &MonitorHandle) -> Option<u32> {
+    use std::{ffi::OsStr, mem::zeroed, os::windows::ffi::OsStrExt};
+
+    use glutin::platform::windows::MonitorHandleExtWindows;
+    use winapi::um::{
+        wingdi::DEVMODEW,
+        winuser::{EnumDisplaySettingsW, ENUM_CURRENT_SETTINGS},
+    };
+
+    let device_name: Vec<u16> = OsStr::new(&monitor.native_id())
+        .encode_wide()
+        .chain(Some(0))
+        .collect();
+    unsafe {
+        let mut mode: DEVMODEW = zeroed();
+        mode.dmSize = std::mem::size_of::<DEVMODEW>() as u16;
+        if EnumDisplaySettingsW(device_name.as_ptr(), ENUM_CURRENT_SETTINGS, &mut mode) == 0 {
+            return None;
+        }
+        // 0 and 1 both stand for the default of the hardware, whatever that is
+        Some(mode.dmDisplayFrequency).filter(|rate| *rate > 1)
+    }
+}
+
+#[cfg(target_os = "macos")]
+pub fn current_refresh_rate(_window: &Window, monitor: &MonitorHandle) -> Option<u32> {
+    use std::os::raw::c_void;
+
+    use glutin::platform::macos::MonitorHandleExtMacOS;
+
+    #[link(name = "CoreGraphics", kind = "framework")]
+    extern "C" {
+        fn CGDisplayCopyDisplayMode(display: u32) -> *mut c_void;
+        fn CGDisplayModeGetRefreshRate(mode: *mut c_void) -> f64;
+        fn CGDisplayModeRelease(mode: *mut c_void);
+    }
+
+    unsafe {
+        let mode = CGDisplayCopyDisplayMode(monitor.native_id());
+        if mode.is_null() {
+            return None;
+        }
+        let rate = CGDisplayModeGetRefreshRate(mode);
+        CGDisplayModeRelease(mode);
+        // Built in displays without a fixed rate report 0
+        Some(rate.round() as u32).filter(|rate| *rate > 0)
+    }
+}
+
+// Winit's monitors on x11 are the randr crtcs, whose mode is looked up on the window's screen.
+// Wayland only tells the current mode to the windows on it, which winit doesn't pass on.
+#[cfg(not(any(windows, target_os = "macos")))]
+pub fn current_refresh_rate(window: &Window, monitor: &MonitorHandle) -> Option<u32> {
+    use glutin::platform::unix::{MonitorHandleExtUnix, WindowExtUnix};
+    use x11_dl::{xlib, xrandr};
+
+    let display = window.xlib_display()? as *mut xlib::Display;
+    let window = window.xlib_window()?;
+    let xrandr = xrandr::Xrandr::open().ok()?;
+    unsafe {
+        let resources = (xrandr.XRRGetScreenResourcesCurrent)(display, window);
+        if resources.is_null() {
+            return None;
+        }
+        let crtc = (xrandr.XRRGetCrtcInfo)(display, resources, monitor.native_id() as _);
+        let rate = if crtc.is_null() {
+            None
+        } else {
+            let mode_id = (*crtc).mode;
+            (xrandr.XRRFreeCrtcInfo)(crtc);
+            let modes = std::slice::from_raw_parts((*resources).modes, (*resources).nmode as usize);
+            modes
+                .iter()
+                .find(|mode| mode.id == mode_id)
+                .and_then(|mode| {
+                    mode_refresh_rate(
+                        mode.dotClock as u64,
+                        mode.hTotal as u64,
+                        mode.vTotal as u64,
+                        mode.modeFlags & xrandr::RR_Interlace as xrandr::XRRModeFlags != 0,
+                        mode.modeFlags & xrandr::RR_DoubleScan as xrandr::XRRModeFlags != 0,
+                    )
+                })
+        };
+        (xrandr.XRRFreeScreenResources)(resources);
+        rate
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mode_refresh_rate() {
+        // 1920x1080 at 60 and at 144, the second with reduced blanking
+        assert_eq!(
+            mode_refresh_rate(148_500_000, 2200, 1125, false, false),
+            Some(60)
+        );
+        assert_eq!(
+            mode_refresh_rate(325_080_000, 2000, 1130, false, false),
+            Some(144)
+        );
+        assert_eq!(
+            mode_refresh_rate(74_250_000, 2200, 1125, true, false),
+            Some(60)
+        );
+        assert_eq!(mode_refresh_rate(148_500_000, 0, 1125, false, false), None);
+    }
+}
@@ -0,0 +1,393 @@
+use std::ptr::{null, null_mut};
+
+use glutin::{platform::windows::WindowExtWindows, window::Window};
+use log::{error, info};
+use skia_safe::gpu::d3d::{BackendContext, TextureResourceInfo};
+use skia_safe::gpu::{
+    BackendRenderTarget, BackendSurfaceAccess, DirectContext, FlushInfo, Protected, SurfaceOrigin,
+};
+use skia_safe::{Canvas, ColorType, Surface};
+use winapi::{
+    shared::{
+        dxgi::{IDXGIAdapter1, DXGI_ADAPTER_DESC1, DXGI_ADAPTER_FLAG_SOFTWARE},
+        dxgi1_2::{IDXGISwapChain1, DXGI_SCALING_STRETCH, DXGI_SWAP_CHAIN_DESC1},
+        dxgi1_4::{IDXGIFactory4, IDXGISwapChain3},
+        dxgiformat::{DXGI_FORMAT_R8G8B8A8_UNORM, DXGI_FORMAT_UNKNOWN},
+        dxgitype::{
+            DXGI_SAMPLE_DESC, DXGI_STANDARD_MULTISAMPLE_QUALITY_PATTERN,
+            DXGI_USAGE_RENDER_TARGET_OUTPUT,
+        },
+        winerror::{DXGI_ERROR_NOT_FOUND, HRESULT, SUCCEEDED},
+    },
+    um::{
+        d3d12::{
+            D3D12CreateDevice, ID3D12CommandQueue, ID3D12Device, ID3D12Fence, ID3D12Resource,
+            D3D12_COMMAND_LIST_TYPE_DIRECT, D3D12_COMMAND_QUEUE_DESC,
+            D3D12_COMMAND_QUEUE_FLAG_NONE, D3D12_FENCE_FLAG_NONE, D3D12_RESOURCE_STATE_COMMON,
+        },
+        d3dcommon::D3D_FEATURE_LEVEL_11_0,
+        dxgi1_3::CreateDXGIFactory2,
+        handleapi::CloseHandle,
+        synchapi::{CreateEventW, WaitForSingleObject},
+        unknwnbase::IUnknown,
+        winbase::INFINITE,
+        winnt::HANDLE,
+    },
+    Interface,
+};
+use wio::com::ComPtr;
+
+use super::{GpuPreference, SkiaRenderer};
+use crate::crash_report::set_gpu_info;
+
+const BUFFER_COUNT: u32 = 2;
+// Dxgi doesn't report whether an adapter is integrated. Integrated gpus share system memory and
+// only reserve a small amount of dedicated video memory, so that is used as a heuristic instead.
+const INTEGRATED_VIDEO_MEMORY: usize = 512 * 1024 * 1024;
+
+/// A d3d12 device on the adapter it was created for.
+pub struct D3DDevice {
+    factory: ComPtr<IDXGIFactory4>,
+    adapter: ComPtr<IDXGIAdapter1>,
+    device: ComPtr<ID3D12Device>,
+    name: String,
+}
+
+// Turns a failed HRESULT into an error naming what couldn't be done
+fn check(result: HRESULT, action: &str) -> Result<(), String> {
+    if SUCCEEDED(result) {
+        Ok(())
+    } else {
+        Err(format!("Could not {}: {:#x}", action, result))
+    }
+}
+
+fn create_factory() -> Option<ComPtr<IDXGIFactory4>> {
+    let mut factory = null_mut();
+    let result = unsafe { CreateDXGIFactory2(0, &IDXGIFactory4::uuidof(), &mut factory) };
+    if SUCCEEDED(result) {
+        Some(unsafe { ComPtr::from_raw(factory as *mut IDXGIFactory4) })
+    } else {
+        error!("Could not create dxgi factory: {:#x}", result);
+        None
+    }
+}
+
+fn adapters(factory: &ComPtr<IDXGIFactory4>) -> Vec<(ComPtr<IDXGIAdapter1>, DXGI_ADAPTER_DESC1)> {
+    let mut adapters = Vec::new();
+
+    for index in 0.. {
+        let mut adapter = null_mut();
+        let result = unsafe { factory.EnumAdapters1(index, &mut adapter) };
+        if result == DXGI_ERROR_NOT_FOUND || !SUCCEEDED(result) {
+            break;
+        }
+
+        let adapter = unsafe { ComPtr::from_raw(adapter) };
+        let mut description = unsafe { std::mem::zeroed::<DXGI_ADAPTER_DESC1>() };
+        unsafe { adapter.GetDesc1(&mut description) };
+
+        // Software adapters are handled by the software renderer instead
+        if description.Flags & DXGI_ADAPTER_FLAG_SOFTWARE != 0 {
+            continue;
+        }
+
+        adapters.push((adapter, description));
+    }
+
+    adapters
+}
+
+fn create_device(adapter: &ComPtr<IDXGIAdapter1>) -> Option<ComPtr<ID3D12Device>> {
+    let mut device = null_mut();
+    let result = unsafe {
+        D3D12CreateDevice(
+            adapter.as_raw() as *mut IUnknown,
+            D3D_FEATURE_LEVEL_11_0,
+            &ID3D12Device::uuidof(),
+            &mut device,
+        )
+    };
+
+    if SUCCEEDED(result) {
+        Some(unsafe { ComPtr::from_raw(device as *mut ID3D12Device) })
+    } else {
+        None
+    }
+}
+
+fn adapter_name(description: &DXGI_ADAPTER_DESC1) -> String {
+    let length = description
+        .Description
+        .iter()
+        .position(|character| *character == 0)
+        .unwrap_or_else(|| description.Description.len());
+    String::from_utf16_lossy(&description.Description[..length])
+}
+
+/// Returns a device on the first hardware adapter which supports d3d12, trying the preferred ones
+/// first.
+pub fn find_device(gpu_preference: &GpuPreference) -> Option<D3DDevice> {
+    let factory = create_factory()?;
+
+    let mut adapters = adapters(&factory);
+    gpu_preference.sort_adapters(
+        &mut adapters,
+        |(_, description)| adapter_name(description),
+        |(_, description)| description.DedicatedVideoMemory < INTEGRATED_VIDEO_MEMORY,
+    );
+
+    for (adapter, description) in adapters {
+        if let Some(device) = create_device(&adapter) {
+            return Some(D3DDevice {
+                factory,
+                adapter,
+                device,
+                name: adapter_name(&description),
+            });
+        }
+    }
+
+    None
+}
+
+pub struct D3DSkiaRenderer {
+    window: Window,
+    device: ComPtr<ID3D12Device>,
+    command_queue: ComPtr<ID3D12CommandQueue>,
+    swap_chain: ComPtr<IDXGISwapChain3>,
+    gr_context: DirectContext,
+    surfaces: Vec<Surface>,
+    buffer_index: usize,
+    fence: ComPtr<ID3D12Fence>,
+    fence_value: u64,
+    fence_event: HANDLE,
+}
+
+impl D3DSkiaRenderer {
+    pub fn new(window: Window, device: D3DDevice) -> Result<D3DSkiaRenderer, String> {
+        let D3DDevice {
+            factory,
+            adapter,
+            device,
+            name,
+        } = device;
+        info!("Using d3d12 adapter {}", name);
+        set_gpu_info(format!("d3d12 on {}", name));
+
+        let command_queue = unsafe {
+            let description = D3D12_COMMAND_QUEUE_DESC {
+                Type: D3D12_COMMAND_LIST_TYPE_DIRECT,
+                Priority: 0,
+                Flags: D3D12_COMMAND_QUEUE_FLAG_NONE,
+                NodeMask: 0,
+            };
+            let mut command_queue = null_mut();
+            check(
+                device.CreateCommandQueue(
+                    &description,
+                    &ID3D12CommandQueue::uuidof(),
+                    &mut command_queue,
+                ),
+                "create d3d12 command queue",
+            )?;
+            ComPtr::from_raw(command_queue as *mut ID3D12CommandQueue)
+        };
+
+        let size = window.inner_size();
+        let swap_chain = unsafe {
+            let description = DXGI_SWAP_CHAIN_DESC1 {
+                Width: size.width,
+                Height: size.height,
+                Format: DXGI_FORMAT_R8G8B8A8_UNORM,
+                Stereo: 0,
+                SampleDesc: DXGI_SAMPLE_DESC {
+                    Count: 1,
+                    Quality: 0,
+                },
+                BufferUsage: DXGI_USAGE_RENDER_TARGET_OUTPUT,
+                BufferCount: BUFFER_COUNT,
+                Scaling: DXGI_SCALING_STRETCH,
+                SwapEffect: winapi::shared::dxgi::DXGI_SWAP_EFFECT_FLIP_DISCARD,
+                AlphaMode: winapi::shared::dxgi1_2::DXGI_ALPHA_MODE_UNSPECIFIED,
+                Flags: 0,
+            };
+            let mut swap_chain: *mut IDXGISwapChain1 = null_mut();
+            check(
+                factory.CreateSwapChainForHwnd(
+                    command_queue.as_raw() as *mut IUnknown,
+                    window.hwnd() as _,
+                    &description,
+                    null(),
+                    null_mut(),
+                    &mut swap_chain,
+                ),
+                "create d3d12 swap chain",
+            )?;
+            ComPtr::from_raw(swap_chain)
+                .cast::<IDXGISwapChain3>()
+                .map_err(|result| format!("Could not get the d3d12 swap chain: {:#x}", result))?
+        };
+
+        let backend_context = BackendContext {
+            adapter,
+            device: device.clone(),
+            queue: command_queue.clone(),
+            memory_allocator: None,
+            protected_context: Protected::No,
+        };
+        let gr_context = unsafe { DirectContext::new_d3d(&backend_context, None) }
+            .ok_or("Could not create the skia d3d context")?;
+
+        let (fence, fence_event) = unsafe {
+            let mut fence = null_mut();
+            check(
+                device.CreateFence(0, D3D12_FENCE_FLAG_NONE, &ID3D12Fence::uuidof(), &mut fence),
+                "create d3d12 fence",
+            )?;
+            let fence = ComPtr::from_raw(fence as *mut ID3D12Fence);
+            let fence_event = CreateEventW(null_mut(), 0, 0, null());
+            if fence_event.is_null() {
+                return Err("Could not create the d3d12 fence event".to_owned());
+            }
+            (fence, fence_event)
+        };
+
+        let mut renderer = D3DSkiaRenderer {
+            window,
+            device,
+            command_queue,
+            swap_chain,
+            gr_context,
+            surfaces: Vec::new(),
+            buffer_index: 0,
+            fence,
+            fence_value: 1,
+            fence_event,
+        };
+        renderer.create_surfaces()?;
+
+        Ok(renderer)
+    }
+
+    fn create_surfaces(&mut self) -> Result<(), String> {
+        let size = self.window.inner_size();
+
+        self.surfaces = (0..BUFFER_COUNT)
+            .map(|index| {
+                let resource = unsafe {
+                    let mut resource = null_mut();
+                    check(
+                        self.swap_chain
+                            .GetBuffer(index, &ID3D12Resource::uuidof(), &mut resource),
+                        "get d3d12 back buffer",
+                    )?;
+                    ComPtr::from_raw(resource as *mut ID3D12Resource)
+                };
+
+                let info = TextureResourceInfo {
+                    resource,
+                    alloc: None,
+                    resource_state: D3D12_RESOURCE_STATE_COMMON,
+                    format: DXGI_FORMAT_R8G8B8A8_UNORM,
+                    sample_count: 1,
+                    level_count: 0,
+                    sample_quality_pattern: DXGI_STANDARD_MULTISAMPLE_QUALITY_PATTERN,
+                    protected: Protected::No,
+                };
+                let backend_render_target =
+                    BackendRenderTarget::new_d3d((size.width as i32, size.height as i32), &info);
+
+                Surface::from_backend_render_target(
+                    &mut self.gr_context,
+                    &backend_render_target,
+                    SurfaceOrigin::TopLeft,
+                    ColorType::RGBA8888,
+                    None,
+                    None,
+                )
+                .ok_or_else(|| "Could not create skia surface".to_owned())
+            })
+            .collect::<Result<_, _>>()?;
+
+        self.buffer_index = unsafe { self.swap_chain.GetCurrentBackBufferIndex() as usize };
+        Ok(())
+    }
+
+    // Blocks until the gpu has finished every command submitted so far. This keeps us from
+    // writing into a back buffer which is still being presented.
+    fn wait_for_gpu(&mut self) {
+        unsafe {
+            let fence_value = self.fence_value;
+            self.command_queue.Signal(self.fence.as_raw(), fence_value);
+            self.fence_value += 1;
+
+            if self.fence.GetCompletedValue() < fence_value {
+                self.fence
+                    .SetEventOnCompletion(fence_value, self.fence_event);
+                WaitForSingleObject(self.fence_event, INFINITE);
+            }
+        }
+    }
+}
+
+impl SkiaRenderer for D3DSkiaRenderer {
+    fn window(&self) -> &Window {
+        &self.window
+    }
+
+    fn canvas(&mut self) -> &mut Canvas {
+        self.surfaces[self.buffer_index].canvas()
+    }
+
+    fn flush(&mut self) {
+        let surface = &mut self.surfaces[self.buffer_index];
+        self.gr_context.flush_surface_with_access(
+            surface,
+            BackendSurfaceAccess::Present,
+            &FlushInfo::default(),
+        );
+        self.gr_context.submit(None);
+    }
+
+    fn swap_buffers(&mut self) {
+        unsafe {
+            self.swap_chain.Present(0, 0);
+        }
+        self.wait_for_gpu();
+        self.buffer_index = unsafe { self.swap_chain.GetCurrentBackBufferIndex() as usize };
+    }
+
+    fn resize(&mut self) {
+        self.wait_for_gpu();
+        // Every reference to the back buffers has to be released before they can be resized
+        self.surfaces.clear();
+        self.gr_context.free_gpu_resources();
+
+        let size = self.window.inner_size();
+        // The renderer can't be swapped out any more at this point, so failing is fatal
+        unsafe {
+            check(
+                self.swap_chain.ResizeBuffers(
+                    BUFFER_COUNT,
+                    size.width,
+                    size.height,
+                    DXGI_FORMAT_UNKNOWN,
+                    0,
+                ),
+                "resize d3d12 swap chain",
+            )
+        }
+        .and_then(|_| self.create_surfaces())
+        .unwrap_or_else(|error| panic!("{}", error));
+    }
+}
+
+impl Drop for D3DSkiaRenderer {
+    fn drop(&mut self) {
+        self.wait_for_gpu();
+        unsafe {
+            CloseHandle(self.fence_event);
+        }
+    }
+}
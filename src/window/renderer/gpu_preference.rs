@@ -0,0 +1,126 @@
+use log::warn;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum GpuPreference {
+    Default,
+    Integrated,
+    Discrete,
+    Named(String),
+}
+
+impl GpuPreference {
+    pub fn from_setting(value: Option<&str>) -> GpuPreference {
+        match value {
+            None | Some("") | Some("default") => GpuPreference::Default,
+            Some("integrated") => GpuPreference::Integrated,
+            Some("discrete") => GpuPreference::Discrete,
+            Some(name) => GpuPreference::Named(name.to_owned()),
+        }
+    }
+
+    fn matches(&self, name: &str, is_integrated: bool) -> bool {
+        match self {
+            GpuPreference::Default => false,
+            GpuPreference::Integrated => is_integrated,
+            GpuPreference::Discrete => !is_integrated,
+            GpuPreference::Named(preferred_name) => {
+                name.to_lowercase().contains(&preferred_name.to_lowercase())
+            }
+        }
+    }
+
+    // Moves the adapters matching the preference to the front while otherwise keeping the order
+    // the os enumerated them in, so backends can still fall back to the next adapter if device
+    // creation fails on the preferred one.
+    pub fn sort_adapters<T>(
+        &self,
+        adapters: &mut [T],
+        name: impl Fn(&T) -> String,
+        is_integrated: impl Fn(&T) -> bool,
+    ) {
+        if *self == GpuPreference::Default {
+            return;
+        }
+
+        adapters.sort_by_key(|adapter| !self.matches(&name(adapter), is_integrated(adapter)));
+
+        if !adapters
+            .first()
+            .map(|adapter| self.matches(&name(adapter), is_integrated(adapter)))
+            .unwrap_or(false)
+        {
+            warn!(
+                "No gpu matches the preference {:?}, using the default",
+                self
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn adapters() -> Vec<(&'static str, bool)> {
+        vec![
+            ("NVIDIA GeForce RTX 3060", false),
+            ("Intel(R) UHD Graphics 630", true),
+            ("AMD Radeon Pro 5500M", false),
+        ]
+    }
+
+    fn sorted(preference: GpuPreference) -> Vec<&'static str> {
+        let mut adapters = adapters();
+        preference.sort_adapters(
+            &mut adapters,
+            |(name, _)| name.to_string(),
+            |(_, integrated)| *integrated,
+        );
+        adapters.into_iter().map(|(name, _)| name).collect()
+    }
+
+    #[test]
+    fn test_from_setting() {
+        assert_eq!(GpuPreference::from_setting(None), GpuPreference::Default);
+        assert_eq!(
+            GpuPreference::from_setting(Some("integrated")),
+            GpuPreference::Integrated
+        );
+        assert_eq!(
+            GpuPreference::from_setting(Some("discrete")),
+            GpuPreference::Discrete
+        );
+        assert_eq!(
+            GpuPreference::from_setting(Some("radeon")),
+            GpuPreference::Named("radeon".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_sort_adapters() {
+        assert_eq!(
+            sorted(GpuPreference::Default),
+            vec![
+                "NVIDIA GeForce RTX 3060",
+                "Intel(R) UHD Graphics 630",
+                "AMD Radeon Pro 5500M"
+            ]
+        );
+        assert_eq!(
+            sorted(GpuPreference::Integrated)[0],
+            "Intel(R) UHD Graphics 630"
+        );
+        assert_eq!(
+            sorted(GpuPreference::Discrete),
+            vec![
+                "NVIDIA GeForce RTX 3060",
+                "AMD Radeon Pro 5500M",
+                "Intel(R) UHD Graphics 630"
+            ]
+        );
+        assert_eq!(
+            sorted(GpuPreference::Named("radeon".to_owned()))[0],
+            "AMD Radeon Pro 5500M"
+        );
+    }
+}
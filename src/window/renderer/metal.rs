@@ -0,0 +1,140 @@
+use cocoa::{appkit::NSView, base::id as cocoa_id};
+use core_graphics_types::geometry::CGSize;
+use foreign_types_shared::{ForeignType, ForeignTypeRef};
+use glutin::{platform::macos::WindowExtMacOS, window::Window};
+use log::trace;
+use metal::{CommandQueue, Device, MTLPixelFormat, MetalDrawable, MetalLayer};
+use objc::runtime::YES;
+use skia_safe::gpu::{mtl, BackendRenderTarget, DirectContext, SurfaceOrigin};
+use skia_safe::{Canvas, ColorType, Surface};
+
+use super::SkiaRenderer;
+use crate::redraw_scheduler::REDRAW_SCHEDULER;
+
+pub struct MetalSkiaRenderer {
+    window: Window,
+    layer: MetalLayer,
+    command_queue: CommandQueue,
+    gr_context: DirectContext,
+    // The drawable and the surface wrapping it are only valid for a single frame. They are
+    // acquired lazily the first time the canvas is requested and released on swap.
+    drawable: Option<MetalDrawable>,
+    surface: Option<Surface>,
+}
+
+impl MetalSkiaRenderer {
+    pub fn new(device: Device, window: Window) -> MetalSkiaRenderer {
+        let layer = MetalLayer::new();
+        layer.set_device(&device);
+        layer.set_pixel_format(MTLPixelFormat::BGRA8Unorm);
+        layer.set_presents_with_transaction(false);
+
+        unsafe {
+            let view = window.ns_view() as cocoa_id;
+            view.setWantsLayer(YES);
+            view.setLayer(layer.as_ref() as *const _ as _);
+        }
+
+        let size = window.inner_size();
+        layer.set_drawable_size(CGSize::new(size.width as f64, size.height as f64));
+
+        let command_queue = device.new_command_queue();
+
+        let backend = unsafe {
+            mtl::BackendContext::new(
+                device.as_ptr() as mtl::Handle,
+                command_queue.as_ptr() as mtl::Handle,
+                std::ptr::null(),
+            )
+        };
+        let gr_context =
+            DirectContext::new_metal(&backend, None).expect("Could not create metal context");
+
+        MetalSkiaRenderer {
+            window,
+            layer,
+            command_queue,
+            gr_context,
+            drawable: None,
+            surface: None,
+        }
+    }
+
+    // The layer runs out of drawables while the window is minimized or being resized, then the
+    // frame is drawn nowhere and tried again on the next one
+    fn create_surface(&mut self) -> Option<Surface> {
+        let drawable = match self.layer.next_drawable() {
+            Some(drawable) => drawable.to_owned(),
+            None => {
+                trace!("No metal drawable available, skipping the frame");
+                REDRAW_SCHEDULER.queue_next_frame();
+                return None;
+            }
+        };
+        let size = self.layer.drawable_size();
+
+        let surface = unsafe {
+            let texture_info = mtl::TextureInfo::new(drawable.texture().as_ptr() as mtl::Handle);
+            let backend_render_target = BackendRenderTarget::new_metal(
+                (size.width as i32, size.height as i32),
+                1,
+                &texture_info,
+            );
+
+            Surface::from_backend_render_target(
+                &mut self.gr_context,
+                &backend_render_target,
+                SurfaceOrigin::TopLeft,
+                ColorType::BGRA8888,
+                None,
+                None,
+            )
+            .expect("Could not create skia surface")
+        };
+
+        self.drawable = Some(drawable);
+        Some(surface)
+    }
+}
+
+impl SkiaRenderer for MetalSkiaRenderer {
+    fn window(&self) -> &Window {
+        &self.window
+    }
+
+    fn canvas(&mut self) -> &mut Canvas {
+        if self.surface.is_none() {
+            let surface = self.create_surface().unwrap_or_else(|| {
+                Surface::new_raster_n32_premul((1, 1)).expect("Could not create skia surface")
+            });
+            self.surface = Some(surface);
+        }
+
+        self.surface.as_mut().unwrap().canvas()
+    }
+
+    fn flush(&mut self) {
+        if let Some(surface) = self.surface.as_mut() {
+            surface.flush_and_submit();
+        }
+    }
+
+    fn swap_buffers(&mut self) {
+        // The surface has to be released before the drawable is presented
+        self.surface = None;
+
+        if let Some(drawable) = self.drawable.take() {
+            let command_buffer = self.command_queue.new_command_buffer();
+            command_buffer.present_drawable(&drawable);
+            command_buffer.commit();
+        }
+    }
+
+    fn resize(&mut self) {
+        let size = self.window.inner_size();
+        self.layer
+            .set_drawable_size(CGSize::new(size.width as f64, size.height as f64));
+        self.surface = None;
+        self.drawable = None;
+    }
+}
@@ -0,0 +1,240 @@
+#[cfg(windows)]
+mod d3d;
+mod gpu_preference;
+#[cfg(target_os = "macos")]
+mod metal;
+mod opengl;
+mod software;
+
+use glutin::{
+    event_loop::EventLoopWindowTarget,
+    window::{Window, WindowBuilder},
+};
+use log::{error, info, warn};
+use skia_safe::Canvas;
+
+#[cfg(target_os = "macos")]
+use self::metal::MetalSkiaRenderer;
+use crate::{cmd_line::CmdLineSettings, crash_report::set_gpu_info, settings::SETTINGS};
+#[cfg(windows)]
+use d3d::D3DSkiaRenderer;
+use gpu_preference::GpuPreference;
+use opengl::OpenGLSkiaRenderer;
+use software::SoftwareSkiaRenderer;
+
+// Each graphics backend owns the os window it renders into, since some of them (OpenGL) need to
+// create the window and the graphics context at the same time.
+pub trait SkiaRenderer {
+    fn window(&self) -> &Window;
+    fn canvas(&mut self) -> &mut Canvas;
+    fn flush(&mut self);
+    fn swap_buffers(&mut self);
+    fn resize(&mut self);
+    /// Minimized windows, and windows resized all the way down, have no area to draw to. Their
+    /// surfaces can't be created at that size, so presenting waits until the window is restored.
+    fn has_area(&self) -> bool {
+        let size = self.window().inner_size();
+        size.width > 0 && size.height > 0
+    }
+    // Opengl keeps the current context per thread, so with several windows the right one has to
+    // be made current before drawing. Other backends don't need this.
+    fn make_current(&mut self) {}
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GraphicsBackend {
+    Auto,
+    OpenGL,
+    Metal,
+    D3D,
+    Software,
+}
+
+impl GraphicsBackend {
+    pub fn from_name(name: &str) -> GraphicsBackend {
+        match name {
+            "opengl" => GraphicsBackend::OpenGL,
+            "metal" => GraphicsBackend::Metal,
+            "d3d" => GraphicsBackend::D3D,
+            "software" => GraphicsBackend::Software,
+            "auto" => GraphicsBackend::Auto,
+            _ => {
+                error!("Unknown graphics backend {}, using auto", name);
+                GraphicsBackend::Auto
+            }
+        }
+    }
+
+    fn is_supported(self) -> bool {
+        match self {
+            GraphicsBackend::Auto | GraphicsBackend::OpenGL | GraphicsBackend::Software => true,
+            GraphicsBackend::Metal => cfg!(target_os = "macos"),
+            GraphicsBackend::D3D => cfg!(windows),
+        }
+    }
+}
+
+#[cfg(any(windows, target_os = "macos"))]
+fn build_window(window_builder: WindowBuilder, event_loop: &EventLoopWindowTarget<()>) -> Window {
+    window_builder
+        .build(event_loop)
+        .expect("Could not create window")
+}
+
+#[cfg(target_os = "macos")]
+fn build_metal_renderer(
+    window_builder: WindowBuilder,
+    event_loop: &EventLoopWindowTarget<()>,
+    gpu_preference: &GpuPreference,
+) -> Option<Box<dyn SkiaRenderer>> {
+    let device = if *gpu_preference == GpuPreference::Default {
+        ::metal::Device::system_default()?
+    } else {
+        let mut devices = ::metal::Device::all();
+        gpu_preference.sort_adapters(
+            &mut devices,
+            |device| device.name().to_owned(),
+            |device| device.is_low_power(),
+        );
+        devices
+            .into_iter()
+            .next()
+            .or_else(::metal::Device::system_default)?
+    };
+    info!("Using metal renderer on {}", device.name());
+    set_gpu_info(format!("metal on {}", device.name()));
+    let window = build_window(window_builder, event_loop);
+    Some(Box::new(MetalSkiaRenderer::new(device, window)))
+}
+
+#[cfg(windows)]
+fn build_d3d_renderer(
+    window_builder: WindowBuilder,
+    event_loop: &EventLoopWindowTarget<()>,
+    gpu_preference: &GpuPreference,
+) -> Option<Box<dyn SkiaRenderer>> {
+    // The device is found first, so no window is made for a machine without one
+    let device = d3d::find_device(gpu_preference)?;
+
+    info!("Using d3d renderer");
+    let window = build_window(window_builder, event_loop);
+    match D3DSkiaRenderer::new(window, device) {
+        Ok(renderer) => Some(Box::new(renderer)),
+        Err(error) => {
+            error!("{}", error);
+            None
+        }
+    }
+}
+
+// Opengl has no way to pick the gpu once the process is running. Mesa reads DRI_PRIME when the
+// context is created though, so on linux the preference can be forwarded there.
+fn apply_opengl_gpu_preference(gpu_preference: &GpuPreference) {
+    if *gpu_preference == GpuPreference::Default {
+        return;
+    }
+
+    if cfg!(target_os = "linux") {
+        if std::env::var("DRI_PRIME").is_ok() {
+            return;
+        }
+
+        match gpu_preference {
+            GpuPreference::Discrete => std::env::set_var("DRI_PRIME", "1"),
+            GpuPreference::Integrated => std::env::set_var("DRI_PRIME", "0"),
+            _ => warn!("The opengl backend can't select a gpu by name, set DRI_PRIME instead"),
+        }
+    } else {
+        warn!("The opengl backend doesn't support selecting a gpu on this platform");
+    }
+}
+
+fn build_opengl_renderer(
+    window_builder: WindowBuilder,
+    event_loop: &EventLoopWindowTarget<()>,
+    gpu_preference: &GpuPreference,
+) -> Option<Box<dyn SkiaRenderer>> {
+    info!("Using opengl renderer");
+    apply_opengl_gpu_preference(gpu_preference);
+    OpenGLSkiaRenderer::new(window_builder, event_loop)
+        .map(|renderer| Box::new(renderer) as Box<dyn SkiaRenderer>)
+}
+
+fn build_software_renderer(
+    window_builder: WindowBuilder,
+    event_loop: &EventLoopWindowTarget<()>,
+) -> Option<Box<dyn SkiaRenderer>> {
+    info!("Using software renderer");
+    set_gpu_info("software".to_owned());
+    SoftwareSkiaRenderer::new(window_builder, event_loop)
+        .map(|renderer| Box::new(renderer) as Box<dyn SkiaRenderer>)
+}
+
+pub fn build_skia_renderer(
+    window_builder: WindowBuilder,
+    event_loop: &EventLoopWindowTarget<()>,
+) -> Box<dyn SkiaRenderer> {
+    let mut backend = GraphicsBackend::from_name(&SETTINGS.get::<CmdLineSettings>().backend);
+    if !backend.is_supported() {
+        warn!(
+            "The {:?} backend is not supported on this platform, using auto",
+            backend
+        );
+        backend = GraphicsBackend::Auto;
+    }
+    let gpu_preference =
+        GpuPreference::from_setting(SETTINGS.get::<CmdLineSettings>().gpu.as_deref());
+
+    if backend == GraphicsBackend::Software {
+        return build_software_renderer(window_builder, event_loop)
+            .expect("Could not create software renderer");
+    }
+
+    #[cfg(target_os = "macos")]
+    {
+        if matches!(backend, GraphicsBackend::Auto | GraphicsBackend::Metal) {
+            if let Some(renderer) =
+                build_metal_renderer(window_builder.clone(), event_loop, &gpu_preference)
+            {
+                return renderer;
+            }
+            warn!("No metal device found, falling back to opengl");
+        }
+    }
+
+    // Direct3D is only tried first when explicitly requested. In auto mode it is the fallback for
+    // machines with broken or missing opengl drivers.
+    #[cfg(windows)]
+    {
+        if backend == GraphicsBackend::D3D {
+            if let Some(renderer) =
+                build_d3d_renderer(window_builder.clone(), event_loop, &gpu_preference)
+            {
+                return renderer;
+            }
+            warn!("Could not create d3d renderer, falling back to opengl");
+        }
+    }
+
+    if let Some(renderer) =
+        build_opengl_renderer(window_builder.clone(), event_loop, &gpu_preference)
+    {
+        return renderer;
+    }
+
+    #[cfg(windows)]
+    {
+        if backend != GraphicsBackend::D3D {
+            warn!("Could not create opengl renderer, falling back to d3d");
+            if let Some(renderer) =
+                build_d3d_renderer(window_builder.clone(), event_loop, &gpu_preference)
+            {
+                return renderer;
+            }
+        }
+    }
+
+    warn!("No usable gpu found, falling back to software rendering. Expect reduced performance");
+    build_software_renderer(window_builder, event_loop)
+        .expect("Could not create a renderer for any graphics backend")
+}
@@ -0,0 +1,200 @@
+use std::convert::TryInto;
+use std::ffi::CStr;
+use std::ops::Deref;
+
+use gl::types::*;
+use glutin::{
+    event_loop::EventLoopWindowTarget,
+    window::{Window, WindowBuilder},
+    ContextBuilder, GlProfile, PossiblyCurrent, WindowedContext,
+};
+use log::error;
+use skia_safe::gpu::gl::FramebufferInfo;
+use skia_safe::gpu::{BackendRenderTarget, DirectContext, SurfaceOrigin};
+use skia_safe::{Canvas, ColorType, Surface};
+
+use super::SkiaRenderer;
+use crate::{cmd_line::CmdLineSettings, crash_report::set_gpu_info, settings::SETTINGS};
+
+// The driver's own description, like the gpu name for GL_RENDERER
+fn gl_string(name: GLenum) -> String {
+    unsafe {
+        let string = gl::GetString(name);
+        if string.is_null() {
+            "unknown".to_owned()
+        } else {
+            CStr::from_ptr(string as *const _)
+                .to_string_lossy()
+                .into_owned()
+        }
+    }
+}
+
+fn create_surface(
+    windowed_context: &WindowedContext<PossiblyCurrent>,
+    gr_context: &mut DirectContext,
+    fb_info: FramebufferInfo,
+) -> Surface {
+    let pixel_format = windowed_context.get_pixel_format();
+    let size = windowed_context.window().inner_size();
+    let size = (
+        size.width.try_into().expect("Could not convert width"),
+        size.height.try_into().expect("Could not convert height"),
+    );
+    let backend_render_target = BackendRenderTarget::new_gl(
+        size,
+        pixel_format
+            .multisampling
+            .map(|s| s.try_into().expect("Could not convert multisampling")),
+        pixel_format
+            .stencil_bits
+            .try_into()
+            .expect("Could not convert stencil"),
+        fb_info,
+    );
+    windowed_context.resize(size.into());
+    Surface::from_backend_render_target(
+        gr_context,
+        &backend_render_target,
+        SurfaceOrigin::BottomLeft,
+        ColorType::RGBA8888,
+        None,
+        None,
+    )
+    .expect("Could not create skia surface")
+}
+
+/// A window's opengl context, which can be made current again once another window's context took
+/// its place. Making a context current consumes it, so it is taken out and put back in.
+pub struct WindowContext(Option<WindowedContext<PossiblyCurrent>>);
+
+impl WindowContext {
+    pub fn new(windowed_context: WindowedContext<PossiblyCurrent>) -> WindowContext {
+        WindowContext(Some(windowed_context))
+    }
+
+    pub fn ensure_current(&mut self) {
+        if self.is_current() {
+            return;
+        }
+
+        // Both outcomes hand the context back, so it is always there again afterwards
+        let context = self
+            .0
+            .take()
+            .expect("The opengl context is always put back");
+        let context = match unsafe { context.make_current() } {
+            Ok(context) => context,
+            Err((context, error)) => {
+                error!("Could not make opengl context current: {}", error);
+                context
+            }
+        };
+        self.0 = Some(context);
+    }
+}
+
+impl Deref for WindowContext {
+    type Target = WindowedContext<PossiblyCurrent>;
+
+    fn deref(&self) -> &WindowedContext<PossiblyCurrent> {
+        self.0
+            .as_ref()
+            .expect("The opengl context is always put back")
+    }
+}
+
+pub struct OpenGLSkiaRenderer {
+    windowed_context: WindowContext,
+    gr_context: DirectContext,
+    fb_info: FramebufferInfo,
+    surface: Surface,
+}
+
+impl OpenGLSkiaRenderer {
+    pub fn new(
+        window_builder: WindowBuilder,
+        event_loop: &EventLoopWindowTarget<()>,
+    ) -> Option<OpenGLSkiaRenderer> {
+        let windowed_context = ContextBuilder::new()
+            .with_pixel_format(24, 8)
+            .with_stencil_buffer(8)
+            .with_gl_profile(GlProfile::Core)
+            .with_vsync(false)
+            .with_srgb(SETTINGS.get::<CmdLineSettings>().srgb)
+            .build_windowed(window_builder, event_loop)
+            .map_err(|error| error!("Could not create opengl context: {}", error))
+            .ok()?;
+        let windowed_context = unsafe { windowed_context.make_current() }
+            .map_err(|(_, error)| error!("Could not make opengl context current: {}", error))
+            .ok()?;
+
+        gl::load_with(|s| windowed_context.get_proc_address(s));
+        set_gpu_info(format!(
+            "opengl {} on {}",
+            gl_string(gl::VERSION),
+            gl_string(gl::RENDERER)
+        ));
+
+        let interface = skia_safe::gpu::gl::Interface::new_load_with(|name| {
+            if name == "eglGetCurrentDisplay" {
+                return std::ptr::null();
+            }
+            windowed_context.get_proc_address(name)
+        });
+        if interface.is_none() {
+            error!("Could not create skia opengl interface");
+        }
+
+        let mut gr_context = match skia_safe::gpu::DirectContext::new_gl(interface, None) {
+            Some(gr_context) => gr_context,
+            None => {
+                error!("Could not create skia opengl context");
+                return None;
+            }
+        };
+        let fb_info = {
+            let mut fboid: GLint = 0;
+            unsafe { gl::GetIntegerv(gl::FRAMEBUFFER_BINDING, &mut fboid) };
+
+            FramebufferInfo {
+                fboid: fboid.try_into().expect("Could not create frame buffer id"),
+                format: skia_safe::gpu::gl::Format::RGBA8.into(),
+            }
+        };
+        let surface = create_surface(&windowed_context, &mut gr_context, fb_info);
+
+        Some(OpenGLSkiaRenderer {
+            windowed_context: WindowContext::new(windowed_context),
+            gr_context,
+            fb_info,
+            surface,
+        })
+    }
+}
+
+impl SkiaRenderer for OpenGLSkiaRenderer {
+    fn window(&self) -> &Window {
+        self.windowed_context.window()
+    }
+
+    fn canvas(&mut self) -> &mut Canvas {
+        self.surface.canvas()
+    }
+
+    fn flush(&mut self) {
+        self.gr_context.flush(None);
+    }
+
+    fn swap_buffers(&mut self) {
+        self.windowed_context.swap_buffers().unwrap();
+    }
+
+    fn resize(&mut self) {
+        self.surface = create_surface(&self.windowed_context, &mut self.gr_context, self.fb_info);
+    }
+
+    fn make_current(&mut self) {
+        self.windowed_context.ensure_current();
+    }
+}
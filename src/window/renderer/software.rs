@@ -0,0 +1,156 @@
+use std::ffi::c_void;
+use std::mem;
+
+use gl::types::*;
+use glutin::{
+    event_loop::EventLoopWindowTarget,
+    window::{Window, WindowBuilder},
+    ContextBuilder,
+};
+use log::error;
+use skia_safe::{AlphaType, Canvas, ColorType, ImageInfo, Surface};
+
+use super::opengl::WindowContext;
+use super::SkiaRenderer;
+
+// Skia can't create surfaces with a zero sized dimension, so minimized windows still get a single
+// pixel to draw into.
+fn create_surface(window: &Window) -> Surface {
+    let size = window.inner_size();
+    let image_info = ImageInfo::new(
+        (size.width.max(1) as i32, size.height.max(1) as i32),
+        ColorType::RGBA8888,
+        AlphaType::Premul,
+        None,
+    );
+    Surface::new_raster(&image_info, None, None).expect("Could not create raster surface")
+}
+
+// The gl crate only covers the core profile, which dropped drawing pixels straight to the window.
+// These have been there since opengl 1.0, so even the gdi driver windows falls back to has them.
+struct LegacyGl {
+    raster_pos: extern "system" fn(GLfloat, GLfloat),
+    pixel_zoom: extern "system" fn(GLfloat, GLfloat),
+    draw_pixels: extern "system" fn(GLsizei, GLsizei, GLenum, GLenum, *const c_void),
+}
+
+impl LegacyGl {
+    fn load(load: impl Fn(&str) -> *const c_void) -> Option<LegacyGl> {
+        let function = |name: &str| {
+            let pointer = load(name);
+            if pointer.is_null() {
+                error!("The opengl driver has no {}", name);
+                None
+            } else {
+                Some(pointer)
+            }
+        };
+        unsafe {
+            Some(LegacyGl {
+                raster_pos: mem::transmute(function("glRasterPos2f")?),
+                pixel_zoom: mem::transmute(function("glPixelZoom")?),
+                draw_pixels: mem::transmute(function("glDrawPixels")?),
+            })
+        }
+    }
+}
+
+fn check_gl_error(action: &str) -> bool {
+    let error = unsafe { gl::GetError() };
+    if error != gl::NO_ERROR {
+        error!("OpenGL error {:#x} while {}", error, action);
+    }
+    error == gl::NO_ERROR
+}
+
+// Renders with skia's cpu rasterizer and copies the finished frame into the window. The opengl
+// context is only used to get the pixels on screen with calls from opengl 1.1, so any
+// implementation works here including the software ones shipped with the os (llvmpipe, the
+// windows gdi driver) and those of remote desktops and virtual machines.
+pub struct SoftwareSkiaRenderer {
+    windowed_context: WindowContext,
+    surface: Surface,
+    legacy_gl: LegacyGl,
+}
+
+impl SoftwareSkiaRenderer {
+    pub fn new(
+        window_builder: WindowBuilder,
+        event_loop: &EventLoopWindowTarget<()>,
+    ) -> Option<SoftwareSkiaRenderer> {
+        let windowed_context = ContextBuilder::new()
+            .with_hardware_acceleration(None)
+            .with_vsync(false)
+            .build_windowed(window_builder, event_loop)
+            .map_err(|error| error!("Could not create software context: {}", error))
+            .ok()?;
+        let windowed_context = unsafe { windowed_context.make_current() }
+            .map_err(|(_, error)| error!("Could not make software context current: {}", error))
+            .ok()?;
+
+        gl::load_with(|s| windowed_context.get_proc_address(s));
+        let legacy_gl = LegacyGl::load(|s| windowed_context.get_proc_address(s))?;
+
+        let surface = create_surface(windowed_context.window());
+
+        Some(SoftwareSkiaRenderer {
+            windowed_context: WindowContext::new(windowed_context),
+            surface,
+            legacy_gl,
+        })
+    }
+}
+
+impl SkiaRenderer for SoftwareSkiaRenderer {
+    fn window(&self) -> &Window {
+        self.windowed_context.window()
+    }
+
+    fn canvas(&mut self) -> &mut Canvas {
+        self.surface.canvas()
+    }
+
+    fn flush(&mut self) {
+        let pixmap = match self.surface.peek_pixels() {
+            Some(pixmap) => pixmap,
+            None => return,
+        };
+        let (width, height) = (pixmap.width(), pixmap.height());
+
+        // Skia stores rows top down while gl stores them bottom up, so the pixels are drawn
+        // downwards from the top left corner of the window
+        unsafe {
+            gl::Viewport(0, 0, width, height);
+            gl::PixelStorei(gl::UNPACK_ROW_LENGTH, (pixmap.row_bytes() / 4) as i32);
+            (self.legacy_gl.raster_pos)(-1.0, 1.0);
+            (self.legacy_gl.pixel_zoom)(1.0, -1.0);
+            (self.legacy_gl.draw_pixels)(
+                width,
+                height,
+                gl::RGBA,
+                gl::UNSIGNED_BYTE,
+                pixmap.addr() as *const _,
+            );
+            gl::PixelStorei(gl::UNPACK_ROW_LENGTH, 0);
+            gl::Flush();
+        }
+        check_gl_error("drawing the frame");
+    }
+
+    fn swap_buffers(&mut self) {
+        // A lost context only loses this frame, the next one is drawn from scratch anyway
+        if let Err(error) = self.windowed_context.swap_buffers() {
+            error!("Could not present the frame: {}", error);
+        }
+    }
+
+    fn resize(&mut self) {
+        let size = self.windowed_context.window().inner_size();
+        self.windowed_context.resize(size);
+        self.surface = create_surface(self.windowed_context.window());
+    }
+
+    fn make_current(&mut self) {
+        self.windowed_context.ensure_current();
+    }
+}
@@ -0,0 +1,305 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender, TrySendError};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use log::{error, info, warn};
+use skia_safe::{Canvas, ISize};
+
+use super::screenshot::read_canvas_pixels;
+use crate::settings::spawn_with_settings;
+
+// Quantizing is the slow part of writing a gif, 10 keeps up with the frame rates used for demos
+const GIF_SPEED: i32 = 10;
+// Frames the encoder may fall behind by before new ones are dropped, so a slow encoder can't
+// fill up the memory with whole window sized frames
+const QUEUED_FRAMES: usize = 4;
+// Longer durations, including infinite ones, are recorded until they are stopped or for a day
+const MAX_DURATION: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum RecordingFormat {
+    Gif,
+    // Anything else ffmpeg can write, picked by the extension of the file
+    Video,
+}
+
+fn recording_format(path: &Path) -> RecordingFormat {
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some("mp4") | Some("mkv") | Some("webm") | Some("mov") => RecordingFormat::Video,
+        _ => RecordingFormat::Gif,
+    }
+}
+
+fn default_recording_path() -> PathBuf {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0);
+    PathBuf::from(format!("neovide_recording_{}.gif", timestamp))
+}
+
+enum FrameEncoder {
+    Gif(gif::Encoder<BufWriter<File>>),
+    Video(Child),
+}
+
+impl FrameEncoder {
+    fn new(path: &Path, size: ISize, fps: u64) -> Result<FrameEncoder, String> {
+        match recording_format(path) {
+            RecordingFormat::Gif => {
+                let file = File::create(path).map_err(|error| error.to_string())?;
+                let mut encoder = gif::Encoder::new(
+                    BufWriter::new(file),
+                    size.width as u16,
+                    size.height as u16,
+                    &[],
+                )
+                .map_err(|error| error.to_string())?;
+                encoder
+                    .set_repeat(gif::Repeat::Infinite)
+                    .map_err(|error| error.to_string())?;
+                Ok(FrameEncoder::Gif(encoder))
+            }
+            // Yuv420p is what players expect, and it needs an even size
+            RecordingFormat::Video => Command::new("ffmpeg")
+                .args(&[
+                    "-y",
+                    "-loglevel",
+                    "error",
+                    "-f",
+                    "rawvideo",
+                    "-pix_fmt",
+                    "rgba",
+                ])
+                .args(&["-s", &format!("{}x{}", size.width, size.height)])
+                .args(&["-r", &fps.to_string(), "-i", "-"])
+                .args(&[
+                    "-vf",
+                    "pad=ceil(iw/2)*2:ceil(ih/2)*2",
+                    "-pix_fmt",
+                    "yuv420p",
+                ])
+                .arg(path)
+                .stdin(Stdio::piped())
+                .spawn()
+                .map(FrameEncoder::Video)
+                .map_err(|error| format!("ffmpeg could not be started: {}", error)),
+        }
+    }
+
+    fn write_frame(&mut self, size: ISize, pixels: &mut [u8], fps: u64) -> Result<(), String> {
+        match self {
+            FrameEncoder::Gif(encoder) => {
+                let mut frame = gif::Frame::from_rgba_speed(
+                    size.width as u16,
+                    size.height as u16,
+                    pixels,
+                    GIF_SPEED,
+                );
+                // In hundredths of a second
+                frame.delay = (100 / fps.max(1)) as u16;
+                encoder
+                    .write_frame(&frame)
+                    .map_err(|error| error.to_string())
+            }
+            FrameEncoder::Video(ffmpeg) => ffmpeg
+                .stdin
+                .as_mut()
+                .expect("ffmpeg has no stdin")
+                .write_all(pixels)
+                .map_err(|error| error.to_string()),
+        }
+    }
+
+    fn finish(self) -> Result<(), String> {
+        match self {
+            // The trailer is written when the encoder is dropped
+            FrameEncoder::Gif(_) => Ok(()),
+            FrameEncoder::Video(mut ffmpeg) => {
+                // Closing stdin tells ffmpeg the video ended
+                drop(ffmpeg.stdin.take());
+                match ffmpeg.wait() {
+                    Ok(status) if status.success() => Ok(()),
+                    Ok(status) => Err(format!("ffmpeg exited with {}", status)),
+                    Err(error) => Err(error.to_string()),
+                }
+            }
+        }
+    }
+}
+
+// The canvas has premultiplied alpha, which has to be undone so transparent backgrounds don't come
+// out darkened. They are made opaque afterwards, since they would show up as holes otherwise.
+fn make_opaque(pixels: &mut [u8]) {
+    for pixel in pixels.chunks_mut(4) {
+        let alpha = pixel[3] as u32;
+        if alpha != 0 && alpha != 255 {
+            for channel in &mut pixel[..3] {
+                *channel = ((*channel as u32 * 255 + alpha / 2) / alpha).min(255) as u8;
+            }
+        }
+        pixel[3] = 255;
+    }
+}
+
+fn recording_duration(duration: f32) -> Duration {
+    if duration.is_nan() || duration <= 0.0 {
+        Duration::from_secs(0)
+    } else if duration >= MAX_DURATION.as_secs_f32() {
+        MAX_DURATION
+    } else {
+        Duration::from_secs_f32(duration)
+    }
+}
+
+// Frames are encoded on their own thread, so recording doesn't slow down drawing much. The size
+// of the first frame is the size of the whole recording.
+fn encode_frames(path: PathBuf, fps: u64, receiver: Receiver<(ISize, Vec<u8>)>) {
+    let mut encoder: Option<(ISize, FrameEncoder)> = None;
+    for (size, mut pixels) in receiver.iter() {
+        make_opaque(&mut pixels);
+
+        if encoder.is_none() {
+            match FrameEncoder::new(&path, size, fps) {
+                Ok(frame_encoder) => encoder = Some((size, frame_encoder)),
+                Err(error) => {
+                    error!("Could not record to {}: {}", path.display(), error);
+                    return;
+                }
+            }
+        }
+
+        let (recording_size, frame_encoder) = encoder.as_mut().unwrap();
+        if size != *recording_size {
+            warn!("Skipped a frame drawn after the window was resized while recording");
+            continue;
+        }
+        if let Err(error) = frame_encoder.write_frame(size, &mut pixels, fps) {
+            error!("Could not record to {}: {}", path.display(), error);
+            return;
+        }
+    }
+
+    if let Some((_, frame_encoder)) = encoder {
+        match frame_encoder.finish() {
+            Ok(()) => info!("Saved recording to {}", path.display()),
+            Err(error) => error!("Could not finish {}: {}", path.display(), error),
+        }
+    }
+}
+
+/// Records the frames drawn in the window for a while, into a gif or, through ffmpeg, a video.
+/// The file is finished once the recording is dropped.
+pub struct ScreenRecording {
+    sender: SyncSender<(ISize, Vec<u8>)>,
+    started: Instant,
+    duration: Duration,
+    frame_interval: Duration,
+    last_frame: Option<Instant>,
+}
+
+impl ScreenRecording {
+    /// Without a path a gif named after the current time is put in the working directory.
+    pub fn start(path: Option<String>, duration: f32, fps: u64) -> ScreenRecording {
+        let path = path
+            .map(PathBuf::from)
+            .unwrap_or_else(default_recording_path);
+        let fps = fps.max(1);
+        info!("Recording to {}", path.display());
+
+        let (sender, receiver) = sync_channel(QUEUED_FRAMES);
+        spawn_with_settings(move || encode_frames(path, fps, receiver));
+        ScreenRecording {
+            sender,
+            started: Instant::now(),
+            duration: recording_duration(duration),
+            frame_interval: Duration::from_nanos(1_000_000_000 / fps),
+            last_frame: None,
+        }
+    }
+
+    fn frame_due(&self, now: Instant) -> bool {
+        self.last_frame
+            .map_or(true, |last_frame| now - last_frame >= self.frame_interval)
+    }
+
+    /// Keeps the frame just drawn when it is time for the next one. Returns when the frame after
+    /// it is due, or nothing once the recording is over.
+    ///
+    /// Reading the pixels back waits for the gpu to finish drawing, so it is only done for the
+    /// frames that are kept, and frames are only drawn for the recording at its own frame rate.
+    pub fn capture(&mut self, canvas: &mut Canvas) -> Option<Instant> {
+        let now = Instant::now();
+        if now - self.started > self.duration {
+            return None;
+        }
+
+        if self.frame_due(now) {
+            self.last_frame = Some(now);
+            if let Some(frame) = read_canvas_pixels(canvas) {
+                match self.sender.try_send(frame) {
+                    Ok(()) => {}
+                    Err(TrySendError::Full(_)) => {
+                        warn!("Dropped a frame the recording couldn't keep up with")
+                    }
+                    Err(TrySendError::Disconnected(_)) => return None,
+                }
+            }
+        }
+        Some(self.last_frame.unwrap_or(now) + self.frame_interval)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recording_format() {
+        assert_eq!(
+            recording_format(Path::new("demo.mp4")),
+            RecordingFormat::Video
+        );
+        assert_eq!(
+            recording_format(Path::new("demo.gif")),
+            RecordingFormat::Gif
+        );
+        assert_eq!(recording_format(Path::new("demo")), RecordingFormat::Gif);
+    }
+
+    #[test]
+    fn test_make_opaque() {
+        let mut pixels = vec![0, 0, 0, 0, 64, 32, 0, 128, 10, 20, 30, 255];
+        make_opaque(&mut pixels);
+        assert_eq!(pixels, vec![0, 0, 0, 255, 128, 64, 0, 255, 10, 20, 30, 255]);
+    }
+
+    #[test]
+    fn test_recording_duration() {
+        assert_eq!(recording_duration(2.5), Duration::from_millis(2500));
+        assert_eq!(recording_duration(-1.0), Duration::from_secs(0));
+        assert_eq!(recording_duration(f32::NAN), Duration::from_secs(0));
+        assert_eq!(recording_duration(f32::INFINITY), MAX_DURATION);
+    }
+
+    #[test]
+    fn test_frames_follow_the_frame_rate() {
+        let (sender, _receiver) = sync_channel(QUEUED_FRAMES);
+        let started = Instant::now();
+        let mut recording = ScreenRecording {
+            sender,
+            started,
+            duration: Duration::from_secs(10),
+            frame_interval: Duration::from_millis(100),
+            last_frame: None,
+        };
+        assert!(recording.frame_due(started));
+
+        recording.last_frame = Some(started);
+        assert!(!recording.frame_due(started + Duration::from_millis(50)));
+        assert!(recording.frame_due(started + Duration::from_millis(100)));
+    }
+}
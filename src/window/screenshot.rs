@@ -0,0 +1,60 @@
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use log::{error, info};
+use skia_safe::{AlphaType, Canvas, ColorType, Data, EncodedImageFormat, ISize, Image, ImageInfo};
+
+fn default_screenshot_path() -> PathBuf {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0);
+    PathBuf::from(format!("neovide_screenshot_{}.png", timestamp))
+}
+
+fn rgba_image_info(size: ISize) -> ImageInfo {
+    ImageInfo::new(size, ColorType::RGBA8888, AlphaType::Premul, None)
+}
+
+/// Reads what is drawn on the canvas as rows of rgba pixels without padding.
+pub fn read_canvas_pixels(canvas: &mut Canvas) -> Option<(ISize, Vec<u8>)> {
+    let size = canvas.base_layer_size();
+    let image_info = rgba_image_info(size);
+    let row_bytes = image_info.min_row_bytes();
+    let mut pixels = vec![0u8; row_bytes * size.height as usize];
+
+    if !canvas.read_pixels(&image_info, &mut pixels, row_bytes, (0, 0)) {
+        error!("Could not read pixels from the window surface");
+        return None;
+    }
+    Some((size, pixels))
+}
+
+fn encode_canvas(canvas: &mut Canvas) -> Option<Data> {
+    let (size, pixels) = read_canvas_pixels(canvas)?;
+    let image_info = rgba_image_info(size);
+    let row_bytes = image_info.min_row_bytes();
+    let image = Image::from_raster_data(&image_info, Data::new_copy(&pixels), row_bytes)?;
+    image.encode_to_data(EncodedImageFormat::PNG)
+}
+
+/// Writes what is currently drawn on the canvas to a png at full surface resolution. Without a
+/// path the file is put in the working directory and named after the current time.
+pub fn save_screenshot(canvas: &mut Canvas, path: Option<String>) {
+    let path = path
+        .map(PathBuf::from)
+        .unwrap_or_else(default_screenshot_path);
+
+    let data = match encode_canvas(canvas) {
+        Some(data) => data,
+        None => {
+            error!("Could not encode screenshot");
+            return;
+        }
+    };
+
+    match std::fs::write(&path, data.as_bytes()) {
+        Ok(()) => info!("Saved screenshot to {}", path.display()),
+        Err(error) => error!("Could not save screenshot to {}: {}", path.display(), error),
+    }
+}
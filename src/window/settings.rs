@@ -1,32 +1,157 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use super::power_saving::POWER_SAVING_REFRESH_RATE;
 use crate::{cmd_line::CmdLineSettings, settings::*};
 
+// Frames are paced to this without a refresh rate setting, before the monitor is known
+const FALLBACK_REFRESH_RATE: u32 = 60;
+
+// The refresh rate of the monitor the focused window is on, or 0 while it isn't known
+static MONITOR_REFRESH_RATE: AtomicU32 = AtomicU32::new(0);
+
+/// Remembers the refresh rate of the monitor the focused window moved to.
+pub fn set_monitor_refresh_rate(refresh_rate: Option<u32>) {
+    MONITOR_REFRESH_RATE.store(refresh_rate.unwrap_or(0), Ordering::Relaxed);
+}
+
 #[derive(Clone, SettingGroup)]
 pub struct WindowSettings {
     pub refresh_rate: u64,
     pub no_idle: bool,
+    pub latency_measurement: bool,
     pub transparency: f32,
+    pub window_blur: bool,
+    pub window_blur_style: String,
     pub fullscreen: bool,
+    pub fullscreen_monitor: String,
+    pub macos_simple_fullscreen: bool,
+    pub toggle_fullscreen_with_f11: bool,
+    pub always_on_top: bool,
+    pub frameless: bool,
+    pub titlebar: String,
+    pub title_format: String,
+    pub theme: String,
+    pub high_contrast_colorscheme: String,
+    pub padding_top: u32,
+    pub padding_right: u32,
+    pub padding_bottom: u32,
+    pub padding_left: u32,
+    pub presentation_zoom: f32,
+    pub presentation_columns: u64,
+    pub resize_mode: String,
     pub iso_layout: bool,
     pub remember_window_size: bool,
+    pub remember_ui_state: bool,
+    pub confirm_quit: bool,
     pub hide_mouse_when_typing: bool,
+    pub right_click_menu: bool,
+    pub scroll_multiplier: f32,
+    pub file_drop_command: String,
+    pub image_paste_directory: String,
+    pub touch_deadzone: f32,
+    pub touch_long_press_time: f32,
+    pub discord_presence: bool,
+    pub discord_client_id: String,
+    pub recording_duration: f32,
+    pub recording_fps: u64,
+    pub window_tabbing: String,
 }
 
 impl Default for WindowSettings {
     fn default() -> Self {
         Self {
-            transparency: 1.0,
-            fullscreen: false,
+            transparency: SETTINGS.get::<CmdLineSettings>().transparency,
+            window_blur: false,
+            window_blur_style: "acrylic".to_string(),
+            fullscreen: SETTINGS.get::<CmdLineSettings>().fullscreen,
+            fullscreen_monitor: SETTINGS
+                .get::<CmdLineSettings>()
+                .fullscreen_monitor
+                .unwrap_or_default(),
+            macos_simple_fullscreen: false,
+            toggle_fullscreen_with_f11: false,
+            always_on_top: false,
+            frameless: SETTINGS.get::<CmdLineSettings>().frameless,
+            titlebar: "native".to_string(),
+            title_format: "".to_string(),
+            theme: "".to_string(),
+            high_contrast_colorscheme: "".to_string(),
+            padding_top: 0,
+            padding_right: 0,
+            padding_bottom: 0,
+            padding_left: 0,
+            presentation_zoom: 1.5,
+            presentation_columns: 0,
+            resize_mode: "none".to_string(),
             iso_layout: false,
-            refresh_rate: 60,
+            refresh_rate: 0,
             no_idle: SETTINGS.get::<CmdLineSettings>().no_idle,
+            latency_measurement: false,
             remember_window_size: false,
+            remember_ui_state: false,
+            confirm_quit: true,
             hide_mouse_when_typing: false,
+            right_click_menu: false,
+            scroll_multiplier: 1.0,
+            file_drop_command: "edit".to_string(),
+            image_paste_directory: "".to_string(),
+            touch_deadzone: 6.0,
+            touch_long_press_time: 0.5,
+            discord_presence: false,
+            discord_client_id: "".to_string(),
+            recording_duration: 10.0,
+            recording_fps: 15,
+            window_tabbing: "automatic".to_string(),
+        }
+    }
+}
+
+impl WindowSettings {
+    /// The alpha applied to the default background. Values set from vimscript aren't validated,
+    /// so they are clamped here.
+    pub fn background_alpha(&self) -> u8 {
+        (255.0 * self.transparency.max(0.0).min(1.0)) as u8
+    }
+
+    pub fn is_transparent(&self) -> bool {
+        self.background_alpha() < 255
+    }
+
+    /// The frames per second to pace drawing to. `g:neovide_refresh_rate` wins when it is set,
+    /// otherwise the monitor's own rate is followed. Saving power caps it further.
+    pub fn target_refresh_rate(&self, saves_power: bool) -> f32 {
+        let refresh_rate = if self.refresh_rate > 0 {
+            self.refresh_rate as f32
+        } else {
+            match MONITOR_REFRESH_RATE.load(Ordering::Relaxed) {
+                0 => FALLBACK_REFRESH_RATE as f32,
+                monitor_refresh_rate => monitor_refresh_rate as f32,
+            }
+        };
+        if saves_power {
+            refresh_rate.min(POWER_SAVING_REFRESH_RATE)
+        } else {
+            refresh_rate
         }
     }
 }
 
-#[derive(Clone, Default, SettingGroup)]
+#[derive(Clone, SettingGroup)]
 #[setting_prefix = "input"]
 pub struct KeyboardSettings {
     pub use_logo: bool,
+    pub macos_alt_is_meta: bool,
+    pub physical_shortcuts: bool,
+}
+
+impl Default for KeyboardSettings {
+    fn default() -> Self {
+        Self {
+            // Command shortcuts are sent to neovim as <D-...> on macos, where the menu handles the
+            // standard ones before they get here
+            use_logo: cfg!(target_os = "macos"),
+            macos_alt_is_meta: false,
+            physical_shortcuts: false,
+        }
+    }
 }
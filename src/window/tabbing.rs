@@ -0,0 +1,128 @@
+use std::mem;
+
+use cocoa::{
+    appkit::NSApp,
+    base::{id, nil, BOOL, NO, YES},
+    foundation::{NSArray, NSString},
+};
+use glutin::{platform::macos::WindowExtMacOS, window::Window};
+use log::warn;
+use objc::{
+    class, msg_send,
+    runtime::{Imp, Object, Sel},
+    sel, sel_impl,
+};
+
+use super::menu::push_menu_action;
+use super::menu_items::MenuAction;
+use super::open_documents::add_app_delegate_method;
+
+// Windows with the same identifier are the ones that can be merged into tabs
+const TABBING_IDENTIFIER: &str = "neovide";
+
+// NSWindowTabbingMode
+const TABBING_MODE_AUTOMATIC: u64 = 0;
+const TABBING_MODE_PREFERRED: u64 = 1;
+const TABBING_MODE_DISALLOWED: u64 = 2;
+
+fn tabbing_mode(setting: &str) -> u64 {
+    match setting {
+        "always" => TABBING_MODE_PREFERRED,
+        "never" => TABBING_MODE_DISALLOWED,
+        "automatic" => TABBING_MODE_AUTOMATIC,
+        _ => {
+            warn!(
+                "Unknown window tabbing {}, following the system instead",
+                setting
+            );
+            TABBING_MODE_AUTOMATIC
+        }
+    }
+}
+
+/// Lets the window join the other neovide windows as a tab. With "automatic" that follows the
+/// "Prefer tabs when opening documents" system preference, "always" and "never" override it.
+/// Only takes effect when the window is shown, so it has to be set while it is still hidden.
+pub fn set_window_tabbing(window: &Window, setting: &str) {
+    unsafe {
+        let ns_window = window.ns_window() as id;
+        // Tabs came with macOS 10.12
+        let supported: BOOL = msg_send![ns_window, respondsToSelector: sel!(setTabbingMode:)];
+        if supported == NO {
+            return;
+        }
+        let _: () = msg_send![class!(NSWindow), setAllowsAutomaticWindowTabbing: YES];
+        let identifier = NSString::alloc(nil).init_str(TABBING_IDENTIFIER);
+        let _: () = msg_send![ns_window, setTabbingIdentifier: identifier];
+        let _: () = msg_send![ns_window, setTabbingMode: tabbing_mode(setting)];
+    }
+}
+
+// Tabs other than the selected one aren't visible, but are still windows to cycle through
+unsafe fn can_cycle_to(window: id) -> bool {
+    let can_become_key: BOOL = msg_send![window, canBecomeKeyWindow];
+    let minimized: BOOL = msg_send![window, isMiniaturized];
+    let visible: BOOL = msg_send![window, isVisible];
+    let tabbed = {
+        let supported: BOOL = msg_send![window, respondsToSelector: sel!(tabbedWindows)];
+        let tabbed_windows: id = if supported != NO {
+            msg_send![window, tabbedWindows]
+        } else {
+            nil
+        };
+        tabbed_windows != nil
+    };
+    can_become_key != NO && minimized == NO && (visible != NO || tabbed)
+}
+
+/// Moves the focus on to the next window or tab for Cmd+`, which would otherwise be sent to
+/// neovim like any other shortcut.
+pub extern "C" fn cycle_windows(_this: &Object, _selector: Sel, _sender: id) {
+    unsafe {
+        let app = NSApp();
+        let all_windows: id = msg_send![app, windows];
+        let windows: Vec<id> = (0..all_windows.count())
+            .map(|index| all_windows.objectAtIndex(index))
+            .filter(|window| can_cycle_to(*window))
+            .collect();
+        if windows.is_empty() {
+            return;
+        }
+
+        let key_window: id = msg_send![app, keyWindow];
+        let next = windows
+            .iter()
+            .position(|window| *window == key_window)
+            .map_or(0, |index| (index + 1) % windows.len());
+        let _: () = msg_send![windows[next], makeKeyAndOrderFront: nil];
+    }
+}
+
+// Sent by the plus button of the tab bar
+extern "C" fn new_window_for_tab(_this: &Object, _selector: Sel, _sender: id) {
+    push_menu_action(MenuAction::NewWindow);
+}
+
+/// Makes the tab bar offer a button for new tabs, which open as new neovide windows.
+pub fn install_new_tab_handler() {
+    let new_window_for_tab: extern "C" fn(&Object, Sel, id) = new_window_for_tab;
+    unsafe {
+        add_app_delegate_method(
+            sel!(newWindowForTab:),
+            mem::transmute::<_, Imp>(new_window_for_tab),
+            b"v@:@\0",
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tabbing_mode() {
+        assert_eq!(tabbing_mode("always"), TABBING_MODE_PREFERRED);
+        assert_eq!(tabbing_mode("never"), TABBING_MODE_DISALLOWED);
+        assert_eq!(tabbing_mode("sometimes"), TABBING_MODE_AUTOMATIC);
+    }
+}
@@ -0,0 +1,145 @@
+#[cfg(not(windows))]
+use std::thread::{self, Thread};
+
+use glutin::window::{Theme, Window};
+#[cfg(not(windows))]
+use parking_lot::Mutex;
+use skia_safe::Color;
+
+#[cfg(not(windows))]
+use crate::redraw_scheduler::REDRAW_SCHEDULER;
+
+#[cfg(not(windows))]
+lazy_static! {
+    // Finding out the theme means starting a process, so after the first time it is read again on
+    // a thread of its own and kept here
+    static ref SYSTEM_THEME: Mutex<Option<Theme>> = Mutex::new(read_system_theme());
+    static ref THEME_READER: Thread = thread::spawn(|| loop {
+        thread::park();
+        let theme = read_system_theme();
+        if std::mem::replace(&mut *SYSTEM_THEME.lock(), theme) != theme {
+            // The event loop passes the new theme on to the windows
+            REDRAW_SCHEDULER.queue_flushed_frame();
+        }
+    })
+    .thread()
+    .clone();
+}
+
+#[cfg(not(windows))]
+fn command_output(program: &str, args: &[&str]) -> Option<String> {
+    let output = std::process::Command::new(program)
+        .args(args)
+        .output()
+        .ok()?;
+    if output.status.success() {
+        String::from_utf8(output.stdout).ok()
+    } else {
+        None
+    }
+}
+
+// Gnome reports the color scheme in quotes, like 'prefer-dark'. Older versions only have the gtk
+// theme name to go on, which by convention ends with -dark for dark variants.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn parse_gnome_theme(color_scheme: Option<&str>, gtk_theme: Option<&str>) -> Option<Theme> {
+    let color_scheme = color_scheme.map(|scheme| scheme.trim().trim_matches('\''));
+    match color_scheme {
+        Some("prefer-dark") => Some(Theme::Dark),
+        Some("prefer-light") => Some(Theme::Light),
+        _ => gtk_theme.map(|theme| {
+            if theme.to_lowercase().contains("dark") {
+                Theme::Dark
+            } else {
+                Theme::Light
+            }
+        }),
+    }
+}
+
+/// The light or dark appearance the os is set to, if it can be found out.
+#[cfg(windows)]
+pub fn system_theme(window: &Window) -> Option<Theme> {
+    use glutin::platform::windows::WindowExtWindows;
+    Some(window.theme())
+}
+
+#[cfg(not(windows))]
+pub fn system_theme(_window: &Window) -> Option<Theme> {
+    *SYSTEM_THEME.lock()
+}
+
+/// Reads the os theme again in the background, where the os doesn't report changes. The windows
+/// pick it up once it was read.
+#[cfg(windows)]
+pub fn refresh_system_theme() {}
+
+#[cfg(not(windows))]
+pub fn refresh_system_theme() {
+    THEME_READER.unpark();
+}
+
+#[cfg(target_os = "macos")]
+fn read_system_theme() -> Option<Theme> {
+    // The key only exists while dark mode is on, so reading it fails in light mode
+    match command_output("defaults", &["read", "-g", "AppleInterfaceStyle"]) {
+        Some(style) if style.trim() == "Dark" => Some(Theme::Dark),
+        _ => Some(Theme::Light),
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn read_system_theme() -> Option<Theme> {
+    let color_scheme = command_output(
+        "gsettings",
+        &["get", "org.gnome.desktop.interface", "color-scheme"],
+    );
+    let gtk_theme = command_output(
+        "gsettings",
+        &["get", "org.gnome.desktop.interface", "gtk-theme"],
+    );
+    parse_gnome_theme(color_scheme.as_deref(), gtk_theme.as_deref())
+}
+
+#[cfg(not(any(windows, target_os = "macos", target_os = "linux")))]
+fn read_system_theme() -> Option<Theme> {
+    None
+}
+
+/// What the window is filled with before neovim sent its colors, so a dark system doesn't flash
+/// a light window and the other way around.
+pub fn startup_background(theme: Theme) -> Color {
+    match theme {
+        Theme::Light => Color::WHITE,
+        Theme::Dark => Color::BLACK,
+    }
+}
+
+pub fn background_name(theme: Theme) -> &'static str {
+    match theme {
+        Theme::Light => "light",
+        Theme::Dark => "dark",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_gnome_theme() {
+        assert_eq!(
+            parse_gnome_theme(Some("'prefer-dark'\n"), Some("'Adwaita'")),
+            Some(Theme::Dark)
+        );
+        assert_eq!(
+            parse_gnome_theme(Some("'default'\n"), Some("'Adwaita-dark'")),
+            Some(Theme::Dark)
+        );
+        assert_eq!(
+            parse_gnome_theme(None, Some("'Adwaita'")),
+            Some(Theme::Light)
+        );
+        assert_eq!(parse_gnome_theme(None, None), None);
+    }
+}
@@ -0,0 +1,107 @@
+use std::path::Path;
+
+const NO_NAME: &str = "[No Name]";
+
+/// What the window title can be built from. The title comes from neovim's own title option, the
+/// rest is reported by autocommands whenever the current buffer or directory changes.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TitleState {
+    pub neovim_title: String,
+    pub path: String,
+    pub modified: bool,
+    pub cwd: String,
+    // While a remote neovim is reconnected to, the title says so in front of everything else
+    pub connection_lost: bool,
+}
+
+impl TitleState {
+    fn file_name(&self) -> String {
+        Path::new(&self.path)
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| NO_NAME.to_owned())
+    }
+
+    /// Expands `{title}`, `{file}`, `{path}`, `{cwd}` and `{modified}` in the format. An empty
+    /// format keeps the title neovim set.
+    pub fn format(&self, format: &str) -> String {
+        let title = self.expand(format);
+        if self.connection_lost {
+            format!("[Reconnecting] {}", title)
+        } else {
+            title
+        }
+    }
+
+    fn expand(&self, format: &str) -> String {
+        if format.is_empty() {
+            return self.neovim_title.clone();
+        }
+
+        let path = if self.path.is_empty() {
+            NO_NAME
+        } else {
+            &self.path
+        };
+        let modified = if self.modified { "[+]" } else { "" };
+
+        format
+            .replace("{title}", &self.neovim_title)
+            .replace("{file}", &self.file_name())
+            .replace("{path}", path)
+            .replace("{cwd}", &self.cwd)
+            .replace("{modified}", modified)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state() -> TitleState {
+        TitleState {
+            neovim_title: "main.rs (~/neovide/src) - NVIM".to_owned(),
+            path: "/home/user/neovide/src/main.rs".to_owned(),
+            modified: true,
+            cwd: "/home/user/neovide".to_owned(),
+            connection_lost: false,
+        }
+    }
+
+    #[test]
+    fn test_empty_format_uses_neovim_title() {
+        assert_eq!(state().format(""), "main.rs (~/neovide/src) - NVIM");
+    }
+
+    #[test]
+    fn test_format_placeholders() {
+        assert_eq!(
+            state().format("{file}{modified} - {cwd}"),
+            "main.rs[+] - /home/user/neovide"
+        );
+        assert_eq!(
+            state().format("{path} | {title}"),
+            "/home/user/neovide/src/main.rs | main.rs (~/neovide/src) - NVIM"
+        );
+    }
+
+    #[test]
+    fn test_format_unnamed_buffer() {
+        let state = TitleState {
+            path: String::new(),
+            modified: false,
+            ..state()
+        };
+        assert_eq!(state.format("{file}{modified}"), "[No Name]");
+        assert_eq!(state.format("{path}"), "[No Name]");
+    }
+
+    #[test]
+    fn test_format_connection_lost() {
+        let state = TitleState {
+            connection_lost: true,
+            ..state()
+        };
+        assert_eq!(state.format("{file}"), "[Reconnecting] main.rs");
+    }
+}
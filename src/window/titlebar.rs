@@ -0,0 +1,542 @@
+use glutin::window::Window;
+use skia_safe::{Canvas, Color, Font, Paint, PaintStyle, Path, Rect, Typeface};
+
+use crate::renderer::high_contrast_colors;
+
+pub const TITLEBAR_HEIGHT: f32 = 32.0;
+const CAPTION_BUTTON_WIDTH: f32 = 46.0;
+const CAPTION_GLYPH_SIZE: f32 = 10.0;
+const TITLE_FONT_SIZE: f32 = 12.0;
+const TITLE_MARGIN: f32 = 12.0;
+const CLOSE_HOVER_COLOR: Color = Color::from_rgb(232, 17, 35);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CaptionButton {
+    Minimize,
+    Maximize,
+    Close,
+}
+
+impl CaptionButton {
+    // Ordered from the right edge of the window
+    const ALL: [CaptionButton; 3] = [
+        CaptionButton::Close,
+        CaptionButton::Maximize,
+        CaptionButton::Minimize,
+    ];
+}
+
+/// Returns the caption button under the given x coordinate of the titlebar, if any.
+pub fn caption_button_at(x: f32, window_width: f32, scale_factor: f32) -> Option<CaptionButton> {
+    let from_right = window_width - x;
+    if x < 0.0 || from_right <= 0.0 {
+        return None;
+    }
+
+    let index = (from_right / (CAPTION_BUTTON_WIDTH * scale_factor)) as usize;
+    CaptionButton::ALL.get(index).copied()
+}
+
+pub fn is_dark(color: Color) -> bool {
+    let luminance = 0.299 * color.r() as f32 + 0.587 * color.g() as f32 + 0.114 * color.b() as f32;
+    luminance < 128.0
+}
+
+fn draw_caption_glyph(
+    canvas: &mut Canvas,
+    button: CaptionButton,
+    region: Rect,
+    scale_factor: f32,
+    paint: &Paint,
+) {
+    let size = CAPTION_GLYPH_SIZE * scale_factor;
+    let glyph = Rect::from_xywh(
+        (region.center_x() - size / 2.0).round() + 0.5,
+        (region.center_y() - size / 2.0).round() + 0.5,
+        size,
+        size,
+    );
+
+    match button {
+        CaptionButton::Minimize => {
+            canvas.draw_line(
+                (glyph.left, glyph.center_y()),
+                (glyph.right, glyph.center_y()),
+                paint,
+            );
+        }
+        CaptionButton::Maximize => {
+            canvas.draw_rect(glyph, paint);
+        }
+        CaptionButton::Close => {
+            let mut path = Path::new();
+            path.move_to((glyph.left, glyph.top));
+            path.line_to((glyph.right, glyph.bottom));
+            path.move_to((glyph.right, glyph.top));
+            path.line_to((glyph.left, glyph.bottom));
+            canvas.draw_path(&path, paint);
+        }
+    }
+}
+
+/// A titlebar drawn by neovide in place of the native one, using the editor colors. The window
+/// procedure is hooked so the system still treats it as a caption for dragging, snapping and the
+/// caption buttons. Only available on Windows; elsewhere the native titlebar is always used, but
+/// it is still tinted with the editor background where the system allows it.
+pub struct Titlebar {
+    font: Font,
+    enabled: bool,
+    transparent: bool,
+    dark: Option<bool>,
+    color: Option<Color>,
+}
+
+impl Titlebar {
+    #[cfg_attr(not(windows), allow(unused_variables))]
+    pub fn new(window: &Window) -> Titlebar {
+        #[cfg(windows)]
+        windows::install(window);
+
+        Titlebar {
+            font: Font::new(Typeface::default(), TITLE_FONT_SIZE),
+            enabled: false,
+            transparent: false,
+            dark: None,
+            color: None,
+        }
+    }
+
+    /// Switches between the custom and native titlebar and keeps the titlebar theme and color in
+    /// line with the default background. On macos the background only shows through the native
+    /// titlebar while it is transparent.
+    #[cfg_attr(not(any(windows, target_os = "macos")), allow(unused_variables))]
+    pub fn update(&mut self, window: &Window, custom: bool, transparent: bool, background: Color) {
+        let enabled = custom && cfg!(windows);
+        if self.enabled != enabled {
+            self.enabled = enabled;
+            #[cfg(windows)]
+            windows::set_enabled(window, enabled);
+        }
+
+        let transparent = transparent && cfg!(target_os = "macos");
+        if self.transparent != transparent {
+            self.transparent = transparent;
+            #[cfg(target_os = "macos")]
+            macos::set_transparent(window, transparent);
+        }
+
+        let dark = is_dark(background);
+        if self.dark != Some(dark) {
+            self.dark = Some(dark);
+            #[cfg(windows)]
+            windows::set_dark_mode(window, dark);
+            #[cfg(target_os = "macos")]
+            macos::set_dark_appearance(window, dark);
+        }
+
+        // The native titlebar follows the colorscheme instead of staying white or grey. Windows
+        // 10 ignores the caption color, but the dark mode above still applies there.
+        if self.color != Some(background) {
+            self.color = Some(background);
+            #[cfg(windows)]
+            windows::set_caption_color(window, background);
+        }
+    }
+
+    pub fn height(&self, scale_factor: f32) -> f32 {
+        if self.enabled {
+            TITLEBAR_HEIGHT * scale_factor
+        } else {
+            0.0
+        }
+    }
+
+    pub fn draw(
+        &mut self,
+        canvas: &mut Canvas,
+        title: &str,
+        width: f32,
+        scale_factor: f32,
+        background: Color,
+    ) {
+        if !self.enabled {
+            return;
+        }
+
+        let height = self.height(scale_factor);
+        let (background, foreground, hover, hover_glyph) = match high_contrast_colors() {
+            Some(colors) => (
+                colors.background,
+                colors.foreground,
+                colors.highlight,
+                colors.highlight_text,
+            ),
+            None => {
+                let foreground = if is_dark(background) {
+                    Color::WHITE
+                } else {
+                    Color::BLACK
+                };
+                (background, foreground, foreground.with_a(30), foreground)
+            }
+        };
+        let buttons_width = CAPTION_BUTTON_WIDTH * scale_factor * CaptionButton::ALL.len() as f32;
+
+        canvas.save();
+        canvas.reset_matrix();
+
+        let mut paint = Paint::default();
+        paint.set_anti_alias(true);
+        paint.set_color(background);
+        canvas.draw_rect(Rect::from_wh(width, height), &paint);
+
+        canvas.save();
+        canvas.clip_rect(
+            Rect::from_wh((width - buttons_width).max(0.0), height),
+            None,
+            Some(false),
+        );
+        let font_size = TITLE_FONT_SIZE * scale_factor;
+        self.font.set_size(font_size);
+        paint.set_color(foreground);
+        canvas.draw_str(
+            title,
+            (TITLE_MARGIN * scale_factor, height / 2.0 + font_size * 0.35),
+            &self.font,
+            &paint,
+        );
+        canvas.restore();
+
+        let hovered = hovered_button();
+        for (index, button) in CaptionButton::ALL.iter().enumerate() {
+            let button_width = CAPTION_BUTTON_WIDTH * scale_factor;
+            let region = Rect::from_xywh(
+                width - button_width * (index + 1) as f32,
+                0.0,
+                button_width,
+                height,
+            );
+
+            let mut glyph_color = foreground;
+            if hovered == Some(*button) {
+                paint.set_style(PaintStyle::Fill);
+                if *button == CaptionButton::Close && high_contrast_colors().is_none() {
+                    paint.set_color(CLOSE_HOVER_COLOR);
+                    glyph_color = Color::WHITE;
+                } else {
+                    paint.set_color(hover);
+                    glyph_color = hover_glyph;
+                }
+                canvas.draw_rect(region, &paint);
+            }
+
+            paint.set_style(PaintStyle::Stroke);
+            paint.set_stroke_width(scale_factor.floor().max(1.0));
+            paint.set_color(glyph_color);
+            draw_caption_glyph(canvas, *button, region, scale_factor, &paint);
+        }
+
+        canvas.restore();
+    }
+}
+
+#[cfg(windows)]
+fn hovered_button() -> Option<CaptionButton> {
+    windows::hovered_button()
+}
+
+#[cfg(not(windows))]
+fn hovered_button() -> Option<CaptionButton> {
+    None
+}
+
+#[cfg(windows)]
+mod windows {
+    use std::mem::size_of;
+    use std::ptr::null_mut;
+    use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+
+    use glutin::{platform::windows::WindowExtWindows, window::Window};
+    use skia_safe::Color;
+    use winapi::{
+        shared::{
+            basetsd::{DWORD_PTR, UINT_PTR},
+            minwindef::{BOOL, DWORD, LPARAM, LPCVOID, LRESULT, TRUE, UINT, WPARAM},
+            windef::{COLORREF, HWND, POINT, RECT},
+        },
+        um::{
+            commctrl::{DefSubclassProc, SetWindowSubclass},
+            dwmapi::DwmSetWindowAttribute,
+            wingdi::RGB,
+            winuser::*,
+        },
+    };
+
+    use super::{caption_button_at, is_dark, CaptionButton, TITLEBAR_HEIGHT};
+    use crate::redraw_scheduler::REDRAW_SCHEDULER;
+
+    // Not part of the winapi bindings yet
+    const DWMWA_USE_IMMERSIVE_DARK_MODE: DWORD = 20;
+    const DWMWA_CAPTION_COLOR: DWORD = 35;
+    const DWMWA_TEXT_COLOR: DWORD = 36;
+    const SUBCLASS_ID: UINT_PTR = 1;
+
+    static ENABLED: AtomicBool = AtomicBool::new(false);
+    static HOVERED: AtomicU8 = AtomicU8::new(0);
+
+    fn button_from_hit(hit: LRESULT) -> Option<CaptionButton> {
+        match hit {
+            HTMINBUTTON => Some(CaptionButton::Minimize),
+            HTMAXBUTTON => Some(CaptionButton::Maximize),
+            HTCLOSE => Some(CaptionButton::Close),
+            _ => None,
+        }
+    }
+
+    fn set_hovered(hit: LRESULT) {
+        let hovered = match button_from_hit(hit) {
+            Some(CaptionButton::Minimize) => 1,
+            Some(CaptionButton::Maximize) => 2,
+            Some(CaptionButton::Close) => 3,
+            None => 0,
+        };
+        if HOVERED.swap(hovered, Ordering::Relaxed) != hovered {
+            REDRAW_SCHEDULER.queue_next_frame();
+        }
+    }
+
+    pub fn hovered_button() -> Option<CaptionButton> {
+        match HOVERED.load(Ordering::Relaxed) {
+            1 => Some(CaptionButton::Minimize),
+            2 => Some(CaptionButton::Maximize),
+            3 => Some(CaptionButton::Close),
+            _ => None,
+        }
+    }
+
+    unsafe fn frame_thickness(hwnd: HWND) -> i32 {
+        let dpi = GetDpiForWindow(hwnd);
+        GetSystemMetricsForDpi(SM_CYFRAME, dpi) + GetSystemMetricsForDpi(SM_CXPADDEDBORDER, dpi)
+    }
+
+    unsafe fn hit_test(hwnd: HWND, lparam: LPARAM) -> LRESULT {
+        let mut point = POINT {
+            x: (lparam & 0xffff) as i16 as i32,
+            y: ((lparam >> 16) & 0xffff) as i16 as i32,
+        };
+        ScreenToClient(hwnd, &mut point);
+
+        let scale_factor = GetDpiForWindow(hwnd) as f32 / 96.0;
+        if point.y as f32 >= TITLEBAR_HEIGHT * scale_factor {
+            return HTCLIENT;
+        }
+
+        // The caption covers the top border, so the resize handle there has to be reported here
+        if IsZoomed(hwnd) == 0 && point.y < frame_thickness(hwnd) {
+            return HTTOP;
+        }
+
+        let mut client_rect: RECT = std::mem::zeroed();
+        GetClientRect(hwnd, &mut client_rect);
+        // Reporting the caption buttons lets windows show the snap layout flyout over maximize
+        match caption_button_at(point.x as f32, client_rect.right as f32, scale_factor) {
+            Some(CaptionButton::Minimize) => HTMINBUTTON,
+            Some(CaptionButton::Maximize) => HTMAXBUTTON,
+            Some(CaptionButton::Close) => HTCLOSE,
+            None => HTCAPTION,
+        }
+    }
+
+    unsafe extern "system" fn subclass_proc(
+        hwnd: HWND,
+        msg: UINT,
+        wparam: WPARAM,
+        lparam: LPARAM,
+        _id: UINT_PTR,
+        _data: DWORD_PTR,
+    ) -> LRESULT {
+        if !ENABLED.load(Ordering::Relaxed) {
+            return DefSubclassProc(hwnd, msg, wparam, lparam);
+        }
+
+        match msg {
+            WM_NCCALCSIZE if wparam == TRUE as WPARAM => {
+                // Keep the side and bottom borders the system computes, but extend the client
+                // area over the native caption
+                let params = &mut *(lparam as *mut NCCALCSIZE_PARAMS);
+                let top = params.rgrc[0].top;
+                let result = DefSubclassProc(hwnd, msg, wparam, lparam);
+                params.rgrc[0].top = top;
+                // Maximized windows hang over the edges of the monitor by the frame thickness
+                if IsZoomed(hwnd) != 0 {
+                    params.rgrc[0].top += frame_thickness(hwnd);
+                }
+                result
+            }
+            WM_NCHITTEST => {
+                let result = DefSubclassProc(hwnd, msg, wparam, lparam);
+                if result == HTCLIENT {
+                    hit_test(hwnd, lparam)
+                } else {
+                    result
+                }
+            }
+            WM_NCMOUSEMOVE => {
+                set_hovered(wparam as LRESULT);
+                let mut track = TRACKMOUSEEVENT {
+                    cbSize: size_of::<TRACKMOUSEEVENT>() as DWORD,
+                    dwFlags: TME_LEAVE | TME_NONCLIENT,
+                    hwndTrack: hwnd,
+                    dwHoverTime: 0,
+                };
+                TrackMouseEvent(&mut track);
+                DefSubclassProc(hwnd, msg, wparam, lparam)
+            }
+            WM_NCMOUSELEAVE | WM_MOUSEMOVE => {
+                set_hovered(HTNOWHERE);
+                DefSubclassProc(hwnd, msg, wparam, lparam)
+            }
+            // The system would draw the classic caption buttons on press, so presses on the
+            // buttons are swallowed and the action happens on release instead
+            WM_NCLBUTTONDOWN if button_from_hit(wparam as LRESULT).is_some() => 0,
+            WM_NCLBUTTONUP if button_from_hit(wparam as LRESULT).is_some() => {
+                let command = match button_from_hit(wparam as LRESULT) {
+                    Some(CaptionButton::Minimize) => SC_MINIMIZE,
+                    Some(CaptionButton::Maximize) if IsZoomed(hwnd) != 0 => SC_RESTORE,
+                    Some(CaptionButton::Maximize) => SC_MAXIMIZE,
+                    _ => SC_CLOSE,
+                };
+                PostMessageW(hwnd, WM_SYSCOMMAND, command, 0);
+                0
+            }
+            _ => DefSubclassProc(hwnd, msg, wparam, lparam),
+        }
+    }
+
+    pub fn install(window: &Window) {
+        unsafe {
+            SetWindowSubclass(window.hwnd() as HWND, Some(subclass_proc), SUBCLASS_ID, 0);
+        }
+    }
+
+    pub fn set_enabled(window: &Window, enabled: bool) {
+        ENABLED.store(enabled, Ordering::Relaxed);
+        set_hovered(HTNOWHERE);
+        // Makes the system recompute the client area with the new frame
+        unsafe {
+            SetWindowPos(
+                window.hwnd() as HWND,
+                null_mut(),
+                0,
+                0,
+                0,
+                0,
+                SWP_FRAMECHANGED | SWP_NOMOVE | SWP_NOSIZE | SWP_NOZORDER | SWP_NOACTIVATE,
+            );
+        }
+    }
+
+    pub fn set_caption_color(window: &Window, background: Color) {
+        let text = if is_dark(background) {
+            Color::WHITE
+        } else {
+            Color::BLACK
+        };
+        let colorref = |color: Color| -> COLORREF { RGB(color.r(), color.g(), color.b()) };
+        let hwnd = window.hwnd() as HWND;
+        for (attribute, color) in [
+            (DWMWA_CAPTION_COLOR, colorref(background)),
+            (DWMWA_TEXT_COLOR, colorref(text)),
+        ] {
+            unsafe {
+                DwmSetWindowAttribute(
+                    hwnd,
+                    attribute,
+                    &color as *const COLORREF as LPCVOID,
+                    size_of::<COLORREF>() as DWORD,
+                );
+            }
+        }
+    }
+
+    pub fn set_dark_mode(window: &Window, dark: bool) {
+        let value = dark as BOOL;
+        unsafe {
+            DwmSetWindowAttribute(
+                window.hwnd() as HWND,
+                DWMWA_USE_IMMERSIVE_DARK_MODE,
+                &value as *const BOOL as LPCVOID,
+                size_of::<BOOL>() as DWORD,
+            );
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod macos {
+    use cocoa::{
+        base::{id, nil, BOOL, NO, YES},
+        foundation::NSString,
+    };
+    use glutin::{platform::macos::WindowExtMacOS, window::Window};
+    use objc::{class, msg_send, sel, sel_impl};
+
+    // A transparent titlebar shows the window background, which the native background keeps in
+    // the editor color
+    pub fn set_transparent(window: &Window, transparent: bool) {
+        let transparent: BOOL = if transparent { YES } else { NO };
+        unsafe {
+            let ns_window = window.ns_window() as id;
+            let _: () = msg_send![ns_window, setTitlebarAppearsTransparent: transparent];
+        }
+    }
+
+    // The appearance picks the titlebar text and button colors that go with the editor
+    pub fn set_dark_appearance(window: &Window, dark: bool) {
+        let appearance_name = if dark {
+            "NSAppearanceNameDarkAqua"
+        } else {
+            "NSAppearanceNameAqua"
+        };
+        unsafe {
+            let ns_window = window.ns_window() as id;
+            let name: id = msg_send![NSString::alloc(nil).init_str(appearance_name), autorelease];
+            let appearance: id = msg_send![class!(NSAppearance), appearanceNamed: name];
+            let _: () = msg_send![ns_window, setAppearance: appearance];
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_caption_button_at() {
+        assert_eq!(caption_button_at(10.0, 800.0, 1.0), None);
+        assert_eq!(
+            caption_button_at(790.0, 800.0, 1.0),
+            Some(CaptionButton::Close)
+        );
+        assert_eq!(
+            caption_button_at(800.0 - 50.0, 800.0, 1.0),
+            Some(CaptionButton::Maximize)
+        );
+        assert_eq!(
+            caption_button_at(800.0 - 100.0, 800.0, 1.0),
+            Some(CaptionButton::Minimize)
+        );
+        // Buttons grow with the scale factor
+        assert_eq!(
+            caption_button_at(800.0 - 100.0, 800.0, 2.0),
+            Some(CaptionButton::Maximize)
+        );
+        assert_eq!(caption_button_at(801.0, 800.0, 1.0), None);
+    }
+
+    #[test]
+    fn test_is_dark() {
+        assert!(is_dark(Color::from_rgb(30, 30, 30)));
+        assert!(!is_dark(Color::from_rgb(250, 250, 240)));
+    }
+}
@@ -0,0 +1,273 @@
+use glutin::window::Window;
+use parking_lot::Mutex;
+
+use super::menu_items::MenuAction;
+use crate::redraw_scheduler::REDRAW_SCHEDULER;
+
+lazy_static! {
+    static ref TRAY_ACTIONS: Mutex<Vec<MenuAction>> = Mutex::new(Vec::new());
+}
+
+// The tray is driven from outside of the event loop, so it has to be woken up
+fn push_action(action: MenuAction) {
+    TRAY_ACTIONS.lock().push(action);
+    REDRAW_SCHEDULER.queue_flushed_frame();
+}
+
+/// Takes the actions picked from the tray since the last call.
+pub fn take_tray_actions() -> Vec<MenuAction> {
+    TRAY_ACTIONS.lock().drain(..).collect()
+}
+
+/// Shows the tray icon. Clicking it shows or hides the windows, and its menu can quit as well.
+#[cfg_attr(not(windows), allow(unused_variables))]
+pub fn install_tray(window: &Window) {
+    #[cfg(windows)]
+    windows::install(window);
+    #[cfg(target_os = "macos")]
+    macos::install();
+    #[cfg(target_os = "linux")]
+    linux::install();
+}
+
+#[cfg(not(windows))]
+pub fn remove_tray(_window: &Window) {}
+
+/// Windows keeps showing icons of exited programs until they are hovered, so it has to be
+/// removed explicitly.
+#[cfg(windows)]
+pub fn remove_tray(window: &Window) {
+    windows::remove(window);
+}
+
+#[cfg(windows)]
+mod windows {
+    use std::{mem::size_of, ptr::null_mut};
+
+    use glutin::{platform::windows::WindowExtWindows, window::Window};
+    use winapi::{
+        shared::{
+            basetsd::{DWORD_PTR, UINT_PTR},
+            minwindef::{LPARAM, LRESULT, UINT, WPARAM},
+            windef::{HWND, POINT},
+        },
+        um::{
+            commctrl::{DefSubclassProc, RemoveWindowSubclass, SetWindowSubclass},
+            libloaderapi::GetModuleHandleW,
+            shellapi::{
+                Shell_NotifyIconW, NIF_ICON, NIF_MESSAGE, NIF_TIP, NIM_ADD, NIM_DELETE,
+                NOTIFYICONDATAW,
+            },
+            winuser::*,
+        },
+    };
+
+    use super::push_action;
+    use crate::window::menu_items::{MenuAction, TRAY_MENU};
+
+    const SUBCLASS_ID: UINT_PTR = 2;
+    const TRAY_ICON_ID: UINT = 1;
+    const WM_TRAY_ICON: UINT = WM_APP + 1;
+    // Winres embeds the exe icon as the first icon resource
+    const ICON_RESOURCE_ID: u16 = 1;
+
+    fn to_wide(text: &str) -> Vec<u16> {
+        text.encode_utf16().chain(Some(0)).collect()
+    }
+
+    fn notify_icon_data(hwnd: HWND) -> NOTIFYICONDATAW {
+        let mut data: NOTIFYICONDATAW = unsafe { std::mem::zeroed() };
+        data.cbSize = size_of::<NOTIFYICONDATAW>() as u32;
+        data.hWnd = hwnd;
+        data.uID = TRAY_ICON_ID;
+        data
+    }
+
+    unsafe fn show_menu(hwnd: HWND) {
+        let menu = CreatePopupMenu();
+        for action in TRAY_MENU.iter() {
+            let title = to_wide(action.title());
+            AppendMenuW(menu, MF_STRING, action.tag(), title.as_ptr());
+        }
+
+        let mut cursor = POINT { x: 0, y: 0 };
+        GetCursorPos(&mut cursor);
+        // Otherwise the menu doesn't close when clicking elsewhere
+        SetForegroundWindow(hwnd);
+        let id = TrackPopupMenu(
+            menu,
+            TPM_RETURNCMD | TPM_NONOTIFY | TPM_RIGHTBUTTON,
+            cursor.x,
+            cursor.y,
+            0,
+            hwnd,
+            null_mut(),
+        );
+        DestroyMenu(menu);
+
+        if let Some(action) = MenuAction::from_tag(id as usize) {
+            push_action(action);
+        }
+    }
+
+    unsafe extern "system" fn subclass_proc(
+        hwnd: HWND,
+        msg: UINT,
+        wparam: WPARAM,
+        lparam: LPARAM,
+        _id: UINT_PTR,
+        _data: DWORD_PTR,
+    ) -> LRESULT {
+        if msg != WM_TRAY_ICON {
+            return DefSubclassProc(hwnd, msg, wparam, lparam);
+        }
+
+        match lparam as UINT {
+            WM_LBUTTONUP => push_action(MenuAction::ToggleWindow),
+            WM_RBUTTONUP => show_menu(hwnd),
+            _ => {}
+        }
+        0
+    }
+
+    pub fn install(window: &Window) {
+        let hwnd = window.hwnd() as HWND;
+        let mut data = notify_icon_data(hwnd);
+        data.uFlags = NIF_MESSAGE | NIF_ICON | NIF_TIP;
+        data.uCallbackMessage = WM_TRAY_ICON;
+
+        let tip = to_wide("Neovide");
+        data.szTip[..tip.len()].copy_from_slice(&tip);
+
+        unsafe {
+            data.hIcon = LoadIconW(
+                GetModuleHandleW(null_mut()),
+                MAKEINTRESOURCEW(ICON_RESOURCE_ID),
+            );
+            if data.hIcon.is_null() {
+                data.hIcon = LoadIconW(null_mut(), IDI_APPLICATION);
+            }
+
+            SetWindowSubclass(hwnd, Some(subclass_proc), SUBCLASS_ID, 0);
+            Shell_NotifyIconW(NIM_ADD, &mut data);
+        }
+    }
+
+    pub fn remove(window: &Window) {
+        let hwnd = window.hwnd() as HWND;
+        let mut data = notify_icon_data(hwnd);
+        unsafe {
+            Shell_NotifyIconW(NIM_DELETE, &mut data);
+            RemoveWindowSubclass(hwnd, Some(subclass_proc), SUBCLASS_ID);
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod macos {
+    use cocoa::{
+        base::{id, nil},
+        foundation::NSString,
+    };
+    use objc::{
+        class,
+        declare::ClassDecl,
+        msg_send,
+        runtime::{Object, Sel},
+        sel, sel_impl,
+    };
+
+    use super::push_action;
+    use crate::window::menu::add_action_item;
+    use crate::window::menu_items::{MenuAction, TRAY_MENU};
+
+    const NS_VARIABLE_STATUS_ITEM_LENGTH: f64 = -1.0;
+
+    extern "C" fn handle_tray_item(_this: &Object, _selector: Sel, sender: id) {
+        let tag: i64 = unsafe { msg_send![sender, tag] };
+        if let Some(action) = MenuAction::from_tag(tag as usize) {
+            push_action(action);
+        }
+    }
+
+    fn tray_handler() -> id {
+        let mut decl = ClassDecl::new("NeovideTrayHandler", class!(NSObject))
+            .expect("Could not declare tray handler class");
+        unsafe {
+            decl.add_method(
+                sel!(handleTrayItem:),
+                handle_tray_item as extern "C" fn(&Object, Sel, id),
+            );
+            let class = decl.register();
+            msg_send![class, new]
+        }
+    }
+
+    pub fn install() {
+        unsafe {
+            let handler = tray_handler();
+            let menu: id = msg_send![class!(NSMenu), new];
+            for action in TRAY_MENU.iter() {
+                add_action_item(menu, handler, *action, sel!(handleTrayItem:), None);
+            }
+
+            let status_bar: id = msg_send![class!(NSStatusBar), systemStatusBar];
+            let status_item: id =
+                msg_send![status_bar, statusItemWithLength: NS_VARIABLE_STATUS_ITEM_LENGTH];
+            // The status bar only holds on to its items weakly
+            let _: id = msg_send![status_item, retain];
+            let button: id = msg_send![status_item, button];
+            let title = NSString::alloc(nil).init_str("Neovide");
+            let _: () = msg_send![button, setTitle: title];
+            let _: () = msg_send![status_item, setMenu: menu];
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use ksni::{menu::StandardItem, MenuItem, Tray, TrayService};
+
+    use super::push_action;
+    use crate::window::menu_items::{MenuAction, TRAY_MENU};
+
+    // Shown through the StatusNotifierItem protocol, which most linux desktops support
+    struct NeovideTray;
+
+    impl Tray for NeovideTray {
+        fn activate(&mut self, _x: i32, _y: i32) {
+            push_action(MenuAction::ToggleWindow);
+        }
+
+        fn id(&self) -> String {
+            "neovide".to_owned()
+        }
+
+        fn title(&self) -> String {
+            "Neovide".to_owned()
+        }
+
+        fn icon_name(&self) -> String {
+            "neovide".to_owned()
+        }
+
+        fn menu(&self) -> Vec<MenuItem<Self>> {
+            TRAY_MENU
+                .iter()
+                .map(|action| {
+                    let action = *action;
+                    StandardItem {
+                        label: action.title().to_owned(),
+                        activate: Box::new(move |_| push_action(action)),
+                        ..Default::default()
+                    }
+                    .into()
+                })
+                .collect()
+        }
+    }
+
+    pub fn install() {
+        TrayService::new(NeovideTray).spawn();
+    }
+}